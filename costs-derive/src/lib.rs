@@ -13,11 +13,28 @@ struct Field {
     hash_val: Ident,
 }
 
+/// Turn a snake_case field name into a CamelCase variant name.
+fn variant_name(field: &Ident) -> Ident {
+    let name = field.to_string()
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    format_ident!("{}", name)
+}
+
 /// Derive our costs impl.
 ///
 /// Effectively, we collect any HashMap fields in the struct (ignoring others)
 /// and implement things like new_with_<field> or get_<field> as well as Add/Div
-/// and our other math stuff.
+/// and our other math stuff. We also generate a `CostBucket` enum with one
+/// variant per HashMap field (plus `Credits`) so the buckets can be iterated
+/// over without naming each one.
 #[proc_macro_derive(Costs)]
 pub fn derive_costs(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -80,9 +97,55 @@ pub fn derive_costs(input: TokenStream) -> TokenStream {
     let field_name_mut = fields.iter().map(|f| format_ident!("{}_mut", f.name)).collect::<Vec<_>>();
     let field_hashkey = fields.iter().map(|f| f.hash_key.clone()).collect::<Vec<_>>();
     let field_hashval = fields.iter().map(|f| f.hash_val.clone()).collect::<Vec<_>>();
+    let field_variant = fields.iter().map(|f| variant_name(&f.name)).collect::<Vec<_>>();
+    let field_variant_comment = fields.iter().map(|f| format!("The `{}` bucket", f.name)).collect::<Vec<_>>();
+    let field_name_str = fields.iter().map(|f| f.name.to_string()).collect::<Vec<_>>();
 
     let cost_impl = quote! {
+        /// Names each of the buckets a cost object tracks values in.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+        pub enum CostBucket {
+            /// The aggregate credit value
+            Credits,
+            #(
+                #[doc = #field_variant_comment]
+                #field_variant,
+            )*
+        }
+
+        impl CostBucket {
+            /// Get the name of this bucket (the same as the field name in the
+            /// cost object)
+            pub fn name(&self) -> &'static str {
+                match self {
+                    CostBucket::Credits => "credits",
+                    #(
+                        CostBucket::#field_variant => #field_name_str,
+                    )*
+                }
+            }
+        }
+
         impl #name {
+            /// Iterate over every value in this cost object as a
+            /// `(bucket, id, value)` tuple.
+            ///
+            /// The aggregate credits always come first (with an empty id),
+            /// followed by each bucket in the order they're defined. Within a
+            /// bucket, values are ordered by id.
+            pub fn iter(&self) -> impl Iterator<Item = (CostBucket, &str, rust_decimal::Decimal)> + '_ {
+                let mut items: Vec<(CostBucket, &str, rust_decimal::Decimal)> = vec![(CostBucket::Credits, "", self.credits().clone())];
+                #(
+                    let mut bucket = self.#field_name().iter()
+                        .map(|(k, v)| (CostBucket::#field_variant, k.as_str(), v.clone()))
+                        .collect::<Vec<_>>();
+                    bucket.sort_by(|a, b| a.1.cmp(b.1));
+                    items.append(&mut bucket);
+                )*
+                items.into_iter()
+            }
+
             #(
                 #[doc = #fn_get_comment]
                 pub fn #fn_get<T: Into<#field_hashkey>>(&self, id: T) -> #field_hashval {
@@ -119,11 +182,16 @@ pub fn derive_costs(input: TokenStream) -> TokenStream {
 
             /// round all values to a standard decimal place
             fn round(&mut self) {
+                self.round_with(&crate::costs::precision::CostPrecision::default());
+            }
+
+            /// round all values using the given precision
+            fn round_with(&mut self, precision: &crate::costs::precision::CostPrecision) {
                 let credits = self.credits_mut();
-                *credits = Costs::do_round(credits);
+                *credits = precision.round(credits);
                 #(
                     for val in self.#field_name_mut().values_mut() {
-                        *val = Costs::do_round(val);
+                        *val = precision.round(val);
                     }
                 )*
             }
@@ -183,6 +251,66 @@ pub fn derive_costs(input: TokenStream) -> TokenStream {
                 false
             }
 
+            /// Add two sets of costs, returning an error on overflow instead of
+            /// panicking.
+            pub fn checked_add(mut self, other: Self) -> crate::error::Result<Self> {
+                self.credits = self.credits.checked_add(other.credits().clone()).ok_or(crate::error::Error::CostOverflow)?;
+                #(
+                    for (k, v) in other.#field_name().iter() {
+                        let entry = self.#field_name_mut().entry(k.clone()).or_insert(#field_hashval::zero());
+                        *entry = entry.checked_add(*v).ok_or(crate::error::Error::CostOverflow)?;
+                    }
+                )*
+                self.normalize();
+                Ok(self)
+            }
+
+            /// Subtract one set of costs from another, returning an error on
+            /// overflow instead of panicking.
+            pub fn checked_sub(mut self, other: Self) -> crate::error::Result<Self> {
+                self.credits = self.credits.checked_sub(other.credits().clone()).ok_or(crate::error::Error::CostOverflow)?;
+                #(
+                    for (k, v) in other.#field_name().iter() {
+                        let entry = self.#field_name_mut().entry(k.clone()).or_insert(#field_hashval::zero());
+                        *entry = entry.checked_sub(*v).ok_or(crate::error::Error::CostOverflow)?;
+                    }
+                )*
+                self.normalize();
+                Ok(self)
+            }
+
+            /// Multiply a set of costs, returning an error on overflow instead
+            /// of panicking.
+            pub fn checked_mul(mut self, rhs: rust_decimal::Decimal) -> crate::error::Result<Self> {
+                self.credits = self.credits.checked_mul(rhs).ok_or(crate::error::Error::CostOverflow)?;
+                #(
+                    for (_, val) in self.#field_name_mut().iter_mut() {
+                        *val = val.checked_mul(rhs).ok_or(crate::error::Error::CostOverflow)?;
+                    }
+                )*
+                self.normalize();
+                Ok(self)
+            }
+
+            /// Divide a set of costs, returning an error on divide-by-zero or
+            /// overflow instead of panicking.
+            pub fn checked_div(mut self, rhs: rust_decimal::Decimal) -> crate::error::Result<Self> {
+                if self.is_zero() {
+                    return Ok(self);
+                }
+                if rhs == Decimal::zero() {
+                    Err(crate::error::Error::DivideByZero)?;
+                }
+                self.credits = self.credits.checked_div(rhs).ok_or(crate::error::Error::CostOverflow)?;
+                #(
+                    for (_, val) in self.#field_name_mut().iter_mut() {
+                        *val = val.checked_div(rhs).ok_or(crate::error::Error::CostOverflow)?;
+                    }
+                )*
+                self.normalize();
+                Ok(self)
+            }
+
             /// Determine if dividing one set of costs by another will result in
             /// a divide-by-zero panic.
             pub fn is_div_by_0(costs1: &Costs, costs2: &Costs) -> bool {
@@ -22,7 +22,7 @@ use chrono::Utc;
 /// starting with a blank slate so we need to add an occupation.
 fn create_voted_occupation(label: &str) -> Result<Occupation> {
     let voter = Vote::systemic(UserID::new("f8636701-2ec0-46e9-bff3-5cff3d7f97cf"), &Utc::now())?;
-    let mods = occupation::create(voter.user(), OccupationID::new("e8677b3c-e125-4fb2-8cf1-04bcdae162b7"), label.into(), "Adding our first occupation", true, &Utc::now())?.into_vec();
+    let mods = occupation::create(voter.user(), OccupationID::new("e8677b3c-e125-4fb2-8cf1-04bcdae162b7"), label.into(), "Adding our first occupation", None, true, &Utc::now())?.into_vec();
     mods[0].clone().expect_op::<Occupation>(Op::Create)
 }
 
@@ -8,14 +8,37 @@
 //!
 //! Generally, the access system just applies to [Users].
 //!
+//! Creating events is split up by action (`EventCreateTransfer`,
+//! `EventCreateWork`, etc), mirroring the per-action [company permissions].
+//! The built-in roles grant all of them, but this lets a network single out
+//! certain kinds of events (like selling to outside markets) and limit them
+//! to certain users.
+//!
+//! Implementations that need rules beyond the built-in permissions (for
+//! instance, restricting some actions to members of a certain region) can run
+//! transactions under an [AccessPolicy] via `with_policy()`. The policy is
+//! consulted *after* the built-in checks pass, so it can only further restrict
+//! access, never grant it. A policy only applies to the transactions run
+//! inside its `with_policy()` call (on the current thread), so there's no
+//! global state: two networks in the same process can each use their own.
+//!
 //! [err_priv]: ../error/enum.Error.html#variant.InsufficientPrivileges
 //! [Users]: ../models/user/struct.User.html
+//! [company permissions]: ../models/company/enum.Permission.html
+//! [AccessPolicy]: trait.AccessPolicy.html
 
 use crate::{
     error::{Error, Result},
+    models::{
+        company::{CompanyID, Permission as CompanyPermission},
+        member::Member,
+        user::User,
+    },
 };
 #[cfg(feature = "with_serde")]
 use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::sync::Arc;
 
 /// Define the system-wide permissions.
 ///
@@ -28,14 +51,19 @@ pub enum Permission {
 
     AccountCreate,
     AccountDelete,
+    AccountDeposit,
     AccountSetOwners,
     AccountTransfer,
     AccountUBIClaim,
+    AccountUBIIssue,
     AccountUpdate,
+    AccountWithdraw,
 
     CompanyCreate,
     CompanyDelete,
     CompanyPayroll,
+    CompanyTreasuryCreate,
+    CompanyTreasuryUpdate,
     CompanyUpdate,
     CompanyUpdateAgreements,
     CompanyUpdateCommitments,
@@ -50,9 +78,33 @@ pub enum Permission {
     CurrencyDelete,
     CurrencyUpdate,
 
-    EventCreate,
+    EventCreateAccept,
+    EventCreateCite,
+    EventCreateConsume,
+    EventCreateDeliverService,
+    EventCreateDropoff,
+    EventCreateInspect,
+    EventCreateLower,
+    EventCreateMarketSell,
+    EventCreateModify,
+    EventCreateMoveCosts,
+    EventCreateMoveResource,
+    EventCreatePickup,
+    EventCreateProduce,
+    EventCreateRaise,
+    EventCreateShipment,
+    EventCreateTransfer,
+    EventCreateTransferAllRights,
+    EventCreateTransferCustody,
+    EventCreateUse,
+    EventCreateWork,
+    EventCreateWriteOff,
     EventUpdate,
 
+    ImpactCreate,
+    ImpactDelete,
+    ImpactUpdate,
+
     UserAdminCreate,
     UserAdminUpdate,
     UserCreate,
@@ -64,9 +116,22 @@ pub enum Permission {
     ResourceSpecDelete,
     ResourceSpecUpdate,
 
+    NetworkConfigCreate,
+    NetworkConfigUpdate,
+
     OccupationCreate,
     OccupationDelete,
     OccupationUpdate,
+
+    OrderPurchase,
+
+    ProposalClose,
+    ProposalCreate,
+    ProposalVote,
+
+    RegionCreate,
+    RegionDelete,
+    RegionUpdate,
 }
 
 /// Define the system-wide roles users can have.
@@ -101,6 +166,11 @@ impl Role {
                     Permission::CurrencyCreate,
                     Permission::CurrencyUpdate,
                     Permission::CurrencyDelete,
+                    Permission::CompanyTreasuryCreate,
+                    Permission::CompanyTreasuryUpdate,
+                    Permission::AccountDeposit,
+                    Permission::AccountUBIIssue,
+                    Permission::AccountWithdraw,
                 ]
             },
             Role::User => {
@@ -128,8 +198,32 @@ impl Role {
                     Permission::AccountSetOwners,
                     Permission::AccountTransfer,
                     Permission::AccountDelete,
-                    Permission::EventCreate,
+                    Permission::EventCreateAccept,
+                    Permission::EventCreateCite,
+                    Permission::EventCreateConsume,
+                    Permission::EventCreateDeliverService,
+                    Permission::EventCreateDropoff,
+                    Permission::EventCreateInspect,
+                    Permission::EventCreateLower,
+                    Permission::EventCreateMarketSell,
+                    Permission::EventCreateModify,
+                    Permission::EventCreateMoveCosts,
+                    Permission::EventCreateMoveResource,
+                    Permission::EventCreatePickup,
+                    Permission::EventCreateProduce,
+                    Permission::EventCreateRaise,
+                    Permission::EventCreateShipment,
+                    Permission::EventCreateTransfer,
+                    Permission::EventCreateTransferAllRights,
+                    Permission::EventCreateTransferCustody,
+                    Permission::EventCreateUse,
+                    Permission::EventCreateWork,
+                    Permission::EventCreateWriteOff,
                     Permission::EventUpdate,
+                    Permission::OrderPurchase,
+                    Permission::ProposalClose,
+                    Permission::ProposalCreate,
+                    Permission::ProposalVote,
                 ]
             }
             Role::Guest => {
@@ -173,9 +267,106 @@ pub fn guest_check(perm: Permission) -> Result<()> {
     }
 }
 
+/// An extension point for layering network-specific access rules on top of
+/// the built-in permission checks.
+///
+/// Both methods default to allowing access, so a policy only needs to
+/// implement the checks it cares about. Returning an error denies access, and
+/// the error is passed back to the caller of the transaction.
+pub trait AccessPolicy: Send + Sync {
+    /// Called after a user passes the built-in check for a system permission.
+    fn check_user(&self, _user: &User, _permission: &Permission) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after a member passes the built-in check for a company
+    /// permission.
+    fn check_member(&self, _member: &Member, _company_id: &CompanyID, _permission: &CompanyPermission) -> Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static POLICY: RefCell<Option<Arc<dyn AccessPolicy>>> = RefCell::new(None);
+}
+
+/// Puts the previous policy back once a `with_policy()` call finishes (even if
+/// it panics).
+struct PolicyGuard(Option<Arc<dyn AccessPolicy>>);
+
+impl Drop for PolicyGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        POLICY.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Run `f` with the given access policy applied to every access check it
+/// makes. The policy only applies for the duration of the call, and only on
+/// the current thread.
+///
+/// ```rust
+/// use basis_core::{
+///     access::{self, AccessPolicy, Permission},
+///     error::{Error, Result},
+///     models::user::User,
+/// };
+/// use std::sync::Arc;
+///
+/// struct NoMarkets;
+///
+/// impl AccessPolicy for NoMarkets {
+///     fn check_user(&self, _user: &User, permission: &Permission) -> Result<()> {
+///         if permission == &Permission::EventCreateMarketSell {
+///             Err(Error::InsufficientPrivileges)?;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let res = access::with_policy(Arc::new(NoMarkets), || {
+///     // run transactions here
+///     42
+/// });
+/// assert_eq!(res, 42);
+/// ```
+pub fn with_policy<T, F: FnOnce() -> T>(policy: Arc<dyn AccessPolicy>, f: F) -> T {
+    let previous = POLICY.with(|current| current.borrow_mut().replace(policy));
+    let _guard = PolicyGuard(previous);
+    f()
+}
+
+/// Grab the current access policy, if any.
+fn policy() -> Option<Arc<dyn AccessPolicy>> {
+    POLICY.with(|current| current.borrow().clone())
+}
+
+/// Run a user through the access policy (if one is set).
+pub(crate) fn policy_check_user(user: &User, permission: &Permission) -> Result<()> {
+    match policy() {
+        Some(policy) => policy.check_user(user, permission),
+        None => Ok(()),
+    }
+}
+
+/// Run a member through the access policy (if one is set).
+pub(crate) fn policy_check_member(member: &Member, company_id: &CompanyID, permission: &CompanyPermission) -> Result<()> {
+    match policy() {
+        Some(policy) => policy.check_member(member, company_id, permission),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::{
+        models::{
+            member::MemberID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
 
     #[test]
     fn permissions_work() {
@@ -186,6 +377,59 @@ pub mod tests {
         assert!(super_admin.can(&Permission::UserAdminUpdate));
         assert!(super_admin.can(&Permission::UserDelete));
         assert!(super_admin.can(&Permission::CompanyCreate));
+        assert!(super_admin.can(&Permission::EventCreateWork));
+
+        let user = Role::User;
+        assert!(user.can(&Permission::EventCreateTransfer));
+        assert!(user.can(&Permission::EventCreateWork));
+        assert!(user.can(&Permission::EventCreateMarketSell));
+        assert!(!Role::Guest.can(&Permission::EventCreateWork));
+        assert!(!Role::Bank.can(&Permission::EventCreateTransfer));
     }
-}
 
+    struct NoEventsForEve;
+
+    impl AccessPolicy for NoEventsForEve {
+        fn check_user(&self, user: &User, permission: &Permission) -> Result<()> {
+            if user.id() == &UserID::new("eve") && permission == &Permission::EventCreateMarketSell {
+                Err(Error::InsufficientPrivileges)?;
+            }
+            Ok(())
+        }
+
+        fn check_member(&self, _member: &Member, company_id: &CompanyID, permission: &CompanyPermission) -> Result<()> {
+            if company_id == &CompanyID::new("eve's widgets") && permission == &CompanyPermission::Consume {
+                Err(Error::InsufficientPrivileges)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn policies_restrict_access() {
+        let now = util::time::now();
+        let eve = make_user(&UserID::new("eve"), None, &now);
+        let company_id = CompanyID::new("eve's widgets");
+        let member = make_member_worker(&MemberID::create(), eve.id(), &company_id, &"widgetmaker".into(), vec![CompanyPermission::All], &now);
+        assert_eq!(eve.access_check(Permission::EventCreateMarketSell), Ok(()));
+        assert_eq!(member.access_check(eve.id(), &company_id, CompanyPermission::Consume), Ok(()));
+
+        with_policy(Arc::new(NoEventsForEve), || {
+            assert_eq!(eve.access_check(Permission::EventCreateMarketSell), Err(Error::InsufficientPrivileges));
+            assert_eq!(eve.access_check(Permission::EventCreateTransfer), Ok(()));
+            assert_eq!(eve.access_check(Permission::EventUpdate), Ok(()));
+            // the policy can't grant what the roles don't
+            assert_eq!(eve.access_check(Permission::UserAdminCreate), Err(Error::InsufficientPrivileges));
+            assert_eq!(member.access_check(eve.id(), &company_id, CompanyPermission::Consume), Err(Error::InsufficientPrivileges));
+            assert_eq!(member.access_check(eve.id(), &company_id, CompanyPermission::Produce), Ok(()));
+            // policies on other threads aren't affected
+            let eve2 = eve.clone();
+            std::thread::spawn(move || {
+                assert_eq!(eve2.access_check(Permission::EventCreateMarketSell), Ok(()));
+            }).join().unwrap();
+        });
+
+        assert_eq!(eve.access_check(Permission::EventCreateMarketSell), Ok(()));
+        assert_eq!(member.access_check(eve.id(), &company_id, CompanyPermission::Consume), Ok(()));
+    }
+}
@@ -97,6 +97,8 @@
 //! in a standard unit, it took to make something.
 //! - **currency** (`currency`) -- The amount of currency that went into
 //! purchasing inputs, useful for pricing either within or without the network.
+//! - **impact** (`impact`) -- The ecological/social externalities (CO2e, water
+//! use, etc) that went into making something. Impacts carry no credit value.
 //!
 //! Labor hours are not used for cost/price value when charging consumers for
 //! end products (we use the wage value, ie `labor`), but are there to track the
@@ -108,24 +110,46 @@
 //! close to raw materials as possible* while still being useful. For instance,
 //! crude oil in itself is good to track as a resource, but it might also be
 //! just as useful to track gasoline, jet fuel, kerosene, etc. Thus we make it
-//! possible to have standard resource transformations, applied on a limited
-//! basis, in order to account for not just raw materials but semi-raw
-//! materials. That said, tracking the widget-content of some product isn't
+//! possible to have standard resource transformations (see
+//! `Costs::apply_transforms()`), applied on a limited basis, in order to
+//! account for not just raw materials but semi-raw materials. That said,
+//! tracking the widget-content of some product isn't
 //! especially useful, nor the yards of linen imbued in it (sorry, Marx). The
 //! ultimate goal is to track resources such that we're more globally aware of
-//! our depletion rates of resources vs their renewal/recycle rates. This mostly
-//! involves tracking the contituent resources, not the higher-level products.
+//! our depletion rates of resources vs their renewal/recycle rates (see the
+//! [depletion] module). This mostly involves tracking the contituent resources,
+//! not the higher-level products.
 //! What products are defined as raw/semi-raw materials (aka "resources") is a
 //! systemwide, collective decision. It will be a function of governance, not
 //! code.
+//!
+//! Reporting code that needs to look at every value in a `Costs` object
+//! (without calling each getter by hand) can use `Costs::iter()`, which yields
+//! each value along with its [CostBucket] and id.
+//!
+//! To show consumers what went into something they're buying, see the
+//! [breakdown] module.
+//!
+//! If the `binary` feature is enabled, costs can also be encoded in a compact
+//! [binary] format for storage.
+//!
+//! [binary]: binary/index.html
+//! [breakdown]: breakdown/index.html
+//! [CostBucket]: enum.CostBucket.html
+//! [depletion]: depletion/index.html
 
+use chrono::{DateTime, Utc};
 use costs_derive::Costs;
 use crate::{
+    costs::precision::CostPrecision,
     error::{Error, Result},
     models::{
-        currency::CurrencyID,
+        currency::{Currency, CurrencyID},
+        impact::ImpactID,
         occupation::OccupationID,
+        lib::basis_model::Model,
         resource_spec::ResourceSpecID,
+        resource_transform::ResourceTransform,
     },
     util::number::Ratio,
 };
@@ -136,6 +160,17 @@ use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::ops::{Add, Sub, Mul, Div};
 
+#[cfg(feature = "binary")]
+pub mod binary;
+pub mod breakdown;
+pub mod depletion;
+pub mod logistics;
+pub mod precision;
+pub mod pricing;
+pub mod reports;
+pub mod validate;
+pub mod variance;
+
 /// A struct that acts as a container for the various types of disaggregate
 /// costs we want to store and track.
 ///
@@ -183,6 +218,12 @@ pub struct Costs {
     /// empty as currency-based markets are phased out.
     #[cfg_attr(feature = "with_serde", serde(default = "Default::default", skip_serializing_if = "std::collections::HashMap::is_empty"))]
     currency: HashMap<CurrencyID, Decimal>,
+    /// Stores ecological and social externalities (CO2e emitted, water used,
+    /// etc) per impact type. These are tracked just like resources, but carry
+    /// no credit value: they describe the footprint of what we make rather
+    /// than its price.
+    #[cfg_attr(feature = "with_serde", serde(default = "Default::default", skip_serializing_if = "std::collections::HashMap::is_empty"))]
+    impact: HashMap<ImpactID, Decimal>,
 }
 
 impl Costs {
@@ -191,15 +232,24 @@ impl Costs {
         Self::default()
     }
 
-    /// Standard abstraction around decimal rounding
+    /// Standard abstraction around decimal rounding. Uses the default
+    /// [precision](precision/index.html).
     pub fn do_round(val: &Decimal) -> Decimal {
-        val.round_dp(16)
+        CostPrecision::default().round(val)
     }
 
     /// Make sure this Costs object is a standard format. This means we do any
     /// rounding needed and remove and zero values.
     pub fn normalize(&mut self) {
-        //self.round();
+        self.round();
+        self.strip();
+        self.dezero();
+    }
+
+    /// Normalize this Costs object using the given precision (generally the
+    /// network config's `cost_precision`) rather than the default one.
+    pub fn normalize_with(&mut self, precision: &CostPrecision) {
+        self.round_with(precision);
         self.strip();
         self.dezero();
     }
@@ -246,6 +296,16 @@ impl Costs {
         costs
     }
 
+    /// Create a new Cost, with one impact entry
+    pub fn new_with_impact<T, V>(id: T, impact: V) -> Self
+        where T: Into<ImpactID>,
+              V: Into<Decimal> + Copy,
+    {
+        let mut costs = Self::new();
+        costs.track_impact(id, impact);
+        costs
+    }
+
     /// Add a credit cost to this Cost
     pub fn track_credits<V>(&mut self, val: V)
         where V: Into<Decimal> + Copy,
@@ -312,6 +372,261 @@ impl Costs {
         self.track_credits(val * conversion_rate.into());
         self.normalize();
     }
+
+    /// Add an impact cost to this Cost. Impacts do not affect our credit value.
+    pub fn track_impact<T, V>(&mut self, id: T, val: V)
+        where T: Into<ImpactID>,
+              V: Into<Decimal> + Copy,
+    {
+        if val.into() < Decimal::zero() {
+            panic!("Costs::track_impact() -- given value must be >= 0");
+        }
+        let entry = self.impact_mut().entry(id.into()).or_insert(rust_decimal::prelude::Zero::zero());
+        *entry += val.into();
+        self.normalize();
+    }
+
+    /// Add a currency cost to this Cost, using the exchange rate stored in the
+    /// given `Currency` at the given point in time.
+    pub fn track_currency_at<V>(&mut self, currency: &Currency, val: V, at: &DateTime<Utc>) -> Result<()>
+        where V: Into<Decimal> + Copy,
+    {
+        let rate = currency.rate_at(at)?;
+        self.track_currency(currency.id().clone(), val, rate);
+        Ok(())
+    }
+
+    /// Decompose the resources in these costs into their constituents using a
+    /// table of [resource transforms][1].
+    ///
+    /// Each resource with an active transform is replaced by its constituent
+    /// resources (scaled by the amount of the resource). Transforms are applied
+    /// repeatedly, so a resource whose constituents also have transforms will
+    /// be decomposed all the way down. Credits and the other buckets are left
+    /// untouched.
+    ///
+    /// If the table contains a cycle (a spec that eventually decomposes into
+    /// itself), decomposition stops once every transform has had a chance to
+    /// apply.
+    ///
+    /// [1]: ../models/resource_transform/index.html
+    pub fn apply_transforms(&self, table: &[ResourceTransform]) -> Costs {
+        let transforms = table.iter()
+            .filter(|transform| transform.is_active())
+            .map(|transform| (transform.resource_spec_id(), transform))
+            .collect::<HashMap<_, _>>();
+        let mut costs = self.clone();
+        for _ in 0..transforms.len() {
+            let mut resource = HashMap::new();
+            let mut changed = false;
+            for (spec_id, amount) in costs.resource() {
+                match transforms.get(spec_id) {
+                    Some(transform) => {
+                        changed = true;
+                        for (constituent_id, ratio) in transform.constituents() {
+                            let entry = resource.entry(constituent_id.clone()).or_insert(Decimal::zero());
+                            *entry += amount.clone() * ratio.clone();
+                        }
+                    }
+                    None => {
+                        let entry = resource.entry(spec_id.clone()).or_insert(Decimal::zero());
+                        *entry += amount.clone();
+                    }
+                }
+            }
+            costs.set_resource(resource);
+            costs.normalize();
+            if !changed {
+                break;
+            }
+        }
+        costs
+    }
+
+    /// Split these costs into parts according to a set of ratios, returning
+    /// one set of costs per ratio.
+    ///
+    /// The ratios are weighed against their sum, so they don't need to add up
+    /// to exactly one (if they sum to zero, the costs are split evenly). Any
+    /// remainder left over from rounding is assigned to the part with the
+    /// largest ratio so that the parts always add back up to the whole.
+    pub fn split(&self, ratios: &[Ratio]) -> Vec<Costs> {
+        if ratios.is_empty() {
+            return vec![];
+        }
+        let total = ratios.iter().fold(Decimal::zero(), |acc, ratio| acc + ratio.inner());
+        let weights = if total == Decimal::zero() {
+            vec![Decimal::one() / Decimal::from(ratios.len()); ratios.len()]
+        } else {
+            ratios.iter().map(|ratio| ratio.inner() / total).collect::<Vec<_>>()
+        };
+        let largest = weights.iter().enumerate()
+            .fold(0, |largest, (idx, weight)| if weight > &weights[largest] { idx } else { largest });
+        let mut parts = weights.iter()
+            .map(|weight| self.clone() * weight.clone())
+            .collect::<Vec<_>>();
+        let allocated = parts.iter().enumerate()
+            .filter(|(idx, _)| idx != &largest)
+            .fold(Costs::new(), |acc, (_, part)| acc + part.clone());
+        parts[largest] = self.clone() - allocated;
+        parts
+    }
+
+    /// Get the costs for a single unit, given the number of units these costs
+    /// cover. Like division, this panics if `quantity` is zero (see
+    /// `checked_div()` for a non-panicking version).
+    pub fn per_unit(&self, quantity: Decimal) -> Costs {
+        self.clone() / quantity
+    }
+
+    /// Compare two cost sets value-by-value across every bucket (including
+    /// the aggregate credits).
+    ///
+    /// Returns `Greater` if every value in `self` is at least as large as the
+    /// matching value in `other` (and `Less` for the opposite), `Equal` if all
+    /// values match, and `None` if some values are larger and others smaller,
+    /// in which case neither cost set dominates the other.
+    pub fn partial_cmp_dominance(&self, other: &Costs) -> Option<std::cmp::Ordering> {
+        let diff = self.clone() - other.clone();
+        let mut has_greater = false;
+        let mut has_less = false;
+        for (_, _, val) in diff.iter() {
+            if val > Decimal::zero() {
+                has_greater = true;
+            } else if val < Decimal::zero() {
+                has_less = true;
+            }
+        }
+        match (has_greater, has_less) {
+            (false, false) => Some(std::cmp::Ordering::Equal),
+            (true, false) => Some(std::cmp::Ordering::Greater),
+            (false, true) => Some(std::cmp::Ordering::Less),
+            (true, true) => None,
+        }
+    }
+
+    /// Find the single largest value tracked in any bucket (not counting the
+    /// aggregate credits), returned as a `(bucket, id, value)` tuple. Ties go
+    /// to whichever comes first in `iter()` order.
+    ///
+    /// Keep in mind that buckets are measured in different units (wages,
+    /// hours, resource units, ...), so this is mainly useful for finding the
+    /// dominant component of a cost set rather than for precise comparisons.
+    pub fn max_component(&self) -> Option<(CostBucket, &str, Decimal)> {
+        self.iter()
+            .filter(|(bucket, _, _)| bucket != &CostBucket::Credits)
+            .fold(None, |max, item| {
+                match max {
+                    Some((_, _, val)) if val >= item.2 => max,
+                    _ => Some(item),
+                }
+            })
+    }
+
+    /// The total credit value of these costs. The same as `credits()` but
+    /// returns an owned value, which reads better in comparisons and sums.
+    pub fn total_credits(&self) -> Decimal {
+        *self.credits()
+    }
+
+    /// Add to our credits, returning the new credit value (without setting it)
+    /// or an error on overflow.
+    fn checked_credits(&self, val: Decimal) -> Result<Decimal> {
+        self.credits().checked_add(val).ok_or(Error::CostOverflow)
+    }
+
+    /// Add a resource cost to this Cost, returning an error instead of
+    /// panicking if the value is negative or the costs overflow.
+    pub fn try_track_resource<T, V, C>(&mut self, id: T, val: V, credit_value_per_unit: C) -> Result<()>
+        where T: Into<ResourceSpecID>,
+              V: Into<Decimal> + Copy,
+              C: Into<Decimal> + Copy,
+    {
+        let val = val.into();
+        if val < Decimal::zero() {
+            Err(Error::NegativeCosts)?;
+        }
+        let id = id.into();
+        let entry = self.get_resource(id.clone()).checked_add(val).ok_or(Error::CostOverflow)?;
+        let credits = self.checked_credits(val.checked_mul(credit_value_per_unit.into()).ok_or(Error::CostOverflow)?)?;
+        self.resource_mut().insert(id, entry);
+        self.set_credits(credits);
+        self.normalize();
+        Ok(())
+    }
+
+    /// Add a labor cost to this Cost, returning an error instead of panicking
+    /// if the value is negative or the costs overflow.
+    pub fn try_track_labor<T, V>(&mut self, id: T, val: V) -> Result<()>
+        where T: Into<OccupationID>,
+              V: Into<Decimal> + Copy,
+    {
+        let val = val.into();
+        if val < Decimal::zero() {
+            Err(Error::NegativeCosts)?;
+        }
+        let id = id.into();
+        let entry = self.get_labor(id.clone()).checked_add(val).ok_or(Error::CostOverflow)?;
+        let credits = self.checked_credits(val)?;
+        self.labor_mut().insert(id, entry);
+        self.set_credits(credits);
+        self.normalize();
+        Ok(())
+    }
+
+    /// Add a labor_hours cost to this Cost, returning an error instead of
+    /// panicking if the value is negative or the costs overflow.
+    pub fn try_track_labor_hours<T, V>(&mut self, id: T, val: V) -> Result<()>
+        where T: Into<OccupationID>,
+              V: Into<Decimal> + Copy,
+    {
+        let val = val.into();
+        if val < Decimal::zero() {
+            Err(Error::NegativeCosts)?;
+        }
+        let id = id.into();
+        let entry = self.get_labor_hours(id.clone()).checked_add(val).ok_or(Error::CostOverflow)?;
+        self.labor_hours_mut().insert(id, entry);
+        self.normalize();
+        Ok(())
+    }
+
+    /// Add a currency cost to this Cost, returning an error instead of
+    /// panicking if the value is negative or the costs overflow.
+    pub fn try_track_currency<T, V, C>(&mut self, id: T, val: V, conversion_rate: C) -> Result<()>
+        where T: Into<CurrencyID>,
+              V: Into<Decimal> + Copy,
+              C: Into<Decimal> + Copy,
+    {
+        let val = val.into();
+        if val < Decimal::zero() {
+            Err(Error::NegativeCosts)?;
+        }
+        let id = id.into();
+        let entry = self.get_currency(id.clone()).checked_add(val).ok_or(Error::CostOverflow)?;
+        let credits = self.checked_credits(val.checked_mul(conversion_rate.into()).ok_or(Error::CostOverflow)?)?;
+        self.currency_mut().insert(id, entry);
+        self.set_credits(credits);
+        self.normalize();
+        Ok(())
+    }
+
+    /// Add an impact cost to this Cost, returning an error instead of
+    /// panicking if the value is negative or the costs overflow.
+    pub fn try_track_impact<T, V>(&mut self, id: T, val: V) -> Result<()>
+        where T: Into<ImpactID>,
+              V: Into<Decimal> + Copy,
+    {
+        let val = val.into();
+        if val < Decimal::zero() {
+            Err(Error::NegativeCosts)?;
+        }
+        let id = id.into();
+        let entry = self.get_impact(id.clone()).checked_add(val).ok_or(Error::CostOverflow)?;
+        self.impact_mut().insert(id, entry);
+        self.normalize();
+        Ok(())
+    }
 }
 
 impl Mul<Ratio> for Costs {
@@ -373,6 +688,36 @@ pub(crate) trait CostMover {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        costs::precision::Rounding,
+        models::{
+            currency::CurrencyRate,
+            resource_transform::ResourceTransformID,
+        },
+        util,
+    };
+
+    #[test]
+    fn normalize_with() {
+        let mut costs = Costs::new_with_labor("machinist", num!(1) / num!(3));
+        costs.track_resource("iron", num!(0.125), num!(2));
+        assert_eq!(costs.get_labor("machinist"), num!(0.3333333333333333));
+
+        let mut costs2 = costs.clone();
+        costs2.normalize_with(&CostPrecision::new(2, Rounding::Bankers));
+        assert_eq!(costs2.get_labor("machinist"), num!(0.33));
+        assert_eq!(costs2.get_resource("iron"), num!(0.12));
+        assert_eq!(costs2.credits(), &num!(0.58));
+
+        let mut costs3 = costs.clone();
+        costs3.normalize_with(&CostPrecision::new(2, Rounding::HalfUp));
+        assert_eq!(costs3.get_resource("iron"), num!(0.13));
+
+        // values that round to zero are dropped
+        let mut costs4 = Costs::new_with_labor("machinist", num!(0.001));
+        costs4.normalize_with(&CostPrecision::new(2, Rounding::Bankers));
+        assert_eq!(costs4, Costs::new());
+    }
 
     #[test]
     fn add() {
@@ -477,11 +822,11 @@ mod tests {
         costs1.track_currency("eur", Decimal::new(43301, 2), num!(0.99891));
 
         let costs = costs1 / num!(1.3);
-        assert_eq!(costs.get_labor("widgetmaker"), num!(6.0) / num!(1.3));
-        assert_eq!(costs.get_resource("widget"), num!(3.1) / num!(1.3));
-        assert_eq!(costs.get_resource("oil"), num!(5.6) / num!(1.3));
-        assert_eq!(costs.get_labor_hours("doctor"), num!(14.0) / num!(1.3));
-        assert_eq!(costs.get_currency("eur"), Decimal::new(43301, 2) / Decimal::from_f64(1.3).unwrap());
+        assert_eq!(costs.get_labor("widgetmaker"), Costs::do_round(&(num!(6.0) / num!(1.3))));
+        assert_eq!(costs.get_resource("widget"), Costs::do_round(&(num!(3.1) / num!(1.3))));
+        assert_eq!(costs.get_resource("oil"), Costs::do_round(&(num!(5.6) / num!(1.3))));
+        assert_eq!(costs.get_labor_hours("doctor"), Costs::do_round(&(num!(14.0) / num!(1.3))));
+        assert_eq!(costs.get_currency("eur"), Costs::do_round(&(Decimal::new(43301, 2) / Decimal::from_f64(1.3).unwrap())));
     }
 
     #[test]
@@ -518,6 +863,45 @@ mod tests {
         assert_eq!(costs.get_labor("clown"), num!(0.0));
     }
 
+    #[test]
+    fn dominance() {
+        let mut costs1 = Costs::new();
+        costs1.track_labor("machinist", num!(10));
+        costs1.track_labor_hours("machinist", num!(1));
+        costs1.track_resource("steel", num!(2), num!(3));
+        let mut costs2 = costs1.clone();
+        assert_eq!(costs1.partial_cmp_dominance(&costs2), Some(std::cmp::Ordering::Equal));
+
+        costs2.track_resource("steel", num!(1), num!(3));
+        assert_eq!(costs1.partial_cmp_dominance(&costs2), Some(std::cmp::Ordering::Less));
+        assert_eq!(costs2.partial_cmp_dominance(&costs1), Some(std::cmp::Ordering::Greater));
+
+        // more steel but fewer hours: neither dominates, even though the
+        // credit totals differ
+        let mut costs3 = Costs::new();
+        costs3.track_labor("machinist", num!(10));
+        costs3.track_labor_hours("machinist", num!(0.5));
+        costs3.track_resource("steel", num!(3), num!(3));
+        assert_eq!(costs1.partial_cmp_dominance(&costs3), None);
+        assert_eq!(costs3.partial_cmp_dominance(&costs1), None);
+
+        assert_eq!(Costs::new().partial_cmp_dominance(&Costs::new()), Some(std::cmp::Ordering::Equal));
+        assert_eq!(Costs::new().partial_cmp_dominance(&Costs::new_with_impact("co2e", num!(1))), Some(std::cmp::Ordering::Less));
+    }
+
+    #[test]
+    fn max_component() {
+        assert_eq!(Costs::new().max_component(), None);
+        let mut costs = Costs::new();
+        costs.track_labor("machinist", num!(10));
+        costs.track_labor_hours("machinist", num!(1));
+        costs.track_resource("steel", num!(12), num!(3));
+        costs.track_impact("co2e", num!(12));
+        assert_eq!(costs.credits(), &num!(46));
+        assert_eq!(costs.max_component(), Some((CostBucket::Resource, "steel", num!(12))));
+        assert_eq!(costs.total_credits(), num!(46));
+    }
+
     #[test]
     fn is_sub_lt_0() {
         let costs1 = Costs::new_with_labor("clown", num!(0.0));
@@ -564,6 +948,167 @@ mod tests {
         assert!(!costs2.is_gt_0());
     }
 
+    #[test]
+    fn checked() {
+        let mut costs1 = Costs::new();
+        costs1.track_labor("widgetmaker", num!(6.0));
+        costs1.track_resource("oil", num!(5.6), num!(2.2));
+        let costs2 = Costs::new_with_labor("widgetmaker", num!(2.0));
+
+        assert_eq!(costs1.clone().checked_add(costs2.clone()), Ok(costs1.clone() + costs2.clone()));
+        assert_eq!(costs1.clone().checked_sub(costs2.clone()), Ok(costs1.clone() - costs2.clone()));
+        assert_eq!(costs1.clone().checked_mul(num!(1.5)), Ok(costs1.clone() * num!(1.5)));
+        assert_eq!(costs1.clone().checked_div(num!(1.5)), Ok(costs1.clone() / num!(1.5)));
+        assert_eq!(costs1.clone().checked_div(num!(0)), Err(Error::DivideByZero));
+        assert_eq!(Costs::new().checked_div(num!(0)), Ok(Costs::new()));
+
+        let huge = Costs::new_with_labor("widgetmaker", Decimal::MAX);
+        assert_eq!(huge.clone().checked_add(costs2.clone()), Err(Error::CostOverflow));
+        assert_eq!((Costs::new() - huge.clone()).checked_sub(costs2.clone()), Err(Error::CostOverflow));
+        assert_eq!(huge.clone().checked_mul(num!(2)), Err(Error::CostOverflow));
+        assert_eq!(huge.clone().checked_div(num!(0.5)), Err(Error::CostOverflow));
+    }
+
+    #[test]
+    fn impact() {
+        let mut costs = Costs::new_with_impact("co2e", num!(12.5));
+        costs.track_labor("miner", num!(10));
+        costs.track_impact("co2e", num!(2.5));
+        costs.track_impact("water", num!(300));
+        assert_eq!(costs.get_impact("co2e"), num!(15));
+        assert_eq!(costs.get_impact("water"), num!(300));
+        assert_eq!(costs.get_impact("land"), num!(0));
+        // impacts don't have a credit value
+        assert_eq!(costs.credits(), &num!(10));
+
+        let costs2 = costs.clone() + Costs::new_with_impact("co2e", num!(5));
+        assert_eq!(costs2.get_impact("co2e"), num!(20));
+        let costs3 = costs2.clone() - Costs::new_with_impact("co2e", num!(20));
+        assert_eq!(costs3.get_impact("co2e"), num!(0));
+        assert_eq!(costs3.impact().len(), 1);
+        assert_eq!((costs.clone() * num!(2)).get_impact("water"), num!(600));
+        assert_eq!((costs.clone() / num!(3)).get_impact("water"), num!(100));
+        assert!(Costs::is_sub_lt_0(&costs, &Costs::new_with_impact("water", num!(301))));
+        assert!(!Costs::new_with_impact("water", num!(1)).is_zero());
+
+        let mut costs4 = costs.clone();
+        assert_eq!(costs4.try_track_impact("water", num!(-1)), Err(Error::NegativeCosts));
+        costs4.try_track_impact("water", num!(1)).unwrap();
+        assert_eq!(costs4.get_impact("water"), num!(301));
+    }
+
+    #[test]
+    fn apply_transforms() {
+        let now = util::time::now();
+        let make_transform = |from: &str, constituents: Vec<(&str, Decimal)>| {
+            ResourceTransform::builder()
+                .id(ResourceTransformID::create())
+                .resource_spec_id(from)
+                .constituents(constituents.into_iter().map(|(k, v)| (k.into(), v)).collect::<HashMap<_, _>>())
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        let table = vec![
+            make_transform("gasoline", vec![("crude oil", num!(1.8))]),
+            make_transform("engine", vec![("steel", num!(80)), ("gasoline", num!(2))]),
+            make_transform("steel", vec![("iron ore", num!(1.6)), ("coal", num!(0.8))]),
+        ];
+        let mut costs = Costs::new_with_resource("engine", num!(2), num!(100));
+        costs.track_resource("gasoline", num!(10), num!(2));
+        costs.track_resource("sand", num!(4), num!(1));
+        costs.track_labor("machinist", num!(40));
+
+        let costs2 = costs.apply_transforms(&table);
+        assert_eq!(costs2.resource().len(), 4);
+        assert_eq!(costs2.get_resource("crude oil"), num!(1.8) * num!(14));
+        assert_eq!(costs2.get_resource("iron ore"), num!(1.6) * num!(160));
+        assert_eq!(costs2.get_resource("coal"), num!(0.8) * num!(160));
+        assert_eq!(costs2.get_resource("sand"), num!(4));
+        assert_eq!(costs2.credits(), costs.credits());
+        assert_eq!(costs2.labor(), costs.labor());
+
+        // inactive transforms are ignored
+        let mut table2 = table.clone();
+        table2[0].set_active(false);
+        assert_eq!(costs.apply_transforms(&table2).get_resource("gasoline"), num!(14));
+
+        // cycles don't loop forever
+        let table3 = vec![
+            make_transform("chicken", vec![("egg", num!(2))]),
+            make_transform("egg", vec![("chicken", num!(0.5))]),
+        ];
+        let costs3 = Costs::new_with_resource("chicken", num!(1), num!(1)).apply_transforms(&table3);
+        assert_eq!(costs3.resource().len(), 1);
+
+        assert_eq!(costs.apply_transforms(&vec![]), costs);
+    }
+
+    #[test]
+    fn split() {
+        let mut costs = Costs::new();
+        costs.track_labor("widgetmaker", num!(10));
+        costs.track_resource("oil", num!(1), num!(2.2));
+        costs.track_labor_hours("widgetmaker", num!(3));
+
+        let thirds = vec![Ratio::new(num!(1) / num!(3)).unwrap(); 3];
+        let parts = costs.split(&thirds);
+        assert_eq!(parts.len(), 3);
+        // the first part gets the rounding remainder
+        assert_eq!(parts[1], costs.clone() * (num!(1) / num!(3)));
+        assert_eq!(parts[2], parts[1]);
+        assert_eq!(parts[0], costs.clone() - (parts[1].clone() * num!(2)));
+        assert_eq!(parts.iter().fold(Costs::new(), |acc, x| acc + x.clone()), costs);
+
+        let parts = costs.split(&vec![Ratio::new(num!(0.25)).unwrap(), Ratio::new(num!(0.75)).unwrap()]);
+        assert_eq!(parts[0], costs.clone() * num!(0.25));
+        assert_eq!(parts[1], costs.clone() * num!(0.75));
+
+        // ratios are weighed against each other
+        let parts = costs.split(&vec![Ratio::new(num!(0.1)).unwrap(), Ratio::new(num!(0.1)).unwrap()]);
+        assert_eq!(parts[0], costs.clone() * num!(0.5));
+        assert_eq!(parts[0].clone() + parts[1].clone(), costs);
+
+        let parts = costs.split(&vec![Ratio::new(num!(0)).unwrap(), Ratio::new(num!(0)).unwrap()]);
+        assert_eq!(parts[0], costs.clone() * num!(0.5));
+        let parts = costs.split(&vec![Ratio::new(num!(0)).unwrap(), Ratio::new(num!(1)).unwrap()]);
+        assert_eq!(parts[0], Costs::new());
+        assert_eq!(parts[1], costs);
+        assert_eq!(costs.split(&vec![]), Vec::<Costs>::new());
+    }
+
+    #[test]
+    fn per_unit() {
+        let costs = Costs::new_with_labor("widgetmaker", num!(10));
+        assert_eq!(costs.per_unit(num!(4)), Costs::new_with_labor("widgetmaker", num!(2.5)));
+        assert_eq!(Costs::new().per_unit(num!(4)), Costs::new());
+    }
+
+    #[test]
+    fn try_track() {
+        let mut costs = Costs::new();
+        costs.try_track_resource("oil", num!(5.6), num!(2.2)).unwrap();
+        costs.try_track_labor("widgetmaker", num!(6.0)).unwrap();
+        costs.try_track_labor_hours("widgetmaker", num!(1.5)).unwrap();
+        costs.try_track_currency("usd", num!(10), num!(0.5)).unwrap();
+        let mut costs2 = Costs::new();
+        costs2.track_resource("oil", num!(5.6), num!(2.2));
+        costs2.track_labor("widgetmaker", num!(6.0));
+        costs2.track_labor_hours("widgetmaker", num!(1.5));
+        costs2.track_currency("usd", num!(10), num!(0.5));
+        assert_eq!(costs, costs2);
+
+        assert_eq!(costs.try_track_resource("oil", num!(-1), num!(2.2)), Err(Error::NegativeCosts));
+        assert_eq!(costs.try_track_labor("widgetmaker", num!(-1)), Err(Error::NegativeCosts));
+        assert_eq!(costs.try_track_labor_hours("widgetmaker", num!(-1)), Err(Error::NegativeCosts));
+        assert_eq!(costs.try_track_currency("usd", num!(-1), num!(0.5)), Err(Error::NegativeCosts));
+        assert_eq!(costs.try_track_labor("widgetmaker", Decimal::MAX), Err(Error::CostOverflow));
+        assert_eq!(costs.try_track_resource("oil", Decimal::MAX, num!(2)), Err(Error::CostOverflow));
+        // failures leave the costs untouched
+        assert_eq!(costs, costs2);
+    }
+
     #[test]
     #[should_panic]
     fn div_f64_by_0() {
@@ -588,6 +1133,44 @@ mod tests {
         assert!(!Costs::new_with_labor("dictator", num!(4.0)).is_zero());
     }
 
+    #[test]
+    fn iter() {
+        let mut costs = Costs::new();
+        costs.track_labor("machinist", num!(10));
+        costs.track_labor("janitor", num!(4));
+        costs.track_labor_hours("machinist", num!(2));
+        costs.track_resource("steel", num!(3), num!(1.5));
+        costs.track_currency("usd", num!(8), num!(0.5));
+        costs.track_impact("co2e", num!(12));
+        let items = costs.iter().collect::<Vec<_>>();
+        assert_eq!(items, vec![
+            (CostBucket::Credits, "", num!(22.5)),
+            (CostBucket::Resource, "steel", num!(3)),
+            (CostBucket::Labor, "janitor", num!(4)),
+            (CostBucket::Labor, "machinist", num!(10)),
+            (CostBucket::LaborHours, "machinist", num!(2)),
+            (CostBucket::Currency, "usd", num!(8)),
+            (CostBucket::Impact, "co2e", num!(12)),
+        ]);
+        assert_eq!(CostBucket::LaborHours.name(), "labor_hours");
+        assert_eq!(CostBucket::Credits.name(), "credits");
+
+        // iterating gets us back the same costs
+        let mut costs2 = Costs::new();
+        for (bucket, id, val) in costs.iter() {
+            match bucket {
+                CostBucket::Credits => { costs2.set_credits(val); }
+                CostBucket::Resource => { costs2.resource_mut().insert(id.into(), val); }
+                CostBucket::Labor => { costs2.labor_mut().insert(id.into(), val); }
+                CostBucket::LaborHours => { costs2.labor_hours_mut().insert(id.into(), val); }
+                CostBucket::Currency => { costs2.currency_mut().insert(id.into(), val); }
+                CostBucket::Impact => { costs2.impact_mut().insert(id.into(), val); }
+            }
+        }
+        assert_eq!(costs2, costs);
+        assert_eq!(Costs::new().iter().collect::<Vec<_>>(), vec![(CostBucket::Credits, "", num!(0))]);
+    }
+
     #[cfg(feature = "with_serde")]
     #[test]
     fn serialize() {
@@ -623,7 +1206,31 @@ mod tests {
         assert_eq!(costs.credits(), &total);
         let ratio = Ratio::new(num!(1) / num!(3)).unwrap();
         let costs2 = costs * ratio.clone();
-        assert_eq!(costs2.credits(), &(total * ratio.inner().clone()));
+        assert_eq!(costs2.credits(), &Costs::do_round(&(total * ratio.inner().clone())));
+    }
+
+    #[test]
+    fn track_currency_at() {
+        let now = crate::util::time::now();
+        let mut currency = Currency::builder()
+            .id("usd")
+            .name("usd")
+            .decimal_places(2)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let mut costs = Costs::new();
+        let res = costs.track_currency_at(&currency, num!(10), &now);
+        assert_eq!(res, Err(Error::CurrencyRateMissing));
+        assert_eq!(costs, Costs::new());
+
+        currency.push_rate(CurrencyRate::new(num!(0.9973), "2020-01-01T00:00:00Z".parse().unwrap()));
+        currency.push_rate(CurrencyRate::new(num!(1.0221), "2020-06-01T00:00:00Z".parse().unwrap()));
+        costs.track_currency_at(&currency, num!(10), &"2020-03-01T00:00:00Z".parse().unwrap()).unwrap();
+        costs.track_currency_at(&currency, num!(5), &now).unwrap();
+        assert_eq!(costs.get_currency("usd"), num!(15));
+        assert_eq!(costs.credits(), &(num!(10) * num!(0.9973) + num!(5) * num!(1.0221)));
     }
 
     #[test]
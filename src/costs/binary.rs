@@ -0,0 +1,208 @@
+//! A compact binary encoding for `Costs`, enabled via the `binary` feature.
+//!
+//! Costs live on nearly every model that moves through the system, and their
+//! JSON representation (one object per bucket, decimals as strings/floats) is
+//! large compared to the data it holds. This module provides a simple,
+//! dependency-free encoding for implementors who store or transmit costs in a
+//! space-constrained medium (chains, embedded storage, etc).
+//!
+//! The format is:
+//!
+//! - one version byte
+//! - the `credits` value as a 16-byte decimal
+//! - for each of the `resource`, `labor`, `labor_hours`, `currency`, and
+//! `impact` buckets, a varint entry count followed by each entry as a
+//! varint-prefixed UTF-8 key and a 16-byte decimal value
+//!
+//! Version 1 of the format has no `impact` bucket, and can still be decoded.
+//!
+//! Entries are written sorted by key, so equal costs always encode to the same
+//! bytes.
+//!
+//! With the `with_serde` feature also enabled, whole models can be encoded via
+//! `Model::to_bytes()` and `Event::to_bytes()`.
+
+use crate::{
+    costs::Costs,
+    error::{Error, Result},
+};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The version of the encoding this module writes.
+const VERSION: u8 = 2;
+
+/// Append an unsigned LEB128 varint to a buffer.
+pub(crate) fn write_varint(buf: &mut Vec<u8>, mut val: u64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Append a bucket of costs to a buffer.
+fn write_bucket<K: Clone + Into<String>>(buf: &mut Vec<u8>, bucket: &HashMap<K, Decimal>) {
+    let mut entries = bucket.iter()
+        .map(|(key, val)| (key.clone().into(), val))
+        .collect::<Vec<(String, _)>>();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    write_varint(buf, entries.len() as u64);
+    for (key, val) in entries {
+        let key = key.as_bytes();
+        write_varint(buf, key.len() as u64);
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(&val.serialize());
+    }
+}
+
+/// Reads values off the front of a byte slice.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            Err(Error::BinaryDecodeFailed("unexpected end of input".into()))?;
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64> {
+        let mut val: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            val |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+        Err(Error::BinaryDecodeFailed("varint too long".into()))
+    }
+
+    fn decimal(&mut self) -> Result<Decimal> {
+        let mut buf = [0u8; 16];
+        buf.copy_from_slice(self.take(16)?);
+        Ok(Decimal::deserialize(buf))
+    }
+
+    fn bucket<K: From<String> + Eq + Hash>(&mut self) -> Result<HashMap<K, Decimal>> {
+        let count = self.varint()?;
+        let mut bucket = HashMap::new();
+        for _ in 0..count {
+            let len = self.varint()? as usize;
+            let key = String::from_utf8(self.take(len)?.to_vec())
+                .map_err(|e| Error::BinaryDecodeFailed(format!("{}", e)))?;
+            bucket.insert(K::from(key), self.decimal()?);
+        }
+        Ok(bucket)
+    }
+}
+
+impl Costs {
+    /// Encode these costs into the compact binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![VERSION];
+        buf.extend_from_slice(&self.credits().serialize());
+        write_bucket(&mut buf, self.resource());
+        write_bucket(&mut buf, self.labor());
+        write_bucket(&mut buf, self.labor_hours());
+        write_bucket(&mut buf, self.currency());
+        write_bucket(&mut buf, self.impact());
+        buf
+    }
+
+    /// Decode costs from the compact binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let version = reader.take(1)?[0];
+        if version == 0 || version > VERSION {
+            Err(Error::BinaryDecodeFailed(format!("unknown version {}", version)))?;
+        }
+        let mut costs = Costs::new();
+        costs.set_credits(reader.decimal()?);
+        costs.set_resource(reader.bucket()?);
+        costs.set_labor(reader.bucket()?);
+        costs.set_labor_hours(reader.bucket()?);
+        costs.set_currency(reader.bucket()?);
+        if version >= 2 {
+            costs.set_impact(reader.bucket()?);
+        }
+        if !reader.is_empty() {
+            Err(Error::BinaryDecodeFailed("trailing data".into()))?;
+        }
+        Ok(costs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let costs = Costs::new();
+        let bytes = costs.to_bytes();
+        assert_eq!(bytes.len(), 1 + 16 + 5);
+        assert_eq!(Costs::from_bytes(&bytes).unwrap(), costs);
+
+        let mut costs = Costs::new();
+        costs.track_resource("gasoline", num!(0.4), num!(1.3));
+        costs.track_resource("iron", num!(2.2), num!(0.0019));
+        costs.track_labor("ceo", num!(42.0));
+        costs.track_labor("machinist", num!(122.0));
+        costs.track_labor_hours("machinist", num!(8.0));
+        costs.track_currency("usd", num!(42.00), num!(0.99891));
+        costs.track_impact("co2e", num!(3.2));
+        let bytes = costs.to_bytes();
+        assert_eq!(Costs::from_bytes(&bytes).unwrap(), costs);
+        // deterministic
+        assert_eq!(costs.clone().to_bytes(), bytes);
+
+        let costs2 = costs.clone() * num!(-1.3333333);
+        assert_eq!(Costs::from_bytes(&costs2.to_bytes()).unwrap(), costs2);
+
+        // long keys need multi-byte varints
+        let costs3 = Costs::new_with_labor("a".repeat(300), num!(1));
+        assert_eq!(Costs::from_bytes(&costs3.to_bytes()).unwrap(), costs3);
+    }
+
+    #[test]
+    fn decodes_v1() {
+        let costs = Costs::new_with_labor("ceo", num!(42));
+        // a v1 encoding is a v2 encoding minus the (empty) impact bucket
+        let mut bytes = costs.to_bytes();
+        bytes[0] = 1;
+        bytes.pop();
+        assert_eq!(Costs::from_bytes(&bytes).unwrap(), costs);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let bytes = Costs::new_with_labor("ceo", num!(42)).to_bytes();
+        assert_eq!(Costs::from_bytes(&[]), Err(Error::BinaryDecodeFailed("unexpected end of input".into())));
+        assert_eq!(Costs::from_bytes(&bytes[0..bytes.len() - 1]), Err(Error::BinaryDecodeFailed("unexpected end of input".into())));
+        let mut bytes2 = bytes.clone();
+        bytes2.push(0);
+        assert_eq!(Costs::from_bytes(&bytes2), Err(Error::BinaryDecodeFailed("trailing data".into())));
+        let mut bytes3 = bytes.clone();
+        bytes3[0] = 99;
+        assert_eq!(Costs::from_bytes(&bytes3), Err(Error::BinaryDecodeFailed("unknown version 99".into())));
+    }
+}
@@ -0,0 +1,218 @@
+//! Breakdowns turn the costs of a resource into something that can be shown to
+//! a consumer: how much of what they're paying for went to each occupation's
+//! labor, which resources went into it, and how much outside currency it took
+//! to make.
+//!
+//! Note that once resources and currencies are tracked in a `Costs` object,
+//! only their *amounts* are kept (their credit values are folded into the
+//! aggregate `credits`). So labor can be broken down as a percentage of the
+//! total credits per-occupation, but resources and currencies can only be given
+//! a combined percentage (whatever is left over after labor).
+
+use crate::{
+    costs::{Costs, pricing},
+    error::{Error, Result},
+    models::{
+        currency::CurrencyID,
+        occupation::OccupationID,
+        resource::Resource,
+        resource_spec::ResourceSpecID,
+    },
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// The labor of one occupation that went into a resource.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct LaborShare {
+    /// The occupation
+    occupation_id: OccupationID,
+    /// The wages (credits) paid for this occupation's labor
+    wages: Decimal,
+    /// The hours this occupation worked
+    hours: Decimal,
+    /// The wages as a percentage (0-100) of the total credits
+    percent: Decimal,
+}
+
+/// The amount of one resource that went into a resource.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ResourceShare {
+    /// The resource spec
+    resource_spec_id: ResourceSpecID,
+    /// The amount of the resource, in the spec's standard unit
+    amount: Decimal,
+}
+
+/// The amount of one currency that went into a resource.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct CurrencyShare {
+    /// The currency
+    currency_id: CurrencyID,
+    /// The amount of currency
+    amount: Decimal,
+}
+
+/// A consumer-facing breakdown of the costs of some quantity of a resource.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct CostBreakdown {
+    /// The quantity (in the resource's unit) this breakdown is for
+    quantity: Decimal,
+    /// The total credits of the quantity
+    credits: Decimal,
+    /// Labor per occupation, largest share first
+    labor: Vec<LaborShare>,
+    /// Labor's combined percentage (0-100) of the total credits
+    labor_percent: Decimal,
+    /// The resources that went into the quantity, sorted by spec
+    resources: Vec<ResourceShare>,
+    /// The currencies that went into the quantity, sorted by currency
+    currency: Vec<CurrencyShare>,
+    /// The percentage (0-100) of the total credits that went to resources and
+    /// currencies
+    other_percent: Decimal,
+    /// The costs the breakdown was derived from
+    costs: Costs,
+}
+
+/// Get a value as a percentage of a total, rounded to two decimal places.
+fn percent_of(val: &Decimal, total: &Decimal) -> Decimal {
+    if total.is_zero() {
+        return Decimal::zero();
+    }
+    ((val.clone() / total.clone()) * Decimal::from(100)).round_dp(2)
+}
+
+/// Explain the costs of some quantity (in the resource's unit) of a resource.
+pub fn explain(resource: &Resource, quantity: Decimal) -> Result<CostBreakdown> {
+    if quantity <= Decimal::zero() {
+        Err(Error::InvalidAmount(quantity))?;
+    }
+    let costs = pricing::costs_for_quantity(resource, quantity)?;
+    let credits = costs.credits().clone();
+
+    let mut labor = costs.labor().iter()
+        .map(|(occupation_id, wages)| {
+            LaborShare {
+                occupation_id: occupation_id.clone(),
+                wages: wages.clone(),
+                hours: costs.get_labor_hours(occupation_id.clone()),
+                percent: percent_of(wages, &credits),
+            }
+        })
+        .collect::<Vec<_>>();
+    labor.sort_by(|a, b| b.wages().cmp(a.wages()).then_with(|| a.occupation_id().as_str().cmp(b.occupation_id().as_str())));
+    let labor_total = labor.iter().fold(Decimal::zero(), |acc, x| acc + x.wages().clone());
+
+    let mut resources = costs.resource().iter()
+        .map(|(resource_spec_id, amount)| {
+            ResourceShare {
+                resource_spec_id: resource_spec_id.clone(),
+                amount: amount.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+    resources.sort_by(|a, b| a.resource_spec_id().as_str().cmp(b.resource_spec_id().as_str()));
+
+    let mut currency = costs.currency().iter()
+        .map(|(currency_id, amount)| {
+            CurrencyShare {
+                currency_id: currency_id.clone(),
+                amount: amount.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+    currency.sort_by(|a, b| a.currency_id().as_str().cmp(b.currency_id().as_str()));
+
+    let other = credits.clone() - labor_total.clone();
+    let other = if other < Decimal::zero() { Decimal::zero() } else { other };
+    Ok(CostBreakdown {
+        quantity,
+        credits: credits.clone(),
+        labor,
+        labor_percent: percent_of(&labor_total, &credits),
+        resources,
+        currency,
+        other_percent: percent_of(&other, &credits),
+        costs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            resource::ResourceID,
+        },
+        util::{self, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn explains() {
+        let now = util::time::now();
+        let mut costs = Costs::new();
+        costs.track_labor("machinist", num!(60));
+        costs.track_labor_hours("machinist", num!(3));
+        costs.track_labor("ceo", num!(20));
+        costs.track_labor_hours("ceo", num!(0.5));
+        costs.track_resource("steel", num!(8), num!(1.5));
+        costs.track_resource("aluminum", num!(2), num!(4));
+        costs.track_currency("usd", num!(4), num!(5));
+        let resource = make_resource(&ResourceID::new("widget"), &CompanyID::new("jerry's widgets"), &Measure::new(num!(10), Unit::One), &costs, &now);
+
+        // 120 credits for all ten, so five is 60 credits
+        let breakdown = explain(&resource, num!(5)).unwrap();
+        assert_eq!(breakdown.quantity(), &num!(5));
+        assert_eq!(breakdown.credits(), &num!(60));
+        assert_eq!(breakdown.costs(), &(costs.clone() * num!(0.5)));
+
+        assert_eq!(breakdown.labor().len(), 2);
+        assert_eq!(breakdown.labor()[0].occupation_id(), &OccupationID::new("machinist"));
+        assert_eq!(breakdown.labor()[0].wages(), &num!(30));
+        assert_eq!(breakdown.labor()[0].hours(), &num!(1.5));
+        assert_eq!(breakdown.labor()[0].percent(), &num!(50));
+        assert_eq!(breakdown.labor()[1].occupation_id(), &OccupationID::new("ceo"));
+        assert_eq!(breakdown.labor()[1].wages(), &num!(10));
+        assert_eq!(breakdown.labor()[1].hours(), &num!(0.25));
+        assert_eq!(breakdown.labor()[1].percent(), &num!(16.67));
+        assert_eq!(breakdown.labor_percent(), &num!(66.67));
+
+        assert_eq!(breakdown.resources().len(), 2);
+        assert_eq!(breakdown.resources()[0].resource_spec_id(), &ResourceSpecID::new("aluminum"));
+        assert_eq!(breakdown.resources()[0].amount(), &num!(1));
+        assert_eq!(breakdown.resources()[1].resource_spec_id(), &ResourceSpecID::new("steel"));
+        assert_eq!(breakdown.resources()[1].amount(), &num!(4));
+        assert_eq!(breakdown.currency().len(), 1);
+        assert_eq!(breakdown.currency()[0].currency_id(), &CurrencyID::new("usd"));
+        assert_eq!(breakdown.currency()[0].amount(), &num!(2));
+        assert_eq!(breakdown.other_percent(), &num!(33.33));
+
+        let resource2 = make_resource(&ResourceID::new("widget"), &CompanyID::new("jerry's widgets"), &Measure::new(num!(10), Unit::One), &Costs::new(), &now);
+        let breakdown = explain(&resource2, num!(1)).unwrap();
+        assert_eq!(breakdown.credits(), &num!(0));
+        assert_eq!(breakdown.labor().len(), 0);
+        assert_eq!(breakdown.labor_percent(), &num!(0));
+        assert_eq!(breakdown.other_percent(), &num!(0));
+
+        let res = explain(&resource, num!(0));
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        let mut resource3 = resource.clone();
+        resource3.inner_mut().set_accounting_quantity(None);
+        let res = explain(&resource3, num!(1));
+        assert_eq!(res, Err(Error::ResourceMeasureMissing));
+    }
+}
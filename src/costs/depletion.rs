@@ -0,0 +1,266 @@
+//! Depletion tracking weighs how quickly we extract resources from a region
+//! against how quickly those resources are renewed or recycled.
+//!
+//! As mentioned in the [costs module docs][1], one of the goals of cost
+//! tracking is to make us globally aware of our depletion rates of resources
+//! versus their renewal/recycle rates. To get there, resources representing
+//! natural stocks (a forest, an aquifer, an ore deposit) are tagged with the
+//! region they're located in, and the events that operate on those resources
+//! are tallied per region and per resource specification:
+//!
+//! - **extraction** is any event that takes quantity out of a region-tagged
+//! resource: `consume`, `lower`, write-offs, and transfers or moves into a
+//! resource that isn't in the same region.
+//! - **renewal** is a `raise` event marked with a [Renewal], generally created
+//! via [`accounting::renew`][2]. Plain `raise` events are treated as accounting
+//! corrections and are not counted.
+//!
+//! Quantities are summed in whatever unit the resources are measured in, so
+//! resources of the same specification should use the same unit.
+//!
+//! [1]: ../index.html
+//! [2]: ../../transactions/event/accounting/fn.renew.html
+//! [Renewal]: ../../models/event/enum.Renewal.html
+
+use crate::{
+    error::Result,
+    models::{
+        event::{Event, EventError, MoveType, Renewal},
+        lib::basis_model::Model,
+        region::RegionID,
+        resource::{Resource, ResourceID},
+        resource_spec::ResourceSpecID,
+    },
+    util::measure,
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use vf_rs::vf;
+
+/// Tallies the extraction and renewal of resources within a single region,
+/// keyed by resource specification.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct RegionDepletion {
+    /// The region we're tracking
+    region_id: RegionID,
+    /// How much of each resource has been extracted
+    extracted: HashMap<ResourceSpecID, Decimal>,
+    /// How much of each resource has regenerated
+    regenerated: HashMap<ResourceSpecID, Decimal>,
+    /// How much of each resource has been recycled
+    recycled: HashMap<ResourceSpecID, Decimal>,
+}
+
+impl RegionDepletion {
+    /// Create a new, empty depletion tally for a region
+    pub fn new(region_id: RegionID) -> Self {
+        Self {
+            region_id,
+            extracted: HashMap::new(),
+            regenerated: HashMap::new(),
+            recycled: HashMap::new(),
+        }
+    }
+
+    /// Get the amount of a resource that has been extracted
+    pub fn get_extracted<T: Into<ResourceSpecID>>(&self, spec_id: T) -> Decimal {
+        self.extracted.get(&spec_id.into()).cloned().unwrap_or(Decimal::zero())
+    }
+
+    /// Get the amount of a resource that has been renewed (regenerated plus
+    /// recycled)
+    pub fn get_renewed<T: Into<ResourceSpecID>>(&self, spec_id: T) -> Decimal {
+        let spec_id = spec_id.into();
+        self.regenerated.get(&spec_id).cloned().unwrap_or(Decimal::zero()) +
+            self.recycled.get(&spec_id).cloned().unwrap_or(Decimal::zero())
+    }
+
+    /// Get the net change of a resource in this region (`renewed - extracted`).
+    /// A negative value means the resource is being depleted.
+    pub fn net<T: Into<ResourceSpecID>>(&self, spec_id: T) -> Decimal {
+        let spec_id = spec_id.into();
+        self.get_renewed(spec_id.clone()) - self.get_extracted(spec_id)
+    }
+
+    /// Determine if a resource is being extracted faster than it's renewed
+    pub fn is_depleting<T: Into<ResourceSpecID>>(&self, spec_id: T) -> bool {
+        self.net(spec_id) < Decimal::zero()
+    }
+
+    /// Add a quantity to one of our tallies
+    fn track(tally: &mut HashMap<ResourceSpecID, Decimal>, spec_id: &ResourceSpecID, quantity: Decimal) {
+        let entry = tally.entry(spec_id.clone()).or_insert(Decimal::zero());
+        *entry += quantity;
+    }
+}
+
+/// Tally resource extraction and renewal per region given a set of events and
+/// the resources they operate on.
+///
+/// Events whose resource isn't tagged with a region (or isn't present in
+/// `resources`) are ignored, as are deleted and pending events.
+pub fn by_region(resources: &[Resource], events: &[Event]) -> Result<HashMap<RegionID, RegionDepletion>> {
+    let resource_map = resources.iter()
+        .map(|r| (r.id().clone(), r))
+        .collect::<HashMap<ResourceID, &Resource>>();
+    let mut regions: HashMap<RegionID, RegionDepletion> = HashMap::new();
+    for event in events {
+        if event.is_deleted() {
+            continue;
+        }
+        if event.inner().has_beginning().is_some() && event.inner().has_end().is_none() {
+            continue;
+        }
+        let resource = match event.inner().resource_inventoried_as().as_ref().and_then(|id| resource_map.get(id)) {
+            Some(resource) => resource,
+            None => continue,
+        };
+        let region_id = match resource.region_id() {
+            Some(region_id) => region_id,
+            None => continue,
+        };
+        let leaves_region = || {
+            event.inner().to_resource_inventoried_as().as_ref()
+                .and_then(|id| resource_map.get(id))
+                .map(|to| to.region_id().as_ref() != Some(region_id))
+                .unwrap_or(true)
+        };
+        let renewal = event.renewal().as_ref();
+        let is_extraction = match event.inner().action() {
+            vf::Action::Consume | vf::Action::Lower => true,
+            vf::Action::Move => match event.move_type() {
+                Some(MoveType::WriteOff) | Some(MoveType::ExternalSale(..)) => true,
+                Some(MoveType::Resource) => leaves_region(),
+                _ => false,
+            },
+            vf::Action::Transfer | vf::Action::TransferAllRights | vf::Action::TransferCustody => leaves_region(),
+            _ => false,
+        };
+        let is_renewal = event.inner().action() == &vf::Action::Raise && renewal.is_some();
+        if !is_extraction && !is_renewal {
+            continue;
+        }
+        let quantity = event.inner().resource_quantity().as_ref().ok_or(EventError::MissingEventMeasure)?;
+        let quantity = measure::to_decimal(quantity)?;
+        let spec_id = resource.inner().conforms_to();
+        let tally = regions.entry(region_id.clone()).or_insert_with(|| RegionDepletion::new(region_id.clone()));
+        match (is_extraction, renewal) {
+            (true, _) => RegionDepletion::track(&mut tally.extracted, spec_id, quantity),
+            (false, Some(Renewal::Regeneration)) => RegionDepletion::track(&mut tally.regenerated, spec_id, quantity),
+            (false, Some(Renewal::Recycling)) => RegionDepletion::track(&mut tally.recycled, spec_id, quantity),
+            (false, None) => {}
+        }
+    }
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            event::EventID,
+        },
+        util::{self, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    fn make_region_resource<T: Into<ResourceID>>(id: T, spec_id: &str, region_id: Option<&str>) -> Resource {
+        let now = util::time::now();
+        let mut resource = make_resource(&id.into(), &CompanyID::new("jerry's logging"), &Measure::new(num!(1000), Unit::One), &Costs::new(), &now);
+        resource.inner_mut().set_conforms_to(spec_id.into());
+        resource.set_region_id(region_id.map(|x| x.into()));
+        resource
+    }
+
+    fn make_event(action: vf::Action, resource_id: &str, to_resource_id: Option<&str>, quantity: Decimal) -> Event {
+        let now = util::time::now();
+        Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action)
+                    .has_point_in_time(now.clone())
+                    .provider(CompanyID::new("jerry's logging"))
+                    .receiver(CompanyID::new("jerry's logging"))
+                    .resource_inventoried_as(Some(resource_id.into()))
+                    .to_resource_inventoried_as(to_resource_id.map(|x| x.into()))
+                    .resource_quantity(Some(Measure::new(quantity, Unit::One)))
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn tallies_by_region() {
+        let now = util::time::now();
+        let resources = vec![
+            make_region_resource("forest", "timber", Some("cascadia")),
+            make_region_resource("forest-depot", "timber", Some("cascadia")),
+            make_region_resource("mine", "copper", Some("cascadia")),
+            make_region_resource("aquifer", "water", Some("ogallala")),
+            make_region_resource("lumberyard", "timber", None),
+        ];
+        let mut regrowth = make_event(vf::Action::Raise, "forest", None, num!(40));
+        regrowth.set_renewal(Some(Renewal::Regeneration));
+        let mut recycle = make_event(vf::Action::Raise, "mine", None, num!(3));
+        recycle.set_renewal(Some(Renewal::Recycling));
+        let mut moved_internal = make_event(vf::Action::Move, "forest", Some("forest-depot"), num!(10));
+        moved_internal.set_move_type(Some(MoveType::Resource));
+        let mut deleted = make_event(vf::Action::Consume, "forest", None, num!(1000));
+        deleted.set_deleted(Some(now.clone()));
+        let mut pending = make_event(vf::Action::Consume, "forest", None, num!(1000));
+        pending.inner_mut().set_has_point_in_time(None);
+        pending.inner_mut().set_has_beginning(Some(now.clone()));
+        let events = vec![
+            make_event(vf::Action::Consume, "forest", None, num!(50)),
+            make_event(vf::Action::Transfer, "forest", Some("lumberyard"), num!(20)),
+            moved_internal,
+            regrowth,
+            // plain raises are accounting corrections, not renewals
+            make_event(vf::Action::Raise, "forest", None, num!(500)),
+            make_event(vf::Action::Consume, "mine", None, num!(5)),
+            recycle,
+            make_event(vf::Action::Lower, "aquifer", None, num!(12)),
+            // no region
+            make_event(vf::Action::Consume, "lumberyard", None, num!(7)),
+            deleted,
+            pending,
+        ];
+
+        let regions = by_region(&resources, &events).unwrap();
+        assert_eq!(regions.len(), 2);
+        let cascadia = regions.get(&RegionID::new("cascadia")).unwrap();
+        assert_eq!(cascadia.region_id(), &RegionID::new("cascadia"));
+        assert_eq!(cascadia.get_extracted("timber"), num!(70));
+        assert_eq!(cascadia.get_renewed("timber"), num!(40));
+        assert_eq!(cascadia.net("timber"), num!(-30));
+        assert!(cascadia.is_depleting("timber"));
+        assert_eq!(cascadia.get_extracted("copper"), num!(5));
+        assert_eq!(cascadia.recycled().get(&ResourceSpecID::new("copper")), Some(&num!(3)));
+        assert_eq!(cascadia.get_renewed("copper"), num!(3));
+        assert!(cascadia.is_depleting("copper"));
+        assert_eq!(cascadia.get_extracted("water"), num!(0));
+        assert!(!cascadia.is_depleting("water"));
+
+        let ogallala = regions.get(&RegionID::new("ogallala")).unwrap();
+        assert_eq!(ogallala.get_extracted("water"), num!(12));
+        assert_eq!(ogallala.get_renewed("water"), num!(0));
+
+        let mut events2 = events.clone();
+        events2.push(make_event(vf::Action::Consume, "forest", None, num!(0)));
+        events2[0].inner_mut().set_resource_quantity(None);
+        let res = by_region(&resources, &events2);
+        assert_eq!(res, Err(EventError::MissingEventMeasure.into()));
+    }
+}
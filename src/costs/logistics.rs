@@ -0,0 +1,192 @@
+//! Logistics estimates the costs of moving goods from one place to another.
+//!
+//! Shipping costs depend on how heavy and how bulky the goods are, and how far
+//! they're going. Given a resource spec's [dimensions][1] and the distance
+//! between two [regions][2], `estimate_shipping()` applies a set of
+//! `ShippingRates` to come up with the labor, resource, and currency costs of
+//! a shipment. This is an estimate (for quoting orders or comparing intents),
+//! not a record of costs actually moved.
+//!
+//! Like most carriers, we charge by weight or by volume, whichever costs more
+//! (in credits), so bulky-but-light goods aren't undercharged.
+//!
+//! [1]: ../../models/resource_spec/struct.Dimensions.html
+//! [2]: ../../models/region/index.html
+
+use crate::{
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        region::Region,
+        resource_spec::ResourceSpec,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::{Measure, Unit};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// The rates used to estimate shipping costs.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ShippingRates {
+    /// The costs of moving one tonne one kilometre
+    per_tonne_km: Costs,
+    /// The costs of moving one cubic metre one kilometre
+    per_cubic_metre_km: Costs,
+    /// A flat cost per shipment (loading, unloading, paperwork, etc)
+    handling: Costs,
+}
+
+impl ShippingRates {
+    /// Create a new set of shipping rates
+    pub fn new(per_tonne_km: Costs, per_cubic_metre_km: Costs, handling: Costs) -> Self {
+        Self { per_tonne_km, per_cubic_metre_km, handling }
+    }
+}
+
+/// Figure out how many units of a spec (in its default unit) a quantity holds.
+/// The quantity can be given in the spec's default unit, or in a unit of
+/// weight/volume if the spec has the matching dimension.
+fn spec_units(spec: &ResourceSpec, quantity: &Measure) -> Result<Decimal> {
+    let unit = quantity.has_unit();
+    if let Some(default_unit) = spec.inner().default_unit_of_resource() {
+        if measure::is_convertible(unit, default_unit) {
+            return measure::to_decimal(&measure::convert(quantity, default_unit)?);
+        }
+    }
+    if let Some(dimensions) = spec.dimensions() {
+        for per_unit in vec![dimensions.weight(), dimensions.volume()].into_iter().flatten() {
+            if measure::is_convertible(unit, per_unit.has_unit()) {
+                let amount = measure::to_decimal(&measure::convert(quantity, per_unit.has_unit())?)?;
+                let per_unit = measure::to_decimal(per_unit)?;
+                if per_unit.is_zero() {
+                    Err(Error::InvalidAmount(per_unit))?;
+                }
+                return Ok(amount / per_unit);
+            }
+        }
+    }
+    if spec.inner().default_unit_of_resource().is_none() {
+        return measure::to_decimal(quantity);
+    }
+    Err(Error::ResourceSpecUnitMismatch(format!("{:?}", unit)))
+}
+
+/// Estimate the costs of shipping a quantity of a resource from one region to
+/// another.
+///
+/// The spec must have [dimensions][1] (weight, volume, or both) and both
+/// regions need a location so we can measure the distance between them. The
+/// result is the handling costs plus the greater (in credits) of the
+/// weight-based and volume-based costs.
+///
+/// [1]: ../../models/resource_spec/struct.Dimensions.html
+pub fn estimate_shipping(spec: &ResourceSpec, quantity: &Measure, from_region: &Region, to_region: &Region, rates: &ShippingRates) -> Result<Costs> {
+    let dimensions = spec.dimensions().as_ref()
+        .ok_or(Error::MissingFields(vec!["dimensions".into()]))?;
+    let km = from_region.distance_km(to_region)
+        .ok_or(Error::MissingFields(vec!["location".into()]))?;
+    let units = spec_units(spec, quantity)?;
+    if units < Decimal::zero() {
+        Err(Error::NegativeMeasurement)?;
+    }
+    let by_weight = match dimensions.weight() {
+        Some(weight) => {
+            let tonnes = measure::to_decimal(&measure::convert(weight, &Unit::Tonne)?)? * units;
+            Some(rates.per_tonne_km().clone() * (tonnes * km))
+        }
+        None => None,
+    };
+    let by_volume = match dimensions.volume() {
+        Some(volume) => {
+            let cubic_metres = measure::to_decimal(&measure::convert(volume, &Unit::CubicMetre)?)? * units;
+            Some(rates.per_cubic_metre_km().clone() * (cubic_metres * km))
+        }
+        None => None,
+    };
+    let transport = match (by_weight, by_volume) {
+        (Some(weight), Some(volume)) => {
+            if volume.credits() > weight.credits() { volume } else { weight }
+        }
+        (Some(costs), None) | (None, Some(costs)) => costs,
+        (None, None) => Err(Error::MissingFields(vec!["dimensions".into()]))?,
+    };
+    Ok(rates.handling().clone() + transport)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            region::RegionID,
+            resource_spec::{Dimensions, ResourceSpecID},
+        },
+        util::{self, test::*},
+    };
+    use vf_rs::geo::SpatialThing;
+
+    fn make_region(id: &str, lat: f64, long: f64) -> Region {
+        let now = util::time::now();
+        Region::builder()
+            .id(RegionID::new(id))
+            .name(id)
+            .description(id)
+            .location(Some(SpatialThing::builder().lat(Some(lat)).long(Some(long)).build().unwrap()))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn estimates_shipping() {
+        let now = util::time::now();
+        let from = make_region("north", 0.0, 0.0);
+        let to = make_region("south", -1.0, 0.0);
+        let km = from.distance_km(&to).unwrap();
+        assert_eq!(km, num!(111.195));
+
+        let mut spec = make_resource_spec(&ResourceSpecID::create(), &CompanyID::create(), "barrel of oil", &now);
+        spec.inner_mut().set_default_unit_of_resource(Some(Unit::One));
+        let mut rates = ShippingRates::new(Costs::new_with_labor("trucker", num!(0.1)), Costs::new_with_labor("trucker", num!(0.1)), Costs::new_with_labor("loader", num!(5)));
+        rates.per_tonne_km.track_currency("usd", num!(0.05), num!(1));
+
+        assert_eq!(estimate_shipping(&spec, &Measure::new(num!(10), Unit::One), &from, &to, &rates), Err(Error::MissingFields(vec!["dimensions".into()])));
+
+        // 160kg and 0.2m3 per barrel
+        spec.set_dimensions(Some(Dimensions::new(Some(Measure::new(num!(160), Unit::Kilogram)), Some(Measure::new(num!(200), Unit::Litre))).unwrap()));
+        let costs = estimate_shipping(&spec, &Measure::new(num!(10), Unit::One), &from, &to, &rates).unwrap();
+        // 1.6t * km by weight beats 2m3 * km by volume once currency is counted
+        let mut expected = Costs::new_with_labor("loader", num!(5));
+        expected = expected + (rates.per_tonne_km().clone() * (num!(1.6) * km));
+        assert_eq!(costs, expected);
+
+        // by weight works the same as by count
+        let costs2 = estimate_shipping(&spec, &Measure::new(num!(1.6), Unit::Tonne), &from, &to, &rates).unwrap();
+        assert_eq!(costs2, costs);
+
+        // light, bulky goods are charged by volume
+        spec.set_dimensions(Some(Dimensions::new(Some(Measure::new(num!(1), Unit::Kilogram)), Some(Measure::new(num!(200), Unit::Litre))).unwrap()));
+        let costs3 = estimate_shipping(&spec, &Measure::new(num!(2000), Unit::Litre), &from, &to, &rates).unwrap();
+        assert_eq!(costs3, Costs::new_with_labor("loader", num!(5)) + (rates.per_cubic_metre_km().clone() * (num!(2) * km)));
+
+        // same region, just handling
+        let costs4 = estimate_shipping(&spec, &Measure::new(num!(10), Unit::One), &from, &from, &rates).unwrap();
+        assert_eq!(costs4, Costs::new_with_labor("loader", num!(5)));
+
+        let res = estimate_shipping(&spec, &Measure::new(num!(10), Unit::Metre), &from, &to, &rates);
+        assert_eq!(res, Err(Error::ResourceSpecUnitMismatch("Metre".into())));
+        let res = estimate_shipping(&spec, &Measure::new(num!(-10), Unit::One), &from, &to, &rates);
+        assert_eq!(res, Err(Error::NegativeMeasurement));
+        let mut nowhere = to.clone();
+        nowhere.set_location(None);
+        let res = estimate_shipping(&spec, &Measure::new(num!(10), Unit::One), &from, &nowhere, &rates);
+        assert_eq!(res, Err(Error::MissingFields(vec!["location".into()])));
+    }
+}
@@ -0,0 +1,103 @@
+//! Controls how precise our costs are, and how they're rounded.
+//!
+//! Costs are normalized after every operation (add, subtract, multiply,
+//! divide, track), and part of normalization is rounding each value to a set
+//! number of decimal places. If two implementations of the system disagree on
+//! precision or rounding, the same series of operations can produce costs that
+//! differ in their last few digits, which is enough to break anything comparing
+//! or hashing costs across a network. This module lets an implementation pin
+//! both down.
+//!
+//! All costs math (the `Add`/`Sub`/`Mul`/`Div` operators and the `track_*`
+//! functions) normalizes using the default precision: 16 decimal places with
+//! banker's rounding. There is no global setting: an implementation that wants
+//! different precision passes its `CostPrecision` (generally the network
+//! config's `cost_precision`) explicitly to the functions that take one:
+//!
+//! ```rust
+//! use basis_core::costs::{Costs, precision::{CostPrecision, Rounding}};
+//! use rust_decimal_macros::*;
+//!
+//! let mut costs = Costs::new_with_labor("machinist", dec!(10) / dec!(3));
+//! costs.normalize_with(&CostPrecision::new(2, Rounding::HalfUp));
+//! assert_eq!(costs.get_labor("machinist"), dec!(3.33));
+//! ```
+
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// The number of decimal places costs are rounded to by default.
+pub const DEFAULT_DECIMAL_PLACES: u32 = 16;
+
+/// The strategy used when a value falls exactly between two rounded values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum Rounding {
+    /// Round to the nearest even number (`0.125` -> `0.12`). Also known as
+    /// banker's rounding, this avoids drifting upward over many operations.
+    Bankers,
+    /// Round away from zero (`0.125` -> `0.13`).
+    HalfUp,
+}
+
+/// Determines the precision and rounding of all costs in the system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub struct CostPrecision {
+    /// How many decimal places cost values are rounded to
+    decimal_places: u32,
+    /// How we round values that fall on a midpoint
+    rounding: Rounding,
+}
+
+impl CostPrecision {
+    /// Create a new precision configuration.
+    pub const fn new(decimal_places: u32, rounding: Rounding) -> Self {
+        Self { decimal_places, rounding }
+    }
+
+    /// The number of decimal places values are rounded to.
+    pub fn decimal_places(&self) -> u32 {
+        self.decimal_places
+    }
+
+    /// The rounding strategy used.
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    /// Round a value according to this configuration.
+    pub fn round(&self, val: &Decimal) -> Decimal {
+        let strategy = match self.rounding {
+            Rounding::Bankers => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        };
+        val.round_dp_with_strategy(self.decimal_places, strategy)
+    }
+}
+
+impl Default for CostPrecision {
+    fn default() -> Self {
+        Self::new(DEFAULT_DECIMAL_PLACES, Rounding::Bankers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds() {
+        let bankers = CostPrecision::new(2, Rounding::Bankers);
+        let half_up = CostPrecision::new(2, Rounding::HalfUp);
+        assert_eq!(bankers.round(&num!(0.125)), num!(0.12));
+        assert_eq!(half_up.round(&num!(0.125)), num!(0.13));
+        assert_eq!(bankers.round(&num!(0.135)), num!(0.14));
+        assert_eq!(half_up.round(&num!(0.135)), num!(0.14));
+        assert_eq!(bankers.round(&num!(-0.125)), num!(-0.12));
+        assert_eq!(half_up.round(&num!(-0.125)), num!(-0.13));
+        assert_eq!(half_up.round(&num!(1.119)), num!(1.12));
+        assert_eq!(CostPrecision::default().round(&(num!(1) / num!(3))), num!(0.3333333333333333));
+    }
+}
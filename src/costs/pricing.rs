@@ -0,0 +1,193 @@
+//! Pricing lets companies that interact with outside (capitalist) markets turn
+//! the costs of a resource into a price quote in some external currency.
+//!
+//! The costs of a resource are first scaled to the quantity being sold, then
+//! converted from credits into the target currency using the currency's stored
+//! exchange rate. How that conversion happens is determined by a
+//! `MarkupPolicy`.
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        currency::{Currency, CurrencyID},
+        resource::Resource,
+    },
+    util::measure,
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// Determines how we arrive at a price from a set of costs.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum MarkupPolicy {
+    /// Sell at cost: all credits are converted into the currency at the
+    /// current exchange rate.
+    CostOnly,
+    /// Sell at cost plus a buffer, given as a ratio of the cost (ie, `0.1`
+    /// adds 10% to the price). Useful for absorbing exchange rate swings.
+    CostPlusBuffer(Decimal),
+    /// Any costs already paid in the target currency are passed through at
+    /// their face value, and only the remaining (non-currency) credits are
+    /// converted at the current exchange rate.
+    ///
+    /// The credits the currency costs account for are figured at the exchange
+    /// rate in effect when the resource was created (for resources bought via
+    /// [purchase_external][1], this is when the currency was paid), falling
+    /// back on the current rate if there is none. This way, the currency part
+    /// of a price doesn't move with the exchange rate.
+    ///
+    /// [1]: ../../transactions/market/fn.purchase_external.html
+    CurrencyPassthrough,
+}
+
+/// A price quote in an external currency.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Price {
+    /// The currency this price is quoted in
+    currency_id: CurrencyID,
+    /// The amount of currency, rounded to the currency's decimal places
+    amount: Decimal,
+    /// The costs the price was derived from
+    costs: Costs,
+}
+
+/// Get the costs for some quantity of a resource, based on the resource's
+/// costs per unit.
+pub(crate) fn costs_for_quantity(resource: &Resource, quantity: Decimal) -> Result<Costs> {
+    let resource_quantity = resource.inner().accounting_quantity().as_ref()
+        .ok_or(Error::ResourceMeasureMissing)?;
+    let resource_quantity = measure::to_decimal(resource_quantity)?;
+    if resource_quantity <= Decimal::zero() {
+        Err(Error::ResourceMeasureMissing)?;
+    }
+    Ok(resource.costs().clone() * (quantity / resource_quantity))
+}
+
+/// Derive a price for some quantity (in the resource's unit) of a resource,
+/// quoted in the given currency using its exchange rate at time `at`.
+pub fn price_for(resource: &Resource, quantity: Decimal, policy: &MarkupPolicy, currency: &Currency, at: &DateTime<Utc>) -> Result<Price> {
+    if quantity <= Decimal::zero() {
+        Err(Error::InvalidAmount(quantity))?;
+    }
+    let rate = currency.rate_at(at)?;
+    if rate <= Decimal::zero() {
+        Err(Error::InvalidAmount(rate))?;
+    }
+    let costs = costs_for_quantity(resource, quantity)?;
+    let credits = costs.credits().clone();
+    let amount = match policy {
+        MarkupPolicy::CostOnly => credits / rate,
+        MarkupPolicy::CostPlusBuffer(buffer) => {
+            if buffer < &Decimal::zero() {
+                Err(Error::InvalidAmount(buffer.clone()))?;
+            }
+            (credits / rate) * (Decimal::one() + buffer.clone())
+        }
+        MarkupPolicy::CurrencyPassthrough => {
+            let passthrough = costs.get_currency(currency.id().clone());
+            let paid_rate = currency.rate_at(resource.created()).unwrap_or(rate);
+            let remaining = credits - (passthrough * paid_rate);
+            let remaining = if remaining < Decimal::zero() { Decimal::zero() } else { remaining };
+            passthrough + (remaining / rate)
+        }
+    };
+    Ok(Price {
+        currency_id: currency.id().clone(),
+        amount: amount.round_dp(*currency.decimal_places() as u32),
+        costs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            currency::CurrencyRate,
+            resource::ResourceID,
+        },
+        util::{self, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    fn make_usd() -> Currency {
+        let now = util::time::now();
+        let mut currency = Currency::builder()
+            .id("usd")
+            .name("usd")
+            .decimal_places(2)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        currency.push_rate(CurrencyRate::new(num!(0.5), "2020-01-01T00:00:00Z".parse().unwrap()));
+        currency
+    }
+
+    #[test]
+    fn prices() {
+        let now = util::time::now();
+        let usd = make_usd();
+        let mut costs = Costs::new_with_labor("machinist", num!(90));
+        costs.track_currency("usd", num!(20), num!(0.5));
+        let resource = make_resource(&ResourceID::new("widget"), &CompanyID::new("jerry's widgets"), &Measure::new(num!(10), Unit::One), &costs, &now);
+
+        // half our resource is 50 credits, or 100 usd
+        let price = price_for(&resource, num!(5), &MarkupPolicy::CostOnly, &usd, &now).unwrap();
+        assert_eq!(price.currency_id(), &CurrencyID::new("usd"));
+        assert_eq!(price.amount(), &num!(100));
+        assert_eq!(price.costs(), &(costs.clone() * num!(0.5)));
+
+        let price = price_for(&resource, num!(5), &MarkupPolicy::CostPlusBuffer(num!(0.15)), &usd, &now).unwrap();
+        assert_eq!(price.amount(), &num!(115));
+
+        // 10 usd passes through, the remaining 45 credits become 90 usd
+        let price = price_for(&resource, num!(5), &MarkupPolicy::CurrencyPassthrough, &usd, &now).unwrap();
+        assert_eq!(price.amount(), &num!(100));
+
+        // the rate changes, but our passthrough usd doesn't: the 10 usd were
+        // worth 5 credits when we paid them, leaving 45 credits for the new
+        // rate to convert.
+        let mut resource2 = resource.clone();
+        resource2.set_created("2020-02-01T00:00:00Z".parse().unwrap());
+        let mut usd2 = usd.clone();
+        usd2.push_rate(CurrencyRate::new(num!(0.25), "2020-06-01T00:00:00Z".parse().unwrap()));
+        let price = price_for(&resource2, num!(5), &MarkupPolicy::CostOnly, &usd2, &now).unwrap();
+        assert_eq!(price.amount(), &num!(200));
+        let price = price_for(&resource2, num!(5), &MarkupPolicy::CurrencyPassthrough, &usd2, &now).unwrap();
+        assert_eq!(price.amount(), &(num!(10) + num!(45) / num!(0.25)));
+        assert_eq!(price.amount(), &num!(190));
+        // if the rate went up, passing the usd through costs more than
+        // converting everything would
+        let mut usd3 = usd.clone();
+        usd3.push_rate(CurrencyRate::new(num!(1), "2020-06-01T00:00:00Z".parse().unwrap()));
+        let price = price_for(&resource2, num!(5), &MarkupPolicy::CostOnly, &usd3, &now).unwrap();
+        assert_eq!(price.amount(), &num!(50));
+        let price = price_for(&resource2, num!(5), &MarkupPolicy::CurrencyPassthrough, &usd3, &now).unwrap();
+        assert_eq!(price.amount(), &num!(55));
+
+        // rounds to the currency's decimal places
+        let price = price_for(&resource, num!(1), &MarkupPolicy::CostPlusBuffer(num!(0.333)), &usd, &now).unwrap();
+        assert_eq!(price.amount(), &num!(26.66));
+
+        let res = price_for(&resource, num!(0), &MarkupPolicy::CostOnly, &usd, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = price_for(&resource, num!(1), &MarkupPolicy::CostPlusBuffer(num!(-0.1)), &usd, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-0.1))));
+        let res = price_for(&resource, num!(1), &MarkupPolicy::CostOnly, &usd, &"2019-01-01T00:00:00Z".parse().unwrap());
+        assert_eq!(res, Err(Error::CurrencyRateMissing));
+
+        let mut resource3 = resource.clone();
+        resource3.inner_mut().set_accounting_quantity(None);
+        let res = price_for(&resource3, num!(1), &MarkupPolicy::CostOnly, &usd, &now);
+        assert_eq!(res, Err(Error::ResourceMeasureMissing));
+    }
+}
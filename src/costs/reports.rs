@@ -0,0 +1,297 @@
+//! Reports summarize the costs held across a set of models, for instance the
+//! total value of a company's inventory, or the costs tied up in unfinished
+//! production (work-in-progress).
+//!
+//! None of these reports are needed by the core system itself, but they're
+//! common enough that it makes sense for them to live here rather than having
+//! each implementation rewrite them.
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    models::{
+        company::CompanyID,
+        currency::CurrencyID,
+        lib::basis_model::Model,
+        occupation::OccupationID,
+        process::Process,
+        resource::Resource,
+        resource_spec::ResourceSpecID,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::Measure;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// The valuation of all the resources conforming to one resource spec.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct SpecValuation {
+    /// The number of resources of this spec
+    resources: u64,
+    /// The total onhand quantity of the resources
+    onhand: Option<Measure>,
+    /// The number of resources whose onhand quantity couldn't be included in
+    /// `onhand`, either because they have no quantity or because their unit
+    /// doesn't match the other resources of this spec.
+    unmeasured: u64,
+    /// The total costs of the resources
+    costs: Costs,
+}
+
+impl SpecValuation {
+    fn add(&mut self, resource: &Resource) {
+        self.resources += 1;
+        self.costs = self.costs.clone() + resource.costs().clone();
+        let added = match (self.onhand.as_mut(), resource.inner().onhand_quantity()) {
+            (Some(onhand), Some(quantity)) => measure::inc_measure(onhand, quantity).is_ok(),
+            (None, Some(quantity)) => {
+                self.onhand = Some(quantity.clone());
+                true
+            }
+            (_, None) => false,
+        };
+        if !added {
+            self.unmeasured += 1;
+        }
+    }
+}
+
+/// A summary of the value of a set of resources.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct InventoryReport {
+    /// Quantities and costs, per resource spec
+    by_spec: HashMap<ResourceSpecID, SpecValuation>,
+    /// Costs held per company (by the resource's `primary_accountable`)
+    by_company: HashMap<CompanyID, Costs>,
+    /// The total costs of all resources
+    total: Costs,
+}
+
+impl InventoryReport {
+    /// The total credit value of the inventory.
+    pub fn credits(&self) -> &Decimal {
+        self.total().credits()
+    }
+
+    /// The amount of each external currency tied up in the inventory.
+    pub fn currency_exposure(&self) -> &HashMap<CurrencyID, Decimal> {
+        self.total().currency()
+    }
+}
+
+/// Value a set of resources, summing their onhand quantities and costs per
+/// resource spec and per company. Deleted resources are skipped.
+pub fn inventory_valuation(resources: &[Resource]) -> InventoryReport {
+    let mut report = InventoryReport::default();
+    for resource in resources {
+        if resource.is_deleted() {
+            continue;
+        }
+        report.by_spec.entry(resource.inner().conforms_to().clone())
+            .or_insert_with(SpecValuation::default)
+            .add(resource);
+        let company_id = resource.inner().primary_accountable().clone()
+            .and_then(|agent_id| CompanyID::try_from(agent_id).ok());
+        if let Some(company_id) = company_id {
+            let entry = report.by_company.entry(company_id).or_insert_with(Costs::new);
+            *entry = entry.clone() + resource.costs().clone();
+        }
+        report.total = report.total.clone() + resource.costs().clone();
+    }
+    report
+}
+
+/// How long a process has been open, used to group work-in-progress costs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum WipAge {
+    /// Opened within the last week
+    Week,
+    /// Opened more than a week but at most 30 days ago
+    Month,
+    /// Opened more than 30 but at most 90 days ago
+    Quarter,
+    /// Opened more than 90 days ago
+    Older,
+}
+
+impl WipAge {
+    /// Find the age bucket for a process created at `created`.
+    pub fn from_created(created: &DateTime<Utc>, now: &DateTime<Utc>) -> Self {
+        let days = (now.clone() - created.clone()).num_days();
+        if days <= 7 {
+            WipAge::Week
+        } else if days <= 30 {
+            WipAge::Month
+        } else if days <= 90 {
+            WipAge::Quarter
+        } else {
+            WipAge::Older
+        }
+    }
+}
+
+/// The labor tied up in unfinished processes for one occupation.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct LaborWip {
+    /// Wages paid for the labor
+    wages: Decimal,
+    /// Hours worked
+    hours: Decimal,
+}
+
+/// A summary of the costs held in unfinished processes.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct WipReport {
+    /// The number of unfinished processes included
+    processes: u64,
+    /// Costs held per company
+    by_company: HashMap<CompanyID, Costs>,
+    /// Labor held per occupation
+    by_occupation: HashMap<OccupationID, LaborWip>,
+    /// Costs held, grouped by how long their process has been open
+    by_age: HashMap<WipAge, Costs>,
+    /// The total costs of all unfinished processes
+    total: Costs,
+}
+
+impl WipReport {
+    /// The total credit value tied up in unfinished processes.
+    pub fn credits(&self) -> &Decimal {
+        self.total().credits()
+    }
+}
+
+/// Summarize the costs tied up in work-in-progress: processes that haven't been
+/// finished yet. Costs are totaled per company, per occupation, and by how long
+/// each process has been open (as of `now`). Deleted and finished processes are
+/// skipped.
+pub fn wip(processes: &[Process], now: &DateTime<Utc>) -> WipReport {
+    let mut report = WipReport::default();
+    for process in processes {
+        if process.is_deleted() || process.inner().finished() == &Some(true) {
+            continue;
+        }
+        let costs = process.costs();
+        report.processes += 1;
+        let entry = report.by_company.entry(process.company_id().clone()).or_insert_with(Costs::new);
+        *entry = entry.clone() + costs.clone();
+        for (occupation_id, wages) in costs.labor() {
+            let entry = report.by_occupation.entry(occupation_id.clone()).or_insert_with(LaborWip::default);
+            entry.wages += wages.clone();
+        }
+        for (occupation_id, hours) in costs.labor_hours() {
+            let entry = report.by_occupation.entry(occupation_id.clone()).or_insert_with(LaborWip::default);
+            entry.hours += hours.clone();
+        }
+        let entry = report.by_age.entry(WipAge::from_created(process.created(), now)).or_insert_with(Costs::new);
+        *entry = entry.clone() + costs.clone();
+        report.total = report.total.clone() + costs.clone();
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            process::ProcessID,
+            resource::ResourceID,
+        },
+        util::{self, test::*},
+    };
+    use chrono::Duration;
+    use om2::Unit;
+
+    #[test]
+    fn values_inventory() {
+        let now = util::time::now();
+        let company1 = CompanyID::new("jerry's widgets");
+        let company2 = CompanyID::new("larry's dongles");
+        let mut costs1 = Costs::new_with_labor("widgetmaker", num!(10));
+        costs1.track_currency("usd", num!(5), num!(1));
+        let costs2 = Costs::new_with_labor("dongler", num!(20));
+        let resource1 = make_resource(&ResourceID::new("widget1"), &company1, &Measure::new(num!(3), Unit::One), &costs1, &now);
+        let resource2 = make_resource(&ResourceID::new("widget2"), &company1, &Measure::new(num!(4), Unit::One), &costs1, &now);
+        let mut resource3 = make_resource(&ResourceID::new("dongle1"), &company2, &Measure::new(num!(7), Unit::Kilogram), &costs2, &now);
+        resource3.inner_mut().set_conforms_to("dongle".into());
+        let mut resource4 = make_resource(&ResourceID::new("dongle2"), &company2, &Measure::new(num!(2), Unit::Hour), &costs2, &now);
+        resource4.inner_mut().set_conforms_to("dongle".into());
+        let mut resource5 = resource1.clone();
+        resource5.set_deleted(Some(now.clone()));
+
+        let report = inventory_valuation(&vec![resource1, resource2, resource3, resource4, resource5]);
+        let widgets = report.by_spec().get(&ResourceSpecID::new("6969")).unwrap();
+        assert_eq!(widgets.resources(), &2);
+        assert_eq!(widgets.onhand(), &Some(Measure::new(num!(7), Unit::One)));
+        assert_eq!(widgets.unmeasured(), &0);
+        assert_eq!(widgets.costs(), &(costs1.clone() * num!(2)));
+        let dongles = report.by_spec().get(&ResourceSpecID::new("dongle")).unwrap();
+        assert_eq!(dongles.resources(), &2);
+        assert_eq!(dongles.onhand(), &Some(Measure::new(num!(7), Unit::Kilogram)));
+        assert_eq!(dongles.unmeasured(), &1);
+        assert_eq!(dongles.costs(), &(costs2.clone() * num!(2)));
+
+        assert_eq!(report.by_company().len(), 2);
+        assert_eq!(report.by_company().get(&company1), Some(&(costs1.clone() * num!(2))));
+        assert_eq!(report.by_company().get(&company2), Some(&(costs2.clone() * num!(2))));
+        assert_eq!(report.total(), &((costs1.clone() + costs2.clone()) * num!(2)));
+        assert_eq!(report.credits(), &num!(70));
+        assert_eq!(report.currency_exposure().get(&CurrencyID::new("usd")), Some(&num!(10)));
+
+        assert_eq!(inventory_valuation(&vec![]), InventoryReport::default());
+    }
+
+    #[test]
+    fn values_wip() {
+        let now = util::time::now();
+        let company1 = CompanyID::new("jerry's widgets");
+        let company2 = CompanyID::new("larry's dongles");
+        let mut costs1 = Costs::new_with_labor("widgetmaker", num!(10));
+        costs1.track_labor_hours("widgetmaker", num!(2));
+        let mut costs2 = Costs::new_with_labor("widgetmaker", num!(5));
+        costs2.track_labor("dongler", num!(20));
+        costs2.track_labor_hours("dongler", num!(3));
+        let process1 = make_process(&ProcessID::new("widgets1"), &company1, "widgets", &costs1, &now);
+        let process2 = make_process(&ProcessID::new("widgets2"), &company1, "widgets", &costs1, &(now.clone() - Duration::days(45)));
+        let process3 = make_process(&ProcessID::new("dongles"), &company2, "dongles", &costs2, &(now.clone() - Duration::days(10)));
+        let mut process4 = process1.clone();
+        process4.inner_mut().set_finished(Some(true));
+        let mut process5 = process1.clone();
+        process5.set_deleted(Some(now.clone()));
+
+        let report = wip(&vec![process1, process2, process3, process4, process5], &now);
+        assert_eq!(report.processes(), &3);
+        assert_eq!(report.by_company().len(), 2);
+        assert_eq!(report.by_company().get(&company1), Some(&(costs1.clone() * num!(2))));
+        assert_eq!(report.by_company().get(&company2), Some(&costs2));
+        let widgetmaker = report.by_occupation().get(&OccupationID::new("widgetmaker")).unwrap();
+        assert_eq!(widgetmaker.wages(), &num!(25));
+        assert_eq!(widgetmaker.hours(), &num!(4));
+        let dongler = report.by_occupation().get(&OccupationID::new("dongler")).unwrap();
+        assert_eq!(dongler.wages(), &num!(20));
+        assert_eq!(dongler.hours(), &num!(3));
+        assert_eq!(report.by_age().get(&WipAge::Week), Some(&costs1));
+        assert_eq!(report.by_age().get(&WipAge::Month), Some(&costs2));
+        assert_eq!(report.by_age().get(&WipAge::Quarter), Some(&costs1));
+        assert_eq!(report.by_age().get(&WipAge::Older), None);
+        assert_eq!(report.total(), &(costs1.clone() * num!(2) + costs2.clone()));
+        assert_eq!(report.credits(), &num!(45));
+
+        assert_eq!(wip(&vec![], &now), WipReport::default());
+    }
+}
@@ -0,0 +1,171 @@
+//! Sanity checks for costs entering the system.
+//!
+//! Costs are generally entered by hand, and nothing about a `Costs` object
+//! stops someone from recording 8000 hours of labor when they meant 8. Once
+//! an event with bad costs is processed, those costs get spread into processes
+//! and resources and are very hard to pull back out, so it's much better to
+//! catch them before processing.
+//!
+//! A `SanityPolicy` sets caps on individual cost entries and can disallow some
+//! buckets entirely. The network's policy lives in the [network config][1].
+//! There's no global policy: it's passed in explicitly. The event transactions
+//! that bring costs in by hand (recording [work][2], [buying from external
+//! markets][3], [bootstrapping resources][4]) take the policy and set it on the
+//! [process state][5], and the event processor rejects any event whose
+//! `move_costs` fall outside of it before anything is processed. Anything else
+//! can be checked with `check_modifications()` before the modifications a
+//! transaction returns are saved.
+//!
+//! ```rust
+//! use basis_core::{
+//!     costs::{Costs, validate::{self, SanityPolicy}},
+//!     error::Error,
+//! };
+//! use rust_decimal_macros::*;
+//!
+//! let policy = SanityPolicy::new(Some(dec!(24)), None, Some(dec!(100000)), vec![]);
+//! let costs = Costs::new_with_labor_hours("machinist", dec!(8000));
+//! assert!(validate::sanity_check(&costs, &policy).is_err());
+//! ```
+//!
+//! [1]: ../../models/config/index.html
+//! [2]: ../../transactions/event/work/index.html
+//! [3]: ../../transactions/market/fn.purchase_external.html
+//! [4]: ../../transactions/resource/fn.bootstrap.html
+//! [5]: ../../models/event/struct.EventProcessState.html#method.with_sanity_policy
+
+use crate::{
+    costs::{Costs, CostBucket},
+    error::{Error, Result},
+    models::{
+        Modifications,
+        Op,
+        event::Event,
+    },
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// Caps and restrictions that costs must fall within. Any cap left as `None`
+/// is not enforced.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct SanityPolicy {
+    /// The most hours a single `labor_hours` entry can hold
+    max_labor_hours: Option<Decimal>,
+    /// The most credits a single `labor` entry can hold
+    max_labor: Option<Decimal>,
+    /// The most a single `currency` entry can hold
+    max_currency: Option<Decimal>,
+    /// Buckets that must not hold any values at all
+    disallowed: Vec<CostBucket>,
+}
+
+impl SanityPolicy {
+    /// Create a new sanity policy.
+    pub const fn new(max_labor_hours: Option<Decimal>, max_labor: Option<Decimal>, max_currency: Option<Decimal>, disallowed: Vec<CostBucket>) -> Self {
+        Self { max_labor_hours, max_labor, max_currency, disallowed }
+    }
+}
+
+/// Check a set of costs against a sanity policy, returning
+/// `Error::CostsRejected` describing the first entry that falls outside of it.
+pub fn sanity_check(costs: &Costs, policy: &SanityPolicy) -> Result<()> {
+    for (bucket, id, val) in costs.iter() {
+        if bucket == CostBucket::Credits || val.is_zero() {
+            continue;
+        }
+        if policy.disallowed().contains(&bucket) {
+            Err(Error::CostsRejected(format!("{} costs are not allowed ({})", bucket.name(), id)))?;
+        }
+        let max = match bucket {
+            CostBucket::LaborHours => policy.max_labor_hours(),
+            CostBucket::Labor => policy.max_labor(),
+            CostBucket::Currency => policy.max_currency(),
+            _ => &None,
+        };
+        if let Some(max) = max {
+            if &val > max {
+                Err(Error::CostsRejected(format!("{} entry {} of {} is over the limit of {}", bucket.name(), id, val, max)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the `move_costs` of every event created or updated by a set of
+/// modifications (generally the result of an event transaction) against a
+/// sanity policy.
+pub fn check_modifications(modifications: &Modifications, policy: &SanityPolicy) -> Result<()> {
+    for (op, event) in modifications.filter_model::<Event>() {
+        if op == &Op::Delete {
+            continue;
+        }
+        if let Some(move_costs) = event.move_costs() {
+            sanity_check(move_costs, policy)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::Permission as CompanyPermission,
+            event::EventID,
+            process::{Process, ProcessID},
+            resource::{Resource, ResourceID},
+        },
+        transactions::event::production,
+        util::{self, number::Ratio, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn checks_sanity() {
+        let mut costs = Costs::new();
+        costs.track_labor("machinist", num!(120));
+        costs.track_labor_hours("machinist", num!(8));
+        costs.track_currency("usd", num!(500), num!(1));
+        costs.track_resource("steel", num!(30), num!(2));
+
+        sanity_check(&costs, &SanityPolicy::default()).unwrap();
+        let policy = SanityPolicy::new(Some(num!(24)), Some(num!(1000)), Some(num!(500)), vec![CostBucket::Impact]);
+        sanity_check(&costs, &policy).unwrap();
+
+        let mut costs2 = costs.clone();
+        costs2.track_labor_hours("machinist", num!(7992));
+        assert_eq!(sanity_check(&costs2, &policy), Err(Error::CostsRejected("labor_hours entry machinist of 8000 is over the limit of 24".into())));
+        let mut costs3 = costs.clone();
+        costs3.track_labor("machinist", num!(900));
+        assert_eq!(sanity_check(&costs3, &policy), Err(Error::CostsRejected("labor entry machinist of 1020 is over the limit of 1000".into())));
+        let mut costs4 = costs.clone();
+        costs4.track_currency("usd", num!(0.01), num!(1));
+        assert_eq!(sanity_check(&costs4, &policy), Err(Error::CostsRejected("currency entry usd of 500.01 is over the limit of 500".into())));
+        let mut costs5 = costs.clone();
+        costs5.track_impact("co2e", num!(3));
+        assert_eq!(sanity_check(&costs5, &policy), Err(Error::CostsRejected("impact costs are not allowed (co2e)".into())));
+        // without the policy, anything goes
+        sanity_check(&costs2, &SanityPolicy::default()).unwrap();
+        sanity_check(&costs5, &SanityPolicy::default()).unwrap();
+    }
+
+    #[test]
+    fn checks_modifications() {
+        let now = util::time::now();
+        let state: TestState<Resource, Process> = TestState::standard(vec![CompanyPermission::Consume], &now);
+        let resource = make_resource(&ResourceID::new("steel"), state.company().id(), &Measure::new(num!(10), Unit::Kilogram), &Costs::new_with_labor_hours("miner", num!(100)), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        let mods = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource, process, Ratio::new(num!(0.5)).unwrap(), 5, None, &now).unwrap();
+
+        let policy = SanityPolicy::new(Some(num!(24)), None, None, vec![]);
+        assert_eq!(check_modifications(&mods, &policy), Err(Error::CostsRejected("labor_hours entry miner of 50 is over the limit of 24".into())));
+        check_modifications(&mods, &SanityPolicy::default()).unwrap();
+        check_modifications(&Modifications::new(), &policy).unwrap();
+    }
+}
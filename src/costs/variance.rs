@@ -0,0 +1,211 @@
+//! Variance analysis compares the costs we *planned* for (via commitments) to
+//! the costs that *actually* moved (via the events that fulfilled those
+//! commitments).
+//!
+//! This lets producers see where production deviates from their plans, for
+//! instance a process that took twice as many machinist hours as expected, or
+//! used less steel than budgeted.
+
+use crate::{
+    costs::Costs,
+    models::{
+        commitment::Commitment,
+        currency::CurrencyID,
+        event::Event,
+        impact::ImpactID,
+        occupation::OccupationID,
+        resource_spec::ResourceSpecID,
+    },
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A single line in a variance report.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Variance {
+    /// The planned amount
+    planned: Decimal,
+    /// The actual amount
+    actual: Decimal,
+    /// The difference between actual and planned (`actual - planned`). A
+    /// positive value means we went over plan.
+    delta: Decimal,
+}
+
+impl Variance {
+    /// Create a new variance line
+    pub fn new(planned: Decimal, actual: Decimal) -> Self {
+        Self {
+            planned,
+            actual,
+            delta: actual - planned,
+        }
+    }
+}
+
+/// A report comparing planned costs to actual costs, broken out by credits
+/// and each of the cost buckets.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct CostVariance {
+    /// Variance in total credits
+    credits: Variance,
+    /// Variance in resources, per resource spec
+    resource: HashMap<ResourceSpecID, Variance>,
+    /// Variance in labor (wages), per occupation
+    labor: HashMap<OccupationID, Variance>,
+    /// Variance in labor hours, per occupation
+    labor_hours: HashMap<OccupationID, Variance>,
+    /// Variance in currency, per currency
+    currency: HashMap<CurrencyID, Variance>,
+    /// Variance in impacts, per impact
+    impact: HashMap<ImpactID, Variance>,
+}
+
+/// Build variance lines for every key in either the planned or actual map.
+fn compare<K: Clone + Eq + Hash>(planned: &HashMap<K, Decimal>, actual: &HashMap<K, Decimal>) -> HashMap<K, Variance> {
+    let mut lines = HashMap::new();
+    for key in planned.keys().chain(actual.keys()) {
+        if lines.contains_key(key) {
+            continue;
+        }
+        let planned_val = planned.get(key).cloned().unwrap_or_else(Decimal::zero);
+        let actual_val = actual.get(key).cloned().unwrap_or_else(Decimal::zero);
+        lines.insert(key.clone(), Variance::new(planned_val, actual_val));
+    }
+    lines
+}
+
+impl CostVariance {
+    /// Compare two `Costs` objects directly.
+    pub fn from_costs(planned: &Costs, actual: &Costs) -> Self {
+        Self {
+            credits: Variance::new(*planned.credits(), *actual.credits()),
+            resource: compare(planned.resource(), actual.resource()),
+            labor: compare(planned.labor(), actual.labor()),
+            labor_hours: compare(planned.labor_hours(), actual.labor_hours()),
+            currency: compare(planned.currency(), actual.currency()),
+            impact: compare(planned.impact(), actual.impact()),
+        }
+    }
+
+    /// Returns true if the actual costs match the planned costs exactly.
+    pub fn is_on_plan(&self) -> bool {
+        fn zero<K>(lines: &HashMap<K, Variance>) -> bool {
+            lines.values().all(|x| x.delta().is_zero())
+        }
+        self.credits().delta().is_zero() &&
+            zero(self.resource()) &&
+            zero(self.labor()) &&
+            zero(self.labor_hours()) &&
+            zero(self.currency()) &&
+            zero(self.impact())
+    }
+}
+
+/// Compare the costs of a set of commitments to the costs of the events that
+/// fulfilled them.
+///
+/// Planned costs are the sum of the commitments' `move_costs`, and actual costs
+/// are the sum of the events' `move_costs` (events without costs count as
+/// zero).
+pub fn variance(commitments: &[Commitment], events: &[Event]) -> CostVariance {
+    let planned = commitments.iter()
+        .fold(Costs::new(), |acc, x| acc + x.move_costs().clone());
+    let actual = events.iter()
+        .filter_map(|x| x.move_costs().clone())
+        .fold(Costs::new(), |acc, x| acc + x);
+    CostVariance::from_costs(&planned, &actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            commitment::CommitmentID,
+            company::CompanyID,
+            event::EventID,
+        },
+        util,
+    };
+    use vf_rs::vf;
+
+    fn make_commitment(costs: Costs) -> Commitment {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        Commitment::builder()
+            .id(CommitmentID::create())
+            .inner(
+                vf::Commitment::builder()
+                    .action(vf::Action::Work)
+                    .provider(company_id.clone())
+                    .receiver(company_id.clone())
+                    .build().unwrap()
+            )
+            .move_costs(costs)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    fn make_event(costs: Option<Costs>) -> Event {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let mut event = Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(vf::Action::Work)
+                    .provider(company_id.clone())
+                    .receiver(company_id.clone())
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        event.set_move_costs(costs);
+        event
+    }
+
+    #[test]
+    fn computes_variance() {
+        let mut planned1 = Costs::new_with_labor("machinist", num!(100));
+        planned1.track_labor_hours("machinist", num!(4));
+        let planned2 = Costs::new_with_resource("steel", num!(10), num!(2));
+        let commitments = vec![make_commitment(planned1), make_commitment(planned2)];
+
+        let mut actual1 = Costs::new_with_labor("machinist", num!(150));
+        actual1.track_labor_hours("machinist", num!(6));
+        actual1.track_labor("welder", num!(20));
+        let actual2 = Costs::new_with_resource("steel", num!(8), num!(2));
+        let events = vec![make_event(Some(actual1)), make_event(None), make_event(Some(actual2))];
+
+        let report = variance(&commitments, &events);
+        assert!(!report.is_on_plan());
+        assert_eq!(report.credits(), &Variance::new(num!(120), num!(186)));
+        assert_eq!(report.credits().delta(), &num!(66));
+        assert_eq!(report.labor().len(), 2);
+        assert_eq!(report.labor().get(&"machinist".into()), Some(&Variance::new(num!(100), num!(150))));
+        assert_eq!(report.labor().get(&"welder".into()).unwrap().delta(), &num!(20));
+        assert_eq!(report.labor_hours().get(&"machinist".into()).unwrap().delta(), &num!(2));
+        assert_eq!(report.resource().get(&"steel".into()).unwrap().delta(), &num!(-2));
+        assert_eq!(report.currency().len(), 0);
+
+        let report2 = variance(&commitments, &vec![make_event(Some(commitments[0].move_costs().clone() + commitments[1].move_costs().clone()))]);
+        assert!(report2.is_on_plan());
+
+        let report3 = variance(&vec![], &vec![]);
+        assert!(report3.is_on_plan());
+        assert_eq!(report3, CostVariance::default());
+    }
+}
@@ -0,0 +1,150 @@
+//! History lets us look at the state of processes and resources as they stood
+//! at some point in the past, for instance to settle a dispute over what a
+//! resource's costs were on June 1.
+//!
+//! `at_time()` takes a set of model snapshots and folds every event processed
+//! up to (and including) the given time into them using [replay], giving back
+//! a read-only view of the state at that moment.
+//!
+//! [replay]: ../replay/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    engine::replay::ReplayState,
+    error::Result,
+    models::{
+        Model,
+        event::Event,
+        process::{Process, ProcessID},
+        resource::{Resource, ResourceID},
+    },
+};
+use getset::Getters;
+
+/// A read-only view of the system's processes and resources at a point in
+/// time.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct StateView {
+    /// The time this view represents
+    as_of: DateTime<Utc>,
+    /// The folded state
+    state: ReplayState,
+}
+
+impl StateView {
+    /// Grab a process as it stood at the time of this view
+    pub fn process(&self, id: &ProcessID) -> Option<&Process> {
+        self.state().process(id)
+    }
+
+    /// Grab a resource as it stood at the time of this view
+    pub fn resource(&self, id: &ResourceID) -> Option<&Resource> {
+        self.state().resource(id)
+    }
+
+    /// Get a process' costs at the time of this view
+    pub fn process_costs(&self, id: &ProcessID) -> Option<&Costs> {
+        self.process(id).map(|process| process.costs())
+    }
+
+    /// Get a resource's costs at the time of this view
+    pub fn resource_costs(&self, id: &ResourceID) -> Option<&Costs> {
+        self.resource(id).map(|resource| resource.costs())
+    }
+}
+
+/// Fold a set of events into a set of model snapshots, giving a view of the
+/// state as of `timestamp`.
+///
+/// Events are ordered by the time they were processed (their `updated` time),
+/// and any processed after `timestamp` are left out. Snapshots should hold the
+/// models as they were *before* the first event given. Snapshots updated after
+/// `timestamp` didn't exist in that form yet, so they're left out as well.
+pub fn at_time(events: &[Event], snapshots: Vec<Model>, timestamp: &DateTime<Utc>) -> Result<StateView> {
+    let initial = snapshots.into_iter()
+        .filter(|model| {
+            match model {
+                Model::Process(process) => process.updated() <= timestamp,
+                Model::Resource(resource) => resource.updated() <= timestamp,
+                Model::Member(member) => member.updated() <= timestamp,
+                _ => true,
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut events = events.iter()
+        .filter(|event| event.updated() <= timestamp)
+        .collect::<Vec<_>>();
+    events.sort_by(|a, b| a.updated().cmp(b.updated()));
+
+    let mut state = ReplayState::from_models(initial);
+    for event in events {
+        let mods = state.process_event(event)?;
+        state.apply(&mods);
+    }
+    Ok(StateView {
+        as_of: timestamp.clone(),
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            Op,
+            company::Permission as CompanyPermission,
+            event::EventID,
+        },
+        transactions::event::production,
+        util::{self, number::Ratio, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn views_history() {
+        let now = util::time::now();
+        let earlier = now - chrono::Duration::days(1);
+        let now2 = now + chrono::Duration::days(1);
+        let state = TestState::<Resource, Process>::standard(vec![CompanyPermission::Consume], &now);
+        let resource = make_resource(&ResourceID::new("widget"), state.company().id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("homemaker", 150), &earlier);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &earlier);
+
+        let mods1 = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource.clone(), process.clone(), Ratio::new(num!(0.2)).unwrap(), 3, None, &now).unwrap().into_vec();
+        let event1 = mods1[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods1[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource2 = mods1[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let mods2 = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource2.clone(), process2.clone(), Ratio::new(num!(0.5)).unwrap(), 6, None, &now2).unwrap().into_vec();
+        let event2 = mods2[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource3 = mods2[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+
+        // events don't need to be given in order
+        let events = vec![event2.clone(), event1.clone()];
+        let snapshots = vec![resource.clone().into(), process.clone().into()];
+
+        let view = at_time(&events, snapshots.clone(), &now).unwrap();
+        assert_eq!(view.as_of(), &now);
+        assert_eq!(view.resource(resource.id()), Some(&resource2));
+        assert_eq!(view.resource_costs(resource.id()), Some(&Costs::new_with_labor("homemaker", 120)));
+        assert_eq!(view.process_costs(process.id()), Some(&Costs::new_with_labor("homemaker", 30)));
+
+        let view = at_time(&events, snapshots.clone(), &(now + chrono::Duration::hours(12))).unwrap();
+        assert_eq!(view.resource(resource.id()), Some(&resource2));
+
+        let view = at_time(&events, snapshots.clone(), &now2).unwrap();
+        assert_eq!(view.resource(resource.id()), Some(&resource3));
+        assert_eq!(view.resource_costs(resource.id()), Some(&Costs::new_with_labor("homemaker", 60)));
+        assert_eq!(view.process_costs(process.id()), Some(&Costs::new_with_labor("homemaker", 90)));
+
+        // before anything happened, we just see the snapshots. snapshots from
+        // the future are left out.
+        let mut future = process.clone();
+        future.set_updated(now2.clone());
+        let view = at_time(&events, vec![resource.clone().into(), future.into()], &(now - chrono::Duration::seconds(1))).unwrap();
+        assert_eq!(view.resource(resource.id()), Some(&resource));
+        assert_eq!(view.process(process.id()), None);
+        assert_eq!(view.resource_costs(&ResourceID::new("nope")), None);
+    }
+}
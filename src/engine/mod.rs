@@ -0,0 +1,12 @@
+//! The engine re-derives state from recorded history. Where the transaction
+//! layer decides what *should* happen, the engine answers questions about what
+//! *did* happen, for instance by replaying an event log against a set of model
+//! snapshots, or by looking at the state as it stood at some point in the past
+//! (see [at_time]).
+//!
+//! [at_time]: history/fn.at_time.html
+
+pub mod history;
+pub mod replay;
+
+pub use history::{at_time, StateView};
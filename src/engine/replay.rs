@@ -0,0 +1,254 @@
+//! Replaying lets us take an ordered log of events, along with snapshots of the
+//! processes and resources they operate on, and re-derive the final state of
+//! those models by running each event through [Event::process] in order.
+//!
+//! This serves two purposes. First, it's an audit: if a log entry carries the
+//! modifications that were recorded when the event was originally processed,
+//! we check that processing it again gives the exact same result, and error
+//! out on the first entry that doesn't. Second, it lets a new node sync its
+//! state from an event log without trusting anyone's copy of the models.
+//!
+//! Events are processed using their `updated` time, which is the time the
+//! transaction layer processes them at.
+//!
+//! [Event::process]: ../../models/event/struct.Event.html#method.process
+
+use crate::{
+    error::{Error, Result},
+    models::{
+        Model,
+        Modification,
+        Modifications,
+        Op,
+        event::{Event, EventProcessState},
+        lib::agent::AgentID,
+        member::{Member, MemberID},
+        process::{Process, ProcessID},
+        resource::{Resource, ResourceID},
+    },
+};
+use getset::Getters;
+use std::collections::HashMap;
+
+/// A single entry in an event log.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct LogEntry {
+    /// The event that was processed
+    event: Event,
+    /// The modifications recorded when the event was originally processed, if
+    /// we have them. Any modifications to the event itself are ignored, so
+    /// it's fine to pass in the full set of modifications returned by the
+    /// transaction that created the event.
+    recorded: Option<Modifications>,
+}
+
+impl LogEntry {
+    /// Create a new log entry
+    pub fn new(event: Event, recorded: Option<Modifications>) -> Self {
+        Self { event, recorded }
+    }
+}
+
+/// The models we track while replaying a log.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct ReplayState {
+    /// The processes events operate on, by ID
+    processes: HashMap<ProcessID, Process>,
+    /// The resources events operate on, by ID
+    resources: HashMap<ResourceID, Resource>,
+    /// The members providing labor (for `work` events), by ID
+    members: HashMap<MemberID, Member>,
+}
+
+impl ReplayState {
+    /// Create an empty replay state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a replay state from a set of model snapshots. Models that events
+    /// don't operate on are ignored.
+    pub fn from_models(models: Vec<Model>) -> Self {
+        let mut state = Self::new();
+        for model in models {
+            state.insert(model);
+        }
+        state
+    }
+
+    /// Grab a process by ID
+    pub fn process(&self, id: &ProcessID) -> Option<&Process> {
+        self.processes.get(id)
+    }
+
+    /// Grab a resource by ID
+    pub fn resource(&self, id: &ResourceID) -> Option<&Resource> {
+        self.resources.get(id)
+    }
+
+    /// Store a model in the state, replacing any previous version of it
+    fn insert(&mut self, model: Model) {
+        match model {
+            Model::Process(process) => { self.processes.insert(process.id().clone(), process); }
+            Model::Resource(resource) => { self.resources.insert(resource.id().clone(), resource); }
+            Model::Member(member) => { self.members.insert(member.id().clone(), member); }
+            _ => {}
+        }
+    }
+
+    /// Remove a model from the state
+    fn remove(&mut self, model: &Model) {
+        match model {
+            Model::Process(process) => { self.processes.remove(process.id()); }
+            Model::Resource(resource) => { self.resources.remove(resource.id()); }
+            Model::Member(member) => { self.members.remove(member.id()); }
+            _ => {}
+        }
+    }
+
+    /// Apply a set of modifications to the state.
+    pub fn apply(&mut self, modifications: &Modifications) {
+        for modification in modifications.iter() {
+            match modification.op() {
+                Op::Create | Op::Update => self.insert(modification.model().clone()),
+                Op::Delete => self.remove(modification.model()),
+            }
+        }
+    }
+
+    /// Run an event through the event processor using the models in this
+    /// state, returning the resulting modifications. The state itself is not
+    /// changed.
+    pub fn process_event(&self, event: &Event) -> Result<Modifications> {
+        let inner = event.inner();
+        let mut builder = EventProcessState::builder();
+        if let Some(process) = inner.input_of().as_ref().and_then(|id| self.processes.get(id)) {
+            builder = builder.input_of(process.clone());
+        }
+        if let Some(process) = inner.output_of().as_ref().and_then(|id| self.processes.get(id)) {
+            builder = builder.output_of(process.clone());
+        }
+        if let AgentID::MemberID(member_id) = inner.provider() {
+            if let Some(member) = self.members.get(member_id) {
+                builder = builder.provider(member.clone());
+            }
+        }
+        if let Some(resource) = inner.resource_inventoried_as().as_ref().and_then(|id| self.resources.get(id)) {
+            builder = builder.resource(resource.clone());
+        }
+        if let Some(resource) = inner.to_resource_inventoried_as().as_ref().and_then(|id| self.resources.get(id)) {
+            builder = builder.to_resource(resource.clone());
+        }
+        let state = builder.build().map_err(|e| Error::BuilderFailed(e))?;
+        event.process(state, event.updated())
+    }
+}
+
+/// Strip any modifications to `event` out of a set of recorded modifications.
+fn without_event(event: &Event, recorded: &Modifications) -> Vec<Modification> {
+    recorded.iter()
+        .filter(|modification| {
+            match modification.model() {
+                Model::Event(recorded_event) => recorded_event.id() != event.id(),
+                _ => true,
+            }
+        })
+        .cloned()
+        .collect::<Vec<_>>()
+}
+
+/// Replay a log of events, in order, against a set of initial model snapshots
+/// and return the final state.
+///
+/// If an entry has recorded modifications, they must match the replayed ones
+/// exactly, otherwise we return `Error::ReplayMismatch` with the ID of the
+/// offending event.
+pub fn replay(initial: Vec<Model>, log: &[LogEntry]) -> Result<ReplayState> {
+    let mut state = ReplayState::from_models(initial);
+    for entry in log {
+        let mods = state.process_event(entry.event())?;
+        if let Some(recorded) = entry.recorded() {
+            if without_event(entry.event(), recorded) != mods.clone().into_vec() {
+                Err(Error::ReplayMismatch(entry.event().id().as_str().into()))?;
+            }
+        }
+        state.apply(&mods);
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::Permission as CompanyPermission,
+            event::EventID,
+        },
+        transactions::event::production,
+        util::{self, number::Ratio, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn can_replay() {
+        let now = util::time::now();
+        let now2 = now + chrono::Duration::hours(1);
+        let state = TestState::<Resource, Process>::standard(vec![CompanyPermission::Consume], &now);
+        let resource = make_resource(&ResourceID::new("widget"), state.company().id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("homemaker", 150), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+
+        let mods1 = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource.clone(), process.clone(), Ratio::new(num!(0.2)).unwrap(), 3, None, &now).unwrap();
+        let event1 = mods1.clone().into_vec()[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods1.clone().into_vec()[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource2 = mods1.clone().into_vec()[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let mods2 = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource2.clone(), process2.clone(), Ratio::new(num!(0.5)).unwrap(), 6, None, &now2).unwrap();
+        let event2 = mods2.clone().into_vec()[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process3 = mods2.clone().into_vec()[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource3 = mods2.clone().into_vec()[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+
+        let initial = vec![resource.clone().into(), process.clone().into()];
+        let log = vec![
+            LogEntry::new(event1.clone(), Some(mods1.clone())),
+            LogEntry::new(event2.clone(), Some(mods2.clone())),
+        ];
+        let replayed = replay(initial.clone(), &log).unwrap();
+        assert_eq!(replayed.processes().len(), 1);
+        assert_eq!(replayed.resources().len(), 1);
+        assert_eq!(replayed.process(process.id()), Some(&process3));
+        assert_eq!(replayed.resource(resource.id()), Some(&resource3));
+        assert_eq!(replayed.resource(resource.id()).unwrap().inner().accounting_quantity(), &Some(Measure::new(num!(6), Unit::One)));
+        assert_eq!(replayed.process(process.id()).unwrap().costs(), &Costs::new_with_labor("homemaker", 90));
+
+        // we don't need recorded modifications to replay
+        let log_unrecorded = vec![
+            LogEntry::new(event1.clone(), None),
+            LogEntry::new(event2.clone(), None),
+        ];
+        assert_eq!(replay(initial.clone(), &log_unrecorded).unwrap(), replayed);
+
+        // replaying out of order doesn't match what was recorded
+        let log_reordered = vec![
+            LogEntry::new(event2.clone(), Some(mods2.clone())),
+            LogEntry::new(event1.clone(), Some(mods1.clone())),
+        ];
+        let res = replay(initial.clone(), &log_reordered);
+        assert_eq!(res, Err(Error::ReplayMismatch(event2.id().as_str().into())));
+
+        // tampered records are caught
+        let mut tampered = resource2.clone();
+        tampered.set_costs(Costs::new_with_labor("homemaker", 200));
+        let mut mods1_tampered = Modifications::new_single(Op::Update, process2.clone());
+        mods1_tampered.push(Op::Update, tampered);
+        let log_tampered = vec![LogEntry::new(event1.clone(), Some(mods1_tampered))];
+        let res = replay(initial.clone(), &log_tampered);
+        assert_eq!(res, Err(Error::ReplayMismatch(event1.id().as_str().into())));
+
+        // missing snapshots surface the event processor's errors
+        let res = replay(vec![resource.clone().into()], &log);
+        assert_eq!(res, Err(Error::Event(crate::models::event::EventError::MissingInputProcess)));
+    }
+}
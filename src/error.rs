@@ -16,6 +16,16 @@ use thiserror::Error;
 #[derive(Error, Debug, PartialEq)]
 #[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
 pub enum Error {
+    /// An agreement's document hash doesn't match the one given
+    #[error("agreement document hash mismatch")]
+    AgreementDocumentMismatch,
+    /// The ballots given for a proposal don't belong to it, repeat a voter, or
+    /// outnumber the eligible voters
+    #[error("ballots don't match the proposal")]
+    BallotsInvalid,
+    /// Binary-encoded data couldn't be decoded
+    #[error("error decoding binary data: {0}")]
+    BinaryDecodeFailed(String),
     /// There was an error while using a builder (likely an internal error)
     #[error("error building object {0}")]
     BuilderFailed(String),
@@ -31,15 +41,86 @@ pub enum Error {
     /// commitment doesn't match the action being performed.
     #[error("commitment is invalid")]
     CommitmentInvalid,
+    /// The commitment lapsed (see `transactions::commitment::expire`) and can
+    /// no longer be fulfilled.
+    #[error("commitment has lapsed")]
+    CommitmentLapsed,
+    /// Trying to expire a commitment that isn't overdue.
+    #[error("commitment is not overdue")]
+    CommitmentNotOverdue,
+    /// A company can't be linked to itself, and a link can only be accepted
+    /// once.
+    #[error("invalid company link")]
+    CompanyLinkInvalid,
+    /// A resource can't be packed into itself, or into one of its contents.
+    #[error("a resource cannot be packed into itself or its contents")]
+    ContainerInvalid,
+    /// The resource is already packed into a container (when packing) or isn't
+    /// packed into the given container (when unpacking).
+    #[error("resource is not packed into the given container")]
+    ContainerMismatch,
+    /// A costs operation overflowed the bounds of our decimal type.
+    #[error("costs overflow")]
+    CostOverflow,
+    /// A set of costs failed a sanity check, for instance a labor entry far
+    /// larger than any single event should ever carry.
+    #[error("costs rejected: {0}")]
+    CostsRejected(String),
+    /// We tried to look up a currency's exchange rate for a point in time that
+    /// has no rate recorded.
+    #[error("no exchange rate recorded for currency")]
+    CurrencyRateMissing,
+    /// A debt's creditor and debtor must be different companies.
+    #[error("invalid debt")]
+    DebtInvalid,
+    /// Tried to repay or forgive more than is owed on a debt. Contains the
+    /// amount outstanding.
+    #[error("amount exceeds the outstanding balance of the debt ({0})")]
+    DebtOverpayment(Decimal),
+    /// A delegation is invalid: it has already expired, delegates to the same
+    /// member, or tries to delegate a permission that can't be delegated.
+    #[error("invalid delegation")]
+    DelegationInvalid,
+    /// Tried to divide by zero.
+    #[error("divide by zero")]
+    DivideByZero,
     /// An error while processing an event.
     #[error("event error {0:?}")]
     Event(#[from] EventError),
     /// You don't have permission to perform this action
     #[error("insufficient privileges")]
     InsufficientPrivileges,
+    /// An intent doesn't have enough quantity available for what's being
+    /// asked of it
+    #[error("intent does not have enough quantity available")]
+    IntentExhausted,
+    /// The intent has expired (or been withdrawn) and can't be committed to.
+    #[error("intent is no longer available")]
+    IntentExpired,
+    /// The intent given doesn't match the one a commitment references
+    #[error("the given intent does not match the one referenced")]
+    IntentMismatch,
+    /// The given amount must be greater than zero (for instance, when
+    /// transferring or depositing credits).
+    #[error("invalid amount {0} (must be > 0)")]
+    InvalidAmount(Decimal),
     /// The given ratio is not a value between 0 and 1 (inclusive)
     #[error("invalid ratio {0} (must be 0 <= R <= 1")]
     InvalidRatio(Decimal),
+    /// A membership invite has already been accepted or rejected.
+    #[error("this invite has already been responded to")]
+    InviteNotPending,
+    /// A ledger period must close after the previous one and no later than
+    /// now.
+    #[error("ledger period must close after the previous one and no later than now")]
+    LedgerPeriodInvalid,
+    /// A resource's lot has expired, so it can no longer be consumed or
+    /// transferred (although it can still be written off).
+    #[error("resource lot {0} has expired")]
+    LotExpired(String),
+    /// Trying to produce one lot into a resource that holds a different lot
+    #[error("resource lot mismatch")]
+    LotMismatch,
     /// Happens when an entity tries to take on more costs than is allowed.
     #[error("maximum costs reached")]
     MaxCostsReached,
@@ -53,6 +134,10 @@ pub enum Error {
     /// We're missing required fields in a call
     #[error("fields missing {0:?}")]
     MissingFields(Vec<String>),
+    /// Two modifications on the same model conflict with each other, for
+    /// instance an update on a model that was previously deleted.
+    #[error("conflicting modifications: {0}")]
+    ModificationConflict(String),
     /// An account cannot have a negative balance
     #[error("operation creates negative account balance")]
     NegativeAccountBalance,
@@ -79,21 +164,127 @@ pub enum Error {
     /// When we try to modify an object that is now in a read-only state.
     #[error("object {0} is read-only")]
     ObjectIsReadOnly(String),
+    /// A worker's primary occupation can't be removed (only replaced)
+    #[error("cannot remove a worker's primary occupation")]
+    OccupationIsPrimary,
+    /// The occupation given doesn't match the one we expected (for instance,
+    /// the worker's occupation when recording work).
+    #[error("the given occupation does not match")]
+    OccupationMismatch,
     /// When we ask a `Modification` for a model but the `Op` we give it doesn't
     /// match expectation.
     #[error("Op does not match expectation")]
     OpMismatch,
+    /// The pay period (or part of it) has already been paid out.
+    #[error("pay period has already been paid")]
+    PayPeriodAlreadyPaid,
+    /// A pay period must end after it begins.
+    #[error("pay period must end after it begins")]
+    PayPeriodInvalid,
+    /// The process can't be worked on until the commitments it's blocked on
+    /// are fulfilled.
+    #[error("process is blocked on commitment(s): {0}")]
+    ProcessBlocked(String),
+    /// An event would create a cycle in the process graph, trapping costs.
+    #[error("operation would create a cycle between processes")]
+    ProcessCycle,
+    /// The proposal is closed and can no longer be voted on.
+    #[error("proposal is not accepting votes")]
+    ProposalClosed,
+    /// The proposal is still open for voting and cannot be closed yet.
+    #[error("proposal is still open for voting")]
+    ProposalOpen,
+    /// Trying to quarantine a resource that didn't fail inspection
+    #[error("only a failed inspection can quarantine a resource")]
+    QuarantineRequiresFailure,
+    /// Replaying an event gave different modifications than the ones recorded
+    /// when it was originally processed.
+    #[error("replaying event {0} does not match its recorded modifications")]
+    ReplayMismatch(String),
     /// We get this when trying to pull a measure out of a resource and come up
     /// blank, for instance when using `consume` on a resource that hasn't had
     /// its quantities initialized via `produce`/`raise`/`transfer`/etc.
     #[error("a resource measurement (account/onhand quantity) is missing")]
     ResourceMeasureMissing,
+    /// The resources given for a merge overlap (a source is listed twice, or
+    /// the merge target is also one of the sources).
+    #[error("merged resources must be distinct")]
+    ResourceMergeOverlap,
+    /// The operation would dip into resource quantity that is being held for
+    /// a pending transfer.
+    #[error("resource quantity is being held for a pending transfer")]
+    ResourceQuantityHeld,
+    /// The operation requires a resource that is tagged with a region.
+    #[error("the resource is not tagged with a region")]
+    ResourceRegionMissing,
+    /// The resource doesn't conform to the resource spec it was checked
+    /// against.
+    #[error("resource does not conform to the given spec")]
+    ResourceSpecMismatch,
+    /// A quantity was recorded in a unit that isn't compatible with the
+    /// resource's spec (its default unit or dimensions). Contains the unit.
+    #[error("unit {0} is not compatible with the resource spec")]
+    ResourceSpecUnitMismatch(String),
+    /// The roles or members given for a role assignment don't match the ones
+    /// on record (for instance, updating a role without passing in every
+    /// member that holds it)
+    #[error("the given roles or members do not match the role assignments")]
+    RoleAssignmentMismatch,
+    /// A proposed process schedule conflicts with its spec or with existing
+    /// processes/commitments
+    #[error("schedule conflict: {0}")]
+    ScheduleConflict(String),
+    /// An object failed to serialize
+    #[error("error serializing object: {0}")]
+    SerializeFailed(String),
+    /// The resources or process given don't match the shipment's
+    #[error("the given resources or process do not match the shipment")]
+    ShipmentMismatch,
+    /// The shipment isn't in the right status for this operation (for
+    /// instance, dropping off a shipment that hasn't been picked up)
+    #[error("shipment is not in the right status for this operation")]
+    ShipmentStatusInvalid,
+    /// A signed transaction failed verification (missing key, malformed or
+    /// mismatched signature)
+    #[error("signature invalid: {0}")]
+    SignatureInvalid(String),
+    /// A stats window must end after it starts and have a positive period
+    #[error("stats window is invalid")]
+    StatsWindowInvalid,
+    /// Materializing another period would go over a service subscription's
+    /// cost cap
+    #[error("subscription cost cap exceeded: {0}")]
+    SubscriptionCapExceeded(String),
+    /// Trying to materialize a subscription period that hasn't started yet
+    #[error("the subscription's next period hasn't started yet")]
+    SubscriptionPeriodNotStarted,
+    /// A service subscription isn't in the right status for an operation (for
+    /// instance, resuming a subscription that was canceled)
+    #[error("subscription is not in the right status for this operation")]
+    SubscriptionStatusInvalid,
+    /// A transfer breaks one of the sending company's transfer policy limits
+    #[error("transfer policy violation: {0}")]
+    TransferPolicyViolation(String),
+    /// Issuing credits would break the company treasury's issuance policy
+    #[error("treasury limit exceeded: {0}")]
+    TreasuryLimitExceeded(String),
     /// We're trying to perform an action on a UBI account that isn't allowed.
     #[error("operation cannot be performed on a UBI account")]
     UBIAccountError,
     /// A UBI account is required for the action you wish to perform.
     #[error("operation can only be performed on a UBI account")]
     UBIAccountRequired,
+    /// UBI has already been issued for (part of) the given period.
+    #[error("UBI has already been issued for this period")]
+    UBIAlreadyIssued,
+    /// A UBI issuance period must end after it begins, and can't end in the
+    /// future.
+    #[error("UBI period must end after it begins and no later than now")]
+    UBIPeriodInvalid,
+    /// The labor costs claimed are inconsistent with the occupation's
+    /// registered wage rate.
+    #[error("labor costs are inconsistent with the occupation's wage rate")]
+    WageRateMismatch,
     /// When we try to convert an AgentID to another ID type but it fails (like
     /// `let company_id: CompanyID = AgentID::UserID(user_id).try_from()?;`).
     #[error("AgentID is the wrong type")]
@@ -31,9 +31,37 @@
 //! To get started, you will want to look at the [transactions]. Transactions
 //! are the main interface for interacting with Basis.
 //!
+//! ## Features
+//!
+//! - `clock` (default) enables chrono's system clock (`Utc::now()`). The core
+//!   never reads the clock itself, so this can be turned off.
+//! - `with_serde` enables serialization of models and other data, as well as
+//!   state digests ([util::hash]).
+//! - `binary` enables the compact binary encoding for [Costs].
+//! - `signatures` enables creating and verifying ed25519 signatures on
+//!   [signed transactions][util::signature].
+//!
+//! The crate (including [costs], event processing, and [Modifications]) builds
+//! for `wasm32-unknown-unknown` with `--no-default-features` (checked with
+//! `make check-wasm`), for instance to run cost math inside a smart contract.
+//! Time is always passed in by the caller, so no OS clock is read outside of
+//! tests, and `url` is pulled in without its `std` features (its serde support
+//! comes with `with_serde`).
+//!
+//! Note that `vf-rs` (as of 0.3.16) depends on chrono and `url` with their
+//! default features, and Cargo unifies features across the dependency tree, so
+//! turning them off here doesn't remove chrono's clock or JS bindings from a
+//! wasm build until `vf-rs` stops enabling them. Nothing in this crate calls
+//! into them.
+//!
 //! [freeassoc]: https://en.wikipedia.org/wiki/Free_association_(Marxism_and_anarchism)
 //! [basis]: https://basisproject.net/
 //! [transactions]: transactions/
+//! [Costs]: costs/struct.Costs.html
+//! [costs]: costs/
+//! [Modifications]: models/struct.Modifications.html
+//! [util::hash]: util/hash/
+//! [util::signature]: util/signature/
 
 pub mod error;
 #[macro_use]
@@ -43,6 +71,7 @@ pub mod access;
 #[macro_use]
 pub mod models;
 pub mod costs;
+pub mod engine;
 pub mod transactions;
 pub mod system;
-
+pub mod views;
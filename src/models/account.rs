@@ -74,6 +74,12 @@ basis_model! {
         /// Whether or not this is a UBI account, and if so, some information
         /// about the UBI
         ubi: Option<Ubi>,
+        /// The last time credits moved into or out of this account (not
+        /// counting demurrage). If never set, the account's creation date is
+        /// used instead.
+        last_activity: Option<DateTime<Utc>>,
+        /// The last time demurrage was charged against this account
+        last_demurrage: Option<DateTime<Utc>>,
     }
     AccountBuilder
 }
@@ -89,6 +95,22 @@ impl Account {
         self.set_balance(new_amount);
         Ok(self.balance())
     }
+
+    /// Move credits into (positive) or out of (negative) the account, marking
+    /// the account as active as of `now`. This is `adjust_balance()` for any
+    /// movement of credits that should keep the account from being charged
+    /// demurrage.
+    pub(crate) fn move_credits<T: Into<Decimal>>(&mut self, amount: T, now: &DateTime<Utc>) -> Result<&Decimal> {
+        self.adjust_balance(amount)?;
+        self.set_last_activity(Some(*now));
+        Ok(self.balance())
+    }
+
+    /// Returns the last time credits moved into or out of this account,
+    /// falling back to the account's creation date.
+    pub fn last_active(&self) -> &DateTime<Utc> {
+        self.last_activity().as_ref().unwrap_or_else(|| self.created())
+    }
 }
 
 #[cfg(test)]
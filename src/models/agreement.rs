@@ -1,12 +1,22 @@
 //! Agreements respresent a larger transaction between two agents. Think of an
 //! agreement like an order, and that order can be made up of multiple
 //! deliverables, modeled as `Commitment`s and `EconomicEvent`s.
+//!
+//! The agreement itself usually lives somewhere else (commitments and events
+//! point to it via their `agreed_in` URL), and nothing stops that document from
+//! changing after the fact. To guard against this, an agreement can hold the
+//! SHA-256 hash of its document. Once it's set, commitments and events made
+//! under the agreement have to present the same hash, so everyone acting on
+//! the agreement is provably acting on the same document.
 
 use crate::{
+    error::{Error, Result},
     models::{
+        agreement_template::AgreementTemplateID,
         lib::agent::AgentID,
     },
 };
+use sha2::{Digest, Sha256};
 use vf_rs::vf;
 
 basis_model! {
@@ -26,6 +36,12 @@ basis_model! {
         /// Note that this might also allow the storage layer to have a list of
         /// signatures needed in order to materially change the agreement.
         participants: Vec<AgentID>,
+        /// The template this agreement was created from, if any
+        template_id: Option<AgreementTemplateID>,
+        /// The SHA-256 hash of the agreement's document, if any. If set,
+        /// commitments and events under this agreement must provide a matching
+        /// hash.
+        document_hash: Option<[u8; 32]>,
     }
     AgreementBuilder
 }
@@ -35,5 +51,63 @@ impl Agreement {
     pub fn has_participant(&self, agent_id: &AgentID) -> bool {
         self.participants().contains(agent_id)
     }
+
+    /// Hash an agreement document's contents, giving the value to store in
+    /// `document_hash`.
+    pub fn hash_document(bytes: &[u8]) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&Sha256::digest(bytes));
+        digest
+    }
+
+    /// Determines if the given document is the one this agreement was made
+    /// over. Agreements without a `document_hash` have nothing to verify
+    /// against, so always return `false` here.
+    pub fn verify_document(&self, bytes: &[u8]) -> bool {
+        match self.document_hash() {
+            Some(hash) => hash == &Self::hash_document(bytes),
+            None => false,
+        }
+    }
+
+    /// Make sure a document hash given alongside a commitment or event matches
+    /// this agreement's. If the agreement has no `document_hash`, anything
+    /// goes. Otherwise the hash must be given (or we get a `MissingFields`
+    /// error) and must match (or we get `AgreementDocumentMismatch`).
+    pub fn check_document_hash(&self, hash: Option<&[u8; 32]>) -> Result<()> {
+        match (self.document_hash(), hash) {
+            (None, _) => Ok(()),
+            (Some(_), None) => Err(Error::MissingFields(vec!["document_hash".into()])),
+            (Some(ours), Some(theirs)) if ours == theirs => Ok(()),
+            (Some(_), Some(_)) => Err(Error::AgreementDocumentMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::company::CompanyID,
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn verifies_documents() {
+        let now = util::time::now();
+        let mut agreement = make_agreement(&AgreementID::create(), &vec![CompanyID::new("jerry's widgets").into()], "order 1234", "widgets", &now);
+        let doc = b"jerry agrees to send larry 12 widgets";
+        let hash = Agreement::hash_document(doc);
+        assert!(!agreement.verify_document(doc));
+        agreement.check_document_hash(None).unwrap();
+        agreement.check_document_hash(Some(&hash)).unwrap();
+
+        agreement.set_document_hash(Some(hash.clone()));
+        assert!(agreement.verify_document(doc));
+        assert!(!agreement.verify_document(b"jerry agrees to send larry 1200 widgets"));
+        agreement.check_document_hash(Some(&hash)).unwrap();
+        assert_eq!(agreement.check_document_hash(None), Err(Error::MissingFields(vec!["document_hash".into()])));
+        assert_eq!(agreement.check_document_hash(Some(&[0u8; 32])), Err(Error::AgreementDocumentMismatch));
+    }
 }
 
@@ -0,0 +1,93 @@
+//! Agreement templates hold the structure of agreements that get made over and
+//! over again (a standing weekly order between two co-ops, for instance) so it
+//! doesn't have to be re-entered for every order.
+//!
+//! A template's body is agreement text containing named slots, written as
+//! `{{slot}}`, which are filled in when an agreement is [created from the
+//! template][1]. The template also records which [order actions][2]
+//! commitments under these agreements generally use.
+//!
+//! [1]: ../../transactions/agreement/fn.create_from_template.html
+//! [2]: ../../transactions/enum.OrderAction.html
+
+use crate::{
+    error::{Error, Result},
+    models::{
+        company::CompanyID,
+    },
+    transactions::OrderAction,
+};
+use std::collections::HashMap;
+
+basis_model! {
+    /// A reusable blueprint for agreements.
+    pub struct AgreementTemplate {
+        id: <<AgreementTemplateID>>,
+        /// The company that owns this template
+        company_id: CompanyID,
+        /// The title given to agreements created from this template
+        title: String,
+        /// The agreement text, with `{{slot}}` placeholders
+        body: String,
+        /// The names of the slots that must be filled in the body
+        slots: Vec<String>,
+        /// The actions commitments under these agreements generally take
+        default_actions: Vec<OrderAction>,
+    }
+    AgreementTemplateBuilder
+}
+
+impl AgreementTemplate {
+    /// Fill in the template's body with the given slot values. Every slot must
+    /// have a value, otherwise we get a `MissingFields` error listing the
+    /// missing slots.
+    pub fn render(&self, values: &HashMap<String, String>) -> Result<String> {
+        let missing = self.slots().iter()
+            .filter(|slot| !values.contains_key(*slot))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            Err(Error::MissingFields(missing))?;
+        }
+        let mut body = self.body().clone();
+        for slot in self.slots() {
+            body = body.replace(&format!("{{{{{}}}}}", slot), &values[slot]);
+        }
+        Ok(body)
+    }
+
+    /// Whether or not the given action is one of this template's defaults
+    pub fn has_default_action(&self, action: &OrderAction) -> bool {
+        self.default_actions().contains(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    fn render() {
+        let now = util::time::now();
+        let template = AgreementTemplate::builder()
+            .id(AgreementTemplateID::create())
+            .company_id(CompanyID::new("jerry's widgets"))
+            .title("Weekly widgets")
+            .body("Jerry's sends {{qty}} widgets to {{buyer}} every week. Thanks, {{buyer}}!")
+            .slots(vec!["qty".into(), "buyer".into()])
+            .default_actions(vec![OrderAction::Transfer])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+
+        let mut values = HashMap::new();
+        values.insert("qty".to_string(), "12".to_string());
+        assert_eq!(template.render(&values), Err(Error::MissingFields(vec!["buyer".into()])));
+        values.insert("buyer".to_string(), "Larry's Chairs".to_string());
+        assert_eq!(template.render(&values).unwrap(), "Jerry's sends 12 widgets to Larry's Chairs every week. Thanks, Larry's Chairs!");
+        assert!(template.has_default_action(&OrderAction::Transfer));
+        assert!(!template.has_default_action(&OrderAction::DeliverService));
+    }
+}
@@ -0,0 +1,39 @@
+//! A ballot is a single voter's choice on a [proposal][1]. Each voter gets one
+//! ballot per proposal, which they can change up until voting closes.
+//!
+//! [1]: ../proposal/index.html
+
+use crate::{
+    models::{
+        proposal::ProposalID,
+        user::UserID,
+    },
+};
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// The choices a voter can make on a proposal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum BallotChoice {
+    /// In favor
+    Yes,
+    /// Against
+    No,
+    /// Counts toward quorum, but not for or against
+    Abstain,
+}
+
+basis_model! {
+    /// The `Ballot` model records a voter's choice on a proposal.
+    pub struct Ballot {
+        id: <<BallotID>>,
+        /// The proposal being voted on
+        proposal_id: ProposalID,
+        /// The user casting the ballot
+        voter: UserID,
+        /// The voter's choice
+        choice: BallotChoice,
+    }
+    BallotBuilder
+}
@@ -8,11 +8,16 @@
 //! [Intent]: ../intent/struct.Intent.html
 //! [Event]: ../event/struct.Event.html
 
+use chrono::{DateTime, Utc};
 use crate::{
     costs::Costs,
     models::{
         agreement::AgreementID,
-        lib::agent::AgentID,
+        intent::IntentID,
+        lib::{
+            agent::AgentID,
+            basis_model::Model,
+        },
         process::ProcessID,
         resource::ResourceID,
         resource_spec::ResourceSpecID,
@@ -35,7 +40,33 @@ basis_model! {
         /// The amount of costs committed to be moved. One could think of this
         /// somewhat like a negotiated price in the current system.
         move_costs: Costs,
+        /// If set, the time this commitment passed its due date without being
+        /// fulfilled and was marked as lapsed.
+        lapsed: Option<DateTime<Utc>>,
+        /// The intent (if any) this commitment was made in response to
+        satisfies: Option<IntentID>,
+        /// The agreement document hash given when the commitment was made (see
+        /// [Agreement::check_document_hash][1]). It must still match the
+        /// agreement's hash when the commitment is executed.
+        ///
+        /// [1]: ../agreement/struct.Agreement.html#method.check_document_hash
+        document_hash: Option<[u8; 32]>,
     }
     CommitmentBuilder
 }
 
+impl Commitment {
+    /// Determine if this commitment is past due. A commitment is overdue if it
+    /// is active, has a `due` date before `now`, hasn't been marked finished,
+    /// and hasn't already lapsed.
+    pub fn is_overdue(&self, now: &DateTime<Utc>) -> bool {
+        if !self.is_active() || self.lapsed().is_some() || self.inner().finished() == &Some(true) {
+            return false;
+        }
+        match self.inner().due() {
+            Some(due) => due < now,
+            None => false,
+        }
+    }
+}
+
@@ -4,12 +4,20 @@
 //!
 //! Companies have their own set of permissions that allow [Members] to perform
 //! actions on the company. Note that while the [access system][access] uses
-//! roles to contain various permissions, companies assign permissions directly.
-//! This ultimately gives more control to companies to determine their own roles
-//! (outside the perview of this library) as needed.
+//! fixed roles to contain various permissions, companies assign permissions
+//! directly. Companies can also define their own [roles][CompanyRole] (named
+//! bundles of permissions) and assign those to members, which is a lot easier
+//! to manage as a company grows.
+//!
+//! Companies can also band together into federations using [company
+//! links][CompanyLink], which let members of the federation manage processes
+//! within the member companies (with whatever process permissions the member
+//! companies grant).
 //!
 //! [Members]: ../member/struct.Member.html
 //! [access]: ../../access/
+//! [CompanyRole]: ../company_role/struct.CompanyRole.html
+//! [CompanyLink]: ../company_link/struct.CompanyLink.html
 
 use crate::{
     costs::Costs,
@@ -39,6 +47,12 @@ pub enum Permission {
     AgreementCreate,
     /// Can finalize agreements (orders)
     AgreementFinalize,
+    /// Can create agreement templates
+    AgreementTemplateCreate,
+    /// Can delete agreement templates
+    AgreementTemplateDelete,
+    /// Can update agreement templates
+    AgreementTemplateUpdate,
     /// Can update agreements (orders)
     AgreementUpdate,
 
@@ -54,18 +68,40 @@ pub enum Permission {
 
     /// Can delete the company
     CompanyDelete,
+    /// Can link the company to a federation
+    CompanyLinkCreate,
+    /// Can remove a link between the company and a federation (or one of the
+    /// federation's member companies)
+    CompanyLinkDelete,
+    /// Can update the permissions the company grants to its federation
+    CompanyLinkUpdate,
     /// Can update the company's basic info
     CompanyUpdate,
 
     /// Can consume a resource
     Consume,
 
+    /// Can record a debt the company owes to another company
+    DebtCreate,
+    /// Can forgive debts other companies owe the company
+    DebtForgive,
+    /// Can repay debts the company owes
+    DebtRepay,
+
     /// Can deliver a service
     DeliverService,
 
     /// Can drop off (for delivery) a resource
     Dropoff,
 
+    /// Can attach metadata to an event
+    EventAnnotate,
+    /// Can finalize a pending (begun but not yet ended) event
+    EventFinalize,
+
+    /// Can record quality-control inspections of resources
+    Inspect,
+
     /// Can create a new intent
     IntentCreate,
     /// Can delete an intent
@@ -76,11 +112,16 @@ pub enum Permission {
     /// Can lower resource quantities within the company
     Lower,
 
+    /// Can buy resources from sellers outside of the network
+    MarketPurchase,
+    /// Can sell resources to buyers outside of the network
+    MarketSell,
+
     /// Can create new members (hire)
     MemberCreate,
     /// Can delete a member (fire)
     MemberDelete,
-    /// Can set existing members' company permissions
+    /// Can set existing members' company permissions (and roles)
     MemberSetPermissions,
     /// Can set a member's compensation (payment)
     MemberSetCompensation,
@@ -98,6 +139,9 @@ pub enum Permission {
     /// Can run payroll for this company
     Payroll,
 
+    /// Can pack resources into containers (and unpack them)
+    Pack,
+
     /// Can pick up (for delivery) a resource
     Pickup,
 
@@ -118,9 +162,25 @@ pub enum Permission {
     /// Can produce a resource
     Produce,
 
+    /// Can close a company proposal once voting has ended
+    ProposalClose,
+    /// Can put forward a proposal for the company to vote on
+    ProposalCreate,
+    /// Can vote on company proposals
+    ProposalVote,
+
     /// Can raise resource quantities within the company
     Raise,
 
+    /// Can create a company role
+    RoleCreate,
+    /// Can delete a company role
+    RoleDelete,
+    /// Can update a company role
+    RoleUpdate,
+
+    /// Can bootstrap a resource, seeding it with its spec's estimated costs
+    ResourceBootstrap,
     /// Can create a resource
     ResourceCreate,
     /// Can delete a resource
@@ -128,6 +188,13 @@ pub enum Permission {
     /// Can update a resource
     ResourceUpdate,
 
+    /// Can create a resource group
+    ResourceGroupCreate,
+    /// Can delete a resource group
+    ResourceGroupDelete,
+    /// Can update a resource group (including adding/removing resources)
+    ResourceGroupUpdate,
+
     /// Can create a resource spec
     ResourceSpecCreate,
     /// Can delete a resource spec
@@ -135,12 +202,23 @@ pub enum Permission {
     /// Can update a resource spec
     ResourceSpecUpdate,
 
+    /// Can create a shipment
+    ShipmentCreate,
+
     /// Transfer ownership/custody to another agent
     Transfer,
+    /// Accept or reject transfers sent to this company
+    TransferAccept,
     /// Transfer ownership to another agent
     TransferAllRights,
     /// Transfer custody to another agent
     TransferCustody,
+    /// Can create a transfer policy
+    TransferPolicyCreate,
+    /// Can delete a transfer policy
+    TransferPolicyDelete,
+    /// Can update a transfer policy
+    TransferPolicyUpdate,
 
     /// Can use a resource in a productive process
     Use,
@@ -149,6 +227,9 @@ pub enum Permission {
     Work,
     /// Can update labor records willy-nilly
     WorkAdmin,
+
+    /// Can write off costs from a resource or process (spoilage, scrap, etc)
+    WriteOff,
 }
 
 basis_model! {
@@ -170,6 +251,10 @@ basis_model! {
         /// The total amount of costs this company possesses. Cannot be above
         /// `max_costs` when converted to a credit value.
         total_costs: Costs,
+        /// A tally of the costs this company has written off (spoilage, scrap,
+        /// etc). Costs that leave a resource or process without being moved
+        /// anywhere else end up here so they don't silently vanish.
+        waste_costs: Costs,
     }
     CompanyBuilder
 }
@@ -195,7 +280,7 @@ impl Company {
     ///
     /// Note that we don't need to check if we're over our `max_costs` value
     /// because we are reducing costs here.
-    fn decrease_costs(&mut self, costs: Costs) -> Result<&Costs> {
+    pub(crate) fn decrease_costs(&mut self, costs: Costs) -> Result<&Costs> {
         if costs.is_lt_0() {
             Err(Error::NegativeCosts)?;
         }
@@ -207,6 +292,15 @@ impl Company {
         Ok(self.total_costs())
     }
 
+    /// Move a set of written-off costs out of this company's `total_costs` and
+    /// into its `waste_costs` tally. Returns the company's post-op
+    /// `waste_costs` value.
+    pub(crate) fn write_off_costs(&mut self, costs: Costs) -> Result<&Costs> {
+        self.decrease_costs(costs.clone())?;
+        self.set_waste_costs(self.waste_costs().clone() + costs);
+        Ok(self.waste_costs())
+    }
+
     /// Transfer a set of costs from this company to another. The receiving
     /// company must not go over their `max_costs` value.
     pub fn transfer_costs_to(&mut self, company_to: &mut Company, costs: Costs) -> Result<&Costs> {
@@ -295,5 +389,29 @@ mod tests {
         let res = company.decrease_costs(costs5);
         assert_eq!(res, Err(Error::NegativeCosts));
     }
-}
 
+    #[test]
+    fn write_off_costs() {
+        let mut company = make_company(&CompanyID::create(), "jerry's delicious widgets", &util::time::now());
+        let mut total = Costs::new_with_labor("widgetmaker", 100);
+        total.track_resource("steel", dec!(20), dec!(0.5));
+        company.set_total_costs(total.clone());
+        let costs1 = Costs::new_with_labor("widgetmaker", 50);
+        let waste = company.write_off_costs(costs1.clone()).unwrap();
+        assert_eq!(waste, &costs1);
+        assert_eq!(company.total_costs(), &(total.clone() - costs1.clone()));
+
+        let costs2 = Costs::new_with_resource("steel", 12, dec!(0.5));
+        let waste = company.write_off_costs(costs2.clone()).unwrap();
+        assert_eq!(waste, &(costs1.clone() + costs2.clone()));
+        assert_eq!(company.total_costs(), &(total.clone() - costs1.clone() - costs2.clone()));
+
+        let costs3 = Costs::new() - Costs::new_with_labor("widgetmaker", 10);
+        let res = company.write_off_costs(costs3);
+        assert_eq!(res, Err(Error::NegativeCosts));
+        // can't write off more than the company has
+        let res = company.write_off_costs(Costs::new_with_labor("widgetmaker", 51));
+        assert_eq!(res, Err(Error::NegativeCosts));
+        assert_eq!(company.waste_costs(), &(costs1 + costs2));
+    }
+}
@@ -0,0 +1,325 @@
+//! A company ledger aggregates all the cost inflows and outflows for a company
+//! so that implementors don't have to reverse-engineer a company's totals from
+//! the (potentially thousands of) events that company has been a part of.
+//!
+//! The ledger is updated by feeding it the modifications returned from
+//! transactions. Only completed events are counted, and each event is counted
+//! once: a pending event (one that has begun but not ended) is counted when an
+//! update completes it (for instance via `event::finalize`, `work::clock_out`,
+//! or `transfer::transfer_accept`). This makes it safe to pass in *all*
+//! modifications from a transaction without filtering them first.
+//!
+//! Sales to buyers outside of the network (see the [market transactions][1])
+//! are tallied separately, along with the running surplus or deficit of
+//! currency those sales have brought in.
+//!
+//! The ledger also keeps track of the outstanding balance of any [debts][2]
+//! the company owes or is owed. Since debts are updated in place (rather than
+//! via events), the ledger stores the latest balance of each debt it sees.
+//!
+//! To know which events it has already counted, the ledger remembers the IDs
+//! of the events it has recorded. Closing a period (`close_period()`) forgets
+//! the events completed before it, after which any event completed before the
+//! period's end is taken to have been counted already. This keeps the ledger
+//! from growing without bound.
+//!
+//! [1]: ../../transactions/market/index.html
+//! [2]: ../debt/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    models::{
+        Model,
+        Modifications,
+        Op,
+        company::CompanyID,
+        currency::CurrencyID,
+        debt::{Debt, DebtID},
+        event::{Event, EventID, MoveType},
+    },
+};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use vf_rs::vf;
+
+basis_model! {
+    /// The `CompanyLedger` model tracks the running totals of costs moving into
+    /// and out of a company.
+    pub struct CompanyLedger {
+        id: <<CompanyLedgerID>>,
+        /// The company this ledger tracks
+        company_id: CompanyID,
+        /// Costs this company has received from other agents (ie, via transfer
+        /// or service delivery)
+        costs_received: Costs,
+        /// Costs this company has sent to other agents
+        costs_sent: Costs,
+        /// Labor costs this company has taken on from its members' work
+        labor_paid: Costs,
+        /// Costs this company has written off (spoilage, scrap, etc)
+        waste: Costs,
+        /// Costs that have left this company via sales to buyers outside of
+        /// the network
+        external_sales: Costs,
+        /// The running surplus (positive) or deficit (negative) of external
+        /// sales, per currency. This is the currency received from sales minus
+        /// the currency costs of what was sold.
+        market_balance: HashMap<CurrencyID, Decimal>,
+        /// The outstanding balance of each unsettled debt this company is a
+        /// party to. Positive balances are owed *to* this company, negative
+        /// balances are owed *by* it.
+        debts: HashMap<DebtID, (CurrencyID, Decimal)>,
+        /// Events completed before this time are taken to have already been
+        /// counted (see `close_period()`).
+        recorded_through: Option<DateTime<Utc>>,
+        /// The events this ledger has counted since `recorded_through`, along
+        /// with when each completed, so that events seen more than once (for
+        /// instance a pending event that is later completed) are only counted
+        /// once.
+        recorded_events: HashMap<EventID, DateTime<Utc>>,
+    }
+    CompanyLedgerBuilder
+}
+
+impl CompanyLedger {
+    /// Record a single event in the ledger. Returns true if the event had an
+    /// effect on the ledger.
+    ///
+    /// Events that are pending (started but not completed), that have already
+    /// been recorded (or completed before `recorded_through`), that don't move
+    /// costs, or that are internal to the company (aside from labor and
+    /// write-offs) are ignored.
+    pub(crate) fn record_event(&mut self, event: &Event) -> bool {
+        if event.inner().has_beginning().is_some() && event.inner().has_end().is_none() {
+            return false;
+        }
+        let completed = event.inner().has_end()
+            .or(*event.inner().has_point_in_time())
+            .unwrap_or(*event.created());
+        if self.recorded_through().map(|through| completed < through).unwrap_or(false) {
+            return false;
+        }
+        if self.recorded_events().contains_key(event.id()) {
+            return false;
+        }
+        let costs = match event.move_costs() {
+            Some(costs) => costs.clone(),
+            None => return false,
+        };
+        let company_agent = self.company_id().clone().into();
+        let is_provider = event.inner().provider() == &company_agent;
+        let is_receiver = event.inner().receiver() == &company_agent;
+        match (event.inner().action(), event.move_type()) {
+            (vf::Action::Work, _) if is_receiver => {
+                self.set_labor_paid(self.labor_paid().clone() + costs);
+            }
+            (vf::Action::Move, Some(MoveType::WriteOff)) if is_provider => {
+                self.set_waste(self.waste().clone() + costs);
+            }
+            (vf::Action::Move, Some(MoveType::ExternalSale(currency_id, amount))) if is_provider => {
+                for (spent_id, spent) in costs.currency() {
+                    *self.market_balance_mut().entry(spent_id.clone()).or_insert(Decimal::zero()) -= spent;
+                }
+                *self.market_balance_mut().entry(currency_id.clone()).or_insert(Decimal::zero()) += amount;
+                self.set_external_sales(self.external_sales().clone() + costs);
+            }
+            _ if is_provider && !is_receiver => {
+                self.set_costs_sent(self.costs_sent().clone() + costs);
+            }
+            _ if is_receiver && !is_provider => {
+                self.set_costs_received(self.costs_received().clone() + costs);
+            }
+            _ => return false,
+        }
+        self.recorded_events_mut().insert(event.id().clone(), completed);
+        true
+    }
+
+    /// Close the ledger's current period at `end`, forgetting the events that
+    /// completed before it. From here on, events completed before `end` are
+    /// ignored, so they must all have been recorded already.
+    pub(crate) fn close_period(&mut self, end: &DateTime<Utc>) {
+        self.recorded_events_mut().retain(|_, completed| &*completed >= end);
+        self.set_recorded_through(Some(*end));
+    }
+
+    /// Record the current state of a debt in the ledger. Returns true if the
+    /// debt had an effect on the ledger.
+    ///
+    /// Settled or deleted debts are removed from the ledger entirely.
+    pub(crate) fn record_debt(&mut self, debt: &Debt, removed: bool) -> bool {
+        let balance = if debt.creditor_id() == self.company_id() {
+            debt.outstanding()
+        } else if debt.debtor_id() == self.company_id() {
+            -debt.outstanding()
+        } else {
+            return false;
+        };
+        if removed || debt.deleted().is_some() || debt.is_settled() {
+            return self.debts_mut().remove(debt.id()).is_some();
+        }
+        let entry = (debt.currency_id().clone(), balance);
+        self.debts_mut().insert(debt.id().clone(), entry.clone()) != Some(entry)
+    }
+
+    /// Get the net outstanding debt balance for this company, per currency.
+    /// A positive balance means the company is owed more than it owes.
+    pub fn debt_balance(&self) -> HashMap<CurrencyID, Decimal> {
+        let mut balance = HashMap::new();
+        for (currency_id, amount) in self.debts().values() {
+            *balance.entry(currency_id.clone()).or_insert(Decimal::zero()) += amount;
+        }
+        balance
+    }
+
+    /// Apply a set of modifications to this ledger, recording any completed
+    /// events (whether newly-created or updated from pending) and any debts
+    /// that affect the ledger's company. Returns true if the ledger was
+    /// changed.
+    pub fn apply(&mut self, modifications: &Modifications) -> bool {
+        let mut changed = false;
+        for modification in modifications.iter() {
+            match (modification.op(), modification.model()) {
+                (Op::Create, Model::Event(event)) | (Op::Update, Model::Event(event)) => {
+                    changed = self.record_event(event) || changed;
+                }
+                (op, Model::Debt(debt)) => {
+                    changed = self.record_debt(debt, op == &Op::Delete) || changed;
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            event::EventID,
+            member::MemberID,
+        },
+        util::{self, test::*},
+    };
+
+    fn make_ledger(company_id: &CompanyID) -> CompanyLedger {
+        let now = util::time::now();
+        CompanyLedger::builder()
+            .id(CompanyLedgerID::create())
+            .company_id(company_id.clone())
+            .costs_received(Costs::new())
+            .costs_sent(Costs::new())
+            .labor_paid(Costs::new())
+            .waste(Costs::new())
+            .external_sales(Costs::new())
+            .market_balance(HashMap::new())
+            .debts(HashMap::new())
+            .recorded_events(HashMap::new())
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    fn make_event<T: Into<crate::models::AgentID>, F: Into<crate::models::AgentID>>(action: vf::Action, provider: T, receiver: F, costs: Option<Costs>) -> Event {
+        let now = util::time::now();
+        let mut event = Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action)
+                    .has_point_in_time(now.clone())
+                    .provider(provider.into())
+                    .receiver(receiver.into())
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        event.set_move_costs(costs);
+        event
+    }
+
+    #[test]
+    fn apply() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let other_id = CompanyID::new("larry's chairs");
+        let mut ledger = make_ledger(&company_id);
+
+        let work = make_event(vf::Action::Work, MemberID::new("jerry"), company_id.clone(), Some(Costs::new_with_labor("machinist", 42)));
+        let received = make_event(vf::Action::Transfer, other_id.clone(), company_id.clone(), Some(Costs::new_with_labor("trucker", 10)));
+        let sent = make_event(vf::Action::Transfer, company_id.clone(), other_id.clone(), Some(Costs::new_with_labor("machinist", 17)));
+        let mut waste = make_event(vf::Action::Move, company_id.clone(), company_id.clone(), Some(Costs::new_with_labor("machinist", 3)));
+        waste.set_move_type(Some(MoveType::WriteOff));
+        let mut sale = make_event(vf::Action::Move, company_id.clone(), company_id.clone(), Some(Costs::new_with_currency("usd", 20, 1) + Costs::new_with_currency("eur", 5, 1) + Costs::new_with_labor("machinist", 6)));
+        sale.set_move_type(Some(MoveType::ExternalSale("usd".into(), num!(32))));
+        let internal = make_event(vf::Action::Move, company_id.clone(), company_id.clone(), Some(Costs::new_with_labor("machinist", 12)));
+        let no_costs = make_event(vf::Action::Lower, company_id.clone(), company_id.clone(), None);
+        let unrelated = make_event(vf::Action::Transfer, other_id.clone(), CompanyID::new("barry's beds"), Some(Costs::new_with_labor("trucker", 1)));
+        let mut pending = make_event(vf::Action::Work, MemberID::new("jerry"), company_id.clone(), Some(Costs::new_with_labor("machinist", 8)));
+        pending.inner_mut().set_has_point_in_time(None);
+        pending.inner_mut().set_has_beginning(Some(now.clone()));
+
+        let mut mods = Modifications::new();
+        mods.push(Op::Create, work);
+        mods.push(Op::Update, make_company(&company_id, "jerry's widgets", &now));
+        mods.push(Op::Create, received);
+        mods.push(Op::Create, sent.clone());
+        mods.push(Op::Create, waste);
+        mods.push(Op::Create, sale);
+        mods.push(Op::Create, internal);
+        mods.push(Op::Create, no_costs);
+        mods.push(Op::Create, unrelated);
+        mods.push(Op::Create, pending.clone());
+        // updates to existing events aren't counted twice
+        mods.push(Op::Update, sent);
+
+        assert!(ledger.apply(&mods));
+        assert_eq!(ledger.labor_paid(), &Costs::new_with_labor("machinist", 42));
+        assert_eq!(ledger.costs_received(), &Costs::new_with_labor("trucker", 10));
+        assert_eq!(ledger.costs_sent(), &Costs::new_with_labor("machinist", 17));
+        assert_eq!(ledger.waste(), &Costs::new_with_labor("machinist", 3));
+        assert_eq!(ledger.external_sales(), &(Costs::new_with_currency("usd", 20, 1) + Costs::new_with_currency("eur", 5, 1) + Costs::new_with_labor("machinist", 6)));
+        assert_eq!(ledger.market_balance().get(&CurrencyID::new("usd")), Some(&num!(12)));
+        assert_eq!(ledger.market_balance().get(&CurrencyID::new("eur")), Some(&num!(-5)));
+
+        // the same events aren't counted twice
+        assert!(!ledger.apply(&mods));
+        assert_eq!(ledger.labor_paid(), &Costs::new_with_labor("machinist", 42));
+
+        // a pending event is counted once an update completes it
+        let mut completed = pending.clone();
+        completed.inner_mut().set_has_end(Some(now.clone()));
+        let mut mods2 = Modifications::new();
+        mods2.push(Op::Update, completed.clone());
+        assert!(ledger.apply(&mods2));
+        assert_eq!(ledger.labor_paid(), &Costs::new_with_labor("machinist", 50));
+        assert!(!ledger.apply(&mods2));
+        assert_eq!(ledger.labor_paid(), &Costs::new_with_labor("machinist", 50));
+
+        assert!(!ledger.apply(&Modifications::new()));
+
+        // closing a period forgets the events completed before it, but still
+        // won't count them again
+        let later = now + chrono::Duration::hours(1);
+        assert_eq!(ledger.recorded_events().len(), 6);
+        ledger.close_period(&later);
+        assert_eq!(ledger.recorded_through(), &Some(later.clone()));
+        assert_eq!(ledger.recorded_events().len(), 0);
+        assert!(!ledger.apply(&mods));
+        assert!(!ledger.apply(&mods2));
+        assert_eq!(ledger.labor_paid(), &Costs::new_with_labor("machinist", 50));
+        let mut work2 = make_event(vf::Action::Work, MemberID::new("jerry"), company_id.clone(), Some(Costs::new_with_labor("machinist", 1)));
+        work2.inner_mut().set_has_point_in_time(Some(later.clone()));
+        let mut mods3 = Modifications::new();
+        mods3.push(Op::Create, work2.clone());
+        assert!(ledger.apply(&mods3));
+        assert_eq!(ledger.recorded_events().get(work2.id()), Some(&later));
+        assert_eq!(ledger.labor_paid(), &Costs::new_with_labor("machinist", 51));
+    }
+}
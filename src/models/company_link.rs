@@ -0,0 +1,122 @@
+//! A company link joins a member company to a federation (a parent company),
+//! for instance a regional federation of co-ops or a network of housing
+//! collectives.
+//!
+//! The member company creates the link, deciding which of its [company
+//! permissions][1] members of the federation inherit, and the federation then
+//! has to accept it. Until it's accepted, a link grants nothing. A federation
+//! member can then act within the member company, but only with permissions
+//! that are both granted by the link *and* held by the member at the
+//! federation itself. See `Member::access_check_federated()`.
+//!
+//! Federation access is limited to managing processes: only the [process
+//! transactions][2] (create, update, delete) honor it, so links can only pass
+//! on the permissions those need (see `CompanyLink::INHERITABLE`, or
+//! `Permission::All` for all of them). Everything else, including recording
+//! events and making commitments against a federated company's processes, still
+//! requires being a member of the company itself.
+//!
+//! Links only go one level deep: members of a federation that is itself a
+//! member of a larger federation don't inherit anything from the larger one.
+//!
+//! [1]: ../company/enum.Permission.html
+//! [2]: ../../transactions/process/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    error::{Error, Result},
+    models::{
+        company::{CompanyID, Permission},
+        lib::basis_model::Model,
+    },
+};
+
+basis_model! {
+    /// The `CompanyLink` model links a member company to a federation.
+    pub struct CompanyLink {
+        id: <<CompanyLinkID>>,
+        /// The federation (parent) company
+        parent_id: CompanyID,
+        /// The member (child) company
+        child_id: CompanyID,
+        /// The permissions members of the federation inherit at the member
+        /// company
+        inherit_permissions: Vec<Permission>,
+        /// When the federation accepted the link. Links grant nothing until
+        /// they're accepted.
+        accepted: Option<DateTime<Utc>>,
+    }
+    CompanyLinkBuilder
+}
+
+impl CompanyLink {
+    /// The permissions a link can pass on to members of the federation.
+    pub const INHERITABLE: [Permission; 3] = [
+        Permission::ProcessCreate,
+        Permission::ProcessUpdate,
+        Permission::ProcessDelete,
+    ];
+
+    /// Check that a set of permissions can be inherited via a link (each is
+    /// either `All` or one of `INHERITABLE`).
+    pub fn check_inheritable(permissions: &[Permission]) -> Result<()> {
+        let inheritable = |permission: &Permission| permission == &Permission::All || Self::INHERITABLE.contains(permission);
+        if !permissions.iter().all(inheritable) {
+            Err(Error::CompanyLinkInvalid)?;
+        }
+        Ok(())
+    }
+
+    /// Whether or not the federation has accepted this link.
+    pub fn is_accepted(&self) -> bool {
+        self.accepted().is_some()
+    }
+
+    /// Determine if this link lets members of `parent_id` exercise a permission
+    /// at `child_id`. Inactive/deleted/unaccepted links grant nothing.
+    pub fn grants(&self, parent_id: &CompanyID, child_id: &CompanyID, permission: &Permission) -> bool {
+        if !self.is_active() || !self.is_accepted() || self.parent_id() != parent_id || self.child_id() != child_id {
+            return false;
+        }
+        self.inherit_permissions().contains(&Permission::All) ||
+            self.inherit_permissions().contains(permission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util,
+    };
+
+    #[test]
+    fn grants() {
+        let now = util::time::now();
+        let federation = CompanyID::new("widget federation");
+        let coop = CompanyID::new("jerry's widgets");
+        let mut link = CompanyLink::builder()
+            .id(CompanyLinkID::create())
+            .parent_id(federation.clone())
+            .child_id(coop.clone())
+            .inherit_permissions(vec![Permission::ProcessCreate])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        assert!(!link.grants(&federation, &coop, &Permission::ProcessCreate));
+        link.set_accepted(Some(now.clone()));
+        assert!(link.grants(&federation, &coop, &Permission::ProcessCreate));
+        assert!(!link.grants(&federation, &coop, &Permission::ProcessDelete));
+        assert!(!link.grants(&coop, &federation, &Permission::ProcessCreate));
+
+        link.set_inherit_permissions(vec![Permission::All]);
+        assert!(link.grants(&federation, &coop, &Permission::ProcessDelete));
+
+        link.set_active(false);
+        assert!(!link.grants(&federation, &coop, &Permission::ProcessCreate));
+        link.set_active(true);
+        link.set_deleted(Some(now.clone()));
+        assert!(!link.grants(&federation, &coop, &Permission::ProcessCreate));
+    }
+}
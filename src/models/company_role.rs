@@ -0,0 +1,126 @@
+//! A company role is a named bundle of [company permissions][1] that can be
+//! assigned to members. Rather than managing the permissions of each member one
+//! by one (which gets tedious quickly for larger collectives), a company can
+//! define roles like "bookkeeper" or "floor lead" and assign those instead.
+//!
+//! A member's effective permissions are their own permissions plus the
+//! permissions of each (active) role they've been assigned.
+//!
+//! Members carry copies of their roles, so a role also keeps track of which
+//! members it's assigned to. This lets the role transactions make sure a change
+//! to the role reaches every member holding it.
+//!
+//! [1]: ../company/enum.Permission.html
+
+use crate::{
+    error::{Error, Result},
+    models::{
+        company::{CompanyID, Permission},
+        lib::basis_model::Model,
+        member::{Member, MemberID},
+    },
+};
+
+basis_model! {
+    /// The `CompanyRole` model describes a named set of company permissions.
+    pub struct CompanyRole {
+        id: <<CompanyRoleID>>,
+        /// The company this role belongs to
+        company_id: CompanyID,
+        /// The role's name
+        name: String,
+        /// The permissions granted by this role
+        permissions: Vec<Permission>,
+        /// The members this role is assigned to
+        member_ids: Vec<MemberID>,
+    }
+    CompanyRoleBuilder
+}
+
+impl CompanyRole {
+    /// Determine if this role grants a permission. Inactive/deleted roles
+    /// grant nothing.
+    pub fn can(&self, permission: &Permission) -> bool {
+        if !self.is_active() {
+            return false;
+        }
+        self.permissions().contains(&Permission::All) ||
+            self.permissions().contains(permission)
+    }
+
+    /// Record that this role has been assigned to a member. Returns false if
+    /// the member already had it.
+    pub(crate) fn assign(&mut self, member_id: &MemberID) -> bool {
+        if self.member_ids().contains(member_id) {
+            return false;
+        }
+        self.member_ids_mut().push(member_id.clone());
+        true
+    }
+
+    /// Record that this role has been taken away from a member. Returns false
+    /// if the member didn't have it.
+    pub(crate) fn unassign(&mut self, member_id: &MemberID) -> bool {
+        let len = self.member_ids().len();
+        self.member_ids_mut().retain(|id| id != member_id);
+        self.member_ids().len() != len
+    }
+
+    /// Make sure the given members are exactly the members this role is
+    /// assigned to (no more, no less).
+    pub fn check_members(&self, members: &[Member]) -> Result<()> {
+        let mut given = members.iter().map(|member| member.id().as_str()).collect::<Vec<_>>();
+        given.sort();
+        given.dedup();
+        let mut assigned = self.member_ids().iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        assigned.sort();
+        if given.len() != members.len() || given != assigned {
+            Err(Error::RoleAssignmentMismatch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            occupation::OccupationID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn assigns_members() {
+        let now = util::time::now();
+        let company_id = CompanyID::create();
+        let mut role = CompanyRole::builder()
+            .id(CompanyRoleID::create())
+            .company_id(company_id.clone())
+            .name("bookkeeper")
+            .permissions(vec![Permission::Payroll])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let member1 = make_member_worker(&MemberID::create(), &UserID::create(), &company_id, &OccupationID::create(), vec![], &now);
+        let member2 = make_member_worker(&MemberID::create(), &UserID::create(), &company_id, &OccupationID::create(), vec![], &now);
+        assert_eq!(role.check_members(&[]), Ok(()));
+
+        assert!(role.assign(member1.id()));
+        assert!(!role.assign(member1.id()));
+        assert!(role.assign(member2.id()));
+        assert_eq!(role.check_members(&[member2.clone(), member1.clone()]), Ok(()));
+        assert_eq!(role.check_members(&[member1.clone()]), Err(Error::RoleAssignmentMismatch));
+        assert_eq!(role.check_members(&[member1.clone(), member1.clone(), member2.clone()]), Err(Error::RoleAssignmentMismatch));
+
+        assert!(role.unassign(member1.id()));
+        assert!(!role.unassign(member1.id()));
+        assert_eq!(role.check_members(&[member2.clone()]), Ok(()));
+        assert_eq!(role.check_members(&[member1.clone(), member2.clone()]), Err(Error::RoleAssignmentMismatch));
+    }
+}
+
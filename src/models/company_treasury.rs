@@ -0,0 +1,174 @@
+//! A company's treasury tracks the credits the company has brought into (and
+//! taken out of) circulation. Credits are *issued* when a company pays its
+//! workers (see [payroll][1]) and *destroyed* when members buy the company's
+//! products (see [order][2]). The difference between the two is the number of
+//! credits the company currently has outstanding.
+//!
+//! The treasury also carries the company's issuance policy: a cap on the
+//! credits it can have outstanding, and a cap on how many it can issue in a
+//! single payroll run. Without these, nothing stops a company from paying out
+//! an unlimited number of labor credits. It also remembers the end of the last
+//! pay period paid out, so the same period can't be paid twice.
+//!
+//! [1]: ../../transactions/company/fn.payroll.html
+//! [2]: ../../transactions/order/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    error::{Error, Result},
+    models::{
+        company::CompanyID,
+        lib::basis_model::Model,
+    },
+};
+use rust_decimal::prelude::*;
+
+basis_model! {
+    /// The `CompanyTreasury` model tallies the credits a company has issued and
+    /// destroyed, and holds the limits on its issuance. Any limit left as
+    /// `None` is not enforced.
+    pub struct CompanyTreasury {
+        id: <<CompanyTreasuryID>>,
+        /// The company this treasury belongs to
+        company_id: CompanyID,
+        /// The total credits issued by this company (wages paid)
+        issued: Decimal,
+        /// The total credits destroyed by this company (products sold)
+        destroyed: Decimal,
+        /// The maximum number of credits this company can have outstanding
+        max_outstanding: Option<Decimal>,
+        /// The maximum number of credits that can be issued in one payroll run
+        max_issue_per_run: Option<Decimal>,
+        /// The end of the last pay period paid out of this treasury. Payroll
+        /// can't pay a period that starts before this.
+        paid_through: Option<DateTime<Utc>>,
+    }
+    CompanyTreasuryBuilder
+}
+
+impl CompanyTreasury {
+    /// The number of credits issued that haven't been destroyed yet
+    pub fn outstanding(&self) -> Decimal {
+        self.issued() - self.destroyed()
+    }
+
+    /// Check whether issuing the given amount of credits (in one run) is
+    /// allowed by this treasury's policy. Inactive treasuries can't issue.
+    pub fn check_issue(&self, amount: &Decimal) -> Result<()> {
+        if amount < &Decimal::zero() {
+            Err(Error::InvalidAmount(*amount))?;
+        }
+        if !self.is_active() {
+            Err(Error::ObjectIsInactive("company_treasury".into()))?;
+        }
+        if let Some(max_issue_per_run) = self.max_issue_per_run() {
+            if amount > max_issue_per_run {
+                Err(Error::TreasuryLimitExceeded(format!("issuing {} credits is over the per-run limit of {}", amount, max_issue_per_run)))?;
+            }
+        }
+        if let Some(max_outstanding) = self.max_outstanding() {
+            if &(self.outstanding() + amount) > max_outstanding {
+                Err(Error::TreasuryLimitExceeded(format!("issuing {} credits would put {} credits outstanding, over the limit of {}", amount, self.outstanding() + amount, max_outstanding)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record an issuance of credits, checking it against our policy first.
+    pub(crate) fn issue(&mut self, amount: Decimal) -> Result<()> {
+        self.check_issue(&amount)?;
+        self.set_issued(self.issued() + amount);
+        Ok(())
+    }
+
+    /// Check that a pay period is valid and hasn't been paid yet (in whole or
+    /// in part).
+    pub fn check_pay_period(&self, period_start: &DateTime<Utc>, period_end: &DateTime<Utc>) -> Result<()> {
+        if period_end <= period_start {
+            Err(Error::PayPeriodInvalid)?;
+        }
+        if let Some(paid_through) = self.paid_through() {
+            if period_start < paid_through {
+                Err(Error::PayPeriodAlreadyPaid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the destruction of credits.
+    pub(crate) fn destroy(&mut self, amount: Decimal) -> Result<()> {
+        if amount < Decimal::zero() {
+            Err(Error::InvalidAmount(amount))?;
+        }
+        self.set_destroyed(self.destroyed() + amount);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    fn issues_and_destroys() {
+        let now = util::time::now();
+        let mut treasury = CompanyTreasury::builder()
+            .id(CompanyTreasuryID::create())
+            .company_id(CompanyID::new("jerry's widgets"))
+            .issued(num!(0))
+            .destroyed(num!(0))
+            .max_outstanding(Some(num!(1000)))
+            .max_issue_per_run(Some(num!(600)))
+            .paid_through(None)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        treasury.issue(num!(500)).unwrap();
+        assert_eq!(treasury.outstanding(), num!(500));
+        assert_eq!(treasury.issue(num!(601)), Err(Error::TreasuryLimitExceeded("issuing 601 credits is over the per-run limit of 600".into())));
+        assert_eq!(treasury.issue(num!(501)), Err(Error::TreasuryLimitExceeded("issuing 501 credits would put 1001 credits outstanding, over the limit of 1000".into())));
+        assert_eq!(treasury.issued(), &num!(500));
+        treasury.destroy(num!(200)).unwrap();
+        assert_eq!(treasury.outstanding(), num!(300));
+        treasury.issue(num!(600)).unwrap();
+        assert_eq!(treasury.outstanding(), num!(900));
+        assert_eq!(treasury.issue(num!(-1)), Err(Error::InvalidAmount(num!(-1))));
+        assert_eq!(treasury.destroy(num!(-1)), Err(Error::InvalidAmount(num!(-1))));
+
+        // inactive treasuries can't issue, but still count what's destroyed
+        treasury.set_active(false);
+        assert_eq!(treasury.issue(num!(5)), Err(Error::ObjectIsInactive("company_treasury".into())));
+        assert_eq!(treasury.issued(), &num!(1100));
+        treasury.destroy(num!(100)).unwrap();
+        assert_eq!(treasury.outstanding(), num!(800));
+    }
+
+    #[test]
+    fn check_pay_period() {
+        let now = util::time::now();
+        let mut treasury = CompanyTreasury::builder()
+            .id(CompanyTreasuryID::create())
+            .company_id(CompanyID::new("jerry's widgets"))
+            .issued(num!(0))
+            .destroyed(num!(0))
+            .max_outstanding(None)
+            .max_issue_per_run(None)
+            .paid_through(None)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let jan1: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let jan15: DateTime<Utc> = "2020-01-15T00:00:00Z".parse().unwrap();
+        let feb1: DateTime<Utc> = "2020-02-01T00:00:00Z".parse().unwrap();
+        assert_eq!(treasury.check_pay_period(&jan1, &jan15), Ok(()));
+        assert_eq!(treasury.check_pay_period(&jan15, &jan1), Err(Error::PayPeriodInvalid));
+        assert_eq!(treasury.check_pay_period(&jan1, &jan1), Err(Error::PayPeriodInvalid));
+        treasury.set_paid_through(Some(jan15.clone()));
+        assert_eq!(treasury.check_pay_period(&jan1, &jan15), Err(Error::PayPeriodAlreadyPaid));
+        assert_eq!(treasury.check_pay_period(&jan1, &feb1), Err(Error::PayPeriodAlreadyPaid));
+        assert_eq!(treasury.check_pay_period(&jan15, &feb1), Ok(()));
+    }
+}
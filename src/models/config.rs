@@ -0,0 +1,116 @@
+//! The network config holds the parameters that apply to the system as a whole
+//! rather than to any one company or user: how much UBI people get, how
+//! precise costs are, how quickly idle credits decay, which resources the
+//! network keeps an eye on, and which costs are too far out of line to accept.
+//!
+//! Keeping these in the data model (rather than hard-coded or off in some
+//! external config file) means every node works from the same parameters, and
+//! changes to them are recorded like any other modification. Only system
+//! admins can change the config.
+//!
+//! Note that the core doesn't load the config itself. Whatever is running the
+//! core passes the relevant parts of it into the functions that need them (for
+//! instance, `config.ubi()` into [ubi::claim][1], or `config.cost_precision()`
+//! into `Costs::normalize_with()`, or `config.sanity_policy()` into
+//! [validate::check_modifications][3]).
+//!
+//! The config can also cap how many hours a member can work within a rolling
+//! period (see `WorkHoursCap`). A system built on human dignity shouldn't let
+//! anyone be worked into the ground, so when a cap is passed into the [work
+//! transactions][2], work that would push a member over it is rejected.
+//!
+//! [1]: ../../system/ubi/fn.claim.html
+//! [2]: ../../transactions/event/work/index.html
+//! [3]: ../../costs/validate/fn.check_modifications.html
+
+use crate::{
+    costs::{
+        precision::CostPrecision,
+        validate::SanityPolicy,
+    },
+    error::{Error, Result},
+    models::resource_spec::ResourceSpecID,
+    system::ubi::UBIParameters,
+    util::measure,
+};
+use getset::Getters;
+use om2::Measure;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// A cap on the number of hours a member can work within a rolling period
+/// (for instance, 60 hours per week).
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct WorkHoursCap {
+    /// The most hours a member can work within any one period
+    max_hours: Decimal,
+    /// How long the period lasts, in units of time up to a week (for instance,
+    /// 1 week)
+    period: Measure,
+}
+
+impl WorkHoursCap {
+    /// Create a new work hours cap. The max hours must be positive, and the
+    /// period must be a positive amount of time.
+    pub fn new(max_hours: Decimal, period: Measure) -> Result<Self> {
+        if max_hours <= Decimal::zero() {
+            Err(Error::InvalidAmount(max_hours))?;
+        }
+        let cap = Self { max_hours, period };
+        cap.period_seconds()?;
+        Ok(cap)
+    }
+
+    /// Get the length of our period in seconds.
+    pub fn period_seconds(&self) -> Result<i64> {
+        measure::to_seconds(self.period())
+    }
+}
+
+basis_model! {
+    /// The `NetworkConfig` model holds network-wide parameters.
+    pub struct NetworkConfig {
+        id: <<NetworkConfigID>>,
+        /// How UBI accrues (and how much of it can pile up)
+        ubi: UBIParameters,
+        /// How precise costs are, and how they're rounded
+        cost_precision: CostPrecision,
+        /// The caps that costs entering the system must fall within
+        sanity_policy: SanityPolicy,
+        /// The fraction of an idle credit balance that decays each day. Zero
+        /// means credits don't decay.
+        demurrage_rate: Decimal,
+        /// The resource specs the network tracks (for instance, for depletion
+        /// or for reporting)
+        tracked_resource_specs: Vec<ResourceSpecID>,
+        /// The most hours a member can work in a given period. `None` means
+        /// hours aren't capped.
+        work_hours_cap: Option<WorkHoursCap>,
+    }
+    NetworkConfigBuilder
+}
+
+impl NetworkConfig {
+    /// Determine if the given resource spec is tracked by the network.
+    pub fn is_tracked(&self, spec_id: &ResourceSpecID) -> bool {
+        self.tracked_resource_specs().contains(spec_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use om2::Unit;
+
+    #[test]
+    fn work_hours_cap() {
+        let cap = WorkHoursCap::new(num!(60), Measure::new(num!(1), Unit::Week)).unwrap();
+        assert_eq!(cap.period_seconds().unwrap(), 60 * 60 * 24 * 7);
+        assert_eq!(WorkHoursCap::new(num!(0), Measure::new(num!(1), Unit::Week)), Err(Error::InvalidAmount(num!(0))));
+        assert_eq!(WorkHoursCap::new(num!(60), Measure::new(num!(0), Unit::Day)), Err(Error::InvalidAmount(num!(0))));
+        assert_eq!(WorkHoursCap::new(num!(60), Measure::new(num!(1), Unit::Kilogram)), Err(Error::MeasureUnitsMismatched));
+    }
+}
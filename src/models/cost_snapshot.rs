@@ -0,0 +1,97 @@
+//! A cost snapshot records a resource's costs (and quantity) as they stood
+//! right after an event changed them. Taken together, a resource's snapshots
+//! form a time series of its costs, which makes it possible to chart how its
+//! per-unit cost changes over time without replaying the full event log.
+//!
+//! Snapshots are optional: the event processor only emits them when the
+//! `emit_snapshots` flag is set on the [EventProcessState][1].
+//!
+//! [1]: ../event/struct.EventProcessState.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        event::EventID,
+        resource::{Resource, ResourceID},
+    },
+    util::measure,
+};
+use om2::Measure;
+
+basis_model! {
+    /// The `CostSnapshot` model captures a resource's costs after an event.
+    pub struct CostSnapshot {
+        id: <<CostSnapshotID>>,
+        /// The resource this snapshot is for
+        resource_id: ResourceID,
+        /// The event that produced this snapshot
+        event_id: EventID,
+        /// The resource's total costs after the event
+        costs: Costs,
+        /// The resource's accounting quantity after the event
+        quantity: Option<Measure>,
+    }
+    CostSnapshotBuilder
+}
+
+impl CostSnapshotID {
+    /// Snapshots are identified by the event and resource they belong to, so
+    /// processing the same event twice yields the same snapshot.
+    pub fn from_event(event_id: &EventID, resource_id: &ResourceID) -> Self {
+        Self::new(format!("{}/{}", event_id.as_str(), resource_id.as_str()))
+    }
+}
+
+impl CostSnapshot {
+    /// Take a snapshot of a resource's costs as of the given event.
+    pub(crate) fn from_resource(event_id: &EventID, resource: &Resource, now: &DateTime<Utc>) -> Result<Self> {
+        Self::builder()
+            .id(CostSnapshotID::from_event(event_id, resource.id()))
+            .resource_id(resource.id().clone())
+            .event_id(event_id.clone())
+            .costs(resource.costs().clone())
+            .quantity(resource.inner().accounting_quantity().clone())
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))
+    }
+
+    /// The costs of a single unit of the resource at the time of the snapshot.
+    /// Returns `None` if the resource had no (or zero) quantity.
+    pub fn costs_per_unit(&self) -> Option<Costs> {
+        let quantity = self.quantity().as_ref().and_then(|x| measure::to_decimal(x).ok())?;
+        if quantity.is_zero() {
+            return None;
+        }
+        Some(self.costs().per_unit(quantity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::company::CompanyID,
+        util::{self, test::*},
+    };
+    use om2::Unit;
+
+    #[test]
+    fn costs_per_unit() {
+        let now = util::time::now();
+        let event_id = EventID::new("event1");
+        let resource = make_resource(&ResourceID::new("widget"), &CompanyID::create(), &Measure::new(num!(4), Unit::One), &Costs::new_with_labor("machinist", num!(100)), &now);
+        let mut snapshot = CostSnapshot::from_resource(&event_id, &resource, &now).unwrap();
+        assert_eq!(snapshot.id(), &CostSnapshotID::new("event1/widget"));
+        assert_eq!(snapshot.costs_per_unit(), Some(Costs::new_with_labor("machinist", num!(25))));
+
+        snapshot.set_quantity(Some(Measure::new(num!(0), Unit::One)));
+        assert_eq!(snapshot.costs_per_unit(), None);
+        snapshot.set_quantity(None);
+        assert_eq!(snapshot.costs_per_unit(), None);
+    }
+}
@@ -5,6 +5,37 @@
 //!
 //! [banking]: https://basisproject.gitlab.io/public/paper#chapter-6-banking
 
+use chrono::{DateTime, Utc};
+use crate::{
+    error::{Error, Result},
+};
+use getset::{Getters, Setters};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// Records the exchange rate (in credits per unit of currency) for a currency
+/// starting at a given point in time.
+#[derive(Clone, Debug, PartialEq, Getters, Setters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub", set = "pub(crate)")]
+pub struct CurrencyRate {
+    /// How many credits one unit of this currency is worth
+    rate: Decimal,
+    /// When this rate takes effect
+    effective: DateTime<Utc>,
+}
+
+impl CurrencyRate {
+    /// Create a new currency rate
+    pub fn new(rate: Decimal, effective: DateTime<Utc>) -> Self {
+        Self {
+            rate,
+            effective,
+        }
+    }
+}
+
 basis_model! {
     /// The currency model allows the banking system to track various currencies
     /// as they move through the system, which ultimately allows an accurate
@@ -15,7 +46,70 @@ basis_model! {
         name: String,
         /// How many decimal places this currency uses.
         decimal_places: u8,
+        /// The history of exchange rates for this currency, sorted by their
+        /// effective date (oldest first).
+        rates: Vec<CurrencyRate>,
     }
     CurrencyBuilder
 }
 
+impl Currency {
+    /// Add a rate to this currency's history, keeping the history sorted by
+    /// effective date. If a rate already exists with the same effective date,
+    /// it is replaced.
+    pub(crate) fn push_rate(&mut self, rate: CurrencyRate) {
+        let rates = self.rates_mut();
+        rates.retain(|x| x.effective() != rate.effective());
+        let idx = rates.iter().position(|x| x.effective() > rate.effective()).unwrap_or(rates.len());
+        rates.insert(idx, rate);
+    }
+
+    /// Find the exchange rate that was in effect at the given time.
+    pub fn rate_at(&self, at: &DateTime<Utc>) -> Result<Decimal> {
+        self.rates().iter()
+            .rev()
+            .find(|x| x.effective() <= at)
+            .map(|x| x.rate().clone())
+            .ok_or(Error::CurrencyRateMissing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util,
+    };
+
+    #[test]
+    fn rate_history() {
+        let now = util::time::now();
+        let mut currency = Currency::builder()
+            .id(CurrencyID::create())
+            .name("usd")
+            .decimal_places(2)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let date1: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let date2: DateTime<Utc> = "2020-02-01T00:00:00Z".parse().unwrap();
+        let date3: DateTime<Utc> = "2020-03-01T00:00:00Z".parse().unwrap();
+        assert_eq!(currency.rate_at(&date1), Err(Error::CurrencyRateMissing));
+
+        currency.push_rate(CurrencyRate::new(num!(1.3), date3.clone()));
+        currency.push_rate(CurrencyRate::new(num!(1.1), date1.clone()));
+        currency.push_rate(CurrencyRate::new(num!(1.4), date2.clone()));
+        currency.push_rate(CurrencyRate::new(num!(1.2), date2.clone()));
+        assert_eq!(currency.rates().len(), 3);
+        assert_eq!(currency.rates()[0].effective(), &date1);
+        assert_eq!(currency.rates()[1].effective(), &date2);
+        assert_eq!(currency.rates()[2].effective(), &date3);
+
+        assert_eq!(currency.rate_at(&"2019-12-31T23:59:59Z".parse().unwrap()), Err(Error::CurrencyRateMissing));
+        assert_eq!(currency.rate_at(&date1), Ok(num!(1.1)));
+        assert_eq!(currency.rate_at(&"2020-01-15T00:00:00Z".parse().unwrap()), Ok(num!(1.1)));
+        assert_eq!(currency.rate_at(&date2), Ok(num!(1.2)));
+        assert_eq!(currency.rate_at(&"2021-01-01T00:00:00Z".parse().unwrap()), Ok(num!(1.3)));
+    }
+}
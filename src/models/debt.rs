@@ -0,0 +1,109 @@
+//! A debt records external currency one company owes another. While the network
+//! itself runs on costs and credits, companies operating in a transitional
+//! market sometimes need to front each other currency (for instance, to buy
+//! inputs from a seller outside the network).
+//!
+//! Debts are interest-free by design: the amount owed can only ever go down,
+//! either by the debtor repaying it or the creditor forgiving it. Due dates are
+//! informational and don't accrue any penalty when missed.
+//!
+//! Debts are tracked in both companies' [ledgers][1].
+//!
+//! [1]: ../company_ledger/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    models::{
+        company::CompanyID,
+        currency::CurrencyID,
+    },
+};
+use rust_decimal::prelude::*;
+
+basis_model! {
+    /// The `Debt` model tracks an amount of currency one company owes another.
+    pub struct Debt {
+        id: <<DebtID>>,
+        /// The company that is owed
+        creditor_id: CompanyID,
+        /// The company that owes
+        debtor_id: CompanyID,
+        /// The currency the debt is denominated in
+        currency_id: CurrencyID,
+        /// The original amount owed
+        principal: Decimal,
+        /// The amount the debtor has repaid so far
+        repaid: Decimal,
+        /// The amount the creditor has forgiven so far
+        forgiven: Decimal,
+        /// When the debt should be repaid by, if ever
+        due: Option<DateTime<Utc>>,
+        /// Notes about the debt (what it's for, etc)
+        note: Option<String>,
+    }
+    DebtBuilder
+}
+
+impl Debt {
+    /// The amount still owed on this debt
+    pub fn outstanding(&self) -> Decimal {
+        self.principal().clone() - self.repaid().clone() - self.forgiven().clone()
+    }
+
+    /// Whether or not this debt has been fully repaid and/or forgiven
+    pub fn is_settled(&self) -> bool {
+        self.outstanding() <= Decimal::zero()
+    }
+
+    /// Whether or not this debt is past its due date without being settled
+    pub fn is_overdue(&self, now: &DateTime<Utc>) -> bool {
+        match self.due() {
+            Some(due) => due < now && !self.is_settled(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util,
+    };
+
+    #[test]
+    fn outstanding() {
+        let now = util::time::now();
+        let mut debt = Debt::builder()
+            .id(DebtID::create())
+            .creditor_id(CompanyID::new("larry's chairs"))
+            .debtor_id(CompanyID::new("jerry's widgets"))
+            .currency_id(CurrencyID::new("usd"))
+            .principal(num!(500))
+            .repaid(Decimal::zero())
+            .forgiven(Decimal::zero())
+            .due(Some(now.clone() + chrono::Duration::days(30)))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        assert_eq!(debt.outstanding(), num!(500));
+        assert!(!debt.is_settled());
+        assert!(!debt.is_overdue(&now));
+        assert!(debt.is_overdue(&(now.clone() + chrono::Duration::days(31))));
+
+        debt.set_repaid(num!(300));
+        debt.set_forgiven(num!(150));
+        assert_eq!(debt.outstanding(), num!(50));
+        assert!(!debt.is_settled());
+
+        debt.set_repaid(num!(350));
+        assert_eq!(debt.outstanding(), num!(0));
+        assert!(debt.is_settled());
+        assert!(!debt.is_overdue(&(now.clone() + chrono::Duration::days(31))));
+
+        debt.set_due(None);
+        debt.set_repaid(num!(0));
+        assert!(!debt.is_overdue(&(now.clone() + chrono::Duration::days(365))));
+    }
+}
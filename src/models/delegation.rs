@@ -0,0 +1,115 @@
+//! A delegation temporarily lends some of one member's company permissions to
+//! another member of the same company, for instance to cover for someone while
+//! they're on vacation. Delegations expire on their own, so nobody has to
+//! remember to take the permissions back. A delegation is only as good as the
+//! permissions of the member who made it: if they lose a permission, so does
+//! everyone they lent it to.
+//!
+//! Delegations are narrow on purpose: right now they only cover approving
+//! payroll (see [DELEGABLE_PERMISSIONS]), which is checked with
+//! [Member::access_check_delegated][1] by the [company payroll][2] transaction.
+//! Every other access check goes through `Member::access_check()`, which does
+//! not look at delegations at all.
+//!
+//! [DELEGABLE_PERMISSIONS]: constant.DELEGABLE_PERMISSIONS.html
+//! [1]: ../member/struct.Member.html#method.access_check_delegated
+//! [2]: ../../transactions/company/fn.payroll.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    models::{
+        company::{CompanyID, Permission},
+        lib::basis_model::Model,
+        member::{Member, MemberID},
+    },
+};
+
+/// The permissions that can be delegated.
+pub const DELEGABLE_PERMISSIONS: &[Permission] = &[Permission::Payroll];
+
+basis_model! {
+    /// The `Delegation` model lends a set of permissions from one member to
+    /// another until it expires.
+    pub struct Delegation {
+        id: <<DelegationID>>,
+        /// The company the permissions apply to
+        company_id: CompanyID,
+        /// The member lending their permissions
+        from_member: MemberID,
+        /// The member receiving the permissions
+        to_member: MemberID,
+        /// The permissions being lent
+        permissions: Vec<Permission>,
+        /// When the delegation stops being honored
+        expires: DateTime<Utc>,
+    }
+    DelegationBuilder
+}
+
+impl Delegation {
+    /// Whether or not this delegation is being honored at the given time
+    pub fn is_in_effect(&self, now: &DateTime<Utc>) -> bool {
+        self.is_active() && now < self.expires()
+    }
+
+    /// Whether or not this delegation gives the given member a permission at a
+    /// company at the given time. The `grantor` is the (current) member who
+    /// made the delegation, and must still hold the permission themselves.
+    pub fn grants(&self, grantor: &Member, member_id: &MemberID, company_id: &CompanyID, permission: &Permission, now: &DateTime<Utc>) -> bool {
+        self.is_in_effect(now) &&
+            self.to_member() == member_id &&
+            self.company_id() == company_id &&
+            self.permissions().contains(permission) &&
+            grantor.id() == self.from_member() &&
+            grantor.group_id() == &company_id.clone().into() &&
+            grantor.can(permission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{occupation::OccupationID, user::UserID},
+        util::{self, test::*},
+    };
+    use chrono::Duration;
+
+    #[test]
+    fn grants() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let member_id = MemberID::new("larry");
+        let mut grantor = make_member_worker(&MemberID::new("jerry"), &UserID::create(), &company_id, &OccupationID::create(), vec![Permission::Payroll], &now);
+        let mut delegation = Delegation::builder()
+            .id(DelegationID::create())
+            .company_id(company_id.clone())
+            .from_member(MemberID::new("jerry"))
+            .to_member(member_id.clone())
+            .permissions(vec![Permission::Payroll])
+            .expires(now.clone() + Duration::days(7))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        assert!(delegation.grants(&grantor, &member_id, &company_id, &Permission::Payroll, &now));
+        assert!(!delegation.grants(&grantor, &member_id, &company_id, &Permission::MemberCreate, &now));
+        assert!(!delegation.grants(&grantor, &MemberID::new("jerry"), &company_id, &Permission::Payroll, &now));
+        assert!(!delegation.grants(&grantor, &member_id, &CompanyID::new("larry's chairs"), &Permission::Payroll, &now));
+        assert!(!delegation.grants(&grantor, &member_id, &company_id, &Permission::Payroll, &(now.clone() + Duration::days(7))));
+
+        // the grantor has to still hold the permission
+        grantor.set_permissions(vec![Permission::MemberCreate]);
+        assert!(!delegation.grants(&grantor, &member_id, &company_id, &Permission::Payroll, &now));
+        grantor.set_permissions(vec![Permission::Payroll]);
+        let mut grantor2 = grantor.clone();
+        grantor2.set_active(false);
+        assert!(!delegation.grants(&grantor2, &member_id, &company_id, &Permission::Payroll, &now));
+        let grantor3 = make_member_worker(&MemberID::new("barry"), &UserID::create(), &company_id, &OccupationID::create(), vec![Permission::Payroll], &now);
+        assert!(!delegation.grants(&grantor3, &member_id, &company_id, &Permission::Payroll, &now));
+        assert!(delegation.grants(&grantor, &member_id, &company_id, &Permission::Payroll, &now));
+
+        delegation.set_deleted(Some(now.clone()));
+        assert!(!delegation.grants(&grantor, &member_id, &company_id, &Permission::Payroll, &now));
+    }
+}
@@ -15,18 +15,24 @@
 //! - A [Commitment] represents "we agree that something will happen"
 //! - An `Event` represents "something did happen"
 //!
+//! Events created while processing other events can be traced back to their
+//! origin using the [provenance] module.
+//!
 //! [Intent]: ../intent/struct.Intent.html
 //! [Commitment]: ../commitment/struct.Commitment.html
+//! [provenance]: provenance/index.html
 
 use chrono::{DateTime, Utc};
 use crate::{
-    costs::{Costs, CostMover},
+    costs::{Costs, CostMover, validate::{self, SanityPolicy}},
     error::{Error, Result},
     models::{
         Op,
         Modifications,
 
         agreement::AgreementID,
+        cost_snapshot::CostSnapshot,
+        currency::CurrencyID,
         member::{Member},
         lib::{
             agent::{Agent, AgentID},
@@ -39,18 +45,25 @@ use crate::{
     util::measure,
 };
 use derive_builder::Builder;
+use getset::Getters;
 use om2::{Measure, NumericUnion, Unit};
 use rust_decimal::prelude::*;
 #[cfg(feature = "with_serde")]
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
 use thiserror::Error;
 use url::Url;
 use vf_rs::vf::{self, Action, InputOutput, ResourceEffect};
 
+pub mod provenance;
+
 /// An error type for when event processing goes awry.
 #[derive(Error, Debug, PartialEq)]
 #[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
 pub enum EventError {
+    /// A byproduct was specified on an event that doesn't produce anything
+    #[error("byproducts can only be recorded on `produce` events")]
+    ByproductMustProduce,
     /// An event's end date must be after its begin date
     #[error("end time must be after begin time")]
     DateEndBeforeBegin,
@@ -64,6 +77,9 @@ pub enum EventError {
     /// We expected an InputOutput value but didn't find one
     #[error("missing InputOutput designation")]
     InvalidInputOutput,
+    /// A labor event would push a process over its labor budget
+    #[error("the process' labor budget has been exceeded")]
+    LaborBudgetExceeded,
     /// A labor event was recorded with some effort unit other than hours
     #[error("labor effort must be recorded in hours")]
     LaborMustBeHours,
@@ -109,9 +125,20 @@ pub enum EventError {
     /// The event is missing the `resource_to` object
     #[error("this event requires the `resource_to` object")]
     MissingResourceTo,
+    /// Trying to finalize an event that isn't pending (it must have a
+    /// `has_beginning` and no `has_end`)
+    #[error("event is not pending")]
+    NotPending,
+    /// Trying to run a work-only operation (like clocking out) on an event
+    /// that isn't a `work` event
+    #[error("event is not a work event")]
+    NotWorkEvent,
     /// When we try to run an operation on a process we don't own
     #[error("operation on a resource you don't own")]
     ProcessOwnerMismatch,
+    /// A renewal was specified on an event that doesn't raise a resource
+    #[error("renewals can only be recorded on `raise` events")]
+    RenewalMustRaise,
     /// The resource's accounting quantity cannot be zero if the resource has
     /// non-zero `costs`
     #[error("event's resource cannot have an accounting quantity == 0 with costs > 0")]
@@ -122,6 +149,17 @@ pub enum EventError {
     /// When performing an operation on a resource that doesn't belong to you
     #[error("operation on a resource you don't own")]
     ResourceOwnerMismatch,
+    /// A pending transfer can only be completed by the receiving company
+    /// accepting it
+    #[error("pending transfers must be accepted by the receiver")]
+    TransferAwaitingAcceptance,
+    /// Recording this work would push a worker over the network's cap on
+    /// working hours
+    #[error("work hours cap exceeded: {0}")]
+    WorkHoursCapExceeded(String),
+    /// A work session overlaps another session recorded by the same worker
+    #[error("work session overlaps an existing session for this worker")]
+    WorkSessionOverlap,
 }
 
 /// When creating a `transfer` event, we need to know if that event transfers
@@ -135,8 +173,48 @@ pub enum MoveType {
     /// This moves a resource internally in the company (this is the original
     /// indended purpose of the `move` action in VF)
     Resource,
+    /// Writes costs off of a resource or process (spoilage, scrap, etc). The
+    /// costs are released from the object and it's up to the transaction
+    /// layer to tally them as waste.
+    WriteOff,
+    /// Sells (part of) a resource to a buyer outside of the network, recording
+    /// the currency and amount received. Like a write-off, the costs are
+    /// released from the resource, but they're accounted for by the payment
+    /// rather than as waste.
+    ExternalSale(CurrencyID, Decimal),
+}
+
+/// Marks a `Raise` event as replenishing a resource (as opposed to a simple
+/// correction of its quantity), which lets us track resource depletion against
+/// renewal.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum Renewal {
+    /// The resource was regenerated naturally or by cultivation (regrowth,
+    /// replanting, aquifer recharge, etc)
+    Regeneration,
+    /// The resource was recovered from used or discarded goods
+    Recycling,
 }
 
+/// Marks a `Produce` event's output as secondary to the main product of its
+/// process, which lets processes with several outputs decide how much of their
+/// costs each output carries.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum Byproduct {
+    /// A useful output that isn't the point of the process (sawdust from
+    /// milling lumber, whey from making cheese, etc)
+    CoProduct,
+    /// A waste stream (scrap, effluent, etc)
+    Waste,
+}
+
+/// Structured data attached to an event (external invoice numbers, photo
+/// hashes, etc) as key/value pairs. Keys are kept sorted so the metadata always
+/// serializes (and hashes) the same way.
+pub type EventMetadata = BTreeMap<String, String>;
+
 basis_model! {
     /// The event model, which is the glue that moves costs between objects.
     ///
@@ -166,6 +244,19 @@ basis_model! {
         /// things more clear when creating the event whether it should be
         /// allowed or not.
         move_type: Option<MoveType>,
+        /// If this is a `Raise` event, marks whether the quantity raised is a
+        /// renewal (or recycling) of the resource. See the [depletion][1]
+        /// module.
+        ///
+        /// [1]: ../../costs/depletion/index.html
+        renewal: Option<Renewal>,
+        /// If this is a `Produce` event, marks whether the output is a
+        /// byproduct (or waste) rather than the process' main product.
+        byproduct: Option<Byproduct>,
+        /// Structured data implementors want to attach to the event, in
+        /// addition to the free-text `note`. The core never reads or changes
+        /// this.
+        metadata: Option<EventMetadata>,
     }
     EventBuilder
 }
@@ -186,6 +277,15 @@ pub struct EventProcessState {
     resource: Option<Resource>,
     /// The secondary resource we're operating on (Transfer/Move/etc)
     to_resource: Option<Resource>,
+    /// If true, a [CostSnapshot] is created for each resource the event
+    /// creates or modifies, recording its costs after the event.
+    ///
+    /// [CostSnapshot]: ../cost_snapshot/struct.CostSnapshot.html
+    emit_snapshots: bool,
+    /// If given, the event's `move_costs` are checked against this policy
+    /// before anything is processed (see `with_sanity_policy()`).
+    #[builder(setter(skip))]
+    sanity_policy: Option<SanityPolicy>,
 }
 
 impl EventProcessState {
@@ -193,6 +293,16 @@ impl EventProcessState {
     pub fn builder() -> EventProcessStateBuilder {
         EventProcessStateBuilder::default()
     }
+
+    /// Check the event's costs against the given [sanity policy][1] (generally
+    /// the one in the network config) when processing, rejecting the event if
+    /// they fall outside of it.
+    ///
+    /// [1]: ../../costs/validate/index.html
+    pub fn with_sanity_policy(mut self, policy: Option<&SanityPolicy>) -> Self {
+        self.sanity_policy = policy.cloned();
+        self
+    }
 }
 
 /// A standard result set our event processor can return, including the items
@@ -252,23 +362,336 @@ impl EventProcessResult {
         resource.set_updated(self.process_time.clone());
         self.modifications.push(Op::Update, resource);
     }
+
+    /// Push a snapshot of a resource's costs into the result set
+    fn snapshot_resource(&mut self, resource: &Resource) -> Result<()> {
+        let snapshot = CostSnapshot::from_resource(&self.event_id, resource, &self.process_time)?;
+        self.modifications.push(Op::Create, snapshot);
+        Ok(())
+    }
+}
+
+/// A hook for implementors that want to react to what an event did (send
+/// notifications, record metrics, etc) without having to dig through the
+/// `Modifications` returned from processing.
+///
+/// All callbacks have no-op defaults, so an observer only needs to implement
+/// the ones it cares about. Callbacks only fire once an event has been
+/// processed successfully: if processing fails, the observer hears nothing.
+pub trait EventObserver {
+    /// Called when costs move from one process/resource to another (or are
+    /// released via a write-off).
+    fn on_costs_moved(&mut self, _event: &Event, _costs: &Costs) {}
+
+    /// Called when processing the event creates a new resource.
+    fn on_resource_created(&mut self, _event: &Event, _resource: &Resource) {}
+
+    /// Called when ownership of a resource (or some quantity of one) changes
+    /// hands. For transfers, `resource` is the receiving resource.
+    fn on_ownership_changed(&mut self, _event: &Event, _resource: &Resource, _from: Option<&AgentID>, _to: &AgentID) {}
+}
+
+/// The do-nothing observer, used by `Event::process()`.
+impl EventObserver for () {}
+
+/// A set of fields on the `Event` (`move_costs`, `resource_quantity`, etc) and
+/// objects in the `EventProcessState` (`input_of`, `resource`, etc). Use
+/// `EventField::name()`/`StateField::name()` to get their names.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct RequiredFields {
+    /// Fields on the event
+    event: Vec<EventField>,
+    /// Objects in the process state
+    state: Vec<StateField>,
+}
+
+impl RequiredFields {
+    /// Returns true if there are no fields in this set
+    pub fn is_empty(&self) -> bool {
+        self.event.is_empty() && self.state.is_empty()
+    }
+}
+
+/// A field on an `Event` that processing can require.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EventField {
+    /// `inner.effort_quantity`
+    EffortQuantity,
+    /// `move_costs`
+    MoveCosts,
+    /// `move_type`
+    MoveType,
+    /// `inner.resource_quantity`
+    ResourceQuantity,
+    /// `inner.to_resource_inventoried_as`
+    ToResourceInventoriedAs,
+}
+
+impl EventField {
+    /// The name of this field, as it appears on the event
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventField::EffortQuantity => "effort_quantity",
+            EventField::MoveCosts => "move_costs",
+            EventField::MoveType => "move_type",
+            EventField::ResourceQuantity => "resource_quantity",
+            EventField::ToResourceInventoriedAs => "to_resource_inventoried_as",
+        }
+    }
+
+    /// Whether or not this field is set on the given event
+    pub fn is_set(&self, event: &Event) -> bool {
+        match self {
+            EventField::EffortQuantity => event.inner().effort_quantity().is_some(),
+            EventField::MoveCosts => event.move_costs().is_some(),
+            EventField::MoveType => event.move_type().is_some(),
+            EventField::ResourceQuantity => event.inner().resource_quantity().is_some(),
+            EventField::ToResourceInventoriedAs => event.inner().to_resource_inventoried_as().is_some(),
+        }
+    }
+}
+
+/// An object in the `EventProcessState` that processing can require.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StateField {
+    /// `input_of`, the process the event is an input of
+    InputOf,
+    /// `output_of`, the process the event is an output of
+    OutputOf,
+    /// `provider`, the member providing labor
+    Provider,
+    /// `resource`, the resource the event acts on
+    Resource,
+    /// `to_resource`, the resource the event moves things into
+    ToResource,
+}
+
+impl StateField {
+    /// The name of this object, as it appears in the process state
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateField::InputOf => "input_of",
+            StateField::OutputOf => "output_of",
+            StateField::Provider => "provider",
+            StateField::Resource => "resource",
+            StateField::ToResource => "to_resource",
+        }
+    }
+
+    /// Whether or not this object is present in the given state
+    pub fn is_set(&self, state: &EventProcessState) -> bool {
+        match self {
+            StateField::InputOf => state.input_of.is_some(),
+            StateField::OutputOf => state.output_of.is_some(),
+            StateField::Provider => state.provider.is_some(),
+            StateField::Resource => state.resource.is_some(),
+            StateField::ToResource => state.to_resource.is_some(),
+        }
+    }
+}
+
+/// Describes what an event needs in order to be processed, based only on its
+/// action and move type. This lets callers know which records to pull out of
+/// storage *before* building an `EventProcessState`.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct StateRequirements {
+    /// Fields that must be set on the event
+    event: Vec<EventField>,
+    /// Objects that must be present in the process state
+    state: Vec<StateField>,
+    /// At least one of these objects must be present in the process state.
+    /// This is used by write-offs, which can come from either a process
+    /// (`output_of`) or a resource (`resource`, which then also requires
+    /// `resource_quantity`).
+    one_of: Vec<StateField>,
+    /// Objects that are used if present but can be left out (for instance,
+    /// `to_resource` can be omitted if processing is meant to create it)
+    optional: Vec<StateField>,
+}
+
+impl StateRequirements {
+    /// Whether or not the given state holds every object required here. Note
+    /// that this doesn't check the event's own fields (see
+    /// `Event::validate()` for that).
+    pub fn is_satisfied_by(&self, state: &EventProcessState) -> bool {
+        let has = |field: &StateField| field.is_set(state);
+        self.state.iter().all(has) &&
+            (self.one_of.is_empty() || self.one_of.iter().any(has))
+    }
 }
 
 impl Event {
-    /// Our event processor. This method is responsible for mutating the objects
-    /// the event operates on (like subtracting costs from one resource/process
-    /// and adding them to another resource/process).
-    ///
-    /// This method returns an array of events that should be created as a
-    /// result of processing this event.
+    /// Determine which fields on the event and which objects in the process
+    /// state are needed to process this event, based on its action and move
+    /// type. This mirrors the logic `process()` uses when pulling values out
+    /// of the event and state.
+    pub fn required_state(&self) -> StateRequirements {
+        let mut event_fields = vec![];
+        let mut state_fields = vec![];
+        let mut one_of = vec![];
+        let mut optional = vec![];
+        let action = self.inner().action();
+        let accounting_effect = Some(action.resource_effect()).and_then(|x| if x == ResourceEffect::NoEffect { None } else { Some(x) });
+        let onhand_effect = Some(action.onhand_effect()).and_then(|x| if x == ResourceEffect::NoEffect { None } else { Some(x) });
+        let bundle_effect = accounting_effect.clone().or(onhand_effect.clone());
+        match action {
+            Action::Move => {
+                event_fields.push(EventField::MoveType);
+                event_fields.push(EventField::MoveCosts);
+                match self.move_type() {
+                    Some(MoveType::Resource) => {
+                        event_fields.push(EventField::ResourceQuantity);
+                        event_fields.push(EventField::ToResourceInventoriedAs);
+                    }
+                    Some(MoveType::ExternalSale(..)) => {
+                        event_fields.push(EventField::ResourceQuantity);
+                    }
+                    _ => {}
+                }
+            }
+            Action::Work => {
+                event_fields.push(EventField::MoveCosts);
+                event_fields.push(EventField::EffortQuantity);
+            }
+            _ => {
+                match (action.input_output(), bundle_effect.clone()) {
+                    (Some(_), _) | (_, Some(ResourceEffect::DecrementIncrement)) => {
+                        event_fields.push(EventField::MoveCosts);
+                    }
+                    _ => {}
+                }
+                if bundle_effect == Some(ResourceEffect::DecrementIncrement) {
+                    event_fields.push(EventField::ToResourceInventoriedAs);
+                }
+                if bundle_effect.is_some() {
+                    event_fields.push(EventField::ResourceQuantity);
+                }
+            }
+        }
+        match action {
+            Action::DeliverService => {
+                state_fields.push(StateField::InputOf);
+                state_fields.push(StateField::OutputOf);
+            }
+            Action::Move => {
+                match self.move_type() {
+                    Some(MoveType::ProcessCosts) => {
+                        state_fields.push(StateField::InputOf);
+                        state_fields.push(StateField::OutputOf);
+                    }
+                    Some(MoveType::Resource) => {
+                        state_fields.push(StateField::Resource);
+                        // to_resource not required because we can create the
+                        // resource via to_resource_inventoried_as
+                        optional.push(StateField::ToResource);
+                    }
+                    Some(MoveType::WriteOff) => {
+                        one_of.push(StateField::OutputOf);
+                        one_of.push(StateField::Resource);
+                    }
+                    Some(MoveType::ExternalSale(..)) => {
+                        state_fields.push(StateField::Resource);
+                    }
+                    _ => {}
+                }
+            }
+            Action::Use => {
+                state_fields.push(StateField::Resource);
+                state_fields.push(StateField::InputOf);
+            }
+            Action::Work => {
+                state_fields.push(StateField::InputOf);
+                state_fields.push(StateField::Provider);
+            }
+            _ => {
+                match action.input_output() {
+                    Some(InputOutput::Input) => {
+                        state_fields.push(StateField::InputOf);
+                    }
+                    Some(InputOutput::Output) => {
+                        state_fields.push(StateField::OutputOf);
+                    }
+                    _ => {}
+                }
+                if bundle_effect.is_some() {
+                    state_fields.push(StateField::Resource);
+                }
+                if bundle_effect == Some(ResourceEffect::DecrementIncrement) {
+                    optional.push(StateField::ToResource);
+                }
+            }
+        }
+        event_fields.sort();
+        state_fields.sort();
+        StateRequirements {
+            event: event_fields,
+            state: state_fields,
+            one_of,
+            optional,
+        }
+    }
+
+    /// Determine which fields on the event and which objects in the given
+    /// process state are needed to process this event. This is
+    /// `required_state()` resolved against an actual state (so a write-off
+    /// against a resource requires the resource, and passing a `to_resource`
+    /// requires `to_resource_inventoried_as`).
+    pub fn required_fields(&self, state: &EventProcessState) -> RequiredFields {
+        let requirements = self.required_state();
+        let mut event_fields = requirements.event().clone();
+        let mut state_fields = requirements.state().clone();
+        if !requirements.one_of().is_empty() {
+            // write-offs: a resource (and the quantity being written off) if
+            // we have one, otherwise the process
+            if state.resource.is_some() {
+                event_fields.push(EventField::ResourceQuantity);
+                state_fields.push(StateField::Resource);
+            } else {
+                state_fields.push(StateField::OutputOf);
+            }
+        }
+        if state.to_resource.is_some() && !event_fields.contains(&EventField::ToResourceInventoriedAs) {
+            event_fields.push(EventField::ToResourceInventoriedAs);
+        }
+        event_fields.sort();
+        state_fields.sort();
+        RequiredFields {
+            event: event_fields,
+            state: state_fields,
+        }
+    }
+
+    /// Run an event through the checks `process()` performs without returning
+    /// any modifications (a dry run). Useful for UIs that want to validate
+    /// forms before submitting them.
     ///
-    /// Note that this method *assumes the event is legitimate* and doesn't do
-    /// any kind of permissions checking. That should happen when the event is
-    /// created (in the transaction layer).
-    pub fn process(&self, state: EventProcessState, now: &DateTime<Utc>) -> Result<Modifications> {
-        // some low-hanging fruit error checking. basically make sure that if we
-        // pass in a process/resource that it's id matches the one we have in
-        // the event's data.
+    /// Returns the event fields and state objects that are required but
+    /// missing. If nothing is missing, the event is processed against a copy
+    /// of the state so that deeper problems (ownership, custody, cost moves,
+    /// etc) are returned as errors.
+    pub fn validate(&self, state: &EventProcessState, now: &DateTime<Utc>) -> Result<RequiredFields> {
+        self.check_state(state)?;
+        // pending events aren't applied, so they don't need anything yet
+        if self.inner().has_beginning().is_some() && self.inner().has_end().is_none() {
+            return Ok(RequiredFields::default());
+        }
+        let required = self.required_fields(state);
+        let missing = RequiredFields {
+            event: required.event().iter().filter(|x| !x.is_set(self)).cloned().collect(),
+            state: required.state().iter().filter(|x| !x.is_set(state)).cloned().collect(),
+        };
+        if missing.is_empty() {
+            self.process(state.clone(), now)?;
+        }
+        Ok(missing)
+    }
+
+    /// Some low-hanging fruit error checking. Basically make sure that if we
+    /// pass in a process/resource that its id matches the one we have in the
+    /// event's data, and that the event's own data is sane.
+    fn check_state(&self, state: &EventProcessState) -> Result<()> {
         if state.output_of.is_some() && self.inner().output_of().as_ref() != state.output_of.as_ref().map(|x| x.id()) {
             Err(EventError::MismatchedOutputProcessID)?;
         }
@@ -297,6 +720,42 @@ impl Event {
             }
             _ => {}
         }
+        if self.renewal().is_some() && self.inner().action() != &Action::Raise {
+            Err(EventError::RenewalMustRaise)?;
+        }
+        if self.byproduct().is_some() && self.inner().action() != &Action::Produce {
+            Err(EventError::ByproductMustProduce)?;
+        }
+        Ok(())
+    }
+
+    /// Our event processor. This method is responsible for mutating the objects
+    /// the event operates on (like subtracting costs from one resource/process
+    /// and adding them to another resource/process).
+    ///
+    /// This method returns an array of events that should be created as a
+    /// result of processing this event.
+    ///
+    /// Note that this method *assumes the event is legitimate* and doesn't do
+    /// any kind of permissions checking. That should happen when the event is
+    /// created (in the transaction layer). If the state carries a [sanity
+    /// policy][1], the event's `move_costs` are checked against it first.
+    ///
+    /// [1]: ../../costs/validate/index.html
+    pub fn process(&self, state: EventProcessState, now: &DateTime<Utc>) -> Result<Modifications> {
+        self.process_observed(state, now, &mut ())
+    }
+
+    /// Process this event (see `process()`), notifying the given observer of
+    /// any costs moved, resources created, or ownership changes once the event
+    /// is processed successfully.
+    pub fn process_observed(&self, state: EventProcessState, now: &DateTime<Utc>, observer: &mut dyn EventObserver) -> Result<Modifications> {
+        self.check_state(&state)?;
+        // catch obviously wrong costs (8000 hours instead of 8) before they
+        // get spread around
+        if let (Some(policy), Some(move_costs)) = (state.sanity_policy.as_ref(), self.move_costs()) {
+            validate::sanity_check(move_costs, policy)?;
+        }
 
         // create our result set.
         let mut res = EventProcessResult::new(self.id(), now);
@@ -347,6 +806,7 @@ impl Event {
         let mut resource2_is_create = false;
         let mut resource_owner_must_match = true;
         let mut move_costs: Option<Costs> = None;
+        let mut write_off = false;
 
         // tries to guess if we *need* a primary resource, and if so, grabs it
         // from the state
@@ -433,6 +893,29 @@ impl Event {
                         default_resource()?;
                         default_resource2(&resource)?;
                     }
+                    Some(MoveType::WriteOff) => {
+                        write_off = true;
+                        // write off from the resource if we have one, otherwise
+                        // from the process we're pulling costs out of
+                        match (state.resource.as_ref(), state.output_of.as_ref()) {
+                            (Some(_), _) => {
+                                default_resource()?;
+                            }
+                            (None, Some(output_process)) => {
+                                if self.inner().provider() != &output_process.company_id().clone().into() {
+                                    Err(EventError::ProcessOwnerMismatch)?;
+                                }
+                                process = Some(output_process.clone());
+                            }
+                            (None, None) => Err(EventError::MissingResource)?,
+                        }
+                    }
+                    Some(MoveType::ExternalSale(..)) => {
+                        // the costs leave with the resource, same as a
+                        // write-off
+                        write_off = true;
+                        default_resource()?;
+                    }
                     None => Err(EventError::MissingMoveType)?,
                 }
             }
@@ -450,8 +933,15 @@ impl Event {
             Action::Work => {
                 let mut input_process = state.input_of.clone().ok_or(EventError::MissingInputProcess)?;
                 let member = state.provider.clone().ok_or(EventError::MissingProvider)?;
-                let occupation_id = member.occupation_id().ok_or(Error::MemberMustBeWorker)?;
+                let primary_occupation_id = member.occupation_id().ok_or(Error::MemberMustBeWorker)?;
                 let move_costs = self.move_costs().as_ref().ok_or(EventError::MissingCosts)?;
+                // figure out which of the worker's occupations the labor is
+                // being claimed under. the costs name the occupation (via its
+                // labor or labor hours), and if none of the worker's
+                // occupations are named we fall back on their primary one.
+                let occupation_id = member.occupation_ids().into_iter()
+                    .find(|id| move_costs.labor().contains_key(*id) || move_costs.labor_hours().contains_key(*id))
+                    .unwrap_or(primary_occupation_id);
 
                 // grab JUST this occupation's costs from the event. in other
                 // words, we only accept costs specific to this occupation. it
@@ -475,6 +965,10 @@ impl Event {
                 costs.track_labor(occupation_id.clone(), occupation_costs);
                 costs.track_labor_hours(occupation_id.clone(), hours);
                 input_process.receive_costs(&costs)?;
+                input_process.spend_labor(&costs);
+                if input_process.is_over_labor_budget() {
+                    Err(EventError::LaborBudgetExceeded)?;
+                }
                 res.modify_process(input_process);
             }
             _ => {
@@ -566,16 +1060,27 @@ impl Event {
         let resource2_clone = resource2.clone();
 
         // cost moving logic
-        if process.is_some() && process2.is_some() {
+        let mut costs_moved: Option<Costs> = None;
+        if write_off {
+            let move_costs = move_costs.ok_or(EventError::MissingCosts)?;
+            if let Some(resource_inner) = resource.as_mut() {
+                resource_inner.release_costs(&move_costs)?;
+            } else if let Some(process_inner) = process.as_mut() {
+                process_inner.release_costs(&move_costs)?;
+            }
+            costs_moved = Some(move_costs);
+        } else if process.is_some() && process2.is_some() {
             let move_costs = move_costs.ok_or(EventError::MissingCosts)?;
             let process_output = process.as_mut().unwrap();
             let process_input = process2.as_mut().unwrap();
             process_output.move_costs_to(process_input, &move_costs)?;
+            costs_moved = Some(move_costs);
         } else if resource.is_some() && resource2.is_some() {
             let move_costs = move_costs.ok_or(EventError::MissingCosts)?;
             let resource_output = resource.as_mut().unwrap();
             let resource_input = resource2.as_mut().unwrap();
             resource_output.move_costs_to(resource_input, &move_costs)?;
+            costs_moved = Some(move_costs);
         } else if resource.is_some() && process.is_some() {
             let move_costs = move_costs.ok_or(EventError::MissingCosts)?;
             let resource_inner = resource.as_mut().unwrap();
@@ -589,6 +1094,7 @@ impl Event {
                 }
                 None => { Err(EventError::InvalidInputOutput)?; }
             }
+            costs_moved = Some(move_costs);
         }
 
         // accounting/offhand quantity adjustments
@@ -601,12 +1107,12 @@ impl Event {
                         let mut $measure = measure::unwrap_or_zero($res.inner().$fn_get(), &event_measure);
                         match effect {
                             ResourceEffect::Decrement | ResourceEffect::DecrementIncrement => {
-                                measure::dec_measure(&mut $measure, &event_measure)?;
+                                measure::dec_measure_with(&mut $measure, &event_measure, *$res.allow_negative())?;
                                 $($extra)*
                                 $res.inner_mut().$fn_set(Some($measure));
                             }
                             ResourceEffect::Increment => {
-                                measure::inc_measure(&mut $measure, &event_measure)?;
+                                measure::inc_measure_with(&mut $measure, &event_measure, *$res.allow_negative())?;
                                 $res.inner_mut().$fn_set(Some($measure));
                             }
                             _ => {}
@@ -624,7 +1130,7 @@ impl Event {
                             .ok_or(EventError::MissingEventMeasure)?;
                         let mut resource_measure = measure::unwrap_or_zero(res.inner().$fn_get(), &event_measure);
                         if effect == ResourceEffect::DecrementIncrement {
-                            measure::inc_measure(&mut resource_measure, &event_measure)?;
+                            measure::inc_measure_with(&mut resource_measure, &event_measure, *res.allow_negative())?;
                             res.inner_mut().$fn_set(Some(resource_measure));
                         }
                     }
@@ -642,6 +1148,10 @@ impl Event {
         incdec_builder_secondary! { accounting_effect, resource2, accounting_quantity, set_accounting_quantity }
         incdec_builder_primary! { onhand_effect.clone(), resource, onhand_quantity, set_onhand_quantity, res, resource_measure, {} }
         incdec_builder_secondary! { onhand_effect, resource2, onhand_quantity, set_onhand_quantity }
+        if let Some(res) = resource.as_ref() {
+            // quantity held for a pending transfer can't be moved or used up
+            res.check_held()?;
+        }
 
         // set resource custody/ownership
         if let Some(res) = resource.as_mut() {
@@ -663,21 +1173,50 @@ impl Event {
 
         // save any resource modifications
         if let Some(location) = self.inner().at_location().as_ref() {
-            if resource2.is_some() {
-                resource2.as_mut().map(|res| { res.inner_mut().set_current_location(Some(location.clone())); });
-            } else if resource.is_some() {
-                resource.as_mut().map(|res| { res.inner_mut().set_current_location(Some(location.clone())); });
+            if let Some(res) = resource2.as_mut().or(resource.as_mut()) {
+                res.move_to(location.clone(), self.id().clone(), now);
+            }
+        }
+
+        // let our observer know what happened
+        if let Some(costs) = costs_moved.as_ref() {
+            observer.on_costs_moved(self, costs);
+        }
+        if resource2_is_create {
+            if let Some(created) = resource2.as_ref() {
+                observer.on_resource_created(self, created);
+            }
+        }
+        if let (Some(after), Some(before)) = (resource.as_ref(), resource_clone.as_ref()) {
+            let from = before.inner().primary_accountable().as_ref();
+            if let Some(to) = after.inner().primary_accountable().as_ref() {
+                if from != Some(to) {
+                    observer.on_ownership_changed(self, after, from, to);
+                }
+            }
+        }
+        if let Some(to_resource) = resource2.as_ref() {
+            if action.resource_effect() == ResourceEffect::DecrementIncrement && self.inner().provider() != self.inner().receiver() {
+                observer.on_ownership_changed(self, to_resource, Some(self.inner().provider()), self.inner().receiver());
             }
         }
 
         // save our changes, if we have them
+        let resource_changed = resource != resource_clone;
+        let resource2_changed = resource2_is_create || resource2 != resource2_clone;
         if process != process_clone { res.modify_process(process.unwrap()); }
         if process2 != process2_clone { res.modify_process(process2.unwrap()); }
-        if resource != resource_clone { res.modify_resource(resource.unwrap()); }
+        if resource_changed { res.modify_resource(resource.clone().unwrap()); }
         if resource2_is_create {
-            res.create_resource(resource2.unwrap());
-        } else if resource2 != resource2_clone {
-            res.modify_resource(resource2.unwrap());
+            res.create_resource(resource2.clone().unwrap());
+        } else if resource2_changed {
+            res.modify_resource(resource2.clone().unwrap());
+        }
+
+        // record our cost snapshots, if asked
+        if state.emit_snapshots {
+            if resource_changed { res.snapshot_resource(resource.as_ref().unwrap())?; }
+            if resource2_changed { res.snapshot_resource(resource2.as_ref().unwrap())?; }
         }
 
         Ok(res.into_modifications())
@@ -693,7 +1232,7 @@ mod tests {
             company::{CompanyID, Permission},
             member::*,
             process::Process,
-            resource::Resource,
+            resource::{LocationChange, Resource},
             user::UserID,
         },
         util,
@@ -701,97 +1240,6 @@ mod tests {
     use om2::{Measure, NumericUnion, Unit};
     use vf_rs::vf;
 
-    fn required_fields(event: &Event, state: &EventProcessState) -> (Vec<&'static str>, Vec<&'static str>) {
-        let mut event_fields = vec![];
-        let mut state_fields = vec![];
-        let action = event.inner().action();
-        let accounting_effect = Some(action.resource_effect()).and_then(|x| if x == ResourceEffect::NoEffect { None } else { Some(x) });
-        let onhand_effect = Some(action.onhand_effect()).and_then(|x| if x == ResourceEffect::NoEffect { None } else { Some(x) });
-        let bundle_effect = accounting_effect.clone().or(onhand_effect.clone());
-        match action {
-            Action::Move => {
-                event_fields.push("move_type");
-                event_fields.push("move_costs");
-                match event.move_type() {
-                    Some(MoveType::Resource) => {
-                        event_fields.push("resource_quantity");
-                        event_fields.push("to_resource_inventoried_as");
-                    }
-                    _ => {}
-                }
-            }
-            Action::Work => {
-                event_fields.push("move_costs");
-                event_fields.push("effort_quantity");
-            }
-            _ => {
-                match (action.input_output(), bundle_effect.clone()) {
-                    (Some(_), _) | (_, Some(ResourceEffect::DecrementIncrement)) => {
-                        event_fields.push("move_costs");
-                    }
-                    _ => {}
-                }
-                if bundle_effect == Some(ResourceEffect::DecrementIncrement) {
-                    event_fields.push("to_resource_inventoried_as");
-                }
-                match bundle_effect {
-                    Some(_) => {
-                        event_fields.push("resource_quantity");
-                    }
-                    _ => {}
-                }
-            }
-        }
-        match action {
-            Action::DeliverService => {
-                state_fields.push("input_of");
-                state_fields.push("output_of");
-            }
-            Action::Move => {
-                match event.move_type() {
-                    Some(MoveType::ProcessCosts) => {
-                        state_fields.push("input_of");
-                        state_fields.push("output_of");
-                    }
-                    Some(MoveType::Resource) => {
-                        state_fields.push("resource");
-                        // to_resource not required because we can create the
-                        // resource via to_resource_inventoried_as
-                    }
-                    _ => {}
-                }
-            }
-            Action::Use => {
-                state_fields.push("resource");
-                state_fields.push("input_of");
-            }
-            Action::Work => {
-                state_fields.push("input_of");
-                state_fields.push("provider");
-            }
-            _ => {
-                match action.input_output() {
-                    Some(InputOutput::Input) => {
-                        state_fields.push("input_of");
-                    }
-                    Some(InputOutput::Output) => {
-                        state_fields.push("output_of");
-                    }
-                    _ => {}
-                }
-                if bundle_effect.is_some() {
-                    state_fields.push("resource");
-                }
-            }
-        }
-        if state.to_resource.is_some() && !event_fields.contains(&"to_resource_inventoried_as") {
-            event_fields.push("to_resource_inventoried_as");
-        }
-        event_fields.sort();
-        state_fields.sort();
-        (event_fields, state_fields)
-    }
-
     fn state_with_fields(state: &EventProcessState, fields: Vec<&'static str>) -> EventProcessState {
         let mut builder = EventProcessState::builder();
         for field in fields {
@@ -877,10 +1325,18 @@ mod tests {
             }
             for fieldset in &all_state_combos {
                 let state = state_with_fields(&state, fieldset.clone());
-                let (must_event_fields, must_state_fields) = required_fields(&event2, &state);
-                let has_event_fields = must_event_fields.iter().fold(true, |acc, x| acc && evfields.contains(x));
-                let has_state_fields = must_state_fields.iter().fold(true, |acc, x| acc && fieldset.contains(x));
+                let required = event2.required_fields(&state);
+                let (must_event_fields, must_state_fields) = (required.event(), required.state());
+                let has_event_fields = must_event_fields.iter().fold(true, |acc, x| acc && evfields.contains(&x.name()));
+                let has_state_fields = must_state_fields.iter().fold(true, |acc, x| acc && fieldset.contains(&x.name()));
                 let should_pass = has_event_fields && has_state_fields;
+                let validated = match event2.validate(&state, now) {
+                    Ok(missing) => missing.is_empty(),
+                    Err(_) => false,
+                };
+                if validated != should_pass {
+                    panic!("event state fuzzer: validate() disagrees with required fields: {:?} {:?}", evfields, fieldset);
+                }
                 match event2.process(state, now) {
                     Ok(_) => {
                         if !should_pass {
@@ -906,6 +1362,7 @@ mod tests {
             .inner(vf::Process::builder().name("Make widgets").build().unwrap())
             .company_id(company_id.clone())
             .costs(Costs::new_with_labor("machinist", num!(100.0)))
+            .labor_spent(Costs::new())
             .active(true)
             .created(now.clone())
             .updated(now.clone())
@@ -915,6 +1372,7 @@ mod tests {
             .inner(vf::Process::builder().name("Check widgets").build().unwrap())
             .company_id(company_to.clone())
             .costs(Costs::default())
+            .labor_spent(Costs::new())
             .active(true)
             .created(now.clone())
             .updated(now.clone())
@@ -1009,6 +1467,7 @@ mod tests {
             for field in &fields_allowed_to_change {
                 match *field {
                     "costs" => { process.set_costs(Costs::new()); }
+                    "labor_spent" => { process.set_labor_spent(Costs::new()); }
                     _ => {}
                 }
             }
@@ -1034,6 +1493,7 @@ mod tests {
                     "onhand_quantity" => { resource.inner_mut().set_onhand_quantity(None); }
                     "primary_accountable" => { resource.inner_mut().set_primary_accountable(None); }
                     "current_location" => { resource.inner_mut().set_current_location(None); }
+                    "location_history" => { resource.set_location_history(vec![]); }
                     // TODO: all other event-editable resource fields
                     _ => {}
                 }
@@ -1079,6 +1539,78 @@ mod tests {
         assert_eq!(res, Err(Error::Event(EventError::DateEndBeforeBegin)));
     }
 
+    #[test]
+    fn validate() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's-widgets-1212");
+        let state_full = make_state(&company_id, &company_id, true, &now);
+        let event = make_event(vf::Action::Consume, &company_id, &company_id, &state_full, &now);
+        let state = state_with_fields(&state_full, vec!["input_of", "resource"]);
+
+        let required = event.required_fields(&state);
+        assert_eq!(required.event(), &vec![EventField::MoveCosts, EventField::ResourceQuantity]);
+        assert_eq!(required.state(), &vec![StateField::InputOf, StateField::Resource]);
+        assert_eq!(event.validate(&state, &now), Ok(RequiredFields::default()));
+
+        let mut event2 = event.clone();
+        event2.set_move_costs(None);
+        let state2 = state_with_fields(&state, vec!["resource"]);
+        let missing = event2.validate(&state2, &now).unwrap();
+        assert_eq!(missing.event(), &vec![EventField::MoveCosts]);
+        assert_eq!(missing.state(), &vec![StateField::InputOf]);
+        assert_eq!(missing.event()[0].name(), "move_costs");
+        assert_eq!(missing.state()[0].name(), "input_of");
+        assert!(!missing.is_empty());
+
+        // errors from deeper in the processor come through
+        let mut state3 = state.clone();
+        state3.resource.as_mut().unwrap().inner_mut().set_primary_accountable(Some(CompanyID::new("larry's chairs").into()));
+        assert_eq!(event.validate(&state3, &now), Err(Error::Event(EventError::ResourceOwnerMismatch)));
+
+        let mut event3 = event.clone();
+        event3.inner_mut().set_resource_inventoried_as(Some(ResourceID::new("gears")));
+        assert_eq!(event3.validate(&state, &now), Err(Error::Event(EventError::MismatchedResourceID)));
+    }
+
+    #[test]
+    fn required_state() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's-widgets-1212");
+        let state_full = make_state(&company_id, &company_id, true, &now);
+
+        let event = make_event(vf::Action::Transfer, &company_id, &company_id, &state_full, &now);
+        let required = event.required_state();
+        assert_eq!(required.event(), &vec![EventField::MoveCosts, EventField::ResourceQuantity, EventField::ToResourceInventoriedAs]);
+        assert_eq!(required.state(), &vec![StateField::Resource]);
+        assert_eq!(required.one_of(), &vec![]);
+        assert_eq!(required.optional(), &vec![StateField::ToResource]);
+        assert!(required.is_satisfied_by(&state_with_fields(&state_full, vec!["resource"])));
+        assert!(!required.is_satisfied_by(&state_with_fields(&state_full, vec!["to_resource"])));
+
+        let event = make_event(vf::Action::Work, &company_id, &company_id, &state_full, &now);
+        let required = event.required_state();
+        assert_eq!(required.event(), &vec![EventField::EffortQuantity, EventField::MoveCosts]);
+        assert_eq!(required.state(), &vec![StateField::InputOf, StateField::Provider]);
+        assert_eq!(required.optional(), &vec![]);
+
+        let mut event = make_event(vf::Action::Move, &company_id, &company_id, &state_full, &now);
+        event.set_move_type(Some(MoveType::WriteOff));
+        let required = event.required_state();
+        assert_eq!(required.event(), &vec![EventField::MoveCosts, EventField::MoveType]);
+        assert_eq!(required.state(), &vec![]);
+        assert_eq!(required.one_of(), &vec![StateField::OutputOf, StateField::Resource]);
+        assert!(required.is_satisfied_by(&state_with_fields(&state_full, vec!["output_of"])));
+        assert!(required.is_satisfied_by(&state_with_fields(&state_full, vec!["resource"])));
+        assert!(!required.is_satisfied_by(&state_with_fields(&state_full, vec!["input_of"])));
+        // resolved against a state, a resource write-off needs a quantity
+        let fields = event.required_fields(&state_with_fields(&state_full, vec!["resource"]));
+        assert_eq!(fields.event(), &vec![EventField::MoveCosts, EventField::MoveType, EventField::ResourceQuantity]);
+        assert_eq!(fields.state(), &vec![StateField::Resource]);
+        let fields = event.required_fields(&state_with_fields(&state_full, vec![]));
+        assert_eq!(fields.event(), &vec![EventField::MoveCosts, EventField::MoveType]);
+        assert_eq!(fields.state(), &vec![StateField::OutputOf]);
+    }
+
     // -------------------------------------------------------------------------
 
     #[test]
@@ -1122,6 +1654,32 @@ mod tests {
         state3.input_of.as_mut().map(|x| x.set_company_id(CompanyID::new("bliv")));
         let res = event.process(state3.clone(), &now);
         assert_eq!(res, Err(Error::Event(EventError::ProcessOwnerMismatch)));
+
+        // events can record quantities in a compatible unit
+        let mut state4 = state.clone();
+        state4.resource.as_mut().unwrap().inner_mut().set_accounting_quantity(Some(Measure::new(10, Unit::Kilogram)));
+        state4.resource.as_mut().unwrap().inner_mut().set_onhand_quantity(Some(Measure::new(10, Unit::Kilogram)));
+        let mut event = make_event(vf::Action::Consume, &company_id, &company_id, &state4, &now);
+        event.inner_mut().set_resource_quantity(Some(Measure::new(6500, Unit::Gram)));
+        let mods = event.process(state4.clone(), &now).unwrap().into_vec();
+        let resource = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(resource.inner().accounting_quantity().clone().unwrap(), Measure::new(num!(3.5), Unit::Kilogram));
+        assert_eq!(resource.inner().onhand_quantity().clone().unwrap(), Measure::new(num!(3.5), Unit::Kilogram));
+        event.inner_mut().set_resource_quantity(Some(Measure::new(6, Unit::Hour)));
+        let res = event.process(state4.clone(), &now);
+        assert_eq!(res, Err(Error::MeasureUnitsMismatched));
+
+        // resources can be allowed to go negative (backordering)
+        let mut event = make_event(vf::Action::Consume, &company_id, &company_id, &state, &now);
+        event.inner_mut().set_resource_quantity(Some(Measure::new(12, Unit::One)));
+        let res = event.process(state.clone(), &now);
+        assert_eq!(res, Err(Error::NegativeMeasurement));
+        let mut state5 = state.clone();
+        state5.resource.as_mut().unwrap().set_allow_negative(true);
+        let mods = event.process(state5.clone(), &now).unwrap().into_vec();
+        let resource = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(resource.inner().accounting_quantity().clone().unwrap(), Measure::new(-2, Unit::One));
+        assert_eq!(resource.inner().onhand_quantity().clone().unwrap(), Measure::new(-1, Unit::One));
     }
 
     #[test]
@@ -1270,6 +1828,77 @@ mod tests {
         assert_eq!(res, Err(Error::Event(EventError::ProcessOwnerMismatch)));
     }
 
+    #[test]
+    fn move_write_off() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's-widgets-1212");
+        let state = make_state(&company_id, &company_id, true, &now);
+        let mut event = make_event(vf::Action::Move, &company_id, &company_id, &state, &now);
+        event.set_move_type(Some(MoveType::WriteOff));
+        fuzz_state(event.clone(), state.clone(), &now);
+
+        // write off from a resource
+        let res = event.process(state.clone(), &now).unwrap();
+        let mods = res.into_vec();
+        assert_eq!(mods.len(), 1);
+
+        let resource = mods[0].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(resource.costs(), &Costs::new_with_labor("machinist", num!(34.91) - num!(30.0)));
+        assert_eq!(resource.inner().accounting_quantity(), &Some(Measure::new(10 - 6, Unit::One)));
+        assert_eq!(resource.inner().onhand_quantity(), &Some(Measure::new(11 - 6, Unit::One)));
+        check_resource_mods(vec!["costs", "accounting_quantity", "onhand_quantity"], &resource, state.resource.as_ref().unwrap());
+
+        // write off from a process
+        let mut state2 = state.clone();
+        state2.resource = None;
+        state2.to_resource = None;
+        let mut event2 = event.clone();
+        event2.inner_mut().set_to_resource_inventoried_as(None);
+        let res = event2.process(state2.clone(), &now).unwrap();
+        let mods = res.into_vec();
+        assert_eq!(mods.len(), 1);
+
+        let process = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process.costs(), &Costs::new_with_labor("machinist", 70));
+        check_process_mods(vec!["costs"], &process, state.output_of.as_ref().unwrap());
+
+        let mut state3 = state2.clone();
+        state3.output_of.as_mut().map(|x| x.set_company_id(CompanyID::new("bliv")));
+        let res = event2.process(state3.clone(), &now);
+        assert_eq!(res, Err(Error::Event(EventError::ProcessOwnerMismatch)));
+
+        let mut event3 = event2.clone();
+        event3.set_move_costs(Some(Costs::new_with_labor("machinist", 101)));
+        let res = event3.process(state2.clone(), &now);
+        assert_eq!(res, Err(Error::NegativeCosts));
+    }
+
+    #[test]
+    fn move_external_sale() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's-widgets-1212");
+        let state = make_state(&company_id, &company_id, true, &now);
+        let mut event = make_event(vf::Action::Move, &company_id, &company_id, &state, &now);
+        event.set_move_type(Some(MoveType::ExternalSale("usd".into(), num!(50))));
+        fuzz_state(event.clone(), state.clone(), &now);
+
+        let res = event.process(state.clone(), &now).unwrap();
+        let mods = res.into_vec();
+        assert_eq!(mods.len(), 1);
+
+        let resource = mods[0].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(resource.costs(), &Costs::new_with_labor("machinist", num!(34.91) - num!(30.0)));
+        assert_eq!(resource.inner().accounting_quantity(), &Some(Measure::new(10 - 6, Unit::One)));
+        assert_eq!(resource.inner().onhand_quantity(), &Some(Measure::new(11 - 6, Unit::One)));
+        check_resource_mods(vec!["costs", "accounting_quantity", "onhand_quantity"], &resource, state.resource.as_ref().unwrap());
+
+        // can only sell resources, not process costs
+        let mut state2 = state.clone();
+        state2.resource = None;
+        let res = event.process(state2, &now);
+        assert_eq!(res, Err(Error::Event(EventError::MissingResource)));
+    }
+
     #[test]
     fn move_resource() {
         let now = util::time::now();
@@ -1309,7 +1938,12 @@ mod tests {
         assert_eq!(resource2.inner().primary_accountable().clone().unwrap(), company_id.clone().into());
         assert_eq!(resource2.inner().current_location().as_ref().unwrap().lat(), &Some(71.665519));
         assert_eq!(resource2.in_custody_of(), &company_id.clone().into());
-        check_resource_mods(vec!["costs", "in_custody_of", "primary_accountable", "accounting_quantity", "onhand_quantity", "current_location"], &resource2, state.to_resource.as_ref().unwrap());
+        check_resource_mods(vec!["costs", "in_custody_of", "primary_accountable", "accounting_quantity", "onhand_quantity", "current_location", "location_history"], &resource2, state.to_resource.as_ref().unwrap());
+        assert_eq!(resource2.location_history().len(), 1);
+        assert_eq!(resource2.location_history()[0].location(), resource2.inner().current_location().as_ref().unwrap());
+        assert_eq!(resource2.location_history()[0].event_id(), event.id());
+        assert_eq!(resource2.location_history()[0].changed(), &now);
+        assert_eq!(resource.location_history().len(), 0);
 
         // going to move just costs (set count to 0 lol)
         let mut event2 = event.clone();
@@ -1337,7 +1971,7 @@ mod tests {
         assert_eq!(resource4.inner().primary_accountable().clone().unwrap(), company_id.clone().into());
         assert_eq!(resource4.inner().current_location().as_ref().unwrap().lat(), &Some(71.665519));
         assert_eq!(resource4.in_custody_of(), &company_id.clone().into());
-        check_resource_mods(vec!["costs", "in_custody_of", "primary_accountable", "accounting_quantity", "onhand_quantity", "current_location"], &resource4, state.to_resource.as_ref().unwrap());
+        check_resource_mods(vec!["costs", "in_custody_of", "primary_accountable", "accounting_quantity", "onhand_quantity", "current_location", "location_history"], &resource4, state.to_resource.as_ref().unwrap());
 
         let mut state2 = state.clone();
         state2.resource.as_mut().map(|x| x.inner_mut().set_primary_accountable(Some(CompanyID::new("bliv").into())));
@@ -1359,6 +1993,7 @@ mod tests {
         resource2_clone.set_costs(Costs::new_with_labor("machinist", num!(30.0)));
         resource2_clone.set_created(now4.clone());
         resource2_clone.set_updated(now4.clone());
+        resource2_clone.set_location_history(vec![LocationChange::new(resource2.inner().current_location().clone().unwrap(), event.id().clone(), now4.clone())]);
         assert_eq!(resource5.id(), event.inner().to_resource_inventoried_as().as_ref().unwrap());
         assert_eq!(resource5, resource2_clone);
     }
@@ -1423,6 +2058,22 @@ mod tests {
         event.inner_mut().set_resource_quantity(Some(Measure::new(NumericUnion::Decimal(num!(-15)), Unit::One)));
         let res = event.process(state.clone(), &now);
         assert_eq!(res, Err(Error::NegativeMeasurement));
+
+        let mut event = make_event(vf::Action::Raise, &company_id, &company_id, &state, &now);
+        event.set_renewal(Some(Renewal::Recycling));
+        let mods = event.process(state.clone(), &now).unwrap().into_vec();
+        let resource2 = mods[0].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(resource2, resource);
+
+        let mut event = make_event(vf::Action::Lower, &company_id, &company_id, &state, &now);
+        event.set_renewal(Some(Renewal::Regeneration));
+        let res = event.process(state.clone(), &now);
+        assert_eq!(res, Err(Error::Event(EventError::RenewalMustRaise)));
+
+        let mut event = make_event(vf::Action::Raise, &company_id, &company_id, &state, &now);
+        event.set_byproduct(Some(Byproduct::Waste));
+        let res = event.process(state.clone(), &now);
+        assert_eq!(res, Err(Error::Event(EventError::ByproductMustProduce)));
     }
 
     #[test]
@@ -1474,6 +2125,93 @@ mod tests {
         assert_eq!(resource5, resource2_clone);
     }
 
+    #[test]
+    fn observer() {
+        #[derive(Default)]
+        struct Recorder {
+            costs: Vec<Costs>,
+            created: Vec<ResourceID>,
+            ownership: Vec<(ResourceID, Option<AgentID>, AgentID)>,
+        }
+        impl EventObserver for Recorder {
+            fn on_costs_moved(&mut self, _event: &Event, costs: &Costs) {
+                self.costs.push(costs.clone());
+            }
+            fn on_resource_created(&mut self, _event: &Event, resource: &Resource) {
+                self.created.push(resource.id().clone());
+            }
+            fn on_ownership_changed(&mut self, _event: &Event, resource: &Resource, from: Option<&AgentID>, to: &AgentID) {
+                self.ownership.push((resource.id().clone(), from.cloned(), to.clone()));
+            }
+        }
+
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's-widgets-1212");
+        let company2_id = CompanyID::new("alejandro's-fine-chairs");
+        let state = make_state(&company_id, &company2_id, true, &now);
+
+        let mut event = make_event(vf::Action::Transfer, &company_id, &company2_id, &state, &now);
+        event.set_move_type(Some(MoveType::Resource));
+
+        let mut recorder = Recorder::default();
+        let mods = event.process_observed(state.clone(), &now, &mut recorder).unwrap();
+        assert_eq!(mods, event.process(state.clone(), &now).unwrap());
+        assert_eq!(recorder.costs, vec![Costs::new_with_labor("machinist", num!(30.0))]);
+        assert_eq!(recorder.created.len(), 0);
+        assert_eq!(recorder.ownership, vec![(ResourceID::new("4445"), Some(company_id.clone().into()), company2_id.clone().into())]);
+
+        let mut state2 = state.clone();
+        state2.to_resource = None;
+        let mut recorder = Recorder::default();
+        event.process_observed(state2.clone(), &now, &mut recorder).unwrap();
+        assert_eq!(recorder.created, vec![ResourceID::new("4445")]);
+        assert_eq!(recorder.ownership.len(), 1);
+
+        let mut state3 = state.clone();
+        state3.resource.as_mut().map(|x| x.set_in_custody_of(CompanyID::new("bliv").into()));
+        let mut recorder = Recorder::default();
+        let res = event.process_observed(state3.clone(), &now, &mut recorder);
+        assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
+        assert_eq!(recorder.costs.len(), 0);
+        assert_eq!(recorder.ownership.len(), 0);
+
+        let event = make_event(vf::Action::Produce, &company_id, &company_id, &state, &now);
+        let mut recorder = Recorder::default();
+        event.process_observed(state.clone(), &now, &mut recorder).unwrap();
+        assert_eq!(recorder.costs.len(), 1);
+        assert_eq!(recorder.ownership.len(), 0);
+    }
+
+    #[test]
+    fn snapshots() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's-widgets-1212");
+        let company2_id = CompanyID::new("alejandro's-fine-chairs");
+        let state = make_state(&company_id, &company2_id, true, &now);
+        let mut event = make_event(vf::Action::Transfer, &company_id, &company2_id, &state, &now);
+        event.set_move_type(Some(MoveType::Resource));
+
+        // no snapshots unless asked
+        let mods = event.process(state.clone(), &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+
+        let mut state2 = state.clone();
+        state2.emit_snapshots = true;
+        let mods = event.process(state2, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 4);
+        let resource = mods[0].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let snapshot = mods[2].clone().expect_op::<CostSnapshot>(Op::Create).unwrap();
+        let snapshot2 = mods[3].clone().expect_op::<CostSnapshot>(Op::Create).unwrap();
+        assert_eq!(snapshot.resource_id(), resource.id());
+        assert_eq!(snapshot.event_id(), event.id());
+        assert_eq!(snapshot.costs(), resource.costs());
+        assert_eq!(snapshot.quantity(), resource.inner().accounting_quantity());
+        assert_eq!(snapshot.created(), &now);
+        assert_eq!(snapshot2.resource_id(), resource2.id());
+        assert_eq!(snapshot2.costs(), resource2.costs());
+    }
+
     #[test]
     fn transfer_all_rights() {
         let now = util::time::now();
@@ -1639,7 +2377,8 @@ mod tests {
 
         let process = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
         assert_eq!(process.costs(), &Costs::new_with_labor("CEO", 69));
-        check_process_mods(vec!["costs"], &process, state.input_of.as_ref().unwrap());
+        assert_eq!(process.labor_spent(), &Costs::new_with_labor("CEO", 69));
+        check_process_mods(vec!["costs", "labor_spent"], &process, state.input_of.as_ref().unwrap());
 
         let mut state2 = state.clone();
         state2.input_of.as_mut().unwrap().set_deleted(Some(now.clone()));
@@ -1662,6 +2401,19 @@ mod tests {
         state4.provider.as_mut().unwrap().set_class(MemberClass::Company(MemberCompany::new()));
         let res = event.process(state4.clone(), &now);
         assert_eq!(res, Err(Error::MemberMustBeWorker));
+
+        let mut state5 = state.clone();
+        state5.input_of.as_mut().unwrap().set_labor_budget(Some(Costs::new_with_labor("CEO", 69)));
+        let res = event.process(state5.clone(), &now);
+        assert!(res.is_ok());
+        state5.input_of.as_mut().unwrap().set_labor_budget(Some(Costs::new_with_labor("CEO", 68)));
+        let res = event.process(state5.clone(), &now);
+        assert_eq!(res, Err(Error::Event(EventError::LaborBudgetExceeded)));
+        // labor that came in with the process' inputs doesn't use up the budget
+        state5.input_of.as_mut().unwrap().set_labor_budget(Some(Costs::new_with_labor("CEO", 69)));
+        state5.input_of.as_mut().unwrap().set_costs(Costs::new_with_labor("CEO", 500));
+        let res = event.process(state5.clone(), &now);
+        assert!(res.is_ok());
     }
 
     #[test]
@@ -1682,7 +2434,7 @@ mod tests {
 
         let process = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
         assert_eq!(process.costs(), &Costs::new_with_labor_hours("CEO", num!(5.4)));
-        check_process_mods(vec!["costs"], &process, state.input_of.as_ref().unwrap());
+        check_process_mods(vec!["costs", "labor_spent"], &process, state.input_of.as_ref().unwrap());
 
         let mut state2 = state.clone();
         state2.input_of.as_mut().unwrap().set_deleted(Some(now.clone()));
@@ -1731,7 +2483,7 @@ mod tests {
         costs.track_labor("CEO", 69);
         costs.track_labor_hours("CEO", 12);
         assert_eq!(process.costs(), &costs);
-        check_process_mods(vec!["costs"], &process, state.input_of.as_ref().unwrap());
+        check_process_mods(vec!["costs", "labor_spent"], &process, state.input_of.as_ref().unwrap());
 
         let mut state2 = state.clone();
         state2.input_of.as_mut().unwrap().set_deleted(Some(now.clone()));
@@ -0,0 +1,169 @@
+//! Provenance lets us trace how events relate to each other.
+//!
+//! When processing an event creates *other* events, those derived events have
+//! their `triggered_by` field set to the event that created them. Given a set
+//! of events, we can use this to assemble the full tree of events that stem
+//! from some root event, which lets auditors follow where the costs in a
+//! resource or process came from.
+
+use crate::{
+    models::event::{Event, EventID},
+};
+use getset::Getters;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+
+/// A tree of events, rooted at one event, where each node's children are the
+/// events it triggered. An empty tree (the default) has no event and no
+/// children.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ProvenanceTree {
+    /// The event at this node of the tree, or `None` if the tree is empty
+    event: Option<Event>,
+    /// The events triggered by this node's event
+    children: Vec<ProvenanceTree>,
+}
+
+impl ProvenanceTree {
+    /// Whether or not this tree has any events in it.
+    pub fn is_empty(&self) -> bool {
+        self.event().is_none()
+    }
+
+    /// The total number of events in this tree (including the root).
+    pub fn len(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        1 + self.children().iter().map(|child| child.len()).sum::<usize>()
+    }
+
+    /// How many levels deep this tree goes (a tree with no children has a
+    /// depth of one, an empty tree a depth of zero).
+    pub fn depth(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        1 + self.children().iter().map(|child| child.depth()).max().unwrap_or(0)
+    }
+
+    /// Flatten this tree into a list of events, depth-first, starting with the
+    /// root.
+    pub fn events(&self) -> Vec<&Event> {
+        let mut events = self.event().iter().collect::<Vec<_>>();
+        for child in self.children() {
+            events.append(&mut child.events());
+        }
+        events
+    }
+}
+
+/// Recursively build a tree node, skipping any event we've already seen so a
+/// malformed `triggered_by` loop can't recurse forever.
+fn build_node<'a>(event: &'a Event, children_of: &HashMap<&'a EventID, Vec<&'a Event>>, seen: &mut HashSet<&'a EventID>) -> ProvenanceTree {
+    seen.insert(event.id());
+    let mut children = vec![];
+    for child in children_of.get(event.id()).map(|x| x.as_slice()).unwrap_or(&[]) {
+        if seen.contains(child.id()) {
+            continue;
+        }
+        children.push(build_node(child, children_of, seen));
+    }
+    ProvenanceTree {
+        event: Some(event.clone()),
+        children,
+    }
+}
+
+/// Build the tree of events derived from `root` out of the given set of events.
+/// Returns an empty tree if the root event isn't in the set.
+///
+/// Children are ordered the same as they appear in `events`.
+pub fn build_chain(events: &[Event], root: &EventID) -> ProvenanceTree {
+    let root_event = match events.iter().find(|event| event.id() == root) {
+        Some(event) => event,
+        None => return ProvenanceTree::default(),
+    };
+    let mut children_of: HashMap<&EventID, Vec<&Event>> = HashMap::new();
+    for event in events {
+        if let Some(parent) = event.inner().triggered_by().as_ref() {
+            children_of.entry(parent).or_default().push(event);
+        }
+    }
+    let mut seen = HashSet::new();
+    build_node(root_event, &children_of, &mut seen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::company::CompanyID,
+        util,
+    };
+    use vf_rs::vf;
+
+    fn make_event(id: &str, triggered_by: Option<&str>) -> Event {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        Event::builder()
+            .id(id)
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(vf::Action::Transfer)
+                    .provider(company_id.clone())
+                    .receiver(company_id.clone())
+                    .triggered_by(triggered_by.map(|x| x.into()))
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn builds_chains() {
+        let events = vec![
+            make_event("root", None),
+            make_event("a", Some("root")),
+            make_event("b", Some("root")),
+            make_event("a1", Some("a")),
+            make_event("a1x", Some("a1")),
+            make_event("unrelated", None),
+            make_event("unrelated2", Some("unrelated")),
+        ];
+        let tree = build_chain(&events, &"root".into());
+        assert!(!tree.is_empty());
+        assert_eq!(tree.event().as_ref().unwrap().id(), &EventID::new("root"));
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.depth(), 4);
+        assert_eq!(tree.children().len(), 2);
+        assert_eq!(tree.children()[0].event().as_ref().unwrap().id(), &EventID::new("a"));
+        assert_eq!(tree.children()[1].event().as_ref().unwrap().id(), &EventID::new("b"));
+        let ids = tree.events().into_iter().map(|x| x.id().as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["root", "a", "a1", "a1x", "b"]);
+
+        let subtree = build_chain(&events, &"a1".into());
+        assert_eq!(subtree.len(), 2);
+        assert_eq!(subtree.depth(), 2);
+
+        let empty = build_chain(&events, &"nope".into());
+        assert_eq!(empty, ProvenanceTree::default());
+        assert!(empty.is_empty());
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.depth(), 0);
+        assert_eq!(empty.events().len(), 0);
+
+        // a triggered_by loop doesn't recurse forever
+        let looped = vec![
+            make_event("x", Some("y")),
+            make_event("y", Some("x")),
+        ];
+        let tree = build_chain(&looped, &"x".into());
+        assert_eq!(tree.len(), 2);
+    }
+}
@@ -0,0 +1,50 @@
+//! A flag is a notification-style record that something needs a person's
+//! attention. Flags don't do anything on their own, but give implementations a
+//! consistent place to surface problems (like a commitment that lapsed without
+//! being fulfilled) to the companies involved.
+
+use chrono::{DateTime, Utc};
+use crate::{
+    models::{
+        commitment::CommitmentID,
+        company::CompanyID,
+        inspection::InspectionID,
+        lib::basis_model::Model,
+        resource::ResourceID,
+    },
+};
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// Why a flag was raised.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum FlagReason {
+    /// A commitment passed its due date without being fulfilled
+    CommitmentLapsed(CommitmentID),
+    /// A resource was quarantined after failing an inspection
+    Quarantined(ResourceID, InspectionID),
+}
+
+basis_model! {
+    /// The `Flag` model marks something a company should pay attention to.
+    pub struct Flag {
+        id: <<FlagID>>,
+        /// The company this flag is raised for
+        company_id: CompanyID,
+        /// Why this flag was raised
+        reason: FlagReason,
+        /// An optional note describing the problem
+        note: Option<String>,
+        /// When (if ever) this flag was resolved
+        resolved: Option<DateTime<Utc>>,
+    }
+    FlagBuilder
+}
+
+impl Flag {
+    /// Determine if this flag still needs attention.
+    pub fn is_open(&self) -> bool {
+        self.is_active() && self.resolved().is_none()
+    }
+}
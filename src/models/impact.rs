@@ -0,0 +1,26 @@
+//! An impact is a kind of ecological or social externality that's tracked as
+//! part of the costs of production, like CO2-equivalent emissions, water use,
+//! or land use.
+//!
+//! Impacts are tracked in the `impact` bucket of [Costs][1] and move through
+//! the economic graph just like resources do. Unlike resources, impacts carry
+//! no credit value: they don't change the price of anything, but they let us
+//! see the full footprint of a product.
+//!
+//! Like regions and occupations, impacts require global systemic management.
+//!
+//! [1]: ../../costs/struct.Costs.html
+
+use om2::Unit;
+
+basis_model! {
+    /// The `Impact` model names a kind of externality we want to track.
+    pub struct Impact {
+        id: <<ImpactID>>,
+        /// The impact's name (ie, "CO2e")
+        name: String,
+        /// The unit impacts of this kind are measured in (ie, kilograms)
+        unit: Unit,
+    }
+    ImpactBuilder
+}
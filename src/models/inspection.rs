@@ -0,0 +1,48 @@
+//! An inspection records a quality-control check of a resource: who inspected
+//! it, how much of it was inspected, and whether it passed or failed.
+//!
+//! Inspections don't move costs or change resource quantities. When an
+//! inspection fails, the failed quantity can be [quarantined][1].
+//!
+//! [1]: ../../transactions/event/qc/index.html
+
+use crate::{
+    models::{
+        company::CompanyID,
+        member::MemberID,
+        resource::ResourceID,
+    },
+};
+use om2::Measure;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// The outcome of an inspection.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum InspectionResult {
+    /// The resource passed inspection
+    Pass,
+    /// The resource failed inspection
+    Fail,
+}
+
+basis_model! {
+    /// The `Inspection` model records a quality-control check of a resource.
+    pub struct Inspection {
+        id: <<InspectionID>>,
+        /// The company holding the inspected resource
+        company_id: CompanyID,
+        /// The resource being inspected
+        resource_id: ResourceID,
+        /// The member who performed the inspection
+        inspector: MemberID,
+        /// How much of the resource was inspected
+        quantity: Option<Measure>,
+        /// Whether the resource passed or failed
+        result: InspectionResult,
+        /// Any notes the inspector left
+        note: Option<String>,
+    }
+    InspectionBuilder
+}
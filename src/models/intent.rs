@@ -5,21 +5,71 @@
 //! - A [Commitment] represents "we agree that something will happen"
 //! - An [Event] represents "something did happen"
 //!
+//! Intents offering a service can also carry a recurring window of
+//! [availability][Availability] ("10 hours of plumbing per week"). The intent's
+//! `available_quantity` is then what's left in the current window: it goes
+//! down as commitments are made against the intent, and is reset each time a
+//! new window starts.
+//!
 //! [Commitment]: ../commitment/struct.Commitment.html
 //! [Event]: ../event/struct.Event.html
+//! [Availability]: struct.Availability.html
 
+use chrono::{DateTime, Duration, Utc};
 use crate::{
     costs::Costs,
+    error::{Error, Result},
     models::{
-        lib::agent::AgentID,
+        lib::{
+            agent::AgentID,
+            basis_model::Model,
+        },
         process::ProcessID,
         resource::ResourceID,
         resource_spec::ResourceSpecID,
-    }
+    },
+    util::measure,
 };
+use getset::{Getters, Setters};
+use om2::Measure;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use url::Url;
 use vf_rs::vf;
 
+/// A recurring window in which some quantity of an intent (generally hours of
+/// a service) is on offer.
+#[derive(Clone, Debug, PartialEq, Getters, Setters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub", set = "pub(crate)")]
+pub struct Availability {
+    /// How much is on offer each period (for instance, 10 hours)
+    per_period: Measure,
+    /// How long each period lasts, in units of time up to a week (for
+    /// instance, 1 week)
+    period: Measure,
+    /// When the current period started
+    period_start: DateTime<Utc>,
+}
+
+impl Availability {
+    /// Create a new availability window, with the first period starting at
+    /// `starts`.
+    pub fn new(per_period: Measure, period: Measure, starts: DateTime<Utc>) -> Self {
+        Self {
+            per_period,
+            period,
+            period_start: starts,
+        }
+    }
+
+    /// Get the length of our period in seconds. Periods must be a positive
+    /// amount of time.
+    pub fn period_seconds(&self) -> Result<i64> {
+        measure::to_seconds(self.period())
+    }
+}
+
 basis_model! {
     /// The `Intent` model is a wrapper around the [ValueFlows intent][vfintent]
     /// object. It is effectively what an [Event] looks like *before the event
@@ -34,7 +84,169 @@ basis_model! {
         /// If this event is an input/output of a process or resource, move some
         /// fixed amount of costs between the two objects.
         move_costs: Option<Costs>,
+        /// If set, the time after which this intent is stale and can no longer
+        /// be committed to.
+        expires_at: Option<DateTime<Utc>>,
+        /// If set, the `available_quantity` of this intent is replenished
+        /// every period. See `Intent::refresh_availability()`.
+        availability: Option<Availability>,
     }
     IntentBuilder
 }
 
+impl Intent {
+    /// Determine if this intent has expired. An intent with no `expires_at`
+    /// never expires.
+    pub fn is_expired(&self, now: &DateTime<Utc>) -> bool {
+        match self.expires_at() {
+            Some(expires_at) => expires_at <= now,
+            None => false,
+        }
+    }
+
+    /// Determine if this intent can be committed to: it must be active (and
+    /// not deleted) and not expired.
+    pub fn is_available(&self, now: &DateTime<Utc>) -> bool {
+        self.is_active() && !self.is_expired(now)
+    }
+
+    /// If this intent has a recurring availability and the current period has
+    /// passed, move on to the period `now` falls in and reset the available
+    /// quantity. Returns whether anything changed.
+    pub fn refresh_availability(&mut self, now: &DateTime<Utc>) -> Result<bool> {
+        let mut availability = match self.availability() {
+            Some(availability) => availability.clone(),
+            None => return Ok(false),
+        };
+        let period_seconds = availability.period_seconds()?;
+        let elapsed = now.timestamp() - availability.period_start().timestamp();
+        if elapsed < period_seconds {
+            return Ok(false);
+        }
+        let periods = elapsed / period_seconds;
+        availability.set_period_start(availability.period_start().clone() + Duration::seconds(periods * period_seconds));
+        self.inner_mut().set_available_quantity(Some(availability.per_period().clone()));
+        self.set_availability(Some(availability));
+        Ok(true)
+    }
+
+    /// Determine if this intent will never have anything more to offer once
+    /// its available quantity is used up: it either doesn't recur, or its next
+    /// period starts after it expires.
+    fn is_final_period(&self) -> Result<bool> {
+        let availability = match self.availability() {
+            Some(availability) => availability,
+            None => return Ok(true),
+        };
+        let next_period = availability.period_start().clone() + Duration::seconds(availability.period_seconds()?);
+        Ok(self.is_expired(&next_period))
+    }
+
+    /// Take some quantity out of this intent's availability, generally because
+    /// a commitment is being made against it. Intents without an
+    /// `available_quantity` are unlimited. If this uses up the last of the
+    /// intent's availability, the intent is closed (marked finished and
+    /// deactivated).
+    pub(crate) fn take(&mut self, quantity: &Measure, now: &DateTime<Utc>) -> Result<()> {
+        self.refresh_availability(now)?;
+        if !self.is_available(now) {
+            Err(Error::IntentExpired)?;
+        }
+        let mut available = match self.inner().available_quantity() {
+            Some(available) => available.clone(),
+            None => return Ok(()),
+        };
+        measure::dec_measure(&mut available, quantity)
+            .map_err(|e| match e {
+                Error::NegativeMeasurement => Error::IntentExhausted,
+                _ => e,
+            })?;
+        let exhausted = available.has_numerical_value().is_zero();
+        self.inner_mut().set_available_quantity(Some(available));
+        if exhausted && self.is_final_period()? {
+            self.inner_mut().set_finished(Some(true));
+            self.set_active(false);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use om2::Unit;
+
+    fn make_service_intent(availability: Option<Availability>, expires_at: Option<DateTime<Utc>>, now: &DateTime<Utc>) -> Intent {
+        Intent::builder()
+            .id(IntentID::create())
+            .inner(
+                vf::Intent::builder()
+                    .action(vf::Action::DeliverService)
+                    .available_quantity(Some(Measure::new(num!(10), Unit::Hour)))
+                    .build().unwrap()
+            )
+            .expires_at(expires_at)
+            .availability(availability)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn takes_availability() {
+        let now: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let mut intent = make_service_intent(None, None, &now);
+        intent.take(&Measure::new(num!(4), Unit::Hour), &now).unwrap();
+        assert_eq!(intent.inner().available_quantity(), &Some(Measure::new(num!(6), Unit::Hour)));
+        assert_eq!(intent.take(&Measure::new(num!(7), Unit::Hour), &now), Err(Error::IntentExhausted));
+        assert_eq!(intent.take(&Measure::new(num!(1), Unit::Kilogram), &now), Err(Error::MeasureUnitsMismatched));
+        // minutes work just as well as hours
+        intent.take(&Measure::new(num!(60), Unit::MinuteTime), &now).unwrap();
+        assert_eq!(intent.inner().available_quantity(), &Some(Measure::new(num!(5), Unit::Hour)));
+        intent.take(&Measure::new(num!(5), Unit::Hour), &now).unwrap();
+        assert_eq!(intent.inner().finished(), &Some(true));
+        assert_eq!(intent.active(), &false);
+        assert_eq!(intent.take(&Measure::new(num!(1), Unit::Hour), &now), Err(Error::IntentExpired));
+
+        // unlimited intents stay open
+        let mut intent2 = make_service_intent(None, None, &now);
+        intent2.inner_mut().set_available_quantity(None);
+        intent2.take(&Measure::new(num!(400), Unit::Hour), &now).unwrap();
+        assert_eq!(intent2.is_active(), true);
+    }
+
+    #[test]
+    fn recurring_availability() {
+        let now: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let availability = Availability::new(Measure::new(num!(10), Unit::Hour), Measure::new(1, Unit::Week), now.clone());
+        assert_eq!(availability.period_seconds(), Ok(604800));
+        let mut intent = make_service_intent(Some(availability), None, &now);
+
+        // using up a week's hours doesn't close a recurring intent...
+        intent.take(&Measure::new(num!(10), Unit::Hour), &now).unwrap();
+        assert_eq!(intent.inner().available_quantity(), &Some(Measure::new(num!(0), Unit::Hour)));
+        assert_eq!(intent.is_active(), true);
+        let midweek: DateTime<Utc> = "2020-06-04T00:00:00Z".parse().unwrap();
+        assert_eq!(intent.take(&Measure::new(num!(1), Unit::Hour), &midweek), Err(Error::IntentExhausted));
+
+        // ...and they're replenished the next week (or however many weeks later)
+        let later: DateTime<Utc> = "2020-06-17T12:00:00Z".parse().unwrap();
+        intent.take(&Measure::new(num!(3), Unit::Hour), &later).unwrap();
+        assert_eq!(intent.inner().available_quantity(), &Some(Measure::new(num!(7), Unit::Hour)));
+        assert_eq!(intent.availability().as_ref().unwrap().period_start(), &"2020-06-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(intent.refresh_availability(&later), Ok(false));
+
+        // once there are no more periods left before the intent expires, using
+        // up the hours closes it
+        intent.set_expires_at(Some("2020-06-20T00:00:00Z".parse().unwrap()));
+        intent.take(&Measure::new(num!(7), Unit::Hour), &later).unwrap();
+        assert_eq!(intent.inner().finished(), &Some(true));
+        assert_eq!(intent.is_active(), false);
+
+        let availability2 = Availability::new(Measure::new(num!(10), Unit::Hour), Measure::new(1, Unit::Month), now.clone());
+        assert_eq!(availability2.period_seconds(), Err(Error::MeasureUnitsMismatched));
+        let availability3 = Availability::new(Measure::new(num!(10), Unit::Hour), Measure::new(0, Unit::Day), now.clone());
+        assert_eq!(availability3.period_seconds(), Err(Error::InvalidAmount(num!(0))));
+    }
+}
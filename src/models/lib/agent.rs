@@ -1,10 +1,16 @@
+//! Agents are the actors in the system: companies, members, and users. This
+//! module holds the `AgentID` type that links them together along with some
+//! helpers for working with agents generically (for instance, when pulling
+//! agents out of a modification stream via `resolve()`).
+
 use crate::{
     error::{Result, Error},
     models::{
-        company::CompanyID,
-        member::MemberID,
+        self,
+        company::{Company, CompanyID},
+        member::{Member, MemberID},
         lib::basis_model::Model,
-        user::UserID,
+        user::{User, UserID},
     },
 };
 #[cfg(feature = "with_serde")]
@@ -33,6 +39,109 @@ pub enum AgentID {
     UserID(UserID),
 }
 
+/// The type of agent an `AgentID` points to.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum AgentKind {
+    Company,
+    Member,
+    User,
+}
+
+impl AgentID {
+    /// Returns what kind of agent this ID points to.
+    pub fn kind(&self) -> AgentKind {
+        match self {
+            AgentID::CompanyID(_) => AgentKind::Company,
+            AgentID::MemberID(_) => AgentKind::Member,
+            AgentID::UserID(_) => AgentKind::User,
+        }
+    }
+
+    /// Returns the inner ID as a string, regardless of agent type.
+    pub fn as_str(&self) -> &str {
+        match self {
+            AgentID::CompanyID(id) => id.as_str(),
+            AgentID::MemberID(id) => id.as_str(),
+            AgentID::UserID(id) => id.as_str(),
+        }
+    }
+
+    /// Convert this AgentID into a CompanyID, erroring if it points to some
+    /// other kind of agent.
+    pub fn try_into_company_id(self) -> Result<CompanyID> {
+        CompanyID::try_from(self)
+    }
+
+    /// Convert this AgentID into a MemberID, erroring if it points to some
+    /// other kind of agent.
+    pub fn try_into_member_id(self) -> Result<MemberID> {
+        MemberID::try_from(self)
+    }
+
+    /// Convert this AgentID into a UserID, erroring if it points to some other
+    /// kind of agent.
+    pub fn try_into_user_id(self) -> Result<UserID> {
+        UserID::try_from(self)
+    }
+}
+
+/// Wraps a reference to any of our agent models so they can be handled
+/// uniformly.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AgentRecord<'a> {
+    Company(&'a Company),
+    Member(&'a Member),
+    User(&'a User),
+}
+
+impl<'a> AgentRecord<'a> {
+    /// Returns the wrapped agent's ID.
+    pub fn id(&self) -> AgentID {
+        match self {
+            AgentRecord::Company(model) => model.agent_id(),
+            AgentRecord::Member(model) => model.agent_id(),
+            AgentRecord::User(model) => model.agent_id(),
+        }
+    }
+
+    /// Returns what kind of agent this is.
+    pub fn kind(&self) -> AgentKind {
+        match self {
+            AgentRecord::Company(_) => AgentKind::Company,
+            AgentRecord::Member(_) => AgentKind::Member,
+            AgentRecord::User(_) => AgentKind::User,
+        }
+    }
+
+    /// Whether or not the wrapped agent is active (and not deleted).
+    pub fn is_active(&self) -> bool {
+        match self {
+            AgentRecord::Company(model) => model.is_active(),
+            AgentRecord::Member(model) => model.is_active(),
+            AgentRecord::User(model) => model.is_active(),
+        }
+    }
+
+    /// Wrap a model, if it's an agent.
+    pub fn from_model(model: &'a models::Model) -> Option<Self> {
+        match model {
+            models::Model::Company(company) => Some(AgentRecord::Company(company)),
+            models::Model::Member(member) => Some(AgentRecord::Member(member)),
+            models::Model::User(user) => Some(AgentRecord::User(user)),
+            _ => None,
+        }
+    }
+}
+
+/// Find the agent with the given ID within a set of models (for instance, the
+/// models pulled out of a set of modifications).
+pub fn resolve<'a>(agents: &'a [models::Model], id: &AgentID) -> Option<AgentRecord<'a>> {
+    agents.iter()
+        .filter_map(|model| AgentRecord::from_model(model))
+        .find(|agent| &agent.id() == id)
+}
+
 /// Implements `From<ModelID> for AgentID` and also `TryFrom<AgentID> for ModelID`
 macro_rules! impl_agent_for_model_id {
     ($idty:ident) => {
@@ -59,3 +168,55 @@ impl_agent_for_model_id! { CompanyID }
 impl_agent_for_model_id! { MemberID }
 impl_agent_for_model_id! { UserID }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::occupation::OccupationID,
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn agent_ids() {
+        let company_id = CompanyID::new("jerry's widgets");
+        let agent_id: AgentID = company_id.clone().into();
+        assert_eq!(agent_id.kind(), AgentKind::Company);
+        assert_eq!(agent_id.as_str(), "jerry's widgets");
+        assert_eq!(agent_id.clone().try_into_company_id(), Ok(company_id));
+        assert_eq!(agent_id.clone().try_into_member_id(), Err(Error::WrongAgentIDType));
+        assert_eq!(agent_id.try_into_user_id(), Err(Error::WrongAgentIDType));
+
+        let agent_id: AgentID = UserID::new("jerry").into();
+        assert_eq!(agent_id.kind(), AgentKind::User);
+        assert_eq!(agent_id.try_into_user_id(), Ok(UserID::new("jerry")));
+    }
+
+    #[test]
+    fn resolves() {
+        let now = util::time::now();
+        let user = make_user(&UserID::new("jerry"), None, &now);
+        let company = make_company(&CompanyID::new("jerry's widgets"), "jerry's widgets", &now);
+        let member = make_member_worker(&MemberID::new("jerry-widgets"), user.id(), company.id(), &OccupationID::new("widgetmaker"), vec![], &now);
+        let mut deleted_user = make_user(&UserID::new("larry"), None, &now);
+        deleted_user.set_deleted(Some(now.clone()));
+        let models = vec![
+            models::Model::Occupation(make_occupation(&OccupationID::new("widgetmaker"), "widgetmaker", None, &now)),
+            models::Model::User(user.clone()),
+            models::Model::Company(company.clone()),
+            models::Model::Member(member.clone()),
+            models::Model::User(deleted_user.clone()),
+        ];
+
+        assert_eq!(resolve(&models, &user.agent_id()), Some(AgentRecord::User(&user)));
+        assert_eq!(resolve(&models, &company.agent_id()), Some(AgentRecord::Company(&company)));
+        let agent = resolve(&models, &member.agent_id()).unwrap();
+        assert_eq!(agent.kind(), AgentKind::Member);
+        assert_eq!(agent.id(), member.agent_id());
+        assert!(agent.is_active());
+        assert!(!resolve(&models, &deleted_user.agent_id()).unwrap().is_active());
+        // ids are matched by type as well as value
+        assert_eq!(resolve(&models, &CompanyID::new("jerry").into()), None);
+        assert_eq!(resolve(&models, &UserID::new("harry").into()), None);
+    }
+}
@@ -1,8 +1,34 @@
 /// A trait that all model IDs implement.
 pub trait ModelID: Into<String> + From<String> + Clone + PartialEq + Eq + std::hash::Hash {}
 
+/// A stable ordering key for models, ordering first by creation time and then
+/// by ID (to break ties). Storage layers can use this to build deterministic
+/// cursors for pagination.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, getset::Getters)]
+#[cfg_attr(feature = "with_serde", derive(serde::Serialize, serde::Deserialize))]
+#[getset(get = "pub")]
+pub struct SortKey {
+    /// When the model was created
+    created: chrono::DateTime<chrono::Utc>,
+    /// The model's ID
+    id: String,
+}
+
+impl SortKey {
+    /// Create a new sort key, for instance when rebuilding a cursor
+    pub fn new<T: Into<String>>(created: chrono::DateTime<chrono::Utc>, id: T) -> Self {
+        Self {
+            created,
+            id: id.into(),
+        }
+    }
+}
+
 /// A trait that all models implement which handles common functionality
 pub trait Model: Clone + PartialEq {
+    /// Get the model's stable ordering key (`created` + `id`).
+    fn sort_key(&self) -> SortKey;
+
     /// Checks whether or not this model has been deleted.
     fn is_deleted(&self) -> bool;
 
@@ -46,6 +72,13 @@ macro_rules! basis_model {
                 Self(uuid::Uuid::new_v4().to_hyphenated().encode_lower(&mut uuid::Uuid::encode_buffer()).to_string())
             }
 
+            /// Derive an id from a namespace and some content. The same
+            /// namespace and payload always produce the same id, which lets
+            /// independent nodes generate matching ids for the same data.
+            pub fn derive<T: AsRef<[u8]>>(namespace: &str, payload: T) -> Self {
+                Self(crate::util::id::derive(namespace, payload.as_ref()))
+            }
+
             /// Convert this ID to a string
             pub fn to_string(self) -> String {
                 self.into()
@@ -122,6 +155,10 @@ macro_rules! basis_model {
 
 
             impl crate::models::lib::basis_model::Model for $model {
+                fn sort_key(&self) -> crate::models::lib::basis_model::SortKey {
+                    crate::models::lib::basis_model::SortKey::new(self.created.clone(), self.id.as_str())
+                }
+
                 fn is_deleted(&self) -> bool {
                     self.deleted.is_some()
                 }
@@ -139,6 +176,18 @@ macro_rules! basis_model {
                 }
             }
 
+            /// Models are ordered by their `sort_key()`. Two models with the
+            /// same sort key that are otherwise different can't be compared.
+            impl std::cmp::PartialOrd for $model {
+                fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                    use crate::models::lib::basis_model::Model;
+                    match self.sort_key().cmp(&other.sort_key()) {
+                        std::cmp::Ordering::Equal if self != other => None,
+                        ordering => Some(ordering),
+                    }
+                }
+            }
+
             impl std::convert::From<$model> for crate::models::Model {
                 fn from(val: $model) -> Self {
                     crate::models::Model::$model(val)
@@ -155,6 +204,17 @@ macro_rules! basis_model {
                     }
                 }
             }
+
+            impl<'a> std::convert::TryFrom<&'a crate::models::Model> for &'a $model {
+                type Error = crate::error::Error;
+
+                fn try_from(val: &'a crate::models::Model) -> std::result::Result<Self, Self::Error> {
+                    match val {
+                        crate::models::Model::$model(val) => Ok(val),
+                        _ => Err(crate::error::Error::WrongModelType),
+                    }
+                }
+            }
         }
         pub use inner::$model;
     }
@@ -270,3 +330,38 @@ macro_rules! basis_model_inner {
     };
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::user::UserID,
+        util::test::*,
+    };
+
+    #[test]
+    fn sorts() {
+        let now1: chrono::DateTime<chrono::Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let now2: chrono::DateTime<chrono::Utc> = "2020-01-02T00:00:00Z".parse().unwrap();
+        let user1 = make_user(&UserID::new("zed"), None, &now1);
+        let user2 = make_user(&UserID::new("abe"), None, &now2);
+        let user3 = make_user(&UserID::new("bob"), None, &now2);
+
+        assert_eq!(user1.sort_key(), SortKey::new(now1.clone(), "zed"));
+        assert!(user1.sort_key() < user2.sort_key());
+        assert!(user2.sort_key() < user3.sort_key());
+        assert!(user1 < user2);
+        assert!(user3 > user2);
+        assert_eq!(user1.partial_cmp(&user1.clone()), Some(std::cmp::Ordering::Equal));
+
+        let mut users = vec![user3.clone(), user1.clone(), user2.clone()];
+        users.sort_by_key(|x| x.sort_key());
+        assert_eq!(users, vec![user1.clone(), user2.clone(), user3.clone()]);
+
+        // same key, different data: no ordering
+        let mut user4 = user1.clone();
+        user4.set_name("zed 2".into());
+        assert_eq!(user4.sort_key(), user1.sort_key());
+        assert_eq!(user1.partial_cmp(&user4), None);
+    }
+}
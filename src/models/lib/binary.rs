@@ -0,0 +1,265 @@
+//! A compact binary encoding for models, enabled via the `binary` and
+//! `with_serde` features together.
+//!
+//! This builds on the [costs encoding][1], but rather than hand-packing every
+//! model's fields (and the ValueFlows types they wrap) the model is run through
+//! its serde representation and that tree of values is packed as binary. The
+//! format is:
+//!
+//! - one version byte
+//! - the value, where each value is a tag byte followed by its data:
+//!   - `0` null, `1` false, `2` true
+//!   - `3` a number, as a varint-prefixed string of its exact decimal digits
+//!   - `4` a string, as varint-prefixed UTF-8
+//!   - `5` an array, as a varint item count followed by each value
+//!   - `6` an object, as a varint entry count followed by each entry as a
+//!     varint-prefixed UTF-8 key and a value
+//!
+//! Object entries are written sorted by key, so equal models always encode to
+//! the same bytes, and numbers keep their full precision.
+//!
+//! [1]: ../../costs/binary/index.html
+
+use crate::{
+    costs::binary::{Reader, write_varint},
+    error::{Error, Result},
+    models::{
+        Model,
+        event::Event,
+    },
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Number, Value};
+use std::str::FromStr;
+
+/// The version of the encoding this module writes.
+const VERSION: u8 = 1;
+
+/// Values nested deeper than this are rejected when decoding.
+const MAX_DEPTH: usize = 128;
+
+/// Append a varint-prefixed string to a buffer.
+fn write_str(buf: &mut Vec<u8>, val: &str) {
+    write_varint(buf, val.len() as u64);
+    buf.extend_from_slice(val.as_bytes());
+}
+
+/// Append a value to a buffer.
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buf.push(0),
+        Value::Bool(false) => buf.push(1),
+        Value::Bool(true) => buf.push(2),
+        Value::Number(num) => {
+            buf.push(3);
+            write_str(buf, &num.to_string());
+        }
+        Value::String(string) => {
+            buf.push(4);
+            write_str(buf, string);
+        }
+        Value::Array(items) => {
+            buf.push(5);
+            write_varint(buf, items.len() as u64);
+            for item in items {
+                write_value(buf, item);
+            }
+        }
+        Value::Object(map) => {
+            buf.push(6);
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            write_varint(buf, entries.len() as u64);
+            for (key, val) in entries {
+                write_str(buf, key);
+                write_value(buf, val);
+            }
+        }
+    }
+}
+
+/// Read a varint-prefixed string.
+fn read_str(reader: &mut Reader) -> Result<String> {
+    let len = reader.varint()? as usize;
+    String::from_utf8(reader.take(len)?.to_vec())
+        .map_err(|e| Error::BinaryDecodeFailed(format!("{}", e)))
+}
+
+/// Read a value.
+fn read_value(reader: &mut Reader, depth: usize) -> Result<Value> {
+    if depth > MAX_DEPTH {
+        Err(Error::BinaryDecodeFailed("value nested too deeply".into()))?;
+    }
+    let value = match reader.take(1)?[0] {
+        0 => Value::Null,
+        1 => Value::Bool(false),
+        2 => Value::Bool(true),
+        3 => {
+            let num = Number::from_str(&read_str(reader)?)
+                .map_err(|e| Error::BinaryDecodeFailed(format!("{}", e)))?;
+            Value::Number(num)
+        }
+        4 => Value::String(read_str(reader)?),
+        5 => {
+            let count = reader.varint()?;
+            let mut items = Vec::new();
+            for _ in 0..count {
+                items.push(read_value(reader, depth + 1)?);
+            }
+            Value::Array(items)
+        }
+        6 => {
+            let count = reader.varint()?;
+            let mut map = Map::new();
+            for _ in 0..count {
+                let key = read_str(reader)?;
+                map.insert(key, read_value(reader, depth + 1)?);
+            }
+            Value::Object(map)
+        }
+        tag => Err(Error::BinaryDecodeFailed(format!("unknown tag {}", tag)))?,
+    };
+    Ok(value)
+}
+
+/// Encode any serializable object into the binary format.
+fn encode<T: Serialize>(obj: &T) -> Result<Vec<u8>> {
+    let value = serde_json::to_value(obj)
+        .map_err(|e| Error::SerializeFailed(e.to_string()))?;
+    let mut buf = vec![VERSION];
+    write_value(&mut buf, &value);
+    Ok(buf)
+}
+
+/// Decode any deserializable object from the binary format.
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let mut reader = Reader::new(bytes);
+    let version = reader.take(1)?[0];
+    if version == 0 || version > VERSION {
+        Err(Error::BinaryDecodeFailed(format!("unknown version {}", version)))?;
+    }
+    let value = read_value(&mut reader, 0)?;
+    if !reader.is_empty() {
+        Err(Error::BinaryDecodeFailed("trailing data".into()))?;
+    }
+    serde_json::from_value(value)
+        .map_err(|e| Error::BinaryDecodeFailed(format!("{}", e)))
+}
+
+impl Event {
+    /// Encode this event into the compact binary format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        encode(self)
+    }
+
+    /// Decode an event from the compact binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        decode(bytes)
+    }
+}
+
+impl Model {
+    /// Encode this model into the compact binary format.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        encode(self)
+    }
+
+    /// Decode a model from the compact binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            account::AccountID,
+            company::CompanyID,
+            event::EventID,
+            process::ProcessID,
+            resource::ResourceID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+    use om2::{Measure, Unit};
+    use vf_rs::vf;
+
+    fn make_event(now: &chrono::DateTime<chrono::Utc>) -> Event {
+        let mut costs = Costs::new_with_labor("machinist", num!(42.1234567890123456789));
+        costs.track_resource("iron", num!(2.2), num!(0.0019));
+        Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(vf::Action::Transfer)
+                    .has_point_in_time(now.clone())
+                    .note(Some("widgets for larry".into()))
+                    .provider(CompanyID::new("jerry's widgets"))
+                    .receiver(CompanyID::new("larry's chairs"))
+                    .resource_inventoried_as(Some(ResourceID::new("widgets")))
+                    .resource_quantity(Some(Measure::new(num!(8), Unit::One)))
+                    .to_resource_inventoried_as(Some(ResourceID::new("widgets2")))
+                    .build().unwrap()
+            )
+            .move_costs(Some(costs))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn round_trips_events() {
+        let now = util::time::now();
+        let event = make_event(&now);
+        let bytes = event.to_bytes().unwrap();
+        assert_eq!(Event::from_bytes(&bytes).unwrap(), event);
+        // deterministic
+        assert_eq!(event.clone().to_bytes().unwrap(), bytes);
+        // smaller than the JSON it's built from
+        assert!(bytes.len() < serde_json::to_vec(&event).unwrap().len());
+    }
+
+    #[test]
+    fn round_trips_models() {
+        let now = util::time::now();
+        let costs = Costs::new_with_labor("machinist", num!(0.0000000000000001));
+        let models = vec![
+            Model::Event(make_event(&now)),
+            Model::Account(make_account(&AccountID::create(), &UserID::create(), num!(1000.5), "jerry's account", &now)),
+            Model::Company(make_company(&CompanyID::create(), "jerry's widgets", &now)),
+            Model::Process(make_process(&ProcessID::create(), &CompanyID::create(), "make widgets", &costs, &now)),
+        ];
+        for model in models {
+            let bytes = model.to_bytes().unwrap();
+            assert_eq!(Model::from_bytes(&bytes).unwrap(), model);
+        }
+
+        // an event encoded as a model doesn't decode as a bare event
+        let event = make_event(&now);
+        let bytes = Model::Event(event.clone()).to_bytes().unwrap();
+        assert!(Event::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let now = util::time::now();
+        let bytes = make_event(&now).to_bytes().unwrap();
+        assert_eq!(Event::from_bytes(&[]), Err(Error::BinaryDecodeFailed("unexpected end of input".into())));
+        assert_eq!(Event::from_bytes(&bytes[0..bytes.len() - 1]), Err(Error::BinaryDecodeFailed("unexpected end of input".into())));
+        let mut bytes2 = bytes.clone();
+        bytes2.push(0);
+        assert_eq!(Event::from_bytes(&bytes2), Err(Error::BinaryDecodeFailed("trailing data".into())));
+        let mut bytes3 = bytes.clone();
+        bytes3[0] = 99;
+        assert_eq!(Event::from_bytes(&bytes3), Err(Error::BinaryDecodeFailed("unknown version 99".into())));
+        assert_eq!(Model::from_bytes(&[VERSION, 7]), Err(Error::BinaryDecodeFailed("unknown tag 7".into())));
+        let mut deep = vec![VERSION];
+        deep.extend(std::iter::repeat(&[5u8, 1u8]).take(MAX_DEPTH + 2).flatten());
+        assert_eq!(Model::from_bytes(&deep), Err(Error::BinaryDecodeFailed("value nested too deeply".into())));
+    }
+}
@@ -25,6 +25,28 @@ macro_rules! load_models {
                 $model(crate::models::$path::$model),
             )*
         }
+
+        impl $enumname {
+            /// Returns the model's type name along with its ID, which together
+            /// uniquely identify the model.
+            pub fn type_and_id(&self) -> (&'static str, &str) {
+                match self {
+                    $(
+                        $enumname::$model(model) => (stringify!($model), model.id().as_str()),
+                    )*
+                }
+            }
+
+            /// Returns the wrapped model's stable ordering key.
+            pub fn sort_key(&self) -> crate::models::lib::basis_model::SortKey {
+                use crate::models::lib::basis_model::Model as _;
+                match self {
+                    $(
+                        $enumname::$model(model) => model.sort_key(),
+                    )*
+                }
+            }
+        }
     };
 
     // entry point
@@ -33,21 +55,41 @@ macro_rules! load_models {
             @$($load_type)*
             (account, Account, AccountID),
             (agreement, Agreement, AgreementID),
+            (agreement_template, AgreementTemplate, AgreementTemplateID),
+            (ballot, Ballot, BallotID),
             (commitment, Commitment, CommitmentID),
             (company, Company, CompanyID),
+            (company_ledger, CompanyLedger, CompanyLedgerID),
+            (company_link, CompanyLink, CompanyLinkID),
+            (company_role, CompanyRole, CompanyRoleID),
+            (company_treasury, CompanyTreasury, CompanyTreasuryID),
+            (config, NetworkConfig, NetworkConfigID),
+            (cost_snapshot, CostSnapshot, CostSnapshotID),
             (member, Member, MemberID),
+            (membership_invite, MembershipInvite, MembershipInviteID),
             (currency, Currency, CurrencyID),
+            (debt, Debt, DebtID),
+            (delegation, Delegation, DelegationID),
             (event, Event, EventID),
+            (flag, Flag, FlagID),
+            (impact, Impact, ImpactID),
+            (inspection, Inspection, InspectionID),
             (intent, Intent, IntentID),
             (occupation, Occupation, OccupationID),
             (process, Process, ProcessID),
             (process_spec, ProcessSpec, ProcessSpecID),
+            (proposal, Proposal, ProposalID),
+            (region, Region, RegionID),
             (resource, Resource, ResourceID),
+            (resource_group, ResourceGroup, ResourceGroupID),
+            (resource_group_link, ResourceGroupLink, ResourceGroupLinkID),
             (resource_spec, ResourceSpec, ResourceSpecID, Dimensions),
+            (resource_transform, ResourceTransform, ResourceTransformID),
+            (service_subscription, ServiceSubscription, ServiceSubscriptionID),
+            (shipment, Shipment, ShipmentID),
+            (transfer_policy, TransferPolicy, TransferPolicyID),
+            (ubi_issuance, UbiIssuance, UbiIssuanceID),
             (user, User, UserID),
-
-            //(resource_group, ResourceGroup, ResourceGroupID),
-            //(resource_group_link, ResourceGroupLink, ResourceGroupLinkID),
         }
     };
 }
@@ -55,4 +97,6 @@ macro_rules! load_models {
 #[macro_use]
 pub(crate) mod basis_model;
 pub mod agent;
+#[cfg(all(feature = "binary", feature = "with_serde"))]
+pub(crate) mod binary;
 
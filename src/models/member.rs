@@ -18,14 +18,23 @@
 //! company, which assign costs to companies and print and transfer credits to
 //! the worker's preferred account.
 //!
+//! Worker compensation is effective-dated: each change is appended to the
+//! worker's `CompensationHistory` so we can always tell what a worker was being
+//! paid at any given point in time (see `Member::compensation_at()`).
+//!
 //! [0]: ../process/struct.Process.html
 //! [1]: ../../transactions/event/work/index.html
 
+use chrono::{DateTime, Utc};
 use crate::{
+    access,
     error::{Error, Result},
     models::{
         account::AccountID,
         company::{CompanyID, Permission},
+        company_link::CompanyLink,
+        company_role::{CompanyRole, CompanyRoleID},
+        delegation::Delegation,
         lib::{
             agent::{Agent, AgentID},
             basis_model::Model,
@@ -33,6 +42,7 @@ use crate::{
         occupation::OccupationID,
         user::UserID,
     },
+    util::measure,
 };
 use getset::{Getters, Setters};
 use om2::{Measure, Unit, NumericUnion};
@@ -115,6 +125,76 @@ impl Compensation {
             est_hours_per_week: Some(est_hours_per_week),
         }
     }
+
+    /// Get the hourly rate this compensation works out to. Salaries are
+    /// converted using `est_hours_per_week`, and if no estimate is given we
+    /// have no way of knowing the hourly rate so `None` is returned.
+    pub fn hourly_rate(&self) -> Result<Option<Decimal>> {
+        let wage = measure::to_decimal(self.wage())?;
+        let weeks = match self.wage().has_unit() {
+            Unit::Hour => return Ok(Some(wage)),
+            Unit::Day => Decimal::one() / Decimal::from(7),
+            Unit::Week => Decimal::one(),
+            Unit::Month => Decimal::from(365) / Decimal::from(12 * 7),
+            Unit::Year => Decimal::from(365) / Decimal::from(7),
+            _ => Err(Error::MeasureUnitsMismatched)?,
+        };
+        match self.est_hours_per_week() {
+            Some(hours) if hours > &Decimal::zero() => Ok(Some(wage / (weeks * hours.clone()))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A single entry in a worker's compensation history.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct CompensationEntry {
+    /// When this compensation takes effect
+    effective: DateTime<Utc>,
+    /// The compensation itself
+    compensation: Compensation,
+}
+
+/// An effective-dated record of a worker's compensation. Entries are only ever
+/// added, never replaced, so we can audit labor costs retroactively.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct CompensationHistory {
+    /// Our entries, ordered by effective date
+    entries: Vec<CompensationEntry>,
+}
+
+impl CompensationHistory {
+    /// Create an empty history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a compensation entry to the history. Entries are kept in order of
+    /// their effective date, and an entry with the same effective date as an
+    /// existing one takes precedence over it.
+    pub fn push(&mut self, effective: DateTime<Utc>, compensation: Compensation) {
+        let idx = self.entries.iter()
+            .position(|entry| entry.effective() > &effective)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(idx, CompensationEntry { effective, compensation });
+    }
+
+    /// Find the compensation in effect at the given time, if any.
+    pub fn at(&self, time: &DateTime<Utc>) -> Option<&Compensation> {
+        self.entries.iter()
+            .rev()
+            .find(|entry| entry.effective() <= time)
+            .map(|entry| entry.compensation())
+    }
+
+    /// Returns true if there are no entries in this history
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
 /// Describes a company that is a member of a company.
@@ -157,9 +237,16 @@ pub struct MemberWorker {
     /// occupation really only applies to worker members, it is a conscious
     /// decision to put occupation in the worker struct.
     occupation: OccupationID,
+    /// Any other occupations this worker holds at this company, beyond their
+    /// primary `occupation`. A worker can record labor under any of their
+    /// occupations.
+    occupations: Vec<OccupationID>,
     /// Describes how the member is compensated for their labor. Must be
     /// defined for the member to perform labor.
     compensation: Option<Compensation>,
+    /// Every compensation this worker has had, along with when each took
+    /// effect.
+    compensation_history: CompensationHistory,
 }
 
 impl MemberWorker {
@@ -167,9 +254,44 @@ impl MemberWorker {
     pub fn new<T: Into<OccupationID>>(occupation_id: T, compensation: Option<Compensation>) -> Self {
         Self {
             occupation: occupation_id.into(),
+            occupations: vec![],
             compensation,
+            compensation_history: CompensationHistory::new(),
         }
     }
+
+    /// Returns true if this worker holds the given occupation (either as their
+    /// primary occupation or as one of their others)
+    pub fn has_occupation(&self, occupation_id: &OccupationID) -> bool {
+        self.occupation() == occupation_id || self.occupations().contains(occupation_id)
+    }
+
+    /// Add an occupation to this worker. Adding an occupation they already
+    /// hold does nothing.
+    pub(crate) fn add_occupation(&mut self, occupation_id: OccupationID) {
+        if !self.has_occupation(&occupation_id) {
+            self.occupations.push(occupation_id);
+        }
+    }
+
+    /// Remove one of this worker's (non-primary) occupations.
+    pub(crate) fn remove_occupation(&mut self, occupation_id: &OccupationID) -> Result<()> {
+        if self.occupation() == occupation_id {
+            Err(Error::OccupationIsPrimary)?;
+        }
+        if !self.occupations().contains(occupation_id) {
+            Err(Error::OccupationMismatch)?;
+        }
+        self.occupations.retain(|x| x != occupation_id);
+        Ok(())
+    }
+
+    /// Set this worker's primary occupation. If the occupation was one of the
+    /// worker's other occupations, it's moved out of that list.
+    pub(crate) fn set_primary_occupation(&mut self, occupation_id: OccupationID) {
+        self.occupations.retain(|x| x != &occupation_id);
+        self.occupation = occupation_id;
+    }
 }
 
 /// Describes the type of membership for a particular Member record.
@@ -207,6 +329,11 @@ basis_model! {
         class: MemberClass,
         /// The permissions this member has at this company (additive)
         permissions: Vec<Permission>,
+        /// The company roles assigned to this member. The permissions from
+        /// these roles are added to the member's own `permissions`. These are
+        /// copies of the roles, kept in sync by the company role transactions
+        /// so permission checks don't have to look the roles up.
+        roles: Vec<CompanyRole>,
         /// Agreement under which this membership takes place. This can be an
         /// employee agreement, or any general membership agreement (for
         /// instance, there might be a "you can be a member of this housing
@@ -228,15 +355,39 @@ impl Member {
         self.inner().object()
     }
 
-    /// Determines if a member can perform an action (base on their permissions
-    /// list). Note that we don't use roles here, the idea is that companies
-    /// manage their own roles and permissions are assigned to users directly.
+    /// Determines if a member can perform an action, either via their own
+    /// permissions list or via one of their assigned roles. Roles that belong
+    /// to another company are ignored.
     pub fn can(&self, permission: &Permission) -> bool {
         if !self.is_active() {
             return false;
         }
         self.permissions().contains(&Permission::All) ||
-            self.permissions().contains(permission)
+            self.permissions().contains(permission) ||
+            self.roles().iter()
+                .filter(|role| self.group_id() == &role.company_id().clone().into())
+                .any(|role| role.can(permission))
+    }
+
+    /// Grab the IDs of the roles assigned to this member
+    pub fn role_ids(&self) -> Vec<&CompanyRoleID> {
+        self.roles().iter().map(|role| role.id()).collect()
+    }
+
+    /// Replace this member's copy of a role with the given (updated) version,
+    /// dropping it if the role was deleted. Returns true if the member has the
+    /// role.
+    pub(crate) fn sync_role(&mut self, role: &CompanyRole) -> bool {
+        let idx = match self.roles().iter().position(|existing| existing.id() == role.id()) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        if role.is_deleted() {
+            self.roles_mut().remove(idx);
+        } else {
+            self.roles_mut()[idx] = role.clone();
+        }
+        true
     }
 
     /// Check if this member can perform an action on a company.
@@ -244,7 +395,63 @@ impl Member {
         if self.member_id() != &user_id.clone().into() || self.group_id() != &company_id.clone().into() || !self.can(&permission) {
             Err(Error::InsufficientPrivileges)?;
         }
-        Ok(())
+        access::policy_check_member(self, company_id, &permission)
+    }
+
+    /// Check if this member can perform an action on a company, either with
+    /// their own permissions or with permissions another member has delegated
+    /// to them (and that are still in effect as of `now`). The `grantors` are
+    /// the members who made the `delegations`, and have to still hold the
+    /// permissions they lent out.
+    ///
+    /// Only the [delegable permissions][1] (payroll approval) are ever
+    /// delegated, so this is only used by the transactions covering them.
+    ///
+    /// [1]: ../delegation/constant.DELEGABLE_PERMISSIONS.html
+    pub fn access_check_delegated(&self, user_id: &UserID, company_id: &CompanyID, permission: Permission, delegations: &[Delegation], grantors: &[Member], now: &DateTime<Utc>) -> Result<()> {
+        if self.member_id() != &user_id.clone().into() || self.group_id() != &company_id.clone().into() {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        let delegated = || {
+            self.is_active() &&
+                delegations.iter().any(|delegation| {
+                    grantors.iter()
+                        .find(|grantor| grantor.id() == delegation.from_member())
+                        .map(|grantor| delegation.grants(grantor, self.id(), company_id, &permission, now))
+                        .unwrap_or(false)
+                })
+        };
+        if !self.can(&permission) && !delegated() {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        access::policy_check_member(self, company_id, &permission)
+    }
+
+    /// Check if this member can perform an action on a company, either
+    /// directly or via a federation.
+    ///
+    /// If this member doesn't belong to `company_id`, but belongs to a
+    /// federation that `company_id` is linked to (via one of the given
+    /// accepted `links`), the member can act on `company_id` as long as the
+    /// link grants the permission and the member holds the permission at the
+    /// federation.
+    ///
+    /// Only the process transactions (create, update, delete) use this check.
+    /// Events, commitments, and everything else use `access_check()`, so
+    /// federation members can set up processes for a member company but can't
+    /// record events or make commitments on its behalf.
+    pub fn access_check_federated(&self, user_id: &UserID, company_id: &CompanyID, permission: Permission, links: &[CompanyLink]) -> Result<()> {
+        if self.group_id() == &company_id.clone().into() {
+            return self.access_check(user_id, company_id, permission);
+        }
+        let federation_id = self.company_id()?;
+        if self.member_id() != &user_id.clone().into() || !self.can(&permission) {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        if !links.iter().any(|link| link.grants(&federation_id, company_id, &permission)) {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        access::policy_check_member(self, company_id, &permission)
     }
 
     /// Try and get a `CompanyID` from this member's group id.
@@ -252,7 +459,7 @@ impl Member {
         self.group_id().clone().try_into()
     }
 
-    /// Grab this member's occupation id, if it has one
+    /// Grab this member's (primary) occupation id, if it has one
     pub fn occupation_id<'a>(&'a self) -> Option<&'a OccupationID> {
         match self.class() {
             MemberClass::Worker(worker) => Some(worker.occupation()),
@@ -260,6 +467,27 @@ impl Member {
         }
     }
 
+    /// Grab all of this member's occupation ids (primary first), if it's a
+    /// worker
+    pub fn occupation_ids<'a>(&'a self) -> Vec<&'a OccupationID> {
+        match self.class() {
+            MemberClass::Worker(worker) => {
+                let mut ids = vec![worker.occupation()];
+                ids.extend(worker.occupations().iter());
+                ids
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns true if this member is a worker holding the given occupation
+    pub fn has_occupation(&self, occupation_id: &OccupationID) -> bool {
+        match self.class() {
+            MemberClass::Worker(worker) => worker.has_occupation(occupation_id),
+            _ => false,
+        }
+    }
+
     /// Grab this member's compensation object, if it has one
     pub fn compensation<'a>(&'a self) -> Option<&'a Compensation> {
         match self.class() {
@@ -267,6 +495,17 @@ impl Member {
             _ => None,
         }
     }
+
+    /// Grab the compensation that was in effect for this member at the given
+    /// time. If the member has no compensation history, we fall back to their
+    /// current compensation.
+    pub fn compensation_at<'a>(&'a self, time: &DateTime<Utc>) -> Option<&'a Compensation> {
+        match self.class() {
+            MemberClass::Worker(worker) if worker.compensation_history().is_empty() => worker.compensation().as_ref(),
+            MemberClass::Worker(worker) => worker.compensation_history().at(time),
+            _ => None,
+        }
+    }
 }
 
 impl Agent for Member {
@@ -280,6 +519,7 @@ mod test {
     use crate::{
         models::{
             company::{CompanyID, Permission as CompanyPermission},
+            company_link::CompanyLinkID,
             user::UserID,
         },
         util::{self, test::*},
@@ -333,5 +573,151 @@ mod test {
         assert!(member7.access_check(&user_id, &company_id, CompanyPermission::MemberCreate).is_err());
         assert!(member7.access_check(&user_id, &company_id, CompanyPermission::CompanyDelete).is_err());
     }
-}
 
+    #[test]
+    fn roles() {
+        let now = util::time::now();
+        let company_id = CompanyID::create();
+        let mut member = make_member_worker(&MemberID::create(), &UserID::create(), &company_id, &OccupationID::create(), vec![CompanyPermission::Work], &now);
+        let user_id: UserID = member.member_id().clone().try_into().unwrap();
+        let make_role = |company_id: &CompanyID, permissions: Vec<CompanyPermission>| {
+            CompanyRole::builder()
+                .id(CompanyRoleID::create())
+                .company_id(company_id.clone())
+                .name("bookkeeper")
+                .permissions(permissions)
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        let role1 = make_role(&company_id, vec![CompanyPermission::Payroll, CompanyPermission::MoveCosts]);
+        let role2 = make_role(&company_id, vec![CompanyPermission::MemberCreate]);
+        let role_other = make_role(&CompanyID::create(), vec![CompanyPermission::All]);
+        assert!(member.access_check(&user_id, &company_id, CompanyPermission::Payroll).is_err());
+        member.set_roles(vec![role1.clone(), role_other.clone()]);
+        assert_eq!(member.role_ids(), vec![role1.id(), role_other.id()]);
+
+        assert!(member.can(&CompanyPermission::Work));
+        // only granted via a role
+        assert!(member.can(&CompanyPermission::Payroll));
+        assert!(member.access_check(&user_id, &company_id, CompanyPermission::MoveCosts).is_ok());
+        // not assigned
+        assert!(member.access_check(&user_id, &company_id, CompanyPermission::MemberCreate).is_err());
+        // assigned, but belongs to another company
+        assert!(member.access_check(&user_id, &company_id, CompanyPermission::CompanyDelete).is_err());
+
+        let mut member2 = member.clone();
+        member2.set_active(false);
+        assert!(member2.access_check(&user_id, &company_id, CompanyPermission::Payroll).is_err());
+
+        // roles stay in sync with their source
+        let mut role1_updated = role1.clone();
+        role1_updated.set_permissions(vec![CompanyPermission::MoveCosts]);
+        let mut member3 = member.clone();
+        assert!(member3.sync_role(&role1_updated));
+        assert!(!member3.sync_role(&role2));
+        assert!(member3.access_check(&user_id, &company_id, CompanyPermission::MoveCosts).is_ok());
+        assert!(member3.access_check(&user_id, &company_id, CompanyPermission::Payroll).is_err());
+        let mut role1_inactive = role1.clone();
+        role1_inactive.set_active(false);
+        assert!(member3.sync_role(&role1_inactive));
+        assert!(member3.access_check(&user_id, &company_id, CompanyPermission::MoveCosts).is_err());
+        let mut role1_deleted = role1.clone();
+        role1_deleted.set_deleted(Some(now.clone()));
+        assert!(member3.sync_role(&role1_deleted));
+        assert_eq!(member3.role_ids(), vec![role_other.id()]);
+        assert!(member3.access_check(&user_id, &company_id, CompanyPermission::Payroll).is_err());
+    }
+
+    #[test]
+    fn access_check_federated() {
+        let now = util::time::now();
+        let federation_id = CompanyID::new("widget federation");
+        let coop_id = CompanyID::new("jerry's widgets");
+        let member = make_member_worker(&MemberID::create(), &UserID::create(), &federation_id, &OccupationID::create(), vec![CompanyPermission::ProcessCreate, CompanyPermission::ProcessDelete], &now);
+        let user_id: UserID = member.member_id().clone().try_into().unwrap();
+        let link = CompanyLink::builder()
+            .id(CompanyLinkID::create())
+            .parent_id(federation_id.clone())
+            .child_id(coop_id.clone())
+            .inherit_permissions(vec![CompanyPermission::ProcessCreate, CompanyPermission::Payroll])
+            .accepted(Some(now.clone()))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let links = vec![link.clone()];
+
+        // acting on our own company works as usual
+        assert!(member.access_check_federated(&user_id, &federation_id, CompanyPermission::ProcessDelete, &links).is_ok());
+        assert!(member.access_check_federated(&user_id, &federation_id, CompanyPermission::Payroll, &links).is_err());
+        // granted by the link and held by the member
+        assert!(member.access_check_federated(&user_id, &coop_id, CompanyPermission::ProcessCreate, &links).is_ok());
+        // held by the member but not granted
+        assert!(member.access_check_federated(&user_id, &coop_id, CompanyPermission::ProcessDelete, &links).is_err());
+        // granted but not held
+        assert!(member.access_check_federated(&user_id, &coop_id, CompanyPermission::Payroll, &links).is_err());
+        // no link
+        assert!(member.access_check_federated(&user_id, &coop_id, CompanyPermission::ProcessCreate, &vec![]).is_err());
+        assert!(member.access_check_federated(&user_id, &CompanyID::new("larry's chairs"), CompanyPermission::ProcessCreate, &links).is_err());
+        // wrong user
+        assert!(member.access_check_federated(&UserID::create(), &coop_id, CompanyPermission::ProcessCreate, &links).is_err());
+
+        let mut link2 = link.clone();
+        link2.set_active(false);
+        assert!(member.access_check_federated(&user_id, &coop_id, CompanyPermission::ProcessCreate, &vec![link2]).is_err());
+        // the federation hasn't accepted the link yet
+        let mut link3 = link.clone();
+        link3.set_accepted(None);
+        assert!(member.access_check_federated(&user_id, &coop_id, CompanyPermission::ProcessCreate, &vec![link3]).is_err());
+        let mut member2 = member.clone();
+        member2.set_active(false);
+        assert!(member2.access_check_federated(&user_id, &coop_id, CompanyPermission::ProcessCreate, &links).is_err());
+    }
+
+    #[test]
+    fn compensation_history() {
+        let jan: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let mar: DateTime<Utc> = "2020-03-01T00:00:00Z".parse().unwrap();
+        let jun: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let comp1 = Compensation::new_hourly(num!(20), "account");
+        let comp2 = Compensation::new_hourly(num!(25), "account");
+        let comp3 = Compensation::new_hourly(num!(22), "account");
+
+        let mut history = CompensationHistory::new();
+        assert!(history.is_empty());
+        history.push(jan.clone(), comp1.clone());
+        history.push(jun.clone(), comp2.clone());
+        // retroactive entries slot in where they belong
+        history.push(mar.clone(), comp3.clone());
+        assert_eq!(history.entries().len(), 3);
+        assert_eq!(history.at(&"2019-12-31T00:00:00Z".parse().unwrap()), None);
+        assert_eq!(history.at(&jan), Some(&comp1));
+        assert_eq!(history.at(&"2020-02-14T00:00:00Z".parse().unwrap()), Some(&comp1));
+        assert_eq!(history.at(&mar), Some(&comp3));
+        assert_eq!(history.at(&"2021-01-01T00:00:00Z".parse().unwrap()), Some(&comp2));
+        // same effective date: the newest entry wins
+        history.push(mar.clone(), comp2.clone());
+        assert_eq!(history.at(&mar), Some(&comp2));
+
+        let mut member = make_member_worker(&MemberID::create(), &UserID::create(), &CompanyID::create(), &OccupationID::create(), vec![], &jan);
+        assert_eq!(member.compensation_at(&jun), None);
+        member.set_class(MemberClass::Worker(MemberWorker::new(OccupationID::create(), Some(comp1.clone()))));
+        assert_eq!(member.compensation_at(&jun), Some(&comp1));
+        match member.class_mut() {
+            MemberClass::Worker(worker) => { worker.set_compensation_history(history.clone()); }
+            _ => panic!("not a worker"),
+        }
+        assert_eq!(member.compensation_at(&jun), Some(&comp2));
+    }
+
+    #[test]
+    fn hourly_rate() {
+        assert_eq!(Compensation::new_hourly(num!(32), "account").hourly_rate(), Ok(Some(num!(32))));
+        let salary = Compensation::new_salary(num!(73000), "account", num!(40));
+        assert_eq!(salary.hourly_rate(), Ok(Some(num!(73000) / (num!(365) / num!(7) * num!(40)))));
+        let salary2 = Compensation::new_salary(num!(73000), "account", num!(0));
+        assert_eq!(salary2.hourly_rate(), Ok(None));
+    }
+}
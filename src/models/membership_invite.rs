@@ -0,0 +1,71 @@
+//! A membership invite is the first step in adding a new member to a company.
+//! Rather than creating memberships directly, a company invites a user, and
+//! the membership is only created once the user accepts.
+//!
+//! This makes the invitee's consent part of the record: an accepted invite
+//! notes when the user agreed to join, under what class, permissions, and
+//! agreement. In worker-managed companies this is what keeps anyone from being
+//! signed up for work (or ownership) they didn't agree to.
+//!
+//! See the [member model][1] and the [member transactions][2].
+//!
+//! [1]: ../member/index.html
+//! [2]: ../../transactions/member/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    models::{
+        company::{CompanyID, Permission as CompanyPermission},
+        member::MemberClass,
+        user::UserID,
+    },
+};
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use url::Url;
+
+/// Where an invite stands.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum InviteStatus {
+    /// Waiting on the invitee to respond
+    Pending,
+    /// The invitee consented to join the company
+    Accepted,
+    /// The invitee declined to join the company
+    Rejected,
+}
+
+basis_model! {
+    /// The `MembershipInvite` model describes a pending (or answered) offer of
+    /// membership in a company.
+    pub struct MembershipInvite {
+        id: <<MembershipInviteID>>,
+        /// The company extending the invite
+        company_id: CompanyID,
+        /// The user being invited
+        invitee: UserID,
+        /// The user who sent the invite (on behalf of the company)
+        invited_by: UserID,
+        /// The membership class being offered (worker, user, etc)
+        class: MemberClass,
+        /// The company permissions the new member will have
+        permissions: Vec<CompanyPermission>,
+        /// The agreement the invitee is consenting to by accepting
+        agreement: Option<Url>,
+        /// The status of the invite
+        status: InviteStatus,
+        /// When the invitee accepted or rejected the invite. For accepted
+        /// invites, this is the record of the invitee's consent.
+        responded: Option<DateTime<Utc>>,
+    }
+    MembershipInviteBuilder
+}
+
+impl MembershipInvite {
+    /// Determine if this invite is still waiting on a response
+    pub fn is_pending(&self) -> bool {
+        self.status() == &InviteStatus::Pending
+    }
+}
+
@@ -32,12 +32,14 @@ use crate::{
 };
 #[cfg(feature = "with_serde")]
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 #[macro_use]
 pub(crate) mod lib;
 
-pub use lib::agent::{Agent, AgentID};
+pub use lib::agent::{self, Agent, AgentID, AgentKind, AgentRecord};
+pub use lib::basis_model::SortKey;
 
 // load all of our pub mod <model>; ... lines
 load_models!{ pub mod }
@@ -107,6 +109,16 @@ impl Modification {
         (self.op, self.model)
     }
 
+    /// Get this modification's `Op`
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+
+    /// Get the model this modification operates on
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
     /// Consume this modification, and verify that the `Op` matches the one
     /// passed in, then return the *unwrapped* model (ie, not `Model::User(user)`
     /// but `user as User`).
@@ -169,6 +181,55 @@ impl Modifications {
         self.modifications
     }
 
+    /// Iterate over the modifications in this set without consuming it
+    pub fn iter(&self) -> std::slice::Iter<'_, Modification> {
+        self.modifications.iter()
+    }
+
+    /// Group the modifications in this set by the model they affect, keyed by
+    /// the model's type name and ID (see `Model::type_and_id()`). Each model's
+    /// modifications are kept in the order they appear in the set.
+    pub fn index_by_id(&self) -> HashMap<(&'static str, &str), Vec<&Modification>> {
+        let mut index: HashMap<(&'static str, &str), Vec<&Modification>> = HashMap::new();
+        for modification in self.iter() {
+            index.entry(modification.model().type_and_id())
+                .or_default()
+                .push(modification);
+        }
+        index
+    }
+
+    /// Iterate over the modifications in this set that operate on a specific
+    /// model type, yielding each `Op` along with the *unwrapped* model. This
+    /// saves storage layers from matching on every `Model` variant:
+    ///
+    /// ```rust
+    /// use basis_core::{
+    ///     models::{
+    ///         account::{Account, AccountID},
+    ///         user::{User, UserID},
+    ///     },
+    ///     transactions,
+    /// };
+    /// use chrono::Utc;
+    ///
+    /// let mods = transactions::user::create(UserID::new("e0f6d6a5-64b5-4c4f-9a4b-1f4d33b47bd2"), "andrew@lyonbros.com", "andrew", AccountID::new("0e2b8a44-6c2a-4d0e-8d5c-0e4a4b5b6a3c"), true, &Utc::now()).unwrap();
+    /// for (_op, user) in mods.filter_model::<User>() {
+    ///     assert_eq!(user.name(), "andrew");
+    /// }
+    /// assert_eq!(mods.filter_model::<Account>().count(), 1);
+    /// ```
+    pub fn filter_model<'a, T>(&'a self) -> impl Iterator<Item = (&'a Op, &'a T)> + 'a
+        where &'a T: TryFrom<&'a Model>,
+              T: 'a,
+    {
+        self.iter()
+            .filter_map(|modification| {
+                <&T>::try_from(modification.model()).ok()
+                    .map(|model| (modification.op(), model))
+            })
+    }
+
     /// Push a raw modification object into the mods list.
     pub(crate) fn push_raw(&mut self, modification: Modification) {
         self.modifications.push(modification);
@@ -179,6 +240,37 @@ impl Modifications {
     pub(crate) fn push<T: Into<Model>>(&mut self, op: Op, model: T) {
         self.push_raw(Modification::new(op, model.into()));
     }
+
+    /// Combine two modification sets into one, with the modifications in
+    /// `other` coming after the ones in `self`.
+    ///
+    /// This checks the combined set for ops that conflict with each other on
+    /// the same model: creating a model twice, creating a model that was
+    /// already updated, or doing anything at all to a model after it has been
+    /// deleted. If a conflict is found, we return an error describing it.
+    pub fn merge(self, other: Modifications) -> Result<Modifications> {
+        let mut merged = Self::new();
+        {
+            let mut last_ops: HashMap<(&'static str, String), Op> = HashMap::new();
+            for modification in self.modifications.iter().chain(other.modifications.iter()) {
+                let (ty, id) = modification.model.type_and_id();
+                let key = (ty, id.to_string());
+                let conflict = match (last_ops.get(&key), &modification.op) {
+                    (Some(Op::Delete), _) => Some("after delete"),
+                    (Some(_), Op::Create) => Some("on existing model"),
+                    _ => None,
+                };
+                if let Some(reason) = conflict {
+                    Err(Error::ModificationConflict(format!("{:?} {} {}({})", modification.op, reason, ty, id)))?;
+                }
+                last_ops.insert(key, modification.op.clone());
+            }
+        }
+        for modification in self.into_iter().chain(other) {
+            merged.push_raw(modification);
+        }
+        Ok(merged)
+    }
 }
 
 impl IntoIterator for Modifications {
@@ -195,6 +287,7 @@ mod tests {
     use super::*;
     use crate::{
         models::{
+            company::{Company, CompanyID},
             process::Process,
             user::{User, UserID},
         },
@@ -231,5 +324,80 @@ mod tests {
         let res = mods[0].clone().expect_op::<Process>(Op::Update);
         assert_eq!(res, Err(Error::OpMismatch));
     }
-}
 
+    #[test]
+    fn merge() {
+        let now = util::time::now();
+        let user = make_user(&UserID::new("slappy"), None, &now);
+        let user2 = make_user(&UserID::new("jerry"), None, &now);
+        let company = make_company(&CompanyID::new("slappy"), "slappy's widgets", &now);
+
+        let mods1 = Modifications::new_single(Op::Create, user.clone());
+        let mut mods2 = Modifications::new_single(Op::Update, user.clone());
+        mods2.push(Op::Create, company.clone());
+        let merged = mods1.clone().merge(mods2.clone()).unwrap().into_vec();
+        assert_eq!(merged.len(), 3);
+        merged[0].clone().expect_op::<User>(Op::Create).unwrap();
+        merged[1].clone().expect_op::<User>(Op::Update).unwrap();
+        merged[2].clone().expect_op::<Company>(Op::Create).unwrap();
+
+        // same id, different model types don't conflict
+        let merged = Modifications::new_single(Op::Delete, user.clone())
+            .merge(Modifications::new_single(Op::Update, company.clone()))
+            .unwrap();
+        assert_eq!(merged.into_vec().len(), 2);
+
+        let res = mods1.clone().merge(Modifications::new_single(Op::Create, user.clone()));
+        assert_eq!(res, Err(Error::ModificationConflict("Create on existing model User(slappy)".into())));
+        let res = mods2.clone().merge(Modifications::new_single(Op::Create, user.clone()));
+        assert_eq!(res, Err(Error::ModificationConflict("Create on existing model User(slappy)".into())));
+        let res = Modifications::new_single(Op::Delete, user.clone()).merge(Modifications::new_single(Op::Update, user.clone()));
+        assert_eq!(res, Err(Error::ModificationConflict("Update after delete User(slappy)".into())));
+        let res = Modifications::new_single(Op::Delete, user.clone()).merge(Modifications::new_single(Op::Delete, user.clone()));
+        assert_eq!(res, Err(Error::ModificationConflict("Delete after delete User(slappy)".into())));
+
+        // conflicts within a single set are caught as well
+        let mut mods3 = Modifications::new_single(Op::Delete, user2.clone());
+        mods3.push(Op::Update, user2.clone());
+        let res = mods3.merge(Modifications::new());
+        assert_eq!(res, Err(Error::ModificationConflict("Update after delete User(jerry)".into())));
+    }
+
+    #[test]
+    fn index_and_filter() {
+        let now = util::time::now();
+        let user = make_user(&UserID::new("slappy"), None, &now);
+        let user2 = make_user(&UserID::new("jerry"), None, &now);
+        let company = make_company(&CompanyID::new("slappy"), "slappy's widgets", &now);
+        let mut mods = Modifications::new_single(Op::Create, user.clone());
+        mods.push(Op::Create, company.clone());
+        mods.push(Op::Update, user.clone());
+        mods.push(Op::Delete, user2.clone());
+
+        let index = mods.index_by_id();
+        assert_eq!(index.len(), 3);
+        let ops = index.get(&("User", "slappy")).unwrap().iter()
+            .map(|x| x.op().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec![Op::Create, Op::Update]);
+        // same id, different model type
+        let ops = index.get(&("Company", "slappy")).unwrap().iter()
+            .map(|x| x.op().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec![Op::Create]);
+        assert_eq!(index.get(&("User", "jerry")).unwrap().len(), 1);
+        assert!(index.get(&("Process", "slappy")).is_none());
+
+        let users = mods.filter_model::<User>()
+            .map(|(op, user)| (op.clone(), user.id().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(users, vec![
+            (Op::Create, UserID::new("slappy")),
+            (Op::Update, UserID::new("slappy")),
+            (Op::Delete, UserID::new("jerry")),
+        ]);
+        let companies = mods.filter_model::<Company>().collect::<Vec<_>>();
+        assert_eq!(companies, vec![(&Op::Create, &company)]);
+        assert_eq!(mods.filter_model::<Process>().count(), 0);
+    }
+}
@@ -2,10 +2,68 @@
 //! are tracked by id in the cost tracking system, allowing only a set amount of
 //! job types to be accounted for (as opposed to using freeform entry).
 //!
-//! Note that occupations require global systemic management.
+//! Note that occupations require global systemic management. This includes an
+//! occupation's (optional) registered wage rate, which keeps companies from
+//! claiming arbitrary labor costs when recording work.
 
+use crate::{
+    error::{Error, Result},
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use vf_rs::vf;
 
+/// A registered range of hourly wages for an occupation.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct WageRate {
+    /// The lowest hourly wage for this occupation
+    min: Decimal,
+    /// The highest hourly wage for this occupation
+    max: Decimal,
+    /// How far (as a ratio, so `0.05` is 5%) claimed labor costs can fall
+    /// outside of the wage range before being rejected. This leaves room for
+    /// rounding and for small adjustments that don't warrant changing the
+    /// registered rate.
+    tolerance: Decimal,
+}
+
+impl WageRate {
+    /// Create a new wage rate. `min` must be non-negative and no greater than
+    /// `max`, and `tolerance` must be non-negative.
+    pub fn new(min: Decimal, max: Decimal, tolerance: Decimal) -> Result<Self> {
+        if min < Decimal::zero() {
+            Err(Error::InvalidAmount(min))?;
+        }
+        if max < min {
+            Err(Error::InvalidAmount(max))?;
+        }
+        if tolerance < Decimal::zero() {
+            Err(Error::InvalidAmount(tolerance))?;
+        }
+        Ok(Self { min, max, tolerance })
+    }
+
+    /// Create a wage rate with a single fixed hourly wage.
+    pub fn fixed(rate: Decimal, tolerance: Decimal) -> Result<Self> {
+        Self::new(rate, rate, tolerance)
+    }
+
+    /// Check that a wage claimed for some number of hours of labor is
+    /// consistent with this rate (within our tolerance).
+    pub fn check(&self, wage: &Decimal, hours: &Decimal) -> Result<()> {
+        let low = self.min * hours * (Decimal::one() - self.tolerance);
+        let high = self.max * hours * (Decimal::one() + self.tolerance);
+        if wage < &low || wage > &high {
+            Err(Error::WageRateMismatch)?;
+        }
+        Ok(())
+    }
+}
+
 basis_model! {
     /// The occupation model assigns an `OccupationID` to a job title and allows
     /// future-proof cost tracking of that job type.
@@ -14,7 +72,36 @@ basis_model! {
         /// The inner VF type which holds our `role_label` field used to hold
         /// the occupation name.
         inner: vf::AgentRelationshipRole,
+        /// The registered hourly wage range for this occupation. If set, labor
+        /// costs claimed in work events must be consistent with this rate.
+        wage_rate: Option<WageRate>,
     }
     OccupationBuilder
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wage_rate() {
+        let rate = WageRate::new(num!(20), num!(30), num!(0.1)).unwrap();
+        assert_eq!(rate.check(&num!(100), &num!(4)), Ok(()));
+        assert_eq!(rate.check(&num!(80), &num!(4)), Ok(()));
+        assert_eq!(rate.check(&num!(120), &num!(4)), Ok(()));
+        // within tolerance
+        assert_eq!(rate.check(&num!(72), &num!(4)), Ok(()));
+        assert_eq!(rate.check(&num!(132), &num!(4)), Ok(()));
+        assert_eq!(rate.check(&num!(71.99), &num!(4)), Err(Error::WageRateMismatch));
+        assert_eq!(rate.check(&num!(132.01), &num!(4)), Err(Error::WageRateMismatch));
+
+        let fixed = WageRate::fixed(num!(25), num!(0)).unwrap();
+        assert_eq!(fixed.check(&num!(50), &num!(2)), Ok(()));
+        assert_eq!(fixed.check(&num!(50.01), &num!(2)), Err(Error::WageRateMismatch));
+
+        assert_eq!(WageRate::new(num!(-1), num!(30), num!(0)), Err(Error::InvalidAmount(num!(-1))));
+        assert_eq!(WageRate::new(num!(20), num!(10), num!(0)), Err(Error::InvalidAmount(num!(10))));
+        assert_eq!(WageRate::new(num!(20), num!(30), num!(-0.1)), Err(Error::InvalidAmount(num!(-0.1))));
+    }
+}
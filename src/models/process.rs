@@ -1,17 +1,33 @@
 //! Processes are aggregators of costs via their inputs, labor and resources,
 //! and dividers/subtractors of costs via their outputs, resources and services.
+//!
+//! A process can be blocked on a set of commitments (for instance, the delivery
+//! of the steel it needs), in which case no work or resources can go into it
+//! until those commitments are fulfilled.
+//!
+//! Chains of processes can be checked for cost loops using the [graph] module,
+//! and a process' costs can be checked against its events using the [audit]
+//! module.
 
 use crate::{
     costs::{Costs, CostMover},
+    error::{Error, Result},
     models::{
+        commitment::CommitmentID,
         company::CompanyID,
         lib::agent::AgentID,
+        occupation::OccupationID,
         process_spec::ProcessSpecID,
     },
 };
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
 use url::Url;
 use vf_rs::vf;
 
+pub mod audit;
+pub mod graph;
+
 basis_model! {
     /// The `Process` model wraps the [vf::Process][vfprocess] object and adds
     /// cost tracking in. Processes are the places where inputs are transformed
@@ -32,10 +48,73 @@ basis_model! {
         company_id: CompanyID,
         /// Our costs tally for this process
         costs: Costs,
+        /// The labor (wages and hours) put directly into this process by work
+        /// events. This is tracked apart from `costs`, which also picks up
+        /// labor carried in by inputs from upstream processes.
+        labor_spent: Costs,
+        /// An optional cap on the labor this process can take on via work
+        /// events. Only the `labor` and `labor_hours` buckets are considered.
+        labor_budget: Option<Costs>,
+        /// Commitments that must be fulfilled before this process can take on
+        /// work or consume resources. Each one is removed once it's fulfilled.
+        blocked_on: Vec<CommitmentID>,
     }
     ProcessBuilder
 }
 
+impl Process {
+    /// Determine if the work put into this process has gone past its labor
+    /// budget.
+    ///
+    /// Only labor from work events (`labor_spent`) counts against the budget,
+    /// not labor carried in by inputs. The `labor` and `labor_hours` buckets
+    /// are checked per occupation, and only for occupations that have an entry
+    /// in that bucket of the budget (so a budget can cap one occupation's hours
+    /// without capping anyone's wages, for instance).
+    pub fn is_over_labor_budget(&self) -> bool {
+        let budget = match self.labor_budget() {
+            Some(budget) => budget,
+            None => return false,
+        };
+        let over = |spent: &HashMap<OccupationID, Decimal>, budget: &HashMap<OccupationID, Decimal>| {
+            budget.iter().any(|(occupation_id, max)| {
+                spent.get(occupation_id).unwrap_or(&Decimal::zero()) > max
+            })
+        };
+        over(self.labor_spent().labor(), budget.labor()) || over(self.labor_spent().labor_hours(), budget.labor_hours())
+    }
+
+    /// Record labor put directly into this process by a work event.
+    pub(crate) fn spend_labor(&mut self, labor: &Costs) {
+        self.set_labor_spent(self.labor_spent().clone() + labor.clone());
+    }
+
+    /// Whether this process is waiting on any commitments.
+    pub fn is_blocked(&self) -> bool {
+        !self.blocked_on().is_empty()
+    }
+
+    /// Return a `ProcessBlocked` error if this process is waiting on any
+    /// commitments.
+    pub fn check_blocked(&self) -> Result<()> {
+        if self.is_blocked() {
+            let ids = self.blocked_on().iter()
+                .map(|id| id.as_str())
+                .collect::<Vec<_>>();
+            Err(Error::ProcessBlocked(ids.join(", ")))?;
+        }
+        Ok(())
+    }
+
+    /// Remove a (fulfilled) commitment from the list this process is blocked
+    /// on, returning whether it was there.
+    pub(crate) fn unblock(&mut self, commitment_id: &CommitmentID) -> bool {
+        let len = self.blocked_on().len();
+        self.blocked_on_mut().retain(|id| id != commitment_id);
+        self.blocked_on().len() != len
+    }
+}
+
 impl CostMover for Process {
     fn costs(&self) -> &Costs {
         self.costs()
@@ -87,5 +166,53 @@ mod tests {
         process3.set_costs(Costs::new_with_labor("machinist", num!(23.2)));
         assert!(process1 == process3);
     }
-}
 
+    #[test]
+    fn labor_budget() {
+        let now = util::time::now();
+        let mut process = make_process(&ProcessID::create(), &CompanyID::create(), "make widgets", &Costs::new(), &now);
+        process.spend_labor(&Costs::new_with_labor("machinist", num!(100)));
+        assert_eq!(process.labor_spent(), &Costs::new_with_labor("machinist", num!(100)));
+        assert!(!process.is_over_labor_budget());
+
+        process.set_labor_budget(Some(Costs::new_with_labor("machinist", num!(100))));
+        assert!(!process.is_over_labor_budget());
+        process.spend_labor(&Costs::new_with_labor("machinist", num!(0.01)));
+        assert!(process.is_over_labor_budget());
+
+        // labor carried in by inputs doesn't count against the budget
+        process.set_labor_spent(Costs::new_with_labor("machinist", num!(50)));
+        process.set_costs(Costs::new_with_labor("machinist", num!(5000)));
+        assert!(!process.is_over_labor_budget());
+
+        // an occupation missing from the budget isn't capped
+        process.spend_labor(&Costs::new_with_labor("ceo", num!(1000)));
+        assert!(!process.is_over_labor_budget());
+
+        // budgeting hours doesn't budget wages
+        let mut spent = Costs::new_with_labor("machinist", num!(5000));
+        spent.track_labor_hours("machinist", num!(8));
+        process.set_labor_spent(spent);
+        process.set_labor_budget(Some(Costs::new_with_labor_hours("machinist", num!(10))));
+        assert!(!process.is_over_labor_budget());
+        process.spend_labor(&Costs::new_with_labor_hours("machinist", num!(2.5)));
+        assert!(process.is_over_labor_budget());
+    }
+
+    #[test]
+    fn blocked_on() {
+        let now = util::time::now();
+        let mut process = make_process(&ProcessID::create(), &CompanyID::create(), "make widgets", &Costs::new(), &now);
+        assert!(!process.is_blocked());
+        assert_eq!(process.check_blocked(), Ok(()));
+
+        process.set_blocked_on(vec![CommitmentID::new("steel"), CommitmentID::new("bolts")]);
+        assert!(process.is_blocked());
+        assert_eq!(process.check_blocked(), Err(Error::ProcessBlocked("steel, bolts".into())));
+        assert!(process.unblock(&CommitmentID::new("steel")));
+        assert!(!process.unblock(&CommitmentID::new("steel")));
+        assert_eq!(process.check_blocked(), Err(Error::ProcessBlocked("bolts".into())));
+        assert!(process.unblock(&CommitmentID::new("bolts")));
+        assert_eq!(process.check_blocked(), Ok(()));
+    }
+}
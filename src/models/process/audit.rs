@@ -0,0 +1,221 @@
+//! Auditing lets us check a process' running `costs` tally against the events
+//! that fed it.
+//!
+//! A process' costs should always equal the costs of everything that went into
+//! it minus the costs of everything that came out. Over the life of a
+//! long-running process, rounding and manual adjustments can cause the two to
+//! drift apart, and since `Event::process()` only ever sees one event at a time
+//! nothing catches it. Given the events that touched a process, [reconcile]
+//! recomputes what its costs *should* be and reports any drift.
+//!
+//! [reconcile]: fn.reconcile.html
+
+use crate::{
+    costs::{Costs, variance::CostVariance},
+    error::Result,
+    models::{
+        event::{Event, EventError},
+        lib::basis_model::Model,
+        process::Process,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::Unit;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use vf_rs::vf;
+
+/// The result of reconciling a process' costs against its events.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ReconciliationReport {
+    /// The costs the process should have, given its events
+    expected: Costs,
+    /// The costs the process actually has
+    actual: Costs,
+    /// The difference between the two (`actual - expected`), per cost bucket
+    drift: CostVariance,
+    /// Hours from work events that don't name the occupation they were worked
+    /// under (a worker's primary occupation, without a wage). These hours did
+    /// go into the process, but we can't say which occupation they went under
+    /// without the worker, so they aren't part of `expected`.
+    unattributed_hours: Decimal,
+}
+
+impl ReconciliationReport {
+    /// Returns true if the process' costs match its events exactly.
+    pub fn is_reconciled(&self) -> bool {
+        self.drift().is_on_plan()
+    }
+}
+
+/// Get the costs an input event added to a process.
+///
+/// Work events are special: the event processor adds the hours worked (from
+/// the event's `effort_quantity`) to the process along with the wage in the
+/// event's `move_costs`. Returns the costs, along with any hours we couldn't
+/// attribute to an occupation.
+fn input_costs(event: &Event) -> Result<(Costs, Decimal)> {
+    let move_costs = event.move_costs().clone().unwrap_or_default();
+    if event.inner().action() != &vf::Action::Work {
+        return Ok((move_costs, Decimal::zero()));
+    }
+    let hours = match event.inner().effort_quantity() {
+        Some(effort) if effort.has_unit() == &Unit::Hour => measure::to_decimal(effort)?,
+        _ => Decimal::zero(),
+    };
+    let occupation_id = move_costs.labor().keys()
+        .chain(move_costs.labor_hours().keys())
+        .next()
+        .cloned();
+    match occupation_id {
+        Some(occupation_id) => {
+            let mut costs = Costs::new();
+            costs.track_labor(occupation_id.clone(), move_costs.get_labor(occupation_id.clone()));
+            costs.track_labor_hours(occupation_id, hours);
+            Ok((costs, Decimal::zero()))
+        }
+        None => Ok((Costs::new(), hours)),
+    }
+}
+
+/// Reconcile a process' costs against the events that moved costs into it
+/// (`input_events`) and out of it (`output_events`).
+///
+/// Events that are deleted or still pending (started but not finished) never
+/// touched the process' costs, so they are skipped. Every other event must
+/// reference the process as its `input_of` (for inputs) or `output_of` (for
+/// outputs).
+///
+/// Note that processes that were created with some starting costs will show
+/// those costs as drift.
+pub fn reconcile(process: &Process, input_events: &[Event], output_events: &[Event]) -> Result<ReconciliationReport> {
+    let applied = |event: &&Event| {
+        let pending = event.inner().has_beginning().is_some() && event.inner().has_end().is_none();
+        !event.is_deleted() && !pending
+    };
+    let mut inputs = Costs::new();
+    let mut unattributed_hours = Decimal::zero();
+    for event in input_events.iter().filter(applied) {
+        if event.inner().input_of().as_ref() != Some(process.id()) {
+            Err(EventError::MismatchedInputProcessID)?;
+        }
+        let (costs, hours) = input_costs(event)?;
+        inputs = inputs + costs;
+        unattributed_hours += hours;
+    }
+    let mut outputs = Costs::new();
+    for event in output_events.iter().filter(applied) {
+        if event.inner().output_of().as_ref() != Some(process.id()) {
+            Err(EventError::MismatchedOutputProcessID)?;
+        }
+        outputs = outputs + event.move_costs().clone().unwrap_or_default();
+    }
+    let expected = inputs - outputs;
+    let actual = process.costs().clone();
+    let drift = CostVariance::from_costs(&expected, &actual);
+    Ok(ReconciliationReport {
+        expected,
+        actual,
+        drift,
+        unattributed_hours,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            event::EventID,
+            process::ProcessID,
+        },
+        util::{self, test::*},
+    };
+    use om2::Measure;
+
+    fn make_event(action: vf::Action, process: &Process, input: bool, costs: Option<Costs>, hours: Option<Decimal>) -> Event {
+        let now = util::time::now();
+        let process_id = Some(process.id().clone());
+        Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action)
+                    .effort_quantity(hours.map(|x| Measure::new(x, Unit::Hour)))
+                    .input_of(if input { process_id.clone() } else { None })
+                    .output_of(if input { None } else { process_id.clone() })
+                    .provider(process.company_id().clone())
+                    .receiver(process.company_id().clone())
+                    .build().unwrap()
+            )
+            .move_costs(costs)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn reconciles() {
+        let now = util::time::now();
+        let mut costs = Costs::new_with_labor("machinist", num!(100));
+        costs.track_labor_hours("machinist", num!(4));
+        costs.track_resource("steel", num!(6), num!(2));
+        costs.track_labor_hours("janitor", num!(2));
+        let mut process = make_process(&ProcessID::create(), &CompanyID::new("jerry's widgets"), "make widgets", &costs, &now);
+
+        let inputs = vec![
+            make_event(vf::Action::Work, &process, true, Some(Costs::new_with_labor("machinist", num!(100))), Some(num!(4))),
+            make_event(vf::Action::Consume, &process, true, Some(Costs::new_with_resource("steel", num!(10), num!(2))), None),
+            make_event(vf::Action::Work, &process, true, Some(Costs::new()), Some(num!(2))),
+        ];
+        let mut deleted = make_event(vf::Action::Consume, &process, true, Some(Costs::new_with_resource("steel", num!(50), num!(2))), None);
+        deleted.set_deleted(Some(now.clone()));
+        let mut pending = make_event(vf::Action::Work, &process, true, Some(Costs::new()), None);
+        pending.inner_mut().set_has_beginning(Some(now.clone()));
+        let outputs = vec![
+            make_event(vf::Action::Produce, &process, false, Some(Costs::new_with_resource("steel", num!(4), num!(2))), None),
+            deleted,
+        ];
+        let mut all_inputs = inputs.clone();
+        all_inputs.push(pending);
+
+        let report = reconcile(&process, &all_inputs, &outputs).unwrap();
+        let mut expected = Costs::new_with_labor("machinist", num!(100));
+        expected.track_labor_hours("machinist", num!(4));
+        expected.track_resource("steel", num!(6), num!(2));
+        assert_eq!(report.expected(), &expected);
+        assert_eq!(report.actual(), &costs);
+        assert_eq!(report.unattributed_hours(), &num!(2));
+        // the janitor's hours can't be attributed without the worker
+        assert!(!report.is_reconciled());
+        assert_eq!(report.drift().labor_hours().get(&"janitor".into()).unwrap().delta(), &num!(2));
+
+        // naming the occupation in the event clears things up
+        let mut inputs2 = inputs.clone();
+        inputs2[2].set_move_costs(Some(Costs::new_with_labor_hours("janitor", num!(2))));
+        let report = reconcile(&process, &inputs2, &outputs).unwrap();
+        assert_eq!(report.unattributed_hours(), &num!(0));
+        assert!(report.is_reconciled());
+
+        // drift shows up
+        let mut costs2 = costs.clone();
+        costs2.track_labor("machinist", num!(0.01));
+        process.set_costs(costs2);
+        let report = reconcile(&process, &inputs2, &outputs).unwrap();
+        assert!(!report.is_reconciled());
+        assert_eq!(report.drift().labor().get(&"machinist".into()).unwrap().delta(), &num!(0.01));
+
+        // events have to reference the process
+        let other = make_process(&ProcessID::create(), &CompanyID::new("jerry's widgets"), "make gears", &Costs::new(), &now);
+        let res = reconcile(&process, &vec![make_event(vf::Action::Consume, &other, true, None, None)], &[]);
+        assert_eq!(res, Err(EventError::MismatchedInputProcessID.into()));
+        let res = reconcile(&process, &[], &inputs2);
+        assert_eq!(res, Err(EventError::MismatchedOutputProcessID.into()));
+    }
+}
@@ -0,0 +1,275 @@
+//! The process graph describes how costs flow between processes (and the
+//! resources sitting between them) based on the events connecting them.
+//!
+//! Costs should always flow "downstream." If a set of processes are chained
+//! together such that costs loop back into a process they've already passed
+//! through, those costs get trapped: they circle forever and never make it into
+//! an output. `Event::process()` only sees one event at a time so it can't
+//! catch this, but given a set of processes and events we can build the graph,
+//! look for cycles, and check whether a *new* event would create one before it
+//! is saved.
+//!
+//! The graph can also point out processes that are finished but still hold
+//! costs with no outgoing flows, meaning those costs have nowhere to go.
+//!
+//! Note that some actions move a resource into a process only for it to come
+//! back out again (`accept`/`modify` for repairs, `pickup`/`dropoff` for
+//! deliveries). These aren't cost loops, so `accept` and `pickup` don't add an
+//! edge into the process: the resource's costs are accounted for when it comes
+//! back out.
+
+use crate::{
+    error::{Error, Result},
+    models::{
+        event::Event,
+        lib::basis_model::Model,
+        process::{Process, ProcessID},
+        resource::ResourceID,
+    },
+};
+use std::collections::{HashMap, HashSet};
+use vf_rs::vf;
+
+/// A node in our process graph.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Node {
+    /// A process
+    Process(ProcessID),
+    /// A resource (generally an output of one process and an input of another)
+    Resource(ResourceID),
+}
+
+/// A directed graph of cost flows between processes and resources.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProcessGraph {
+    /// Maps each node to the nodes its costs flow into
+    edges: HashMap<Node, HashSet<Node>>,
+    /// The processes we were built with
+    processes: HashMap<ProcessID, Process>,
+}
+
+impl ProcessGraph {
+    /// Build a graph from a set of processes and the events connecting them.
+    /// Deleted events are ignored.
+    pub fn build(processes: &[Process], events: &[Event]) -> Self {
+        let mut graph = Self {
+            edges: HashMap::new(),
+            processes: processes.iter().map(|p| (p.id().clone(), p.clone())).collect(),
+        };
+        for event in events {
+            if event.is_deleted() {
+                continue;
+            }
+            graph.add_event(event);
+        }
+        graph
+    }
+
+    /// Determine which edges an event adds to the graph.
+    fn edges_for(event: &Event) -> Vec<(Node, Node)> {
+        let inner = event.inner();
+        let process_out = inner.output_of().clone().map(Node::Process);
+        let process_in = inner.input_of().clone().map(Node::Process);
+        let resource = inner.resource_inventoried_as().clone().map(Node::Resource);
+        let resource_to = inner.to_resource_inventoried_as().clone().map(Node::Resource);
+        let round_trip = matches!(inner.action(), vf::Action::Accept | vf::Action::Pickup);
+        let mut edges = vec![];
+        match (process_out, process_in) {
+            (Some(from), Some(to)) => edges.push((from, to)),
+            (Some(from), None) => {
+                if let Some(resource) = resource.clone() {
+                    edges.push((from, resource));
+                }
+            }
+            (None, Some(to)) => {
+                if let (Some(resource), false) = (resource.clone(), round_trip) {
+                    edges.push((resource, to));
+                }
+            }
+            (None, None) => {}
+        }
+        if let (Some(from), Some(to)) = (resource, resource_to) {
+            edges.push((from, to));
+        }
+        edges
+    }
+
+    /// Add an event's edges to the graph.
+    fn add_event(&mut self, event: &Event) {
+        for (from, to) in Self::edges_for(event) {
+            self.edges.entry(from).or_default().insert(to);
+        }
+    }
+
+    /// Get the nodes a node's costs flow into.
+    pub fn outputs(&self, node: &Node) -> Vec<&Node> {
+        self.edges.get(node)
+            .map(|outputs| outputs.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Find a path from one node to another, if one exists.
+    fn path(&self, from: &Node, to: &Node) -> Option<Vec<Node>> {
+        let mut stack = vec![vec![from.clone()]];
+        let mut seen = HashSet::new();
+        while let Some(path) = stack.pop() {
+            let last = path.last().unwrap().clone();
+            if &last == to && path.len() > 1 {
+                return Some(path);
+            }
+            if !seen.insert(last.clone()) {
+                continue;
+            }
+            for next in self.outputs(&last) {
+                let mut path2 = path.clone();
+                path2.push(next.clone());
+                stack.push(path2);
+            }
+        }
+        None
+    }
+
+    /// Find a cycle in the graph, if there is one. The cycle is returned as a
+    /// list of nodes, where the first and last node are the same.
+    pub fn find_cycle(&self) -> Option<Vec<Node>> {
+        for node in self.edges.keys() {
+            if let Some(path) = self.path(node, node) {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    /// Returns true if there are no cycles in the graph.
+    pub fn is_acyclic(&self) -> bool {
+        self.find_cycle().is_none()
+    }
+
+    /// Check whether adding an event to the graph would create a cycle,
+    /// returning `Error::ProcessCycle` if so.
+    pub fn check_event(&self, event: &Event) -> Result<()> {
+        for (from, to) in Self::edges_for(event) {
+            if from == to || self.path(&to, &from).is_some() {
+                Err(Error::ProcessCycle)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find processes that are finished (or deleted) but still hold costs, and
+    /// have no outgoing flows for those costs to leave through.
+    pub fn orphaned_sinks(&self) -> Vec<&Process> {
+        self.processes.values()
+            .filter(|process| process.inner().finished() == &Some(true) || process.is_deleted())
+            .filter(|process| !process.costs().is_zero())
+            .filter(|process| self.outputs(&Node::Process(process.id().clone())).is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            event::{EventID, MoveType},
+        },
+        util::{self, test::*},
+    };
+
+    fn make_event(action: vf::Action, output_of: Option<&str>, input_of: Option<&str>, resource: Option<&str>, resource_to: Option<&str>) -> Event {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let mut event = Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action.clone())
+                    .has_point_in_time(now.clone())
+                    .input_of(input_of.map(|x| x.into()))
+                    .output_of(output_of.map(|x| x.into()))
+                    .provider(company_id.clone())
+                    .receiver(company_id.clone())
+                    .resource_inventoried_as(resource.map(|x| x.into()))
+                    .to_resource_inventoried_as(resource_to.map(|x| x.into()))
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        if action == vf::Action::Move && input_of.is_some() {
+            event.set_move_type(Some(MoveType::ProcessCosts));
+        }
+        event
+    }
+
+    fn process_node(id: &str) -> Node {
+        Node::Process(id.into())
+    }
+
+    #[test]
+    fn builds_and_finds_cycles() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let mut smelt = make_process(&"smelt".into(), &company_id, "smelt", &Costs::new(), &now);
+        let mut cast = make_process(&"cast".into(), &company_id, "cast", &Costs::new_with_labor("caster", 20), &now);
+        cast.inner_mut().set_finished(Some(true));
+        let finish = make_process(&"finish".into(), &company_id, "finish", &Costs::new_with_labor("polisher", 20), &now);
+        let events = vec![
+            make_event(vf::Action::Produce, Some("smelt"), None, Some("ingot"), None),
+            make_event(vf::Action::Consume, None, Some("cast"), Some("ingot"), None),
+            make_event(vf::Action::Move, Some("cast"), Some("finish"), None, None),
+            // repairs go in and come back out, which isn't a cycle
+            make_event(vf::Action::Accept, None, Some("smelt"), Some("crucible"), None),
+            make_event(vf::Action::Modify, Some("smelt"), None, Some("crucible"), None),
+        ];
+        let graph = ProcessGraph::build(&vec![smelt.clone(), cast.clone(), finish.clone()], &events);
+        assert_eq!(graph.outputs(&process_node("smelt")).len(), 2);
+        assert_eq!(graph.outputs(&Node::Resource("ingot".into())), vec![&process_node("cast")]);
+        assert_eq!(graph.outputs(&process_node("cast")), vec![&process_node("finish")]);
+        assert!(graph.is_acyclic());
+
+        // finish -> smelt would loop costs back to the start
+        let bad = make_event(vf::Action::Move, Some("finish"), Some("smelt"), None, None);
+        assert_eq!(graph.check_event(&bad), Err(Error::ProcessCycle));
+        let selfloop = make_event(vf::Action::Move, Some("finish"), Some("finish"), None, None);
+        assert_eq!(graph.check_event(&selfloop), Err(Error::ProcessCycle));
+        let fine = make_event(vf::Action::Produce, Some("finish"), None, Some("widget"), None);
+        assert_eq!(graph.check_event(&fine), Ok(()));
+
+        let mut events2 = events.clone();
+        events2.push(bad.clone());
+        let graph2 = ProcessGraph::build(&vec![smelt.clone(), cast.clone(), finish.clone()], &events2);
+        assert!(!graph2.is_acyclic());
+        let cycle = graph2.find_cycle().unwrap();
+        assert_eq!(cycle.first(), cycle.last());
+        assert!(cycle.contains(&process_node("cast")));
+        assert!(cycle.contains(&process_node("finish")));
+
+        // deleted events don't count
+        let mut events3 = events.clone();
+        let mut bad_deleted = bad.clone();
+        bad_deleted.set_deleted(Some(now.clone()));
+        events3.push(bad_deleted);
+        assert!(ProcessGraph::build(&vec![], &events3).is_acyclic());
+
+        // transfers between resources count as well
+        let mut events4 = events.clone();
+        events4.push(make_event(vf::Action::Produce, Some("finish"), None, Some("widget"), None));
+        events4.push(make_event(vf::Action::Transfer, None, None, Some("widget"), Some("ingot")));
+        assert!(!ProcessGraph::build(&vec![], &events4).is_acyclic());
+
+        // cast is finished and its costs flow to finish. finish isn't done yet.
+        assert_eq!(graph.orphaned_sinks().len(), 0);
+        let mut finish2 = finish.clone();
+        finish2.inner_mut().set_finished(Some(true));
+        smelt.inner_mut().set_finished(Some(true));
+        let graph3 = ProcessGraph::build(&vec![smelt.clone(), cast.clone(), finish2.clone()], &events);
+        let sinks = graph3.orphaned_sinks();
+        assert_eq!(sinks.len(), 1);
+        assert_eq!(sinks[0].id(), finish2.id());
+    }
+}
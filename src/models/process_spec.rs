@@ -6,14 +6,107 @@
 //! Process specifications can also contain resource transformations (such as
 //! turning iron into steel). In effect, the transformation acts to *consume*
 //! the input resource, whereas in most cases processes just move resources.
+//!
+//! A company can publish its process specs to a shared catalog, so other
+//! companies can [fork][1] them: copy the spec (and its recipe) into their own
+//! company, with a link back to where it came from.
+//!
+//! [1]: ../../transactions/process_spec/fn.fork.html
 
+use chrono::{DateTime, Utc};
 use crate::{
     models::{
         company::CompanyID,
+        occupation::OccupationID,
+        resource_spec::ResourceSpecID,
     },
 };
+use getset::{Getters, Setters};
+use om2::Measure;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use vf_rs::vf;
 
+/// A resource that goes into or comes out of a recipe, for instance "2kg of
+/// steel" or "1 widget."
+#[derive(Clone, Debug, PartialEq, Getters, Setters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub", set = "pub(crate)")]
+pub struct RecipeResource {
+    /// The resource spec of the input/output
+    resource_spec_id: ResourceSpecID,
+    /// How much of the resource we expect
+    quantity: Measure,
+}
+
+impl RecipeResource {
+    /// Create a new recipe resource
+    pub fn new<T: Into<ResourceSpecID>>(resource_spec_id: T, quantity: Measure) -> Self {
+        Self {
+            resource_spec_id: resource_spec_id.into(),
+            quantity,
+        }
+    }
+}
+
+/// Labor that goes into a recipe, for instance "3 hours of machinist."
+#[derive(Clone, Debug, PartialEq, Getters, Setters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub", set = "pub(crate)")]
+pub struct RecipeLabor {
+    /// The occupation performing the labor
+    occupation_id: OccupationID,
+    /// How many hours of labor we expect
+    hours: Decimal,
+}
+
+impl RecipeLabor {
+    /// Create a new recipe labor entry
+    pub fn new<T: Into<OccupationID>, H: Into<Decimal>>(occupation_id: T, hours: H) -> Self {
+        Self {
+            occupation_id: occupation_id.into(),
+            hours: hours.into(),
+        }
+    }
+}
+
+/// A bill of materials for a process spec: what goes in (resources and labor)
+/// and what comes out. This allows a process spec to act as a template for
+/// planning processes.
+#[derive(Clone, Debug, Default, PartialEq, Getters, Setters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub", set = "pub(crate)")]
+pub struct Recipe {
+    /// The resources we expect to consume
+    inputs: Vec<RecipeResource>,
+    /// The labor we expect to perform
+    labor: Vec<RecipeLabor>,
+    /// The resources we expect to produce
+    outputs: Vec<RecipeResource>,
+}
+
+impl Recipe {
+    /// Create a new recipe
+    pub fn new(inputs: Vec<RecipeResource>, labor: Vec<RecipeLabor>, outputs: Vec<RecipeResource>) -> Self {
+        Self {
+            inputs,
+            labor,
+            outputs,
+        }
+    }
+
+    /// The total number of items (inputs, labor, and outputs) in this recipe.
+    pub fn len(&self) -> usize {
+        self.inputs().len() + self.labor().len() + self.outputs().len()
+    }
+
+    /// Returns true if this recipe has no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 basis_model! {
     /// The `ProcessSpec` model 
     pub struct ProcessSpec {
@@ -22,6 +115,18 @@ basis_model! {
         inner: vf::ProcessSpecification,
         /// The company this process spec belongs to
         company_id: CompanyID,
+        /// The expected inputs/outputs of processes based on this spec
+        recipe: Option<Recipe>,
+        /// How many hours we expect a process based on this spec to take
+        estimated_duration: Option<Decimal>,
+        /// The maximum number of processes based on this spec that can run at
+        /// the same time (for instance, the number of kilns we have)
+        capacity: Option<u32>,
+        /// When this spec was published to the shared catalog. Unpublished
+        /// specs can't be forked by other companies.
+        published: Option<DateTime<Utc>>,
+        /// The spec this one was forked from, if any
+        forked_from: Option<ProcessSpecID>,
         // TODO: implement some concept of a known transformation (ie, refining
         // crude oil)
         //resource_transform: Option<ResourceTransformProcessID>,
@@ -29,3 +134,10 @@ basis_model! {
     ProcessSpecBuilder
 }
 
+impl ProcessSpec {
+    /// Whether or not this spec is published to the shared catalog.
+    pub fn is_published(&self) -> bool {
+        self.published().is_some()
+    }
+}
+
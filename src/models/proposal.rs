@@ -0,0 +1,113 @@
+//! A proposal is something put up for a vote, either systemically, within a
+//! specific company, or within a specific region. Members cast [ballots][1] on a proposal, and once voting
+//! closes the ballots are tallied against the proposal's rules to determine
+//! the outcome.
+//!
+//! Note that a proposal doesn't *do* anything when it passes. It's up to the
+//! implementation to act on the outcome (for instance, by running transactions
+//! using a [voting user][2]).
+//!
+//! [1]: ../ballot/index.html
+//! [2]: ../../system/vote/struct.Vote.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    error::{Error, Result},
+    models::{
+        company::CompanyID,
+        lib::basis_model::Model,
+        region::RegionID,
+        user::UserID,
+    },
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// Who a proposal applies to (and therefor who can vote on it).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum VoteScope {
+    /// A vote open to all users of the system
+    Systemic,
+    /// A vote open to the members of a single company
+    Company(CompanyID),
+    /// A vote open to the users living in a single region
+    Region(RegionID),
+}
+
+/// The rules a proposal's votes are tallied by.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct VoteRules {
+    /// The share of yes votes (out of yes + no votes) that must be *exceeded*
+    /// for a proposal to pass. `0.5` is a simple majority.
+    threshold: Decimal,
+    /// The share of eligible voters that must cast a ballot (including
+    /// abstentions) for the vote to count.
+    quorum: Decimal,
+}
+
+impl VoteRules {
+    /// Create a new set of voting rules. Both `threshold` and `quorum` must be
+    /// between 0 and 1.
+    pub fn new(threshold: Decimal, quorum: Decimal) -> Result<Self> {
+        for val in &[threshold, quorum] {
+            if val < &Decimal::zero() || val > &Decimal::one() {
+                Err(Error::InvalidAmount(val.clone()))?;
+            }
+        }
+        Ok(Self { threshold, quorum })
+    }
+
+    /// A simple majority with no quorum.
+    pub fn simple_majority() -> Self {
+        Self {
+            threshold: num!(0.5),
+            quorum: Decimal::zero(),
+        }
+    }
+}
+
+/// The final outcome of a proposal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum ProposalOutcome {
+    /// The proposal passed
+    Passed,
+    /// The proposal did not pass
+    Failed,
+    /// Not enough voters cast ballots for the vote to count
+    NoQuorum,
+}
+
+basis_model! {
+    /// The `Proposal` model describes something being voted on.
+    pub struct Proposal {
+        id: <<ProposalID>>,
+        /// The user that put this proposal forward
+        proposer: UserID,
+        /// Who this proposal applies to
+        scope: VoteScope,
+        /// The proposal's name
+        name: String,
+        /// A description of what's being proposed
+        description: String,
+        /// The rules used to tally votes
+        rules: VoteRules,
+        /// When voting closes
+        closes: DateTime<Utc>,
+        /// The outcome of the vote, set once the proposal is closed
+        outcome: Option<ProposalOutcome>,
+    }
+    ProposalBuilder
+}
+
+impl Proposal {
+    /// Determine if this proposal is accepting votes at the given time.
+    pub fn is_open(&self, now: &DateTime<Utc>) -> bool {
+        self.is_active() && self.outcome().is_none() && now < self.closes()
+    }
+}
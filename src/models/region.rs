@@ -0,0 +1,86 @@
+//! A region is a geographic area that resources can be tagged with. Regions
+//! let us track how quickly the resources in a given area are being extracted
+//! versus how quickly they are renewed or recycled (see the [depletion][1]
+//! module).
+//!
+//! Regions can also be given a location (generally their center), which lets
+//! us estimate the distance between them for [shipping][2].
+//!
+//! Note that regions require global systemic management.
+//!
+//! [1]: ../../costs/depletion/index.html
+//! [2]: ../../costs/logistics/index.html
+
+use rust_decimal::prelude::*;
+use vf_rs::geo::SpatialThing;
+
+/// The mean radius of the earth, in kilometres.
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+basis_model! {
+    /// The `Region` model names a geographic area resources can belong to.
+    pub struct Region {
+        id: <<RegionID>>,
+        /// The region's name
+        name: String,
+        /// A description of the area this region covers
+        description: String,
+        /// A point representing the region's location (generally its center).
+        /// Only the latitude and longitude are used.
+        location: Option<SpatialThing>,
+    }
+    RegionBuilder
+}
+
+impl Region {
+    /// Estimate the distance (in kilometres, rounded to the metre) between
+    /// this region and another, going by the great-circle distance between
+    /// their locations. Returns `None` if either region is missing a latitude
+    /// or longitude.
+    pub fn distance_km(&self, other: &Region) -> Option<Decimal> {
+        let coords = |region: &Region| -> Option<(f64, f64)> {
+            let location = region.location().as_ref()?;
+            Some(((*location.lat())?.to_radians(), (*location.long())?.to_radians()))
+        };
+        let (lat1, long1) = coords(self)?;
+        let (lat2, long2) = coords(other)?;
+        let a = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((long2 - long1) / 2.0).sin().powi(2);
+        let km = 2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin();
+        Decimal::from_f64(km).map(|x| x.round_dp(3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    fn make_located(id: &str, lat: Option<f64>, long: Option<f64>) -> Region {
+        let now = util::time::now();
+        Region::builder()
+            .id(RegionID::new(id))
+            .name(id)
+            .description(id)
+            .location(Some(SpatialThing::builder().lat(lat).long(long).build().unwrap()))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn distance() {
+        let seattle = make_located("seattle", Some(47.6062), Some(-122.3321));
+        let portland = make_located("portland", Some(45.5152), Some(-122.6784));
+        let nowhere = make_located("nowhere", None, Some(-122.6784));
+        let km = seattle.distance_km(&portland).unwrap();
+        assert!(km > num!(233) && km < num!(235));
+        assert_eq!(portland.distance_km(&seattle), Some(km));
+        assert_eq!(seattle.distance_km(&seattle), Some(num!(0)));
+        assert_eq!(seattle.distance_km(&nowhere), None);
+        let mut unlocated = seattle.clone();
+        unlocated.set_location(None);
+        assert_eq!(unlocated.distance_km(&portland), None);
+    }
+}
+
@@ -4,19 +4,156 @@
 //! instance of a *resource specification*. When you look at a chair on Wamazon,
 //! the page describes a resource specification. When the chair is shipped to
 //! you, what you get is a resource (a manifestation of the chair specification).
+//!
+//! Resources can optionally carry lot information (which batch they came from
+//! and when they expire) via the VF resource's `lot` field. This gives us
+//! traceability for recalls, and expired lots are refused by the consume and
+//! transfer transactions.
+//!
+//! When an event moves a resource to a new location, the move is also recorded
+//! in the resource's [location history][history], so logistics chains can be
+//! reconstructed after the fact. Only the most recent
+//! [LOCATION_HISTORY_MAX] moves are kept.
+//!
+//! [history]: struct.LocationChange.html
+//! [LOCATION_HISTORY_MAX]: constant.LOCATION_HISTORY_MAX.html
 
+use chrono::{DateTime, Utc};
 use crate::{
     costs::{Costs, CostMover},
+    error::{Error, Result},
     models::{
+        event::EventID,
         lib::agent::AgentID,
         process::ProcessID,
+        region::RegionID,
         resource_spec::ResourceSpecID,
     },
-    util::measure,
+    util::{
+        measure,
+        number::Ratio,
+    },
 };
-use om2::Unit;
+use getset::{Getters, Setters};
+use om2::{Measure, Unit};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use url::Url;
-use vf_rs::vf;
+use vf_rs::{vf, dfc, geo::SpatialThing};
+
+/// How many location changes a resource remembers. Once its history is full,
+/// the oldest changes are dropped.
+pub const LOCATION_HISTORY_MAX: usize = 32;
+
+/// Describes how the costs of a resource are spread out over its lifetime.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum AmortizationMethod {
+    /// Each unit of use moves an equal share of the resource's original costs.
+    Linear,
+    /// Each unit of use moves a fixed share (`factor / lifetime`) of the
+    /// resource's *remaining* costs, so earlier uses carry more of the costs
+    /// than later ones. A factor of 2 gives "double-declining balance."
+    DecliningBalance(Decimal),
+}
+
+/// An amortization schedule for a resource, allowing the costs of something
+/// like a 3D printer to be moved into processes bit by bit as it's used.
+///
+/// The lifetime can be expressed in any unit: hours of use (`Unit::Hour`),
+/// number of uses (`Unit::One`), etc. Effort passed in must use the same unit.
+#[derive(Clone, Debug, PartialEq, Getters, Setters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub", set = "pub(crate)")]
+pub struct Amortization {
+    /// The projected lifetime of the resource
+    lifetime: Measure,
+    /// How much of the lifetime has been used so far
+    used: Measure,
+    /// How we amortize
+    method: AmortizationMethod,
+}
+
+impl Amortization {
+    /// Create a new amortization schedule with no usage
+    pub fn new(lifetime: Measure, method: AmortizationMethod) -> Self {
+        let mut used = lifetime.clone();
+        measure::set_zero(&mut used);
+        Self {
+            lifetime,
+            used,
+            method,
+        }
+    }
+
+    /// Given some amount of effort (hours, uses, etc), determine what ratio of
+    /// the resource's *current* costs should be moved.
+    pub fn ratio_for(&self, effort: &Measure) -> Result<Ratio> {
+        let effort = measure::to_decimal(&measure::convert(effort, self.lifetime().has_unit())?)?;
+        if effort < Decimal::zero() {
+            Err(Error::NegativeMeasurement)?;
+        }
+        let lifetime = measure::to_decimal(self.lifetime())?;
+        let ratio = match self.method() {
+            AmortizationMethod::Linear => {
+                let remaining = lifetime - measure::to_decimal(self.used())?;
+                if remaining <= Decimal::zero() {
+                    num!(1)
+                } else {
+                    effort / remaining
+                }
+            }
+            AmortizationMethod::DecliningBalance(factor) => {
+                if lifetime <= Decimal::zero() {
+                    num!(1)
+                } else {
+                    (factor.clone() * effort) / lifetime
+                }
+            }
+        };
+        Ratio::new(ratio.min(num!(1)).max(Decimal::zero()))
+    }
+
+    /// Record some effort against this schedule's lifetime.
+    pub(crate) fn record_use(&mut self, effort: &Measure) -> Result<()> {
+        measure::inc_measure(&mut self.used, effort)?;
+        Ok(())
+    }
+}
+
+/// Determine if a lot has expired as of the given time. Lots without an expiry
+/// date never expire.
+pub fn is_lot_expired(lot: &dfc::ProductBatch, now: &DateTime<Utc>) -> bool {
+    match lot.expiry_date() {
+        Some(expires) => expires <= now,
+        None => false,
+    }
+}
+
+/// A record of an event moving a resource to a new location.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct LocationChange {
+    /// Where the resource was moved to
+    location: SpatialThing,
+    /// The event that moved the resource
+    event_id: EventID,
+    /// When the move was processed
+    changed: DateTime<Utc>,
+}
+
+impl LocationChange {
+    /// Create a new location change record
+    pub fn new(location: SpatialThing, event_id: EventID, changed: DateTime<Utc>) -> Self {
+        Self {
+            location,
+            event_id,
+            changed,
+        }
+    }
+}
 
 basis_model! {
     /// The resource model. Wraps the [vf::Resource][vfresource] object, and
@@ -36,6 +173,35 @@ basis_model! {
         /// and we have a measure of 16g, the `5 hours` cost encompasses all
         /// 16g.
         costs: Costs,
+        /// An optional amortization schedule, used to move this resource's
+        /// costs into processes as the resource is used.
+        amortization: Option<Amortization>,
+        /// The region this resource is located in, if any. Tagging a resource
+        /// with a region lets us track its depletion vs renewal (generally
+        /// this is used for raw or semi-raw materials).
+        region_id: Option<RegionID>,
+        /// Whether this resource's quantities are allowed to go below zero.
+        /// Generally a resource can't give up more than it has, but some
+        /// workflows (like backordering) intentionally dip into negative
+        /// inventory for a while.
+        #[builder(default)]
+        #[cfg_attr(feature = "with_serde", serde(default))]
+        allow_negative: bool,
+        /// The quantity of this resource being held for pending transfers.
+        /// Held quantity stays on the sender's books but can't be moved or
+        /// used up until the transfer is accepted, rejected, or cancelled.
+        held: Option<Measure>,
+        /// If this resource was [bootstrapped][1], the spec whose default
+        /// costs it was seeded with. Resources with this set carry *estimated*
+        /// costs rather than costs tracked through the network.
+        ///
+        /// [1]: ../../transactions/resource/fn.bootstrap.html
+        estimated_from: Option<ResourceSpecID>,
+        /// The moves events have made to this resource's location, oldest
+        /// first. Holds at most [LOCATION_HISTORY_MAX] entries.
+        ///
+        /// [LOCATION_HISTORY_MAX]: constant.LOCATION_HISTORY_MAX.html
+        location_history: Vec<LocationChange>,
     }
     ResourceBuilder
 }
@@ -47,12 +213,85 @@ impl Resource {
             .map(|measure| measure.has_unit().clone())
     }
 
+    /// Whether this resource's costs are estimated (seeded from its spec's
+    /// default costs) instead of tracked.
+    pub fn is_estimated(&self) -> bool {
+        self.estimated_from().is_some()
+    }
+
+    /// Make sure this resource isn't from an expired lot (the `lot` of its VF
+    /// resource).
+    pub fn check_lot(&self, now: &DateTime<Utc>) -> Result<()> {
+        match self.inner().lot() {
+            Some(lot) if is_lot_expired(lot, now) => Err(Error::LotExpired(lot.batch_number().clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Hold some quantity of this resource for a pending transfer. Fails if
+    /// the resource doesn't have enough unheld quantity to cover the hold.
+    pub(crate) fn hold(&mut self, hold: &Measure) -> Result<()> {
+        let mut held = measure::unwrap_or_zero(self.held(), hold);
+        measure::inc_measure(&mut held, hold)?;
+        let previous = self.held().clone();
+        self.set_held(Some(held));
+        if let Err(e) = self.check_held() {
+            self.set_held(previous);
+            Err(e)?;
+        }
+        Ok(())
+    }
+
+    /// Release a hold placed by [hold][Resource::hold].
+    pub(crate) fn release(&mut self, hold: &Measure) -> Result<()> {
+        let mut held = measure::unwrap_or_zero(self.held(), hold);
+        measure::dec_measure(&mut held, hold)?;
+        if held.has_numerical_value().is_zero() {
+            self.set_held(None);
+        } else {
+            self.set_held(Some(held));
+        }
+        Ok(())
+    }
+
+    /// Make sure this resource's accounting quantity covers the quantity
+    /// being held on it (resources allowed to go negative always pass).
+    pub fn check_held(&self) -> Result<()> {
+        let held = match self.held() {
+            Some(held) if !self.allow_negative() => held,
+            _ => return Ok(()),
+        };
+        let mut remaining = measure::unwrap_or_zero(self.inner().accounting_quantity(), held);
+        match measure::dec_measure(&mut remaining, held) {
+            Err(Error::NegativeMeasurement) => Err(Error::ResourceQuantityHeld),
+            res => res.map(|_| ()),
+        }
+    }
+
+    /// Move this resource to a new location, recording the move in its
+    /// location history (and dropping the oldest entry if the history is
+    /// full). Moving a resource to where it already is does nothing.
+    pub(crate) fn move_to(&mut self, location: SpatialThing, event_id: EventID, now: &DateTime<Utc>) {
+        if self.inner().current_location().as_ref() == Some(&location) {
+            return;
+        }
+        self.inner_mut().set_current_location(Some(location.clone()));
+        let history = self.location_history_mut();
+        history.push(LocationChange::new(location, event_id, now.clone()));
+        if history.len() > LOCATION_HISTORY_MAX {
+            let excess = history.len() - LOCATION_HISTORY_MAX;
+            history.drain(0..excess);
+        }
+    }
+
     /// Zero out the accounting/onhand quantity measurements for this resource.
     pub fn zero_measures(&mut self) {
-        self.inner_mut().accounting_quantity_mut().as_mut()
-            .map(|x| measure::set_zero(x));
-        self.inner_mut().onhand_quantity_mut().as_mut()
-            .map(|x| measure::set_zero(x));
+        if let Some(x) = self.inner_mut().accounting_quantity_mut().as_mut() {
+            measure::set_zero(x);
+        }
+        if let Some(x) = self.inner_mut().onhand_quantity_mut().as_mut() {
+            measure::set_zero(x);
+        }
     }
 }
 
@@ -125,5 +364,80 @@ mod tests {
 
 
     }
-}
 
+    #[test]
+    fn amortization_linear() {
+        let mut amortization = Amortization::new(Measure::new(num!(1000), Unit::Hour), AmortizationMethod::Linear);
+        assert_eq!(amortization.used(), &Measure::new(num!(0), Unit::Hour));
+        let ratio = amortization.ratio_for(&Measure::new(num!(3), Unit::Hour)).unwrap();
+        assert_eq!(ratio.inner(), &num!(0.003));
+        amortization.record_use(&Measure::new(num!(3), Unit::Hour)).unwrap();
+        // 3 hours out of the remaining 997 moves the same amount of the
+        // original costs as the first 3 hours did
+        let ratio = amortization.ratio_for(&Measure::new(num!(3), Unit::Hour)).unwrap();
+        assert_eq!(ratio.inner(), &(num!(3) / num!(997)));
+        let ratio = amortization.ratio_for(&Measure::new(num!(2000), Unit::Hour)).unwrap();
+        assert_eq!(ratio.inner(), &num!(1));
+        amortization.record_use(&Measure::new(num!(997), Unit::Hour)).unwrap();
+        let ratio = amortization.ratio_for(&Measure::new(num!(1), Unit::Hour)).unwrap();
+        assert_eq!(ratio.inner(), &num!(1));
+
+        let res = amortization.ratio_for(&Measure::new(num!(3), Unit::One));
+        assert_eq!(res, Err(Error::MeasureUnitsMismatched));
+        let res = amortization.ratio_for(&Measure::new(num!(-3), Unit::Hour));
+        assert_eq!(res, Err(Error::NegativeMeasurement));
+    }
+
+    #[test]
+    fn amortization_declining() {
+        let amortization = Amortization::new(Measure::new(num!(500), Unit::One), AmortizationMethod::DecliningBalance(num!(2)));
+        let ratio = amortization.ratio_for(&Measure::new(num!(10), Unit::One)).unwrap();
+        assert_eq!(ratio.inner(), &num!(0.04));
+        let ratio = amortization.ratio_for(&Measure::new(num!(300), Unit::One)).unwrap();
+        assert_eq!(ratio.inner(), &num!(1));
+    }
+
+    #[test]
+    fn lots() {
+        let now: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let mut resource = make_resource(&ResourceID::new("milk"), &CompanyID::new("jerry's dairy"), &Measure::new(10, Unit::One), &Costs::new(), &now);
+        assert_eq!(resource.check_lot(&now), Ok(()));
+
+        let lot = dfc::ProductBatch::builder()
+            .batch_number("A-113")
+            .production_date(Some(now.clone()))
+            .expiry_date(Some("2020-06-14T00:00:00Z".parse().unwrap()))
+            .build().unwrap();
+        assert!(!is_lot_expired(&lot, &now));
+        assert!(is_lot_expired(&lot, &"2020-06-14T00:00:00Z".parse().unwrap()));
+        resource.inner_mut().set_lot(Some(lot));
+        assert_eq!(resource.check_lot(&now), Ok(()));
+        assert_eq!(resource.check_lot(&"2020-07-01T00:00:00Z".parse().unwrap()), Err(Error::LotExpired("A-113".into())));
+
+        resource.inner_mut().set_lot(Some(dfc::ProductBatch::builder().batch_number("B-2").build().unwrap()));
+        assert_eq!(resource.check_lot(&"2099-07-01T00:00:00Z".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn location_history() {
+        let now = util::time::now();
+        let mut resource = make_resource(&ResourceID::new("widget"), &CompanyID::new("jerry's widgets"), &Measure::new(10, Unit::One), &Costs::new(), &now);
+        let place = |lat: f64| SpatialThing::builder().lat(Some(lat)).long(Some(-122.0)).build().unwrap();
+        assert_eq!(resource.location_history().len(), 0);
+
+        resource.move_to(place(45.0), EventID::new("move-1"), &now);
+        assert_eq!(resource.inner().current_location(), &Some(place(45.0)));
+        assert_eq!(resource.location_history(), &vec![LocationChange::new(place(45.0), EventID::new("move-1"), now.clone())]);
+        // staying put isn't a move
+        resource.move_to(place(45.0), EventID::new("move-2"), &now);
+        assert_eq!(resource.location_history().len(), 1);
+
+        for i in 0..(LOCATION_HISTORY_MAX + 5) {
+            resource.move_to(place(i as f64), EventID::new(format!("move-{}", i + 3)), &now);
+        }
+        assert_eq!(resource.location_history().len(), LOCATION_HISTORY_MAX);
+        assert_eq!(resource.location_history()[0].location(), &place(5.0));
+        assert_eq!(resource.location_history()[LOCATION_HISTORY_MAX - 1].event_id(), &EventID::new(format!("move-{}", LOCATION_HISTORY_MAX + 7)));
+        assert_eq!(resource.inner().current_location(), &Some(place((LOCATION_HISTORY_MAX + 4) as f64)));
+    }
+}
@@ -1,16 +1,140 @@
+//! Resource groups let a company organize its inventory by collecting related
+//! resources together, for instance "all lumber at warehouse 3" or "tools out
+//! for repair." Resources are added to a group via [resource group links][1],
+//! and a resource can belong to any number of groups.
+//!
+//! Groups are purely organizational: they don't own anything or hold costs of
+//! their own, but can summarize the costs and quantities of the resources in
+//! them.
+//!
+//! [1]: ../resource_group_link/index.html
+
+use crate::{
+    costs::Costs,
+    error::Result,
+    models::{
+        company::CompanyID,
+        lib::basis_model::Model,
+        resource::{Resource, ResourceID},
+        resource_group_link::ResourceGroupLink,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::Measure;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
 basis_model! {
-    /// Acts as a group for various products classified as resources.
-    ///
-    /// For instance, a group might be "iron", and all the iron produced by iron
-    /// mines might link to the group.
+    /// The `ResourceGroup` model collects related resources under a name.
     pub struct ResourceGroup {
-        /// The name of the group, generally will be some easily-identifiable
-        /// resource name like "iron" or "silicon" or "fresh water"
+        id: <<ResourceGroupID>>,
+        /// The company this group belongs to
+        company_id: CompanyID,
+        /// The name of the group, ie "lumber (warehouse 3)"
         name: String,
-        /// The globally-decided cost (in credits) for products under this group.
-        credit_cost_per_unit: f64,
+        /// Notes about the group
+        note: Option<String>,
     }
-    ResourceGroupID
     ResourceGroupBuilder
 }
 
+/// A summary of the resources in a group.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ResourceGroupSummary {
+    /// The IDs of the resources in the group
+    resource_ids: Vec<ResourceID>,
+    /// The total costs of the resources in the group
+    costs: Costs,
+    /// The total accounting quantity of the resources in the group, one entry
+    /// per unit (a group of lumber and nails might have quantities in both
+    /// metres and units).
+    quantities: Vec<Measure>,
+}
+
+impl ResourceGroup {
+    /// Summarize the costs and quantities of the resources in this group.
+    ///
+    /// Resources are only counted if they have an active link to this group.
+    /// Deleted resources (and resources we don't have links for) are skipped.
+    pub fn summarize(&self, links: &[ResourceGroupLink], resources: &[Resource]) -> Result<ResourceGroupSummary> {
+        let mut summary = ResourceGroupSummary::default();
+        for resource in resources {
+            if resource.is_deleted() || summary.resource_ids.contains(resource.id()) {
+                continue;
+            }
+            let linked = links.iter().any(|link| link.is_active() && link.group_id() == self.id() && link.resource_id() == resource.id());
+            if !linked {
+                continue;
+            }
+            summary.resource_ids.push(resource.id().clone());
+            summary.costs = summary.costs.clone() + resource.costs().clone();
+            if let Some(quantity) = resource.inner().accounting_quantity() {
+                match summary.quantities.iter_mut().find(|x| x.has_unit() == quantity.has_unit()) {
+                    Some(total) => {
+                        measure::inc_measure_with(total, quantity, true)?;
+                    }
+                    None => summary.quantities.push(quantity.clone()),
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::resource_group_link::ResourceGroupLinkID,
+        util::{self, test::*},
+    };
+    use om2::Unit;
+
+    #[test]
+    fn summarize() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's lumber");
+        let group = ResourceGroup::builder()
+            .id(ResourceGroupID::new("warehouse 3"))
+            .company_id(company_id.clone())
+            .name("lumber (warehouse 3)")
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let make_link = |resource_id: &str| {
+            ResourceGroupLink::builder()
+                .id(ResourceGroupLinkID::create())
+                .group_id(group.id().clone())
+                .resource_id(ResourceID::new(resource_id))
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        let oak = make_resource(&ResourceID::new("oak"), &company_id, &Measure::new(num!(20), Unit::Metre), &Costs::new_with_labor("logger", num!(100)), &now);
+        let pine = make_resource(&ResourceID::new("pine"), &company_id, &Measure::new(num!(30), Unit::Metre), &Costs::new_with_labor("logger", num!(60)), &now);
+        let nails = make_resource(&ResourceID::new("nails"), &company_id, &Measure::new(num!(500), Unit::One), &Costs::new_with_labor("machinist", num!(5)), &now);
+        let glue = make_resource(&ResourceID::new("glue"), &company_id, &Measure::new(num!(2), Unit::Litre), &Costs::new_with_labor("chemist", num!(12)), &now);
+        let mut old_link = make_link("glue");
+        old_link.set_deleted(Some(now.clone()));
+        let links = vec![make_link("oak"), make_link("pine"), make_link("nails"), old_link];
+        let resources = vec![oak.clone(), pine.clone(), nails.clone(), glue.clone(), oak.clone()];
+
+        let summary = group.summarize(&links, &resources).unwrap();
+        assert_eq!(summary.resource_ids(), &vec![ResourceID::new("oak"), ResourceID::new("pine"), ResourceID::new("nails")]);
+        let mut costs = Costs::new_with_labor("logger", num!(160));
+        costs.track_labor("machinist", num!(5));
+        assert_eq!(summary.costs(), &costs);
+        assert_eq!(summary.quantities(), &vec![Measure::new(num!(50), Unit::Metre), Measure::new(num!(500), Unit::One)]);
+
+        // links to other groups don't count
+        let mut other_link = make_link("glue");
+        other_link.set_group_id(ResourceGroupID::new("warehouse 4"));
+        let summary = group.summarize(&vec![other_link], &resources).unwrap();
+        assert_eq!(summary, ResourceGroupSummary::default());
+    }
+}
@@ -1,19 +1,22 @@
+//! A resource group link adds a resource to a [resource group][1].
+//!
+//! [1]: ../resource_group/index.html
+
 use crate::{
     models::{
-        resource_spec::ResourceSpecID,
+        resource::ResourceID,
         resource_group::ResourceGroupID,
     },
 };
+
 basis_model! {
+    /// The `ResourceGroupLink` model links a resource to a group.
     pub struct ResourceGroupLink {
-        /// The ID of the resource group.
+        id: <<ResourceGroupLinkID>>,
+        /// The ID of the resource group
         group_id: ResourceGroupID,
-        /// The ID of the product we're linking to the group.
-        product_id: ResourceSpecID,
-        // TODO: at some point, store meta information about the resource
-        // quantity/renewal/depletion/etc
+        /// The ID of the resource we're linking to the group
+        resource_id: ResourceID,
     }
-    ResourceGroupLinkID
     ResourceGroupLinkBuilder
 }
-
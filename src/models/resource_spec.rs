@@ -2,15 +2,75 @@
 //! buy a "Zenith Men's 96.0529.4035/51.M Defy Xtreme Tourbillon Titanium
 //! Chronograph Watch" on Wamazon, the watch you get in the mail is the resource
 //! and the *resource specification* is the Wamazon product description page.
+//!
+//! Specs are versioned. Rather than editing a spec in place (which would change
+//! the meaning of every resource already built against it) a company can
+//! *revise* it, creating a new version that supersedes the old one. The old
+//! version is marked deprecated and becomes read-only, and existing resources
+//! keep pointing at the version they conformed to.
+//!
+//! Specs can also record the physical [Dimensions] of one unit of the resource
+//! (its weight and/or volume). Quantities of resources conforming to the spec
+//! must be recorded in a unit that's compatible with the spec's default unit,
+//! or with one of its dimensions: a spec counted in units that weighs 2kg per
+//! unit can be produced by the kilogram, but not by the litre.
+//!
+//! Specs can carry default (estimated) costs per unit, which let a network
+//! that's just starting out bring resources in from the outside world without
+//! knowing their full cost composition.
+//!
+//! Like process specs, resource specs can be published to a shared catalog
+//! and [forked][1] by other companies.
+//!
+//! [Dimensions]: struct.Dimensions.html
+//! [1]: ../../transactions/resource_spec/fn.fork.html
 
+use chrono::{DateTime, Utc};
 use crate::{
+    costs::Costs,
+    error::{Error, Result},
     models::{
         company::CompanyID,
+        resource::Resource,
     },
+    util::measure,
 };
+use getset::Getters;
+use om2::{Measure, Unit};
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use url::Url;
 use vf_rs::vf;
 
+/// The physical dimensions of one unit of a resource.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Dimensions {
+    /// The weight (mass) of one unit
+    weight: Option<Measure>,
+    /// The volume of one unit
+    volume: Option<Measure>,
+}
+
+impl Dimensions {
+    /// Create a new set of dimensions. The weight must be given in a unit of
+    /// mass and the volume in a unit of volume.
+    pub fn new(weight: Option<Measure>, volume: Option<Measure>) -> Result<Self> {
+        if let Some(weight) = weight.as_ref() {
+            if !measure::is_convertible(weight.has_unit(), &Unit::Gram) {
+                Err(Error::MeasureUnitsMismatched)?;
+            }
+        }
+        if let Some(volume) = volume.as_ref() {
+            if !measure::is_convertible(volume.has_unit(), &Unit::Litre) {
+                Err(Error::MeasureUnitsMismatched)?;
+            }
+        }
+        Ok(Self { weight, volume })
+    }
+}
+
 basis_model! {
     /// The `ResourceSpec` model wraps our heroic [vf::ResourceSpecification][vfresource]
     /// object, with one addition: we add a `CompanyID`, which effectively acts
@@ -27,7 +87,100 @@ basis_model! {
         /// if we want to group products together, we certainly can, but this is
         /// not the place for it.
         company_id: CompanyID,
+        /// The version of this spec, starting at 1 and incremented with each
+        /// revision.
+        version: u32,
+        /// The previous version of this spec, if this is a revision
+        supersedes: Option<ResourceSpecID>,
+        /// The version that replaced this one. If set, this spec is deprecated.
+        superseded_by: Option<ResourceSpecID>,
+        /// The physical dimensions of one unit of a resource of this spec
+        dimensions: Option<Dimensions>,
+        /// When this spec was published to the shared catalog. Unpublished
+        /// specs can't be forked by other companies.
+        published: Option<DateTime<Utc>>,
+        /// The spec this one was forked from, if any
+        forked_from: Option<ResourceSpecID>,
+        /// Estimated costs for one unit (of the spec's default unit) of this
+        /// resource, used to [bootstrap][1] resources brought in from outside
+        /// the network before their real cost composition is known.
+        ///
+        /// [1]: ../../transactions/resource/fn.bootstrap.html
+        default_costs_per_unit: Option<Costs>,
     }
     ResourceSpecBuilder
 }
 
+impl ResourceSpec {
+    /// Whether or not this spec has been replaced by a newer version.
+    pub fn is_deprecated(&self) -> bool {
+        self.superseded_by().is_some()
+    }
+
+    /// Whether or not this spec is published to the shared catalog.
+    pub fn is_published(&self) -> bool {
+        self.published().is_some()
+    }
+
+    /// Make sure a quantity recorded in the given unit makes sense for a
+    /// resource of this spec. The unit must be convertible to the spec's
+    /// default unit (if it has one) or to one of the spec's dimensions.
+    pub fn check_unit(&self, unit: &Unit) -> Result<()> {
+        let default_unit = match self.inner().default_unit_of_resource() {
+            Some(default_unit) => default_unit,
+            None => return Ok(()),
+        };
+        if measure::is_convertible(unit, default_unit) {
+            return Ok(());
+        }
+        if let Some(dimensions) = self.dimensions() {
+            let per_unit = vec![dimensions.weight(), dimensions.volume()];
+            if per_unit.into_iter().flatten().any(|x| measure::is_convertible(unit, x.has_unit())) {
+                return Ok(());
+            }
+        }
+        Err(Error::ResourceSpecUnitMismatch(format!("{:?}", unit)))
+    }
+
+    /// Make sure a resource conforms to this spec, and that its quantities are
+    /// recorded in a unit compatible with it.
+    pub fn check_resource(&self, resource: &Resource) -> Result<()> {
+        if resource.inner().conforms_to() != self.id() {
+            Err(Error::ResourceSpecMismatch)?;
+        }
+        match resource.get_unit() {
+            Some(unit) => self.check_unit(&unit),
+            None => Ok(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{self, test::*};
+
+    #[test]
+    fn dimensions() {
+        assert!(Dimensions::new(Some(Measure::new(num!(2), Unit::Kilogram)), Some(Measure::new(num!(3), Unit::Litre))).is_ok());
+        assert_eq!(Dimensions::new(Some(Measure::new(num!(2), Unit::Litre)), None), Err(Error::MeasureUnitsMismatched));
+        assert_eq!(Dimensions::new(None, Some(Measure::new(num!(2), Unit::Kilogram))), Err(Error::MeasureUnitsMismatched));
+    }
+
+    #[test]
+    fn check_unit() {
+        let now = util::time::now();
+        let mut spec = make_resource_spec(&ResourceSpecID::create(), &CompanyID::create(), "widget", &now);
+        assert_eq!(spec.check_unit(&Unit::Litre), Ok(()));
+
+        spec.inner_mut().set_default_unit_of_resource(Some(Unit::One));
+        assert_eq!(spec.check_unit(&Unit::One), Ok(()));
+        assert_eq!(spec.check_unit(&Unit::Kilogram), Err(Error::ResourceSpecUnitMismatch("Kilogram".into())));
+
+        spec.set_dimensions(Some(Dimensions::new(Some(Measure::new(num!(2), Unit::Kilogram)), None).unwrap()));
+        assert_eq!(spec.check_unit(&Unit::Kilogram), Ok(()));
+        assert_eq!(spec.check_unit(&Unit::PoundAvoirdupois), Ok(()));
+        assert_eq!(spec.check_unit(&Unit::Litre), Err(Error::ResourceSpecUnitMismatch("Litre".into())));
+    }
+}
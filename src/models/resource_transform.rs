@@ -0,0 +1,32 @@
+//! A resource transform describes how a semi-raw material breaks down into the
+//! raw materials it's made from. For instance, a liter of gasoline might be
+//! decomposed into some amount of crude oil.
+//!
+//! Transforms let us track resources in [costs][1] at whatever level is most
+//! useful to the producers involved (gasoline, jet fuel, kerosene), while
+//! still being able to account for the raw materials underneath when tracking
+//! depletion (see `Costs::apply_transforms()`).
+//!
+//! What transforms exist is a systemwide, collective decision.
+//!
+//! [1]: ../../costs/index.html
+
+use crate::{
+    models::resource_spec::ResourceSpecID,
+};
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+basis_model! {
+    /// The `ResourceTransform` model maps one resource spec to the constituent
+    /// specs it's made of.
+    pub struct ResourceTransform {
+        id: <<ResourceTransformID>>,
+        /// The resource spec being decomposed
+        resource_spec_id: ResourceSpecID,
+        /// The constituent resource specs, along with how much of each goes
+        /// into one unit of `resource_spec_id`
+        constituents: HashMap<ResourceSpecID, Decimal>,
+    }
+    ResourceTransformBuilder
+}
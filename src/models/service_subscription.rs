@@ -0,0 +1,160 @@
+//! A service subscription is an ongoing agreement for one company to provide a
+//! service to another every period: IT support, cleaning, maintenance, etc.
+//! Relationships like these don't fit one-off orders, so rather than making
+//! both sides re-enter the same commitment over and over, the subscription
+//! holds the recurring terms and [materializes][1] each period's commitment
+//! from them.
+//!
+//! Subscriptions can carry a cost cap, which limits the total credits the
+//! subscription can ever commit the receiver to.
+//!
+//! [1]: ../../transactions/service_subscription/fn.materialize_period.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        agreement::AgreementID,
+        company::CompanyID,
+        lib::basis_model::Model,
+        resource_spec::ResourceSpecID,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::Measure;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// The terms of a subscription: what gets delivered each period, and for what
+/// costs.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ServiceTerms {
+    /// The amount of service delivered each period (for instance, 10 hours)
+    effort_per_period: Measure,
+    /// How long each period lasts, in units of time up to a week (for
+    /// instance, 1 week)
+    period: Measure,
+    /// The costs moved for each period's delivery
+    move_costs: Costs,
+    /// The spec of the service being delivered, if any
+    resource_conforms_to: Option<ResourceSpecID>,
+}
+
+impl ServiceTerms {
+    /// Create a new set of service terms
+    pub fn new(effort_per_period: Measure, period: Measure, move_costs: Costs, resource_conforms_to: Option<ResourceSpecID>) -> Self {
+        Self {
+            effort_per_period,
+            period,
+            move_costs,
+            resource_conforms_to,
+        }
+    }
+
+    /// Get the length of our period in seconds. Periods must be a positive
+    /// amount of time.
+    pub fn period_seconds(&self) -> Result<i64> {
+        measure::to_seconds(self.period())
+    }
+}
+
+/// Where a subscription is at.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum SubscriptionStatus {
+    /// The subscription is running, and periods can be materialized
+    Running,
+    /// The subscription is on hold. It can be resumed later.
+    Paused,
+    /// The subscription has been ended for good
+    Canceled,
+}
+
+basis_model! {
+    /// The `ServiceSubscription` model describes a recurring delivery of a
+    /// service from one company to another.
+    pub struct ServiceSubscription {
+        id: <<ServiceSubscriptionID>>,
+        /// The company providing the service
+        provider: CompanyID,
+        /// The company receiving the service
+        receiver: CompanyID,
+        /// The agreement the subscription's commitments are clauses of
+        agreement_id: AgreementID,
+        /// What's delivered each period, and for what costs
+        terms: ServiceTerms,
+        /// The most credits the subscription can commit the receiver to over
+        /// its lifetime. `None` means no cap.
+        cost_cap: Option<Decimal>,
+        /// The costs committed to by all periods materialized so far
+        committed: Costs,
+        /// When the next period to be materialized starts
+        next_period: DateTime<Utc>,
+        /// Where the subscription is at
+        status: SubscriptionStatus,
+    }
+    ServiceSubscriptionBuilder
+}
+
+impl ServiceSubscription {
+    /// Whether or not this subscription is running (and not deleted or
+    /// otherwise inactive)
+    pub fn is_running(&self) -> bool {
+        self.is_active() && self.status() == &SubscriptionStatus::Running
+    }
+
+    /// Make sure committing to one more period doesn't go over our cost cap.
+    pub fn check_cap(&self) -> Result<()> {
+        if let Some(cost_cap) = self.cost_cap() {
+            let total = self.committed().credits() + self.terms().move_costs().credits();
+            if &total > cost_cap {
+                Err(Error::SubscriptionCapExceeded(format!("another period would commit {} credits, over the cap of {}", total, cost_cap)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use om2::Unit;
+
+    #[test]
+    fn checks_cap() {
+        let now = util::time::now();
+        let terms = ServiceTerms::new(Measure::new(num!(10), Unit::Hour), Measure::new(num!(1), Unit::Week), Costs::new_with_labor("janitor", num!(300)), None);
+        let mut subscription = ServiceSubscription::builder()
+            .id(ServiceSubscriptionID::create())
+            .provider(CompanyID::new("jerry's janitorial"))
+            .receiver(CompanyID::new("larry's widgets"))
+            .agreement_id(AgreementID::new("cleaning"))
+            .terms(terms)
+            .cost_cap(Some(num!(600)))
+            .committed(Costs::new())
+            .next_period(now.clone())
+            .status(SubscriptionStatus::Running)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        assert!(subscription.is_running());
+        assert_eq!(subscription.terms().period_seconds().unwrap(), 604800);
+        subscription.check_cap().unwrap();
+        subscription.set_committed(Costs::new_with_labor("janitor", num!(300)));
+        subscription.check_cap().unwrap();
+        subscription.set_committed(Costs::new_with_labor("janitor", num!(600)));
+        assert_eq!(subscription.check_cap(), Err(Error::SubscriptionCapExceeded("another period would commit 900 credits, over the cap of 600".into())));
+        subscription.set_cost_cap(None);
+        subscription.check_cap().unwrap();
+
+        subscription.set_status(SubscriptionStatus::Paused);
+        assert!(!subscription.is_running());
+    }
+}
@@ -0,0 +1,64 @@
+//! A shipment groups a set of resources being delivered together by a carrier,
+//! from pickup at an origin to dropoff at a destination.
+//!
+//! Shipments don't move anything on their own: the underlying `pickup` and
+//! `dropoff` events do that (see the [delivery transactions][1]). The shipment
+//! just ties those events together and tracks where the delivery is at.
+//!
+//! [1]: ../../transactions/event/delivery/index.html
+
+use crate::{
+    models::{
+        lib::agent::AgentID,
+        process::ProcessID,
+        resource::ResourceID,
+    },
+};
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use vf_rs::geo::SpatialThing;
+
+/// Where a shipment is at in its journey.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum ShipmentStatus {
+    /// The shipment has been created but not picked up
+    Pending,
+    /// The shipment has been picked up by the carrier
+    InTransit,
+    /// The shipment has been dropped off at its destination
+    Delivered,
+}
+
+basis_model! {
+    /// The `Shipment` model tracks a set of resources being delivered together.
+    pub struct Shipment {
+        id: <<ShipmentID>>,
+        /// The agent carrying the shipment
+        carrier: AgentID,
+        /// The carrier's delivery process, which the costs of the delivery
+        /// accumulate in
+        process_id: ProcessID,
+        /// Where the shipment is being picked up from
+        origin: Option<SpatialThing>,
+        /// Where the shipment is being delivered to
+        destination: Option<SpatialThing>,
+        /// The resources contained in this shipment
+        resources: Vec<ResourceID>,
+        /// Where the shipment is at
+        status: ShipmentStatus,
+    }
+    ShipmentBuilder
+}
+
+impl Shipment {
+    /// Determine if this shipment contains exactly the given set of resources
+    /// (in any order).
+    pub fn contains_exactly(&self, resource_ids: &[&ResourceID]) -> bool {
+        let mut ours = self.resources().iter().collect::<Vec<_>>();
+        let mut theirs = resource_ids.to_vec();
+        ours.sort();
+        theirs.sort();
+        ours == theirs
+    }
+}
@@ -0,0 +1,117 @@
+//! A transfer policy lets a company put guardrails on the transfers it makes
+//! to other companies: a cap on how many credits can move in one transfer,
+//! which companies it can transfer to, and which agreement transfers must be
+//! made under. The [transfer transactions][1] consult the policy (if given)
+//! before doing anything.
+//!
+//! This is mostly a guard against fat-fingered transfers, but it also gives
+//! worker collectives a way to codify the limits they've agreed on.
+//!
+//! [1]: ../../transactions/event/transfer/index.html
+
+use crate::{
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        agreement::{Agreement, AgreementID},
+        company::{Company, CompanyID},
+        lib::basis_model::Model,
+    },
+};
+use rust_decimal::prelude::*;
+
+basis_model! {
+    /// The `TransferPolicy` model describes the limits a company places on its
+    /// outgoing transfers. Any limit left as `None` is not enforced.
+    pub struct TransferPolicy {
+        id: <<TransferPolicyID>>,
+        /// The company this policy applies to (the sending side of transfers)
+        company_id: CompanyID,
+        /// The maximum credit value of the costs moved in a single transfer
+        max_credits: Option<Decimal>,
+        /// The companies we're allowed to transfer to
+        allowed_counterparties: Option<Vec<CompanyID>>,
+        /// The agreement all transfers must be made under
+        required_agreement: Option<AgreementID>,
+    }
+    TransferPolicyBuilder
+}
+
+impl TransferPolicy {
+    /// Check a transfer from `company_from` to `company_to` (under the given
+    /// agreement, moving `move_costs`) against this policy. Inactive policies
+    /// allow everything.
+    pub fn check(&self, company_from: &Company, company_to: &Company, agreement: &Agreement, move_costs: &Costs) -> Result<()> {
+        if company_from.id() != self.company_id() {
+            Err(Error::TransferPolicyViolation("policy does not belong to the sending company".into()))?;
+        }
+        if !self.is_active() {
+            return Ok(());
+        }
+        if let Some(max_credits) = self.max_credits() {
+            if move_costs.credits() > max_credits {
+                Err(Error::TransferPolicyViolation(format!("transfer of {} credits is over the limit of {}", move_costs.credits(), max_credits)))?;
+            }
+        }
+        if let Some(allowed) = self.allowed_counterparties() {
+            if !allowed.contains(company_to.id()) {
+                Err(Error::TransferPolicyViolation(format!("company {} is not an allowed counterparty", company_to.id().as_str())))?;
+            }
+        }
+        if let Some(required_agreement) = self.required_agreement() {
+            if agreement.id() != required_agreement {
+                Err(Error::TransferPolicyViolation(format!("transfers must be made under agreement {}", required_agreement.as_str())))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::lib::agent::Agent,
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn check() {
+        let now = util::time::now();
+        let company_from = make_company(&CompanyID::new("jerry's widgets"), "jerry's widgets", &now);
+        let company_to = make_company(&CompanyID::new("larry's chairs"), "larry's chairs", &now);
+        let company_other = make_company(&CompanyID::new("garry's gears"), "garry's gears", &now);
+        let agreement = make_agreement(&AgreementID::new("order 1"), &vec![company_from.agent_id(), company_to.agent_id()], "order 1", "widgets please", &now);
+        let agreement2 = make_agreement(&AgreementID::new("order 2"), &vec![company_from.agent_id(), company_to.agent_id()], "order 2", "more widgets please", &now);
+        let mut policy = TransferPolicy::builder()
+            .id(TransferPolicyID::new("policy"))
+            .company_id(company_from.id().clone())
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let costs = Costs::new_with_labor("machinist", num!(100));
+
+        // no limits, anything goes
+        assert_eq!(policy.check(&company_from, &company_to, &agreement, &costs), Ok(()));
+        assert_eq!(policy.check(&company_to, &company_from, &agreement, &costs), Err(Error::TransferPolicyViolation("policy does not belong to the sending company".into())));
+
+        policy.set_max_credits(Some(num!(100)));
+        assert_eq!(policy.check(&company_from, &company_to, &agreement, &costs), Ok(()));
+        policy.set_max_credits(Some(num!(99.99)));
+        assert_eq!(policy.check(&company_from, &company_to, &agreement, &costs), Err(Error::TransferPolicyViolation("transfer of 100 credits is over the limit of 99.99".into())));
+        policy.set_max_credits(None);
+
+        policy.set_allowed_counterparties(Some(vec![company_to.id().clone()]));
+        assert_eq!(policy.check(&company_from, &company_to, &agreement, &costs), Ok(()));
+        assert_eq!(policy.check(&company_from, &company_other, &agreement, &costs), Err(Error::TransferPolicyViolation("company garry's gears is not an allowed counterparty".into())));
+
+        policy.set_required_agreement(Some(agreement.id().clone()));
+        assert_eq!(policy.check(&company_from, &company_to, &agreement, &costs), Ok(()));
+        assert_eq!(policy.check(&company_from, &company_to, &agreement2, &costs), Err(Error::TransferPolicyViolation("transfers must be made under agreement order 1".into())));
+
+        // inactive policies don't limit anything
+        policy.set_active(false);
+        assert_eq!(policy.check(&company_from, &company_other, &agreement2, &costs), Ok(()));
+    }
+}
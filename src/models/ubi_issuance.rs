@@ -0,0 +1,28 @@
+//! A UBI issuance records a single deposit of UBI credits into a UBI account
+//! for a specific period of time. Keeping a record of each issuance lets us
+//! audit how UBI credits were created and guarantees the same period is never
+//! paid out twice.
+
+use chrono::{DateTime, Utc};
+use crate::{
+    models::account::AccountID,
+};
+use rust_decimal::prelude::*;
+
+basis_model! {
+    /// The `UbiIssuance` model records UBI credits created for an account over
+    /// a given period.
+    pub struct UbiIssuance {
+        id: <<UbiIssuanceID>>,
+        /// The UBI account the credits were deposited into
+        account_id: AccountID,
+        /// The start of the period this issuance covers
+        period_start: DateTime<Utc>,
+        /// The end of the period this issuance covers
+        period_end: DateTime<Utc>,
+        /// The amount of credits issued. This can be less than the full amount
+        /// accrued over the period if the account hit the UBI ceiling.
+        amount: Decimal,
+    }
+    UbiIssuanceBuilder
+}
@@ -6,12 +6,13 @@
 //! by a `User` object.
 
 use crate::{
-    access::{Permission, Role},
+    access::{self, Permission, Role},
     models::{
         lib::{
             agent::{Agent, AgentID},
             basis_model::Model,
         },
+        region::RegionID,
     },
     error::{Error, Result},
 };
@@ -27,6 +28,9 @@ basis_model! {
         email: String,
         /// The user's full name.
         name: String,
+        /// The region the user lives in, if any. This decides which regional
+        /// proposals the user can vote on.
+        region_id: Option<RegionID>,
     }
     UserBuilder
 }
@@ -50,7 +54,7 @@ impl User {
         if !self.can(&permission) {
             Err(Error::InsufficientPrivileges)?;
         }
-        Ok(())
+        access::policy_check_user(self, &permission)
     }
 }
 
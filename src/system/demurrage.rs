@@ -0,0 +1,206 @@
+//! Demurrage decays credits that sit idle, so that credits circulate rather
+//! than pile up.
+//!
+//! Demurrage is optional and is driven by the network config's
+//! `demurrage_rate`: the fraction of an idle balance that decays each day. A
+//! rate of zero turns demurrage off entirely. Whatever runs the core calls
+//! `apply()` on a recurring basis (say, once a day) with the accounts it wants
+//! to charge and the length of time since the last run.
+//!
+//! An account counts as idle if no credits have moved into or out of it during
+//! the period being charged (see `Account::last_active()`). Other edits to an
+//! account, like renaming it, don't count as activity, and neither does
+//! demurrage itself. An account already charged within the period is skipped,
+//! so overlapping runs don't charge it twice. UBI accounts are never charged,
+//! since their ceiling already keeps them from piling up credits.
+//!
+//! Decayed credits are destroyed, not moved anywhere, and the [Demurrage]
+//! record returned tallies how many were destroyed (and from which accounts).
+//!
+//! Like the rest of the system module, this doesn't perform any permission
+//! checks.
+//!
+//! [Demurrage]: struct.Demurrage.html
+
+use chrono::{DateTime, Duration, Utc};
+use crate::{
+    costs::precision::CostPrecision,
+    error::{Error, Result},
+    models::{
+        Modifications,
+        Op,
+        account::{Account, AccountID},
+        lib::basis_model::Model,
+    },
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// A record of the credits destroyed by a demurrage run.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Demurrage {
+    /// The start of the period charged
+    period_start: DateTime<Utc>,
+    /// The end of the period charged
+    period_end: DateTime<Utc>,
+    /// The daily rate charged
+    rate: Decimal,
+    /// The credits destroyed from each account charged
+    accounts: Vec<(AccountID, Decimal)>,
+    /// The total credits destroyed
+    destroyed: Decimal,
+}
+
+/// Find how much of a balance is left after decaying at `rate` per day over
+/// the given number of seconds. Whole days compound, and any leftover part of
+/// a day decays proportionally.
+fn decay_factor(rate: &Decimal, seconds: i64) -> Decimal {
+    let keep = Decimal::one() - rate;
+    let days = seconds / 86400;
+    let remainder = Decimal::from(seconds % 86400) / num!(86400);
+    let mut factor = Decimal::one();
+    for _ in 0..days {
+        factor *= keep;
+    }
+    factor * (Decimal::one() - (rate * remainder))
+}
+
+/// Charge demurrage on any idle accounts for the `period` ending `now`,
+/// returning an update for each account charged along with a record of the
+/// credits destroyed.
+///
+/// `rate` is the fraction of a balance that decays per day (generally the
+/// network config's `demurrage_rate`) and must be between 0 and 1. The amount
+/// decayed from each account is rounded using `precision` (generally the
+/// network config's `cost_precision`). Deleted, UBI, empty, and non-idle
+/// accounts are skipped.
+pub fn apply(accounts: Vec<Account>, rate: &Decimal, precision: &CostPrecision, period: &Duration, now: &DateTime<Utc>) -> Result<(Modifications, Demurrage)> {
+    if rate < &Decimal::zero() || rate > &Decimal::one() {
+        Err(Error::InvalidRatio(*rate))?;
+    }
+    let seconds = period.num_seconds();
+    if seconds <= 0 {
+        Err(Error::InvalidAmount(Decimal::from(seconds)))?;
+    }
+    let period_start = now.clone() - *period;
+    let factor = decay_factor(rate, seconds);
+    let mut mods = Modifications::new();
+    let mut record = Demurrage {
+        period_start: period_start.clone(),
+        period_end: now.clone(),
+        rate: rate.clone(),
+        accounts: Vec::new(),
+        destroyed: Decimal::zero(),
+    };
+    if rate.is_zero() {
+        return Ok((mods, record));
+    }
+    for mut account in accounts {
+        if account.is_deleted() || account.ubi().is_some() || account.last_active() > &period_start {
+            continue;
+        }
+        if account.last_demurrage().as_ref().map(|x| x > &period_start).unwrap_or(false) {
+            continue;
+        }
+        let balance = account.balance().clone();
+        let decayed = precision.round(&(balance - (balance * factor)));
+        if decayed <= Decimal::zero() {
+            continue;
+        }
+        account.adjust_balance(-decayed)?;
+        account.set_last_demurrage(Some(*now));
+        account.set_updated(now.clone());
+        record.accounts.push((account.id().clone(), decayed));
+        record.destroyed += decayed;
+        mods.push(Op::Update, account);
+    }
+    Ok((mods, record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::precision::Rounding,
+        models::{
+            account::Ubi,
+            user::UserID,
+        },
+        util::test::*,
+    };
+
+    #[test]
+    fn decays_idle_balances() {
+        let start: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let now: DateTime<Utc> = "2020-01-03T00:00:00Z".parse().unwrap();
+        let busy_time: DateTime<Utc> = "2020-01-02T12:00:00Z".parse().unwrap();
+        let idle = make_account(&AccountID::new("idle"), &UserID::create(), num!(1000), "idle", &start);
+        let busy = make_account(&AccountID::new("busy"), &UserID::create(), num!(1000), "busy", &busy_time);
+        let empty = make_account(&AccountID::new("empty"), &UserID::create(), num!(0), "empty", &start);
+        let mut ubi = make_account(&AccountID::new("ubi"), &UserID::create(), num!(1000), "ubi", &start);
+        ubi.set_ubi(Some(Ubi::new(start.clone())));
+        let mut deleted = idle.clone();
+        deleted.set_id(AccountID::new("deleted"));
+        deleted.set_deleted(Some(start.clone()));
+        let idle2 = make_account(&AccountID::new("idle2"), &UserID::create(), num!(50), "idle2", &start);
+
+        let accounts = vec![idle.clone(), busy.clone(), empty.clone(), ubi.clone(), deleted.clone(), idle2.clone()];
+        let (mods, record) = apply(accounts.clone(), &num!(0.1), &CostPrecision::default(), &Duration::days(1), &now).unwrap();
+        let mods = mods.into_vec();
+        assert_eq!(mods.len(), 2);
+        let idle_after = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(idle_after.id(), idle.id());
+        assert_eq!(idle_after.balance(), &num!(900));
+        assert_eq!(idle_after.updated(), &now);
+        assert_eq!(idle_after.last_demurrage(), &Some(now.clone()));
+        assert_eq!(idle_after.last_activity(), &None);
+        let charged = idle_after.clone();
+        let idle2_after = mods[1].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(idle2_after.balance(), &num!(45));
+        assert_eq!(record.period_start(), &"2020-01-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(record.period_end(), &now);
+        assert_eq!(record.rate(), &num!(0.1));
+        assert_eq!(record.accounts(), &vec![(idle.id().clone(), num!(100)), (idle2.id().clone(), num!(5))]);
+        assert_eq!(record.destroyed(), &num!(105));
+
+        // whole days compound, partial days are proportional
+        let (mods, record) = apply(vec![idle.clone()], &num!(0.1), &CostPrecision::default(), &(Duration::days(2) - Duration::hours(12)), &now).unwrap();
+        let idle_after = mods.into_vec()[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(idle_after.balance(), &num!(855));
+        assert_eq!(record.destroyed(), &num!(145));
+
+        // the amount decayed is rounded with the precision we're given
+        let (_, record) = apply(vec![idle.clone()], &num!(0.0333), &CostPrecision::new(0, Rounding::HalfUp), &Duration::days(1), &now).unwrap();
+        assert_eq!(record.destroyed(), &num!(33));
+
+        let (mods, record) = apply(accounts.clone(), &num!(0), &CostPrecision::default(), &Duration::days(1), &now).unwrap();
+        assert_eq!(mods.into_vec().len(), 0);
+        assert_eq!(record.destroyed(), &num!(0));
+
+        // editing an account isn't activity, but moving credits is
+        let mut renamed = idle.clone();
+        renamed.set_name("renamed".into());
+        renamed.set_updated(busy_time.clone());
+        let mut spent = idle.clone();
+        spent.move_credits(num!(-100), &busy_time).unwrap();
+        spent.set_updated(busy_time.clone());
+        let (mods, record) = apply(vec![renamed.clone(), spent.clone()], &num!(0.1), &CostPrecision::default(), &Duration::days(1), &now).unwrap();
+        let mods = mods.into_vec();
+        assert_eq!(mods.len(), 1);
+        assert_eq!(mods[0].clone().expect_op::<Account>(Op::Update).unwrap().id(), renamed.id());
+        assert_eq!(record.destroyed(), &num!(100));
+
+        // an account already charged during the period isn't charged again
+        let (_, record) = apply(vec![charged.clone()], &num!(0.1), &CostPrecision::default(), &Duration::days(1), &(now.clone() + Duration::hours(12))).unwrap();
+        assert_eq!(record.destroyed(), &num!(0));
+        let (_, record) = apply(vec![charged.clone()], &num!(0.1), &CostPrecision::default(), &Duration::days(1), &(now.clone() + Duration::days(1))).unwrap();
+        assert_eq!(record.destroyed(), &num!(90));
+
+        assert_eq!(apply(accounts.clone(), &num!(1.1), &CostPrecision::default(), &Duration::days(1), &now), Err(Error::InvalidRatio(num!(1.1))));
+        assert_eq!(apply(accounts.clone(), &num!(0.1), &CostPrecision::default(), &Duration::days(0), &now), Err(Error::InvalidAmount(num!(0))));
+    }
+}
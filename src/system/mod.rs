@@ -2,6 +2,11 @@
 //! the system itself. For instance, a voting user/member that acts on behalf of
 //! the system or a company, or a user that masks/anonymizes consumer purchases.
 
+pub mod demurrage;
+pub mod payroll;
+pub mod proxy;
+pub mod schedule;
+pub mod stats;
 pub mod ubi;
 pub mod vote;
 
@@ -0,0 +1,271 @@
+//! Payroll turns the work recorded by a company's workers into the credits
+//! each member is owed, and deposits those credits into their accounts.
+//!
+//! Every `work` event carries its wage costs, which are derived from the
+//! worker's compensation when the work is recorded (an hourly wage as-is, a
+//! salary converted to an hourly rate using the worker's estimated hours per
+//! week). What a member is owed for a pay period is the sum of the credits in
+//! their work events that fall within it, so the amount paid is always exactly
+//! the amount the work cost.
+//!
+//! This is the calculation the [payroll transaction][1] pays out with. On its
+//! own it's a systemic process: it doesn't check permissions, charge the work
+//! to the company and its processes, or record the period as paid in the
+//! company's treasury. Use the transaction to actually pay a company's workers.
+//!
+//! [1]: ../../transactions/company/fn.payroll.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    error::{Error, Result},
+    models::{
+        Modifications,
+        Op,
+        account::Account,
+        event::Event,
+        member::MemberID,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::Unit;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use vf_rs::vf;
+
+/// Describes what a single member is owed for a pay period.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Paycheck {
+    /// The member being paid
+    member_id: MemberID,
+    /// The number of hours worked in the period, as recorded by work events
+    hours: Decimal,
+    /// The amount of credits owed
+    amount: Decimal,
+}
+
+/// Grab the number of hours worked from a work event, either via its effort
+/// quantity or its begin/end times.
+fn hours_worked(event: &Event) -> Result<Decimal> {
+    match (event.inner().effort_quantity(), event.inner().has_beginning(), event.inner().has_end()) {
+        (Some(effort), _, _) => {
+            if effort.has_unit() != &Unit::Hour {
+                Err(Error::MeasureUnitsMismatched)?;
+            }
+            measure::to_decimal(effort)
+        }
+        (None, Some(begin), Some(end)) => {
+            let milliseconds = end.timestamp_millis() - begin.timestamp_millis();
+            Ok(Decimal::from(milliseconds) / Decimal::from(1000 * 60 * 60))
+        }
+        _ => Ok(Decimal::zero()),
+    }
+}
+
+/// Determine if an event is work that gets paid in the given period. We go by
+/// when the work ended (or when it happened, if it's a point in time).
+pub(crate) fn payable(event: &Event, period_start: &DateTime<Utc>, period_end: &DateTime<Utc>) -> bool {
+    let inner = event.inner();
+    if inner.action() != &vf::Action::Work {
+        return false;
+    }
+    let timestamp = inner.has_end().as_ref()
+        .or(inner.has_point_in_time().as_ref())
+        .or(inner.has_beginning().as_ref());
+    match timestamp {
+        Some(time) => time >= period_start && time < period_end,
+        None => false,
+    }
+}
+
+/// Tally up how much each member is owed for the given pay period.
+///
+/// Events that aren't `work` or that don't fall within the period are skipped
+/// (`period_start` is inclusive, `period_end` is exclusive). Paychecks are
+/// returned in the order each member first shows up in `work_events`.
+pub fn paychecks(work_events: &[Event], period_start: &DateTime<Utc>, period_end: &DateTime<Utc>) -> Result<Vec<Paycheck>> {
+    if period_end <= period_start {
+        Err(Error::PayPeriodInvalid)?;
+    }
+    let mut paychecks: Vec<Paycheck> = Vec::new();
+    for work in work_events {
+        if !payable(work, period_start, period_end) {
+            continue;
+        }
+        let member_id: MemberID = work.inner().provider().clone().try_into()?;
+        let costs = work.move_costs().as_ref().ok_or_else(|| Error::MissingFields(vec!["move_costs".into()]))?;
+        let hours = hours_worked(work)?;
+        match paychecks.iter_mut().find(|paycheck| paycheck.member_id() == &member_id) {
+            Some(paycheck) => {
+                paycheck.hours += hours;
+                paycheck.amount += costs.credits();
+            }
+            None => {
+                paychecks.push(Paycheck {
+                    member_id,
+                    hours,
+                    amount: *costs.credits(),
+                });
+            }
+        }
+    }
+    Ok(paychecks)
+}
+
+/// Calculate how much each member is owed for the given pay period and deposit
+/// it into their account.
+///
+/// Takes a hash map of MemberID -> Account pairs, which must contain the
+/// account of every member being paid. Returns an update for each account paid
+/// into, in the same order as [paychecks][1].
+///
+/// [1]: fn.paychecks.html
+pub fn calculate(mut accounts: HashMap<MemberID, Account>, work_events: &[Event], period_start: &DateTime<Utc>, period_end: &DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let mut mods = Modifications::new();
+    for paycheck in paychecks(work_events, period_start, period_end)? {
+        if paycheck.amount().is_zero() {
+            continue;
+        }
+        let mut account = accounts.remove(paycheck.member_id())
+            .ok_or_else(|| Error::MissingFields(vec![format!("accounts::{}", paycheck.member_id().as_str())]))?;
+        account.move_credits(*paycheck.amount(), now)?;
+        account.set_updated(*now);
+        mods.push(Op::Update, account);
+    }
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            account::AccountID,
+            company::CompanyID,
+            event::EventID,
+            member::{Compensation, Member, MemberClass, MemberWorker},
+            occupation::OccupationID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+    use om2::Measure;
+
+    fn make_work(member: &Member, action: vf::Action, begin: &str, end: &str, now: &DateTime<Utc>) -> Event {
+        let begin: DateTime<Utc> = begin.parse().unwrap();
+        let end: DateTime<Utc> = end.parse().unwrap();
+        let hours = Decimal::from(end.timestamp() - begin.timestamp()) / Decimal::from(60 * 60);
+        let rate = member.compensation().unwrap().hourly_rate().unwrap().unwrap();
+        Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action)
+                    .effort_quantity(Some(Measure::new(hours, Unit::Hour)))
+                    .has_beginning(Some(begin))
+                    .has_end(Some(end))
+                    .provider(member.id().clone())
+                    .receiver(member.company_id().unwrap())
+                    .build().unwrap()
+            )
+            .move_costs(Some(Costs::new_with_labor("machinist", rate * hours)))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    fn make_worker(company_id: &CompanyID, compensation: Compensation, now: &DateTime<Utc>) -> Member {
+        let mut member = make_member_worker(&MemberID::create(), &UserID::create(), company_id, &OccupationID::create(), vec![], now);
+        member.set_class(MemberClass::Worker(MemberWorker::new(OccupationID::create(), Some(compensation))));
+        member
+    }
+
+    #[test]
+    fn tallies_paychecks() {
+        let now = util::time::now();
+        let company_id = CompanyID::create();
+        let hourly = make_worker(&company_id, Compensation::new_hourly(num!(30), "hourly-account"), &now);
+        // works out to 35/hour
+        let salaried = make_worker(&company_id, Compensation::new_salary(num!(73000), "salary-account", num!(40)), &now);
+
+        let events = vec![
+            make_work(&hourly, vf::Action::Work, "2020-01-01T08:00:00Z", "2020-01-01T16:00:00Z", &now),
+            make_work(&salaried, vf::Action::Work, "2020-01-01T09:00:00Z", "2020-01-01T17:00:00Z", &now),
+            make_work(&hourly, vf::Action::Work, "2020-01-02T08:00:00Z", "2020-01-02T12:30:00Z", &now),
+            // outside of the period
+            make_work(&hourly, vf::Action::Work, "2020-01-15T08:00:00Z", "2020-01-15T16:00:00Z", &now),
+            // not work
+            make_work(&salaried, vf::Action::Use, "2020-01-01T09:00:00Z", "2020-01-01T17:00:00Z", &now),
+        ];
+        let start: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let end: DateTime<Utc> = "2020-01-15T00:00:00Z".parse().unwrap();
+        let paychecks = paychecks(&events, &start, &end).unwrap();
+        assert_eq!(paychecks.len(), 2);
+        assert_eq!(paychecks[0].member_id(), hourly.id());
+        assert_eq!(paychecks[0].hours(), &num!(12.5));
+        assert_eq!(paychecks[0].amount(), &num!(375));
+        assert_eq!(paychecks[1].member_id(), salaried.id());
+        assert_eq!(paychecks[1].hours(), &num!(8));
+        assert_eq!(paychecks[1].amount(), &num!(280));
+
+        let mut events2 = events.clone();
+        events2[0].set_move_costs(None);
+        let res = super::paychecks(&events2, &start, &end);
+        assert_eq!(res, Err(Error::MissingFields(vec!["move_costs".into()])));
+
+        let res = super::paychecks(&events, &end, &start);
+        assert_eq!(res, Err(Error::PayPeriodInvalid));
+    }
+
+    #[test]
+    fn deposits_paychecks() {
+        let now = util::time::now();
+        let company_id = CompanyID::create();
+        let hourly = make_worker(&company_id, Compensation::new_hourly(num!(30), "hourly-account"), &now);
+        let salaried = make_worker(&company_id, Compensation::new_salary(num!(73000), "salary-account", num!(40)), &now);
+        let account1 = make_account(&AccountID::create(), &UserID::create(), num!(10), "hourly's account", &now);
+        let account2 = make_account(&AccountID::create(), &UserID::create(), num!(0), "salaried's account", &now);
+        let mut accounts = HashMap::new();
+        accounts.insert(hourly.id().clone(), account1.clone());
+        accounts.insert(salaried.id().clone(), account2.clone());
+
+        let mut unpaid = make_work(&salaried, vf::Action::Work, "2020-01-03T09:00:00Z", "2020-01-03T17:00:00Z", &now);
+        unpaid.set_move_costs(Some(Costs::new()));
+        let events = vec![
+            make_work(&hourly, vf::Action::Work, "2020-01-01T08:00:00Z", "2020-01-01T16:00:00Z", &now),
+            make_work(&hourly, vf::Action::Work, "2020-01-02T08:00:00Z", "2020-01-02T12:30:00Z", &now),
+            unpaid.clone(),
+        ];
+        let start: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let end: DateTime<Utc> = "2020-01-15T00:00:00Z".parse().unwrap();
+        let now2 = util::time::now();
+        let mods = calculate(accounts.clone(), &events, &start, &end, &now2).unwrap().into_vec();
+        // the salaried worker is owed nothing, so their account isn't touched
+        assert_eq!(mods.len(), 1);
+        let account1_2 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(account1_2.id(), account1.id());
+        assert_eq!(account1_2.balance(), &num!(385));
+        assert_eq!(account1_2.last_activity(), &Some(now2.clone()));
+        assert_eq!(account1_2.updated(), &now2);
+
+        let mut events2 = events.clone();
+        events2.push(make_work(&salaried, vf::Action::Work, "2020-01-04T09:00:00Z", "2020-01-04T17:00:00Z", &now));
+        let mods = calculate(accounts.clone(), &events2, &start, &end, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let account2_2 = mods[1].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(account2_2.id(), account2.id());
+        assert_eq!(account2_2.balance(), &num!(280));
+
+        let mut accounts2 = accounts.clone();
+        accounts2.remove(salaried.id());
+        let res = calculate(accounts2, &events2, &start, &end, &now2);
+        assert_eq!(res, Err(Error::MissingFields(vec![format!("accounts::{}", salaried.id().as_str())])));
+    }
+}
@@ -0,0 +1,286 @@
+//! The proxy module lets consumers buy things without the selling company
+//! knowing who they are.
+//!
+//! A `Proxy` is a system user that makes purchases on behalf of real users. The
+//! purchase happens in two steps:
+//!
+//! 1. The proxy buys the resource from the company, paying with the real
+//!    user's account. The company only ever sees the proxy as the receiver.
+//! 2. The proxy hands the resource (along with all of its costs) off to the
+//!    real user. The company isn't a party to this second event.
+//!
+//! The credits spent and costs moved are exactly the same as a direct
+//! purchase, so everything still resolves to the real user's account and
+//! resources.
+//!
+//! ```rust
+//! use basis_core::{
+//!     access::Role,
+//!     models::user::UserID,
+//!     system::proxy::Proxy,
+//! };
+//! use chrono::Utc;
+//!
+//! let user_id = UserID::new("0c4f9ef9-f4dc-4a53-a3b2-ad2a1d09a0c5");
+//! let proxy = Proxy::new(user_id.clone(), &Utc::now()).unwrap();
+//! assert_eq!(proxy.user().id(), &user_id);
+//! assert_eq!(proxy.user().roles(), &vec![Role::User]);
+//! ```
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::{Permission, Role},
+    error::{Error, Result},
+    models::{
+        Model,
+        Modifications,
+        Op,
+        account::Account,
+        company::Company,
+        company_treasury::CompanyTreasury,
+        event::{Event, EventID, EventProcessState},
+        intent::Intent,
+        lib::basis_model::Model as _,
+        resource::{Resource, ResourceID},
+        user::{User, UserID},
+    },
+    transactions::{
+        event::ResourceMover,
+        order,
+    },
+};
+use getset::Getters;
+use om2::NumericUnion;
+use vf_rs::vf;
+
+/// A system user that makes purchases on behalf of real users, masking their
+/// identity from the companies they buy from.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct Proxy {
+    /// Holds our proxy user
+    user: User,
+}
+
+impl Proxy {
+    /// Create a new proxy user.
+    ///
+    /// The proxy only needs regular user abilities, since it never spends
+    /// from (or owns) any accounts of its own.
+    pub fn new(user_id: UserID, now: &DateTime<Utc>) -> Result<Self> {
+        let user = User::builder()
+            .roles(vec![Role::User])
+            .email(format!("proxy-{}@basisproject.net", user_id.as_str()))
+            .name(format!("Proxy {}", user_id.as_str()))
+            .id(user_id)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))?;
+        Ok(Self { user })
+    }
+}
+
+/// Purchase a resource from a company through a proxy.
+///
+/// This works like [order::purchase], except the company sells to the proxy
+/// (into a resource created as `proxy_resource_id`), and the proxy then
+/// transfers the full resource to the caller (via the `handoff_id` event). The
+/// credits are spent from the caller's `account`, and recorded as destroyed in
+/// the company's `treasury`.
+///
+/// The proxy's resource is left empty once the handoff completes, and is
+/// returned already marked as deleted.
+///
+/// [order::purchase]: ../../transactions/order/fn.purchase.html
+pub fn purchase<T: Into<NumericUnion>>(proxy: &Proxy, caller: &User, account: Account, company: &Company, treasury: CompanyTreasury, offer: &Intent, id: EventID, handoff_id: EventID, proxy_resource_id: ResourceID, resource_from: Resource, resource_to: ResourceMover, move_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::OrderPurchase)?;
+    if !proxy.user().is_active() {
+        Err(Error::ObjectIsInactive("proxy".into()))?;
+    }
+    if proxy.user().id() == caller.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let purchase_mods = order::purchase_for(caller, proxy.user(), account, company, treasury, offer, id, resource_from, ResourceMover::Create(proxy_resource_id.clone()), move_measure, note, now)?.into_vec();
+
+    let mut proxy_resource = None;
+    for modification in &purchase_mods {
+        if let (Op::Create, Model::Resource(resource)) = (modification.op(), modification.model()) {
+            if resource.id() == &proxy_resource_id {
+                proxy_resource = Some(resource.clone());
+            }
+        }
+    }
+    let mut proxy_resource = proxy_resource.ok_or(Error::MissingFields(vec!["proxy_resource".into()]))?;
+    let measure = proxy_resource.inner().accounting_quantity().clone().ok_or(Error::ResourceMeasureMissing)?;
+    let move_costs = proxy_resource.costs().clone();
+
+    let mut statebuilder = EventProcessState::builder()
+        .resource(proxy_resource.clone());
+    let resource_to_id = match resource_to {
+        ResourceMover::Create(resource_id) => resource_id,
+        ResourceMover::Update(resource) => {
+            let resource_id = resource.id().clone();
+            statebuilder = statebuilder.to_resource(resource);
+            resource_id
+        }
+    };
+    let state = statebuilder
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let handoff = Event::builder()
+        .id(handoff_id)
+        .inner(
+            vf::EconomicEvent::builder()
+                .action(vf::Action::Transfer)
+                .has_point_in_time(now.clone())
+                .provider(proxy.user().id().clone())
+                .receiver(caller.id().clone())
+                .resource_inventoried_as(Some(proxy_resource_id.clone()))
+                .resource_quantity(Some(measure))
+                .to_resource_inventoried_as(Some(resource_to_id))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(Some(move_costs))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let handoff_mods = handoff.process(state, now)?.into_vec();
+
+    // the proxy's resource is fully spent by the handoff, so rather than
+    // creating it and then updating it, we create it in its final state.
+    let mut resource_mods = Vec::new();
+    for modification in handoff_mods {
+        match modification.into_pair() {
+            (Op::Update, Model::Resource(resource)) if resource.id() == &proxy_resource_id => {
+                proxy_resource = resource;
+            }
+            (op, model) => resource_mods.push((op, model)),
+        }
+    }
+    proxy_resource.set_deleted(Some(now.clone()));
+
+    let mut mods = Modifications::new();
+    for modification in purchase_mods {
+        match modification.into_pair() {
+            (Op::Create, Model::Resource(resource)) if resource.id() == &proxy_resource_id => {
+                mods.push(Op::Create, proxy_resource.clone());
+            }
+            (op, model) => mods.push(op, model),
+        }
+    }
+    mods.push(Op::Create, handoff);
+    for (op, model) in resource_mods {
+        mods.push(op, model);
+    }
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            account::AccountID,
+            company::CompanyID,
+            intent::IntentID,
+            lib::agent::Agent,
+        },
+        util::{self, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn can_purchase() {
+        let now = util::time::now();
+        let proxy = Proxy::new(UserID::create(), &now).unwrap();
+        let user = make_user(&UserID::create(), Some(vec![Role::User]), &now);
+        let account = make_account(&AccountID::create(), user.id(), num!(100), "jerry's account", &now);
+        let mut company = make_company(&CompanyID::create(), "jerry's widgets", &now);
+        company.set_total_costs(Costs::new_with_labor("machinist", num!(500)));
+        let resource = make_resource(&ResourceID::new("widgets"), company.id(), &Measure::new(num!(10), Unit::One), &Costs::new_with_labor("machinist", num!(300)), &now);
+        let treasury = make_company_treasury(company.id(), num!(1000), &now);
+        let offer = Intent::builder()
+            .id(IntentID::create())
+            .inner(
+                vf::Intent::builder()
+                    .action(vf::Action::Transfer)
+                    .provider(Some(company.agent_id()))
+                    .resource_inventoried_as(Some(resource.id().clone()))
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let id = EventID::create();
+        let handoff_id = EventID::create();
+        let proxy_resource_id = ResourceID::create();
+        let user_resource_id = ResourceID::new("my widgets");
+
+        let testfn = |proxy: &Proxy, user: &User, quantity: i64| {
+            purchase(proxy, user, account.clone(), &company, treasury.clone(), &offer, id.clone(), handoff_id.clone(), proxy_resource_id.clone(), resource.clone(), ResourceMover::Create(user_resource_id.clone()), quantity, None, &now)
+        };
+
+        let mods = testfn(&proxy, &user, 2).unwrap().into_vec();
+        assert_eq!(mods.len(), 8);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let proxy_resource = mods[2].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let account2 = mods[3].clone().expect_op::<Account>(Op::Update).unwrap();
+        let company2 = mods[4].clone().expect_op::<Company>(Op::Update).unwrap();
+        let treasury2 = mods[5].clone().expect_op::<CompanyTreasury>(Op::Update).unwrap();
+        let handoff = mods[6].clone().expect_op::<Event>(Op::Create).unwrap();
+        let user_resource = mods[7].clone().expect_op::<Resource>(Op::Create).unwrap();
+
+        // the company only sees the proxy
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().provider(), &company.agent_id());
+        assert_eq!(event.inner().receiver(), &proxy.user().agent_id());
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(60))));
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(8), Unit::One)));
+        assert_eq!(company2.total_costs(), &Costs::new_with_labor("machinist", num!(440)));
+        assert_eq!(treasury2.destroyed(), &num!(60));
+
+        // but the user pays, and ends up with the resource
+        assert_eq!(account2.id(), account.id());
+        assert_eq!(account2.balance(), &num!(40));
+        assert_eq!(handoff.id(), &handoff_id);
+        assert_eq!(handoff.inner().provider(), &proxy.user().agent_id());
+        assert_eq!(handoff.inner().receiver(), &user.agent_id());
+        assert_eq!(handoff.move_costs(), &Some(Costs::new_with_labor("machinist", num!(60))));
+        assert_eq!(user_resource.id(), &user_resource_id);
+        assert_eq!(user_resource.inner().primary_accountable(), &Some(user.agent_id()));
+        assert_eq!(user_resource.in_custody_of(), &user.agent_id());
+        assert_eq!(user_resource.inner().accounting_quantity(), &Some(Measure::new(num!(2), Unit::One)));
+        assert_eq!(user_resource.costs(), &Costs::new_with_labor("machinist", num!(60)));
+
+        // the proxy is left with nothing
+        assert_eq!(proxy_resource.id(), &proxy_resource_id);
+        assert_eq!(proxy_resource.inner().accounting_quantity(), &Some(Measure::new(num!(0), Unit::One)));
+        assert_eq!(proxy_resource.costs(), &Costs::new());
+        assert_eq!(proxy_resource.deleted(), &Some(now.clone()));
+
+        let res = testfn(&proxy, &user, 4);
+        assert_eq!(res, Err(Error::NegativeAccountBalance));
+
+        // only the account's owner can buy through the proxy
+        let user2 = make_user(&UserID::create(), Some(vec![Role::User]), &now);
+        let res = testfn(&proxy, &user2, 2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let proxy2 = Proxy { user: user.clone() };
+        let res = testfn(&proxy2, &user, 2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut proxy3 = proxy.clone();
+        proxy3.user.set_active(false);
+        let res = testfn(&proxy3, &user, 2);
+        assert_eq!(res, Err(Error::ObjectIsInactive("proxy".into())));
+    }
+}
@@ -0,0 +1,236 @@
+//! Scheduling proposes who should work on what.
+//!
+//! Given how many hours each member is available, a company's open processes,
+//! and the labor their process specs' [recipes][1] call for, `schedule()`
+//! greedily assigns members to the labor each process still needs. The result
+//! is only a proposal: it's up to the company to turn the assignments into
+//! commitments (or ignore them entirely).
+//!
+//! The scheduler is deliberately naive. Processes are filled oldest first,
+//! members are used in the order they're given, and a process spec's
+//! `capacity` limits how many of its processes get scheduled at once (the rest
+//! are deferred). It's meant to be something to build on.
+//!
+//! Like the rest of the system module, this doesn't perform any permission
+//! checks and doesn't modify anything.
+//!
+//! [1]: ../../models/process_spec/struct.Recipe.html
+
+use crate::{
+    models::{
+        lib::basis_model::Model,
+        member::{Member, MemberID},
+        occupation::OccupationID,
+        process::{Process, ProcessID},
+        process_spec::ProcessSpec,
+    },
+};
+use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+/// A proposed block of work for one member on one process.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Assignment {
+    /// The member doing the work
+    member_id: MemberID,
+    /// The process being worked on
+    process_id: ProcessID,
+    /// The occupation the member is working as
+    occupation_id: OccupationID,
+    /// How many hours of work
+    hours: Decimal,
+}
+
+/// Labor a process needs that no available member could cover.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Shortfall {
+    /// The process needing labor
+    process_id: ProcessID,
+    /// The occupation needed
+    occupation_id: OccupationID,
+    /// How many hours are left uncovered
+    hours: Decimal,
+}
+
+/// A proposed schedule.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Schedule {
+    /// The proposed work assignments
+    assignments: Vec<Assignment>,
+    /// Labor that couldn't be covered by the available members
+    shortfalls: Vec<Shortfall>,
+    /// Processes left out because their spec is already at capacity
+    deferred: Vec<ProcessID>,
+}
+
+impl Schedule {
+    /// The total hours assigned to the given member
+    pub fn hours_for(&self, member_id: &MemberID) -> Decimal {
+        self.assignments().iter()
+            .filter(|x| x.member_id() == member_id)
+            .fold(Decimal::zero(), |acc, x| acc + x.hours())
+    }
+}
+
+/// Propose a schedule for a set of processes.
+///
+/// `availability` maps members to the number of hours they can work in the
+/// period being scheduled. Members not listed (or not active workers of the
+/// process' company) aren't scheduled.
+///
+/// The labor a process needs is its spec's recipe labor, less the hours
+/// already recorded against the process (its `labor_hours` costs). Processes
+/// without a spec (or whose spec has no recipe) need no labor, and deleted or
+/// finished processes are skipped.
+pub fn schedule(members: &[Member], availability: &HashMap<MemberID, Decimal>, processes: &[Process], specs: &[ProcessSpec]) -> Schedule {
+    let mut remaining = members.iter()
+        .filter(|member| member.is_active())
+        .filter_map(|member| availability.get(member.id()).map(|hours| (member.id().clone(), hours.clone())))
+        .collect::<HashMap<_, _>>();
+    let mut open = processes.iter()
+        .filter(|process| !process.is_deleted() && process.inner().finished() != &Some(true))
+        .collect::<Vec<_>>();
+    open.sort_by(|a, b| a.created().cmp(b.created()));
+
+    let mut schedule = Schedule::default();
+    let mut running: HashMap<_, u32> = HashMap::new();
+    for process in open {
+        let spec = match process.inner().based_on().as_ref().and_then(|spec_id| specs.iter().find(|spec| spec.id() == spec_id)) {
+            Some(spec) => spec,
+            None => continue,
+        };
+        let recipe = match spec.recipe() {
+            Some(recipe) => recipe,
+            None => continue,
+        };
+        let count = running.entry(spec.id().clone()).or_insert(0);
+        if spec.capacity().map(|capacity| *count >= capacity).unwrap_or(false) {
+            schedule.deferred.push(process.id().clone());
+            continue;
+        }
+        *count += 1;
+
+        for labor in recipe.labor() {
+            let worked = process.costs().labor_hours().get(labor.occupation_id()).cloned().unwrap_or(Decimal::zero());
+            let mut needed = labor.hours() - worked;
+            for member in members {
+                if needed <= Decimal::zero() {
+                    break;
+                }
+                if member.company_id().ok().as_ref() != Some(process.company_id()) || !member.has_occupation(labor.occupation_id()) {
+                    continue;
+                }
+                let available = match remaining.get_mut(member.id()) {
+                    Some(available) if *available > Decimal::zero() => available,
+                    _ => continue,
+                };
+                let hours = if *available < needed { available.clone() } else { needed };
+                *available -= hours;
+                needed -= hours;
+                schedule.assignments.push(Assignment {
+                    member_id: member.id().clone(),
+                    process_id: process.id().clone(),
+                    occupation_id: labor.occupation_id().clone(),
+                    hours,
+                });
+            }
+            if needed > Decimal::zero() {
+                schedule.shortfalls.push(Shortfall {
+                    process_id: process.id().clone(),
+                    occupation_id: labor.occupation_id().clone(),
+                    hours: needed,
+                });
+            }
+        }
+    }
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            process_spec::{ProcessSpecID, Recipe, RecipeLabor},
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn schedules() {
+        let now = util::time::now();
+        let company_id = CompanyID::create();
+        let machinist = OccupationID::new("machinist");
+        let welder = OccupationID::new("welder");
+        let mut spec = make_process_spec(&ProcessSpecID::create(), &company_id, "make widgets", true, &now);
+        spec.set_recipe(Some(Recipe::new(vec![], vec![RecipeLabor::new(machinist.clone(), 10), RecipeLabor::new(welder.clone(), 4)], vec![])));
+        spec.set_capacity(Some(2));
+
+        let make_widgets = |id: &str, costs: Costs, created| {
+            let mut process = make_process(&ProcessID::new(id), &company_id, "make widgets", &costs, &created);
+            process.inner_mut().set_based_on(Some(spec.id().clone()));
+            process
+        };
+        let process1 = make_widgets("widgets1", Costs::new_with_labor_hours(machinist.clone(), 4), now.clone());
+        let process2 = make_widgets("widgets2", Costs::new(), now.clone() + chrono::Duration::seconds(1));
+        let process3 = make_widgets("widgets3", Costs::new(), now.clone() + chrono::Duration::seconds(2));
+        let mut process4 = make_widgets("widgets4", Costs::new(), now.clone());
+        process4.inner_mut().set_finished(Some(true));
+        let process5 = make_process(&ProcessID::new("specless"), &company_id, "make chairs", &Costs::new(), &now);
+
+        let member1 = make_member_worker(&MemberID::new("jerry"), &UserID::create(), &company_id, &machinist, vec![], &now);
+        let member2 = make_member_worker(&MemberID::new("larry"), &UserID::create(), &company_id, &machinist, vec![], &now);
+        let member3 = make_member_worker(&MemberID::new("sandra"), &UserID::create(), &company_id, &welder, vec![], &now);
+        let member4 = make_member_worker(&MemberID::new("moonlighter"), &UserID::create(), &CompanyID::create(), &machinist, vec![], &now);
+        let mut availability = HashMap::new();
+        availability.insert(member1.id().clone(), num!(10));
+        availability.insert(member2.id().clone(), num!(5));
+        availability.insert(member3.id().clone(), num!(6));
+        availability.insert(member4.id().clone(), num!(40));
+
+        let members = vec![member1.clone(), member2.clone(), member3.clone(), member4.clone()];
+        // processes are scheduled oldest first, regardless of order given
+        let processes = vec![process3.clone(), process2.clone(), process1.clone(), process4, process5];
+        let schedule = schedule(&members, &availability, &processes, &[spec.clone()]);
+
+        let summary = schedule.assignments().iter()
+            .map(|x| (x.member_id().as_str(), x.process_id().as_str(), x.occupation_id().as_str(), x.hours().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            ("jerry", "widgets1", "machinist", num!(6)),
+            ("sandra", "widgets1", "welder", num!(4)),
+            ("jerry", "widgets2", "machinist", num!(4)),
+            ("larry", "widgets2", "machinist", num!(5)),
+            ("sandra", "widgets2", "welder", num!(2)),
+        ]);
+        let shortfalls = schedule.shortfalls().iter()
+            .map(|x| (x.process_id().as_str(), x.occupation_id().as_str(), x.hours().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(shortfalls, vec![
+            ("widgets2", "machinist", num!(1)),
+            ("widgets2", "welder", num!(2)),
+        ]);
+        assert_eq!(schedule.deferred(), &vec![process3.id().clone()]);
+        assert_eq!(schedule.hours_for(member1.id()), num!(10));
+        assert_eq!(schedule.hours_for(member4.id()), num!(0));
+
+        // inactive members don't get scheduled
+        let mut member1_2 = member1.clone();
+        member1_2.set_active(false);
+        let schedule = super::schedule(&[member1_2], &availability, &[process1.clone()], &[spec.clone()]);
+        assert_eq!(schedule.assignments().len(), 0);
+        assert_eq!(schedule.shortfalls().len(), 2);
+    }
+}
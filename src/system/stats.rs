@@ -0,0 +1,540 @@
+//! Stats aggregate the events recorded in the system into signals that can be
+//! used for planning.
+//!
+//! For instance, `consumption_summary()` rolls up how much of each resource
+//! specification was consumed and produced over a series of periods. This gives
+//! bottom-up planning a picture of actual demand (and supply) to work from.
+//!
+//! `company_summary()` provides the numbers most company dashboards want: labor
+//! hours worked, what was produced, the costs that flowed in and out of the
+//! company, and the value of its inventory and work-in-progress.
+//!
+//! Like the rest of the system module, these functions don't perform any
+//! permission checks and don't modify anything.
+
+use chrono::{DateTime, Duration, Utc};
+use crate::{
+    costs::{Costs, reports},
+    error::{Error, Result},
+    models::{
+        company::Company,
+        event::Event,
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
+        occupation::OccupationID,
+        process::Process,
+        resource::{Resource, ResourceID},
+        resource_spec::ResourceSpecID,
+    },
+    util::measure,
+};
+use getset::Getters;
+use om2::Unit;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use vf_rs::vf;
+
+/// A window of time, split up into equal periods, that stats are gathered
+/// over.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct StatsWindow {
+    /// The start of the window (inclusive)
+    start: DateTime<Utc>,
+    /// The end of the window (exclusive)
+    end: DateTime<Utc>,
+    /// The length of each period within the window. The last period is cut
+    /// short if the window doesn't divide evenly.
+    period: Duration,
+}
+
+impl StatsWindow {
+    /// Create a new stats window. The window must end after it starts and the
+    /// period must be positive.
+    pub fn new(start: DateTime<Utc>, end: DateTime<Utc>, period: Duration) -> Result<Self> {
+        if end <= start || period <= Duration::zero() {
+            Err(Error::StatsWindowInvalid)?;
+        }
+        Ok(Self { start, end, period })
+    }
+
+    /// Get the start/end of each period in this window.
+    pub fn periods(&self) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let mut periods = Vec::new();
+        let mut start = self.start.clone();
+        while start < self.end {
+            let end = std::cmp::min(start + self.period, self.end.clone());
+            periods.push((start, end.clone()));
+            start = end;
+        }
+        periods
+    }
+
+    /// Find which period (by index) the given time falls into, if any.
+    fn period_index(&self, time: &DateTime<Utc>) -> Option<usize> {
+        if time < &self.start || time >= &self.end {
+            return None;
+        }
+        let elapsed = time.clone() - self.start.clone();
+        let period_ms = self.period.num_milliseconds();
+        Some((elapsed.num_milliseconds() / period_ms) as usize)
+    }
+}
+
+/// The quantities consumed and produced of a resource spec in one period.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct PeriodStats {
+    /// The start of this period (inclusive)
+    start: DateTime<Utc>,
+    /// The end of this period (exclusive)
+    end: DateTime<Utc>,
+    /// How much was consumed in this period
+    consumed: Decimal,
+    /// How much was produced in this period
+    produced: Decimal,
+}
+
+/// Consumption/production stats for a single resource spec.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct SpecStats {
+    /// The unit the quantities are measured in (`None` if no events were
+    /// counted for this spec)
+    unit: Option<Unit>,
+    /// Our per-period stats, in order
+    periods: Vec<PeriodStats>,
+}
+
+impl SpecStats {
+    /// Total consumption over the whole window
+    pub fn total_consumed(&self) -> Decimal {
+        self.periods.iter().map(|x| x.consumed().clone()).sum()
+    }
+
+    /// Total production over the whole window
+    pub fn total_produced(&self) -> Decimal {
+        self.periods.iter().map(|x| x.produced().clone()).sum()
+    }
+}
+
+/// Demand (and supply) signals, per resource spec, over a window of time.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct DemandStats {
+    /// Stats for each resource spec that saw any consumption or production
+    by_spec: HashMap<ResourceSpecID, SpecStats>,
+}
+
+/// Grab the time an event happened, going by when it ended (or when it
+/// happened, if it's a point in time).
+fn event_time(event: &Event) -> Option<&DateTime<Utc>> {
+    let inner = event.inner();
+    inner.has_end().as_ref()
+        .or(inner.has_point_in_time().as_ref())
+}
+
+/// Aggregate the quantities consumed and produced per resource spec for each
+/// period in the given window.
+///
+/// Only active, non-pending `consume` and `produce` events with a resource
+/// quantity are counted. An event's resource spec is taken from its
+/// `resource_conforms_to` field, or if that's blank, looked up via the resource
+/// it acts on in `resources`. Events we can't find a spec for are skipped.
+///
+/// Errors if the events for a single spec are measured in different units.
+pub fn consumption_summary(events: &[Event], resources: &[Resource], window: &StatsWindow) -> Result<DemandStats> {
+    let resource_specs = resources.iter()
+        .map(|resource| (resource.id(), resource.inner().conforms_to()))
+        .collect::<HashMap<&ResourceID, &ResourceSpecID>>();
+    let periods = window.periods();
+    let mut by_spec: HashMap<ResourceSpecID, SpecStats> = HashMap::new();
+    for event in events {
+        if !event.is_active() {
+            continue;
+        }
+        let action = event.inner().action();
+        if action != &vf::Action::Consume && action != &vf::Action::Produce {
+            continue;
+        }
+        let idx = match event_time(event).and_then(|time| window.period_index(time)) {
+            Some(idx) => idx,
+            None => continue,
+        };
+        let quantity = match event.inner().resource_quantity() {
+            Some(quantity) => quantity,
+            None => continue,
+        };
+        let spec_id = event.inner().resource_conforms_to().as_ref()
+            .or_else(|| {
+                event.inner().resource_inventoried_as().as_ref()
+                    .and_then(|resource_id| resource_specs.get(resource_id).map(|x| *x))
+            });
+        let spec_id = match spec_id {
+            Some(x) => x,
+            None => continue,
+        };
+        let stats = by_spec.entry(spec_id.clone()).or_insert_with(|| {
+            SpecStats {
+                unit: None,
+                periods: periods.iter()
+                    .map(|(start, end)| PeriodStats {
+                        start: start.clone(),
+                        end: end.clone(),
+                        consumed: Decimal::zero(),
+                        produced: Decimal::zero(),
+                    })
+                    .collect(),
+            }
+        });
+        match &stats.unit {
+            Some(unit) if unit != quantity.has_unit() => Err(Error::MeasureUnitsMismatched)?,
+            _ => stats.unit = Some(quantity.has_unit().clone()),
+        }
+        let amount = measure::to_decimal(quantity)?;
+        let period = &mut stats.periods[idx];
+        if action == &vf::Action::Consume {
+            period.consumed += amount;
+        } else {
+            period.produced += amount;
+        }
+    }
+    Ok(DemandStats { by_spec })
+}
+
+/// A quantity of something, along with the unit it's measured in.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Quantity {
+    /// The unit the quantity is measured in
+    unit: Unit,
+    /// The amount
+    amount: Decimal,
+}
+
+/// Summary statistics for a single company over a window of time.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct CompanySummary {
+    /// Labor hours worked for the company in the window, per occupation
+    labor_hours: HashMap<OccupationID, Decimal>,
+    /// How much of each resource spec the company produced in the window
+    produced: HashMap<ResourceSpecID, Quantity>,
+    /// The number of the company's processes that are still open
+    open_processes: u64,
+    /// Costs moved into the company from other agents in the window (labor,
+    /// transfers in, etc)
+    cost_inflow: Costs,
+    /// Costs moved out of the company to other agents in the window
+    cost_outflow: Costs,
+    /// The costs held in the company's resources
+    inventory: Costs,
+    /// The costs held in the company's unfinished processes
+    wip: Costs,
+}
+
+impl CompanySummary {
+    /// The total labor hours worked across all occupations
+    pub fn total_labor_hours(&self) -> Decimal {
+        self.labor_hours().values().sum()
+    }
+
+    /// The credit value of the company's inventory
+    pub fn inventory_credits(&self) -> &Decimal {
+        self.inventory().credits()
+    }
+}
+
+/// Summarize a company's activity over the given window.
+///
+/// Events are counted if they're active, not pending, and fall within the
+/// window. Labor hours come from the `labor_hours` costs of `work` events the
+/// company received, production from `produce` events the company provided
+/// (the spec is found the same way as `consumption_summary()`). Costs moved
+/// between two agents count as inflow or outflow depending on which side of
+/// the event the company is on; costs moved within the company are ignored.
+///
+/// Inventory and work-in-progress are point-in-time values taken from the
+/// given resources (by `primary_accountable`) and processes, and aren't
+/// limited by the window. Models belonging to other companies are ignored.
+///
+/// Errors if the production of a single spec is measured in different units.
+pub fn company_summary(company: &Company, processes: &[Process], resources: &[Resource], events: &[Event], window: &StatsWindow) -> Result<CompanySummary> {
+    let agent_id = company.agent_id();
+    let resource_specs = resources.iter()
+        .map(|resource| (resource.id(), resource.inner().conforms_to()))
+        .collect::<HashMap<&ResourceID, &ResourceSpecID>>();
+    let mut labor_hours: HashMap<OccupationID, Decimal> = HashMap::new();
+    let mut produced: HashMap<ResourceSpecID, Quantity> = HashMap::new();
+    let mut cost_inflow = Costs::new();
+    let mut cost_outflow = Costs::new();
+    for event in events {
+        if !event.is_active() {
+            continue;
+        }
+        if event_time(event).and_then(|time| window.period_index(time)).is_none() {
+            continue;
+        }
+        let inner = event.inner();
+        let is_provider = inner.provider() == &agent_id;
+        let is_receiver = inner.receiver() == &agent_id;
+        if !is_provider && !is_receiver {
+            continue;
+        }
+        if let Some(move_costs) = event.move_costs() {
+            if is_receiver && !is_provider {
+                cost_inflow = cost_inflow + move_costs.clone();
+            } else if is_provider && !is_receiver {
+                cost_outflow = cost_outflow + move_costs.clone();
+            }
+        }
+        match inner.action() {
+            vf::Action::Work if is_receiver => {
+                if let Some(move_costs) = event.move_costs() {
+                    for (occupation_id, hours) in move_costs.labor_hours() {
+                        *labor_hours.entry(occupation_id.clone()).or_insert(Decimal::zero()) += hours;
+                    }
+                }
+            }
+            vf::Action::Produce if is_provider => {
+                let quantity = match inner.resource_quantity() {
+                    Some(quantity) => quantity,
+                    None => continue,
+                };
+                let spec_id = inner.resource_conforms_to().as_ref()
+                    .or_else(|| {
+                        inner.resource_inventoried_as().as_ref()
+                            .and_then(|resource_id| resource_specs.get(resource_id).map(|x| *x))
+                    });
+                let spec_id = match spec_id {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let amount = measure::to_decimal(quantity)?;
+                let entry = produced.entry(spec_id.clone()).or_insert_with(|| Quantity {
+                    unit: quantity.has_unit().clone(),
+                    amount: Decimal::zero(),
+                });
+                if &entry.unit != quantity.has_unit() {
+                    Err(Error::MeasureUnitsMismatched)?;
+                }
+                entry.amount += amount;
+            }
+            _ => {}
+        }
+    }
+
+    let inventory = reports::inventory_valuation(resources).by_company().get(company.id())
+        .cloned()
+        .unwrap_or_else(Costs::new);
+    let company_processes = processes.iter()
+        .filter(|process| process.company_id() == company.id())
+        .cloned()
+        .collect::<Vec<_>>();
+    let wip = reports::wip(&company_processes, window.end());
+    Ok(CompanySummary {
+        labor_hours,
+        produced,
+        open_processes: *wip.processes(),
+        cost_inflow,
+        cost_outflow,
+        inventory,
+        wip: wip.total().clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            event::EventID,
+            lib::agent::AgentID,
+            member::MemberID,
+            process::ProcessID,
+        },
+        util::{self, test::*},
+    };
+    use om2::Measure;
+
+    fn make_event(action: vf::Action, resource_id: &ResourceID, conforms_to: Option<&str>, quantity: Measure, at: &str) -> Event {
+        let now = util::time::now();
+        Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action)
+                    .has_point_in_time(at.parse::<DateTime<Utc>>().unwrap())
+                    .provider(CompanyID::new("jerry's widgets"))
+                    .receiver(CompanyID::new("jerry's widgets"))
+                    .resource_conforms_to(conforms_to.map(|x| x.into()))
+                    .resource_inventoried_as(Some(resource_id.clone()))
+                    .resource_quantity(Some(quantity))
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn windows() {
+        let start: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let end: DateTime<Utc> = "2020-01-10T00:00:00Z".parse().unwrap();
+        let window = StatsWindow::new(start.clone(), end.clone(), Duration::days(4)).unwrap();
+        let periods = window.periods();
+        assert_eq!(periods.len(), 3);
+        assert_eq!(periods[0], (start.clone(), "2020-01-05T00:00:00Z".parse().unwrap()));
+        assert_eq!(periods[2], ("2020-01-09T00:00:00Z".parse().unwrap(), end.clone()));
+        assert_eq!(window.period_index(&start), Some(0));
+        assert_eq!(window.period_index(&"2020-01-09T12:00:00Z".parse().unwrap()), Some(2));
+        assert_eq!(window.period_index(&end), None);
+
+        assert_eq!(StatsWindow::new(end.clone(), start.clone(), Duration::days(1)), Err(Error::StatsWindowInvalid));
+        assert_eq!(StatsWindow::new(start.clone(), end.clone(), Duration::zero()), Err(Error::StatsWindowInvalid));
+    }
+
+    #[test]
+    fn summarizes_consumption() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let steel = make_resource(&ResourceID::new("steel"), &company_id, &Measure::new(num!(100), Unit::Kilogram), &Costs::new(), &now);
+        let steel_spec = steel.inner().conforms_to().clone();
+        let widget_id = ResourceID::new("widget");
+        let window = StatsWindow::new("2020-01-01T00:00:00Z".parse().unwrap(), "2020-01-15T00:00:00Z".parse().unwrap(), Duration::weeks(1)).unwrap();
+
+        let mut deleted = make_event(vf::Action::Consume, steel.id(), None, Measure::new(num!(1000), Unit::Kilogram), "2020-01-02T00:00:00Z");
+        deleted.set_deleted(Some(now.clone()));
+        let events = vec![
+            make_event(vf::Action::Consume, steel.id(), None, Measure::new(num!(10), Unit::Kilogram), "2020-01-02T00:00:00Z"),
+            make_event(vf::Action::Consume, steel.id(), None, Measure::new(num!(5.5), Unit::Kilogram), "2020-01-03T00:00:00Z"),
+            make_event(vf::Action::Consume, steel.id(), None, Measure::new(num!(7), Unit::Kilogram), "2020-01-09T00:00:00Z"),
+            make_event(vf::Action::Produce, steel.id(), None, Measure::new(num!(50), Unit::Kilogram), "2020-01-10T00:00:00Z"),
+            make_event(vf::Action::Produce, &widget_id, Some("widget"), Measure::new(num!(12), Unit::One), "2020-01-04T00:00:00Z"),
+            // outside the window
+            make_event(vf::Action::Consume, steel.id(), None, Measure::new(num!(99), Unit::Kilogram), "2020-01-20T00:00:00Z"),
+            // not consume/produce
+            make_event(vf::Action::Lower, steel.id(), None, Measure::new(num!(99), Unit::Kilogram), "2020-01-02T00:00:00Z"),
+            // no way to find the spec
+            make_event(vf::Action::Consume, &ResourceID::new("mystery"), None, Measure::new(num!(99), Unit::Kilogram), "2020-01-02T00:00:00Z"),
+            deleted,
+        ];
+        let stats = consumption_summary(&events, &vec![steel.clone()], &window).unwrap();
+        assert_eq!(stats.by_spec().len(), 2);
+
+        let steel_stats = stats.by_spec().get(&steel_spec).unwrap();
+        assert_eq!(steel_stats.unit(), &Some(Unit::Kilogram));
+        assert_eq!(steel_stats.periods().len(), 2);
+        assert_eq!(steel_stats.periods()[0].consumed(), &num!(15.5));
+        assert_eq!(steel_stats.periods()[0].produced(), &num!(0));
+        assert_eq!(steel_stats.periods()[1].consumed(), &num!(7));
+        assert_eq!(steel_stats.periods()[1].produced(), &num!(50));
+        assert_eq!(steel_stats.total_consumed(), num!(22.5));
+        assert_eq!(steel_stats.total_produced(), num!(50));
+
+        let widget_stats = stats.by_spec().get(&ResourceSpecID::new("widget")).unwrap();
+        assert_eq!(widget_stats.unit(), &Some(Unit::One));
+        assert_eq!(widget_stats.total_consumed(), num!(0));
+        assert_eq!(widget_stats.periods()[0].produced(), &num!(12));
+
+        let mut events2 = events.clone();
+        events2.push(make_event(vf::Action::Consume, steel.id(), None, Measure::new(num!(3), Unit::Gram), "2020-01-02T00:00:00Z"));
+        assert_eq!(consumption_summary(&events2, &vec![steel.clone()], &window), Err(Error::MeasureUnitsMismatched));
+    }
+
+    fn make_flow(action: vf::Action, provider: AgentID, receiver: AgentID, costs: Option<Costs>, quantity: Option<Measure>, at: &str) -> Event {
+        let now = util::time::now();
+        Event::builder()
+            .id(EventID::create())
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(action)
+                    .has_point_in_time(at.parse::<DateTime<Utc>>().unwrap())
+                    .provider(provider)
+                    .receiver(receiver)
+                    .resource_conforms_to(quantity.as_ref().map(|_| "widget".into()))
+                    .resource_quantity(quantity)
+                    .build().unwrap()
+            )
+            .move_costs(costs)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn summarizes_company() {
+        let now = util::time::now();
+        let company = make_company(&CompanyID::new("jerry's widgets"), "jerry's widgets", &now);
+        let other = make_company(&CompanyID::new("larry's steel"), "larry's steel", &now);
+        let worker: AgentID = MemberID::new("jerry").into();
+        let us = company.agent_id();
+        let them = other.agent_id();
+        let window = StatsWindow::new("2020-01-01T00:00:00Z".parse().unwrap(), "2020-01-15T00:00:00Z".parse().unwrap(), Duration::weeks(1)).unwrap();
+
+        let mut labor1 = Costs::new_with_labor("machinist", num!(100));
+        labor1.track_labor_hours("machinist", num!(4));
+        let mut labor2 = Costs::new_with_labor("welder", num!(60));
+        labor2.track_labor_hours("welder", num!(2));
+        let mut labor3 = Costs::new_with_labor("machinist", num!(50));
+        labor3.track_labor_hours("machinist", num!(2));
+        let mut inactive = make_flow(vf::Action::Work, worker.clone(), us.clone(), Some(labor1.clone()), None, "2020-01-02T00:00:00Z");
+        inactive.set_active(false);
+        let events = vec![
+            make_flow(vf::Action::Work, worker.clone(), us.clone(), Some(labor1.clone()), None, "2020-01-02T00:00:00Z"),
+            make_flow(vf::Action::Work, worker.clone(), us.clone(), Some(labor2.clone()), None, "2020-01-03T00:00:00Z"),
+            make_flow(vf::Action::Work, worker.clone(), us.clone(), Some(labor3.clone()), None, "2020-01-10T00:00:00Z"),
+            make_flow(vf::Action::TransferAllRights, them.clone(), us.clone(), Some(Costs::new_with_labor("miner", num!(30))), None, "2020-01-04T00:00:00Z"),
+            make_flow(vf::Action::TransferAllRights, us.clone(), them.clone(), Some(Costs::new_with_labor("machinist", num!(25))), None, "2020-01-05T00:00:00Z"),
+            // internal, doesn't count as flow
+            make_flow(vf::Action::Produce, us.clone(), us.clone(), Some(Costs::new_with_labor("machinist", num!(80))), Some(Measure::new(num!(8), Unit::One)), "2020-01-06T00:00:00Z"),
+            make_flow(vf::Action::Produce, us.clone(), us.clone(), None, Some(Measure::new(num!(3), Unit::One)), "2020-01-12T00:00:00Z"),
+            // outside the window
+            make_flow(vf::Action::Work, worker.clone(), us.clone(), Some(labor1.clone()), None, "2020-01-20T00:00:00Z"),
+            // someone else's
+            make_flow(vf::Action::Work, worker.clone(), them.clone(), Some(labor1.clone()), None, "2020-01-02T00:00:00Z"),
+            make_flow(vf::Action::Produce, them.clone(), them.clone(), None, Some(Measure::new(num!(99), Unit::One)), "2020-01-06T00:00:00Z"),
+            inactive,
+        ];
+
+        let resource1 = make_resource(&ResourceID::new("widgets"), company.id(), &Measure::new(num!(10), Unit::One), &Costs::new_with_labor("machinist", num!(40)), &now);
+        let resource2 = make_resource(&ResourceID::new("steel"), other.id(), &Measure::new(num!(10), Unit::Kilogram), &Costs::new_with_labor("miner", num!(12)), &now);
+        let process1 = make_process(&ProcessID::new("make widgets"), company.id(), "make widgets", &Costs::new_with_labor("welder", num!(20)), &now);
+        let mut process2 = make_process(&ProcessID::new("make more widgets"), company.id(), "make widgets", &Costs::new_with_labor("welder", num!(7)), &now);
+        process2.inner_mut().set_finished(Some(true));
+        let process3 = make_process(&ProcessID::new("make steel"), other.id(), "make steel", &Costs::new_with_labor("miner", num!(9)), &now);
+
+        let summary = company_summary(&company, &[process1, process2, process3], &[resource1, resource2], &events, &window).unwrap();
+        assert_eq!(summary.labor_hours().len(), 2);
+        assert_eq!(summary.labor_hours().get(&OccupationID::new("machinist")), Some(&num!(6)));
+        assert_eq!(summary.labor_hours().get(&OccupationID::new("welder")), Some(&num!(2)));
+        assert_eq!(summary.total_labor_hours(), num!(8));
+        assert_eq!(summary.produced().len(), 1);
+        let widgets = summary.produced().get(&ResourceSpecID::new("widget")).unwrap();
+        assert_eq!(widgets.unit(), &Unit::One);
+        assert_eq!(widgets.amount(), &num!(11));
+        assert_eq!(summary.open_processes(), &1);
+        assert_eq!(summary.cost_inflow(), &(labor1 + labor2 + labor3 + Costs::new_with_labor("miner", num!(30))));
+        assert_eq!(summary.cost_outflow(), &Costs::new_with_labor("machinist", num!(25)));
+        assert_eq!(summary.inventory(), &Costs::new_with_labor("machinist", num!(40)));
+        assert_eq!(summary.inventory_credits(), &num!(40));
+        assert_eq!(summary.wip(), &Costs::new_with_labor("welder", num!(20)));
+
+        let mut events2 = events.clone();
+        events2.push(make_flow(vf::Action::Produce, us.clone(), us.clone(), None, Some(Measure::new(num!(3), Unit::Kilogram)), "2020-01-12T00:00:00Z"));
+        assert_eq!(company_summary(&company, &[], &[], &events2, &window), Err(Error::MeasureUnitsMismatched));
+    }
+}
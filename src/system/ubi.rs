@@ -1,9 +1,28 @@
 //! Defines systemic parameters for the Basis UBI, such as how much is paid
 //! over time and the upper ceiling on UBI accounts (to prevent endless
 //! accumulation).
+//!
+//! This module also handles issuing UBI into accounts. UBI can be issued by the
+//! system on a recurring basis (see `schedule()` and `issue()`) or claimed by
+//! an account's owners (`claim()`). Each issuance moves the account's last
+//! claim date forward and creates a `UbiIssuance` record, so the same period
+//! can never be paid out twice.
 
+use chrono::{DateTime, Duration, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Modifications,
+        Op,
+        account::{Account, Ubi},
+        lib::basis_model::Model,
+        ubi_issuance::{UbiIssuance, UbiIssuanceID},
+        user::User,
+    },
+};
 use getset::{Getters, Setters};
-use rust_decimal::Decimal;
+use rust_decimal::prelude::*;
 #[cfg(feature = "with_serde")]
 use serde::{Serialize, Deserialize};
 
@@ -25,3 +44,240 @@ impl UBIParameters {
     }
 }
 
+/// A period of time UBI can be issued for.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct UbiPeriod {
+    /// When the period starts
+    start: DateTime<Utc>,
+    /// When the period ends
+    end: DateTime<Utc>,
+}
+
+/// Grab the UBI info from an account, erroring if it's not a UBI account.
+fn get_ubi(account: &Account) -> Result<Ubi> {
+    account.ubi().clone().ok_or(Error::UBIAccountRequired)
+}
+
+/// Deposit UBI for the given period into an account and record the issuance.
+///
+/// The period must begin at or after the account's last claim, which makes it
+/// impossible to issue UBI for the same stretch of time twice, and can't end
+/// after `now`, so UBI can't be issued ahead of time.
+fn issue_inner(ubi_params: &UBIParameters, id: UbiIssuanceID, mut account: Account, period_start: &DateTime<Utc>, period_end: &DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    if account.is_deleted() {
+        Err(Error::ObjectIsDeleted("account".into()))?;
+    }
+    let mut ubi = get_ubi(&account)?;
+    if period_end <= period_start || period_end > now {
+        Err(Error::UBIPeriodInvalid)?;
+    }
+    if period_start < ubi.last_claim() {
+        Err(Error::UBIAlreadyIssued)?;
+    }
+    let days = Decimal::from(period_end.timestamp() - period_start.timestamp()) / num!(86400);
+    let accrued = days * ubi_params.balance_per_day().clone();
+    let room = (ubi_params.ceiling().clone() - account.balance().clone()).max(Decimal::zero());
+    let amount = accrued.min(room).normalize();
+    account.move_credits(amount, now)?;
+    ubi.set_last_claim(period_end.clone());
+    account.set_ubi(Some(ubi));
+    account.set_updated(now.clone());
+
+    let issuance = UbiIssuance::builder()
+        .id(id)
+        .account_id(account.id().clone())
+        .period_start(period_start.clone())
+        .period_end(period_end.clone())
+        .amount(amount)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, account);
+    mods.push(Op::Create, issuance);
+    Ok(mods)
+}
+
+/// Returns the full days of UBI an account is owed between its last claim and
+/// `until`, one period per day. Any leftover partial day is not included.
+///
+/// This is meant to be used by whatever drives UBI on a recurring basis: each
+/// period returned can be passed to `issue()`.
+pub fn schedule(account: &Account, until: &DateTime<Utc>) -> Result<Vec<UbiPeriod>> {
+    let ubi = get_ubi(account)?;
+    let mut periods = Vec::new();
+    let mut start = ubi.last_claim().clone();
+    loop {
+        let end = start + Duration::days(1);
+        if &end > until {
+            break;
+        }
+        periods.push(UbiPeriod { start, end });
+        start = end;
+    }
+    Ok(periods)
+}
+
+/// Issue UBI to an account for a specific period. This is meant to be run by
+/// the system (or a bank) on a recurring basis, generally using the periods
+/// returned from `schedule()`.
+pub fn issue(caller: &User, ubi_params: &UBIParameters, id: UbiIssuanceID, account: Account, period: &UbiPeriod, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::AccountUBIIssue)?;
+    issue_inner(ubi_params, id, account, period.start(), period.end(), now)
+}
+
+/// Claim all UBI accrued on an account since its last claim. Only the account's
+/// owners can claim.
+pub fn claim(caller: &User, ubi_params: &UBIParameters, id: UbiIssuanceID, account: Account, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::AccountUBIClaim)?;
+    if !account.user_ids().contains(caller.id()) {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let ubi = get_ubi(&account)?;
+    if now <= ubi.last_claim() {
+        Err(Error::UBIAlreadyIssued)?;
+    }
+    issue_inner(ubi_params, id, account, &ubi.last_claim().clone(), now, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        models::{
+            account::AccountID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+
+    fn make_ubi_account(user_id: &UserID, balance: Decimal) -> Account {
+        let now = util::time::now();
+        let mut account = make_account(&AccountID::create(), user_id, balance, "ubi", &now);
+        account.set_ubi(Some(Ubi::new("2020-01-01T00:00:00Z".parse().unwrap())));
+        account
+    }
+
+    fn make_params() -> UBIParameters {
+        let mut ubi_params = UBIParameters::new();
+        ubi_params.set_ceiling(num!(100));
+        ubi_params.set_balance_per_day(num!(10));
+        ubi_params
+    }
+
+    #[test]
+    fn schedules() {
+        let user = make_user(&UserID::create(), None, &util::time::now());
+        let account = make_ubi_account(user.id(), num!(0));
+        let periods = schedule(&account, &"2020-01-03T12:00:00Z".parse().unwrap()).unwrap();
+        assert_eq!(periods.len(), 2);
+        assert_eq!(periods[0].start(), &"2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(periods[0].end(), &"2020-01-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(periods[1].start(), &"2020-01-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(periods[1].end(), &"2020-01-03T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+
+        let periods = schedule(&account, &"2020-01-01T23:59:59Z".parse().unwrap()).unwrap();
+        assert_eq!(periods.len(), 0);
+
+        let mut account2 = account.clone();
+        account2.set_ubi(None);
+        let res = schedule(&account2, &"2020-01-03T12:00:00Z".parse().unwrap());
+        assert_eq!(res, Err(Error::UBIAccountRequired));
+    }
+
+    #[test]
+    fn can_issue() {
+        let now = util::time::now();
+        let bank = make_user(&UserID::create(), Some(vec![Role::Bank]), &now);
+        let user = make_user(&UserID::create(), None, &now);
+        let account = make_ubi_account(user.id(), num!(0));
+        let ubi_params = make_params();
+        let periods = schedule(&account, &"2020-01-03T00:00:00Z".parse().unwrap()).unwrap();
+        let id = UbiIssuanceID::create();
+
+        let mods = issue(&bank, &ubi_params, id.clone(), account.clone(), &periods[0], &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let account2 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        let issuance = mods[1].clone().expect_op::<UbiIssuance>(Op::Create).unwrap();
+        assert_eq!(account2.balance(), &num!(10));
+        assert_eq!(account2.ubi().as_ref().unwrap().last_claim(), periods[0].end());
+        assert_eq!(account2.updated(), &now);
+        assert_eq!(issuance.id(), &id);
+        assert_eq!(issuance.account_id(), account.id());
+        assert_eq!(issuance.period_start(), periods[0].start());
+        assert_eq!(issuance.period_end(), periods[0].end());
+        assert_eq!(issuance.amount(), &num!(10));
+        assert_eq!(issuance.created(), &now);
+
+        // can't issue the same period twice
+        let res = issue(&bank, &ubi_params, UbiIssuanceID::create(), account2.clone(), &periods[0], &now);
+        assert_eq!(res, Err(Error::UBIAlreadyIssued));
+
+        let mods = issue(&bank, &ubi_params, UbiIssuanceID::create(), account2.clone(), &periods[1], &now).unwrap().into_vec();
+        let account3 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(account3.balance(), &num!(20));
+
+        // the ceiling limits how much is issued
+        let mut account4 = account2.clone();
+        account4.set_balance(num!(95));
+        let mods = issue(&bank, &ubi_params, UbiIssuanceID::create(), account4, &periods[1], &now).unwrap().into_vec();
+        let account5 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        let issuance2 = mods[1].clone().expect_op::<UbiIssuance>(Op::Create).unwrap();
+        assert_eq!(account5.balance(), &num!(100));
+        assert_eq!(issuance2.amount(), &num!(5));
+
+        let backwards = UbiPeriod { start: periods[1].end().clone(), end: periods[1].start().clone() };
+        let res = issue(&bank, &ubi_params, UbiIssuanceID::create(), account2.clone(), &backwards, &now);
+        assert_eq!(res, Err(Error::UBIPeriodInvalid));
+
+        // periods that haven't ended yet can't be issued
+        let res = issue(&bank, &ubi_params, UbiIssuanceID::create(), account2.clone(), &periods[1], &"2020-01-02T23:59:59Z".parse().unwrap());
+        assert_eq!(res, Err(Error::UBIPeriodInvalid));
+        let res = issue(&bank, &ubi_params, UbiIssuanceID::create(), account2.clone(), &periods[1], periods[1].end());
+        assert!(res.is_ok());
+
+        let res = issue(&user, &ubi_params, UbiIssuanceID::create(), account.clone(), &periods[0], &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut account6 = account.clone();
+        account6.set_ubi(None);
+        let res = issue(&bank, &ubi_params, UbiIssuanceID::create(), account6, &periods[0], &now);
+        assert_eq!(res, Err(Error::UBIAccountRequired));
+
+        let mut account7 = account.clone();
+        account7.set_deleted(Some(now.clone()));
+        let res = issue(&bank, &ubi_params, UbiIssuanceID::create(), account7, &periods[0], &now);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("account".into())));
+    }
+
+    #[test]
+    fn can_claim() {
+        let user = make_user(&UserID::create(), None, &util::time::now());
+        let account = make_ubi_account(user.id(), num!(0));
+        let ubi_params = make_params();
+        let now: DateTime<Utc> = "2020-01-04T12:00:00Z".parse().unwrap();
+
+        let mods = claim(&user, &ubi_params, UbiIssuanceID::create(), account.clone(), &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let account2 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        let issuance = mods[1].clone().expect_op::<UbiIssuance>(Op::Create).unwrap();
+        assert_eq!(account2.balance(), &num!(35));
+        assert_eq!(account2.ubi().as_ref().unwrap().last_claim(), &now);
+        assert_eq!(issuance.period_start(), &"2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(issuance.period_end(), &now);
+        assert_eq!(issuance.amount(), &num!(35));
+
+        // claiming again right away does nothing
+        let res = claim(&user, &ubi_params, UbiIssuanceID::create(), account2.clone(), &now);
+        assert_eq!(res, Err(Error::UBIAlreadyIssued));
+
+        let user2 = make_user(&UserID::create(), None, &now);
+        let res = claim(&user2, &ubi_params, UbiIssuanceID::create(), account.clone(), &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
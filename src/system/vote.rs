@@ -6,6 +6,12 @@
 //! The idea here is to provide an interface for democracy without the core
 //! needing to know the implementation details.
 //!
+//! This module also provides the primitives for the votes themselves: a
+//! `Proposal` is put forward (`propose()`), voters cast a `Ballot` on it
+//! (`cast()`), and once voting ends the ballots are counted (`tally()`) and the
+//! outcome is recorded on the proposal (`close()`). Proposals can be systemic
+//! or scoped to a single company or region.
+//!
 //! ```rust
 //! use basis_core::{
 //!     access::Role,
@@ -39,16 +45,27 @@
 
 use chrono::{DateTime, Utc};
 use crate::{
-    access::Role,
+    access::{Permission, Role},
     error::{Error, Result},
     models::{
+        Modifications,
+        Op,
+        ballot::{Ballot, BallotChoice, BallotID},
         company::{CompanyID, Permission as CompanyPermission},
-        lib::agent::{Agent, AgentID},
+        lib::{
+            agent::{Agent, AgentID},
+            basis_model::Model,
+        },
         member::*,
+        proposal::{Proposal, ProposalID, ProposalOutcome, VoteRules, VoteScope},
         user::{User, UserID},
     },
 };
 use getset::Getters;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
 use vf_rs::vf;
 
 /// An object that holds information about a voting user as well as any extra
@@ -128,11 +145,186 @@ impl Vote {
     }
 }
 
+/// The result of counting the ballots on a proposal.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Tally {
+    /// Number of yes votes
+    yes: u64,
+    /// Number of no votes
+    no: u64,
+    /// Number of abstentions
+    abstain: u64,
+    /// The number of voters eligible to vote on the proposal
+    eligible: u64,
+    /// The outcome given the proposal's rules
+    outcome: ProposalOutcome,
+}
+
+/// Make sure the caller is allowed to do something within a proposal's scope.
+/// Company-scoped proposals require a member of that company, and
+/// region-scoped proposals require a user living in that region.
+fn scope_check(caller: &User, member: Option<&Member>, scope: &VoteScope, permission: Permission, company_permission: CompanyPermission) -> Result<()> {
+    caller.access_check(permission)?;
+    match scope {
+        VoteScope::Systemic => {}
+        VoteScope::Company(company_id) => {
+            let member = member.ok_or(Error::InsufficientPrivileges)?;
+            member.access_check(caller.id(), company_id, company_permission)?;
+        }
+        VoteScope::Region(region_id) => {
+            if caller.region_id().as_ref() != Some(region_id) {
+                Err(Error::InsufficientPrivileges)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Put forward a new proposal to be voted on.
+pub fn propose<T: Into<String>>(caller: &User, member: Option<&Member>, id: ProposalID, scope: VoteScope, name: T, description: T, rules: VoteRules, closes: DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    scope_check(caller, member, &scope, Permission::ProposalCreate, CompanyPermission::ProposalCreate)?;
+    if &closes <= now {
+        Err(Error::ProposalClosed)?;
+    }
+    let model = Proposal::builder()
+        .id(id)
+        .proposer(caller.id().clone())
+        .scope(scope)
+        .name(name)
+        .description(description)
+        .rules(rules)
+        .closes(closes)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Cast a ballot on a proposal. If the voter has already cast a ballot on this
+/// proposal, pass it in as `existing_ballot` and it will be updated with the
+/// new choice (`id` is ignored in this case).
+pub fn cast(caller: &User, member: Option<&Member>, proposal: &Proposal, id: BallotID, existing_ballot: Option<Ballot>, choice: BallotChoice, now: &DateTime<Utc>) -> Result<Modifications> {
+    scope_check(caller, member, proposal.scope(), Permission::ProposalVote, CompanyPermission::ProposalVote)?;
+    if !proposal.is_open(now) {
+        Err(Error::ProposalClosed)?;
+    }
+    match existing_ballot {
+        Some(mut ballot) => {
+            if ballot.proposal_id() != proposal.id() || ballot.voter() != caller.id() {
+                Err(Error::InsufficientPrivileges)?;
+            }
+            if ballot.is_deleted() {
+                Err(Error::ObjectIsDeleted("ballot".into()))?;
+            }
+            ballot.set_choice(choice);
+            ballot.set_updated(now.clone());
+            Ok(Modifications::new_single(Op::Update, ballot))
+        }
+        None => {
+            let model = Ballot::builder()
+                .id(id)
+                .proposal_id(proposal.id().clone())
+                .voter(caller.id().clone())
+                .choice(choice)
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?;
+            Ok(Modifications::new_single(Op::Create, model))
+        }
+    }
+}
+
+/// Count the ballots on a proposal against its rules.
+///
+/// Ballots for other proposals and deleted ballots are ignored. If a voter
+/// somehow has more than one ballot, only their most recently updated ballot
+/// is counted. `eligible_voters` is the number of users (or company members,
+/// or users in the region) that were able to vote, and is used to determine quorum.
+pub fn tally(proposal: &Proposal, ballots: &[Ballot], eligible_voters: u64) -> Tally {
+    let mut latest: HashMap<&UserID, &Ballot> = HashMap::new();
+    for ballot in ballots {
+        if ballot.proposal_id() != proposal.id() || ballot.is_deleted() {
+            continue;
+        }
+        match latest.get(ballot.voter()) {
+            Some(existing) if existing.updated() >= ballot.updated() => {}
+            _ => { latest.insert(ballot.voter(), ballot); }
+        }
+    }
+    let (mut yes, mut no, mut abstain) = (0u64, 0u64, 0u64);
+    for ballot in latest.values() {
+        match ballot.choice() {
+            BallotChoice::Yes => yes += 1,
+            BallotChoice::No => no += 1,
+            BallotChoice::Abstain => abstain += 1,
+        }
+    }
+    let cast = yes + no + abstain;
+    let quorum_met = if eligible_voters == 0 {
+        proposal.rules().quorum().is_zero()
+    } else {
+        Decimal::from(cast) / Decimal::from(eligible_voters) >= proposal.rules().quorum().clone()
+    };
+    let outcome = if !quorum_met {
+        ProposalOutcome::NoQuorum
+    } else if yes + no > 0 && Decimal::from(yes) / Decimal::from(yes + no) > proposal.rules().threshold().clone() {
+        ProposalOutcome::Passed
+    } else {
+        ProposalOutcome::Failed
+    };
+    Tally {
+        yes,
+        no,
+        abstain,
+        eligible: eligible_voters,
+        outcome,
+    }
+}
+
+/// Close a proposal once voting has ended, tallying its ballots and recording
+/// the outcome.
+///
+/// Every ballot passed in must belong to the proposal and no voter can appear
+/// more than once, so a caller can't sway the outcome by padding the list.
+pub fn close(caller: &User, member: Option<&Member>, mut subject: Proposal, ballots: &[Ballot], eligible_voters: u64, now: &DateTime<Utc>) -> Result<Modifications> {
+    scope_check(caller, member, subject.scope(), Permission::ProposalClose, CompanyPermission::ProposalClose)?;
+    let mut voters = HashSet::new();
+    for ballot in ballots {
+        if ballot.proposal_id() != subject.id() || !voters.insert(ballot.voter()) {
+            Err(Error::BallotsInvalid)?;
+        }
+    }
+    if ballots.len() as u64 > eligible_voters {
+        Err(Error::BallotsInvalid)?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("proposal".into()))?;
+    }
+    if subject.outcome().is_some() {
+        Err(Error::ProposalClosed)?;
+    }
+    if now < subject.closes() {
+        Err(Error::ProposalOpen)?;
+    }
+    let result = tally(&subject, ballots, eligible_voters);
+    subject.set_outcome(Some(result.outcome().clone()));
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Duration;
     use crate::{
-        util,
+        models::region::RegionID,
+        util::{self, test::*},
     };
 
     #[test]
@@ -175,5 +367,282 @@ mod tests {
         assert_eq!(member.created(), &now);
         assert_eq!(member.updated(), &now);
     }
-}
 
+    fn make_proposal(scope: VoteScope, rules: VoteRules, now: &DateTime<Utc>) -> Proposal {
+        let user = make_user(&UserID::create(), None, now);
+        let mods = propose(&user, None, ProposalID::create(), VoteScope::Systemic, "more parks", "we should build more parks", rules, *now + Duration::days(7), now).unwrap().into_vec();
+        let mut proposal = mods[0].clone().expect_op::<Proposal>(Op::Create).unwrap();
+        proposal.set_scope(scope);
+        proposal
+    }
+
+    fn make_ballot(proposal: &Proposal, choice: BallotChoice, now: &DateTime<Utc>) -> Ballot {
+        let user = make_user(&UserID::create(), None, now);
+        let mods = cast(&user, None, proposal, BallotID::create(), None, choice, now).unwrap().into_vec();
+        mods[0].clone().expect_op::<Ballot>(Op::Create).unwrap()
+    }
+
+    #[test]
+    fn can_propose() {
+        let now = util::time::now();
+        let id = ProposalID::create();
+        let state = TestState::standard(vec![CompanyPermission::ProposalCreate], &now);
+        let scope = VoteScope::Company(state.company().id().clone());
+        let closes = now + Duration::days(3);
+
+        let testfn = |state: &TestState<Proposal, Proposal>| {
+            propose(state.user(), state.member.as_ref(), id.clone(), scope.clone(), "buy a truck", "our old truck broke", VoteRules::simple_majority(), closes.clone(), &now)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let proposal = mods[0].clone().expect_op::<Proposal>(Op::Create).unwrap();
+        assert_eq!(proposal.id(), &id);
+        assert_eq!(proposal.proposer(), state.user().id());
+        assert_eq!(proposal.scope(), &scope);
+        assert_eq!(proposal.name(), "buy a truck");
+        assert_eq!(proposal.description(), "our old truck broke");
+        assert_eq!(proposal.rules(), &VoteRules::simple_majority());
+        assert_eq!(proposal.closes(), &closes);
+        assert_eq!(proposal.outcome(), &None);
+        assert_eq!(proposal.active(), &true);
+        assert_eq!(proposal.created(), &now);
+        assert_eq!(proposal.updated(), &now);
+        assert!(proposal.is_open(&now));
+        assert!(!proposal.is_open(&closes));
+
+        // must be a member of the company to propose
+        let mut state2 = state.clone();
+        state2.member = None;
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.member_mut().set_permissions(vec![CompanyPermission::ProposalVote]);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state4 = state.clone();
+        state4.user_mut().set_roles(vec![]);
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let res = propose(state.user(), state.member.as_ref(), id.clone(), scope.clone(), "buy a truck", "our old truck broke", VoteRules::simple_majority(), now.clone(), &now);
+        assert_eq!(res, Err(Error::ProposalClosed));
+
+        assert_eq!(VoteRules::new(num!(1.1), num!(0)), Err(Error::InvalidAmount(num!(1.1))));
+        assert_eq!(VoteRules::new(num!(0.5), num!(-0.2)), Err(Error::InvalidAmount(num!(-0.2))));
+    }
+
+    #[test]
+    fn can_cast() {
+        let now = util::time::now();
+        let id = BallotID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ProposalVote], &now);
+        let proposal = make_proposal(VoteScope::Company(state.company().id().clone()), VoteRules::simple_majority(), &now);
+        state.model = Some(proposal);
+
+        let testfn = |state: &TestState<Proposal, Ballot>| {
+            cast(state.user(), state.member.as_ref(), state.model(), id.clone(), state.model2.clone(), BallotChoice::Yes, &now)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let ballot = mods[0].clone().expect_op::<Ballot>(Op::Create).unwrap();
+        assert_eq!(ballot.id(), &id);
+        assert_eq!(ballot.proposal_id(), state.model().id());
+        assert_eq!(ballot.voter(), state.user().id());
+        assert_eq!(ballot.choice(), &BallotChoice::Yes);
+        assert_eq!(ballot.created(), &now);
+
+        // changing our vote updates the existing ballot
+        let now2 = now + Duration::hours(1);
+        let mods = cast(state.user(), state.member.as_ref(), state.model(), BallotID::create(), Some(ballot.clone()), BallotChoice::No, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let ballot2 = mods[0].clone().expect_op::<Ballot>(Op::Update).unwrap();
+        assert_eq!(ballot2.id(), &id);
+        assert_eq!(ballot2.choice(), &BallotChoice::No);
+        assert_eq!(ballot2.updated(), &now2);
+
+        // can't update someone else's ballot
+        let mut state2 = state.clone();
+        let mut ballot3 = ballot.clone();
+        ballot3.set_voter(UserID::create());
+        state2.model2 = Some(ballot3);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // can't vote in a company we're not a member of
+        let mut state3 = state.clone();
+        state3.model_mut().set_scope(VoteScope::Company(CompanyID::create()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // regional votes are open to the users living in the region
+        let mut state5 = state.clone();
+        state5.model_mut().set_scope(VoteScope::Region(RegionID::new("cascadia")));
+        state5.member = None;
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        state5.user_mut().set_region_id(Some(RegionID::new("sonora")));
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        state5.user_mut().set_region_id(Some(RegionID::new("cascadia")));
+        let mods = testfn(&state5).unwrap().into_vec();
+        let ballot4 = mods[0].clone().expect_op::<Ballot>(Op::Create).unwrap();
+        assert_eq!(ballot4.proposal_id(), state5.model().id());
+
+        // can't vote after voting closes
+        let res = cast(state.user(), state.member.as_ref(), state.model(), id.clone(), None, BallotChoice::Yes, state.model().closes());
+        assert_eq!(res, Err(Error::ProposalClosed));
+        let mut state4 = state.clone();
+        state4.model_mut().set_outcome(Some(ProposalOutcome::Failed));
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::ProposalClosed));
+    }
+
+    #[test]
+    fn tallies() {
+        let now = util::time::now();
+        let proposal = make_proposal(VoteScope::Systemic, VoteRules::new(num!(0.5), num!(0.5)).unwrap(), &now);
+        let mut ballots = vec![
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+            make_ballot(&proposal, BallotChoice::No, &now),
+            make_ballot(&proposal, BallotChoice::Abstain, &now),
+        ];
+
+        let result = tally(&proposal, &ballots, 8);
+        assert_eq!(result.yes(), &2);
+        assert_eq!(result.no(), &1);
+        assert_eq!(result.abstain(), &1);
+        assert_eq!(result.eligible(), &8);
+        assert_eq!(result.outcome(), &ProposalOutcome::Passed);
+
+        // not enough voters
+        let result = tally(&proposal, &ballots, 9);
+        assert_eq!(result.outcome(), &ProposalOutcome::NoQuorum);
+
+        // a tie fails
+        let ballots_tied = vec![
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+            make_ballot(&proposal, BallotChoice::No, &now),
+            make_ballot(&proposal, BallotChoice::No, &now),
+        ];
+        let result = tally(&proposal, &ballots_tied, 8);
+        assert_eq!(result.yes(), &2);
+        assert_eq!(result.no(), &2);
+        assert_eq!(result.outcome(), &ProposalOutcome::Failed);
+
+        // only a voter's latest ballot counts
+        let mut changed = ballots[0].clone();
+        changed.set_choice(BallotChoice::No);
+        changed.set_updated(now + Duration::minutes(5));
+        ballots.push(changed);
+        let result = tally(&proposal, &ballots, 8);
+        assert_eq!(result.yes(), &1);
+        assert_eq!(result.no(), &2);
+        assert_eq!(result.outcome(), &ProposalOutcome::Failed);
+
+        // ballots from other proposals/deleted ballots don't count
+        let other = make_proposal(VoteScope::Systemic, VoteRules::simple_majority(), &now);
+        let mut deleted = make_ballot(&proposal, BallotChoice::No, &now);
+        deleted.set_deleted(Some(now.clone()));
+        let ballots2 = vec![
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+            make_ballot(&other, BallotChoice::No, &now),
+            make_ballot(&other, BallotChoice::No, &now),
+            deleted,
+        ];
+        let result = tally(&proposal, &ballots2, 2);
+        assert_eq!(result.yes(), &1);
+        assert_eq!(result.no(), &0);
+        assert_eq!(result.outcome(), &ProposalOutcome::Passed);
+
+        let result = tally(&proposal, &vec![], 0);
+        assert_eq!(result.outcome(), &ProposalOutcome::NoQuorum);
+        let result = tally(&other, &vec![], 0);
+        assert_eq!(result.outcome(), &ProposalOutcome::Failed);
+    }
+
+    #[test]
+    fn can_close() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProposalClose], &now);
+        let mut proposal = make_proposal(VoteScope::Systemic, VoteRules::simple_majority(), &now);
+        let ballots = vec![
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+            make_ballot(&proposal, BallotChoice::No, &now),
+            make_ballot(&proposal, BallotChoice::Yes, &now),
+        ];
+        let extra_ballot = make_ballot(&proposal, BallotChoice::No, &now);
+        proposal.set_scope(VoteScope::Company(state.company().id().clone()));
+        state.model = Some(proposal.clone());
+        let now2 = proposal.closes().clone();
+
+        let testfn_inner = |state: &TestState<Proposal, Proposal>, ballots: &[Ballot], now: &DateTime<Utc>| {
+            close(state.user(), state.member.as_ref(), state.model().clone(), ballots, 3, now)
+        };
+        let testfn = |state: &TestState<Proposal, Proposal>| {
+            testfn_inner(state, &ballots, &now2)
+        };
+
+        let res = testfn_inner(&state, &ballots, &now);
+        assert_eq!(res, Err(Error::ProposalOpen));
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let proposal2 = mods[0].clone().expect_op::<Proposal>(Op::Update).unwrap();
+        assert_eq!(proposal2.outcome(), &Some(ProposalOutcome::Passed));
+        assert_eq!(proposal2.updated(), &now2);
+        assert!(!proposal2.is_open(&now));
+
+        // must be able to close proposals in the company
+        let mut state2 = state.clone();
+        state2.member_mut().set_permissions(vec![CompanyPermission::ProposalVote]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.member = None;
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state4 = state.clone();
+        state4.user_mut().set_roles(vec![]);
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // ballots for another proposal are rejected
+        let other = make_proposal(VoteScope::Systemic, VoteRules::simple_majority(), &now);
+        let mut ballots2 = ballots.clone();
+        ballots2.push(make_ballot(&other, BallotChoice::No, &now));
+        let res = testfn_inner(&state, &ballots2, &now2);
+        assert_eq!(res, Err(Error::BallotsInvalid));
+
+        // a voter can't appear twice
+        let mut ballots3 = ballots[0..2].to_vec();
+        let mut dupe = ballots[1].clone();
+        dupe.set_id(BallotID::create());
+        ballots3.push(dupe);
+        let res = testfn_inner(&state, &ballots3, &now2);
+        assert_eq!(res, Err(Error::BallotsInvalid));
+
+        // can't have more ballots than eligible voters
+        let mut ballots4 = ballots.clone();
+        ballots4.push(extra_ballot);
+        let res = testfn_inner(&state, &ballots4, &now2);
+        assert_eq!(res, Err(Error::BallotsInvalid));
+
+        let mut state5 = state.clone();
+        state5.model = Some(proposal2.clone());
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::ProposalClosed));
+
+        let mut state6 = state.clone();
+        state6.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state6);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("proposal".into())));
+    }
+}
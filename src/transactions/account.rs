@@ -1,5 +1,13 @@
 //! Accounts (named after "bank accounts") hold credits that are created through
 //! labor.
+//!
+//! There are two kinds of credits an account can hold. Work credits are paid
+//! out via payroll (or deposited by a bank) and can move freely between
+//! regular accounts. UBI credits are only ever created by issuing UBI into a
+//! UBI account (see [system::ubi][1]), and cannot be transferred, deposited, or
+//! withdrawn. This keeps the two pools of credits from mixing.
+//!
+//! [1]: ../../system/ubi/index.html
 
 use chrono::{DateTime, Utc};
 use crate::{
@@ -12,7 +20,6 @@ use crate::{
         lib::basis_model::Model,
         user::{User, UserID},
     },
-    system::ubi::UBIParameters,
 };
 use rust_decimal::prelude::*;
 
@@ -77,17 +84,26 @@ pub fn set_owners(caller: &User, mut subject: Account, user_ids: Option<Vec<User
 }
 
 /// Transfer credits from one account to another.
+///
+/// Neither account can be a UBI account, and the amount being transferred
+/// cannot exceed the balance of the sending account.
 pub fn transfer(caller: &User, mut subject: Account, mut to_account: Account, amount: Decimal, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::AccountTransfer)?;
     if !subject.user_ids().contains(caller.id()) {
         Err(Error::InsufficientPrivileges)?;
     }
-    if subject.ubi().is_some() {
+    if subject.ubi().is_some() || to_account.ubi().is_some() {
         Err(Error::UBIAccountError)?;
     }
-    subject.adjust_balance(-amount)?;
+    if subject.is_deleted() || to_account.is_deleted() {
+        Err(Error::ObjectIsDeleted("account".into()))?;
+    }
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    subject.move_credits(-amount, now)?;
     subject.set_updated(now.clone());
-    to_account.adjust_balance(amount)?;
+    to_account.move_credits(amount, now)?;
     to_account.set_updated(now.clone());
     let mut mods = Modifications::new();
     mods.push(Op::Update, subject);
@@ -95,25 +111,38 @@ pub fn transfer(caller: &User, mut subject: Account, mut to_account: Account, am
     Ok(mods)
 }
 
-/// Claim UBI. This reads the difference between now and the last time UBI was
-/// claimed and puts the appropriate amount into the account (with an upper
-/// ceiling).
-///
-/// Takes a systemic `UBIParameters` object which tells us how the UBI behaves.
-pub fn claim_ubi(caller: &User, mut subject: Account, ubi_params: &UBIParameters, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::AccountUBIClaim)?;
-    if !subject.user_ids().contains(caller.id()) {
-        Err(Error::InsufficientPrivileges)?;
+/// Deposit work credits into an account. This is meant to be run by a bank
+/// when credits enter the system from outside of the normal payroll process.
+pub fn deposit(caller: &User, mut subject: Account, amount: Decimal, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::AccountDeposit)?;
+    if subject.ubi().is_some() {
+        Err(Error::UBIAccountError)?;
     }
-    let ubi = if let Some(ubi) = subject.ubi().clone() {
-        ubi
-    } else {
-        return Err(Error::UBIAccountRequired);
-    };
-    let days_elapsed = Decimal::from(now.timestamp() - ubi.last_claim().timestamp()) / num!(86400);
-    let balance_adjustment = days_elapsed * ubi_params.balance_per_day().clone();
-    subject.adjust_balance(balance_adjustment)?;
-    subject.set_balance(subject.balance().clone().min(ubi_params.ceiling().clone()).normalize());
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("account".into()))?;
+    }
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    subject.move_credits(amount, now)?;
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Withdraw work credits from an account. Like `deposit`, this is run by a
+/// bank. The account cannot be overdrawn.
+pub fn withdraw(caller: &User, mut subject: Account, amount: Decimal, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::AccountWithdraw)?;
+    if subject.ubi().is_some() {
+        Err(Error::UBIAccountError)?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("account".into()))?;
+    }
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    subject.move_credits(-amount, now)?;
     subject.set_updated(now.clone());
     Ok(Modifications::new_single(Op::Update, subject))
 }
@@ -137,10 +166,39 @@ pub fn delete(caller: &User, mut subject: Account, now: &DateTime<Utc>) -> Resul
     Ok(Modifications::new_single(Op::Delete, subject))
 }
 
+/// Close an account, moving its remaining balance into another account and
+/// deleting it. This is effectively a `transfer` of the full balance followed
+/// by a `delete`.
+pub fn close(caller: &User, mut subject: Account, mut to_account: Account, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::AccountDelete)?;
+    if !subject.user_ids().contains(caller.id()) {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if subject.ubi().is_some() || to_account.ubi().is_some() {
+        Err(Error::UBIAccountError)?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("account".into()))?;
+    }
+    if to_account.is_deleted() || subject.id() == to_account.id() {
+        Err(Error::CannotEraseCredits)?;
+    }
+    let balance = subject.balance().clone();
+    subject.move_credits(-balance, now)?;
+    subject.set_deleted(Some(now.clone()));
+    to_account.move_credits(balance, now)?;
+    to_account.set_updated(now.clone());
+    let mut mods = Modifications::new();
+    mods.push(Op::Delete, subject);
+    mods.push(Op::Update, to_account);
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        access::Role,
         models::{
             account::Ubi,
         },
@@ -208,6 +266,7 @@ mod tests {
         assert_eq!(account2.ubi(), state.model().ubi());
         assert_eq!(account2.created(), state.model().created());
         assert_eq!(account2.updated(), &now2);
+        assert_eq!(account2.last_activity(), state.model().last_activity());
         assert_eq!(account2.deleted(), &None);
 
         let mut state2 = state.clone();
@@ -293,6 +352,7 @@ mod tests {
         assert_eq!(account3.ubi(), state.model().ubi());
         assert_eq!(account3.created(), state.model().created());
         assert_eq!(account3.updated(), &now2);
+        assert_eq!(account3.last_activity(), &Some(now2.clone()));
         assert_eq!(account3.deleted(), &None);
         let account4 = mods[1].clone().expect_op::<Account>(Op::Update).unwrap();
         assert_eq!(account4.balance(), &num!(10));
@@ -317,65 +377,110 @@ mod tests {
         let res = testfn_inner(&state, num!(56));
         assert_eq!(res, Err(Error::NegativeAccountBalance));
 
+        let res = testfn_inner(&state, num!(0));
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = testfn_inner(&state, num!(-10));
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-10))));
+
         let mut state3 = state.clone();
         state3.model_mut().set_ubi(Some(Ubi::new(now2.clone())));
         let res = testfn(&state3);
         assert_eq!(res, Err(Error::UBIAccountError));
+
+        let mut state4 = state.clone();
+        state4.model2_mut().set_ubi(Some(Ubi::new(now2.clone())));
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::UBIAccountError));
+
+        let mut state5 = state.clone();
+        state5.model2_mut().set_deleted(Some(now2.clone()));
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("account".into())));
     }
 
     #[test]
-    fn can_claim_ubi() {
-        let id = AccountID::create();
+    fn can_deposit() {
         let now = util::time::now();
         let mut state = TestState::standard(vec![], &now);
-        let multisig = vec![Multisig::new(1)];
-        let mods = create(state.user(), id.clone(), vec![state.user().id().clone()], multisig.clone(), "UBI", "UBI", true, &now).unwrap().into_vec();
-        let mut account = mods[0].clone().expect_op::<Account>(Op::Create).unwrap();
-        let mut ubi_params = UBIParameters::new();
-        ubi_params.set_ceiling(num!(100));
-        ubi_params.set_balance_per_day(num!(900) / num!(30));
-        account.set_ubi(Some(Ubi::new("2020-01-01T00:00:00Z".parse().unwrap())));
+        let account = make_account(&AccountID::create(), &UserID::create(), num!(50), "Jerry's account", &now);
+        state.user_mut().set_roles(vec![Role::Bank]);
         state.company = None;
         state.member = None;
         state.model = Some(account);
 
-        let now2 = "2020-01-06T12:30:00Z".parse().unwrap();
-        let testfn_inner = |state: &TestState<Account, Account>, ubi_params: &UBIParameters| {
-            claim_ubi(state.user(), state.model().clone(), ubi_params, &now2)
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<Account, Account>, amount: Decimal| {
+            deposit(state.user(), state.model().clone(), amount, &now2)
         };
         let testfn = |state: &TestState<Account, Account>| {
-            testfn_inner(state, &ubi_params)
+            testfn_inner(state, num!(12.5))
         };
         test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "account", &testfn);
 
         let mods = testfn(&state).unwrap().into_vec();
         assert_eq!(mods.len(), 1);
         let account2 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
         assert_eq!(account2.id(), state.model().id());
-        assert_eq!(account2.balance(), &num!(100));
+        assert_eq!(account2.balance(), &num!(62.5));
         assert_eq!(account2.created(), state.model().created());
         assert_eq!(account2.updated(), &now2);
-        assert_eq!(account2.deleted(), &None);
-
-        let mut ubi_params2 = ubi_params.clone();
-        ubi_params2.set_ceiling(num!(5000));
-        let mods = testfn_inner(&state, &ubi_params2).unwrap().into_vec();
-        assert_eq!(mods.len(), 1);
-        let account3 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
-        assert_eq!(account3.id(), state.model().id());
-        assert_eq!(account3.balance(), &num!(165.625));
-        assert_eq!(account3.created(), state.model().created());
-        assert_eq!(account3.updated(), &now2);
 
         let mut state2 = state.clone();
-        state2.user_mut().set_id(UserID::create());
+        state2.user_mut().set_roles(vec![Role::User]);
         let res = testfn(&state2);
         assert_eq!(res, Err(Error::InsufficientPrivileges));
 
+        let res = testfn_inner(&state, num!(-1));
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+
         let mut state3 = state.clone();
-        state3.model_mut().set_ubi(None);
+        state3.model_mut().set_ubi(Some(Ubi::new(now2.clone())));
         let res = testfn(&state3);
-        assert_eq!(res, Err(Error::UBIAccountRequired));
+        assert_eq!(res, Err(Error::UBIAccountError));
+    }
+
+    #[test]
+    fn can_withdraw() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        let account = make_account(&AccountID::create(), &UserID::create(), num!(50), "Jerry's account", &now);
+        state.user_mut().set_roles(vec![Role::Bank]);
+        state.company = None;
+        state.member = None;
+        state.model = Some(account);
+
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<Account, Account>, amount: Decimal| {
+            withdraw(state.user(), state.model().clone(), amount, &now2)
+        };
+        let testfn = |state: &TestState<Account, Account>| {
+            testfn_inner(state, num!(12.5))
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "account", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let account2 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(account2.id(), state.model().id());
+        assert_eq!(account2.balance(), &num!(37.5));
+        assert_eq!(account2.created(), state.model().created());
+        assert_eq!(account2.updated(), &now2);
+
+        let mods = testfn_inner(&state, num!(50)).unwrap().into_vec();
+        let account3 = mods[0].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(account3.balance(), &num!(0));
+
+        let res = testfn_inner(&state, num!(50.01));
+        assert_eq!(res, Err(Error::NegativeAccountBalance));
+        let res = testfn_inner(&state, num!(0));
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_ubi(Some(Ubi::new(now2.clone())));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::UBIAccountError));
     }
 
     #[test]
@@ -426,5 +531,57 @@ mod tests {
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::UBIAccountError));
     }
+
+    #[test]
+    fn can_close() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        let account1 = make_account(&AccountID::create(), state.user().id(), num!(50), "Jerry's account", &now);
+        let account2 = make_account(&AccountID::create(), &UserID::create(), num!(3), "Larry's account", &now);
+        state.company = None;
+        state.member = None;
+        state.model = Some(account1);
+        state.model2 = Some(account2);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Account, Account>| {
+            close(state.user(), state.model().clone(), state.model2().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "account", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let account3 = mods[0].clone().expect_op::<Account>(Op::Delete).unwrap();
+        assert_eq!(account3.id(), state.model().id());
+        assert_eq!(account3.balance(), &num!(0));
+        assert_eq!(account3.updated(), state.model().updated());
+        assert_eq!(account3.deleted(), &Some(now2.clone()));
+        let account4 = mods[1].clone().expect_op::<Account>(Op::Update).unwrap();
+        assert_eq!(account4.id(), state.model2().id());
+        assert_eq!(account4.balance(), &num!(53));
+        assert_eq!(account4.updated(), &now2);
+        assert_eq!(account4.deleted(), &None);
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_id(UserID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model2_mut().set_deleted(Some(now2.clone()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::CannotEraseCredits));
+
+        let mut state4 = state.clone();
+        state4.model2 = Some(state4.model().clone());
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::CannotEraseCredits));
+
+        let mut state5 = state.clone();
+        state5.model2_mut().set_ubi(Some(Ubi::new(now2.clone())));
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::UBIAccountError));
+    }
 }
 
@@ -3,9 +3,21 @@
 //!
 //! In other words, an agreement is basically an order.
 //!
+//! Agreements that get made over and over can be saved as an [agreement
+//! template][tpl] and [created from it][from] with only the details that change.
+//!
+//! Two companies trading in both directions under the same agreement will
+//! accumulate offsetting commitments (I owe you 10 widgets, you owe me 4).
+//! Rather than fulfilling every leg separately, these can be [netted][net]
+//! against each other so only the difference needs to actually move (which
+//! happens in the same transaction, via regular transfer events).
+//!
 //! See the [agreement model.][1]
 //!
 //! [1]: ../../models/agreement/index.html
+//! [net]: fn.net_settle.html
+//! [tpl]: ../../models/agreement_template/index.html
+//! [from]: fn.create_from_template.html
 
 use chrono::{DateTime, Utc};
 use crate::{
@@ -15,15 +27,30 @@ use crate::{
         Op,
         Modifications,
         lib::{
-            agent::AgentID,
+            agent::{Agent, AgentID},
             basis_model::Model,
         },
         agreement::{Agreement, AgreementID},
+        agreement_template::AgreementTemplate,
+        commitment::Commitment,
         company::{Company, Permission as CompanyPermission},
+        event::EventID,
         member::Member,
+        resource::Resource,
+        resource_spec::ResourceSpecID,
+        transfer_policy::TransferPolicy,
         user::User,
     },
+    costs::Costs,
+    transactions::event::{ResourceMover, transfer},
+    util::{measure, number::Ratio},
 };
+use getset::Getters;
+use om2::{Measure, Unit};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 use vf_rs::vf;
 
 /// Create a new agreement/order.
@@ -32,7 +59,12 @@ use vf_rs::vf;
 /// agreement's `participants` list will be allowed to complete updates. This
 /// makes it so only those involved in the agreement can modify it or any of its
 /// data in any way.
-pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, id: AgreementID, participants: Vec<AgentID>, name: T, note: T, created: Option<DateTime<Utc>>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+///
+/// If `document_hash` is given (see [Agreement::hash_document][hash]), all
+/// commitments and events made under the agreement must provide the same hash.
+///
+/// [hash]: ../../models/agreement/struct.Agreement.html#method.hash_document
+pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, id: AgreementID, participants: Vec<AgentID>, name: T, note: T, created: Option<DateTime<Utc>>, document_hash: Option<[u8; 32]>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateAgreements)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::AgreementCreate)?;
     if !company.is_active() {
@@ -49,6 +81,7 @@ pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company
                 .map_err(|e| Error::BuilderFailed(e))?
         )
         .participants(participants)
+        .document_hash(document_hash)
         .active(active)
         .created(now.clone())
         .updated(now.clone())
@@ -57,8 +90,50 @@ pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company
     Ok(Modifications::new_single(Op::Create, model))
 }
 
+/// Create a new agreement/order from an agreement template.
+///
+/// The agreement is named after the template's title, and its note is the
+/// template body with each slot filled in from `values` (every slot must be
+/// given a value). The template must belong to our company.
+pub fn create_from_template(caller: &User, member: &Member, company: &Company, template: &AgreementTemplate, id: AgreementID, participants: Vec<AgentID>, values: HashMap<String, String>, created: Option<DateTime<Utc>>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::AgreementCreate)?;
+    if company.id() != template.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !template.is_active() {
+        Err(Error::ObjectIsInactive("agreement_template".into()))?;
+    }
+    let note = template.render(&values)?;
+    let model = Agreement::builder()
+        .id(id)
+        .inner(
+            vf::Agreement::builder()
+                .created(created)
+                .name(Some(template.title().clone()))
+                .note(Some(note))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .participants(participants)
+        .template_id(Some(template.id().clone()))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
 /// Update an agreement, including the participant list.
-pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Agreement, participants: Option<Vec<AgentID>>, name: Option<String>, note: Option<String>, created: Option<Option<DateTime<Utc>>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+///
+/// Changing the `document_hash` means commitments made under the old document
+/// can no longer be executed (they carry the old hash), so the parties have to
+/// re-commit under the new one.
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Agreement, participants: Option<Vec<AgentID>>, name: Option<String>, note: Option<String>, created: Option<Option<DateTime<Utc>>>, document_hash: Option<Option<[u8; 32]>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateAgreements)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::AgreementUpdate)?;
     if !company.is_active() {
@@ -76,6 +151,9 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Ag
     if let Some(note) = note {
         subject.inner_mut().set_note(Some(note));
     }
+    if let Some(document_hash) = document_hash {
+        subject.set_document_hash(document_hash);
+    }
     if let Some(active) = active {
         subject.set_active(active);
     }
@@ -83,17 +161,244 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Ag
     Ok(Modifications::new_single(Op::Update, subject))
 }
 
+/// Describes how to send what our company still owes of some resource after
+/// [netting][net]: the remainder is moved out of `resource_from` (which must
+/// conform to the commitments' resource spec and use their unit) into
+/// `resource_to` with a regular transfer event.
+///
+/// [net]: fn.net_settle.html
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct NetRemainder {
+    /// The ID of the transfer event settling the remainder
+    id: EventID,
+    /// The resource we're sending the remainder from
+    resource_from: Resource,
+    /// The counterparty's resource receiving the remainder
+    resource_to: ResourceMover,
+}
+
+impl NetRemainder {
+    /// Create a new net remainder
+    pub fn new(id: EventID, resource_from: Resource, resource_to: ResourceMover) -> Self {
+        Self {
+            id,
+            resource_from,
+            resource_to,
+        }
+    }
+}
+
+/// Net out mutual, unfulfilled transfer commitments between our company and
+/// one other company (`counterparty`) under an agreement.
+///
+/// Commitments are grouped by resource spec and unit. Within each group, the
+/// smaller of the two directions is offset entirely against the larger one:
+/// offset commitments are marked finished, and at most one commitment in each
+/// direction is reduced in quantity (with its `move_costs` reduced by the same
+/// proportion).
+///
+/// Whatever our company still owes in a group is then settled with a regular
+/// transfer event, using the entry in `remainders` whose resource conforms to
+/// the group's spec. The event carries the `move_costs` of the commitments it
+/// settles (which were already reduced in proportion to the offset), and those
+/// commitments are marked finished. If there's no matching entry, the remainder
+/// is left on the (reduced) commitments. Whatever the counterparty still owes
+/// is left for them to send.
+///
+/// Only the commitments that change are returned, followed by the
+/// modifications from the transfer events. Every commitment given must be an
+/// active, unfinished transfer under `agreement` between our company and the
+/// counterparty, and must specify a `resource_conforms_to` and a
+/// `resource_quantity`.
+pub fn net_settle(caller: &User, member: &Member, company: &Company, counterparty: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, commitments: Vec<Commitment>, remainders: Vec<NetRemainder>, document_hash: Option<[u8; 32]>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateCommitments)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::CommitmentUpdate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if agreement.is_deleted() {
+        Err(Error::ObjectIsDeleted("agreement".into()))?;
+    }
+    let company_agent_id = company.agent_id();
+    let counterparty_agent_id = counterparty.agent_id();
+    if !agreement.has_participant(&company_agent_id) || !agreement.has_participant(&counterparty_agent_id) {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    agreement.check_document_hash(document_hash.as_ref())?;
+
+    // (spec, unit, commitments we owe, commitments owed to us)
+    let mut groups: Vec<(ResourceSpecID, Unit, Vec<usize>, Vec<usize>)> = Vec::new();
+    for (idx, commitment) in commitments.iter().enumerate() {
+        if commitment.is_deleted() {
+            Err(Error::ObjectIsDeleted("commitment".into()))?;
+        }
+        let inner = commitment.inner();
+        if inner.clause_of().as_ref() != Some(agreement.id()) {
+            Err(Error::CommitmentInvalid)?;
+        }
+        if !commitment.is_active() || commitment.lapsed().is_some() || inner.finished() == &Some(true) || inner.action() != &vf::Action::Transfer {
+            Err(Error::CommitmentInvalid)?;
+        }
+        let (outgoing, other) = if inner.provider() == &company_agent_id {
+            (true, inner.receiver())
+        } else if inner.receiver() == &company_agent_id {
+            (false, inner.provider())
+        } else {
+            Err(Error::InsufficientPrivileges)?
+        };
+        if other != &counterparty_agent_id {
+            Err(Error::CommitmentInvalid)?;
+        }
+        let spec_id = inner.resource_conforms_to().as_ref().ok_or(Error::CommitmentInvalid)?;
+        let unit = inner.resource_quantity().as_ref().ok_or(Error::CommitmentInvalid)?.has_unit();
+        let group_idx = match groups.iter().position(|(group_spec, group_unit, _, _)| group_spec == spec_id && group_unit == unit) {
+            Some(group_idx) => group_idx,
+            None => {
+                groups.push((spec_id.clone(), unit.clone(), Vec::new(), Vec::new()));
+                groups.len() - 1
+            }
+        };
+        if outgoing {
+            groups[group_idx].2.push(idx);
+        } else {
+            groups[group_idx].3.push(idx);
+        }
+    }
+
+    let quantity = |commitment: &Commitment| -> Result<Decimal> {
+        measure::to_decimal(commitment.inner().resource_quantity().as_ref().ok_or(Error::CommitmentInvalid)?)
+    };
+    let mut commitments = commitments;
+    let mut modified = vec![false; commitments.len()];
+    let mut remainders = remainders;
+    let mut transfers = Vec::new();
+    for (spec_id, unit, owed, owing) in groups {
+        let mut total_owed = Decimal::zero();
+        for idx in &owed {
+            total_owed += quantity(&commitments[*idx])?;
+        }
+        let mut total_owing = Decimal::zero();
+        for idx in &owing {
+            total_owing += quantity(&commitments[*idx])?;
+        }
+        let offset = std::cmp::min(total_owed, total_owing);
+        if offset > Decimal::zero() {
+            for idxs in [&owed, &owing] {
+                let mut remaining = offset;
+                for idx in idxs {
+                    if remaining <= Decimal::zero() {
+                        break;
+                    }
+                    let commitment = &mut commitments[*idx];
+                    let qty = quantity(commitment)?;
+                    if qty <= remaining {
+                        commitment.inner_mut().set_finished(Some(true));
+                        remaining -= qty;
+                    } else {
+                        let move_costs = commitment.move_costs().clone() * Ratio::new((qty - remaining) / qty)?;
+                        let mut new_quantity = commitment.inner().resource_quantity().clone().ok_or(Error::CommitmentInvalid)?;
+                        measure::dec_measure(&mut new_quantity, &Measure::new(remaining, unit.clone()))?;
+                        commitment.inner_mut().set_resource_quantity(Some(new_quantity));
+                        commitment.set_move_costs(move_costs);
+                        remaining = Decimal::zero();
+                    }
+                    commitment.set_updated(now.clone());
+                    modified[*idx] = true;
+                }
+            }
+        }
+
+        // send whatever we still owe
+        let remainder = total_owed - offset;
+        if remainder <= Decimal::zero() {
+            continue;
+        }
+        let remainder_idx = remainders.iter().position(|net| {
+            net.resource_from().inner().conforms_to() == &spec_id && net.resource_from().get_unit().as_ref() == Some(&unit)
+        });
+        let net = match remainder_idx {
+            Some(remainder_idx) => remainders.remove(remainder_idx),
+            None => continue,
+        };
+        let mut move_costs = Costs::new();
+        for idx in &owed {
+            let commitment = &mut commitments[*idx];
+            if commitment.inner().finished() == &Some(true) {
+                continue;
+            }
+            move_costs = move_costs + commitment.move_costs().clone();
+            commitment.inner_mut().set_finished(Some(true));
+            commitment.set_updated(now.clone());
+            modified[*idx] = true;
+        }
+        transfers.push((net, move_costs, remainder));
+    }
+
+    let mut mods = Modifications::new();
+    for (commitment, modified) in commitments.into_iter().zip(modified) {
+        if modified {
+            mods.push(Op::Update, commitment);
+        }
+    }
+    // each transfer updates both companies, so the next one has to build on
+    // the last one's version of them
+    let mut company_from = company.clone();
+    let mut company_to = counterparty.clone();
+    for (net, move_costs, remainder) in transfers {
+        let NetRemainder { id, resource_from, resource_to } = net;
+        let transfer_mods = transfer::transfer_with_costs(caller, member, &company_from, &company_to, agreement, policy, id, resource_from, resource_to, move_costs, remainder, None, document_hash, None, now)?;
+        for transfer_mod in transfer_mods {
+            if let Ok(company) = transfer_mod.clone().expect_op::<Company>(Op::Update) {
+                if company.id() == company_from.id() {
+                    company_from = company;
+                } else {
+                    company_to = company;
+                }
+            }
+            mods.push_raw(transfer_mod);
+        }
+    }
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        costs::Costs,
         models::{
-            lib::agent::Agent,
+            agreement_template::AgreementTemplateID,
+            commitment::CommitmentID,
             company::CompanyID,
+            event::Event,
+            resource::ResourceID,
         },
+        transactions::OrderAction,
         util::{self, test::{self, *}},
     };
 
+    fn make_commitment(agreement: &Agreement, provider: &Company, receiver: &Company, spec_id: &ResourceSpecID, quantity: Measure, costs: Costs, now: &DateTime<Utc>) -> Commitment {
+        Commitment::builder()
+            .id(CommitmentID::create())
+            .inner(
+                vf::Commitment::builder()
+                    .action(vf::Action::Transfer)
+                    .clause_of(Some(agreement.id().clone()))
+                    .provider(provider.agent_id())
+                    .receiver(receiver.agent_id())
+                    .resource_conforms_to(Some(spec_id.clone()))
+                    .resource_quantity(Some(quantity))
+                    .build().unwrap()
+            )
+            .move_costs(costs)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
     #[test]
     fn can_create() {
         let now = util::time::now();
@@ -103,7 +408,7 @@ mod tests {
         let participants = vec![state.company().agent_id(), company_from.agent_id()];
 
         let testfn = |state: &TestState<Agreement, Agreement>| {
-            create(state.user(), state.member(), state.company(), id.clone(), participants.clone(), "order 1234141", "hi i'm jerry. just going to order some widgets. don't mind me, just ordering widgets.", Some(now.clone()), true, &now)
+            create(state.user(), state.member(), state.company(), id.clone(), participants.clone(), "order 1234141", "hi i'm jerry. just going to order some widgets. don't mind me, just ordering widgets.", Some(now.clone()), Some(Agreement::hash_document(b"12 widgets")), true, &now)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -116,12 +421,67 @@ mod tests {
         assert_eq!(agreement.inner().name(), &Some("order 1234141".into()));
         assert_eq!(agreement.inner().note(), &Some("hi i'm jerry. just going to order some widgets. don't mind me, just ordering widgets.".into()));
         assert_eq!(agreement.participants(), &participants);
+        assert!(agreement.verify_document(b"12 widgets"));
         assert_eq!(agreement.active(), &true);
         assert_eq!(agreement.created(), &now);
         assert_eq!(agreement.updated(), &now);
         assert_eq!(agreement.deleted(), &None);
     }
 
+    #[test]
+    fn can_create_from_template() {
+        let now = util::time::now();
+        let id = AgreementID::create();
+        let state = TestState::standard(vec![CompanyPermission::AgreementCreate], &now);
+        let company_from = make_company(&CompanyID::create(), "jerry's widgets", &now);
+        let participants = vec![state.company().agent_id(), company_from.agent_id()];
+        let template = AgreementTemplate::builder()
+            .id(AgreementTemplateID::create())
+            .company_id(state.company().id().clone())
+            .title("Weekly widgets")
+            .body("{{qty}} widgets, delivered every {{day}}")
+            .slots(vec!["qty".into(), "day".into()])
+            .default_actions(vec![OrderAction::Transfer])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let mut values = HashMap::new();
+        values.insert("qty".to_string(), "50".to_string());
+        values.insert("day".to_string(), "tuesday".to_string());
+
+        let testfn = |state: &TestState<Agreement, Agreement>| {
+            create_from_template(state.user(), state.member(), state.company(), &template, id.clone(), participants.clone(), values.clone(), None, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let agreement = mods[0].clone().expect_op::<Agreement>(Op::Create).unwrap();
+        assert_eq!(agreement.id(), &id);
+        assert_eq!(agreement.inner().name(), &Some("Weekly widgets".into()));
+        assert_eq!(agreement.inner().note(), &Some("50 widgets, delivered every tuesday".into()));
+        assert_eq!(agreement.participants(), &participants);
+        assert_eq!(agreement.template_id(), &Some(template.id().clone()));
+        assert_eq!(agreement.active(), &true);
+        assert_eq!(agreement.created(), &now);
+
+        let mut values2 = values.clone();
+        values2.remove("day");
+        let res = create_from_template(state.user(), state.member(), state.company(), &template, id.clone(), participants.clone(), values2, None, &now);
+        assert_eq!(res, Err(Error::MissingFields(vec!["day".into()])));
+
+        let mut template2 = template.clone();
+        template2.set_company_id(company_from.id().clone());
+        let res = create_from_template(state.user(), state.member(), state.company(), &template2, id.clone(), participants.clone(), values.clone(), None, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut template3 = template.clone();
+        template3.set_active(false);
+        let res = create_from_template(state.user(), state.member(), state.company(), &template3, id.clone(), participants.clone(), values.clone(), None, &now);
+        assert_eq!(res, Err(Error::ObjectIsInactive("agreement_template".into())));
+    }
+
     #[test]
     fn can_update() {
         let now = util::time::now();
@@ -130,12 +490,12 @@ mod tests {
         let company_from = make_company(&CompanyID::create(), "jerry's widgets", &now);
         let participants = vec![state.company().agent_id(), company_from.agent_id()];
 
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), participants.clone(), "order 1234141", "hi i'm jerry. just going to order some widgets. don't mind me, just ordering widgets.", Some(now.clone()), true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), id.clone(), participants.clone(), "order 1234141", "hi i'm jerry. just going to order some widgets. don't mind me, just ordering widgets.", Some(now.clone()), None, true, &now).unwrap().into_vec();
         let agreement1 = mods[0].clone().expect_op::<Agreement>(Op::Create).unwrap();
         let now2 = util::time::now();
 
         let testfn = |state: &TestState<Agreement, Agreement>| {
-            update(state.user(), state.member(), state.company(), agreement1.clone(), Some(vec![company_from.agent_id()]), Some("order 1111222".into()), Some("jerry's long-winded order".into()), None, None, &now2)
+            update(state.user(), state.member(), state.company(), agreement1.clone(), Some(vec![company_from.agent_id()]), Some("order 1111222".into()), Some("jerry's long-winded order".into()), None, Some(Some(Agreement::hash_document(b"12 widgets"))), None, &now2)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -147,10 +507,147 @@ mod tests {
         assert_eq!(agreement2.inner().name(), &Some("order 1111222".into()));
         assert_eq!(agreement2.inner().note(), &Some("jerry's long-winded order".into()));
         assert_eq!(agreement2.participants(), &vec![company_from.agent_id()]);
+        assert_eq!(agreement1.document_hash(), &None);
+        assert!(agreement2.verify_document(b"12 widgets"));
         assert_eq!(agreement2.active(), agreement1.active());
         assert_eq!(agreement2.created(), agreement1.created());
         assert_eq!(agreement2.updated(), &now2);
         assert_eq!(agreement2.deleted(), &None);
     }
-}
 
+    #[test]
+    fn can_net_settle() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::CommitmentUpdate, CompanyPermission::Transfer], &now);
+        let mut company = state.company().clone();
+        company.set_total_costs(Costs::new_with_labor("machinist", num!(1000)));
+        state.company = Some(company.clone());
+        let company2 = make_company(&CompanyID::create(), "larry's chairs", &now);
+        let company3 = make_company(&CompanyID::create(), "garry's gears", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![company.agent_id(), company2.agent_id()], "trade", "widgets for widgets", &now);
+        let widgets = ResourceSpecID::new("widgets");
+        let gears = ResourceSpecID::new("gears");
+
+        let c1 = make_commitment(&agreement, &company, &company2, &widgets, Measure::new(num!(10), Unit::One), Costs::new_with_labor("machinist", num!(100)), &now);
+        let c2 = make_commitment(&agreement, &company, &company2, &widgets, Measure::new(num!(5), Unit::One), Costs::new_with_labor("machinist", num!(50)), &now);
+        let c3 = make_commitment(&agreement, &company2, &company, &widgets, Measure::new(num!(12), Unit::One), Costs::new_with_labor("assembler", num!(60)), &now);
+        let c4 = make_commitment(&agreement, &company, &company2, &gears, Measure::new(num!(3), Unit::One), Costs::new_with_labor("machinist", num!(30)), &now);
+        let commitments = vec![c1.clone(), c2.clone(), c3.clone(), c4.clone()];
+
+        let mut widget_stock = make_resource(&ResourceID::create(), company.id(), &Measure::new(num!(20), Unit::One), &Costs::new_with_labor("machinist", num!(200)), &now);
+        widget_stock.inner_mut().set_conforms_to(widgets.clone());
+        let widget_remainder = NetRemainder::new(EventID::create(), widget_stock.clone(), ResourceMover::Create(ResourceID::create()));
+
+        let now2 = util::time::now();
+        let testfn_remainders = |state: &TestState<Commitment, Commitment>, commitments: Vec<Commitment>, remainders: Vec<NetRemainder>| {
+            net_settle(state.user(), state.member(), state.company(), &company2, &agreement, None, commitments, remainders, None, &now2)
+        };
+        let testfn_inner = |state: &TestState<Commitment, Commitment>, commitments: Vec<Commitment>| {
+            testfn_remainders(state, commitments, vec![])
+        };
+        let testfn = |state: &TestState<Commitment, Commitment>| {
+            testfn_inner(state, commitments.clone())
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let c1_2 = mods[0].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let c2_2 = mods[1].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let c3_2 = mods[2].clone().expect_op::<Commitment>(Op::Update).unwrap();
+
+        assert_eq!(c1_2.id(), c1.id());
+        assert_eq!(c1_2.inner().finished(), &Some(true));
+        assert_eq!(c1_2.inner().resource_quantity(), c1.inner().resource_quantity());
+        assert_eq!(c1_2.move_costs(), c1.move_costs());
+        assert_eq!(c1_2.updated(), &now2);
+
+        assert_eq!(c2_2.id(), c2.id());
+        assert_eq!(c2_2.inner().finished(), &None);
+        assert_eq!(c2_2.inner().resource_quantity(), &Some(Measure::new(num!(3), Unit::One)));
+        assert_eq!(c2_2.move_costs(), &Costs::new_with_labor("machinist", num!(30)));
+
+        assert_eq!(c3_2.id(), c3.id());
+        assert_eq!(c3_2.inner().finished(), &Some(true));
+        assert_eq!(c3_2.move_costs(), c3.move_costs());
+
+        // the 3 widgets we still owe get sent along, with the costs left on
+        // their commitment
+        let mods = testfn_remainders(&state, commitments.clone(), vec![widget_remainder.clone()]).unwrap().into_vec();
+        assert_eq!(mods.len(), 8);
+        let c1_3 = mods[0].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let c2_3 = mods[1].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let c3_3 = mods[2].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let event = mods[3].clone().expect_op::<Event>(Op::Create).unwrap();
+        let widget_stock2 = mods[4].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let widgets_sent = mods[5].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let company_2 = mods[6].clone().expect_op::<Company>(Op::Update).unwrap();
+        let company2_2 = mods[7].clone().expect_op::<Company>(Op::Update).unwrap();
+        assert_eq!(c1_3.inner().finished(), &Some(true));
+        assert_eq!(c2_3.inner().finished(), &Some(true));
+        assert_eq!(c2_3.inner().resource_quantity(), &Some(Measure::new(num!(3), Unit::One)));
+        assert_eq!(c3_3.inner().finished(), &Some(true));
+        assert_eq!(event.id(), widget_remainder.id());
+        assert_eq!(event.inner().action(), &vf::Action::Transfer);
+        assert_eq!(event.inner().provider(), &company.agent_id());
+        assert_eq!(event.inner().receiver(), &company2.agent_id());
+        assert_eq!(event.inner().realization_of(), &Some(agreement.id().clone()));
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(num!(3), Unit::One)));
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(30))));
+        assert_eq!(widget_stock2.inner().accounting_quantity(), &Some(Measure::new(num!(17), Unit::One)));
+        assert_eq!(widget_stock2.costs(), &Costs::new_with_labor("machinist", num!(170)));
+        assert_eq!(widgets_sent.inner().accounting_quantity(), &Some(Measure::new(num!(3), Unit::One)));
+        assert_eq!(widgets_sent.costs(), &Costs::new_with_labor("machinist", num!(30)));
+        assert_eq!(company_2.total_costs(), &Costs::new_with_labor("machinist", num!(970)));
+        assert_eq!(company2_2.total_costs(), &Costs::new_with_labor("machinist", num!(30)));
+
+        // a remainder that doesn't match anything we owe is left alone, as is
+        // the gears commitment we have no remainder for
+        let mods = testfn_remainders(&state, vec![c3.clone(), c4.clone()], vec![widget_remainder.clone()]).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+
+        // nothing to offset
+        let mods = testfn_inner(&state, vec![c1.clone(), c4.clone()]).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+
+        // nothing to offset, but we can still send what we owe
+        let mods = testfn_remainders(&state, vec![c1.clone()], vec![widget_remainder.clone()]).unwrap().into_vec();
+        assert_eq!(mods.len(), 6);
+        let c1_4 = mods[0].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let event = mods[1].clone().expect_op::<Event>(Op::Create).unwrap();
+        assert_eq!(c1_4.inner().finished(), &Some(true));
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(num!(10), Unit::One)));
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(100))));
+
+        // only commitments with our one counterparty
+        let agreement2 = make_agreement(agreement.id(), &vec![company.agent_id(), company2.agent_id(), company3.agent_id()], "trade", "widgets for widgets", &now);
+        let c5 = make_commitment(&agreement2, &company3, &company, &widgets, Measure::new(num!(1), Unit::One), Costs::new(), &now);
+        let res = testfn_inner(&state, vec![c1.clone(), c5.clone()]);
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+        let res = net_settle(state.user(), state.member(), state.company(), &company3, &agreement, None, vec![c1.clone()], vec![], None, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let c6 = make_commitment(&agreement2, &company3, &company2, &widgets, Measure::new(num!(1), Unit::One), Costs::new(), &now);
+        let res = testfn_inner(&state, vec![c1.clone(), c6.clone()]);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut c7 = c3.clone();
+        c7.inner_mut().set_clause_of(Some(AgreementID::create()));
+        let res = testfn_inner(&state, vec![c1.clone(), c7]);
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+
+        let mut c8 = c3.clone();
+        c8.inner_mut().set_finished(Some(true));
+        let res = testfn_inner(&state, vec![c1.clone(), c8]);
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+
+        let mut c9 = c3.clone();
+        c9.inner_mut().set_resource_conforms_to(None);
+        let res = testfn_inner(&state, vec![c1.clone(), c9]);
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+
+        let mut c10 = c3.clone();
+        c10.set_deleted(Some(now.clone()));
+        let res = testfn_inner(&state, vec![c1.clone(), c10]);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("commitment".into())));
+    }
+}
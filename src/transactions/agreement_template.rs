@@ -0,0 +1,179 @@
+//! Agreement templates let companies save the structure of agreements they make
+//! regularly, and [create agreements from them][1] later.
+//!
+//! See the [agreement template model.][2]
+//!
+//! [1]: ../agreement/fn.create_from_template.html
+//! [2]: ../../models/agreement_template/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        agreement_template::{AgreementTemplate, AgreementTemplateID},
+        company::{Company, Permission as CompanyPermission},
+        member::Member,
+        lib::basis_model::Model,
+        user::User,
+    },
+    transactions::OrderAction,
+};
+
+/// Create a new agreement template
+pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, id: AgreementTemplateID, title: T, body: T, slots: Vec<String>, default_actions: Vec<OrderAction>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::AgreementTemplateCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    let model = AgreementTemplate::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .title(title)
+        .body(body)
+        .slots(slots)
+        .default_actions(default_actions)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update an agreement template. Agreements already created from the template
+/// are not affected.
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: AgreementTemplate, title: Option<String>, body: Option<String>, slots: Option<Vec<String>>, default_actions: Option<Vec<OrderAction>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::AgreementTemplateUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if let Some(title) = title {
+        subject.set_title(title);
+    }
+    if let Some(body) = body {
+        subject.set_body(body);
+    }
+    if let Some(slots) = slots {
+        subject.set_slots(slots);
+    }
+    if let Some(default_actions) = default_actions {
+        subject.set_default_actions(default_actions);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Delete an agreement template
+pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: AgreementTemplate, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::AgreementTemplateDelete)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("agreement_template".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::company::CompanyID,
+        util::{self, test::{self, *}},
+    };
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = AgreementTemplateID::create();
+        let state = TestState::standard(vec![CompanyPermission::AgreementTemplateCreate], &now);
+
+        let testfn = |state: &TestState<AgreementTemplate, AgreementTemplate>| {
+            create(state.user(), state.member(), state.company(), id.clone(), "Weekly widgets", "{{qty}} widgets a week", vec!["qty".into()], vec![OrderAction::Transfer], true, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let template = mods[0].clone().expect_op::<AgreementTemplate>(Op::Create).unwrap();
+        assert_eq!(template.id(), &id);
+        assert_eq!(template.company_id(), state.company().id());
+        assert_eq!(template.title(), "Weekly widgets");
+        assert_eq!(template.body(), "{{qty}} widgets a week");
+        assert_eq!(template.slots(), &vec!["qty".to_string()]);
+        assert_eq!(template.default_actions(), &vec![OrderAction::Transfer]);
+        assert_eq!(template.active(), &true);
+        assert_eq!(template.created(), &now);
+        assert_eq!(template.updated(), &now);
+        assert_eq!(template.deleted(), &None);
+    }
+
+    #[test]
+    fn can_update() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::AgreementTemplateCreate, CompanyPermission::AgreementTemplateUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), AgreementTemplateID::create(), "Weekly widgets", "{{qty}} widgets a week", vec!["qty".into()], vec![OrderAction::Transfer], true, &now).unwrap().into_vec();
+        let template = mods[0].clone().expect_op::<AgreementTemplate>(Op::Create).unwrap();
+        state.model = Some(template);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<AgreementTemplate, AgreementTemplate>| {
+            update(state.user(), state.member(), state.company(), state.model().clone(), Some("Monthly widgets".into()), Some("{{qty}} widgets a month".into()), None, Some(vec![OrderAction::Transfer, OrderAction::TransferCustody]), Some(false), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let template2 = mods[0].clone().expect_op::<AgreementTemplate>(Op::Update).unwrap();
+        assert_eq!(template2.title(), "Monthly widgets");
+        assert_eq!(template2.body(), "{{qty}} widgets a month");
+        assert_eq!(template2.slots(), &vec!["qty".to_string()]);
+        assert_eq!(template2.default_actions(), &vec![OrderAction::Transfer, OrderAction::TransferCustody]);
+        assert_eq!(template2.active(), &false);
+        assert_eq!(template2.created(), &now);
+        assert_eq!(template2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::new("zing"));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_delete() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::AgreementTemplateCreate, CompanyPermission::AgreementTemplateDelete], &now);
+        let mods = create(state.user(), state.member(), state.company(), AgreementTemplateID::create(), "Weekly widgets", "{{qty}} widgets a week", vec!["qty".into()], vec![OrderAction::Transfer], true, &now).unwrap().into_vec();
+        let template = mods[0].clone().expect_op::<AgreementTemplate>(Op::Create).unwrap();
+        state.model = Some(template);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<AgreementTemplate, AgreementTemplate>| {
+            delete(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "agreement_template", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let template2 = mods[0].clone().expect_op::<AgreementTemplate>(Op::Delete).unwrap();
+        assert_eq!(template2.deleted(), &Some(now2.clone()));
+    }
+}
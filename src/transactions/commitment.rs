@@ -6,8 +6,12 @@
 //!
 //! See the [commitment model.][2]
 //!
+//! Once it's time to make good on a commitment, [execute] creates the event the
+//! commitment describes and marks the commitment as finished.
+//!
 //! [1]: ../enum.OrderAction.html
 //! [2]: ../../models/commitment/index.html
+//! [execute]: fn.execute.html
 
 use chrono::{DateTime, Utc};
 use crate::{
@@ -15,29 +19,49 @@ use crate::{
     costs::Costs,
     error::{Error, Result},
     models::{
+        Model as AnyModel,
         Op,
         Modifications,
         agreement::Agreement,
         commitment::{Commitment, CommitmentID},
         company::{Company, Permission as CompanyPermission},
+        event::EventID,
+        flag::{Flag, FlagID, FlagReason},
+        intent::{Intent, IntentID},
         member::Member,
         lib::{
             agent::{Agent, AgentID},
             basis_model::Model,
         },
-        process::ProcessID,
-        resource::ResourceID,
+        process::{Process, ProcessID},
+        resource::{Resource, ResourceID},
         resource_spec::ResourceSpecID,
+        transfer_policy::TransferPolicy,
         user::User,
     },
-    transactions::OrderAction,
+    transactions::{
+        OrderAction,
+        event::{ResourceMover, service, transfer},
+    },
 };
 use om2::Measure;
 use url::Url;
 use vf_rs::{vf, geo::SpatialThing};
 
-/// Create a new commitment
-pub fn create(caller: &User, member: &Member, company: &Company, agreement: &Agreement, id: CommitmentID, move_costs: Costs, action: OrderAction, agreed_in: Option<Url>, at_location: Option<SpatialThing>, created: Option<DateTime<Utc>>, due: Option<DateTime<Utc>>, effort_quantity: Option<Measure>, finished: Option<bool>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, has_point_in_time: Option<DateTime<Utc>>, in_scope_of: Vec<AgentID>, input_of: Option<ProcessID>, name: Option<String>, note: Option<String>, output_of: Option<ProcessID>, provider: AgentID, receiver: AgentID, resource_conforms_to: Option<ResourceSpecID>, resource_inventoried_as: Option<ResourceID>, resource_quantity: Option<Measure>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+/// Create a new commitment.
+///
+/// If the commitment is being made in response to an intent, its ID is passed
+/// as `satisfies` and the intent itself as `intent`. The intent is always
+/// checked when it's referenced: leaving it out (or passing a different one) is
+/// an error, and expired (or otherwise withdrawn) intents can't be committed
+/// to. If the intent has an `available_quantity`, the commitment's quantity
+/// (its effort quantity, or failing that its resource quantity) is taken out of
+/// it and the updated intent is returned after the commitment. An intent that
+/// runs out of quantity is closed.
+///
+/// If the agreement has a `document_hash`, the same hash must be given, and
+/// is stored with the commitment.
+pub fn create(caller: &User, member: &Member, company: &Company, agreement: &Agreement, satisfies: Option<IntentID>, intent: Option<&Intent>, id: CommitmentID, move_costs: Costs, action: OrderAction, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, at_location: Option<SpatialThing>, created: Option<DateTime<Utc>>, due: Option<DateTime<Utc>>, effort_quantity: Option<Measure>, finished: Option<bool>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, has_point_in_time: Option<DateTime<Utc>>, in_scope_of: Vec<AgentID>, input_of: Option<ProcessID>, name: Option<String>, note: Option<String>, output_of: Option<ProcessID>, provider: AgentID, receiver: AgentID, resource_conforms_to: Option<ResourceSpecID>, resource_inventoried_as: Option<ResourceID>, resource_quantity: Option<Measure>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateCommitments)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::CommitmentCreate)?;
     if !company.is_active() {
@@ -52,11 +76,26 @@ pub fn create(caller: &User, member: &Member, company: &Company, agreement: &Agr
         // can't create a commitment for an agreement you are not party to
         Err(Error::InsufficientPrivileges)?;
     }
-    let event_action = match action {
-        OrderAction::DeliverService => vf::Action::DeliverService,
-        OrderAction::Transfer => vf::Action::Transfer,
-        OrderAction::TransferCustody => vf::Action::TransferCustody,
+    agreement.check_document_hash(document_hash.as_ref())?;
+    let satisfies = match (satisfies, intent) {
+        (None, None) => None,
+        (Some(_), None) => Err(Error::MissingFields(vec!["intent".into()]))?,
+        (Some(intent_id), Some(intent)) if &intent_id == intent.id() => {
+            let mut intent = intent.clone();
+            intent.refresh_availability(now)?;
+            if !intent.is_available(now) {
+                Err(Error::IntentExpired)?;
+            }
+            if intent.inner().available_quantity().is_some() {
+                let quantity = effort_quantity.as_ref().or(resource_quantity.as_ref())
+                    .ok_or(Error::MissingFields(vec!["effort_quantity".into(), "resource_quantity".into()]))?;
+                intent.take(quantity, now)?;
+            }
+            Some(intent)
+        }
+        _ => Err(Error::IntentMismatch)?,
     };
+    let event_action = action.to_action();
     let model = Commitment::builder()
         .id(id)
         .inner(
@@ -86,12 +125,21 @@ pub fn create(caller: &User, member: &Member, company: &Company, agreement: &Agr
                 .map_err(|e| Error::BuilderFailed(e))?
         )
         .move_costs(move_costs)
+        .satisfies(satisfies.as_ref().map(|intent| intent.id().clone()))
+        .document_hash(document_hash)
         .active(active)
         .created(now.clone())
         .updated(now.clone())
         .build()
         .map_err(|e| Error::BuilderFailed(e))?;
-    Ok(Modifications::new_single(Op::Create, model))
+    let mut mods = Modifications::new_single(Op::Create, model);
+    if let Some(mut intent) = satisfies {
+        if intent.inner().available_quantity().is_some() {
+            intent.set_updated(now.clone());
+            mods.push(Op::Update, intent);
+        }
+    }
+    Ok(mods)
 }
 
 /// Update a commitment
@@ -101,13 +149,7 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Co
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
-    let event_action = action.map(|x| {
-        match x {
-            OrderAction::DeliverService => vf::Action::DeliverService,
-            OrderAction::Transfer => vf::Action::Transfer,
-            OrderAction::TransferCustody => vf::Action::TransferCustody,
-        }
-    });
+    let event_action = action.map(|x| x.to_action());
 
     if let Some(move_costs) = move_costs {
         subject.set_move_costs(move_costs);
@@ -187,6 +229,178 @@ pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: Co
     Ok(Modifications::new_single(Op::Delete, subject))
 }
 
+/// Mark an overdue commitment as lapsed. Only the commitment's provider or
+/// receiver can expire it.
+///
+/// If a `flag_id` is given, a [Flag][1] is also created for the company
+/// expiring the commitment so the lapse can be surfaced to its members.
+///
+/// [1]: ../../models/flag/index.html
+pub fn expire(caller: &User, member: &Member, company: &Company, mut subject: Commitment, flag_id: Option<FlagID>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateCommitments)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::CommitmentUpdate)?;
+    let company_agent = company.agent_id();
+    if subject.inner().provider() != &company_agent && subject.inner().receiver() != &company_agent {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("commitment".into()))?;
+    }
+    if !subject.is_overdue(now) {
+        Err(Error::CommitmentNotOverdue)?;
+    }
+    subject.set_lapsed(Some(now.clone()));
+    subject.set_updated(now.clone());
+    let mut mods = Modifications::new();
+    if let Some(flag_id) = flag_id {
+        let flag = Flag::builder()
+            .id(flag_id)
+            .company_id(company.id().clone())
+            .reason(FlagReason::CommitmentLapsed(subject.id().clone()))
+            .note(subject.inner().name().clone())
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))?;
+        mods.push(Op::Create, flag);
+    }
+    mods.push(Op::Update, subject);
+    Ok(mods)
+}
+
+/// The objects needed to run the event a commitment describes, passed to
+/// [execute].
+///
+/// [execute]: fn.execute.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecuteState {
+    /// The providing and receiving processes for a `DeliverService` commitment
+    Service {
+        /// The process providing the service
+        process_from: Process,
+        /// The process receiving the service
+        process_to: Process,
+    },
+    /// The resources for a `Transfer` or `TransferCustody` commitment
+    Resource {
+        /// The resource being sent
+        resource_from: Resource,
+        /// Where the resource lands on the receiver's end
+        resource_to: ResourceMover,
+    },
+}
+
+/// Fulfill a commitment, creating the event it describes (via the matching
+/// event transaction) and marking the commitment finished.
+///
+/// The event's costs, quantity, agreement, and notes all come from the
+/// commitment, so the caller only has to supply the objects the event acts on.
+/// The commitment must be active and not lapsed (see [expire]). Its provider
+/// must be `company_from` and its receiver `company_to`, and if the commitment names a resource, spec, or processes,
+/// the objects in `state` must match them. Otherwise we get a
+/// `CommitmentInvalid` error.
+///
+/// The document hash the commitment was made with is passed along to the
+/// event, so if the agreement's `document_hash` has changed since, the
+/// commitment can't be executed.
+///
+/// Any processes waiting on this commitment (see [process::set_blocked_on][1])
+/// should be passed in as `blocked`, and have the commitment cleared from their
+/// `blocked_on` list. Processes the event itself updates are cleared as well.
+///
+/// Returns the event's modifications followed by an update of the commitment
+/// and an update of each unblocked process.
+///
+/// [expire]: fn.expire.html
+/// [1]: ../process/fn.set_blocked_on.html
+pub fn execute(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, mut subject: Commitment, id: EventID, state: ExecuteState, blocked: Vec<Process>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateCommitments)?;
+    member.access_check(caller.id(), company_from.id(), CompanyPermission::CommitmentUpdate)?;
+    if !company_from.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("commitment".into()))?;
+    }
+    if !subject.is_active() {
+        Err(Error::ObjectIsInactive("commitment".into()))?;
+    }
+    if subject.lapsed().is_some() {
+        Err(Error::CommitmentLapsed)?;
+    }
+    let inner = subject.inner().clone();
+    if inner.clause_of().as_ref() != Some(agreement.id()) || inner.finished() == &Some(true) {
+        Err(Error::CommitmentInvalid)?;
+    }
+    if inner.provider() != &company_from.agent_id() || inner.receiver() != &company_to.agent_id() {
+        Err(Error::CommitmentInvalid)?;
+    }
+    let action = OrderAction::from_action(inner.action()).ok_or(Error::CommitmentInvalid)?;
+    let move_costs = subject.move_costs().clone();
+    let agreed_in = inner.agreed_in().clone();
+    let document_hash = *subject.document_hash();
+    let note = inner.note().clone();
+
+    let mods = match (action, state) {
+        (OrderAction::DeliverService, ExecuteState::Service { process_from, process_to }) => {
+            if inner.output_of().as_ref().map(|x| x != process_from.id()).unwrap_or(false) || inner.input_of().as_ref().map(|x| x != process_to.id()).unwrap_or(false) {
+                Err(Error::CommitmentInvalid)?;
+            }
+            service::deliver_service_with_costs(caller, member, company_from, company_to, agreement, id, process_from, process_to, move_costs, agreed_in, document_hash, note, now)?
+        }
+        (action, ExecuteState::Resource { resource_from, resource_to }) if action != OrderAction::DeliverService => {
+            if inner.resource_inventoried_as().as_ref().map(|x| x != resource_from.id()).unwrap_or(false) || inner.resource_conforms_to().as_ref().map(|x| x != resource_from.inner().conforms_to()).unwrap_or(false) {
+                Err(Error::CommitmentInvalid)?;
+            }
+            let quantity = inner.resource_quantity().as_ref().ok_or(Error::MissingFields(vec!["resource_quantity".into()]))?;
+            if Some(quantity.has_unit()) != resource_from.get_unit().as_ref() {
+                Err(Error::CommitmentInvalid)?;
+            }
+            let move_measure = quantity.has_numerical_value().clone();
+            if action == OrderAction::Transfer {
+                transfer::transfer_with_costs(caller, member, company_from, company_to, agreement, policy, id, resource_from, resource_to, move_costs, move_measure, agreed_in, document_hash, note, now)?
+            } else {
+                transfer::transfer_custody_with_costs(caller, member, company_from, company_to, agreement, policy, id, resource_from, resource_to, move_costs, move_measure, agreed_in, document_hash, note, now)?
+            }
+        }
+        _ => Err(Error::CommitmentInvalid)?,
+    };
+
+    // clear the commitment out of any processes the event touched
+    let mut mods = mods.into_iter()
+        .fold(Modifications::new(), |mut acc, modification| {
+            match modification.into_pair() {
+                (op, AnyModel::Process(mut process)) => {
+                    process.unblock(subject.id());
+                    acc.push(op, process);
+                }
+                (op, model) => acc.push(op, model),
+            }
+            acc
+        });
+    subject.inner_mut().set_finished(Some(true));
+    subject.set_updated(now.clone());
+    let touched = mods.filter_model::<Process>()
+        .map(|(_, process)| process.id().clone())
+        .collect::<Vec<_>>();
+    let commitment_id = subject.id().clone();
+    mods.push(Op::Update, subject);
+    for mut process in blocked {
+        if touched.contains(process.id()) {
+            continue;
+        }
+        if process.unblock(&commitment_id) {
+            process.set_updated(now.clone());
+            mods.push(Op::Update, process);
+        }
+    }
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,6 +408,8 @@ mod tests {
         models::{
             agreement::AgreementID,
             company::CompanyID,
+            event::Event,
+            intent::IntentID,
         },
         util::{self, test::{self, *}},
     };
@@ -211,7 +427,7 @@ mod tests {
         let resource = make_resource(&ResourceID::new("widget1"), company_from.id(), &Measure::new(num!(30), Unit::One), &Costs::new_with_labor("widgetmaker", num!(50)), &now);
 
         let testfn_inner = |state: &TestState<Commitment, Commitment>, agreement: &Agreement, company_from: &Company, company_to: &Company| {
-            create(state.user(), state.member(), state.company(), &agreement, id.clone(), costs.clone(), OrderAction::Transfer, None, Some(state.loc().clone()), Some(now.clone()), None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now)
+            create(state.user(), state.member(), state.company(), &agreement, None, None, id.clone(), costs.clone(), OrderAction::Transfer, None, None, Some(state.loc().clone()), Some(now.clone()), None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now)
         };
         let testfn = |state: &TestState<Commitment, Commitment>| {
             testfn_inner(state, &agreement, &company_from, &company_to)
@@ -244,11 +460,75 @@ mod tests {
         assert_eq!(commitment.inner().resource_conforms_to(), &None);
         assert_eq!(commitment.inner().resource_inventoried_as(), &Some(ResourceID::new("widget1")));
         assert_eq!(commitment.inner().resource_quantity(), &Some(Measure::new(num!(10), Unit::One)));
+        assert_eq!(commitment.satisfies(), &None);
+        assert_eq!(commitment.document_hash(), &None);
         assert_eq!(commitment.active(), &true);
         assert_eq!(commitment.created(), &now);
         assert_eq!(commitment.updated(), &now);
         assert_eq!(commitment.deleted(), &None);
 
+        // agreements with a document hash need a matching hash
+        let hash = Agreement::hash_document(b"10 widgets to larry");
+        let mut agreement2 = agreement.clone();
+        agreement2.set_document_hash(Some(hash.clone()));
+        let testfn_hash = |hash: Option<[u8; 32]>| {
+            create(state.user(), state.member(), state.company(), &agreement2, None, None, id.clone(), costs.clone(), OrderAction::Transfer, None, hash, None, None, None, None, None, None, None, None, vec![], None, None, None, None, company_from.agent_id(), state.company().agent_id(), None, None, None, true, &now)
+        };
+        assert_eq!(testfn_hash(None), Err(Error::MissingFields(vec!["document_hash".into()])));
+        assert_eq!(testfn_hash(Some(Agreement::hash_document(b"100 widgets to larry"))), Err(Error::AgreementDocumentMismatch));
+        let mods = testfn_hash(Some(hash.clone())).unwrap().into_vec();
+        let commitment = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert_eq!(commitment.document_hash(), &Some(hash));
+
+        // commitments can satisfy intents, as long as they're still available
+        let mut intent = Intent::builder()
+            .id(IntentID::create())
+            .inner(vf::Intent::builder().action(vf::Action::Transfer).provider(Some(company_from.agent_id())).build().unwrap())
+            .expires_at(Some(now.clone() + chrono::Duration::days(1)))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let testfn_intent = |intent: &Intent| {
+            create(state.user(), state.member(), state.company(), &agreement, Some(intent.id().clone()), Some(intent), id.clone(), costs.clone(), OrderAction::Transfer, None, None, None, None, None, None, None, None, None, None, vec![], None, None, None, None, company_from.agent_id(), state.company().agent_id(), None, None, None, true, &now)
+        };
+        let mods = testfn_intent(&intent).unwrap().into_vec();
+        let commitment = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert_eq!(commitment.satisfies(), &Some(intent.id().clone()));
+        intent.set_expires_at(Some(now.clone()));
+        assert_eq!(testfn_intent(&intent), Err(Error::IntentExpired));
+        intent.set_expires_at(None);
+        intent.set_active(false);
+        assert_eq!(testfn_intent(&intent), Err(Error::IntentExpired));
+
+        // a referenced intent always has to be given (and match), so the
+        // checks above can't be skipped
+        let testfn_ref = |satisfies: Option<IntentID>, intent: Option<&Intent>| {
+            create(state.user(), state.member(), state.company(), &agreement, satisfies, intent, id.clone(), costs.clone(), OrderAction::Transfer, None, None, None, None, None, None, None, None, None, None, vec![], None, None, None, None, company_from.agent_id(), state.company().agent_id(), None, None, None, true, &now)
+        };
+        assert_eq!(testfn_ref(Some(intent.id().clone()), None), Err(Error::MissingFields(vec!["intent".into()])));
+        assert_eq!(testfn_ref(Some(IntentID::create()), Some(&intent)), Err(Error::IntentMismatch));
+        assert_eq!(testfn_ref(None, Some(&intent)), Err(Error::IntentMismatch));
+
+        // commitments draw down an intent's available quantity
+        intent.set_active(true);
+        intent.inner_mut().set_available_quantity(Some(Measure::new(num!(10), Unit::Hour)));
+        let testfn_effort = |intent: &Intent, effort: Option<Measure>| {
+            create(state.user(), state.member(), state.company(), &agreement, Some(intent.id().clone()), Some(intent), id.clone(), costs.clone(), OrderAction::DeliverService, None, None, None, None, None, effort, None, None, None, None, vec![], None, None, None, None, company_from.agent_id(), state.company().agent_id(), None, None, None, true, &now)
+        };
+        let mods = testfn_effort(&intent, Some(Measure::new(num!(4), Unit::Hour))).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let intent2 = mods[1].clone().expect_op::<Intent>(Op::Update).unwrap();
+        assert_eq!(intent2.id(), intent.id());
+        assert_eq!(intent2.inner().available_quantity(), &Some(Measure::new(num!(6), Unit::Hour)));
+        assert_eq!(intent2.active(), &true);
+        assert_eq!(testfn_effort(&intent2, Some(Measure::new(num!(7), Unit::Hour))), Err(Error::IntentExhausted));
+        assert_eq!(testfn_effort(&intent2, None), Err(Error::MissingFields(vec!["effort_quantity".into(), "resource_quantity".into()])));
+        let mods = testfn_effort(&intent2, Some(Measure::new(num!(6), Unit::Hour))).unwrap().into_vec();
+        let intent3 = mods[1].clone().expect_op::<Intent>(Op::Update).unwrap();
+        assert_eq!(intent3.inner().finished(), &Some(true));
+        assert_eq!(intent3.active(), &false);
+
         let mut company3 = state.company().clone();
         let mut company4 = state.company().clone();
         company3.set_id(CompanyID::new("bill's zingers, get your premium zings here. got a friend who constantly pranks you? turn the tables and zing that doofus in front of everyone!!"));
@@ -275,7 +555,7 @@ mod tests {
         let resource = make_resource(&ResourceID::new("widget1"), company_from.id(), &Measure::new(num!(30), Unit::One), &Costs::new_with_labor("widgetmaker", num!(50)), &now);
         let agreement_url: Url = "http://legalzoom.com/standard-widget-shopping-cart-agreement".parse().unwrap();
 
-        let mods = create(state.user(), state.member(), state.company(), &agreement, id.clone(), costs1.clone(), OrderAction::Transfer, None, Some(state.loc().clone()), Some(now.clone()), None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), &agreement, None, None, id.clone(), costs1.clone(), OrderAction::Transfer, None, None, Some(state.loc().clone()), Some(now.clone()), None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now).unwrap().into_vec();
         let commitment1 = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
         let now2 = util::time::now();
         state.model = Some(commitment1.clone());
@@ -328,7 +608,7 @@ mod tests {
         let resource = make_resource(&ResourceID::new("widget1"), company_from.id(), &Measure::new(num!(30), Unit::One), &Costs::new_with_labor("widgetmaker", num!(50)), &now);
         let costs1 = Costs::new_with_labor("widgetmaker", 42);
 
-        let mods = create(state.user(), state.member(), state.company(), &agreement, id.clone(), costs1.clone(), OrderAction::Transfer, None, Some(state.loc().clone()), Some(now.clone()), None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), &agreement, None, None, id.clone(), costs1.clone(), OrderAction::Transfer, None, None, Some(state.loc().clone()), Some(now.clone()), None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now).unwrap().into_vec();
         let commitment1 = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
         let now2 = util::time::now();
         state.model = Some(commitment1.clone());
@@ -371,5 +651,197 @@ mod tests {
         assert_eq!(commitment2.updated(), commitment1.updated());
         assert_eq!(commitment2.deleted(), &Some(now2.clone()));
     }
-}
 
+    #[test]
+    fn can_expire() {
+        let now = util::time::now();
+        let id = CommitmentID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::CommitmentCreate, CompanyPermission::CommitmentUpdate], &now);
+        let company_from = make_company(&CompanyID::create(), "bridget's widgets", &now);
+        let company_to = state.company().clone();
+        let agreement = make_agreement(&AgreementID::create(), &vec![company_from.agent_id(), company_to.agent_id()], "order 111222", "UwU big order of widgetzzz", &now);
+        let due: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+
+        let mods = create(state.user(), state.member(), state.company(), &agreement, None, None, id.clone(), Costs::new_with_labor("widgetmaker", 42), OrderAction::Transfer, None, None, None, Some(now.clone()), Some(due.clone()), None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), None, None, company_from.agent_id(), company_to.agent_id(), None, Some(ResourceID::new("widget1")), Some(Measure::new(num!(10), Unit::One)), true, &now).unwrap().into_vec();
+        let commitment1 = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert!(commitment1.is_overdue(&now));
+        assert!(!commitment1.is_overdue(&"2019-12-31T00:00:00Z".parse().unwrap()));
+        state.model = Some(commitment1.clone());
+        let now2 = util::time::now();
+
+        let testfn = |state: &TestState<Commitment, Commitment>| {
+            expire(state.user(), state.member(), state.company(), state.model().clone(), None, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "commitment", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let commitment2 = mods[0].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        assert_eq!(commitment2.lapsed(), &Some(now2.clone()));
+        assert_eq!(commitment2.updated(), &now2);
+        assert!(!commitment2.is_overdue(&now2));
+
+        let flag_id = FlagID::create();
+        let mods = expire(state.user(), state.member(), state.company(), state.model().clone(), Some(flag_id.clone()), &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let flag = mods[0].clone().expect_op::<Flag>(Op::Create).unwrap();
+        assert_eq!(flag.id(), &flag_id);
+        assert_eq!(flag.company_id(), state.company().id());
+        assert_eq!(flag.reason(), &FlagReason::CommitmentLapsed(id.clone()));
+        assert_eq!(flag.note(), &Some("widgetzz".into()));
+        assert!(flag.is_open());
+        mods[1].clone().expect_op::<Commitment>(Op::Update).unwrap();
+
+        // already lapsed
+        let res = expire(state.user(), state.member(), state.company(), commitment2.clone(), None, &now2);
+        assert_eq!(res, Err(Error::CommitmentNotOverdue));
+
+        // finished commitments don't lapse
+        let mut state2 = state.clone();
+        state2.model_mut().inner_mut().set_finished(Some(true));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::CommitmentNotOverdue));
+
+        // no due date, no lapse
+        let mut state3 = state.clone();
+        state3.model_mut().inner_mut().set_due(None);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::CommitmentNotOverdue));
+
+        // the provider can expire it too, but outsiders can't
+        let mut state4 = state.clone();
+        state4.company = Some(company_from.clone());
+        state4.member_mut().inner_mut().set_object(company_from.agent_id());
+        let mods = testfn(&state4).unwrap().into_vec();
+        mods[0].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let mut state5 = state.clone();
+        let outsider = make_company(&CompanyID::create(), "larry's chairs", &now);
+        state5.member_mut().inner_mut().set_object(outsider.agent_id());
+        state5.company = Some(outsider);
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_execute() {
+        let now = util::time::now();
+        let id = CommitmentID::create();
+        let event_id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::CommitmentCreate, CompanyPermission::CommitmentUpdate, CompanyPermission::Transfer, CompanyPermission::DeliverService], &now);
+        let company_to = make_company(&CompanyID::create(), "larry's chairs", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![state.company().agent_id(), company_to.agent_id()], "order 111222", "UwU big order of widgetzzz", &now);
+        let resource = make_resource(&ResourceID::new("widget1"), state.company().id(), &Measure::new(num!(30), Unit::One), &Costs::new_with_labor("widgetmaker", num!(50)), &now);
+        let costs = Costs::new_with_labor("widgetmaker", 42);
+        state.company_mut().set_total_costs(Costs::new_with_labor("widgetmaker", 100));
+        let company_from = state.company().clone();
+
+        let mods = create(state.user(), state.member(), state.company(), &agreement, None, None, id.clone(), costs.clone(), OrderAction::Transfer, None, None, None, None, None, None, Some(false), None, None, None, vec![], None, Some("widgetzz".into()), Some("sending widgets to larry".into()), None, company_from.agent_id(), company_to.agent_id(), None, Some(resource.id().clone()), Some(Measure::new(num!(10), Unit::One)), true, &now).unwrap().into_vec();
+        let commitment = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        state.model = Some(commitment.clone());
+
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<Commitment, Commitment>, exec_state: ExecuteState| {
+            execute(state.user(), state.member(), state.company(), &company_to, &agreement, None, state.model().clone(), event_id.clone(), exec_state, vec![], &now2)
+        };
+        let testfn = |state: &TestState<Commitment, Commitment>| {
+            testfn_inner(state, ExecuteState::Resource { resource_from: resource.clone(), resource_to: ResourceMover::Create(ResourceID::new("widget2")) })
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "commitment", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 6);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let resource3 = mods[2].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let company_from2 = mods[3].clone().expect_op::<Company>(Op::Update).unwrap();
+        let company_to2 = mods[4].clone().expect_op::<Company>(Op::Update).unwrap();
+        let commitment2 = mods[5].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        assert_eq!(event.id(), &event_id);
+        assert_eq!(event.inner().action(), &vf::Action::Transfer);
+        assert_eq!(event.inner().note(), &Some("sending widgets to larry".into()));
+        assert_eq!(event.inner().realization_of(), &Some(agreement.id().clone()));
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(num!(10), Unit::One)));
+        assert_eq!(event.move_costs(), &Some(costs.clone()));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("widgetmaker", 8));
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(20), Unit::One)));
+        assert_eq!(resource3.id(), &ResourceID::new("widget2"));
+        assert_eq!(resource3.costs(), &costs);
+        assert_eq!(company_from2.total_costs(), &Costs::new_with_labor("widgetmaker", 58));
+        assert_eq!(company_to2.total_costs(), &costs);
+        assert_eq!(commitment2.inner().finished(), &Some(true));
+        assert_eq!(commitment2.updated(), &now2);
+
+        // can't execute twice
+        let mut state2 = state.clone();
+        state2.model = Some(commitment2);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+
+        // inactive and lapsed commitments can't be executed
+        let mut state3 = state.clone();
+        state3.model_mut().set_active(false);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsInactive("commitment".into())));
+        let mut state4 = state.clone();
+        state4.model_mut().set_lapsed(Some(now.clone()));
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::CommitmentLapsed));
+
+        // the state has to match the commitment
+        let process_from = make_process(&ProcessID::create(), company_from.id(), "make widgets", &Costs::new_with_labor("widgetmaker", 100), &now);
+        let process_to = make_process(&ProcessID::create(), company_to.id(), "make chairs", &Costs::new(), &now);
+        let res = testfn_inner(&state, ExecuteState::Service { process_from: process_from.clone(), process_to: process_to.clone() });
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+        let mut resource_other = resource.clone();
+        resource_other.set_id(ResourceID::new("widget3"));
+        let res = testfn_inner(&state, ExecuteState::Resource { resource_from: resource_other, resource_to: ResourceMover::Create(ResourceID::new("widget2")) });
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+
+        // if the agreement's document changed since the commitment was made, the
+        // commitment can't be executed
+        let mut agreement2 = agreement.clone();
+        agreement2.set_document_hash(Some(Agreement::hash_document(b"20 widgets")));
+        let res = execute(state.user(), state.member(), state.company(), &company_to, &agreement2, None, commitment.clone(), event_id.clone(), ExecuteState::Resource { resource_from: resource.clone(), resource_to: ResourceMover::Create(ResourceID::new("widget2")) }, vec![], &now2);
+        assert_eq!(res, Err(Error::MissingFields(vec!["document_hash".into()])));
+
+        // only the provider can execute
+        let res = execute(state.user(), state.member(), state.company(), state.company(), &agreement, None, commitment.clone(), event_id.clone(), ExecuteState::Resource { resource_from: resource.clone(), resource_to: ResourceMover::Create(ResourceID::new("widget2")) }, vec![], &now2);
+        assert_eq!(res, Err(Error::CommitmentInvalid));
+
+        // services work too
+        let mods = create(state.user(), state.member(), state.company(), &agreement, None, None, CommitmentID::create(), costs.clone(), OrderAction::DeliverService, None, None, None, None, None, None, None, None, None, None, vec![], Some(process_to.id().clone()), None, None, None, company_from.agent_id(), company_to.agent_id(), None, None, None, true, &now).unwrap().into_vec();
+        let commitment3 = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        let mods = execute(state.user(), state.member(), state.company(), &company_to, &agreement, None, commitment3, event_id.clone(), ExecuteState::Service { process_from: process_from.clone(), process_to: process_to.clone() }, vec![], &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 4);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process_from2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let process_to2 = mods[2].clone().expect_op::<Process>(Op::Update).unwrap();
+        let commitment4 = mods[3].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        assert_eq!(event.inner().action(), &vf::Action::DeliverService);
+        assert_eq!(process_from2.costs(), &Costs::new_with_labor("widgetmaker", 58));
+        assert_eq!(process_to2.costs(), &costs);
+        assert_eq!(commitment4.inner().finished(), &Some(true));
+
+        // processes blocked on the commitment get unblocked
+        let mut process_to3 = process_to.clone();
+        process_to3.set_blocked_on(vec![commitment4.id().clone(), CommitmentID::new("bolts")]);
+        let mut process_other = make_process(&ProcessID::create(), company_to.id(), "paint chairs", &Costs::new(), &now);
+        process_other.set_blocked_on(vec![commitment4.id().clone()]);
+        let process_unrelated = make_process(&ProcessID::create(), company_to.id(), "sand chairs", &Costs::new(), &now);
+        let mut commitment5 = commitment4.clone();
+        commitment5.inner_mut().set_finished(Some(false));
+        let blocked = vec![process_to3.clone(), process_other.clone(), process_unrelated.clone()];
+        let mods = execute(state.user(), state.member(), state.company(), &company_to, &agreement, None, commitment5, event_id.clone(), ExecuteState::Service { process_from: process_from.clone(), process_to: process_to3.clone() }, blocked, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let process_to4 = mods[2].clone().expect_op::<Process>(Op::Update).unwrap();
+        mods[3].clone().expect_op::<Commitment>(Op::Update).unwrap();
+        let process_other2 = mods[4].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process_to4.costs(), &costs);
+        assert_eq!(process_to4.blocked_on(), &vec![CommitmentID::new("bolts")]);
+        assert_eq!(process_other2.id(), process_other.id());
+        assert!(!process_other2.is_blocked());
+        assert_eq!(process_other2.updated(), &now2);
+    }
+}
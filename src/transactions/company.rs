@@ -20,16 +20,18 @@ use crate::{
         Modifications,
         account::Account,
         company::{Company, CompanyID, Permission as CompanyPermission},
+        company_treasury::CompanyTreasury,
+        delegation::Delegation,
         event::Event,
         lib::basis_model::Model,
         member::{Member, MemberID, MemberClass},
         process::{Process, ProcessID},
         user::User,
     },
+    system::payroll,
 };
 use rust_decimal::prelude::*;
 use std::collections::HashMap;
-use std::convert::TryInto;
 use vf_rs::vf;
 
 /// An object that is passed into a `company::create()` transaction that
@@ -69,6 +71,7 @@ pub fn create<T: Into<String>>(caller: &User, id: CompanyID, company_name: T, co
         .email(company_email)
         .max_costs(Decimal::zero())
         .total_costs(Costs::new())
+        .waste_costs(Costs::new())
         .active(company_active)
         .created(now.clone())
         .updated(now.clone())
@@ -122,60 +125,73 @@ pub fn update(caller: &User, member: &Member, mut subject: Company, name: Option
 ///
 /// Takes a set of `work` events, a hash map of MemberID -> Account pairs, and
 /// a hash map of ProcessID -> Process pairs and returns any modifications done
-/// to the subject Company, Processes, and Accounts.
-pub fn payroll(caller: &User, member: &Member, mut subject: Company, mut accounts: HashMap<MemberID, Account>, mut processes: HashMap<ProcessID, Process>, work_events: &Vec<Event>, now: &DateTime<Utc>) -> Result<Modifications> {
+/// to the subject Company, Accounts, and Processes (in that order).
+///
+/// Only work events that fall within the pay period are paid (`period_start`
+/// is inclusive, `period_end` is exclusive, and we go by when the work ended).
+/// A period can only be paid once: the company's `treasury` remembers the end
+/// of the last period paid, and payroll won't pay a period starting before it.
+/// What each member is owed, and the deposits into their accounts, come from
+/// [system::payroll][2].
+///
+/// The credits paid out are recorded as issued by the treasury and checked
+/// against its issuance policy. The updated treasury is returned last.
+///
+/// A member without the `Payroll` permission can still run payroll if it was
+/// [delegated][1] to them: pass in the member's `delegations` along with the
+/// members that granted them (`grantors`).
+///
+/// [1]: ../member/fn.delegate.html
+/// [2]: ../../system/payroll/index.html
+pub fn payroll(caller: &User, member: &Member, delegations: &[Delegation], grantors: &[Member], mut subject: Company, mut treasury: CompanyTreasury, accounts: HashMap<MemberID, Account>, mut processes: HashMap<ProcessID, Process>, work_events: &[Event], period_start: &DateTime<Utc>, period_end: &DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyPayroll)?;
-    member.access_check(caller.id(), subject.id(), CompanyPermission::Payroll)?;
+    member.access_check_delegated(caller.id(), subject.id(), CompanyPermission::Payroll, delegations, grantors, now)?;
     if subject.is_deleted() {
         Err(Error::ObjectIsDeleted("company".into()))?;
     }
-    let mut mod_company = false;
-    let mut mod_account: HashMap<MemberID, ()> = HashMap::new();
-    let mut mod_process: HashMap<ProcessID, ()> = HashMap::new();
+    if treasury.company_id() != subject.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    treasury.check_pay_period(period_start, period_end)?;
+    let account_mods = payroll::calculate(accounts, work_events, period_start, period_end, now)?;
+    let mut total = Decimal::zero();
+    let mut modified_processes: Vec<ProcessID> = Vec::new();
     let err_mf = |msg| { || Error::MissingFields(vec![msg]) };
-    // loop once, make any mods we want, and track the objects we modified in
-    // a sorted btree hash
-    for work in work_events {
+    // charge each paid work event's costs to the company and the process the
+    // work went into, tracking the processes in the order they're first
+    // charged so the updates returned are deterministic.
+    for work in work_events.iter().filter(|work| payroll::payable(work, period_start, period_end)) {
         let costs = work.move_costs().clone().ok_or_else(err_mf("move_costs".into()))?;
         if costs.is_zero() {
             continue;
         }
-        let member_id: MemberID = work.inner().provider().clone().try_into()?;
         let process_id = work.inner().input_of().clone().ok_or_else(err_mf("process.inner.input_of".into()))?;
-        let account = accounts.get_mut(&member_id).ok_or_else(err_mf(format!("accounts::{}", member_id.as_str())))?;
         let process = processes.get_mut(&process_id).ok_or_else(err_mf(format!("processes::{}", process_id.as_str())))?;
         subject.increase_costs(costs.clone())?;
-        account.adjust_balance(costs.credits().clone())?;
-        process.set_costs(process.costs().clone() + costs.clone());
-        subject.set_updated(now.clone());
-        account.set_updated(now.clone());
-        process.set_updated(now.clone());
-
-        mod_company = true;
-        mod_account.insert(member_id.clone(), ());
-        mod_process.insert(process_id.clone(), ());
+        total += costs.credits();
+        process.set_costs(process.costs().clone() + costs);
+        process.set_updated(*now);
+        if !modified_processes.contains(&process_id) {
+            modified_processes.push(process_id);
+        }
     }
     let mut mods = Modifications::new();
-    if mod_company {
+    if !modified_processes.is_empty() {
+        subject.set_updated(*now);
         mods.push(Op::Update, subject);
     }
-    // loop again, pulling out any modified objects, and creating Ops for them.
-    // we do a double-loop so that the order of the updates returned is
-    // *deterministic* based on the order of the work events passed in.
-    for work in work_events {
-        let member_id: MemberID = work.inner().provider().clone().try_into()?;
-        let process_id = work.inner().input_of().clone().ok_or_else(err_mf("process.inner.input_of".into()))?;
-        if mod_account.contains_key(&member_id) {
-            if let Some(account) = accounts.remove(&member_id) {
-                mods.push(Op::Update, account);
-            }
-        }
-        if mod_process.contains_key(&process_id) {
-            if let Some(process) = processes.remove(&process_id) {
-                mods.push(Op::Update, process);
-            }
+    for account_mod in account_mods {
+        mods.push_raw(account_mod);
+    }
+    for process_id in modified_processes {
+        if let Some(process) = processes.remove(&process_id) {
+            mods.push(Op::Update, process);
         }
     }
+    treasury.issue(total)?;
+    treasury.set_paid_through(Some(period_end.clone()));
+    treasury.set_updated(now.clone());
+    mods.push(Op::Update, treasury);
     Ok(mods)
 }
 
@@ -200,6 +216,7 @@ mod tests {
         models::{
             Op,
             account::AccountID,
+            delegation::DelegationID,
             event::EventID,
             lib::agent::Agent,
             member::{MemberClass, MemberWorker},
@@ -314,9 +331,10 @@ mod tests {
         let process_ids = vec![process1.id().clone(), process2.id().clone()];
         processes.insert(process1.id().clone(), process1);
         processes.insert(process2.id().clone(), process2);
+        let occupation = make_occupation(&OccupationID::new("bantha herder"), "bantha herder", None, &now);
         for i in 0..3 {
             let user = make_user(&UserID::create(), None, &now);
-            let member = make_member_worker(&MemberID::create(), user.id(), state.company().id(), &OccupationID::new("bantha herder"), vec![CompanyPermission::Work], &now);
+            let member = make_member_worker(&MemberID::create(), user.id(), state.company().id(), occupation.id(), vec![CompanyPermission::Work], &now);
             let account = make_account(&AccountID::create(), user.id(), num!(0), format!("{}'s account", user.id().as_str()), &now);
             let process_id = if i < 1 { process_ids[0].clone() } else { process_ids[1].clone() };
             accounts.insert(member.id().clone(), account);
@@ -324,14 +342,17 @@ mod tests {
                 let start = "2020-01-01T08:00:00.001-08:00".parse().unwrap();
                 let end = "2020-01-01T16:34:00.001-08:00".parse().unwrap();
                 let wage = rust_decimal::Decimal::from(10 + (i + 1) + (ii + 1));
-                let mods = crate::transactions::event::work::work(&user, &member, state.company(), EventID::create(), member.clone(), processes.get(&process_id).unwrap().clone(), Some(wage), start, end, Some("working".into()), &now).unwrap().into_vec();
+                let mods = crate::transactions::event::work::work(&user, &member, state.company(), EventID::create(), member.clone(), &occupation, processes.get(&process_id).unwrap().clone(), Some(wage), start, end, None, None, &[], Some("working".into()), &now).unwrap().into_vec();
                 let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
                 work_events.push(event);
             }
         }
+        let treasury = make_company_treasury(state.company().id(), num!(0), &now);
+        let period_start: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let period_end: DateTime<Utc> = "2020-01-15T00:00:00Z".parse().unwrap();
         let now2 = util::time::now();
         let testfn_inner = |state: &TestState<Company, Company>, accounts, processes| {
-            payroll(state.user(), state.member(), state.company().clone(), accounts, processes, &work_events, &now2)
+            payroll(state.user(), state.member(), &[], &[], state.company().clone(), treasury.clone(), accounts, processes, &work_events, &period_start, &period_end, &now2)
         };
         let testfn = |state: &TestState<Company, Company>| {
             testfn_inner(state, accounts.clone(), processes.clone())
@@ -339,20 +360,52 @@ mod tests {
         test::permissions_checks(&state, &testfn);
 
         let mods = testfn(&state).unwrap().into_vec();
-        assert_eq!(mods.len(), 6);
+        assert_eq!(mods.len(), 7);
         let company2 = mods[0].clone().expect_op::<Company>(Op::Update).unwrap();
         let account1_2 = mods[1].clone().expect_op::<Account>(Op::Update).unwrap();
-        let process1_2 = mods[2].clone().expect_op::<Process>(Op::Update).unwrap();
-        let account2_2 = mods[3].clone().expect_op::<Account>(Op::Update).unwrap();
-        let process2_2 = mods[4].clone().expect_op::<Process>(Op::Update).unwrap();
-        let account3_2 = mods[5].clone().expect_op::<Account>(Op::Update).unwrap();
+        let account2_2 = mods[2].clone().expect_op::<Account>(Op::Update).unwrap();
+        let account3_2 = mods[3].clone().expect_op::<Account>(Op::Update).unwrap();
+        let process1_2 = mods[4].clone().expect_op::<Process>(Op::Update).unwrap();
+        let process2_2 = mods[5].clone().expect_op::<Process>(Op::Update).unwrap();
+        let treasury2 = mods[6].clone().expect_op::<CompanyTreasury>(Op::Update).unwrap();
 
         assert_eq!(company2.total_costs(), &Costs::new_with_labor("bantha herder", 81));
         assert_eq!(account1_2.balance(), &num!(25));
         assert_eq!(account2_2.balance(), &num!(27));
         assert_eq!(account3_2.balance(), &num!(29));
+        // the same amounts system::payroll says each member is owed
+        let owed = payroll::paychecks(&work_events, &period_start, &period_end).unwrap().into_iter()
+            .map(|paycheck| *paycheck.amount())
+            .collect::<Vec<_>>();
+        assert_eq!(owed, vec![num!(25), num!(27), num!(29)]);
         assert_eq!(process1_2.costs(), &Costs::new_with_labor("bantha herder", 25));
         assert_eq!(process2_2.costs(), &Costs::new_with_labor("bantha herder", 27 + 29));
+        assert_eq!(treasury2.issued(), &num!(81));
+        assert_eq!(treasury2.paid_through(), &Some(period_end.clone()));
+        assert_eq!(treasury2.updated(), &now2);
+
+        // a period can't be paid twice
+        let res = payroll(state.user(), state.member(), &[], &[], state.company().clone(), treasury2.clone(), accounts.clone(), processes.clone(), &work_events, &period_start, &period_end, &now2);
+        assert_eq!(res, Err(Error::PayPeriodAlreadyPaid));
+        let res = payroll(state.user(), state.member(), &[], &[], state.company().clone(), treasury.clone(), accounts.clone(), processes.clone(), &work_events, &period_end, &period_start, &now2);
+        assert_eq!(res, Err(Error::PayPeriodInvalid));
+        // and work outside of the period isn't paid
+        let period2_end: DateTime<Utc> = "2020-02-01T00:00:00Z".parse().unwrap();
+        let mods = payroll(state.user(), state.member(), &[], &[], state.company().clone(), treasury2.clone(), accounts.clone(), processes.clone(), &work_events, &period_end, &period2_end, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let treasury3 = mods[0].clone().expect_op::<CompanyTreasury>(Op::Update).unwrap();
+        assert_eq!(treasury3.issued(), &num!(81));
+        assert_eq!(treasury3.paid_through(), &Some(period2_end.clone()));
+
+        // the treasury's issuance policy caps what payroll can pay out
+        let mut treasury3 = treasury.clone();
+        treasury3.set_max_issue_per_run(Some(num!(80)));
+        let res = payroll(state.user(), state.member(), &[], &[], state.company().clone(), treasury3, accounts.clone(), processes.clone(), &work_events, &period_start, &period_end, &now2);
+        assert_eq!(res, Err(Error::TreasuryLimitExceeded("issuing 81 credits is over the per-run limit of 80".into())));
+        let mut treasury4 = treasury.clone();
+        treasury4.set_company_id(CompanyID::create());
+        let res = payroll(state.user(), state.member(), &[], &[], state.company().clone(), treasury4, accounts.clone(), processes.clone(), &work_events, &period_start, &period_end, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
 
         let mut state2 = state.clone();
         state2.company_mut().set_max_costs(num!(81));
@@ -369,6 +422,21 @@ mod tests {
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::ObjectIsDeleted("company".into())));
 
+        // a coworker can run payroll with a delegated permission, as long as
+        // the member who delegated it still has it
+        let cover_user = make_user(&UserID::create(), None, &now);
+        let cover = make_member_worker(&MemberID::create(), cover_user.id(), state.company().id(), &OccupationID::create(), vec![], &now);
+        let delegation = crate::transactions::member::delegate(state.user(), state.member(), state.company(), DelegationID::create(), &cover, vec![CompanyPermission::Payroll], now2.clone() + chrono::Duration::days(7), &now).unwrap().into_vec()[0].clone().expect_op::<Delegation>(Op::Create).unwrap();
+        let delegations = vec![delegation];
+        let res = payroll(&cover_user, &cover, &[], &[], state.company().clone(), treasury.clone(), accounts.clone(), processes.clone(), &work_events, &period_start, &period_end, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let res = payroll(&cover_user, &cover, &delegations, &[state.member().clone()], state.company().clone(), treasury.clone(), accounts.clone(), processes.clone(), &work_events, &period_start, &period_end, &now2);
+        assert!(res.is_ok());
+        let mut grantor = state.member().clone();
+        grantor.set_permissions(vec![]);
+        let res = payroll(&cover_user, &cover, &delegations, &[grantor], state.company().clone(), treasury.clone(), accounts.clone(), processes.clone(), &work_events, &period_start, &period_end, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
         let mut accounts2 = accounts.clone();
         let key = accounts2.keys().collect::<Vec<_>>()[0].clone();
         accounts2.remove(&key).unwrap();
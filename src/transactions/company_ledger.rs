@@ -0,0 +1,266 @@
+//! A company ledger keeps running totals of the costs moving into and out of a
+//! company.
+//!
+//! Once created, a ledger is kept up to date by passing it the modifications
+//! returned from other transactions via `apply()`. Periodically closing the
+//! ledger's period (`close_period()`) keeps the list of events it has counted
+//! from growing forever.
+//!
+//! See the [company ledger model.][1]
+//!
+//! [1]: ../../models/company_ledger/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        company_ledger::{CompanyLedger, CompanyLedgerID},
+        member::Member,
+        lib::basis_model::Model,
+        user::User,
+    },
+};
+use std::collections::HashMap;
+
+/// Create a new, empty ledger for a company
+pub fn create(caller: &User, member: &Member, company: &Company, id: CompanyLedgerID, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::CompanyUpdate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    let model = CompanyLedger::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .costs_received(Costs::new())
+        .costs_sent(Costs::new())
+        .labor_paid(Costs::new())
+        .waste(Costs::new())
+        .external_sales(Costs::new())
+        .market_balance(HashMap::new())
+        .debts(HashMap::new())
+        .recorded_events(HashMap::new())
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Apply the modifications from a set of already-run transactions to a
+/// company's ledger.
+///
+/// No permissions are checked here because the ledger only summarizes changes
+/// that have already been permitted. If none of the modifications affect the
+/// ledger, no modifications are returned.
+pub fn apply(mut subject: CompanyLedger, modifications: &Modifications, now: &DateTime<Utc>) -> Result<Modifications> {
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_ledger".into()))?;
+    }
+    if !subject.apply(modifications) {
+        return Ok(Modifications::new());
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Close a ledger's period at `end`, forgetting the events it has counted that
+/// completed before then.
+///
+/// Every event completed before `end` must already have been applied: once the
+/// period is closed, those events are ignored by `apply()`. The period must
+/// end after the previous one and no later than `now`.
+pub fn close_period(caller: &User, member: &Member, company: &Company, mut subject: CompanyLedger, end: DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::CompanyUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_ledger".into()))?;
+    }
+    if &end > now || subject.recorded_through().map(|through| end <= through).unwrap_or(false) {
+        Err(Error::LedgerPeriodInvalid)?;
+    }
+    subject.close_period(&end);
+    subject.set_updated(*now);
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            event::{Event, EventID, EventProcessState},
+            member::Member,
+            process::ProcessID,
+            resource::ResourceID,
+        },
+        transactions::event::{self, accounting::{self, WriteOff}, work},
+        util::{self, test::{self, *}},
+    };
+    use om2::{Measure, NumericUnion, Unit};
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = CompanyLedgerID::create();
+        let state = TestState::standard(vec![CompanyPermission::CompanyUpdate], &now);
+
+        let testfn = |state: &TestState<CompanyLedger, CompanyLedger>| {
+            create(state.user(), state.member(), state.company(), id.clone(), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Create).unwrap();
+        assert_eq!(ledger.id(), &id);
+        assert_eq!(ledger.company_id(), state.company().id());
+        assert_eq!(ledger.costs_received(), &Costs::new());
+        assert_eq!(ledger.costs_sent(), &Costs::new());
+        assert_eq!(ledger.labor_paid(), &Costs::new());
+        assert_eq!(ledger.waste(), &Costs::new());
+        assert_eq!(ledger.active(), &true);
+        assert_eq!(ledger.created(), &now);
+        assert_eq!(ledger.updated(), &now);
+        assert_eq!(ledger.deleted(), &None);
+    }
+
+    #[test]
+    fn can_apply() {
+        let now = util::time::now();
+        let mut state: TestState<CompanyLedger, CompanyLedger> = TestState::standard(vec![CompanyPermission::CompanyUpdate, CompanyPermission::WriteOff], &now);
+        let mods = create(state.user(), state.member(), state.company(), CompanyLedgerID::create(), &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Create).unwrap();
+        let costs = Costs::new_with_labor("cheesemaker", 100);
+        let resource = make_resource(&ResourceID::new("cheese"), state.company().id(), &Measure::new(num!(10), Unit::One), &costs, &now);
+        state.company_mut().set_total_costs(costs.clone());
+        state.model = Some(ledger);
+
+        let write_off_mods = accounting::write_off(state.user(), state.member(), state.company(), EventID::create(), WriteOff::Resource(resource, NumericUnion::Integer(2)), Costs::new_with_labor("cheesemaker", 20), None, &now).unwrap();
+
+        let now2 = util::time::now();
+        let mods = apply(state.model().clone(), &write_off_mods, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let ledger2 = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger2.id(), state.model().id());
+        assert_eq!(ledger2.waste(), &Costs::new_with_labor("cheesemaker", 20));
+        assert_eq!(ledger2.costs_received(), &Costs::new());
+        assert_eq!(ledger2.updated(), &now2);
+
+        // modifications that don't touch the ledger don't update it
+        let mut unrelated = Modifications::new();
+        unrelated.push(Op::Update, make_resource(&ResourceID::new("cheese"), &CompanyID::new("larry's cheese"), &Measure::new(num!(10), Unit::One), &costs, &now));
+        let mods = apply(state.model().clone(), &unrelated, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_deleted(Some(now.clone()));
+        let res = apply(state2.model().clone(), &write_off_mods, &now2);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("company_ledger".into())));
+    }
+
+    #[test]
+    fn applies_completed_pending_events() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let now2: DateTime<Utc> = "2018-06-06T06:00:00Z".parse().unwrap();
+        let state: TestState<CompanyLedger, CompanyLedger> = TestState::standard(vec![CompanyPermission::CompanyUpdate, CompanyPermission::Work, CompanyPermission::EventFinalize], &now);
+        let mods = create(state.user(), state.member(), state.company(), CompanyLedgerID::create(), &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Create).unwrap();
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let occupation = make_occupation(&occupation_id, "machinist", None, &now);
+        let worker: Member = state.member().clone();
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+
+        // clocking in doesn't count, but clocking out does
+        let in_mods = work::clock_in(state.user(), state.member(), state.company(), EventID::create(), &worker, &occupation, &process, &vec![], None, now.clone(), None, &now).unwrap();
+        let mods = apply(ledger.clone(), &in_mods, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+        let session = in_mods.into_vec()[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let out_mods = work::clock_out(state.user(), state.member(), state.company(), session.clone(), worker.clone(), &occupation, process.clone(), Some(num!(120)), &vec![session.clone()], None, None, now2.clone(), &now2).unwrap();
+        let mods = apply(ledger.clone(), &out_mods, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let ledger2 = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger2.labor_paid(), &Costs::new_with_labor(occupation_id.clone(), num!(120)));
+        // and applying the same modifications again doesn't count them twice
+        let mods = apply(ledger2.clone(), &out_mods, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+
+        // finalizing a pending event counts it
+        let work_mods = work::work(state.user(), state.member(), state.company(), EventID::create(), worker.clone(), &occupation, process.clone(), Some(num!(40)), now.clone(), now2.clone(), None, None, &vec![], None, &now).unwrap().into_vec();
+        let mut pending = work_mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        pending.inner_mut().set_has_end(None);
+        let mut created = Modifications::new();
+        created.push(Op::Create, pending.clone());
+        let mods = apply(ledger2.clone(), &created, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+        let process_state = EventProcessState::builder()
+            .input_of(process.clone())
+            .provider(worker.clone())
+            .build().unwrap();
+        let final_mods = event::finalize(state.user(), state.member(), state.company(), pending, now2.clone(), process_state, &now2).unwrap();
+        let mods = apply(ledger2.clone(), &final_mods, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let ledger3 = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger3.labor_paid(), &Costs::new_with_labor(occupation_id.clone(), num!(160)));
+        assert_eq!(ledger3.recorded_events().len(), 2);
+    }
+
+    #[test]
+    fn can_close_period() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let now2: DateTime<Utc> = "2018-06-07T00:00:00Z".parse().unwrap();
+        let mut state: TestState<CompanyLedger, CompanyLedger> = TestState::standard(vec![CompanyPermission::CompanyUpdate, CompanyPermission::WriteOff], &now);
+        let mods = create(state.user(), state.member(), state.company(), CompanyLedgerID::create(), &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Create).unwrap();
+        let costs = Costs::new_with_labor("cheesemaker", 100);
+        let resource = make_resource(&ResourceID::new("cheese"), state.company().id(), &Measure::new(num!(10), Unit::One), &costs, &now);
+        state.company_mut().set_total_costs(costs.clone());
+        let write_off_mods = accounting::write_off(state.user(), state.member(), state.company(), EventID::create(), WriteOff::Resource(resource, NumericUnion::Integer(2)), Costs::new_with_labor("cheesemaker", 20), None, &now).unwrap();
+        let mods = apply(ledger, &write_off_mods, &now).unwrap().into_vec();
+        state.model = Some(mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap());
+        assert_eq!(state.model().recorded_events().len(), 1);
+
+        let end: DateTime<Utc> = "2018-06-06T12:00:00Z".parse().unwrap();
+        let testfn = |state: &TestState<CompanyLedger, CompanyLedger>| {
+            close_period(state.user(), state.member(), state.company(), state.model().clone(), end.clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "company_ledger", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let ledger2 = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger2.recorded_through(), &Some(end.clone()));
+        assert_eq!(ledger2.recorded_events().len(), 0);
+        assert_eq!(ledger2.waste(), &Costs::new_with_labor("cheesemaker", 20));
+        assert_eq!(ledger2.updated(), &now2);
+        // events from the closed period aren't counted again
+        let mods = apply(ledger2.clone(), &write_off_mods, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+
+        // periods must move forward, and can't close in the future
+        let res = close_period(state.user(), state.member(), state.company(), ledger2.clone(), end.clone(), &now2);
+        assert_eq!(res, Err(Error::LedgerPeriodInvalid));
+        let res = close_period(state.user(), state.member(), state.company(), state.model().clone(), now2 + chrono::Duration::seconds(1), &now2);
+        assert_eq!(res, Err(Error::LedgerPeriodInvalid));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
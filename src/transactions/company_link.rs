@@ -0,0 +1,279 @@
+//! Company links join member companies to federations. A company asks to join
+//! a federation by linking itself to it, choosing which of its permissions the
+//! federation's members inherit, and the federation then accepts the link.
+//! Either side can break the link.
+//!
+//! See the [company link model.][1]
+//!
+//! [1]: ../../models/company_link/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        company_link::{CompanyLink, CompanyLinkID},
+        member::Member,
+        lib::basis_model::Model,
+        user::User,
+    },
+};
+
+/// Link a company (`child`) to a federation (`parent`). This is done by a
+/// member of the child company, since it's the child that grants permissions
+/// to the federation's members. The link doesn't grant anything until the
+/// federation [accepts it][accept].
+///
+/// Links can only pass on the [process permissions][inheritable], since those
+/// are the only transactions that honor federation access.
+///
+/// [accept]: fn.accept.html
+/// [inheritable]: ../../models/company_link/struct.CompanyLink.html#associatedconstant.INHERITABLE
+pub fn link(caller: &User, member: &Member, parent: &Company, child: &Company, id: CompanyLinkID, inherit_permissions: Vec<CompanyPermission>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), child.id(), CompanyPermission::CompanyLinkCreate)?;
+    if !child.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !parent.is_active() {
+        Err(Error::ObjectIsInactive("federation".into()))?;
+    }
+    if parent.id() == child.id() {
+        Err(Error::CompanyLinkInvalid)?;
+    }
+    CompanyLink::check_inheritable(&inherit_permissions)?;
+    let model = CompanyLink::builder()
+        .id(id)
+        .parent_id(parent.id().clone())
+        .child_id(child.id().clone())
+        .inherit_permissions(inherit_permissions)
+        .accepted(None)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Accept a link to a federation (`parent`) from one of its member companies.
+/// This is done by a member of the federation.
+pub fn accept(caller: &User, member: &Member, parent: &Company, mut subject: CompanyLink, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), parent.id(), CompanyPermission::CompanyLinkCreate)?;
+    if parent.id() != subject.parent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !parent.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_link".into()))?;
+    }
+    if subject.is_accepted() {
+        Err(Error::CompanyLinkInvalid)?;
+    }
+    subject.set_accepted(Some(*now));
+    subject.set_updated(*now);
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Update the permissions a company grants to its federation's members (or
+/// pause the link). Only the child company can do this.
+pub fn update(caller: &User, member: &Member, child: &Company, mut subject: CompanyLink, inherit_permissions: Option<Vec<CompanyPermission>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), child.id(), CompanyPermission::CompanyLinkUpdate)?;
+    if child.id() != subject.child_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !child.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_link".into()))?;
+    }
+    if let Some(inherit_permissions) = inherit_permissions {
+        CompanyLink::check_inheritable(&inherit_permissions)?;
+        subject.set_inherit_permissions(inherit_permissions);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Remove a link. This can be done from either side of the link (the
+/// federation or the member company).
+pub fn unlink(caller: &User, member: &Member, company: &Company, mut subject: CompanyLink, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::CompanyLinkDelete)?;
+    if company.id() != subject.parent_id() && company.id() != subject.child_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_link".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::company::CompanyID,
+        util::{self, test::{self, *}},
+    };
+
+    #[test]
+    fn can_link() {
+        let now = util::time::now();
+        let id = CompanyLinkID::create();
+        let state = TestState::standard(vec![CompanyPermission::CompanyLinkCreate], &now);
+        let federation = make_company(&CompanyID::new("widget federation"), "widget federation", &now);
+
+        let testfn = |state: &TestState<CompanyLink, CompanyLink>| {
+            link(state.user(), state.member(), &federation, state.company(), id.clone(), vec![CompanyPermission::ProcessCreate], &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let link1 = mods[0].clone().expect_op::<CompanyLink>(Op::Create).unwrap();
+        assert_eq!(link1.id(), &id);
+        assert_eq!(link1.parent_id(), federation.id());
+        assert_eq!(link1.child_id(), state.company().id());
+        assert_eq!(link1.inherit_permissions(), &vec![CompanyPermission::ProcessCreate]);
+        assert_eq!(link1.accepted(), &None);
+        assert_eq!(link1.active(), &true);
+        assert_eq!(link1.created(), &now);
+        assert_eq!(link1.updated(), &now);
+        assert_eq!(link1.deleted(), &None);
+
+        let res = link(state.user(), state.member(), state.company(), state.company(), id.clone(), vec![], &now);
+        assert_eq!(res, Err(Error::CompanyLinkInvalid));
+
+        let mut federation2 = federation.clone();
+        federation2.set_deleted(Some(now.clone()));
+        let res = link(state.user(), state.member(), &federation2, state.company(), id.clone(), vec![], &now);
+        assert_eq!(res, Err(Error::ObjectIsInactive("federation".into())));
+
+        // only the child company can link itself
+        let res = link(state.user(), state.member(), state.company(), &federation, id.clone(), vec![], &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // federation access only covers processes
+        let res = link(state.user(), state.member(), &federation, state.company(), id.clone(), vec![CompanyPermission::ProcessCreate, CompanyPermission::Work], &now);
+        assert_eq!(res, Err(Error::CompanyLinkInvalid));
+    }
+
+    #[test]
+    fn can_accept() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::CompanyLinkCreate], &now);
+        let member_company = make_company(&CompanyID::new("jerry's widgets"), "jerry's widgets", &now);
+        let link = CompanyLink::builder()
+            .id(CompanyLinkID::create())
+            .parent_id(state.company().id().clone())
+            .child_id(member_company.id().clone())
+            .inherit_permissions(vec![CompanyPermission::ProcessCreate])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        state.model = Some(link);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<CompanyLink, CompanyLink>| {
+            accept(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "company_link", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let link2 = mods[0].clone().expect_op::<CompanyLink>(Op::Update).unwrap();
+        assert_eq!(link2.accepted(), &Some(now2.clone()));
+        assert_eq!(link2.updated(), &now2);
+
+        // can't accept twice
+        let mut state2 = state.clone();
+        state2.model = Some(link2.clone());
+        assert_eq!(testfn(&state2), Err(Error::CompanyLinkInvalid));
+
+        // only the federation can accept
+        let mut state3 = state.clone();
+        state3.model_mut().set_parent_id(member_company.id().clone());
+        state3.model_mut().set_child_id(state.company().id().clone());
+        assert_eq!(testfn(&state3), Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_update() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::CompanyLinkCreate, CompanyPermission::CompanyLinkUpdate], &now);
+        let federation = make_company(&CompanyID::new("widget federation"), "widget federation", &now);
+        let mods = link(state.user(), state.member(), &federation, state.company(), CompanyLinkID::create(), vec![CompanyPermission::ProcessCreate], &now).unwrap().into_vec();
+        state.model = Some(mods[0].clone().expect_op::<CompanyLink>(Op::Create).unwrap());
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<CompanyLink, CompanyLink>| {
+            update(state.user(), state.member(), state.company(), state.model().clone(), Some(vec![CompanyPermission::All]), Some(false), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "company_link", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let link2 = mods[0].clone().expect_op::<CompanyLink>(Op::Update).unwrap();
+        assert_eq!(link2.inherit_permissions(), &vec![CompanyPermission::All]);
+        assert_eq!(link2.active(), &false);
+        assert_eq!(link2.updated(), &now2);
+
+        // the federation can't change what it's granted
+        let mut state2 = state.clone();
+        state2.model_mut().set_child_id(federation.id().clone());
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+
+        let res = update(state.user(), state.member(), state.company(), state.model().clone(), Some(vec![CompanyPermission::CommitmentCreate]), None, &now2);
+        assert_eq!(res, Err(Error::CompanyLinkInvalid));
+    }
+
+    #[test]
+    fn can_unlink() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::CompanyLinkCreate, CompanyPermission::CompanyLinkDelete], &now);
+        let federation = make_company(&CompanyID::new("widget federation"), "widget federation", &now);
+        let mods = link(state.user(), state.member(), &federation, state.company(), CompanyLinkID::create(), vec![CompanyPermission::ProcessCreate], &now).unwrap().into_vec();
+        state.model = Some(mods[0].clone().expect_op::<CompanyLink>(Op::Create).unwrap());
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<CompanyLink, CompanyLink>| {
+            unlink(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "company_link", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let link2 = mods[0].clone().expect_op::<CompanyLink>(Op::Delete).unwrap();
+        assert_eq!(link2.deleted(), &Some(now2.clone()));
+
+        // the federation side can unlink too
+        let mut state2 = state.clone();
+        state2.model_mut().set_child_id(CompanyID::new("larry's chairs"));
+        state2.model_mut().set_parent_id(state.company().id().clone());
+        assert!(testfn(&state2).is_ok());
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_child_id(CompanyID::new("larry's chairs"));
+        assert_eq!(testfn(&state3), Err(Error::InsufficientPrivileges));
+    }
+}
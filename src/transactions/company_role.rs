@@ -0,0 +1,260 @@
+//! Company roles are named bundles of company permissions that can be assigned
+//! to members (see `member::set_roles()`). Members carry copies of their roles,
+//! so updating or deleting a role also updates the members it's assigned to,
+//! all of which must be passed in.
+//!
+//! See the [company role model.][1]
+//!
+//! [1]: ../../models/company_role/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        company_role::{CompanyRole, CompanyRoleID},
+        member::Member,
+        lib::basis_model::Model,
+        user::User,
+    },
+};
+
+/// Create a new company role
+pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, id: CompanyRoleID, name: T, permissions: Vec<CompanyPermission>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::RoleCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    let model = CompanyRole::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .name(name)
+        .permissions(permissions)
+        .member_ids(vec![])
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Make sure the members a role is being synced to belong to the role's
+/// company, and that they're every member the role is assigned to.
+fn check_role_members(company: &Company, role: &CompanyRole, members: &[Member]) -> Result<()> {
+    for role_member in members {
+        if &role_member.company_id()? != company.id() {
+            Err(Error::InsufficientPrivileges)?;
+        }
+    }
+    role.check_members(members)
+}
+
+/// Sync a role into the members it's assigned to, adding an update for each
+/// (non-deleted) member that holds it.
+fn sync_role_members(mods: &mut Modifications, role: &CompanyRole, members: Vec<Member>, now: &DateTime<Utc>) {
+    for mut role_member in members {
+        if role_member.is_deleted() {
+            continue;
+        }
+        if role_member.sync_role(role) {
+            role_member.set_updated(now.clone());
+            mods.push(Op::Update, role_member);
+        }
+    }
+}
+
+/// Update a company role. `members` must hold every member the role is
+/// assigned to (and only those), which get updated with the new version of the
+/// role.
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: CompanyRole, name: Option<String>, permissions: Option<Vec<CompanyPermission>>, active: Option<bool>, members: Vec<Member>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::RoleUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_role".into()))?;
+    }
+    check_role_members(company, &subject, &members)?;
+    if let Some(name) = name {
+        subject.set_name(name);
+    }
+    if let Some(permissions) = permissions {
+        subject.set_permissions(permissions);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    let mut mods = Modifications::new_single(Op::Update, subject.clone());
+    sync_role_members(&mut mods, &subject, members, now);
+    Ok(mods)
+}
+
+/// Delete a company role, removing it from the `members` it's assigned to
+/// (which must be every member holding the role).
+pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: CompanyRole, members: Vec<Member>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::RoleDelete)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("company_role".into()))?;
+    }
+    check_role_members(company, &subject, &members)?;
+    subject.set_deleted(Some(now.clone()));
+    let mut mods = Modifications::new_single(Op::Delete, subject.clone());
+    sync_role_members(&mut mods, &subject, members, now);
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            member::MemberID,
+            occupation::OccupationID,
+            user::UserID,
+        },
+        util::{self, test::{self, *}},
+    };
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = CompanyRoleID::create();
+        let state = TestState::standard(vec![CompanyPermission::RoleCreate], &now);
+
+        let testfn = |state: &TestState<CompanyRole, CompanyRole>| {
+            create(state.user(), state.member(), state.company(), id.clone(), "bookkeeper", vec![CompanyPermission::Payroll, CompanyPermission::MoveCosts], true, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let role = mods[0].clone().expect_op::<CompanyRole>(Op::Create).unwrap();
+        assert_eq!(role.id(), &id);
+        assert_eq!(role.company_id(), state.company().id());
+        assert_eq!(role.name(), "bookkeeper");
+        assert_eq!(role.permissions(), &vec![CompanyPermission::Payroll, CompanyPermission::MoveCosts]);
+        assert_eq!(role.active(), &true);
+        assert_eq!(role.created(), &now);
+        assert_eq!(role.updated(), &now);
+        assert_eq!(role.deleted(), &None);
+        assert!(role.can(&CompanyPermission::Payroll));
+        assert!(!role.can(&CompanyPermission::CompanyDelete));
+    }
+
+    #[test]
+    fn can_update() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::RoleCreate, CompanyPermission::RoleUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), CompanyRoleID::create(), "bookkeeper", vec![CompanyPermission::Payroll], true, &now).unwrap().into_vec();
+        let mut role = mods[0].clone().expect_op::<CompanyRole>(Op::Create).unwrap();
+        let user_id = UserID::create();
+        let mut holder = make_member_worker(&MemberID::create(), &user_id, state.company().id(), &OccupationID::create(), vec![], &now);
+        role.assign(holder.id());
+        holder.set_roles(vec![role.clone()]);
+        let bystander = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), &OccupationID::create(), vec![], &now);
+        assert!(holder.access_check(&user_id, state.company().id(), CompanyPermission::Payroll).is_ok());
+        state.model = Some(role);
+
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<CompanyRole, CompanyRole>, members: Vec<Member>| {
+            update(state.user(), state.member(), state.company(), state.model().clone(), Some("accountant".into()), Some(vec![CompanyPermission::Payroll, CompanyPermission::WriteOff]), Some(false), members, &now2)
+        };
+        let testfn = |state: &TestState<CompanyRole, CompanyRole>| {
+            testfn_inner(state, vec![holder.clone()])
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let role2 = mods[0].clone().expect_op::<CompanyRole>(Op::Update).unwrap();
+        let holder2 = mods[1].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(role2.id(), state.model().id());
+        assert_eq!(role2.name(), "accountant");
+        assert_eq!(role2.permissions(), &vec![CompanyPermission::Payroll, CompanyPermission::WriteOff]);
+        assert_eq!(role2.active(), &false);
+        assert_eq!(role2.created(), &now);
+        assert_eq!(role2.updated(), &now2);
+        assert!(!role2.can(&CompanyPermission::Payroll));
+        assert_eq!(holder2.id(), holder.id());
+        assert_eq!(holder2.roles(), &vec![role2.clone()]);
+        assert_eq!(holder2.updated(), &now2);
+        assert!(holder2.access_check(&user_id, state.company().id(), CompanyPermission::Payroll).is_err());
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // can't sync into another company's members
+        let outsider = make_member_worker(&MemberID::create(), &UserID::create(), &CompanyID::create(), &OccupationID::create(), vec![], &now);
+        let res = testfn_inner(&state, vec![outsider]);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // every member holding the role (and only those) must be given
+        let res = testfn_inner(&state, vec![]);
+        assert_eq!(res, Err(Error::RoleAssignmentMismatch));
+        let res = testfn_inner(&state, vec![holder.clone(), bystander.clone()]);
+        assert_eq!(res, Err(Error::RoleAssignmentMismatch));
+
+        // deleted holders don't get updated
+        let mut holder_deleted = holder.clone();
+        holder_deleted.set_deleted(Some(now.clone()));
+        let mods = testfn_inner(&state, vec![holder_deleted]).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+    }
+
+    #[test]
+    fn can_delete() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::RoleCreate, CompanyPermission::RoleDelete], &now);
+        let mods = create(state.user(), state.member(), state.company(), CompanyRoleID::create(), "bookkeeper", vec![CompanyPermission::Payroll], true, &now).unwrap().into_vec();
+        let mut role = mods[0].clone().expect_op::<CompanyRole>(Op::Create).unwrap();
+        let user_id = UserID::create();
+        let mut holder = make_member_worker(&MemberID::create(), &user_id, state.company().id(), &OccupationID::create(), vec![], &now);
+        role.assign(holder.id());
+        holder.set_roles(vec![role.clone()]);
+        state.model = Some(role);
+
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<CompanyRole, CompanyRole>, members: Vec<Member>| {
+            delete(state.user(), state.member(), state.company(), state.model().clone(), members, &now2)
+        };
+        let testfn = |state: &TestState<CompanyRole, CompanyRole>| {
+            testfn_inner(state, vec![holder.clone()])
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "company_role", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let role2 = mods[0].clone().expect_op::<CompanyRole>(Op::Delete).unwrap();
+        let holder2 = mods[1].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(holder2.roles().len(), 0);
+        assert!(holder2.access_check(&user_id, state.company().id(), CompanyPermission::Payroll).is_err());
+        assert_eq!(role2.id(), state.model().id());
+        assert_eq!(role2.deleted(), &Some(now2.clone()));
+        assert!(!role2.can(&CompanyPermission::Payroll));
+
+        // can't leave a holder with a copy of the deleted role
+        let res = testfn_inner(&state, vec![]);
+        assert_eq!(res, Err(Error::RoleAssignmentMismatch));
+    }
+}
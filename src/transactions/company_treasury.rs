@@ -0,0 +1,161 @@
+//! The company treasury tracks the credits a company issues and destroys, and
+//! the limits it places on issuing them.
+//!
+//! Treasuries can't be deleted, since they hold the company's running tally.
+//! A company's issuance policy is set by the system (not by the company
+//! itself), and deactivating a treasury stops the company from issuing credits
+//! altogether.
+//!
+//! See the [company treasury model.][1]
+//!
+//! [1]: ../../models/company_treasury/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::Company,
+        company_treasury::{CompanyTreasury, CompanyTreasuryID},
+        lib::basis_model::Model,
+        user::User,
+    },
+};
+use rust_decimal::prelude::*;
+
+/// Make sure an optional limit isn't negative
+fn check_limit(limit: &Option<Decimal>) -> Result<()> {
+    if let Some(val) = limit.as_ref() {
+        if val < &Decimal::zero() {
+            Err(Error::InvalidAmount(*val))?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a company's treasury, starting with nothing issued or destroyed
+pub fn create(caller: &User, company: &Company, id: CompanyTreasuryID, max_outstanding: Option<Decimal>, max_issue_per_run: Option<Decimal>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyTreasuryCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    check_limit(&max_outstanding)?;
+    check_limit(&max_issue_per_run)?;
+    let model = CompanyTreasury::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .issued(Decimal::zero())
+        .destroyed(Decimal::zero())
+        .max_outstanding(max_outstanding)
+        .max_issue_per_run(max_issue_per_run)
+        .paid_through(None)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update a company treasury's issuance policy
+pub fn update(caller: &User, mut subject: CompanyTreasury, max_outstanding: Option<Option<Decimal>>, max_issue_per_run: Option<Option<Decimal>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyTreasuryUpdate)?;
+    if let Some(max_outstanding) = max_outstanding {
+        check_limit(&max_outstanding)?;
+        subject.set_max_outstanding(max_outstanding);
+    }
+    if let Some(max_issue_per_run) = max_issue_per_run {
+        check_limit(&max_issue_per_run)?;
+        subject.set_max_issue_per_run(max_issue_per_run);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = CompanyTreasuryID::create();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::Bank]);
+
+        let testfn = |state: &TestState<CompanyTreasury, CompanyTreasury>| {
+            create(state.user(), state.company(), id.clone(), Some(num!(50000)), Some(num!(10000)), true, &now)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let treasury = mods[0].clone().expect_op::<CompanyTreasury>(Op::Create).unwrap();
+        assert_eq!(treasury.id(), &id);
+        assert_eq!(treasury.company_id(), state.company().id());
+        assert_eq!(treasury.issued(), &num!(0));
+        assert_eq!(treasury.destroyed(), &num!(0));
+        assert_eq!(treasury.max_outstanding(), &Some(num!(50000)));
+        assert_eq!(treasury.max_issue_per_run(), &Some(num!(10000)));
+        assert_eq!(treasury.active(), &true);
+        assert_eq!(treasury.created(), &now);
+        assert_eq!(treasury.updated(), &now);
+        assert_eq!(treasury.deleted(), &None);
+
+        let res = create(state.user(), state.company(), id.clone(), None, Some(num!(-1)), true, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+
+        let mut state2 = state.clone();
+        state2.company_mut().set_active(false);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::ObjectIsInactive("company".into())));
+
+        // companies can't set their own issuance policy
+        let mut state3 = state.clone();
+        state3.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_update() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::Bank]);
+        let mods = create(state.user(), state.company(), CompanyTreasuryID::create(), Some(num!(50000)), None, true, &now).unwrap().into_vec();
+        let treasury = mods[0].clone().expect_op::<CompanyTreasury>(Op::Create).unwrap();
+        state.model = Some(treasury);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<CompanyTreasury, CompanyTreasury>| {
+            update(state.user(), state.model().clone(), Some(None), Some(Some(num!(2000))), Some(false), &now2)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let treasury2 = mods[0].clone().expect_op::<CompanyTreasury>(Op::Update).unwrap();
+        assert_eq!(treasury2.id(), state.model().id());
+        assert_eq!(treasury2.max_outstanding(), &None);
+        assert_eq!(treasury2.max_issue_per_run(), &Some(num!(2000)));
+        assert_eq!(treasury2.active(), &false);
+        assert_eq!(treasury2.created(), &now);
+        assert_eq!(treasury2.updated(), &now2);
+
+        let res = update(state.user(), state.model().clone(), Some(Some(num!(-5))), None, None, &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-5))));
+
+        // a company member can't loosen their own company's limits
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
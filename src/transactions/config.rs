@@ -0,0 +1,203 @@
+//! The network config holds network-wide parameters, and can only be managed
+//! by system admins.
+//!
+//! See the [network config model.][1]
+//!
+//! [1]: ../../models/config/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    costs::{
+        precision::CostPrecision,
+        validate::SanityPolicy,
+    },
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        config::{NetworkConfig, NetworkConfigID, WorkHoursCap},
+        lib::basis_model::Model,
+        resource_spec::ResourceSpecID,
+        user::User,
+    },
+    system::ubi::UBIParameters,
+};
+use rust_decimal::prelude::*;
+
+/// Make sure the UBI amounts aren't negative
+fn check_ubi(ubi_ceiling: &Decimal, ubi_balance_per_day: &Decimal) -> Result<()> {
+    for amount in &[ubi_ceiling, ubi_balance_per_day] {
+        if amount < &&Decimal::zero() {
+            Err(Error::InvalidAmount(**amount))?;
+        }
+    }
+    Ok(())
+}
+
+/// Make sure the demurrage rate is a valid ratio
+fn check_demurrage_rate(demurrage_rate: &Decimal) -> Result<()> {
+    if demurrage_rate < &Decimal::zero() || demurrage_rate > &Decimal::one() {
+        Err(Error::InvalidRatio(*demurrage_rate))?;
+    }
+    Ok(())
+}
+
+/// Create the network config
+pub fn create(caller: &User, id: NetworkConfigID, ubi_ceiling: Decimal, ubi_balance_per_day: Decimal, cost_precision: CostPrecision, sanity_policy: SanityPolicy, demurrage_rate: Decimal, tracked_resource_specs: Vec<ResourceSpecID>, work_hours_cap: Option<WorkHoursCap>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::NetworkConfigCreate)?;
+    check_ubi(&ubi_ceiling, &ubi_balance_per_day)?;
+    check_demurrage_rate(&demurrage_rate)?;
+    let mut ubi = UBIParameters::new();
+    ubi.set_ceiling(ubi_ceiling);
+    ubi.set_balance_per_day(ubi_balance_per_day);
+    let model = NetworkConfig::builder()
+        .id(id)
+        .ubi(ubi)
+        .cost_precision(cost_precision)
+        .sanity_policy(sanity_policy)
+        .demurrage_rate(demurrage_rate)
+        .tracked_resource_specs(tracked_resource_specs)
+        .work_hours_cap(work_hours_cap)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update the network config
+pub fn update(caller: &User, mut subject: NetworkConfig, ubi_ceiling: Option<Decimal>, ubi_balance_per_day: Option<Decimal>, cost_precision: Option<CostPrecision>, sanity_policy: Option<SanityPolicy>, demurrage_rate: Option<Decimal>, tracked_resource_specs: Option<Vec<ResourceSpecID>>, work_hours_cap: Option<Option<WorkHoursCap>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::NetworkConfigUpdate)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("network_config".into()))?;
+    }
+    let mut ubi = subject.ubi().clone();
+    if let Some(ubi_ceiling) = ubi_ceiling {
+        ubi.set_ceiling(ubi_ceiling);
+    }
+    if let Some(ubi_balance_per_day) = ubi_balance_per_day {
+        ubi.set_balance_per_day(ubi_balance_per_day);
+    }
+    check_ubi(ubi.ceiling(), ubi.balance_per_day())?;
+    subject.set_ubi(ubi);
+    if let Some(cost_precision) = cost_precision {
+        subject.set_cost_precision(cost_precision);
+    }
+    if let Some(sanity_policy) = sanity_policy {
+        subject.set_sanity_policy(sanity_policy);
+    }
+    if let Some(demurrage_rate) = demurrage_rate {
+        check_demurrage_rate(&demurrage_rate)?;
+        subject.set_demurrage_rate(demurrage_rate);
+    }
+    if let Some(tracked_resource_specs) = tracked_resource_specs {
+        subject.set_tracked_resource_specs(tracked_resource_specs);
+    }
+    if let Some(work_hours_cap) = work_hours_cap {
+        subject.set_work_hours_cap(work_hours_cap);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        costs::precision::Rounding,
+        util::{self, test::*},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn can_create() {
+        let id = NetworkConfigID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let testfn = |state: &TestState<NetworkConfig, NetworkConfig>| {
+            create(state.user(), id.clone(), num!(1000), num!(25), CostPrecision::new(12, Rounding::HalfUp), SanityPolicy::new(Some(num!(24)), None, None, vec![]), num!(0.001), vec![ResourceSpecID::new("iron")], Some(WorkHoursCap::new(num!(60), Measure::new(num!(1), Unit::Week)).unwrap()), true, &now)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let config = mods[0].clone().expect_op::<NetworkConfig>(Op::Create).unwrap();
+        assert_eq!(config.id(), &id);
+        assert_eq!(config.ubi().ceiling(), &num!(1000));
+        assert_eq!(config.ubi().balance_per_day(), &num!(25));
+        assert_eq!(config.cost_precision(), &CostPrecision::new(12, Rounding::HalfUp));
+        assert_eq!(config.sanity_policy().max_labor_hours(), &Some(num!(24)));
+        assert_eq!(config.demurrage_rate(), &num!(0.001));
+        assert_eq!(config.tracked_resource_specs(), &vec![ResourceSpecID::new("iron")]);
+        assert_eq!(config.work_hours_cap().as_ref().unwrap().max_hours(), &num!(60));
+        assert!(config.is_tracked(&ResourceSpecID::new("iron")));
+        assert!(!config.is_tracked(&ResourceSpecID::new("copper")));
+        assert_eq!(config.active(), &true);
+        assert_eq!(config.created(), &now);
+        assert_eq!(config.updated(), &now);
+
+        let res = create(state.user(), id.clone(), num!(1000), num!(-25), CostPrecision::default(), SanityPolicy::default(), num!(0), vec![], None, true, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-25))));
+        let res = create(state.user(), id.clone(), num!(1000), num!(25), CostPrecision::default(), SanityPolicy::default(), num!(1.5), vec![], None, true, &now);
+        assert_eq!(res, Err(Error::InvalidRatio(num!(1.5))));
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_update() {
+        let id = NetworkConfigID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let mods = create(state.user(), id.clone(), num!(1000), num!(25), CostPrecision::default(), SanityPolicy::default(), num!(0), vec![], None, true, &now).unwrap().into_vec();
+        let config = mods[0].clone().expect_op::<NetworkConfig>(Op::Create).unwrap();
+        state.model = Some(config);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<NetworkConfig, NetworkConfig>| {
+            update(state.user(), state.model().clone(), None, Some(num!(30)), None, Some(SanityPolicy::new(None, Some(num!(5000)), None, vec![])), Some(num!(0.002)), Some(vec![ResourceSpecID::new("lithium")]), Some(Some(WorkHoursCap::new(num!(12), Measure::new(num!(1), Unit::Day)).unwrap())), None, &now2)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let config2 = mods[0].clone().expect_op::<NetworkConfig>(Op::Update).unwrap();
+        assert_eq!(config2.id(), &id);
+        assert_eq!(config2.ubi().ceiling(), &num!(1000));
+        assert_eq!(config2.ubi().balance_per_day(), &num!(30));
+        assert_eq!(config2.cost_precision(), &CostPrecision::default());
+        assert_eq!(config2.sanity_policy().max_labor(), &Some(num!(5000)));
+        assert_eq!(config2.demurrage_rate(), &num!(0.002));
+        assert_eq!(config2.tracked_resource_specs(), &vec![ResourceSpecID::new("lithium")]);
+        assert_eq!(config2.work_hours_cap().as_ref().unwrap().max_hours(), &num!(12));
+        assert_eq!(config2.active(), &true);
+        assert_eq!(config2.created(), &now);
+        assert_eq!(config2.updated(), &now2);
+
+        let res = update(state.user(), state.model().clone(), Some(num!(-1)), None, None, None, None, None, None, None, &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+        let res = update(state.user(), state.model().clone(), None, None, None, None, Some(num!(-0.1)), None, None, None, &now2);
+        assert_eq!(res, Err(Error::InvalidRatio(num!(-0.1))));
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("network_config".into())));
+    }
+}
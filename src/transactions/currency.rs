@@ -1,7 +1,7 @@
 //! Currencies track real-world market currencies in the cost tracking system.
 //!
 //! This set of transactions deals with creating currencies tracked by Basis,
-//! such as USD, EUR, etc.
+//! such as USD, EUR, etc, as well as recording their exchange rates over time.
 //!
 //! See the [currency model.][1]
 //!
@@ -14,11 +14,12 @@ use crate::{
     models::{
         Op,
         Modifications,
-        currency::{Currency, CurrencyID},
+        currency::{Currency, CurrencyID, CurrencyRate},
         lib::basis_model::Model,
         user::User,
     },
 };
+use rust_decimal::prelude::*;
 
 /// Create a new `Currency`.
 pub fn create<T: Into<String>>(caller: &User, id: CurrencyID, name: T, decimal_places: u8, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
@@ -51,6 +52,22 @@ pub fn update(caller: &User, mut subject: Currency, name: Option<String>, decima
     Ok(Modifications::new_single(Op::Update, subject))
 }
 
+/// Record an exchange rate (credits per unit of currency) for a `Currency`,
+/// taking effect at the given time. Rates are kept as a history so costs can
+/// be converted using the rate that was in effect when they were incurred.
+pub fn set_rate(caller: &User, mut subject: Currency, rate: Decimal, effective: DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CurrencyUpdate)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("currency".into()))?;
+    }
+    if rate <= Decimal::zero() {
+        Err(Error::InvalidAmount(rate))?;
+    }
+    subject.push_rate(CurrencyRate::new(rate, effective));
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
 /// Delete a `Currency`
 pub fn delete(caller: &User, mut subject: Currency, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CurrencyDelete)?;
@@ -136,6 +153,52 @@ mod tests {
         assert_eq!(res, Err(Error::InsufficientPrivileges));
     }
 
+    #[test]
+    fn can_set_rate() {
+        let id = CurrencyID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::Bank]);
+        let mods = create(state.user(), id.clone(), "usd", 2, true, &now).unwrap().into_vec();
+        let currency = mods[0].clone().expect_op::<Currency>(Op::Create).unwrap();
+        state.model = Some(currency);
+
+        let now2 = util::time::now();
+        let effective: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let testfn_inner = |state: &TestState<Currency, Currency>, rate: Decimal| {
+            set_rate(state.user(), state.model().clone(), rate, effective.clone(), &now2)
+        };
+        let testfn = |state: &TestState<Currency, Currency>| {
+            testfn_inner(state, num!(0.9973))
+        };
+        test::double_deleted_tester(&state, "currency", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let currency2 = mods[0].clone().expect_op::<Currency>(Op::Update).unwrap();
+        assert_eq!(currency2.id(), state.model().id());
+        assert_eq!(currency2.rates(), &vec![CurrencyRate::new(num!(0.9973), effective.clone())]);
+        assert_eq!(currency2.rate_at(&now2), Ok(num!(0.9973)));
+        assert_eq!(currency2.created(), state.model().created());
+        assert_eq!(currency2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model = Some(currency2);
+        let mods = set_rate(state2.user(), state2.model().clone(), num!(1.02), "2020-02-01T00:00:00Z".parse().unwrap(), &now2).unwrap().into_vec();
+        let currency3 = mods[0].clone().expect_op::<Currency>(Op::Update).unwrap();
+        assert_eq!(currency3.rates().len(), 2);
+        assert_eq!(currency3.rate_at(&"2020-01-31T00:00:00Z".parse().unwrap()), Ok(num!(0.9973)));
+        assert_eq!(currency3.rate_at(&now2), Ok(num!(1.02)));
+
+        let res = testfn_inner(&state, num!(0));
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        let mut state3 = state.clone();
+        state3.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
     #[test]
     fn can_delete() {
         let id = CurrencyID::create();
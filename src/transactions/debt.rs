@@ -0,0 +1,299 @@
+//! Debts track external currency companies owe each other. A debt is recorded
+//! by the company that owes it, repaid by that same company, and can be
+//! forgiven (in whole or in part) by the company it's owed to.
+//!
+//! Debts never accrue interest: the only ways the outstanding balance changes
+//! are repayment and forgiveness.
+//!
+//! See the [debt model.][1]
+//!
+//! [1]: ../../models/debt/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        currency::CurrencyID,
+        debt::{Debt, DebtID},
+        member::Member,
+        lib::basis_model::Model,
+        user::User,
+    },
+};
+use rust_decimal::prelude::*;
+
+/// Record a debt owed by `debtor` to `creditor`. This is done by a member of
+/// the debtor company.
+pub fn create<T: Into<String>>(caller: &User, member: &Member, debtor: &Company, creditor: &Company, id: DebtID, currency_id: CurrencyID, principal: Decimal, due: Option<DateTime<Utc>>, note: Option<T>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), debtor.id(), CompanyPermission::DebtCreate)?;
+    if !debtor.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !creditor.is_active() {
+        Err(Error::ObjectIsInactive("creditor".into()))?;
+    }
+    if debtor.id() == creditor.id() {
+        Err(Error::DebtInvalid)?;
+    }
+    if principal <= Decimal::zero() {
+        Err(Error::InvalidAmount(principal))?;
+    }
+    let model = Debt::builder()
+        .id(id)
+        .creditor_id(creditor.id().clone())
+        .debtor_id(debtor.id().clone())
+        .currency_id(currency_id)
+        .principal(principal)
+        .repaid(Decimal::zero())
+        .forgiven(Decimal::zero())
+        .due(due)
+        .note(note.map(|x| x.into()))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Repay some or all of a debt. Only the debtor company can do this, and it
+/// can't repay more than is outstanding.
+pub fn repay(caller: &User, member: &Member, debtor: &Company, mut subject: Debt, amount: Decimal, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), debtor.id(), CompanyPermission::DebtRepay)?;
+    if debtor.id() != subject.debtor_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !debtor.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("debt".into()))?;
+    }
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    if amount > subject.outstanding() {
+        Err(Error::DebtOverpayment(subject.outstanding()))?;
+    }
+    subject.set_repaid(subject.repaid().clone() + amount);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Forgive some of a debt (or, if `amount` is `None`, all that remains of it).
+/// Only the creditor company can do this.
+pub fn forgive(caller: &User, member: &Member, creditor: &Company, mut subject: Debt, amount: Option<Decimal>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateAgreements)?;
+    member.access_check(caller.id(), creditor.id(), CompanyPermission::DebtForgive)?;
+    if creditor.id() != subject.creditor_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !creditor.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("debt".into()))?;
+    }
+    let amount = amount.unwrap_or(subject.outstanding());
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    if amount > subject.outstanding() {
+        Err(Error::DebtOverpayment(subject.outstanding()))?;
+    }
+    subject.set_forgiven(subject.forgiven().clone() + amount);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            company_ledger::{CompanyLedger, CompanyLedgerID},
+        },
+        transactions::company_ledger,
+        util::{self, test::{self, *}},
+    };
+
+    fn create_debt(state: &TestState<Debt, Debt>, creditor: &Company, principal: Decimal, now: &DateTime<Utc>) -> Debt {
+        let mods = create(state.user(), state.member(), state.company(), creditor, DebtID::create(), CurrencyID::new("usd"), principal, None, None::<String>, now).unwrap().into_vec();
+        mods[0].clone().expect_op::<Debt>(Op::Create).unwrap()
+    }
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = DebtID::create();
+        let state = TestState::standard(vec![CompanyPermission::DebtCreate], &now);
+        let creditor = make_company(&CompanyID::new("larry's chairs"), "larry's chairs", &now);
+        let due = now.clone() + chrono::Duration::days(90);
+
+        let testfn = |state: &TestState<Debt, Debt>| {
+            create(state.user(), state.member(), state.company(), &creditor, id.clone(), CurrencyID::new("usd"), num!(1200), Some(due.clone()), Some("bought a lathe"), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let debt = mods[0].clone().expect_op::<Debt>(Op::Create).unwrap();
+        assert_eq!(debt.id(), &id);
+        assert_eq!(debt.creditor_id(), creditor.id());
+        assert_eq!(debt.debtor_id(), state.company().id());
+        assert_eq!(debt.currency_id(), &CurrencyID::new("usd"));
+        assert_eq!(debt.principal(), &num!(1200));
+        assert_eq!(debt.repaid(), &num!(0));
+        assert_eq!(debt.forgiven(), &num!(0));
+        assert_eq!(debt.outstanding(), num!(1200));
+        assert_eq!(debt.due(), &Some(due.clone()));
+        assert_eq!(debt.note(), &Some("bought a lathe".into()));
+        assert_eq!(debt.active(), &true);
+        assert_eq!(debt.created(), &now);
+        assert_eq!(debt.updated(), &now);
+        assert_eq!(debt.deleted(), &None);
+
+        let res = create(state.user(), state.member(), state.company(), state.company(), id.clone(), CurrencyID::new("usd"), num!(1200), None, None::<String>, &now);
+        assert_eq!(res, Err(Error::DebtInvalid));
+
+        let res = create(state.user(), state.member(), state.company(), &creditor, id.clone(), CurrencyID::new("usd"), num!(0), None, None::<String>, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        let mut creditor2 = creditor.clone();
+        creditor2.set_deleted(Some(now.clone()));
+        let res = create(state.user(), state.member(), state.company(), &creditor2, id.clone(), CurrencyID::new("usd"), num!(1200), None, None::<String>, &now);
+        assert_eq!(res, Err(Error::ObjectIsInactive("creditor".into())));
+    }
+
+    #[test]
+    fn can_repay() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::DebtCreate, CompanyPermission::DebtRepay], &now);
+        let creditor = make_company(&CompanyID::new("larry's chairs"), "larry's chairs", &now);
+        state.model = Some(create_debt(&state, &creditor, num!(500), &now));
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Debt, Debt>| {
+            repay(state.user(), state.member(), state.company(), state.model().clone(), num!(200), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "debt", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let debt = mods[0].clone().expect_op::<Debt>(Op::Update).unwrap();
+        assert_eq!(debt.repaid(), &num!(200));
+        assert_eq!(debt.outstanding(), num!(300));
+        assert_eq!(debt.updated(), &now2);
+
+        let res = repay(state.user(), state.member(), state.company(), debt.clone(), num!(300.01), &now2);
+        assert_eq!(res, Err(Error::DebtOverpayment(num!(300))));
+        let res = repay(state.user(), state.member(), state.company(), debt.clone(), num!(-5), &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-5))));
+
+        let mods = repay(state.user(), state.member(), state.company(), debt.clone(), num!(300), &now2).unwrap().into_vec();
+        let debt2 = mods[0].clone().expect_op::<Debt>(Op::Update).unwrap();
+        assert!(debt2.is_settled());
+
+        // the creditor can't "repay" a debt on the debtor's behalf
+        let mut state2 = state.clone();
+        state2.model_mut().set_debtor_id(creditor.id().clone());
+        state2.model_mut().set_creditor_id(state.company().id().clone());
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_forgive() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::DebtForgive], &now);
+        let debtor = make_company(&CompanyID::new("larry's chairs"), "larry's chairs", &now);
+        let mut debt = Debt::builder()
+            .id(DebtID::create())
+            .creditor_id(state.company().id().clone())
+            .debtor_id(debtor.id().clone())
+            .currency_id(CurrencyID::new("usd"))
+            .principal(num!(500))
+            .repaid(num!(100))
+            .forgiven(Decimal::zero())
+            .due(None)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        state.model = Some(debt.clone());
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Debt, Debt>| {
+            forgive(state.user(), state.member(), state.company(), state.model().clone(), Some(num!(150)), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "debt", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let debt2 = mods[0].clone().expect_op::<Debt>(Op::Update).unwrap();
+        assert_eq!(debt2.forgiven(), &num!(150));
+        assert_eq!(debt2.outstanding(), num!(250));
+        assert_eq!(debt2.updated(), &now2);
+
+        let mods = forgive(state.user(), state.member(), state.company(), debt2.clone(), None, &now2).unwrap().into_vec();
+        let debt3 = mods[0].clone().expect_op::<Debt>(Op::Update).unwrap();
+        assert_eq!(debt3.forgiven(), &num!(400));
+        assert!(debt3.is_settled());
+
+        let res = forgive(state.user(), state.member(), state.company(), debt3.clone(), None, &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = forgive(state.user(), state.member(), state.company(), debt2.clone(), Some(num!(251)), &now2);
+        assert_eq!(res, Err(Error::DebtOverpayment(num!(250))));
+
+        // the debtor can't forgive its own debt
+        debt.set_creditor_id(debtor.id().clone());
+        debt.set_debtor_id(state.company().id().clone());
+        let res = forgive(state.user(), state.member(), state.company(), debt, None, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn tracks_in_ledger() {
+        let now = util::time::now();
+        let state: TestState<Debt, Debt> = TestState::standard(vec![CompanyPermission::DebtCreate, CompanyPermission::DebtRepay, CompanyPermission::CompanyUpdate], &now);
+        let creditor = make_company(&CompanyID::new("larry's chairs"), "larry's chairs", &now);
+        let mods = company_ledger::create(state.user(), state.member(), state.company(), CompanyLedgerID::create(), &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Create).unwrap();
+
+        let create_mods = create(state.user(), state.member(), state.company(), &creditor, DebtID::create(), CurrencyID::new("usd"), num!(500), None, None::<String>, &now).unwrap();
+        let debt = create_mods.clone().into_vec()[0].clone().expect_op::<Debt>(Op::Create).unwrap();
+        let mods = company_ledger::apply(ledger, &create_mods, &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger.debt_balance().get(&CurrencyID::new("usd")), Some(&num!(-500)));
+
+        let repay_mods = repay(state.user(), state.member(), state.company(), debt.clone(), num!(125), &now).unwrap();
+        let debt = repay_mods.clone().into_vec()[0].clone().expect_op::<Debt>(Op::Update).unwrap();
+        let mods = company_ledger::apply(ledger, &repay_mods, &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger.debt_balance().get(&CurrencyID::new("usd")), Some(&num!(-375)));
+
+        // applying the same modifications twice doesn't double-count
+        let mods = company_ledger::apply(ledger.clone(), &repay_mods, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+
+        let repay_mods = repay(state.user(), state.member(), state.company(), debt.clone(), num!(375), &now).unwrap();
+        let mods = company_ledger::apply(ledger, &repay_mods, &now).unwrap().into_vec();
+        let ledger = mods[0].clone().expect_op::<CompanyLedger>(Op::Update).unwrap();
+        assert_eq!(ledger.debts().len(), 0);
+        assert_eq!(ledger.debt_balance().get(&CurrencyID::new("usd")), None);
+
+        // the creditor's ledger sees the other side
+        let mut ledger2 = ledger.clone();
+        ledger2.set_company_id(creditor.id().clone());
+        assert!(ledger2.apply(&create_mods));
+        assert_eq!(ledger2.debt_balance().get(&CurrencyID::new("usd")), Some(&num!(500)));
+    }
+}
@@ -3,32 +3,93 @@
 //! For instance, if a company wanted to raise or lower some quantity of a
 //! resource or move costs between processes or resources, this is where they
 //! could do it.
+//!
+//! Resources tagged with a region can also be renewed (regrowth, recycling,
+//! etc), which raises their quantity while marking the event as a renewal for
+//! the purposes of [depletion tracking][1].
+//!
+//! Resources can also be [split][2] into several new resources or several
+//! resources [merged][3] into one, which is a series of resource moves under
+//! the hood with the costs apportioned exactly.
+//!
+//! Costs that need to be disposed of entirely (spoilage, scrap, a failed batch)
+//! can be written off, which removes them from the resource/process and records
+//! them in the company's waste tally.
+//!
+//! [1]: ../../../costs/depletion/index.html
+//! [2]: fn.split_resource.html
+//! [3]: fn.merge_resources.html
 
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
+    costs::{Costs, validate::SanityPolicy},
     error::{Error, Result},
     models::{
         Op,
         Modifications,
-        event::{Event, EventID, EventProcessState, MoveType},
+        event::{Event, EventID, EventProcessState, MoveType, Renewal},
         company::{Company, Permission as CompanyPermission},
+        Model as AnyModel,
         member::Member,
         lib::basis_model::Model,
         process::Process,
-        resource::Resource,
+        resource::{Resource, ResourceID},
+        resource_spec::ResourceSpec,
         user::User,
     },
     transactions::event::ResourceMover,
-    util::number::Ratio,
+    util::{measure, number::Ratio},
 };
+use getset::Getters;
 use om2::{Measure, NumericUnion};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
 use vf_rs::{vf, geo::SpatialThing};
 
+/// Describes what object we're writing costs off of.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum WriteOff {
+    /// Write costs off of a process (scrap, a failed batch, etc)
+    Process(Process),
+    /// Write costs off of a resource, lowering its quantity by the given
+    /// amount (spoilage, breakage, etc)
+    Resource(Resource, NumericUnion),
+}
+
+/// One of the new resources created when [splitting a resource][1].
+///
+/// [1]: fn.split_resource.html
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct SplitPart {
+    /// The ID of the move event creating this part
+    id: EventID,
+    /// The ID of the resource this part creates
+    resource_id: ResourceID,
+    /// This part's share of the original resource's quantity (and costs)
+    ratio: Ratio,
+}
+
+impl SplitPart {
+    /// Create a new split part
+    pub fn new(id: EventID, resource_id: ResourceID, ratio: Ratio) -> Self {
+        Self {
+            id,
+            resource_id,
+            ratio,
+        }
+    }
+}
+
 /// Lower the quantity (both accounting and obhand) or a resource by a fixed
 /// amount.
 pub fn lower<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, resource_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateLower)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Lower)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -50,7 +111,7 @@ pub fn lower<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Co
             vf::EconomicEvent::builder()
                 .action(vf::Action::Lower)
                 .has_point_in_time(now.clone())
-                .note(note.map(|x| x.into()))
+                .note(note)
                 .provider(company.id().clone())
                 .receiver(company.id().clone())
                 .resource_inventoried_as(Some(resource_id))
@@ -78,7 +139,7 @@ pub fn lower<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Co
 /// This can be useful to send costs from one process to another, for instance
 /// if a process has an excess of costs that should be moved somewhere else.
 pub fn move_costs(caller: &User, member: &Member, company: &Company, id: EventID, process_from: Process, process_to: Process, move_costs_ratio: Ratio, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateMoveCosts)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::MoveCosts)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -127,7 +188,13 @@ pub fn move_costs(caller: &User, member: &Member, company: &Company, id: EventID
 /// Move a resource internally. This can split a resource into two, or move one
 /// resource entirely into another one.
 pub fn move_resource<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, resource_measure: T, new_location: Option<SpatialThing>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    move_resource_inner(caller, member, company, id, resource_from, resource_to, move_costs, resource_measure, new_location, note, now)
+}
+
+/// Move a resource internally, moving a fixed set of costs with it.
+fn move_resource_inner<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs: Costs, resource_measure: T, new_location: Option<SpatialThing>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateMoveResource)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::MoveResource)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -138,7 +205,6 @@ pub fn move_resource<T: Into<NumericUnion>>(caller: &User, member: &Member, comp
         Measure::new(resource_measure, unit)
     };
     let resource_from_id = resource_from.id().clone();
-    let move_costs = resource_from.costs().clone() * move_costs_ratio;
 
     let mut statebuilder = EventProcessState::builder()
         .resource(resource_from);
@@ -187,14 +253,151 @@ pub fn move_resource<T: Into<NumericUnion>>(caller: &User, member: &Member, comp
     Ok(mods)
 }
 
+/// Split a resource into several new resources.
+///
+/// The entirety of the resource's accounting quantity and costs are divided
+/// between the parts according to their ratios, which are weighed against
+/// their sum (see `Costs::split()`). Any rounding remainder goes to the part
+/// with the largest ratio, so the parts always add back up to the original and
+/// the original resource is left empty.
+///
+/// Returns the move event and created resource for each part, followed by the
+/// final update of the original resource.
+pub fn split_resource(caller: &User, member: &Member, company: &Company, resource: Resource, parts: Vec<SplitPart>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    if parts.is_empty() {
+        Err(Error::MissingFields(vec!["parts".into()]))?;
+    }
+    let quantity = resource.inner().accounting_quantity().as_ref()
+        .ok_or(Error::ResourceMeasureMissing)
+        .and_then(measure::to_decimal)?;
+    let ratios = parts.iter().map(|part| part.ratio().clone()).collect::<Vec<_>>();
+    let cost_parts = resource.costs().split(&ratios);
+    // split the quantity the same way Costs::split() splits costs
+    let quantity_parts = {
+        let total = ratios.iter().fold(Decimal::zero(), |acc, ratio| acc + ratio.inner());
+        let weights = if total.is_zero() {
+            vec![Decimal::one() / Decimal::from(ratios.len()); ratios.len()]
+        } else {
+            ratios.iter().map(|ratio| ratio.inner() / total).collect::<Vec<_>>()
+        };
+        let largest = weights.iter().enumerate()
+            .fold(0, |largest, (idx, weight)| if weight > &weights[largest] { idx } else { largest });
+        let mut quantity_parts = weights.iter().map(|weight| quantity * weight).collect::<Vec<_>>();
+        let allocated = quantity_parts.iter().enumerate()
+            .filter(|(idx, _)| idx != &largest)
+            .fold(Decimal::zero(), |acc, (_, part)| acc + part);
+        quantity_parts[largest] = quantity - allocated;
+        quantity_parts
+    };
+
+    let mut resource = resource;
+    let mut mods = Modifications::new();
+    for ((part, move_costs), move_quantity) in parts.into_iter().zip(cost_parts).zip(quantity_parts) {
+        let SplitPart { id, resource_id, .. } = part;
+        let move_mods = move_resource_inner(caller, member, company, id, resource.clone(), ResourceMover::Create(resource_id), move_costs, move_quantity, None, note.clone(), now)?;
+        for move_mod in move_mods {
+            match move_mod.into_pair() {
+                (Op::Update, AnyModel::Resource(updated)) if updated.id() == resource.id() => {
+                    resource = updated;
+                }
+                (op, model) => mods.push(op, model),
+            }
+        }
+    }
+    mods.push(Op::Update, resource);
+    Ok(mods)
+}
+
+/// Merge several resources into one.
+///
+/// The entirety of each source resource's accounting quantity and costs are
+/// moved into `resource_to`, which is either created (with the first move) or
+/// updated. All the resources must conform to the same spec and share the
+/// same unit, and each resource can only be given once (the target can't also
+/// be a source).
+///
+/// Returns the move event and emptied source resource for each source,
+/// followed by the final version of the merged resource.
+pub fn merge_resources(caller: &User, member: &Member, company: &Company, sources: Vec<(EventID, Resource)>, resource_to: ResourceMover, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let first = sources.first().map(|(_, resource)| resource.clone()).ok_or(Error::MissingFields(vec!["sources".into()]))?;
+    let check_resource = |resource: &Resource| -> Result<()> {
+        if resource.inner().conforms_to() != first.inner().conforms_to() {
+            Err(Error::ResourceSpecMismatch)?;
+        }
+        if resource.get_unit() != first.get_unit() {
+            Err(Error::MeasureUnitsMismatched)?;
+        }
+        Ok(())
+    };
+    for (_, resource) in &sources {
+        check_resource(resource)?;
+    }
+    let (op_to, resource_to_id) = match &resource_to {
+        ResourceMover::Create(resource_id) => (Op::Create, resource_id.clone()),
+        ResourceMover::Update(resource) => {
+            check_resource(resource)?;
+            (Op::Update, resource.id().clone())
+        }
+    };
+    let mut seen = HashSet::new();
+    for (_, resource) in &sources {
+        if resource.id() == &resource_to_id || !seen.insert(resource.id()) {
+            Err(Error::ResourceMergeOverlap)?;
+        }
+    }
+    let mut resource_to = resource_to;
+
+    let mut mods = Modifications::new();
+    for (id, resource) in sources {
+        let move_quantity = resource.inner().accounting_quantity().as_ref()
+            .ok_or(Error::ResourceMeasureMissing)?
+            .has_numerical_value()
+            .clone();
+        let move_costs = resource.costs().clone();
+        let move_mods = move_resource_inner(caller, member, company, id, resource, resource_to.clone(), move_costs, move_quantity, None, note.clone(), now)?;
+        for move_mod in move_mods {
+            match move_mod.into_pair() {
+                // hold onto the merged resource so the next move builds on it
+                (_, AnyModel::Resource(merged)) if merged.id() == &resource_to_id => {
+                    resource_to = ResourceMover::Update(merged);
+                }
+                (op, model) => mods.push(op, model),
+            }
+        }
+    }
+    if let ResourceMover::Update(merged) = resource_to {
+        mods.push(op_to, merged);
+    }
+    Ok(mods)
+}
+
 /// Raise the quantity (both accounting and onhand) or a resource by a fixed
 /// amount.
-pub fn raise<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, resource_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+///
+/// The resource must conform to the given `spec`, and its unit must be
+/// compatible with the spec's default unit or dimensions.
+pub fn raise<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, spec: &ResourceSpec, resource_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    raise_inner(caller, member, company, id, resource, spec, resource_measure, None, note, now)
+}
+
+/// Record the renewal of a region-tagged resource, raising its quantity by a
+/// fixed amount. This is how regrowth, replanting, recycling, etc are recorded
+/// so they can be weighed against extraction of the resource.
+pub fn renew<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, spec: &ResourceSpec, resource_measure: T, renewal: Renewal, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    raise_inner(caller, member, company, id, resource, spec, resource_measure, Some(renewal), note, now)
+}
+
+/// Raises a resource's quantity, optionally marking the event as a renewal.
+fn raise_inner<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, spec: &ResourceSpec, resource_measure: T, renewal: Option<Renewal>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateRaise)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Raise)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
+    if renewal.is_some() && resource.region_id().is_none() {
+        Err(Error::ResourceRegionMissing)?;
+    }
+    spec.check_resource(&resource)?;
 
     let measure = {
         let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
@@ -220,6 +423,141 @@ pub fn raise<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Co
                 .build()
                 .map_err(|e| Error::BuilderFailed(e))?
         )
+        .renewal(renewal)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+
+    let evmods = event.process(state, now)?.into_vec();
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, event);
+    for evmod in evmods {
+        mods.push_raw(evmod);
+    }
+    Ok(mods)
+}
+
+/// Bring a brand new resource into the network from outside of it, recording
+/// a `raise` event that carries its quantity and `costs` in. The resource is
+/// created in its raised state (its given quantities and costs are replaced).
+///
+/// This is a building block for other transactions (bootstrapping, buying from
+/// external markets) and does no permission checks of its own. The costs are
+/// checked against `sanity_policy`, if given.
+pub(crate) fn raise_new(company: &Company, id: EventID, mut resource: Resource, quantity: Measure, costs: Costs, sanity_policy: Option<&SanityPolicy>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let zero = measure::unwrap_or_zero(&None, &quantity);
+    resource.inner_mut().set_accounting_quantity(Some(zero.clone()));
+    resource.inner_mut().set_onhand_quantity(Some(zero));
+    resource.set_costs(Costs::new());
+    let resource_id = resource.id().clone();
+
+    let event = Event::builder()
+        .id(id)
+        .inner(
+            vf::EconomicEvent::builder()
+                .action(vf::Action::Raise)
+                .has_point_in_time(now.clone())
+                .note(note)
+                .provider(company.id().clone())
+                .receiver(company.id().clone())
+                .resource_inventoried_as(Some(resource_id.clone()))
+                .resource_quantity(Some(quantity))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(Some(costs.clone()))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let state = EventProcessState::builder()
+        .resource(resource.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?
+        .with_sanity_policy(sanity_policy);
+
+    let evmods = event.process(state, now)?.into_vec();
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, event);
+    let mut raised = None;
+    for evmod in evmods {
+        match evmod.into_pair() {
+            (Op::Update, AnyModel::Resource(res)) if res.id() == &resource_id => { raised = Some(res); }
+            (op, model) => { mods.push(op, model); }
+        }
+    }
+    // the resource doesn't exist yet, so we create it as raised, carrying the
+    // costs brought in with it
+    let mut raised = raised.unwrap_or(resource);
+    raised.set_costs(costs);
+    mods.push(Op::Create, raised);
+    Ok(mods)
+}
+
+/// Write off a set of costs from a resource or process.
+///
+/// The costs are removed from the given object (and from the company's
+/// `total_costs`) and added to the company's `waste_costs` tally. If writing
+/// off a resource, its quantity is lowered as well. Deleted resources and
+/// processes can't be written off.
+pub fn write_off(caller: &User, member: &Member, company: &Company, id: EventID, source: WriteOff, costs: Costs, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateWriteOff)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::WriteOff)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if costs.is_lt_0() {
+        Err(Error::NegativeCosts)?;
+    }
+    if costs.is_zero() {
+        Err(Error::InvalidAmount(costs.credits().clone()))?;
+    }
+
+    let mut statebuilder = EventProcessState::builder();
+    let mut eventbuilder = vf::EconomicEvent::builder()
+        .action(vf::Action::Move)
+        .has_point_in_time(now.clone())
+        .note(note)
+        .provider(company.id().clone())
+        .receiver(company.id().clone());
+    match source {
+        WriteOff::Process(process) => {
+            if process.is_deleted() {
+                Err(Error::ObjectIsDeleted("process".into()))?;
+            }
+            eventbuilder = eventbuilder.output_of(Some(process.id().clone()));
+            statebuilder = statebuilder.output_of(process);
+        }
+        WriteOff::Resource(resource, resource_measure) => {
+            if resource.is_deleted() {
+                Err(Error::ObjectIsDeleted("resource".into()))?;
+            }
+            let measure = {
+                let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+                Measure::new(resource_measure, unit)
+            };
+            eventbuilder = eventbuilder
+                .resource_inventoried_as(Some(resource.id().clone()))
+                .resource_quantity(Some(measure));
+            statebuilder = statebuilder.resource(resource);
+        }
+    }
+
+    let state = statebuilder
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let event = Event::builder()
+        .id(id)
+        .inner(
+            eventbuilder
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(Some(costs.clone()))
+        .move_type(Some(MoveType::WriteOff))
         .active(true)
         .created(now.clone())
         .updated(now.clone())
@@ -227,11 +565,16 @@ pub fn raise<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Co
         .map_err(|e| Error::BuilderFailed(e))?;
 
     let evmods = event.process(state, now)?.into_vec();
+    let mut company = company.clone();
+    company.write_off_costs(costs)?;
+    company.set_updated(now.clone());
+
     let mut mods = Modifications::new();
     mods.push(Op::Create, event);
     for evmod in evmods {
         mods.push_raw(evmod);
     }
+    mods.push(Op::Update, company);
     Ok(mods)
 }
 
@@ -246,7 +589,9 @@ mod tests {
             event::{EventID, EventError},
             occupation::OccupationID,
             process::{Process, ProcessID},
+            region::RegionID,
             resource::ResourceID,
+            resource_spec::ResourceSpecID,
         },
         util::{self, test::{self, *}},
     };
@@ -487,10 +832,11 @@ mod tests {
         let id = EventID::create();
         let mut state = TestState::standard(vec![CompanyPermission::Raise], &now);
         let resource = make_resource(&ResourceID::new("widget"), state.company().id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("homemaker", 157), &now);
+        let spec = make_resource_spec(&ResourceSpecID::new("6969"), state.company().id(), "widget", &now);
         state.model = Some(resource);
 
         let testfn = |state: &TestState<Resource, Resource>| {
-            raise(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), 8, Some("toot".into()), &now)
+            raise(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &spec, 8, Some("toot".into()), &now)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -529,6 +875,276 @@ mod tests {
         state3.model_mut().set_in_custody_of(CompanyID::new("ziggy").into());
         let res = testfn(&state3);
         assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
+
+        // the resource must conform to the spec
+        let other_spec = make_resource_spec(&ResourceSpecID::new("gadget"), state.company().id(), "gadget", &now);
+        let res = raise(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &other_spec, 8, None, &now);
+        assert_eq!(res, Err(Error::ResourceSpecMismatch));
+
+        // and its unit must be compatible with the spec
+        let mut spec2 = spec.clone();
+        spec2.inner_mut().set_default_unit_of_resource(Some(Unit::Kilogram));
+        let res = raise(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &spec2, 8, None, &now);
+        assert_eq!(res, Err(Error::ResourceSpecUnitMismatch("One".into())));
     }
-}
 
+    #[test]
+    fn can_renew() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Raise], &now);
+        let mut resource = make_resource(&ResourceID::new("trees"), state.company().id(), &Measure::new(num!(100), Unit::One), &Costs::new_with_labor("forester", 20), &now);
+        resource.set_region_id(Some(RegionID::new("cascadia")));
+        let spec = make_resource_spec(&ResourceSpecID::new("6969"), state.company().id(), "trees", &now);
+        state.model = Some(resource);
+
+        let testfn = |state: &TestState<Resource, Resource>| {
+            renew(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &spec, 12, Renewal::Regeneration, Some("replanted".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Raise);
+        assert_eq!(event.inner().note(), &Some("replanted".into()));
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(12, Unit::One)));
+        assert_eq!(event.renewal(), &Some(Renewal::Regeneration));
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(112), Unit::One)));
+        assert_eq!(resource2.inner().onhand_quantity(), &Some(Measure::new(num!(112), Unit::One)));
+        assert_eq!(resource2.costs(), state.model().costs());
+
+        // only resources tagged with a region can be renewed
+        let mut state2 = state.clone();
+        state2.model_mut().set_region_id(None);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::ResourceRegionMissing));
+    }
+
+    #[test]
+    fn can_write_off() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::WriteOff], &now);
+        let costs = Costs::new_with_labor("homemaker", 150);
+        state.company_mut().set_total_costs(costs.clone() * num!(2));
+        let resource = make_resource(&ResourceID::new("milk"), state.company().id(), &Measure::new(num!(15), Unit::One), &costs, &now);
+        let costs_to_waste = costs.clone() * Ratio::new(num!(0.2)).unwrap();
+        state.model = Some(resource);
+
+        let testfn = |state: &TestState<Resource, Resource>| {
+            write_off(state.user(), state.member(), state.company(), id.clone(), WriteOff::Resource(state.model().clone(), NumericUnion::Integer(3)), costs_to_waste.clone(), Some("spoiled".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let company2 = mods[2].clone().expect_op::<Company>(Op::Update).unwrap();
+
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Move);
+        assert_eq!(event.move_type(), &Some(MoveType::WriteOff));
+        assert_eq!(event.inner().has_point_in_time(), &Some(now.clone()));
+        assert_eq!(event.inner().note(), &Some("spoiled".into()));
+        assert_eq!(event.inner().output_of(), &None);
+        assert_eq!(event.inner().provider().clone(), state.company().agent_id());
+        assert_eq!(event.inner().receiver().clone(), state.company().agent_id());
+        assert_eq!(event.inner().resource_inventoried_as(), &Some(state.model().id().clone()));
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(3, Unit::One)));
+        assert_eq!(event.move_costs(), &Some(costs_to_waste.clone()));
+        assert_eq!(event.active(), &true);
+        assert_eq!(event.created(), &now);
+        assert_eq!(event.updated(), &now);
+
+        assert_eq!(resource2.id(), state.model().id());
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(12), Unit::One)));
+        assert_eq!(resource2.inner().onhand_quantity(), &Some(Measure::new(num!(12), Unit::One)));
+        assert_eq!(resource2.costs(), &(costs.clone() - costs_to_waste.clone()));
+
+        assert_eq!(company2.id(), state.company().id());
+        assert_eq!(company2.waste_costs(), &costs_to_waste);
+        assert_eq!(company2.total_costs(), &(state.company().total_costs().clone() - costs_to_waste.clone()));
+        assert_eq!(company2.updated(), &now);
+
+        // can't write off more costs than the resource has
+        let res = write_off(state.user(), state.member(), state.company(), id.clone(), WriteOff::Resource(state.model().clone(), NumericUnion::Integer(3)), costs.clone() * num!(2), None, &now);
+        assert_eq!(res, Err(Error::NegativeCosts));
+
+        // can't write off zero/negative costs
+        let res = write_off(state.user(), state.member(), state.company(), id.clone(), WriteOff::Resource(state.model().clone(), NumericUnion::Integer(3)), Costs::new(), None, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = write_off(state.user(), state.member(), state.company(), id.clone(), WriteOff::Resource(state.model().clone(), NumericUnion::Integer(3)), Costs::new() - costs_to_waste.clone(), None, &now);
+        assert_eq!(res, Err(Error::NegativeCosts));
+
+        // a company that doesn't own a resource can't write it off
+        let mut state2 = state.clone();
+        state2.model_mut().inner_mut().set_primary_accountable(Some(CompanyID::new("ziggy").into()));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::Event(EventError::ResourceOwnerMismatch)));
+
+        // now write off a process
+        let process = make_process(&ProcessID::create(), state.company().id(), "making cheese", &costs, &now);
+        let mut state3 = state.company().clone();
+        state3.set_waste_costs(Costs::new_with_labor("cheesemaker", 10));
+        let mods = write_off(state.user(), state.member(), &state3, id.clone(), WriteOff::Process(process.clone()), costs.clone(), None, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let company3 = mods[2].clone().expect_op::<Company>(Op::Update).unwrap();
+        assert_eq!(event.inner().output_of(), &Some(process.id().clone()));
+        assert_eq!(event.inner().resource_quantity(), &None);
+        assert_eq!(process2.costs(), &Costs::new());
+        assert_eq!(company3.waste_costs(), &(Costs::new_with_labor("cheesemaker", 10) + costs.clone()));
+        assert_eq!(company3.total_costs(), &costs);
+
+        // the company has to actually hold the costs being written off
+        let mut state4 = state.company().clone();
+        state4.set_total_costs(Costs::new_with_labor("homemaker", 20));
+        let res = write_off(state.user(), state.member(), &state4, id.clone(), WriteOff::Process(process.clone()), costs.clone(), None, &now);
+        assert_eq!(res, Err(Error::NegativeCosts));
+
+        // can't write off a process you don't own
+        let mut process3 = process.clone();
+        process3.set_company_id(CompanyID::new("zing").into());
+        let res = write_off(state.user(), state.member(), state.company(), id.clone(), WriteOff::Process(process3), costs.clone(), None, &now);
+        assert_eq!(res, Err(Error::Event(EventError::ProcessOwnerMismatch)));
+
+        // can't write off a deleted process
+        let mut process4 = process.clone();
+        process4.set_deleted(Some(now.clone()));
+        let res = write_off(state.user(), state.member(), state.company(), id.clone(), WriteOff::Process(process4), costs.clone(), None, &now);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("process".into())));
+
+        // can't write off a deleted resource
+        let mut state5 = state.clone();
+        state5.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("resource".into())));
+    }
+
+    #[test]
+    fn can_split_resource() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MoveResource], &now);
+        let resource = make_resource(&ResourceID::new("plank"), state.company().id(), &Measure::new(num!(10), Unit::One), &Costs::new_with_labor("homemaker", 100), &now);
+        state.model = Some(resource);
+
+        let id1 = EventID::create();
+        let id2 = EventID::create();
+        let id3 = EventID::create();
+        let testfn = |state: &TestState<Resource, Resource>| {
+            let parts = vec![
+                SplitPart::new(id1.clone(), ResourceID::new("plank1"), Ratio::new(num!(1)).unwrap()),
+                SplitPart::new(id2.clone(), ResourceID::new("plank2"), Ratio::new(num!(1)).unwrap()),
+                SplitPart::new(id3.clone(), ResourceID::new("plank3"), Ratio::new(num!(1)).unwrap()),
+            ];
+            split_resource(state.user(), state.member(), state.company(), state.model().clone(), parts, Some("thirds".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 7);
+        let event1 = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let plank1 = mods[1].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let event2 = mods[2].clone().expect_op::<Event>(Op::Create).unwrap();
+        let plank2 = mods[3].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let event3 = mods[4].clone().expect_op::<Event>(Op::Create).unwrap();
+        let plank3 = mods[5].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let resource2 = mods[6].clone().expect_op::<Resource>(Op::Update).unwrap();
+
+        assert_eq!(event1.id(), &id1);
+        assert_eq!(event2.id(), &id2);
+        assert_eq!(event3.id(), &id3);
+        assert_eq!(event1.inner().note(), &Some("thirds".into()));
+        assert_eq!(plank1.id(), &ResourceID::new("plank1"));
+        assert_eq!(plank2.id(), &ResourceID::new("plank2"));
+        assert_eq!(plank3.id(), &ResourceID::new("plank3"));
+        let total_quantity = [&plank1, &plank2, &plank3].iter()
+            .map(|x| measure::to_decimal(x.inner().accounting_quantity().as_ref().unwrap()).unwrap())
+            .fold(Decimal::zero(), |acc, x| acc + x);
+        assert_eq!(total_quantity, num!(10));
+        assert_eq!(plank1.costs().clone() + plank2.costs().clone() + plank3.costs().clone(), Costs::new_with_labor("homemaker", 100));
+        assert_eq!(plank2.costs(), plank3.costs());
+        assert_eq!(resource2.id(), state.model().id());
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(0), Unit::One)));
+        assert!(resource2.costs().is_zero());
+
+        let res = split_resource(state.user(), state.member(), state.company(), state.model().clone(), vec![], None, &now);
+        assert_eq!(res, Err(Error::MissingFields(vec!["parts".into()])));
+    }
+
+    #[test]
+    fn can_merge_resources() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MoveResource], &now);
+        let plank1 = make_resource(&ResourceID::new("plank1"), state.company().id(), &Measure::new(num!(3), Unit::One), &Costs::new_with_labor("homemaker", 30), &now);
+        let plank2 = make_resource(&ResourceID::new("plank2"), state.company().id(), &Measure::new(num!(5), Unit::One), &Costs::new_with_labor("homemaker", 45), &now);
+        let plank3 = make_resource(&ResourceID::new("plank3"), state.company().id(), &Measure::new(num!(2), Unit::One), &Costs::new_with_labor("homemaker", 10), &now);
+        state.model = Some(plank1);
+        state.model2 = Some(plank2);
+
+        let id1 = EventID::create();
+        let id2 = EventID::create();
+        let testfn_inner = |state: &TestState<Resource, Resource>, resource_to: ResourceMover| {
+            let sources = vec![(id1.clone(), state.model().clone()), (id2.clone(), state.model2().clone())];
+            merge_resources(state.user(), state.member(), state.company(), sources, resource_to, None, &now)
+        };
+        let testfn_create = |state: &TestState<Resource, Resource>| {
+            testfn_inner(state, ResourceMover::Create(ResourceID::new("planks")))
+        };
+        test::standard_transaction_tests(&state, &testfn_create);
+
+        let mods = testfn_create(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let event1 = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let plank1_2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let event2 = mods[2].clone().expect_op::<Event>(Op::Create).unwrap();
+        let plank2_2 = mods[3].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let planks = mods[4].clone().expect_op::<Resource>(Op::Create).unwrap();
+        assert_eq!(event1.id(), &id1);
+        assert_eq!(event2.id(), &id2);
+        assert_eq!(event2.inner().to_resource_inventoried_as(), &Some(ResourceID::new("planks")));
+        assert_eq!(plank1_2.inner().accounting_quantity(), &Some(Measure::new(num!(0), Unit::One)));
+        assert!(plank1_2.costs().is_zero());
+        assert_eq!(plank2_2.inner().accounting_quantity(), &Some(Measure::new(num!(0), Unit::One)));
+        assert!(plank2_2.costs().is_zero());
+        assert_eq!(planks.id(), &ResourceID::new("planks"));
+        assert_eq!(planks.inner().accounting_quantity(), &Some(Measure::new(num!(8), Unit::One)));
+        assert_eq!(planks.costs(), &Costs::new_with_labor("homemaker", 75));
+
+        // merging into an existing resource
+        let mods = testfn_inner(&state, ResourceMover::Update(plank3.clone())).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let plank3_2 = mods[4].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(plank3_2.id(), plank3.id());
+        assert_eq!(plank3_2.inner().accounting_quantity(), &Some(Measure::new(num!(10), Unit::One)));
+        assert_eq!(plank3_2.costs(), &Costs::new_with_labor("homemaker", 85));
+
+        let mut state2 = state.clone();
+        state2.model2_mut().inner_mut().set_conforms_to(ResourceSpecID::new("chairs"));
+        let res = testfn_create(&state2);
+        assert_eq!(res, Err(Error::ResourceSpecMismatch));
+
+        let mut state3 = state.clone();
+        state3.model2_mut().inner_mut().set_accounting_quantity(Some(Measure::new(num!(5), Unit::Kilogram)));
+        let res = testfn_create(&state3);
+        assert_eq!(res, Err(Error::MeasureUnitsMismatched));
+
+        // the same source twice
+        let mut state4 = state.clone();
+        state4.model2 = Some(state.model().clone());
+        let res = testfn_create(&state4);
+        assert_eq!(res, Err(Error::ResourceMergeOverlap));
+
+        // merging a source into itself
+        let res = testfn_inner(&state, ResourceMover::Update(state.model2().clone()));
+        assert_eq!(res, Err(Error::ResourceMergeOverlap));
+        let res = testfn_inner(&state, ResourceMover::Create(state.model().id().clone()));
+        assert_eq!(res, Err(Error::ResourceMergeOverlap));
+    }
+}
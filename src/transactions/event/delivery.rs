@@ -3,6 +3,12 @@
 //! For instance, if shipping a box of widgets between two companies, a shipping
 //! company would use the actions in this module to describe the process and
 //! account for the costs along the way.
+//!
+//! Resources that travel together can be grouped into a [shipment][1], which
+//! creates the underlying `pickup`/`dropoff` events for all of its resources at
+//! once and keeps track of the shipment's status.
+//!
+//! [1]: ../../../models/shipment/index.html
 
 use chrono::{DateTime, Utc};
 use crate::{
@@ -10,18 +16,22 @@ use crate::{
     costs::Costs,
     error::{Error, Result},
     models::{
+        self,
         Op,
         Modifications,
         event::{Event, EventID, EventProcessState},
         company::{Company, Permission as CompanyPermission},
         member::Member,
         lib::basis_model::Model,
+        lib::agent::Agent,
         process::Process,
         resource::Resource,
+        shipment::{Shipment, ShipmentID, ShipmentStatus},
         user::User,
     },
     util::number::Ratio,
 };
+use rust_decimal::prelude::*;
 use vf_rs::{vf, geo::SpatialThing};
 
 /// Signifies that a delivery has been dropped off at the desired location. Note
@@ -30,7 +40,14 @@ use vf_rs::{vf, geo::SpatialThing};
 ///
 /// This operates on a whole resource.
 pub fn dropoff(caller: &User, member: &Member, company: &Company, id: EventID, process: Process, resource: Resource, move_costs_ratio: Ratio, new_location: Option<SpatialThing>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    let move_costs = process.costs().clone() * move_costs_ratio;
+    dropoff_costs(caller, member, company, id, process, resource, move_costs, new_location, note, now)
+}
+
+/// Drop off a resource, moving exactly `move_costs` out of the delivery process
+/// and into the resource.
+fn dropoff_costs(caller: &User, member: &Member, company: &Company, id: EventID, process: Process, resource: Resource, move_costs: Costs, new_location: Option<SpatialThing>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateDropoff)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Dropoff)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -38,7 +55,6 @@ pub fn dropoff(caller: &User, member: &Member, company: &Company, id: EventID, p
 
     let process_id = process.id().clone();
     let resource_id = resource.id().clone();
-    let move_costs = process.costs().clone() * move_costs_ratio;
 
     let state = EventProcessState::builder()
         .output_of(process)
@@ -82,7 +98,7 @@ pub fn dropoff(caller: &User, member: &Member, company: &Company, id: EventID, p
 ///
 /// This operates on a whole resource.
 pub fn pickup(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, process: Process, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreatePickup)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Pickup)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -126,6 +142,117 @@ pub fn pickup(caller: &User, member: &Member, company: &Company, id: EventID, re
     Ok(mods)
 }
 
+/// Create a shipment of resources, carried by the current company using the
+/// given delivery process.
+pub fn create_shipment(caller: &User, member: &Member, company: &Company, id: ShipmentID, process: &Process, resources: Vec<&Resource>, origin: Option<SpatialThing>, destination: Option<SpatialThing>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateShipment)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ShipmentCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if process.company_id() != company.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if resources.is_empty() {
+        Err(Error::MissingFields(vec!["resources".into()]))?;
+    }
+    let model = Shipment::builder()
+        .id(id)
+        .carrier(company.agent_id())
+        .process_id(process.id().clone())
+        .origin(origin)
+        .destination(destination)
+        .resources(resources.iter().map(|resource| resource.id().clone()).collect::<Vec<_>>())
+        .status(ShipmentStatus::Pending)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Make sure a shipment is being operated on by its carrier, in the expected
+/// status, and with exactly the resources it contains.
+fn check_shipment(company: &Company, shipment: &Shipment, process: &Process, resources: &[(EventID, Resource)], status: ShipmentStatus) -> Result<()> {
+    if shipment.is_deleted() {
+        Err(Error::ObjectIsDeleted("shipment".into()))?;
+    }
+    if !shipment.is_active() {
+        Err(Error::ObjectIsInactive("shipment".into()))?;
+    }
+    if shipment.carrier() != &company.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if shipment.status() != &status {
+        Err(Error::ShipmentStatusInvalid)?;
+    }
+    if shipment.process_id() != process.id() {
+        Err(Error::ShipmentMismatch)?;
+    }
+    let resource_ids = resources.iter().map(|(_, resource)| resource.id()).collect::<Vec<_>>();
+    if !shipment.contains_exactly(&resource_ids) {
+        Err(Error::ShipmentMismatch)?;
+    }
+    Ok(())
+}
+
+/// Pick up all the resources in a pending shipment, creating one `pickup` event
+/// (with the given ID) for each resource and marking the shipment in transit.
+///
+/// Custody of the resources must already have been transferred to the carrier.
+pub fn pickup_shipment(caller: &User, member: &Member, company: &Company, mut subject: Shipment, process: &Process, resources: Vec<(EventID, Resource)>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_shipment(company, &subject, process, &resources, ShipmentStatus::Pending)?;
+    let mut mods = Modifications::new();
+    for (event_id, resource) in resources {
+        for pickup_mod in pickup(caller, member, company, event_id, resource, process.clone(), note.clone(), now)? {
+            mods.push_raw(pickup_mod);
+        }
+    }
+    subject.set_status(ShipmentStatus::InTransit);
+    subject.set_updated(now.clone());
+    mods.push(Op::Update, subject);
+    Ok(mods)
+}
+
+/// Drop off all the resources in an in-transit shipment at its destination,
+/// creating one `dropoff` event (with the given ID) for each resource and
+/// marking the shipment delivered.
+///
+/// `move_costs_ratio` is the portion of the delivery process' costs that go to
+/// this shipment. These costs are split evenly between the shipment's
+/// resources.
+pub fn dropoff_shipment(caller: &User, member: &Member, company: &Company, mut subject: Shipment, mut process: Process, resources: Vec<(EventID, Resource)>, move_costs_ratio: Ratio, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_shipment(company, &subject, &process, &resources, ShipmentStatus::InTransit)?;
+    let move_costs = process.costs().clone() * move_costs_ratio;
+    let per_resource = move_costs.clone() / Decimal::from(resources.len());
+    let num_resources = resources.len();
+    let mut moved = Costs::new();
+    let mut mods = Modifications::new();
+    for (idx, (event_id, resource)) in resources.into_iter().enumerate() {
+        // the last resource gets whatever is left so rounding doesn't strand
+        // any costs in the process
+        let resource_costs = if idx == num_resources - 1 {
+            move_costs.clone() - moved.clone()
+        } else {
+            per_resource.clone()
+        };
+        moved = moved + resource_costs.clone();
+        for dropoff_mod in dropoff_costs(caller, member, company, event_id, process.clone(), resource, resource_costs, subject.destination().clone(), note.clone(), now)? {
+            let (op, model) = dropoff_mod.into_pair();
+            match model {
+                models::Model::Process(updated) => process = updated,
+                model => mods.push(op, model),
+            }
+        }
+    }
+    mods.push(Op::Update, process);
+    subject.set_status(ShipmentStatus::Delivered);
+    subject.set_updated(now.clone());
+    mods.push(Op::Update, subject);
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -260,5 +387,93 @@ mod tests {
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
     }
-}
 
+    #[test]
+    fn can_ship() {
+        let now = util::time::now();
+        let shipment_id = ShipmentID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ShipmentCreate, CompanyPermission::Pickup, CompanyPermission::Dropoff], &now);
+        let widgets = make_resource(&ResourceID::new("widgets"), state.company().id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("machinist", 100), &now);
+        let gizmos = make_resource(&ResourceID::new("gizmos"), state.company().id(), &Measure::new(num!(3), Unit::One), &Costs::new_with_labor("machinist", 20), &now);
+        let gadgets = make_resource(&ResourceID::new("gadgets"), state.company().id(), &Measure::new(num!(8), Unit::One), &Costs::new_with_labor("machinist", 50), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "deliver things", &Costs::new_with_labor("trucker", 100), &now);
+        let destination = state.loc().clone();
+        state.model = Some(process.clone());
+
+        let testfn = |state: &TestState<Process, Process>| {
+            create_shipment(state.user(), state.member(), state.company(), shipment_id.clone(), state.model(), vec![&widgets, &gizmos, &gadgets], None, Some(destination.clone()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let shipment = mods[0].clone().expect_op::<Shipment>(Op::Create).unwrap();
+        assert_eq!(shipment.id(), &shipment_id);
+        assert_eq!(shipment.carrier(), &state.company().agent_id());
+        assert_eq!(shipment.process_id(), process.id());
+        assert_eq!(shipment.origin(), &None);
+        assert_eq!(shipment.destination(), &Some(destination.clone()));
+        assert_eq!(shipment.resources(), &vec![widgets.id().clone(), gizmos.id().clone(), gadgets.id().clone()]);
+        assert_eq!(shipment.status(), &ShipmentStatus::Pending);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::new("zing"));
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+        let res = create_shipment(state.user(), state.member(), state.company(), shipment_id.clone(), &process, vec![], None, None, &now);
+        assert_eq!(res, Err(Error::MissingFields(vec!["resources".into()])));
+
+        // pick it up
+        let items = vec![
+            (EventID::create(), gadgets.clone()),
+            (EventID::create(), widgets.clone()),
+            (EventID::create(), gizmos.clone()),
+        ];
+        let mods = pickup_shipment(state.user(), state.member(), state.company(), shipment.clone(), &process, items.clone(), None, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 4);
+        for (i, (event_id, resource)) in items.iter().enumerate() {
+            let event = mods[i].clone().expect_op::<Event>(Op::Create).unwrap();
+            assert_eq!(event.id(), event_id);
+            assert_eq!(event.inner().action(), &vf::Action::Pickup);
+            assert_eq!(event.inner().resource_inventoried_as(), &Some(resource.id().clone()));
+        }
+        let shipment2 = mods[3].clone().expect_op::<Shipment>(Op::Update).unwrap();
+        assert_eq!(shipment2.status(), &ShipmentStatus::InTransit);
+
+        // can't pick up twice, drop off before pickup, or ship the wrong stuff
+        let res = pickup_shipment(state.user(), state.member(), state.company(), shipment2.clone(), &process, items.clone(), None, &now);
+        assert_eq!(res, Err(Error::ShipmentStatusInvalid));
+        let res = dropoff_shipment(state.user(), state.member(), state.company(), shipment.clone(), process.clone(), items.clone(), Ratio::new(1).unwrap(), None, &now);
+        assert_eq!(res, Err(Error::ShipmentStatusInvalid));
+        let res = pickup_shipment(state.user(), state.member(), state.company(), shipment.clone(), &process, items[0..2].to_vec(), None, &now);
+        assert_eq!(res, Err(Error::ShipmentMismatch));
+        let other_process = make_process(&ProcessID::create(), state.company().id(), "deliver other things", &Costs::new(), &now);
+        let res = pickup_shipment(state.user(), state.member(), state.company(), shipment.clone(), &other_process, items.clone(), None, &now);
+        assert_eq!(res, Err(Error::ShipmentMismatch));
+        let mut state3 = state.clone();
+        state3.company_mut().set_id(CompanyID::new("zing"));
+        let res = pickup_shipment(state3.user(), state3.member(), state3.company(), shipment.clone(), &process, items.clone(), None, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // drop it off, splitting 75% of the delivery costs over the resources
+        let mods = dropoff_shipment(state.user(), state.member(), state.company(), shipment2.clone(), process.clone(), items.clone(), Ratio::new(num!(0.75)).unwrap(), Some("delivered".into()), &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 8);
+        let mut delivered_costs = Costs::new();
+        for i in 0..3 {
+            let event = mods[i * 2].clone().expect_op::<Event>(Op::Create).unwrap();
+            let resource = mods[(i * 2) + 1].clone().expect_op::<Resource>(Op::Update).unwrap();
+            assert_eq!(event.id(), &items[i].0);
+            assert_eq!(event.inner().action(), &vf::Action::Dropoff);
+            assert_eq!(event.inner().note(), &Some("delivered".into()));
+            assert_eq!(resource.id(), items[i].1.id());
+            assert_eq!(resource.inner().current_location(), &Some(destination.clone()));
+            delivered_costs = delivered_costs + event.move_costs().clone().unwrap();
+        }
+        assert_eq!(mods[0].clone().expect_op::<Event>(Op::Create).unwrap().move_costs(), &Some(Costs::new_with_labor("trucker", 25)));
+        assert_eq!(mods[3].clone().expect_op::<Resource>(Op::Update).unwrap().costs(), &(Costs::new_with_labor("machinist", 100) + Costs::new_with_labor("trucker", 25)));
+        assert_eq!(delivered_costs, Costs::new_with_labor("trucker", 75));
+        let process2 = mods[6].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.costs(), &Costs::new_with_labor("trucker", 25));
+        let shipment3 = mods[7].clone().expect_op::<Shipment>(Op::Update).unwrap();
+        assert_eq!(shipment3.status(), &ShipmentStatus::Delivered);
+    }
+}
@@ -1,16 +1,38 @@
 //! Events are what move costs/resources through the system.
 //!
+//! Any of the event transactions can have structured [metadata][2] attached to
+//! the events they create using [with_metadata], and metadata can be changed
+//! after the fact with [set_metadata].
+//!
 //! See the [event model.][1]
 //!
 //! [1]: ../../models/event/index.html
+//! [2]: ../../models/event/type.EventMetadata.html
+//! [with_metadata]: fn.with_metadata.html
+//! [set_metadata]: fn.set_metadata.html
 
+use chrono::{DateTime, Utc};
 use crate::{
+    access::Permission,
+    error::{Error, Result},
     models::{
+        Op,
+        Model as AnyModel,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        event::{Event, EventError, EventMetadata, EventProcessState, MoveType},
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
+        member::Member,
         resource::{ResourceID, Resource},
+        user::User,
     },
 };
 #[cfg(feature = "with_serde")]
 use serde::{Serialize, Deserialize};
+use vf_rs::vf;
 
 /// Helps us signify whether we want an operation that moves a resource from one
 /// place to another to a) create a new resource copied from the original or b)
@@ -27,11 +49,296 @@ pub enum ResourceMover {
     Update(Resource),
 }
 
+/// Grab the user and company permissions needed to create the given event.
+fn create_permissions(subject: &Event) -> (Permission, CompanyPermission) {
+    match subject.inner().action() {
+        vf::Action::Accept => (Permission::EventCreateAccept, CompanyPermission::Accept),
+        vf::Action::Cite => (Permission::EventCreateCite, CompanyPermission::Cite),
+        vf::Action::Consume => (Permission::EventCreateConsume, CompanyPermission::Consume),
+        vf::Action::DeliverService => (Permission::EventCreateDeliverService, CompanyPermission::DeliverService),
+        vf::Action::Dropoff => (Permission::EventCreateDropoff, CompanyPermission::Dropoff),
+        vf::Action::Lower => (Permission::EventCreateLower, CompanyPermission::Lower),
+        vf::Action::Modify => (Permission::EventCreateModify, CompanyPermission::Modify),
+        vf::Action::Move => match subject.move_type() {
+            Some(MoveType::ProcessCosts) => (Permission::EventCreateMoveCosts, CompanyPermission::MoveCosts),
+            Some(MoveType::WriteOff) => (Permission::EventCreateWriteOff, CompanyPermission::WriteOff),
+            Some(MoveType::ExternalSale(..)) => (Permission::EventCreateMarketSell, CompanyPermission::MarketSell),
+            Some(MoveType::Resource) | None => (Permission::EventCreateMoveResource, CompanyPermission::MoveResource),
+        },
+        vf::Action::Pickup => (Permission::EventCreatePickup, CompanyPermission::Pickup),
+        vf::Action::Produce => (Permission::EventCreateProduce, CompanyPermission::Produce),
+        vf::Action::Raise => (Permission::EventCreateRaise, CompanyPermission::Raise),
+        vf::Action::Transfer => (Permission::EventCreateTransfer, CompanyPermission::Transfer),
+        vf::Action::TransferAllRights => (Permission::EventCreateTransferAllRights, CompanyPermission::TransferAllRights),
+        vf::Action::TransferCustody => (Permission::EventCreateTransferCustody, CompanyPermission::TransferCustody),
+        vf::Action::Use => (Permission::EventCreateUse, CompanyPermission::Use),
+        vf::Action::Work => (Permission::EventCreateWork, CompanyPermission::Work),
+    }
+}
+
+/// Finalize a pending event.
+///
+/// An event that has a `has_beginning` but no `has_end` is considered pending,
+/// and processing it does nothing. This sets the event's `has_end` and runs it
+/// through processing again (using the given `state`, which should hold the
+/// *current* versions of the processes/resources the event references),
+/// returning an update of the event along with any modifications processing
+/// creates.
+///
+/// The calling company must be either the provider or receiver of the event,
+/// and on top of the finalize permissions, the caller needs the same
+/// permissions it would take to create the event outright. Pending transfers
+/// can't be finalized this way: they have to be [accepted][1] by the receiving
+/// company.
+///
+/// [1]: transfer/fn.transfer_accept.html
+pub fn finalize(caller: &User, member: &Member, company: &Company, mut subject: Event, end: DateTime<Utc>, state: EventProcessState, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::EventFinalize)?;
+    let (create_permission, create_company_permission) = create_permissions(&subject);
+    caller.access_check(create_permission)?;
+    member.access_check(caller.id(), company.id(), create_company_permission)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("event".into()))?;
+    }
+    if subject.inner().provider() != &company.agent_id() && subject.inner().receiver() != &company.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if subject.inner().has_beginning().is_none() || subject.inner().has_end().is_some() {
+        Err(EventError::NotPending)?;
+    }
+    if subject.inner().action() == &vf::Action::Transfer {
+        // pending transfers go through transfer::transfer_accept(), which
+        // also moves the costs between the two companies
+        Err(EventError::TransferAwaitingAcceptance)?;
+    }
+    subject.inner_mut().set_has_end(Some(end));
+    subject.set_updated(now.clone());
+
+    let evmods = subject.process(state, now)?.into_vec();
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, subject);
+    for evmod in evmods {
+        mods.push_raw(evmod);
+    }
+    Ok(mods)
+}
+
+/// Attach metadata to every event created by a set of modifications, for
+/// instance the result of `production::produce()`. Modifications that aren't
+/// event creations are passed through untouched.
+pub fn with_metadata(mods: Modifications, metadata: EventMetadata) -> Modifications {
+    let mut new_mods = Modifications::new();
+    for modification in mods.into_vec() {
+        match modification.into_pair() {
+            (Op::Create, AnyModel::Event(mut event)) => {
+                event.set_metadata(Some(metadata.clone()));
+                new_mods.push(Op::Create, event);
+            }
+            (op, model) => new_mods.push(op, model),
+        }
+    }
+    new_mods
+}
+
+/// Set (or clear) the metadata on an existing event. This doesn't re-process
+/// the event, since metadata has no effect on costs or resources.
+///
+/// The calling company must be either the provider or receiver of the event.
+pub fn set_metadata(caller: &User, member: &Member, company: &Company, mut subject: Event, metadata: Option<EventMetadata>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::EventAnnotate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("event".into()))?;
+    }
+    if subject.inner().provider() != &company.agent_id() && subject.inner().receiver() != &company.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    subject.set_metadata(metadata);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
 pub mod accounting;
 pub mod delivery;
 pub mod production;
+pub mod qc;
 pub mod modification;
 pub mod service;
 pub mod transfer;
 pub mod work;
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            event::EventID,
+            occupation::OccupationID,
+            process::{Process, ProcessID},
+        },
+        transactions::event::production,
+        util::{self, number::Ratio, test::{self, *}},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn can_finalize() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::Consume, CompanyPermission::EventFinalize], &now);
+        let resource = make_resource(&ResourceID::new("steel"), state.company().id(), &Measure::new(num!(10), Unit::Kilogram), &Costs::new_with_labor("miner", num!(100)), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        let mods = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource.clone(), process.clone(), Ratio::new(num!(0.5)).unwrap(), 5, None, &now).unwrap().into_vec();
+        let mut event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        event.inner_mut().set_has_point_in_time(None);
+        event.inner_mut().set_has_beginning(Some(now.clone()));
+        let process_state = EventProcessState::builder()
+            .input_of(process.clone())
+            .resource(resource.clone())
+            .build().unwrap();
+        // pending events don't do anything when processed
+        assert_eq!(event.process(process_state.clone(), &now).unwrap().into_vec().len(), 0);
+        state.model = Some(event);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Event, Event>| {
+            finalize(state.user(), state.member(), state.company(), state.model().clone(), now2.clone(), process_state.clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "event", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event2 = mods[0].clone().expect_op::<Event>(Op::Update).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource2 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event2.id(), state.model().id());
+        assert_eq!(event2.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(event2.inner().has_end(), &Some(now2.clone()));
+        assert_eq!(event2.updated(), &now2);
+        assert_eq!(process2.costs(), &Costs::new_with_labor("miner", num!(50)));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("miner", num!(50)));
+
+        // already finalized
+        let res = finalize(state.user(), state.member(), state.company(), event2.clone(), now2.clone(), process_state.clone(), &now2);
+        assert_eq!(res, Err(Error::Event(EventError::NotPending)));
+
+        // never started
+        let mut state2 = state.clone();
+        state2.model_mut().inner_mut().set_has_beginning(None);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::Event(EventError::NotPending)));
+
+        // ending before the beginning is still caught by processing
+        let res = finalize(state.user(), state.member(), state.company(), state.model().clone(), "2000-01-01T00:00:00Z".parse().unwrap(), process_state.clone(), &now2);
+        assert_eq!(res, Err(Error::Event(EventError::DateEndBeforeBegin)));
+
+        // can't finalize other companies' events
+        let mut state3 = state.clone();
+        state3.model_mut().inner_mut().set_provider(CompanyID::new("zing").into());
+        state3.model_mut().inner_mut().set_receiver(CompanyID::new("zing").into());
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // finalizing takes the same permissions as creating the event
+        let mut state4 = state.clone();
+        state4.member = Some(make_member_worker(state.member().id(), state.user().id(), state.company().id(), &OccupationID::create(), vec![CompanyPermission::EventFinalize], &now));
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn maps_create_permissions() {
+        let now = util::time::now();
+        let make = |action: vf::Action, move_type: Option<MoveType>| {
+            Event::builder()
+                .id(EventID::create())
+                .inner(
+                    vf::EconomicEvent::builder()
+                        .action(action)
+                        .provider(CompanyID::new("jerry's widgets"))
+                        .receiver(CompanyID::new("jerry's widgets"))
+                        .build().unwrap()
+                )
+                .move_type(move_type)
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        assert_eq!(create_permissions(&make(vf::Action::Consume, None)), (Permission::EventCreateConsume, CompanyPermission::Consume));
+        assert_eq!(create_permissions(&make(vf::Action::Produce, None)), (Permission::EventCreateProduce, CompanyPermission::Produce));
+        assert_eq!(create_permissions(&make(vf::Action::Transfer, None)), (Permission::EventCreateTransfer, CompanyPermission::Transfer));
+        assert_eq!(create_permissions(&make(vf::Action::Raise, None)), (Permission::EventCreateRaise, CompanyPermission::Raise));
+        assert_eq!(create_permissions(&make(vf::Action::Lower, None)), (Permission::EventCreateLower, CompanyPermission::Lower));
+        assert_eq!(create_permissions(&make(vf::Action::Work, None)), (Permission::EventCreateWork, CompanyPermission::Work));
+        assert_eq!(create_permissions(&make(vf::Action::Move, Some(MoveType::WriteOff))), (Permission::EventCreateWriteOff, CompanyPermission::WriteOff));
+        assert_eq!(create_permissions(&make(vf::Action::Move, Some(MoveType::ProcessCosts))), (Permission::EventCreateMoveCosts, CompanyPermission::MoveCosts));
+        assert_eq!(create_permissions(&make(vf::Action::Move, None)), (Permission::EventCreateMoveResource, CompanyPermission::MoveResource));
+    }
+
+    #[test]
+    fn can_attach_metadata() {
+        let now = util::time::now();
+        let state: TestState<Event, Event> = TestState::standard(vec![CompanyPermission::Consume], &now);
+        let resource = make_resource(&ResourceID::new("steel"), state.company().id(), &Measure::new(num!(10), Unit::Kilogram), &Costs::new_with_labor("miner", num!(100)), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        let mods = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource.clone(), process.clone(), Ratio::new(num!(0.5)).unwrap(), 5, None, &now).unwrap();
+        let mut metadata = EventMetadata::new();
+        metadata.insert("invoice".into(), "INV-0042".into());
+        let mods = with_metadata(mods, metadata.clone()).into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource2 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event.metadata(), &Some(metadata));
+        assert_eq!(process2.costs(), &Costs::new_with_labor("miner", num!(50)));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("miner", num!(50)));
+    }
+
+    #[test]
+    fn can_set_metadata() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::Consume, CompanyPermission::EventAnnotate], &now);
+        let resource = make_resource(&ResourceID::new("steel"), state.company().id(), &Measure::new(num!(10), Unit::Kilogram), &Costs::new_with_labor("miner", num!(100)), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        let mods = production::consume(state.user(), state.member(), state.company(), EventID::create(), resource.clone(), process.clone(), Ratio::new(num!(0.5)).unwrap(), 5, Some("need steel".into()), &now).unwrap().into_vec();
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        assert_eq!(event.metadata(), &None);
+        state.model = Some(event);
+
+        let mut metadata = EventMetadata::new();
+        metadata.insert("photo_sha256".into(), "d2a84f4b8b650937ec8f73cd8be2c74a".into());
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Event, Event>| {
+            set_metadata(state.user(), state.member(), state.company(), state.model().clone(), Some(metadata.clone()), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "event", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let event2 = mods[0].clone().expect_op::<Event>(Op::Update).unwrap();
+        assert_eq!(event2.metadata(), &Some(metadata.clone()));
+        assert_eq!(event2.inner().note(), &Some("need steel".into()));
+        assert_eq!(event2.updated(), &now2);
+
+        let mods = set_metadata(state.user(), state.member(), state.company(), event2.clone(), None, &now2).unwrap().into_vec();
+        let event3 = mods[0].clone().expect_op::<Event>(Op::Update).unwrap();
+        assert_eq!(event3.metadata(), &None);
+
+        let mut state2 = state.clone();
+        state2.model_mut().inner_mut().set_provider(CompanyID::new("zing").into());
+        state2.model_mut().inner_mut().set_receiver(CompanyID::new("zing").into());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
@@ -3,6 +3,11 @@
 //!
 //! For instance, modification could describe a repair of a vehicle or large
 //! machine.
+//!
+//! This module also handles packing resources into containers (and unpacking
+//! them), for instance stacking boxes of widgets onto a pallet. A packed
+//! resource's costs are carried by its container, and it isn't on hand by
+//! itself until it's unpacked again.
 
 use chrono::{DateTime, Utc};
 use crate::{
@@ -12,10 +17,13 @@ use crate::{
     models::{
         Op,
         Modifications,
-        event::{Event, EventID, EventProcessState},
+        event::{Event, EventError, EventID, EventProcessState},
         company::{Company, Permission as CompanyPermission},
         member::Member,
-        lib::basis_model::Model,
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
         process::Process,
         resource::Resource,
         user::User,
@@ -30,7 +38,7 @@ use vf_rs::vf;
 /// Effectively, you `accept` a resource into a repair process, and the output
 /// of that process would be `modify`.
 pub fn accept<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, process: Process, resource_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateAccept)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Accept)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -84,7 +92,7 @@ pub fn accept<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &C
 /// Effectively, you `accept` a resource into a repair process, and the output
 /// of that process would be `modify`.
 pub fn modify<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, process: Process, resource: Resource, move_costs_ratio: Ratio, resource_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateModify)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Modify)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -134,14 +142,92 @@ pub fn modify<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &C
     Ok(mods)
 }
 
+/// Make sure the company owns and has custody of a resource we're (un)packing.
+fn check_packable(company: &Company, resource: &Resource) -> Result<()> {
+    if resource.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource".into()))?;
+    }
+    if resource.inner().primary_accountable().as_ref() != Some(&company.agent_id()) {
+        Err(EventError::ResourceOwnerMismatch)?;
+    }
+    if resource.in_custody_of() != &company.agent_id() {
+        Err(EventError::ResourceCustodyMismatch)?;
+    }
+    Ok(())
+}
+
+/// Pack a resource (`content`) into another resource (`container`).
+///
+/// The content's costs move into the container, and its onhand quantity drops
+/// to zero (it's on hand as part of the container now). Its accounting quantity
+/// doesn't change.
+pub fn pack(caller: &User, member: &Member, company: &Company, mut container: Resource, mut content: Resource, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::Pack)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    check_packable(company, &container)?;
+    check_packable(company, &content)?;
+    if container.id() == content.id() || container.inner().contained_in().as_ref() == Some(content.id()) {
+        Err(Error::ContainerInvalid)?;
+    }
+    if content.inner().contained_in().is_some() {
+        Err(Error::ContainerMismatch)?;
+    }
+
+    let unit = content.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+    container.set_costs(container.costs().clone() + content.costs().clone());
+    container.set_updated(now.clone());
+    content.set_costs(Costs::new());
+    content.inner_mut().set_contained_in(Some(container.id().clone()));
+    content.inner_mut().set_onhand_quantity(Some(Measure::new(num!(0), unit)));
+    content.set_updated(now.clone());
+
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, container);
+    mods.push(Op::Update, content);
+    Ok(mods)
+}
+
+/// Unpack a resource (`content`) from the container it was packed into.
+///
+/// `move_costs_ratio` is the share of the container's costs that go back to
+/// the content. The content is on hand again, in its full accounting quantity.
+pub fn unpack(caller: &User, member: &Member, company: &Company, mut container: Resource, mut content: Resource, move_costs_ratio: Ratio, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::Pack)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    check_packable(company, &container)?;
+    check_packable(company, &content)?;
+    if content.inner().contained_in().as_ref() != Some(container.id()) {
+        Err(Error::ContainerMismatch)?;
+    }
+
+    let move_costs = container.costs().clone() * move_costs_ratio;
+    container.set_costs(container.costs().clone() - move_costs.clone());
+    container.set_updated(now.clone());
+    content.set_costs(content.costs().clone() + move_costs);
+    content.inner_mut().set_contained_in(None);
+    let onhand = content.inner().accounting_quantity().clone();
+    content.inner_mut().set_onhand_quantity(onhand);
+    content.set_updated(now.clone());
+
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, container);
+    mods.push(Op::Update, content);
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         models::{
             company::CompanyID,
-            event::{EventError, EventID},
-            lib::agent::Agent,
+            event::EventID,
             occupation::OccupationID,
             process::ProcessID,
             resource::ResourceID,
@@ -279,5 +365,66 @@ mod tests {
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
     }
-}
 
+    #[test]
+    fn can_pack_unpack() {
+        let now = util::time::now();
+        let now2 = now + chrono::Duration::hours(1);
+        let mut state = TestState::standard(vec![CompanyPermission::Pack], &now);
+        let pallet = make_resource(&ResourceID::new("pallet"), state.company().id(), &Measure::new(num!(1), Unit::One), &Costs::new_with_labor("carpenter", 10), &now);
+        let widgets = make_resource(&ResourceID::new("widgets"), state.company().id(), &Measure::new(num!(40), Unit::One), &Costs::new_with_labor("machinist", 200), &now);
+        state.model = Some(pallet.clone());
+        state.model2 = Some(widgets.clone());
+
+        let testfn = |state: &TestState<Resource, Resource>| {
+            pack(state.user(), state.member(), state.company(), state.model().clone(), state.model2().clone(), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let pallet2 = mods[0].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let widgets2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(pallet2.costs(), &(Costs::new_with_labor("carpenter", 10) + Costs::new_with_labor("machinist", 200)));
+        assert_eq!(pallet2.inner().onhand_quantity(), &Some(Measure::new(num!(1), Unit::One)));
+        assert_eq!(widgets2.costs(), &Costs::new());
+        assert_eq!(widgets2.inner().contained_in(), &Some(pallet.id().clone()));
+        assert_eq!(widgets2.inner().accounting_quantity(), &Some(Measure::new(num!(40), Unit::One)));
+        assert_eq!(widgets2.inner().onhand_quantity(), &Some(Measure::new(num!(0), Unit::One)));
+        assert_eq!(widgets2.updated(), &now);
+
+        // can't pack twice, into itself, or into something it contains
+        let res = pack(state.user(), state.member(), state.company(), pallet2.clone(), widgets2.clone(), &now);
+        assert_eq!(res, Err(Error::ContainerMismatch));
+        let res = pack(state.user(), state.member(), state.company(), pallet.clone(), pallet.clone(), &now);
+        assert_eq!(res, Err(Error::ContainerInvalid));
+        let res = pack(state.user(), state.member(), state.company(), widgets2.clone(), pallet2.clone(), &now);
+        assert_eq!(res, Err(Error::ContainerInvalid));
+        let mut state2 = state.clone();
+        state2.model2_mut().set_in_custody_of(CompanyID::new("ziggy").into());
+        assert_eq!(testfn(&state2), Err(Error::Event(EventError::ResourceCustodyMismatch)));
+        let mut state3 = state.clone();
+        state3.model_mut().inner_mut().set_primary_accountable(Some(CompanyID::new("ziggy").into()));
+        assert_eq!(testfn(&state3), Err(Error::Event(EventError::ResourceOwnerMismatch)));
+
+        // unpack, taking the widgets' costs back out
+        let ratio = Ratio::new(num!(200) / num!(210)).unwrap();
+        let mods = unpack(state.user(), state.member(), state.company(), pallet2.clone(), widgets2.clone(), ratio.clone(), &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let pallet3 = mods[0].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let widgets3 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(pallet3.costs().clone() + widgets3.costs().clone(), pallet2.costs().clone());
+        assert_eq!(widgets3.costs(), &(pallet2.costs().clone() * ratio.clone()));
+        assert_eq!(widgets3.inner().contained_in(), &None);
+        assert_eq!(widgets3.inner().onhand_quantity(), &Some(Measure::new(num!(40), Unit::One)));
+        assert_eq!(widgets3.updated(), &now2);
+
+        // can only unpack from the right container
+        let res = unpack(state.user(), state.member(), state.company(), pallet2.clone(), widgets.clone(), ratio.clone(), &now2);
+        assert_eq!(res, Err(Error::ContainerMismatch));
+        let mut state4 = state.clone();
+        state4.member_mut().set_permissions(vec![]);
+        let res = unpack(state4.user(), state4.member(), state4.company(), pallet2.clone(), widgets2.clone(), ratio.clone(), &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
@@ -1,26 +1,98 @@
 //! Production is about using, consuming, and producing resources. The `use` and
 //! `consume` actions are inputs to the productive process and `produce` is the
 //! output the creates a resource.
+//!
+//! Processes often have more than one output. `produce_byproduct()` records
+//! secondary outputs (co-products and waste) with their own cost allocation,
+//! so a sawmill doesn't have to push all of its costs into the lumber and none
+//! into the sawdust (or vice versa).
 
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
+    costs::Costs,
     error::{Error, Result},
     models::{
+        self,
         Op,
         Modifications,
-        event::{Event, EventID, EventProcessState},
+        event::{Byproduct, Event, EventID, EventProcessState},
         company::{Company, Permission as CompanyPermission},
         member::Member,
         lib::basis_model::Model,
         process::Process,
-        resource::Resource,
+        resource::{Resource, ResourceID},
+        resource_spec::ResourceSpec,
         user::User,
     },
-    util::number::Ratio,
+    util::{measure, number::Ratio},
 };
+use getset::Getters;
 use om2::{Measure, NumericUnion};
-use vf_rs::vf;
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use vf_rs::{vf, dfc};
+
+/// One of the inputs consumed by [consume_many].
+///
+/// [consume_many]: fn.consume_many.html
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct ConsumeInput {
+    /// The ID of the consume event for this input
+    id: EventID,
+    /// The resource being consumed
+    resource: Resource,
+    /// The ratio of the resource's costs to move into the process
+    move_costs_ratio: Ratio,
+    /// How much of the resource to consume (in the resource's unit)
+    move_measure: NumericUnion,
+}
+
+impl ConsumeInput {
+    /// Create a new consume input
+    pub fn new<T: Into<NumericUnion>>(id: EventID, resource: Resource, move_costs_ratio: Ratio, move_measure: T) -> Self {
+        Self {
+            id,
+            resource,
+            move_costs_ratio,
+            move_measure: move_measure.into(),
+        }
+    }
+}
+
+/// Determines how many of a process' costs move into the resource it produces.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum CostAllocation {
+    /// Move all of the process' accumulated costs into the produced resource.
+    /// This is what you want for a process that produces its output in one go.
+    AllAvailable,
+    /// Split the process' accumulated costs evenly over the units it's going to
+    /// produce. The value is the number of units the process' *current* costs
+    /// are expected to cover, including the ones being produced now, so a
+    /// process producing 100 widgets in batches of 10 would pass `100` for the
+    /// first batch, `90` for the second, etc.
+    PerUnitAverage(Decimal),
+    /// Move exactly the given costs.
+    Explicit(Costs),
+}
+
+/// Determines how many of a process' costs move into a byproduct it produces.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub enum ByproductAllocation {
+    /// Move no costs at all: the byproduct is free, and the process' costs all
+    /// stay with its main product. This is generally what you want for waste.
+    ZeroCost,
+    /// Move the given share of the process' accumulated costs.
+    Proportional(Ratio),
+    /// Move exactly the given costs.
+    Explicit(Costs),
+}
 
 /// Cite a resource in a process, for instance a design specification.
 ///
@@ -32,7 +104,7 @@ use vf_rs::vf;
 /// citing. For instance, if it took a year of research to derive a formula,
 /// the costs of that research would be imbued in the formula.
 pub fn cite(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, process: Process, move_costs_ratio: Ratio, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateCite)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Cite)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -83,12 +155,17 @@ pub fn cite(caller: &User, member: &Member, company: &Company, id: EventID, reso
 /// If you make widgets out of steel, then steel is the resource, and the
 /// process would be the fabrication that "consumes" steel (with the output,
 /// ie `produce`, of a widget).
+///
+/// Resources from an expired lot cannot be consumed, and resources can't be
+/// consumed into a process that's blocked on unfulfilled commitments.
 pub fn consume<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, process: Process, move_costs_ratio: Ratio, move_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateConsume)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Consume)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
+    process.check_blocked()?;
+    resource.check_lot(now)?;
 
     let measure = {
         let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
@@ -135,28 +212,144 @@ pub fn consume<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &
     Ok(mods)
 }
 
+/// Consume a number of resources into a single process in one go, for instance
+/// all the parts that go into making one widget.
+///
+/// Each input gets its own `consume` event. The first input's event acts as the
+/// anchor for the batch: the events of all the other inputs are marked as
+/// `triggered_by` it, so the whole batch can be pulled back up via the
+/// [provenance] module.
+///
+/// Either every input is consumed or (if any of them fail) none are. The
+/// process is only updated once, with the costs of all the inputs.
+///
+/// [provenance]: ../../../models/event/provenance/index.html
+pub fn consume_many(caller: &User, member: &Member, company: &Company, mut process: Process, inputs: Vec<ConsumeInput>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    if inputs.is_empty() {
+        Err(Error::MissingFields(vec!["inputs".into()]))?;
+    }
+    let mut anchor: Option<EventID> = None;
+    // if a resource is listed more than once, later inputs need to operate on
+    // the version left over by the earlier ones
+    let mut consumed: HashMap<ResourceID, Resource> = HashMap::new();
+    let mut mods = Modifications::new();
+    for input in inputs {
+        let ConsumeInput { id, resource, move_costs_ratio, move_measure } = input;
+        let resource = consumed.remove(resource.id()).unwrap_or(resource);
+        let consume_mods = consume(caller, member, company, id.clone(), resource, process.clone(), move_costs_ratio, move_measure, note.clone(), now)?;
+        for consume_mod in consume_mods {
+            let (op, model) = consume_mod.into_pair();
+            match model {
+                models::Model::Event(mut event) => {
+                    event.inner_mut().set_triggered_by(anchor.clone());
+                    mods.push(op, event);
+                }
+                models::Model::Process(updated) => {
+                    process = updated;
+                }
+                models::Model::Resource(updated) => {
+                    consumed.insert(updated.id().clone(), updated.clone());
+                    mods.push(op, updated);
+                }
+                model => {
+                    mods.push(op, model);
+                }
+            }
+        }
+        if anchor.is_none() {
+            anchor = Some(id);
+        }
+    }
+    mods.push(Op::Update, process);
+    Ok(mods)
+}
 
 /// Produce a resource, transferring some or all of the costs of the originating
 /// process into the resulting resource.
 ///
 /// For instance, a process might `consume` steel and have a `work` input and
 /// then `produce` a widget.
-pub fn produce<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, process: Process, resource: Resource, move_costs_ratio: Ratio, produce_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+///
+/// The costs moved are determined by the given [CostAllocation].
+///
+/// A `lot` can be given to record which batch the produced resource belongs
+/// to (it's stored as the VF resource's `lot`). A resource can only hold one
+/// lot, so producing into a resource that already has a lot with a different
+/// batch number is an error.
+///
+/// The resource must conform to the given `spec`, and its unit must be
+/// compatible with the spec's default unit or dimensions.
+///
+/// [CostAllocation]: enum.CostAllocation.html
+pub fn produce<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, process: Process, mut resource: Resource, spec: &ResourceSpec, allocation: CostAllocation, produce_measure: T, lot: Option<dfc::ProductBatch>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateProduce)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::Produce)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    spec.check_resource(&resource)?;
+    if let Some(lot) = lot {
+        match resource.inner().lot() {
+            Some(existing) if existing.batch_number() != lot.batch_number() => Err(Error::LotMismatch)?,
+            _ => {}
+        }
+        resource.inner_mut().set_lot(Some(lot));
+    }
+
+    let measure = {
+        let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+        Measure::new(produce_measure, unit)
+    };
+
+    let move_costs = match allocation {
+        CostAllocation::AllAvailable => process.costs().clone(),
+        CostAllocation::PerUnitAverage(expected_units) => {
+            if expected_units <= Decimal::zero() {
+                Err(Error::InvalidAmount(expected_units))?;
+            }
+            let produced_units = measure::to_decimal(&measure)?;
+            process.costs().clone() * Ratio::new(produced_units / expected_units)?
+        }
+        CostAllocation::Explicit(costs) => costs,
+    };
+    produce_event(company, id, process, resource, measure, move_costs, None, note, now)
+}
+
+/// Produce a byproduct (or waste) of a process.
+///
+/// This works like `produce()`, except the event is marked with the given
+/// kind of [Byproduct] and the costs moved are determined by a
+/// [ByproductAllocation]. Waste generally uses `ZeroCost`, leaving all of the
+/// process' costs for its main product, while a valuable co-product might
+/// carry a proportional share.
+///
+/// [Byproduct]: ../../../models/event/enum.Byproduct.html
+/// [ByproductAllocation]: enum.ByproductAllocation.html
+pub fn produce_byproduct<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, process: Process, resource: Resource, spec: &ResourceSpec, kind: Byproduct, allocation: ByproductAllocation, produce_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateProduce)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Produce)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
+    spec.check_resource(&resource)?;
 
     let measure = {
         let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
         Measure::new(produce_measure, unit)
     };
+    let move_costs = match allocation {
+        ByproductAllocation::ZeroCost => Costs::new(),
+        ByproductAllocation::Proportional(ratio) => process.costs().clone() * ratio,
+        ByproductAllocation::Explicit(costs) => costs,
+    };
+    produce_event(company, id, process, resource, measure, move_costs, Some(kind), note, now)
+}
 
+/// Build and process a produce event, moving `move_costs` from the process
+/// into the resource.
+fn produce_event(company: &Company, id: EventID, process: Process, resource: Resource, measure: Measure, move_costs: Costs, byproduct: Option<Byproduct>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
     let process_id = process.id().clone();
     let resource_id = resource.id().clone();
-    let move_costs = process.costs().clone() * move_costs_ratio;
-
     let state = EventProcessState::builder()
         .output_of(process)
         .resource(resource)
@@ -178,6 +371,7 @@ pub fn produce<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &
                 .map_err(|e| Error::BuilderFailed(e))?
         )
         .move_costs(Some(move_costs))
+        .byproduct(byproduct)
         .active(true)
         .created(now.clone())
         .updated(now.clone())
@@ -212,8 +406,11 @@ pub fn produce<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &
 /// If you're trying to express some resource being "used up" (for instance
 /// screws being used to build a chair) then you'll probably want `consume`
 /// instead of `use`.
+///
+/// See `use_amortized` for a version of this transaction that derives the
+/// costs to move from the resource's amortization schedule.
 pub fn useeee(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, process: Process, move_costs_ratio: Ratio, effort_quantity: Option<Measure>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+    caller.access_check(Permission::EventCreateUse)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::Use)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -259,18 +456,36 @@ pub fn useeee(caller: &User, member: &Member, company: &Company, id: EventID, re
     Ok(mods)
 }
 
+/// Use a resource that has an amortization schedule.
+///
+/// This works exactly like `useeee`, except instead of passing in a ratio of
+/// costs to move, the ratio is derived from the `effort_quantity` and the
+/// resource's `Amortization` (for instance using a 3D printer for 3 hours out
+/// of its projected 1000 hour lifetime). The effort is recorded against the
+/// amortization schedule.
+pub fn use_amortized(caller: &User, member: &Member, company: &Company, id: EventID, mut resource: Resource, process: Process, effort_quantity: Measure, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let mut amortization = resource.amortization().clone()
+        .ok_or_else(|| Error::MissingFields(vec!["resource.amortization".into()]))?;
+    let move_costs_ratio = amortization.ratio_for(&effort_quantity)?;
+    amortization.record_use(&effort_quantity)?;
+    resource.set_amortization(Some(amortization));
+    useeee(caller, member, company, id, resource, process, move_costs_ratio, Some(effort_quantity), note, now)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         costs::Costs,
         models::{
+            commitment::CommitmentID,
             company::CompanyID,
             event::{EventError, EventID},
             lib::agent::Agent,
             occupation::OccupationID,
             process::ProcessID,
-            resource::ResourceID,
+            resource::{Amortization, AmortizationMethod, ResourceID},
+            resource_spec::{Dimensions, ResourceSpecID},
         },
         util::{self, test::{self, *}},
     };
@@ -366,6 +581,11 @@ mod tests {
         };
         test::standard_transaction_tests(&state, &testfn);
 
+        let mut state_blocked = state.clone();
+        state_blocked.model2_mut().set_blocked_on(vec![CommitmentID::new("steel delivery")]);
+        let res = testfn(&state_blocked);
+        assert_eq!(res, Err(Error::ProcessBlocked("steel delivery".into())));
+
         let mods = testfn(&state).unwrap().into_vec();
         assert_eq!(mods.len(), 3);
         let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
@@ -415,6 +635,75 @@ mod tests {
         assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
     }
 
+    #[test]
+    fn can_consume_many() {
+        let now = util::time::now();
+        let id1 = EventID::create();
+        let id2 = EventID::create();
+        let id3 = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Consume], &now);
+        let steel = make_resource(&ResourceID::new("steel"), state.company().id(), &Measure::new(num!(10), Unit::Kilogram), &Costs::new_with_labor("miner", 100), &now);
+        let screws = make_resource(&ResourceID::new("screws"), state.company().id(), &Measure::new(num!(100), Unit::One), &Costs::new_with_labor("machinist", 50), &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new_with_labor("assembler", 10), &now);
+        state.model = Some(process);
+
+        let testfn = |state: &TestState<Process, Process>| {
+            let inputs = vec![
+                ConsumeInput::new(id1.clone(), steel.clone(), Ratio::new(num!(0.2)).unwrap(), 2),
+                ConsumeInput::new(id2.clone(), screws.clone(), Ratio::new(num!(0.1)).unwrap(), 10),
+                ConsumeInput::new(id3.clone(), steel.clone(), Ratio::new(num!(0.5)).unwrap(), 4),
+            ];
+            consume_many(state.user(), state.member(), state.company(), state.model().clone(), inputs, Some("widget #1".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 7);
+        let event1 = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let steel2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let event2 = mods[2].clone().expect_op::<Event>(Op::Create).unwrap();
+        let screws2 = mods[3].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let event3 = mods[4].clone().expect_op::<Event>(Op::Create).unwrap();
+        let steel3 = mods[5].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let process2 = mods[6].clone().expect_op::<Process>(Op::Update).unwrap();
+
+        assert_eq!(event1.id(), &id1);
+        assert_eq!(event1.inner().triggered_by(), &None);
+        assert_eq!(event1.inner().note(), &Some("widget #1".into()));
+        assert_eq!(event2.id(), &id2);
+        assert_eq!(event2.inner().triggered_by(), &Some(id1.clone()));
+        assert_eq!(event3.id(), &id3);
+        assert_eq!(event3.inner().triggered_by(), &Some(id1.clone()));
+        assert_eq!(event3.move_costs(), &Some(Costs::new_with_labor("miner", 40)));
+
+        assert_eq!(steel2.inner().accounting_quantity(), &Some(Measure::new(num!(8), Unit::Kilogram)));
+        assert_eq!(steel2.costs(), &Costs::new_with_labor("miner", 80));
+        assert_eq!(steel3.inner().accounting_quantity(), &Some(Measure::new(num!(4), Unit::Kilogram)));
+        assert_eq!(steel3.costs(), &Costs::new_with_labor("miner", 40));
+        assert_eq!(screws2.inner().accounting_quantity(), &Some(Measure::new(num!(90), Unit::One)));
+        assert_eq!(screws2.costs(), &Costs::new_with_labor("machinist", 45));
+
+        let mut costs = Costs::new_with_labor("assembler", 10);
+        costs.track_labor("miner", 60);
+        costs.track_labor("machinist", 5);
+        assert_eq!(process2.costs(), &costs);
+
+        // if one input fails, they all do
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::new("zing"));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::Event(EventError::ProcessOwnerMismatch)));
+        let inputs = vec![
+            ConsumeInput::new(id1.clone(), steel.clone(), Ratio::new(num!(0.2)).unwrap(), 2),
+            ConsumeInput::new(id2.clone(), screws.clone(), Ratio::new(num!(0.1)).unwrap(), 200),
+        ];
+        let res = consume_many(state.user(), state.member(), state.company(), state.model().clone(), inputs, None, &now);
+        assert_eq!(res, Err(Error::NegativeMeasurement));
+
+        let res = consume_many(state.user(), state.member(), state.company(), state.model().clone(), vec![], None, &now);
+        assert_eq!(res, Err(Error::MissingFields(vec!["inputs".into()])));
+    }
+
     #[test]
     fn can_produce() {
         let now = util::time::now();
@@ -426,13 +715,13 @@ mod tests {
         costs.track_labor("homemaker", num!(89.3));
         let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &costs, &now);
         let resource = make_resource(&ResourceID::new("widget"), state.company().id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("homemaker", 157), &now);
-        let move_costs_ratio = Ratio::new(num!(0.5777)).unwrap();
-        let costs_to_move = process.costs().clone() * move_costs_ratio.clone();
+        let spec = make_resource_spec(&ResourceSpecID::new("6969"), state.company().id(), "widget", &now);
+        let costs_to_move = process.costs().clone() * Ratio::new(num!(0.5777)).unwrap();
         state.model = Some(process);
         state.model2 = Some(resource);
 
         let testfn = |state: &TestState<Process, Resource>| {
-            produce(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), move_costs_ratio.clone(), 8, Some("memo".into()), &now)
+            produce(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), &spec, CostAllocation::Explicit(costs_to_move.clone()), 8, None, Some("memo".into()), &now)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -482,6 +771,144 @@ mod tests {
         state4.model2_mut().set_in_custody_of(CompanyID::new("ziggy").into());
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
+
+        // a resource produced by weight needs a spec that knows its weight
+        let mut state5 = state.clone();
+        state5.model2_mut().inner_mut().set_accounting_quantity(Some(Measure::new(num!(1500), Unit::Gram)));
+        state5.model2_mut().inner_mut().set_onhand_quantity(Some(Measure::new(num!(1500), Unit::Gram)));
+        let mut spec2 = spec.clone();
+        spec2.inner_mut().set_default_unit_of_resource(Some(Unit::One));
+        let res = produce(state5.user(), state5.member(), state5.company(), id.clone(), state5.model().clone(), state5.model2().clone(), &spec2, CostAllocation::Explicit(costs_to_move.clone()), 8, None, None, &now);
+        assert_eq!(res, Err(Error::ResourceSpecUnitMismatch("Gram".into())));
+        spec2.set_dimensions(Some(Dimensions::new(Some(Measure::new(num!(2), Unit::Kilogram)), None).unwrap()));
+        let res = produce(state5.user(), state5.member(), state5.company(), id.clone(), state5.model().clone(), state5.model2().clone(), &spec2, CostAllocation::Explicit(costs_to_move.clone()), 8, None, None, &now);
+        assert!(res.is_ok());
+
+        // and the resource has to conform to the spec in the first place
+        let spec3 = make_resource_spec(&ResourceSpecID::new("gizmo"), state.company().id(), "gizmo", &now);
+        let res = produce(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), &spec3, CostAllocation::Explicit(costs_to_move.clone()), 8, None, None, &now);
+        assert_eq!(res, Err(Error::ResourceSpecMismatch));
+    }
+
+    #[test]
+    fn produce_cost_allocations() {
+        let now = util::time::now();
+        let state: TestState<Process, Resource> = TestState::standard(vec![CompanyPermission::Produce], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new_with_labor("machinist", num!(120)), &now);
+        let resource = make_resource(&ResourceID::new("widget"), state.company().id(), &Measure::new(num!(0), Unit::One), &Costs::new(), &now);
+        let spec = make_resource_spec(&ResourceSpecID::new("6969"), state.company().id(), "widget", &now);
+        let produce_with = |allocation: CostAllocation, amount: i64| -> Result<(Event, Process, Resource)> {
+            let mods = produce(state.user(), state.member(), state.company(), EventID::create(), process.clone(), resource.clone(), &spec, allocation, amount, None, None, &now)?.into_vec();
+            Ok((
+                mods[0].clone().expect_op::<Event>(Op::Create)?,
+                mods[1].clone().expect_op::<Process>(Op::Update)?,
+                mods[2].clone().expect_op::<Resource>(Op::Update)?,
+            ))
+        };
+
+        let (event, process2, resource2) = produce_with(CostAllocation::AllAvailable, 12).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(120))));
+        assert!(process2.costs().is_zero());
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("machinist", num!(120)));
+
+        let (event, process2, resource2) = produce_with(CostAllocation::PerUnitAverage(num!(40)), 10).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(30))));
+        assert_eq!(process2.costs(), &Costs::new_with_labor("machinist", num!(90)));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("machinist", num!(30)));
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(10), Unit::One)));
+
+        let (event, process2, _) = produce_with(CostAllocation::Explicit(Costs::new_with_labor("machinist", num!(7))), 1).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(7))));
+        assert_eq!(process2.costs(), &Costs::new_with_labor("machinist", num!(113)));
+
+        // can't produce more units than the costs are expected to cover
+        let res = produce_with(CostAllocation::PerUnitAverage(num!(5)), 10);
+        assert_eq!(res, Err(Error::InvalidRatio(num!(2))));
+        let res = produce_with(CostAllocation::PerUnitAverage(num!(0)), 10);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+    }
+
+    #[test]
+    fn can_produce_byproduct() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Produce], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "mill lumber", &Costs::new_with_labor("sawyer", num!(200)), &now);
+        let resource = make_resource(&ResourceID::new("sawdust"), state.company().id(), &Measure::new(num!(0), Unit::One), &Costs::new(), &now);
+        let spec = make_resource_spec(&ResourceSpecID::new("6969"), state.company().id(), "sawdust", &now);
+        state.model = Some(process);
+        state.model2 = Some(resource);
+
+        let produce_with = |state: &TestState<Process, Resource>, kind: Byproduct, allocation: ByproductAllocation| {
+            produce_byproduct(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), &spec, kind, allocation, 30, Some("sawdust".into()), &now)
+        };
+        let testfn = |state: &TestState<Process, Resource>| {
+            produce_with(state, Byproduct::Waste, ByproductAllocation::ZeroCost)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        // no costs move, so the process is left alone
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Produce);
+        assert_eq!(event.inner().output_of(), &Some(state.model().id().clone()));
+        assert_eq!(event.inner().note(), &Some("sawdust".into()));
+        assert_eq!(event.byproduct(), &Some(Byproduct::Waste));
+        assert_eq!(event.move_costs(), &Some(Costs::new()));
+        assert_eq!(resource2.costs(), &Costs::new());
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(30), Unit::One)));
+
+        let mods = produce_with(&state, Byproduct::CoProduct, ByproductAllocation::Proportional(Ratio::new(num!(0.1)).unwrap())).unwrap().into_vec();
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource2 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event.byproduct(), &Some(Byproduct::CoProduct));
+        assert_eq!(process2.costs(), &Costs::new_with_labor("sawyer", num!(180)));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("sawyer", num!(20)));
+
+        let mods = produce_with(&state, Byproduct::CoProduct, ByproductAllocation::Explicit(Costs::new_with_labor("sawyer", num!(5)))).unwrap().into_vec();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.costs(), &Costs::new_with_labor("sawyer", num!(195)));
+        let res = produce_with(&state, Byproduct::CoProduct, ByproductAllocation::Explicit(Costs::new_with_labor("sawyer", num!(500))));
+        assert_eq!(res, Err(Error::NegativeCosts));
+
+        let spec2 = make_resource_spec(&ResourceSpecID::new("lumber"), state.company().id(), "lumber", &now);
+        let res = produce_byproduct(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), &spec2, Byproduct::Waste, ByproductAllocation::ZeroCost, 30, None, &now);
+        assert_eq!(res, Err(Error::ResourceSpecMismatch));
+    }
+
+    #[test]
+    fn lots_produce_and_expire() {
+        let now: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let later: DateTime<Utc> = "2020-07-01T00:00:00Z".parse().unwrap();
+        let state: TestState<Process, Resource> = TestState::standard(vec![CompanyPermission::Produce, CompanyPermission::Consume], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make cheese", &Costs::new_with_labor("cheesemaker", num!(100)), &now);
+        let resource = make_resource(&ResourceID::new("cheese"), state.company().id(), &Measure::new(num!(0), Unit::One), &Costs::new(), &now);
+        let spec = make_resource_spec(&ResourceSpecID::new("6969"), state.company().id(), "cheese", &now);
+        let lot = dfc::ProductBatch::builder()
+            .batch_number("2020-06-A")
+            .production_date(Some(now.clone()))
+            .expiry_date(Some("2020-06-20T00:00:00Z".parse().unwrap()))
+            .build().unwrap();
+
+        let mods = produce(state.user(), state.member(), state.company(), EventID::create(), process.clone(), resource.clone(), &spec, CostAllocation::AllAvailable, 10, Some(lot.clone()), None, &now).unwrap().into_vec();
+        let cheese = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(cheese.inner().lot(), &Some(lot.clone()));
+
+        // producing the same lot again is fine, another lot is not
+        assert!(produce(state.user(), state.member(), state.company(), EventID::create(), process.clone(), cheese.clone(), &spec, CostAllocation::PerUnitAverage(num!(20)), 2, Some(lot.clone()), None, &now).is_ok());
+        let mods = produce(state.user(), state.member(), state.company(), EventID::create(), process.clone(), cheese.clone(), &spec, CostAllocation::PerUnitAverage(num!(20)), 2, None, None, &now).unwrap().into_vec();
+        assert_eq!(mods[2].clone().expect_op::<Resource>(Op::Update).unwrap().inner().lot(), &Some(lot.clone()));
+        let res = produce(state.user(), state.member(), state.company(), EventID::create(), process.clone(), cheese.clone(), &spec, CostAllocation::PerUnitAverage(num!(20)), 2, Some(dfc::ProductBatch::builder().batch_number("2020-06-B").build().unwrap()), None, &now);
+        assert_eq!(res, Err(Error::LotMismatch));
+
+        let process2 = make_process(&ProcessID::create(), state.company().id(), "make pizza", &Costs::new(), &now);
+        assert!(consume(state.user(), state.member(), state.company(), EventID::create(), cheese.clone(), process2.clone(), Ratio::new(num!(0.1)).unwrap(), 1, None, &now).is_ok());
+        let res = consume(state.user(), state.member(), state.company(), EventID::create(), cheese.clone(), process2.clone(), Ratio::new(num!(0.1)).unwrap(), 1, None, &later);
+        assert_eq!(res, Err(Error::LotExpired("2020-06-A".into())));
     }
 
     #[test]
@@ -551,5 +978,59 @@ mod tests {
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::Event(EventError::ResourceCustodyMismatch)));
     }
+
+    #[test]
+    fn can_use_amortized() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Use], &now);
+        let mut resource = make_resource(&ResourceID::new("3d printer"), state.company().id(), &Measure::new(num!(1), Unit::One), &Costs::new_with_labor("machinist", 1000), &now);
+        resource.set_amortization(Some(Amortization::new(Measure::new(num!(500), Unit::Hour), AmortizationMethod::Linear)));
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        state.model = Some(resource);
+        state.model2 = Some(process);
+
+        let testfn_inner = |state: &TestState<Resource, Process>, hours: Decimal| {
+            use_amortized(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), Measure::new(hours, Unit::Hour), Some("printin".into()), &now)
+        };
+        let testfn = |state: &TestState<Resource, Process>| {
+            testfn_inner(state, num!(5))
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource2 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().effort_quantity(), &Some(Measure::new(num!(5), Unit::Hour)));
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", 10)));
+        assert_eq!(process2.costs(), &Costs::new_with_labor("machinist", 10));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("machinist", 990));
+        assert_eq!(resource2.amortization().as_ref().unwrap().used(), &Measure::new(num!(5), Unit::Hour));
+
+        // the next use moves the same amount of the original costs
+        let mut state2 = state.clone();
+        state2.model = Some(resource2);
+        state2.model2 = Some(process2);
+        let costs_to_move = state2.model().costs().clone() * (num!(5) / num!(495));
+        let mods = testfn(&state2).unwrap().into_vec();
+        let process3 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let resource3 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(costs_to_move.get_labor("machinist").round_dp(8), num!(10));
+        assert_eq!(process3.costs(), &(state2.model2().costs().clone() + costs_to_move.clone()));
+        assert_eq!(resource3.costs(), &(state2.model().costs().clone() - costs_to_move.clone()));
+        assert_eq!(resource3.amortization().as_ref().unwrap().used(), &Measure::new(num!(10), Unit::Hour));
+
+        let res = testfn_inner(&state, num!(-1));
+        assert_eq!(res, Err(Error::NegativeMeasurement));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_amortization(None);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::MissingFields(vec!["resource.amortization".into()])));
+    }
 }
 
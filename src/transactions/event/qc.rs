@@ -0,0 +1,266 @@
+//! Quality control lets a company record [inspections][1] of its resources.
+//!
+//! Inspections don't move any costs or change any quantities on their own.
+//! When an inspection fails, some (or all) of the resource can be quarantined:
+//! the failed quantity is moved into a separate resource via a `move` event
+//! (along with its share of the costs, so no costs are created or destroyed)
+//! and a [flag][2] is raised on the quarantined resource so it gets looked at.
+//! From there, the quarantined resource can be repaired, written off, etc.
+//!
+//! [1]: ../../../models/inspection/index.html
+//! [2]: ../../../models/flag/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        event::{EventError, EventID},
+        flag::{Flag, FlagID, FlagReason},
+        inspection::{Inspection, InspectionID, InspectionResult},
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
+        member::Member,
+        resource::Resource,
+        user::User,
+    },
+    transactions::event::{accounting, ResourceMover},
+    util::{measure, number::Ratio},
+};
+use getset::Getters;
+use om2::{Measure, NumericUnion};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+
+/// Describes how to quarantine part of a resource that failed inspection.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Quarantine {
+    /// The ID of the move event that quarantines the resource
+    event_id: EventID,
+    /// The ID of the flag raised on the quarantined resource
+    flag_id: FlagID,
+    /// The resource the failed quantity is moved into
+    resource_to: ResourceMover,
+    /// How much of the resource to quarantine (in the resource's unit)
+    quantity: NumericUnion,
+}
+
+impl Quarantine {
+    /// Create a new quarantine object
+    pub fn new<T: Into<NumericUnion>>(event_id: EventID, flag_id: FlagID, resource_to: ResourceMover, quantity: T) -> Self {
+        Self {
+            event_id,
+            flag_id,
+            resource_to,
+            quantity: quantity.into(),
+        }
+    }
+}
+
+/// Record an inspection of a resource by `inspector`, a member of the company
+/// holding the resource. The entire (accounting) quantity of the resource is
+/// considered inspected.
+///
+/// If the inspection fails, a `quarantine` can be given, which moves the failed
+/// quantity of the resource (and its proportional costs) into another resource
+/// and flags it. Quarantining requires the `MoveResource` permission in
+/// addition to `Inspect`.
+pub fn inspect(caller: &User, member: &Member, company: &Company, id: InspectionID, resource: Resource, inspector: &Member, result: InspectionResult, quarantine: Option<Quarantine>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateInspect)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::Inspect)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !inspector.is_active() || inspector.group_id() != &company.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if resource.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource".into()))?;
+    }
+    if resource.in_custody_of() != &company.agent_id() {
+        Err(EventError::ResourceCustodyMismatch)?;
+    }
+    if quarantine.is_some() && result != InspectionResult::Fail {
+        Err(Error::QuarantineRequiresFailure)?;
+    }
+
+    let inspection = Inspection::builder()
+        .id(id.clone())
+        .company_id(company.id().clone())
+        .resource_id(resource.id().clone())
+        .inspector(inspector.id().clone())
+        .quantity(resource.inner().accounting_quantity().clone())
+        .result(result)
+        .note(note.clone())
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, inspection);
+
+    if let Some(quarantine) = quarantine {
+        let Quarantine { event_id, flag_id, resource_to, quantity } = quarantine;
+        let quarantined_resource_id = match &resource_to {
+            ResourceMover::Create(resource_id) => resource_id.clone(),
+            ResourceMover::Update(resource) => resource.id().clone(),
+        };
+        // the quarantined quantity takes its share of the costs with it
+        let total = resource.inner().accounting_quantity().as_ref().ok_or(Error::ResourceMeasureMissing)?;
+        let total = measure::to_decimal(total)?;
+        let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+        let amount = measure::to_decimal(&Measure::new(quantity.clone(), unit))?;
+        if total <= Decimal::zero() {
+            Err(Error::ResourceMeasureMissing)?;
+        }
+        let ratio = Ratio::new(amount / total)?;
+        let move_mods = accounting::move_resource(caller, member, company, event_id, resource, resource_to, ratio, quantity, None, note.clone(), now)?;
+        for move_mod in move_mods.into_vec() {
+            mods.push_raw(move_mod);
+        }
+        let flag = Flag::builder()
+            .id(flag_id)
+            .company_id(company.id().clone())
+            .reason(FlagReason::Quarantined(quarantined_resource_id, id))
+            .note(note)
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))?;
+        mods.push(Op::Create, flag);
+    }
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            event::Event,
+            member::MemberID,
+            resource::ResourceID,
+        },
+        util::{self, test::{self, *}},
+    };
+    use om2::Unit;
+    use vf_rs::vf;
+
+    #[test]
+    fn can_inspect() {
+        let now = util::time::now();
+        let id = InspectionID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Inspect], &now);
+        let resource = make_resource(&ResourceID::new("lettuce"), state.company().id(), &Measure::new(num!(20), Unit::Kilogram), &Costs::new_with_labor("farmer", num!(100)), &now);
+        let inspector = state.member().clone();
+        state.model = Some(resource);
+
+        let testfn = |state: &TestState<Resource, Resource>| {
+            inspect(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &inspector, InspectionResult::Pass, None, Some("looks crisp".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let inspection = mods[0].clone().expect_op::<Inspection>(Op::Create).unwrap();
+        assert_eq!(inspection.id(), &id);
+        assert_eq!(inspection.company_id(), state.company().id());
+        assert_eq!(inspection.resource_id(), state.model().id());
+        assert_eq!(inspection.inspector(), inspector.id());
+        assert_eq!(inspection.quantity(), &Some(Measure::new(num!(20), Unit::Kilogram)));
+        assert_eq!(inspection.result(), &InspectionResult::Pass);
+        assert_eq!(inspection.note(), &Some("looks crisp".into()));
+        assert_eq!(inspection.active(), &true);
+        assert_eq!(inspection.created(), &now);
+
+        let res = inspect(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &inspector, InspectionResult::Fail, None, None, &now).unwrap().into_vec();
+        assert_eq!(res.len(), 1);
+        let inspection = res[0].clone().expect_op::<Inspection>(Op::Create).unwrap();
+        assert_eq!(inspection.result(), &InspectionResult::Fail);
+
+        // inspectors must be members of the company
+        let mut inspector2 = inspector.clone();
+        inspector2.set_id(MemberID::create());
+        inspector2.inner_mut().set_object(CompanyID::create().into());
+        let res = inspect(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &inspector2, InspectionResult::Pass, None, None, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_in_custody_of(CompanyID::new("ziggy").into());
+        assert_eq!(testfn(&state2), Err(Error::Event(EventError::ResourceCustodyMismatch)));
+
+        // can't quarantine something that passed
+        let quarantine = Quarantine::new(EventID::create(), FlagID::create(), ResourceMover::Create(ResourceID::create()), 5);
+        let res = inspect(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &inspector, InspectionResult::Pass, Some(quarantine), None, &now);
+        assert_eq!(res, Err(Error::QuarantineRequiresFailure));
+    }
+
+    #[test]
+    fn can_quarantine() {
+        let now = util::time::now();
+        let id = InspectionID::create();
+        let move_id = EventID::create();
+        let flag_id = FlagID::create();
+        let quarantine_id = ResourceID::new("lettuce-quarantine");
+        let mut state = TestState::standard(vec![CompanyPermission::Inspect, CompanyPermission::MoveResource], &now);
+        let costs = Costs::new_with_labor("farmer", num!(100));
+        let resource = make_resource(&ResourceID::new("lettuce"), state.company().id(), &Measure::new(num!(20), Unit::Kilogram), &costs, &now);
+        let inspector = state.member().clone();
+        state.model = Some(resource);
+
+        let testfn = |state: &TestState<Resource, Resource>| {
+            let quarantine = Quarantine::new(move_id.clone(), flag_id.clone(), ResourceMover::Create(quarantine_id.clone()), 5);
+            inspect(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &inspector, InspectionResult::Fail, Some(quarantine), Some("wilted".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let inspection = mods[0].clone().expect_op::<Inspection>(Op::Create).unwrap();
+        let move_event = mods[1].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let quarantined = mods[3].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let flag = mods[4].clone().expect_op::<Flag>(Op::Create).unwrap();
+
+        assert_eq!(inspection.id(), &id);
+        assert_eq!(inspection.result(), &InspectionResult::Fail);
+        assert_eq!(move_event.id(), &move_id);
+        assert_eq!(move_event.inner().action(), &vf::Action::Move);
+
+        // costs are split with the quantity, not created or destroyed
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(15), Unit::Kilogram)));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("farmer", num!(75)));
+        assert_eq!(quarantined.id(), &quarantine_id);
+        assert_eq!(quarantined.inner().accounting_quantity(), &Some(Measure::new(num!(5), Unit::Kilogram)));
+        assert_eq!(quarantined.costs(), &Costs::new_with_labor("farmer", num!(25)));
+        assert_eq!(resource2.costs().clone() + quarantined.costs().clone(), costs);
+
+        assert_eq!(flag.id(), &flag_id);
+        assert_eq!(flag.company_id(), state.company().id());
+        assert_eq!(flag.reason(), &FlagReason::Quarantined(quarantine_id.clone(), id.clone()));
+        assert_eq!(flag.note(), &Some("wilted".into()));
+        assert!(flag.is_open());
+
+        // quarantining needs move permissions
+        let mut state2 = state.clone();
+        state2.member_mut().set_permissions(vec![CompanyPermission::Inspect]);
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+
+        // can't quarantine more than we have
+        let quarantine = Quarantine::new(move_id.clone(), flag_id.clone(), ResourceMover::Create(quarantine_id.clone()), 30);
+        let res = inspect(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &inspector, InspectionResult::Fail, Some(quarantine), None, &now);
+        assert_eq!(res, Err(Error::InvalidRatio(num!(1.5))));
+    }
+}
@@ -6,6 +6,7 @@
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
+    costs::Costs,
     error::{Error, Result},
     models::{
         Op,
@@ -18,7 +19,7 @@ use crate::{
             agent::Agent,
             basis_model::Model,
         },
-        process::Process,
+        process::{Process, ProcessID},
         user::User,
     },
     util::number::Ratio,
@@ -26,31 +27,9 @@ use crate::{
 use url::Url;
 use vf_rs::vf;
 
-/// Provide a service to another agent, moving costs along the way.
-pub fn deliver_service(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, id: EventID, process_from: Process, process_to: Process, move_costs_ratio: Ratio, agreed_in: Option<Url>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
-    member.access_check(caller.id(), company_from.id(), CompanyPermission::DeliverService)?;
-    if !company_from.is_active() {
-        Err(Error::ObjectIsInactive("company".into()))?;
-    }
-    if !company_to.is_active() {
-        Err(Error::ObjectIsInactive("company".into()))?;
-    }
-    if !agreement.has_participant(&company_from.agent_id()) || !agreement.has_participant(&company_from.agent_id()) {
-        // can't create an event for an agreement you are not party to
-        Err(Error::InsufficientPrivileges)?;
-    }
-
-    let process_from_id = process_from.id().clone();
-    let process_to_id = process_to.id().clone();
-    let move_costs = process_from.costs().clone() * move_costs_ratio;
-
-    let state = EventProcessState::builder()
-        .output_of(process_from)
-        .input_of(process_to)
-        .build()
-        .map_err(|e| Error::BuilderFailed(e))?;
-    let event = Event::builder()
+/// Build a `deliver-service` event between two processes.
+fn service_event(id: EventID, company_from: &Company, company_to: &Company, agreement: &Agreement, process_from_id: ProcessID, process_to_id: ProcessID, move_costs: Costs, agreed_in: Option<Url>, note: Option<String>, now: &DateTime<Utc>) -> Result<Event> {
+    Event::builder()
         .id(id)
         .inner(
             vf::EconomicEvent::builder()
@@ -71,7 +50,43 @@ pub fn deliver_service(caller: &User, member: &Member, company_from: &Company, c
         .created(now.clone())
         .updated(now.clone())
         .build()
+        .map_err(|e| Error::BuilderFailed(e))
+}
+
+/// Provide a service to another agent, moving costs along the way.
+///
+/// If the agreement has a `document_hash`, the same hash must be given here.
+pub fn deliver_service(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, id: EventID, process_from: Process, process_to: Process, move_costs_ratio: Ratio, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let move_costs = process_from.costs().clone() * move_costs_ratio;
+    deliver_service_with_costs(caller, member, company_from, company_to, agreement, id, process_from, process_to, move_costs, agreed_in, document_hash, note, now)
+}
+
+/// Like `deliver_service()`, but moves a fixed set of costs instead of a ratio
+/// of the providing process' costs.
+pub(crate) fn deliver_service_with_costs(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, id: EventID, process_from: Process, process_to: Process, move_costs: Costs, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateDeliverService)?;
+    member.access_check(caller.id(), company_from.id(), CompanyPermission::DeliverService)?;
+    if !company_from.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !company_to.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !agreement.has_participant(&company_from.agent_id()) || !agreement.has_participant(&company_from.agent_id()) {
+        // can't create an event for an agreement you are not party to
+        Err(Error::InsufficientPrivileges)?;
+    }
+    agreement.check_document_hash(document_hash.as_ref())?;
+
+    let process_from_id = process_from.id().clone();
+    let process_to_id = process_to.id().clone();
+
+    let state = EventProcessState::builder()
+        .output_of(process_from)
+        .input_of(process_to)
+        .build()
         .map_err(|e| Error::BuilderFailed(e))?;
+    let event = service_event(id, company_from, company_to, agreement, process_from_id, process_to_id, move_costs, agreed_in, note, now)?;
 
     let evmods = event.process(state, now)?.into_vec();
     let mut mods = Modifications::new();
@@ -82,6 +97,65 @@ pub fn deliver_service(caller: &User, member: &Member, company_from: &Company, c
     Ok(mods)
 }
 
+/// Provide a service to another agent via an intermediary (for instance, a
+/// logistics co-op delivering a service on behalf of another company).
+///
+/// The costs being moved (`move_costs_ratio` of `process_from`'s costs) are
+/// split between the broker's process and the final receiver's process: the
+/// broker receives `broker_ratio` of the moved costs, and the receiver gets
+/// the rest. This creates two `deliver-service` events, one for each leg.
+///
+/// All three companies must be participants in the agreement, and if the
+/// agreement has a `document_hash` the same hash must be given here.
+pub fn deliver_service_brokered(caller: &User, member: &Member, company_from: &Company, company_broker: &Company, company_to: &Company, agreement: &Agreement, id_broker: EventID, id_to: EventID, process_from: Process, process_broker: Process, process_to: Process, move_costs_ratio: Ratio, broker_ratio: Ratio, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateDeliverService)?;
+    member.access_check(caller.id(), company_from.id(), CompanyPermission::DeliverService)?;
+    for company in &[company_from, company_broker, company_to] {
+        if !company.is_active() {
+            Err(Error::ObjectIsInactive("company".into()))?;
+        }
+        if !agreement.has_participant(&company.agent_id()) {
+            // can't create an event for an agreement you are not party to
+            Err(Error::InsufficientPrivileges)?;
+        }
+    }
+    agreement.check_document_hash(document_hash.as_ref())?;
+
+    let process_from_id = process_from.id().clone();
+    let process_broker_id = process_broker.id().clone();
+    let process_to_id = process_to.id().clone();
+    let move_costs = process_from.costs().clone() * move_costs_ratio;
+    let move_costs_broker = move_costs.clone() * broker_ratio;
+    let move_costs_to = move_costs - move_costs_broker.clone();
+
+    let state_broker = EventProcessState::builder()
+        .output_of(process_from)
+        .input_of(process_broker)
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let event_broker = service_event(id_broker, company_from, company_broker, agreement, process_from_id.clone(), process_broker_id, move_costs_broker, agreed_in.clone(), note.clone(), now)?;
+    let mut evmods_broker = event_broker.process(state_broker, now)?.into_vec().into_iter();
+    let process_from = evmods_broker.next().ok_or(Error::WrongModelType).and_then(|m| m.expect_op::<Process>(Op::Update))?;
+    let process_broker = evmods_broker.next().ok_or(Error::WrongModelType).and_then(|m| m.expect_op::<Process>(Op::Update))?;
+
+    let state_to = EventProcessState::builder()
+        .output_of(process_from)
+        .input_of(process_to)
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let event_to = service_event(id_to, company_from, company_to, agreement, process_from_id, process_to_id, move_costs_to, agreed_in, note, now)?;
+    let evmods_to = event_to.process(state_to, now)?.into_vec();
+
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, event_broker);
+    mods.push(Op::Create, event_to);
+    mods.push(Op::Update, process_broker);
+    for evmod in evmods_to {
+        mods.push_raw(evmod);
+    }
+    Ok(mods)
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -117,7 +191,7 @@ mod tests {
         state.model2 = Some(process_to);
 
         let testfn_inner = |state: &TestState<Process, Process>, company_from: &Company, company_to: &Company, agreement: &Agreement| {
-            deliver_service(state.user(), state.member(), company_from, company_to, agreement, id.clone(), state.model().clone(), state.model2().clone(), move_costs_ratio.clone(), Some(agreed_in.clone()), Some("making planks lol".into()), &now)
+            deliver_service(state.user(), state.member(), company_from, company_to, agreement, id.clone(), state.model().clone(), state.model2().clone(), move_costs_ratio.clone(), Some(agreed_in.clone()), None, Some("making planks lol".into()), &now)
         };
         let testfn_from = |state: &TestState<Process, Process>| {
             testfn_inner(state, state.company(), &company_to, &agreement)
@@ -174,9 +248,85 @@ mod tests {
         let res = testfn_inner(&state, &company_from, &company_to, &agreement2);
         assert_eq!(res, Err(Error::InsufficientPrivileges));
 
+        // if the agreement has a document hash, we need to match it
+        let mut agreement3 = agreement.clone();
+        agreement3.set_document_hash(Some(Agreement::hash_document(b"make some planks")));
+        let res = testfn_inner(&state, &company_from, &company_to, &agreement3);
+        assert_eq!(res, Err(Error::MissingFields(vec!["document_hash".into()])));
+        let res = deliver_service(state.user(), state.member(), &company_from, &company_to, &agreement3, id.clone(), state.model().clone(), state.model2().clone(), move_costs_ratio.clone(), Some(agreed_in.clone()), Some(Agreement::hash_document(b"make some boards")), None, &now);
+        assert_eq!(res, Err(Error::AgreementDocumentMismatch));
+        let mods = deliver_service(state.user(), state.member(), &company_from, &company_to, &agreement3, id.clone(), state.model().clone(), state.model2().clone(), move_costs_ratio.clone(), Some(agreed_in.clone()), Some(Agreement::hash_document(b"make some planks")), None, &now).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+
         let mut state5 = state.clone();
         state5.company = Some(company_to.clone());
         test::deleted_company_tester(&state5, &testfn_to);
     }
-}
 
+    #[test]
+    fn can_deliver_service_brokered() {
+        let now = util::time::now();
+        let id_broker = EventID::create();
+        let id_to = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::DeliverService], &now);
+        let company_from = state.company().clone();
+        let company_broker = make_company(&CompanyID::create(), "larry's logistics co-op", &now);
+        let company_to = make_company(&CompanyID::create(), "jinkey's skateboards", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![company_from.agent_id(), company_broker.agent_id(), company_to.agent_id()], "order 1234", "deliver some legal advice", &now);
+        let occupation_id = OccupationID::new("lawyer");
+        let process_from = make_process(&ProcessID::create(), company_from.id(), "various lawyerings", &Costs::new_with_labor(occupation_id.clone(), num!(100)), &now);
+        let process_broker = make_process(&ProcessID::create(), company_broker.id(), "courier", &Costs::new_with_labor("courier", num!(10)), &now);
+        let process_to = make_process(&ProcessID::create(), company_to.id(), "employee legal agreement drafting", &Costs::new(), &now);
+        state.model = Some(process_from);
+        state.model2 = Some(process_to);
+
+        let testfn_inner = |state: &TestState<Process, Process>, company_from: &Company, agreement: &Agreement| {
+            deliver_service_brokered(state.user(), state.member(), company_from, &company_broker, &company_to, agreement, id_broker.clone(), id_to.clone(), state.model().clone(), process_broker.clone(), state.model2().clone(), Ratio::new(num!(0.5)).unwrap(), Ratio::new(num!(0.2)).unwrap(), None, None, Some("special delivery".into()), &now)
+        };
+        let testfn = |state: &TestState<Process, Process>| {
+            testfn_inner(state, state.company(), &agreement)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let event_broker = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let event_to = mods[1].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process_broker2 = mods[2].clone().expect_op::<Process>(Op::Update).unwrap();
+        let process_from2 = mods[3].clone().expect_op::<Process>(Op::Update).unwrap();
+        let process_to2 = mods[4].clone().expect_op::<Process>(Op::Update).unwrap();
+
+        assert_eq!(event_broker.id(), &id_broker);
+        assert_eq!(event_broker.inner().output_of(), &Some(state.model().id().clone()));
+        assert_eq!(event_broker.inner().input_of(), &Some(process_broker.id().clone()));
+        assert_eq!(event_broker.inner().receiver().clone(), company_broker.agent_id());
+        assert_eq!(event_broker.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(10))));
+        assert_eq!(event_to.id(), &id_to);
+        assert_eq!(event_to.inner().output_of(), &Some(state.model().id().clone()));
+        assert_eq!(event_to.inner().input_of(), &Some(state.model2().id().clone()));
+        assert_eq!(event_to.inner().receiver().clone(), company_to.agent_id());
+        assert_eq!(event_to.inner().note(), &Some("special delivery".into()));
+        assert_eq!(event_to.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(40))));
+
+        let mut broker_costs = Costs::new_with_labor("courier", num!(10));
+        broker_costs.track_labor(occupation_id.clone(), num!(10));
+        assert_eq!(process_broker2.id(), process_broker.id());
+        assert_eq!(process_broker2.costs(), &broker_costs);
+        assert_eq!(process_from2.id(), state.model().id());
+        assert_eq!(process_from2.costs(), &Costs::new_with_labor(occupation_id.clone(), num!(50)));
+        assert_eq!(process_to2.id(), state.model2().id());
+        assert_eq!(process_to2.costs(), &Costs::new_with_labor(occupation_id.clone(), num!(40)));
+
+        // the broker has to be party to the agreement
+        let mut agreement2 = agreement.clone();
+        agreement2.set_participants(vec![company_from.agent_id(), company_to.agent_id()]);
+        let res = testfn_inner(&state, state.company(), &agreement2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // can't move costs from a process you don't own
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::new("zing").into());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::Event(EventError::ProcessOwnerMismatch)));
+    }
+}
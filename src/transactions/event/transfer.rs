@@ -3,17 +3,40 @@
 //!
 //! If you're looking for internal transfers, see the [accounting transactions.][1]
 //!
+//! Resources from an expired lot cannot be transferred.
+//!
+//! If the sending company has a [transfer policy][2], it should be passed in,
+//! and transfers that break the policy's limits are refused.
+//!
+//! If the agreement has a [document hash][3], the same hash must be passed in
+//! as `document_hash`.
+//!
+//! Transfers can also happen in two phases, giving the receiving company a say
+//! in what enters its books: [transfer_initiate] creates a pending transfer
+//! event (one with a beginning but no end) that moves nothing but holds the
+//! quantity being sent on the sender's resource. A member of the receiving
+//! company then either [accepts][transfer_accept] it, which completes the
+//! transfer, or [rejects][transfer_reject] it. The sender can
+//! [cancel][transfer_cancel] a transfer that hasn't been accepted yet.
+//!
 //! [1]: ../accounting/index.html
+//! [2]: ../../../models/transfer_policy/index.html
+//! [3]: ../../../models/agreement/index.html
+//! [transfer_initiate]: fn.transfer_initiate.html
+//! [transfer_accept]: fn.transfer_accept.html
+//! [transfer_reject]: fn.transfer_reject.html
+//! [transfer_cancel]: fn.transfer_cancel.html
 
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
+    costs::Costs,
     error::{Error, Result},
     models::{
         Op,
         Modifications,
         agreement::Agreement,
-        event::{Event, EventID, EventProcessState},
+        event::{Event, EventError, EventID, EventProcessState},
         lib::{
             agent::Agent,
             basis_model::Model,
@@ -21,6 +44,7 @@ use crate::{
         company::{Company, Permission as CompanyPermission},
         member::Member,
         resource::Resource,
+        transfer_policy::TransferPolicy,
         user::User,
     },
     transactions::event::ResourceMover,
@@ -32,8 +56,15 @@ use vf_rs::vf;
 
 /// Transfer a resource (custody and ownership) from one company to another,
 /// moving a set of costs with it.
-pub fn transfer<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+pub fn transfer<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    transfer_with_costs(caller, member, company_from, company_to, agreement, policy, id, resource_from, resource_to, move_costs, move_measure, agreed_in, document_hash, note, now)
+}
+
+/// Like `transfer()`, but moves a fixed set of costs instead of a ratio of the
+/// resource's costs.
+pub(crate) fn transfer_with_costs<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs: Costs, move_measure: T, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateTransfer)?;
     member.access_check(caller.id(), company_from.id(), CompanyPermission::Transfer)?;
     if !company_from.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -45,13 +76,17 @@ pub fn transfer<T: Into<NumericUnion>>(caller: &User, member: &Member, company_f
         // can't create an event for an agreement you are not party to
         Err(Error::InsufficientPrivileges)?;
     }
+    agreement.check_document_hash(document_hash.as_ref())?;
+    resource_from.check_lot(now)?;
     let measure = {
         let unit = resource_from.get_unit().ok_or(Error::ResourceMeasureMissing)?;
         Measure::new(move_measure, unit)
     };
 
     let resource_id = resource_from.id().clone();
-    let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    if let Some(policy) = policy {
+        policy.check(company_from, company_to, agreement, &move_costs)?;
+    }
 
     let mut statebuilder = EventProcessState::builder()
         .resource(resource_from);
@@ -113,8 +148,8 @@ pub fn transfer<T: Into<NumericUnion>>(caller: &User, member: &Member, company_f
 
 /// Transfer ownership (but not custody) of a resource from one company to
 /// another, moving a set of costs with it.
-pub fn transfer_all_rights<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+pub fn transfer_all_rights<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateTransferAllRights)?;
     member.access_check(caller.id(), company_from.id(), CompanyPermission::TransferAllRights)?;
     if !company_from.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -126,6 +161,8 @@ pub fn transfer_all_rights<T: Into<NumericUnion>>(caller: &User, member: &Member
         // can't create an event for an agreement you are not party to
         Err(Error::InsufficientPrivileges)?;
     }
+    agreement.check_document_hash(document_hash.as_ref())?;
+    resource_from.check_lot(now)?;
     let measure = {
         let unit = resource_from.get_unit().ok_or(Error::ResourceMeasureMissing)?;
         Measure::new(move_measure, unit)
@@ -133,6 +170,9 @@ pub fn transfer_all_rights<T: Into<NumericUnion>>(caller: &User, member: &Member
 
     let resource_id = resource_from.id().clone();
     let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    if let Some(policy) = policy {
+        policy.check(company_from, company_to, agreement, &move_costs)?;
+    }
 
     let mut statebuilder = EventProcessState::builder()
         .resource(resource_from);
@@ -194,8 +234,15 @@ pub fn transfer_all_rights<T: Into<NumericUnion>>(caller: &User, member: &Member
 
 /// Transfer custody (but not ownership) of a resource from one company to
 /// another, moving a set of costs with it.
-pub fn transfer_custody<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+pub fn transfer_custody<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    transfer_custody_with_costs(caller, member, company_from, company_to, agreement, policy, id, resource_from, resource_to, move_costs, move_measure, agreed_in, document_hash, note, now)
+}
+
+/// Like `transfer_custody()`, but moves a fixed set of costs instead of a ratio of the
+/// resource's costs.
+pub(crate) fn transfer_custody_with_costs<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_costs: Costs, move_measure: T, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateTransferCustody)?;
     member.access_check(caller.id(), company_from.id(), CompanyPermission::TransferCustody)?;
     if !company_from.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
@@ -207,13 +254,17 @@ pub fn transfer_custody<T: Into<NumericUnion>>(caller: &User, member: &Member, c
         // can't create an event for an agreement you are not party to
         Err(Error::InsufficientPrivileges)?;
     }
+    agreement.check_document_hash(document_hash.as_ref())?;
+    resource_from.check_lot(now)?;
     let measure = {
         let unit = resource_from.get_unit().ok_or(Error::ResourceMeasureMissing)?;
         Measure::new(move_measure, unit)
     };
 
     let resource_id = resource_from.id().clone();
-    let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    if let Some(policy) = policy {
+        policy.check(company_from, company_to, agreement, &move_costs)?;
+    }
 
     let mut statebuilder = EventProcessState::builder()
         .resource(resource_from);
@@ -262,6 +313,198 @@ pub fn transfer_custody<T: Into<NumericUnion>>(caller: &User, member: &Member, c
     Ok(mods)
 }
 
+/// Start a two-phase transfer of a resource (custody and ownership) from one
+/// company to another.
+///
+/// This creates a pending transfer event that doesn't move any costs or
+/// resources until the receiving company [accepts it][transfer_accept]. The
+/// costs being moved are fixed here, and the sending company's transfer
+/// policy is checked against them. The quantity being sent is held on
+/// `resource_from` so it can't be moved or used up while the transfer is
+/// pending. Returns the created event and an update of `resource_from`.
+///
+/// [transfer_accept]: fn.transfer_accept.html
+pub fn transfer_initiate<T: Into<NumericUnion>>(caller: &User, member: &Member, company_from: &Company, company_to: &Company, agreement: &Agreement, policy: Option<&TransferPolicy>, id: EventID, mut resource_from: Resource, move_costs_ratio: Ratio, move_measure: T, agreed_in: Option<Url>, document_hash: Option<[u8; 32]>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateTransfer)?;
+    member.access_check(caller.id(), company_from.id(), CompanyPermission::Transfer)?;
+    if !company_from.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !company_to.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !agreement.has_participant(&company_from.agent_id()) || !agreement.has_participant(&company_to.agent_id()) {
+        // can't create an event for an agreement you are not party to
+        Err(Error::InsufficientPrivileges)?;
+    }
+    agreement.check_document_hash(document_hash.as_ref())?;
+    if resource_from.in_custody_of() != &company_from.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    resource_from.check_lot(now)?;
+    let measure = {
+        let unit = resource_from.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+        Measure::new(move_measure, unit)
+    };
+
+    let move_costs = resource_from.costs().clone() * move_costs_ratio;
+    if let Some(policy) = policy {
+        policy.check(company_from, company_to, agreement, &move_costs)?;
+    }
+    resource_from.hold(&measure)?;
+    resource_from.set_updated(now.clone());
+
+    let event = Event::builder()
+        .id(id)
+        .inner(
+            vf::EconomicEvent::builder()
+                .action(vf::Action::Transfer)
+                .agreed_in(agreed_in)
+                .has_beginning(Some(now.clone()))
+                .note(note)
+                .provider(company_from.id().clone())
+                .realization_of(Some(agreement.id().clone()))
+                .receiver(company_to.id().clone())
+                .resource_inventoried_as(Some(resource_from.id().clone()))
+                .resource_quantity(Some(measure))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(Some(move_costs))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, event);
+    mods.push(Op::Update, resource_from);
+    Ok(mods)
+}
+
+/// Make sure the given event is a pending transfer that hasn't been deleted.
+fn check_pending_transfer(subject: &Event) -> Result<()> {
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("event".into()))?;
+    }
+    if subject.inner().action() != &vf::Action::Transfer || subject.inner().has_beginning().is_none() || subject.inner().has_end().is_some() {
+        Err(EventError::NotPending)?;
+    }
+    Ok(())
+}
+
+/// Release the hold a pending transfer placed on the resource being sent.
+fn release_pending_transfer(subject: &Event, resource_from: &mut Resource) -> Result<()> {
+    if subject.inner().resource_inventoried_as().as_ref() != Some(resource_from.id()) {
+        Err(EventError::MismatchedResourceID)?;
+    }
+    let measure = subject.inner().resource_quantity().as_ref()
+        .ok_or(EventError::MissingEventMeasure)?;
+    resource_from.release(measure)
+}
+
+/// Accept a pending transfer sent to the receiving company, completing it.
+///
+/// The receiving company decides where the resource lands on its books via
+/// `resource_to`. `resource_from` should be the *current* version of the
+/// resource being sent. Returns an update of the event along with the
+/// resource and company modifications a regular transfer would create.
+pub fn transfer_accept(caller: &User, member: &Member, company_from: &Company, company_to: &Company, mut subject: Event, mut resource_from: Resource, resource_to: ResourceMover, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventUpdate)?;
+    member.access_check(caller.id(), company_to.id(), CompanyPermission::TransferAccept)?;
+    if !company_from.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !company_to.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    check_pending_transfer(&subject)?;
+    if subject.inner().receiver() != &company_to.agent_id() || subject.inner().provider() != &company_from.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    resource_from.check_lot(now)?;
+    release_pending_transfer(&subject, &mut resource_from)?;
+
+    let mut statebuilder = EventProcessState::builder()
+        .resource(resource_from);
+    let resource_to_id = match resource_to {
+        ResourceMover::Create(resource_id) => resource_id,
+        ResourceMover::Update(resource) => {
+            let resource_id = resource.id().clone();
+            statebuilder = statebuilder.to_resource(resource);
+            resource_id
+        }
+    };
+    let state = statebuilder
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+
+    let move_costs = subject.move_costs().clone().unwrap_or_default();
+    subject.inner_mut().set_to_resource_inventoried_as(Some(resource_to_id));
+    subject.inner_mut().set_has_end(Some(now.clone()));
+    subject.set_updated(now.clone());
+
+    let evmods = subject.process(state, now)?.into_vec();
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, subject);
+    for evmod in evmods {
+        mods.push_raw(evmod);
+    }
+
+    let mut company_from_new = company_from.clone();
+    let mut company_to_new = company_to.clone();
+    company_from_new.transfer_costs_to(&mut company_to_new, move_costs)?;
+    mods.push(Op::Update, company_from_new);
+    mods.push(Op::Update, company_to_new);
+    Ok(mods)
+}
+
+/// Reject a pending transfer sent to the receiving company. Nothing was moved
+/// when the transfer was initiated, so this deletes the pending event and
+/// releases the hold on the sender's resource (`resource_from`, which should
+/// be the *current* version of the resource).
+pub fn transfer_reject(caller: &User, member: &Member, company_to: &Company, mut subject: Event, mut resource_from: Resource, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventUpdate)?;
+    member.access_check(caller.id(), company_to.id(), CompanyPermission::TransferAccept)?;
+    if !company_to.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    check_pending_transfer(&subject)?;
+    if subject.inner().receiver() != &company_to.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    release_pending_transfer(&subject, &mut resource_from)?;
+    resource_from.set_updated(now.clone());
+    subject.set_deleted(Some(now.clone()));
+    let mut mods = Modifications::new();
+    mods.push(Op::Delete, subject);
+    mods.push(Op::Update, resource_from);
+    Ok(mods)
+}
+
+/// Cancel a pending transfer the sending company initiated, before the
+/// receiving company accepts it. Deletes the pending event and releases the
+/// hold on `resource_from` (which should be the *current* version of the
+/// resource).
+pub fn transfer_cancel(caller: &User, member: &Member, company_from: &Company, mut subject: Event, mut resource_from: Resource, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventUpdate)?;
+    member.access_check(caller.id(), company_from.id(), CompanyPermission::Transfer)?;
+    if !company_from.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    check_pending_transfer(&subject)?;
+    if subject.inner().provider() != &company_from.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    release_pending_transfer(&subject, &mut resource_from)?;
+    resource_from.set_updated(now.clone());
+    subject.set_deleted(Some(now.clone()));
+    let mut mods = Modifications::new();
+    mods.push(Op::Delete, subject);
+    mods.push(Op::Update, resource_from);
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,13 +513,17 @@ mod tests {
         models::{
             agreement::AgreementID,
             company::CompanyID,
-            event::{EventID, EventError},
+            event::EventID,
             lib::agent::Agent,
+            member::MemberID,
+            occupation::OccupationID,
             resource::ResourceID,
+            transfer_policy::TransferPolicyID,
         },
         util::{self, test::{self, *}},
     };
     use om2::Unit;
+    use vf_rs::dfc;
 
     #[test]
     fn can_transfer() {
@@ -297,7 +544,7 @@ mod tests {
         state.model2 = Some(resource_to);
 
         let testfn_inner = |state: &TestState<Resource, Resource>, company_from: &Company, company_to: &Company, agreement: &Agreement, resource_to: ResourceMover| {
-            transfer(state.user(), state.member(), company_from, company_to, &agreement, id.clone(), state.model().clone(), resource_to, move_costs_ratio.clone(), 8, Some(agreed_in.clone()), Some("giving jinkey some post-capitalist planks".into()), &now)
+            transfer(state.user(), state.member(), company_from, company_to, &agreement, None, id.clone(), state.model().clone(), resource_to, move_costs_ratio.clone(), 8, Some(agreed_in.clone()), None, Some("giving jinkey some post-capitalist planks".into()), &now)
         };
         let testfn_update = |state: &TestState<Resource, Resource>| {
             testfn_inner(state, state.company(), &company_to, &agreement, ResourceMover::Update(state.model2().clone()))
@@ -435,6 +682,20 @@ mod tests {
         state6.company_mut().set_max_costs(company_to.total_costs().credits().clone());
         let res = testfn_update_to(&state6);
         assert_eq!(res, Err(Error::MaxCostsReached));
+
+        // lots follow the resource, and expired lots can't be transferred
+        let mut state7 = state.clone();
+        let lot = dfc::ProductBatch::builder()
+            .batch_number("PL-77")
+            .expiry_date(Some(now.clone() + chrono::Duration::days(30)))
+            .build().unwrap();
+        state7.model_mut().inner_mut().set_lot(Some(lot.clone()));
+        let mods = testfn_create(&state7).unwrap().into_vec();
+        let resource_created = mods[2].clone().expect_op::<Resource>(Op::Create).unwrap();
+        assert_eq!(resource_created.inner().lot(), &Some(lot.clone()));
+        state7.model_mut().inner_mut().set_lot(Some(dfc::ProductBatch::builder().batch_number("PL-76").expiry_date(Some(now.clone())).build().unwrap()));
+        let res = testfn_update(&state7);
+        assert_eq!(res, Err(Error::LotExpired("PL-76".into())));
     }
 
     #[test]
@@ -456,7 +717,7 @@ mod tests {
         state.model2 = Some(resource_to);
 
         let testfn_inner = |state: &TestState<Resource, Resource>, company_from: &Company, company_to: &Company, agreement: &Agreement, resource_to: ResourceMover| {
-            transfer_all_rights(state.user(), state.member(), &company_from, &company_to, &agreement, id.clone(), state.model().clone(), resource_to, move_costs_ratio.clone(), 8, Some(agreed_in.clone()), Some("note blah blah".into()), &now)
+            transfer_all_rights(state.user(), state.member(), &company_from, &company_to, &agreement, None, id.clone(), state.model().clone(), resource_to, move_costs_ratio.clone(), 8, Some(agreed_in.clone()), None, Some("note blah blah".into()), &now)
         };
         let testfn_update = |state: &TestState<Resource, Resource>| {
             testfn_inner(state, state.company(), &company_to, &agreement, ResourceMover::Update(state.model2().clone()))
@@ -609,7 +870,7 @@ mod tests {
         state.model2 = Some(resource_to);
 
         let testfn_inner = |state: &TestState<Resource, Resource>, company_from: &Company, company_to: &Company, agreement: &Agreement, resource_to: ResourceMover| {
-            transfer_custody(state.user(), state.member(), &company_from, &company_to, &agreement, id.clone(), state.model().clone(), resource_to, move_costs_ratio.clone(), 8, Some(agreed_in.clone()), Some("nomnomnom".into()), &now)
+            transfer_custody(state.user(), state.member(), &company_from, &company_to, &agreement, None, id.clone(), state.model().clone(), resource_to, move_costs_ratio.clone(), 8, Some(agreed_in.clone()), None, Some("nomnomnom".into()), &now)
         };
         let testfn_update = |state: &TestState<Resource, Resource>| {
             testfn_inner(state, state.company(), &company_to, &agreement, ResourceMover::Update(state.model2().clone()))
@@ -721,5 +982,215 @@ mod tests {
         state5.company = Some(company_to.clone());
         test::deleted_company_tester(&state5, &testfn_update_to);
     }
-}
 
+    #[test]
+    fn transfers_check_policy() {
+        let now = util::time::now();
+        let state: TestState<Resource, Resource> = TestState::standard(vec![CompanyPermission::Transfer, CompanyPermission::TransferAllRights, CompanyPermission::TransferCustody], &now);
+        let mut company_from = state.company().clone();
+        let company_to = make_company(&CompanyID::create(), "jinkey's skateboards", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![company_from.agent_id(), company_to.agent_id()], "order 1234", "gotta get some planks", &now);
+        let resource_from = make_resource(&ResourceID::new("plank"), company_from.id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("homemaker", 150), &now);
+        company_from.set_total_costs(resource_from.costs().clone());
+        let mut policy = TransferPolicy::builder()
+            .id(TransferPolicyID::create())
+            .company_id(company_from.id().clone())
+            .max_credits(Some(num!(100)))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let half = Ratio::new(num!(0.5)).unwrap();
+        let all = Ratio::new(num!(1)).unwrap();
+        let resource_to = || ResourceMover::Create(ResourceID::create());
+
+        let res = transfer(state.user(), state.member(), &company_from, &company_to, &agreement, Some(&policy), EventID::create(), resource_from.clone(), resource_to(), half.clone(), 7, None, None, None, &now);
+        assert!(res.is_ok());
+        let res = transfer(state.user(), state.member(), &company_from, &company_to, &agreement, Some(&policy), EventID::create(), resource_from.clone(), resource_to(), all.clone(), 15, None, None, None, &now);
+        assert_eq!(res, Err(Error::TransferPolicyViolation("transfer of 150 credits is over the limit of 100".into())));
+        let res = transfer_all_rights(state.user(), state.member(), &company_from, &company_to, &agreement, Some(&policy), EventID::create(), resource_from.clone(), resource_to(), all.clone(), 15, None, None, None, &now);
+        assert_eq!(res, Err(Error::TransferPolicyViolation("transfer of 150 credits is over the limit of 100".into())));
+        let res = transfer_custody(state.user(), state.member(), &company_from, &company_to, &agreement, Some(&policy), EventID::create(), resource_from.clone(), resource_to(), all.clone(), 15, None, None, None, &now);
+        assert_eq!(res, Err(Error::TransferPolicyViolation("transfer of 150 credits is over the limit of 100".into())));
+
+        policy.set_max_credits(None);
+        policy.set_allowed_counterparties(Some(vec![CompanyID::new("garry's gears")]));
+        let res = transfer_custody(state.user(), state.member(), &company_from, &company_to, &agreement, Some(&policy), EventID::create(), resource_from.clone(), resource_to(), all.clone(), 15, None, None, None, &now);
+        assert_eq!(res, Err(Error::TransferPolicyViolation(format!("company {} is not an allowed counterparty", company_to.id().as_str()))));
+
+        // no policy, no limits
+        let res = transfer(state.user(), state.member(), &company_from, &company_to, &agreement, None, EventID::create(), resource_from.clone(), resource_to(), all.clone(), 15, None, None, None, &now);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn can_transfer_two_phase() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Transfer, CompanyPermission::EventFinalize], &now);
+        let mut company_from = state.company().clone();
+        let company_to = make_company(&CompanyID::create(), "jinkey's skateboards", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![company_from.agent_id(), company_to.agent_id()], "order 1234", "gotta get some planks", &now);
+        let resource_from = make_resource(&ResourceID::new("plank"), company_from.id(), &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("homemaker", 157), &now);
+        let resource_to = make_resource(&ResourceID::new("plank2"), company_to.id(), &Measure::new(num!(3), Unit::One), &Costs::new_with_labor("homemaker", 2), &now);
+        let move_costs_ratio = Ratio::new(num!(0.5)).unwrap();
+        let costs_to_move = resource_from.costs().clone() * move_costs_ratio.clone();
+        company_from.set_total_costs(costs_to_move.clone() * num!(5));
+        state.company = Some(company_from.clone());
+        state.model = Some(resource_from.clone());
+
+        let testfn_initiate = |state: &TestState<Resource, Resource>| {
+            transfer_initiate(state.user(), state.member(), state.company(), &company_to, &agreement, None, id.clone(), state.model().clone(), move_costs_ratio.clone(), 8, None, None, Some("planks, if you want them".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn_initiate);
+
+        let mods = testfn_initiate(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource_held = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Transfer);
+        assert_eq!(event.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(event.inner().has_end(), &None);
+        assert_eq!(event.inner().provider().clone(), company_from.agent_id());
+        assert_eq!(event.inner().receiver().clone(), company_to.agent_id());
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(8, Unit::One)));
+        assert_eq!(event.inner().to_resource_inventoried_as(), &None);
+        assert_eq!(event.move_costs(), &Some(costs_to_move.clone()));
+        assert_eq!(resource_held.id(), resource_from.id());
+        assert_eq!(resource_held.held(), &Some(Measure::new(8, Unit::One)));
+        assert_eq!(resource_held.inner().accounting_quantity(), resource_from.inner().accounting_quantity());
+        assert_eq!(resource_held.costs(), resource_from.costs());
+        assert_eq!(resource_held.updated(), &now);
+
+        // both companies have to be in on the agreement
+        let mut agreement2 = agreement.clone();
+        agreement2.set_participants(vec![company_from.agent_id()]);
+        let res = transfer_initiate(state.user(), state.member(), state.company(), &company_to, &agreement2, None, id.clone(), state.model().clone(), move_costs_ratio.clone(), 8, None, None, None, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // can't hold more than the resource has, including what's already held
+        let res = transfer_initiate(state.user(), state.member(), state.company(), &company_to, &agreement, None, id.clone(), state.model().clone(), move_costs_ratio.clone(), 16, None, None, None, &now);
+        assert_eq!(res, Err(Error::ResourceQuantityHeld));
+        let res = transfer_initiate(state.user(), state.member(), state.company(), &company_to, &agreement, None, id.clone(), resource_held.clone(), move_costs_ratio.clone(), 8, None, None, None, &now);
+        assert_eq!(res, Err(Error::ResourceQuantityHeld));
+        let mods = transfer_initiate(state.user(), state.member(), state.company(), &company_to, &agreement, None, id.clone(), resource_held.clone(), move_costs_ratio.clone(), 7, None, None, None, &now).unwrap().into_vec();
+        let resource_held2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(resource_held2.held(), &Some(Measure::new(15, Unit::One)));
+
+        // held quantity can't be moved elsewhere while the transfer is pending
+        let res = transfer(state.user(), state.member(), state.company(), &company_to, &agreement, None, EventID::create(), resource_held.clone(), ResourceMover::Create(ResourceID::create()), move_costs_ratio.clone(), 8, None, None, None, &now);
+        assert_eq!(res, Err(Error::ResourceQuantityHeld));
+        let res = transfer(state.user(), state.member(), state.company(), &company_to, &agreement, None, EventID::create(), resource_held.clone(), ResourceMover::Create(ResourceID::create()), move_costs_ratio.clone(), 7, None, None, None, &now);
+        assert!(res.is_ok());
+
+        // the sender can't complete the transfer on its own
+        let process_state = EventProcessState::builder()
+            .resource(resource_held.clone())
+            .build().unwrap();
+        let res = crate::transactions::event::finalize(state.user(), state.member(), state.company(), event.clone(), now.clone(), process_state, &now);
+        assert_eq!(res, Err(Error::Event(EventError::TransferAwaitingAcceptance)));
+
+        // now the receiving company gets to weigh in
+        let mut state2: TestState<Event, Resource> = TestState::standard(vec![CompanyPermission::TransferAccept], &now);
+        state2.company = Some(company_to.clone());
+        state2.member = Some(make_member_worker(&MemberID::create(), state2.user().id(), company_to.id(), &OccupationID::create(), vec![CompanyPermission::TransferAccept], &now));
+        state2.model = Some(event.clone());
+        state2.model2 = Some(resource_to.clone());
+
+        let now2 = util::time::now();
+        let testfn_accept = |state: &TestState<Event, Resource>| {
+            transfer_accept(state.user(), state.member(), &company_from, state.company(), state.model().clone(), resource_held.clone(), ResourceMover::Update(state.model2().clone()), &now2)
+        };
+        let testfn_reject = |state: &TestState<Event, Resource>| {
+            transfer_reject(state.user(), state.member(), state.company(), state.model().clone(), resource_held.clone(), &now2)
+        };
+        test::standard_transaction_tests(&state2, &testfn_accept);
+        test::standard_transaction_tests(&state2, &testfn_reject);
+        test::double_deleted_tester(&state2, "event", &testfn_accept);
+        test::double_deleted_tester(&state2, "event", &testfn_reject);
+
+        let mods = testfn_accept(&state2).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let event2 = mods[0].clone().expect_op::<Event>(Op::Update).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let resource_to2 = mods[2].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let company_from2 = mods[3].clone().expect_op::<Company>(Op::Update).unwrap();
+        let company_to2 = mods[4].clone().expect_op::<Company>(Op::Update).unwrap();
+        assert_eq!(event2.id(), &id);
+        assert_eq!(event2.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(event2.inner().has_end(), &Some(now2.clone()));
+        assert_eq!(event2.inner().to_resource_inventoried_as(), &Some(resource_to.id().clone()));
+        assert_eq!(event2.updated(), &now2);
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(15) - num!(8), Unit::One)));
+        assert_eq!(resource2.held(), &None);
+        assert_eq!(resource2.costs(), &(resource_from.costs().clone() - costs_to_move.clone()));
+        assert_eq!(resource_to2.inner().accounting_quantity(), &Some(Measure::new(num!(8) + num!(3), Unit::One)));
+        assert_eq!(resource_to2.in_custody_of(), &company_to.agent_id());
+        assert_eq!(resource_to2.costs(), &(resource_to.costs().clone() + costs_to_move.clone()));
+        assert_eq!(company_from2.total_costs(), &(company_from.total_costs().clone() - costs_to_move.clone()));
+        assert_eq!(company_to2.total_costs(), &(company_to.total_costs().clone() + costs_to_move.clone()));
+
+        // the receiver can put the resource somewhere new on its books
+        let mods = transfer_accept(state2.user(), state2.member(), &company_from, state2.company(), event.clone(), resource_held.clone(), ResourceMover::Create(ResourceID::new("plank3")), &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let resource_created = mods[2].clone().expect_op::<Resource>(Op::Create).unwrap();
+        assert_eq!(resource_created.id(), &ResourceID::new("plank3"));
+        assert_eq!(resource_created.costs(), &costs_to_move);
+
+        // the resource being sent has to match the event
+        let res = transfer_accept(state2.user(), state2.member(), &company_from, state2.company(), event.clone(), resource_to.clone(), ResourceMover::Create(ResourceID::new("plank3")), &now2);
+        assert_eq!(res, Err(Error::Event(EventError::MismatchedResourceID)));
+
+        // can't accept twice
+        let res = transfer_accept(state2.user(), state2.member(), &company_from, state2.company(), event2.clone(), resource2.clone(), ResourceMover::Update(resource_to.clone()), &now2);
+        assert_eq!(res, Err(Error::Event(EventError::NotPending)));
+        let res = transfer_reject(state2.user(), state2.member(), state2.company(), event2.clone(), resource2.clone(), &now2);
+        assert_eq!(res, Err(Error::Event(EventError::NotPending)));
+
+        let mods = testfn_reject(&state2).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let event3 = mods[0].clone().expect_op::<Event>(Op::Delete).unwrap();
+        let resource3 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event3.deleted(), &Some(now2.clone()));
+        assert_eq!(resource3.held(), &None);
+        assert_eq!(resource3.inner().accounting_quantity(), resource_from.inner().accounting_quantity());
+        assert_eq!(resource3.updated(), &now2);
+
+        // only the receiver can accept/reject
+        let mut state3 = state2.clone();
+        state3.model_mut().inner_mut().set_receiver(CompanyID::new("zing").into());
+        let res = testfn_accept(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let res = testfn_reject(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // the sender can cancel the transfer before it's accepted
+        let mut state4: TestState<Event, Resource> = state2.clone();
+        state4.company = Some(company_from.clone());
+        state4.user = state.user.clone();
+        state4.member = state.member.clone();
+        let testfn_cancel = |state: &TestState<Event, Resource>| {
+            transfer_cancel(state.user(), state.member(), state.company(), state.model().clone(), resource_held.clone(), &now2)
+        };
+        test::standard_transaction_tests(&state4, &testfn_cancel);
+        test::double_deleted_tester(&state4, "event", &testfn_cancel);
+
+        let mods = testfn_cancel(&state4).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let event4 = mods[0].clone().expect_op::<Event>(Op::Delete).unwrap();
+        let resource4 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        assert_eq!(event4.deleted(), &Some(now2.clone()));
+        assert_eq!(resource4.held(), &None);
+        assert_eq!(resource4.updated(), &now2);
+
+        let res = transfer_cancel(state4.user(), state4.member(), state4.company(), event2.clone(), resource2.clone(), &now2);
+        assert_eq!(res, Err(Error::Event(EventError::NotPending)));
+
+        // only the sender can cancel
+        let mut state5 = state4.clone();
+        state5.model_mut().inner_mut().set_provider(CompanyID::new("zing").into());
+        let res = testfn_cancel(&state5);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
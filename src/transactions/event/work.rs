@@ -2,40 +2,57 @@
 //! and hourly labor) get attributed to processes (and as a result resources).
 //! They also act as the systemic marker for paying company members. Record
 //! labor, get paid.
+//!
+//! Work can be recorded all at once via `work()`, or as a timesheet session
+//! using `clock_in()` (which creates a pending work event) and `clock_out()`
+//! (which finalizes it, at which point the hours are known and the labor costs
+//! are attributed).
+//!
+//! Companies logging work for many members at once can use `work_bulk()`,
+//! which records a batch of work events against one process and updates the
+//! process a single time.
+//!
+//! All of these optionally take a [WorkHoursCap] (generally the one in the
+//! network config) along with the worker's other work sessions. Work that
+//! would push the worker over the cap within the cap's period is rejected, and
+//! a worker who has already hit the cap can't clock in.
+//!
+//! They also optionally take the network's [sanity policy][1], which the labor
+//! costs of the work are checked against before they're attributed.
+//!
+//! [WorkHoursCap]: ../../../models/config/struct.WorkHoursCap.html
+//! [1]: ../../../costs/validate/index.html
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use crate::{
     access::Permission,
-    costs::Costs,
+    costs::{Costs, validate::SanityPolicy},
     error::{Error, Result},
     models::{
+        self,
         Op,
         Modifications,
+        config::WorkHoursCap,
         event::{Event, EventID, EventProcessState},
         company::{Company, Permission as CompanyPermission},
         member::Member,
-        lib::basis_model::Model,
+        event::EventError,
+        lib::{agent::Agent, basis_model::Model},
+        occupation::{Occupation, OccupationID},
         process::Process,
         user::User,
     },
 };
+use getset::Getters;
 use om2::{Measure, Unit};
 use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use vf_rs::vf;
 
-/// Create a new work event with the option of passing hourly data, wage data,
-/// or both.
-///
-/// Most of the time you'll want to pass both wage (`wage_cost`) and hourly
-/// (`begin`/`end`) data together, unless you're truly tracking them separately.
-/// Sometimes you might not know or care to track detailed hourly data (as with
-/// salary) but it can be estimated to some extent using data in the worker's
-/// Member record.
-///
-/// Note that this creates a full work event with a defined start and end. This
-/// function cannot create pending work events.
-pub fn work(caller: &User, member: &Member, company: &Company, id: EventID, worker: Member, process: Process, wage_cost: Option<Decimal>, begin: DateTime<Utc>, end: DateTime<Utc>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
-    caller.access_check(Permission::EventCreate)?;
+/// Make sure the caller is allowed to record work for the given worker.
+fn check_work_access(caller: &User, member: &Member, company: &Company, worker: &Member) -> Result<()> {
+    caller.access_check(Permission::EventCreateWork)?;
     // if we're recording our own work event, we can just check the regular
     // `Work` permission, otherwise we need admin privs
     if member.id() == worker.id() {
@@ -46,17 +63,165 @@ pub fn work(caller: &User, member: &Member, company: &Company, id: EventID, work
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
+    Ok(())
+}
 
-    let effort = {
-        let milliseconds = end.timestamp_millis() - begin.timestamp_millis();
-        let hours = Decimal::from(milliseconds) / Decimal::from(1000 * 60 * 60);
-        Measure::new(hours, Unit::Hour)
-    };
-    let occupation_id = worker.occupation_id().ok_or(Error::MemberMustBeWorker)?.clone();
-    let costs = match wage_cost {
+/// Make sure the given occupation is one the worker holds (and is still
+/// around), returning the occupation's ID.
+fn check_occupation(worker: &Member, occupation: &Occupation) -> Result<OccupationID> {
+    worker.occupation_id().ok_or(Error::MemberMustBeWorker)?;
+    if !worker.has_occupation(occupation.id()) {
+        Err(Error::OccupationMismatch)?;
+    }
+    if occupation.is_deleted() {
+        Err(Error::ObjectIsDeleted("occupation".into()))?;
+    }
+    Ok(occupation.id().clone())
+}
+
+/// Build the costs for a work event. The costs name the occupation the labor
+/// is claimed under, which is how the event processor knows which of the
+/// worker's occupations to attribute the labor to. Without a wage we name the
+/// occupation with the hours worked instead (unless it's the worker's primary
+/// occupation, which the processor falls back on).
+fn work_costs(worker: &Member, occupation_id: OccupationID, wage_cost: Option<Decimal>, hours: &Decimal) -> Costs {
+    match wage_cost {
         Some(val) => Costs::new_with_labor(occupation_id, val),
-        None => Costs::new(),
+        None if worker.occupation_id() == Some(&occupation_id) => Costs::new(),
+        None => Costs::new_with_labor_hours(occupation_id, hours.clone()),
+    }
+}
+
+/// Get the number of hours between two times.
+fn hours_between(begin: &DateTime<Utc>, end: &DateTime<Utc>) -> Decimal {
+    let milliseconds = end.timestamp_millis() - begin.timestamp_millis();
+    Decimal::from(milliseconds) / Decimal::from(1000 * 60 * 60)
+}
+
+/// Determine the wage cost for a stretch of work. If a `wage_cost` is given it
+/// is used as-is, otherwise we derive it from the hourly rate of the worker's
+/// compensation in effect when the work began. If the worker has no
+/// compensation at that time (or it can't be converted to an hourly rate) only
+/// hours are tracked.
+fn wage_cost_for(worker: &Member, wage_cost: Option<Decimal>, begin: &DateTime<Utc>, hours: &Decimal) -> Result<Option<Decimal>> {
+    if wage_cost.is_some() {
+        return Ok(wage_cost);
+    }
+    match worker.compensation_at(begin) {
+        Some(compensation) => Ok(compensation.hourly_rate()?.map(|rate| rate * hours.clone())),
+        None => Ok(None),
+    }
+}
+
+/// Check a session running from `begin` to `end` (open-ended if `end` is
+/// `None`) against a worker's other sessions. Sessions that are still pending
+/// are treated as running indefinitely. The event given by `skip` (generally
+/// the session being clocked out of) is ignored.
+pub(crate) fn check_overlap(sessions: &[Event], worker: &Member, skip: Option<&EventID>, begin: &DateTime<Utc>, end: Option<&DateTime<Utc>>) -> Result<()> {
+    let worker_id = worker.agent_id();
+    for session in sessions {
+        if Some(session.id()) == skip || session.is_deleted() {
+            continue;
+        }
+        if session.inner().action() != &vf::Action::Work || session.inner().provider() != &worker_id {
+            continue;
+        }
+        let session_begin = match session.inner().has_beginning() {
+            Some(x) => x,
+            None => continue,
+        };
+        let starts_before_end = end.map(|end| session_begin < end).unwrap_or(true);
+        let ends_after_begin = session.inner().has_end().as_ref().map(|session_end| session_end > begin).unwrap_or(true);
+        if starts_before_end && ends_after_begin {
+            Err(EventError::WorkSessionOverlap)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find how many hours a worker has left under a work hours cap, in the cap's
+/// period ending at `end`. Only finished sessions count, and only the part of
+/// each that falls within the period. The event given by `skip` (generally the
+/// session being clocked out of) is ignored. Returns `None` if there's no cap.
+fn hours_left(cap: Option<&WorkHoursCap>, sessions: &[Event], worker: &Member, skip: Option<&EventID>, end: &DateTime<Utc>) -> Result<Option<Decimal>> {
+    let cap = match cap {
+        Some(cap) => cap,
+        None => return Ok(None),
     };
+    let period_start = end.clone() - Duration::seconds(cap.period_seconds()?);
+    let worker_id = worker.agent_id();
+    let mut worked = Decimal::zero();
+    for session in sessions {
+        if Some(session.id()) == skip || session.is_deleted() {
+            continue;
+        }
+        if session.inner().action() != &vf::Action::Work || session.inner().provider() != &worker_id {
+            continue;
+        }
+        let (session_begin, session_end) = match (session.inner().has_beginning(), session.inner().has_end()) {
+            (Some(session_begin), Some(session_end)) => (session_begin, session_end),
+            _ => continue,
+        };
+        let session_begin = std::cmp::max(session_begin, &period_start);
+        let session_end = std::cmp::min(session_end, end);
+        if session_end > session_begin {
+            worked += hours_between(session_begin, session_end);
+        }
+    }
+    Ok(Some(cap.max_hours() - worked))
+}
+
+/// Make sure `hours` of work ending at `end` doesn't push the worker over the
+/// given work hours cap (if any).
+fn check_hours_cap(cap: Option<&WorkHoursCap>, sessions: &[Event], worker: &Member, skip: Option<&EventID>, end: &DateTime<Utc>, hours: &Decimal) -> Result<()> {
+    if let Some(left) = hours_left(cap, sessions, worker, skip, end)? {
+        if hours > &left {
+            Err(EventError::WorkHoursCapExceeded(format!("{} hours of work with only {} hours left in the period", hours.normalize(), std::cmp::max(left, Decimal::zero()).normalize())))?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a new work event with the option of passing hourly data, wage data,
+/// or both.
+///
+/// Most of the time you'll want to pass both wage (`wage_cost`) and hourly
+/// (`begin`/`end`) data together, unless you're truly tracking them separately.
+/// Sometimes you might not know or care to track detailed hourly data (as with
+/// salary) but it can be estimated to some extent using data in the worker's
+/// Member record.
+///
+/// If `wage_cost` is `None`, the wage is derived from the worker's
+/// compensation in effect at `begin` (if they have one), so changing a worker's
+/// compensation never changes the costs of work already recorded.
+///
+/// The worker's `occupation` must be passed in. If the occupation has a
+/// registered wage rate, the `wage_cost` being claimed must be consistent with
+/// that rate given the hours worked.
+///
+/// Work can't go into a process that's [blocked] on unfulfilled commitments.
+///
+/// If a `hours_cap` is given, the worker's other work events should be passed
+/// in as `sessions` so we can make sure this work doesn't put the worker over
+/// the cap.
+///
+/// Note that this creates a full work event with a defined start and end. This
+/// function cannot create pending work events.
+///
+/// [blocked]: ../../../models/process/struct.Process.html#method.check_blocked
+pub fn work(caller: &User, member: &Member, company: &Company, id: EventID, worker: Member, occupation: &Occupation, process: Process, wage_cost: Option<Decimal>, begin: DateTime<Utc>, end: DateTime<Utc>, hours_cap: Option<&WorkHoursCap>, sanity_policy: Option<&SanityPolicy>, sessions: &[Event], note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_work_access(caller, member, company, &worker)?;
+    process.check_blocked()?;
+
+    let hours = hours_between(&begin, &end);
+    check_hours_cap(hours_cap, sessions, &worker, None, &end, &hours)?;
+    let effort = Measure::new(hours, Unit::Hour);
+    let occupation_id = check_occupation(&worker, occupation)?;
+    let wage_cost = wage_cost_for(&worker, wage_cost, &begin, &hours)?;
+    if let (Some(wage_rate), Some(wage)) = (occupation.wage_rate(), wage_cost.as_ref()) {
+        wage_rate.check(wage, &hours)?;
+    }
+    let costs = work_costs(&worker, occupation_id, wage_cost, &hours);
     let process_id = process.id().clone();
     let member_id = worker.id().clone();
     let agreement = worker.agreement().clone();
@@ -65,7 +230,8 @@ pub fn work(caller: &User, member: &Member, company: &Company, id: EventID, work
         .input_of(process)
         .provider(worker)
         .build()
-        .map_err(|e| Error::BuilderFailed(e))?;
+        .map_err(|e| Error::BuilderFailed(e))?
+        .with_sanity_policy(sanity_policy);
     let event = Event::builder()
         .id(id)
         .inner(
@@ -97,16 +263,200 @@ pub fn work(caller: &User, member: &Member, company: &Company, id: EventID, work
     Ok(mods)
 }
 
+/// One worker's entry in a [work_bulk] batch.
+///
+/// [work_bulk]: fn.work_bulk.html
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct WorkEntry {
+    /// The ID of the work event for this entry
+    id: EventID,
+    /// The member who did the work
+    worker: Member,
+    /// The occupation the work was done under
+    occupation: Occupation,
+    /// The wage cost of the work (see [work])
+    ///
+    /// [work]: fn.work.html
+    wage_cost: Option<Decimal>,
+    /// When the work began
+    begin: DateTime<Utc>,
+    /// When the work ended
+    end: DateTime<Utc>,
+}
+
+impl WorkEntry {
+    /// Create a new work entry
+    pub fn new(id: EventID, worker: Member, occupation: Occupation, wage_cost: Option<Decimal>, begin: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            worker,
+            occupation,
+            wage_cost,
+            begin,
+            end,
+        }
+    }
+}
+
+/// Record work for a batch of workers against the same process.
+///
+/// Each entry gets its own work event (checked exactly as [work] would check
+/// it), but the process is only updated once, with the labor costs of the
+/// entire batch. Either every entry is recorded or (if any of them fail) none
+/// are. Entries count towards the `hours_cap` of the entries after them.
+///
+/// [work]: fn.work.html
+pub fn work_bulk(caller: &User, member: &Member, company: &Company, mut process: Process, entries: Vec<WorkEntry>, hours_cap: Option<&WorkHoursCap>, sanity_policy: Option<&SanityPolicy>, sessions: &[Event], note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    if entries.is_empty() {
+        Err(Error::MissingFields(vec!["entries".into()]))?;
+    }
+    let mut sessions = sessions.to_vec();
+    let mut mods = Modifications::new();
+    for entry in entries {
+        let WorkEntry { id, worker, occupation, wage_cost, begin, end } = entry;
+        let work_mods = work(caller, member, company, id, worker, &occupation, process.clone(), wage_cost, begin, end, hours_cap, sanity_policy, &sessions, note.clone(), now)?;
+        for work_mod in work_mods {
+            let (op, model) = work_mod.into_pair();
+            match model {
+                models::Model::Process(updated) => {
+                    process = updated;
+                }
+                models::Model::Event(event) => {
+                    sessions.push(event.clone());
+                    mods.push(op, event);
+                }
+                model => {
+                    mods.push(op, model);
+                }
+            }
+        }
+    }
+    mods.push(Op::Update, process);
+    Ok(mods)
+}
+
+/// Start a work session, creating a pending work event (one with a beginning
+/// but no end) against the given process.
+///
+/// The worker's other work events should be passed in as `sessions` so we can
+/// make sure the new session doesn't overlap any of them. A worker can only
+/// have one session open at a time, and if a `hours_cap` is given, a worker
+/// who has no hours left under it can't clock in.
+pub fn clock_in(caller: &User, member: &Member, company: &Company, id: EventID, worker: &Member, occupation: &Occupation, process: &Process, sessions: &[Event], hours_cap: Option<&WorkHoursCap>, begin: DateTime<Utc>, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_work_access(caller, member, company, worker)?;
+    check_occupation(worker, occupation)?;
+    if process.company_id() != company.id() {
+        Err(EventError::ProcessOwnerMismatch)?;
+    }
+    if !process.is_active() {
+        Err(EventError::InputOnInactiveProcess)?;
+    }
+    process.check_blocked()?;
+    check_overlap(sessions, worker, None, &begin, None)?;
+    if let Some(left) = hours_left(hours_cap, sessions, worker, None, &begin)? {
+        if left <= Decimal::zero() {
+            Err(EventError::WorkHoursCapExceeded("no hours left in the period".into()))?;
+        }
+    }
+
+    let event = Event::builder()
+        .id(id)
+        .inner(
+            vf::EconomicEvent::builder()
+                .action(vf::Action::Work)
+                .agreed_in(worker.agreement().clone())
+                .has_beginning(Some(begin))
+                .input_of(Some(process.id().clone()))
+                .note(note)
+                .provider(worker.id().clone())
+                .receiver(company.id().clone())
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, event))
+}
+
+/// End a work session started with `clock_in()`.
+///
+/// This sets the event's end time and effort (in hours), applies the claimed
+/// `wage_cost` (checked against the occupation's wage rate, if any, and
+/// derived from the worker's compensation if not given) and then
+/// processes the event, attributing the labor to the session's process.
+///
+/// As with `clock_in()`, the worker's other work events should be passed in as
+/// `sessions` so we can make sure the finished session doesn't overlap any of
+/// them (or, if a `hours_cap` is given, put the worker over the cap).
+pub fn clock_out(caller: &User, member: &Member, company: &Company, mut subject: Event, worker: Member, occupation: &Occupation, process: Process, wage_cost: Option<Decimal>, sessions: &[Event], hours_cap: Option<&WorkHoursCap>, sanity_policy: Option<&SanityPolicy>, end: DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_work_access(caller, member, company, &worker)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("event".into()))?;
+    }
+    if subject.inner().action() != &vf::Action::Work {
+        Err(EventError::NotWorkEvent)?;
+    }
+    if subject.inner().provider() != &worker.agent_id() {
+        Err(EventError::MismatchedProviderID)?;
+    }
+    if subject.inner().receiver() != &company.agent_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let begin = match (subject.inner().has_beginning(), subject.inner().has_end()) {
+        (Some(begin), None) => begin.clone(),
+        _ => Err(EventError::NotPending)?,
+    };
+    if end <= begin {
+        Err(EventError::DateEndBeforeBegin)?;
+    }
+    check_overlap(sessions, &worker, Some(subject.id()), &begin, Some(&end))?;
+
+    let hours = hours_between(&begin, &end);
+    check_hours_cap(hours_cap, sessions, &worker, Some(subject.id()), &end, &hours)?;
+    let occupation_id = check_occupation(&worker, occupation)?;
+    let wage_cost = wage_cost_for(&worker, wage_cost, &begin, &hours)?;
+    if let (Some(wage_rate), Some(wage)) = (occupation.wage_rate(), wage_cost.as_ref()) {
+        wage_rate.check(wage, &hours)?;
+    }
+    let costs = work_costs(&worker, occupation_id, wage_cost, &hours);
+    subject.inner_mut().set_effort_quantity(Some(Measure::new(hours, Unit::Hour)));
+    subject.inner_mut().set_has_end(Some(end));
+    subject.set_move_costs(Some(costs));
+    subject.set_updated(now.clone());
+
+    let state = EventProcessState::builder()
+        .input_of(process)
+        .provider(worker)
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?
+        .with_sanity_policy(sanity_policy);
+    let evmods = subject.process(state, now)?.into_vec();
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, subject);
+    for evmod in evmods {
+        mods.push_raw(evmod);
+    }
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         models::{
+            commitment::CommitmentID,
             company::CompanyID,
             member::*,
             event::{Event, EventID, EventError},
             lib::agent::Agent,
+            occupation::{OccupationID, WageRate},
             process::ProcessID,
+            user::UserID,
         },
         util::test::{self, *},
     };
@@ -120,14 +470,20 @@ mod tests {
         let occupation_id = state.member().occupation_id().unwrap().clone();
         let worker = state.member().clone();
         let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new_with_labor(occupation_id.clone(), num!(177.5)), &now);
+        let occupation = make_occupation(&occupation_id, "machinist", None, &now);
         state.model = Some(worker);
         state.model2 = Some(process);
 
         let testfn = |state: &TestState<Member, Process>| {
-            work(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), state.model2().clone(), Some(num!(78.4)), now.clone(), now2.clone(), Some("just doing some work".into()), &now2)
+            work(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &occupation, state.model2().clone(), Some(num!(78.4)), now.clone(), now2.clone(), None, None, &[], Some("just doing some work".into()), &now2)
         };
         test::standard_transaction_tests(&state, &testfn);
 
+        let mut state_blocked = state.clone();
+        state_blocked.model2_mut().set_blocked_on(vec![CommitmentID::new("steel delivery")]);
+        let res = testfn(&state_blocked);
+        assert_eq!(res, Err(Error::ProcessBlocked("steel delivery".into())));
+
         let mods = testfn(&state).unwrap().into_vec();
         assert_eq!(mods.len(), 2);
         let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
@@ -200,6 +556,372 @@ mod tests {
         state4.model_mut().set_class(MemberClass::Company(MemberCompany::new()));
         let res = testfn(&state4);
         assert_eq!(res, Err(Error::MemberMustBeWorker));
+
+        // the network's sanity policy catches an outlandish wage claim
+        let work_with_policy = |policy: &SanityPolicy| {
+            work(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), &occupation, state.model2().clone(), Some(num!(78.4)), now.clone(), now2.clone(), None, Some(policy), &[], None, &now2)
+        };
+        let res = work_with_policy(&SanityPolicy::new(None, Some(num!(50)), None, vec![]));
+        assert_eq!(res, Err(Error::CostsRejected(format!("labor entry {} of 78.4 is over the limit of 50", occupation_id.as_str()))));
+        assert!(work_with_policy(&SanityPolicy::new(None, Some(num!(100)), None, vec![])).is_ok());
+    }
+
+    #[test]
+    fn can_work_bulk() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let now2: DateTime<Utc> = "2018-06-06T06:00:00Z".parse().unwrap();
+        let id1 = EventID::create();
+        let id2 = EventID::create();
+        let id3 = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Work, CompanyPermission::WorkAdmin], &now);
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let occupation = make_occupation(&occupation_id, "machinist", None, &now);
+        let occupation2 = make_occupation(&OccupationID::new("welder"), "welder", None, &now);
+        let worker1 = state.member().clone();
+        let worker2 = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), &occupation_id, vec![], &now);
+        let worker3 = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), occupation2.id(), vec![], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new_with_labor(occupation_id.clone(), num!(10)), &now);
+        state.model = Some(process);
+
+        let entries = vec![
+            WorkEntry::new(id1.clone(), worker1.clone(), occupation.clone(), Some(num!(60)), now.clone(), now2.clone()),
+            WorkEntry::new(id2.clone(), worker2.clone(), occupation.clone(), Some(num!(50)), now.clone(), now2.clone()),
+            WorkEntry::new(id3.clone(), worker3.clone(), occupation2.clone(), Some(num!(40)), now.clone(), now2.clone()),
+        ];
+        let testfn_inner = |state: &TestState<Process, Process>, entries: Vec<WorkEntry>| {
+            work_bulk(state.user(), state.member(), state.company(), state.model().clone(), entries, None, None, &[], Some("monday shift".into()), &now2)
+        };
+        let testfn = |state: &TestState<Process, Process>| {
+            testfn_inner(state, entries.clone())
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 4);
+        let event1 = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let event2 = mods[1].clone().expect_op::<Event>(Op::Create).unwrap();
+        let event3 = mods[2].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[3].clone().expect_op::<Process>(Op::Update).unwrap();
+
+        assert_eq!(event1.id(), &id1);
+        assert_eq!(event1.inner().provider().clone(), worker1.agent_id());
+        assert_eq!(event1.inner().note(), &Some("monday shift".into()));
+        assert_eq!(event1.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(60))));
+        assert_eq!(event2.id(), &id2);
+        assert_eq!(event2.inner().provider().clone(), worker2.agent_id());
+        assert_eq!(event3.id(), &id3);
+        assert_eq!(event3.inner().provider().clone(), worker3.agent_id());
+        assert_eq!(event3.move_costs(), &Some(Costs::new_with_labor(occupation2.id().clone(), num!(40))));
+
+        let mut costs2 = Costs::new();
+        costs2.track_labor(occupation_id.clone(), num!(120));
+        costs2.track_labor_hours(occupation_id.clone(), num!(12));
+        costs2.track_labor(occupation2.id().clone(), num!(40));
+        costs2.track_labor_hours(occupation2.id().clone(), num!(6));
+        assert_eq!(process2.id(), state.model().id());
+        assert_eq!(process2.costs(), &costs2);
+
+        // if one entry fails, they all do
+        let entries2 = vec![
+            WorkEntry::new(id1.clone(), worker1.clone(), occupation.clone(), Some(num!(60)), now.clone(), now2.clone()),
+            WorkEntry::new(id3.clone(), worker3.clone(), occupation.clone(), Some(num!(40)), now.clone(), now2.clone()),
+        ];
+        let res = testfn_inner(&state, entries2);
+        assert_eq!(res, Err(Error::OccupationMismatch));
+
+        // recording others' work requires admin privileges
+        let mut state2 = state.clone();
+        state2.member_mut().set_permissions(vec![CompanyPermission::Work]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let res = testfn_inner(&state, vec![]);
+        assert_eq!(res, Err(Error::MissingFields(vec!["entries".into()])));
     }
-}
 
+    #[test]
+    fn work_checks_wage_rate() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let now2: DateTime<Utc> = "2018-06-06T06:00:00Z".parse().unwrap();
+        let mut state = TestState::standard(vec![CompanyPermission::Work], &now);
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let worker = state.member().clone();
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        state.model = Some(worker);
+        state.model2 = Some(process);
+
+        let work_with = |state: &TestState<Member, Process>, occupation: &Occupation, wage: Option<Decimal>| {
+            work(state.user(), state.member(), state.company(), EventID::create(), state.model().clone(), occupation, state.model2().clone(), wage, now.clone(), now2.clone(), None, None, &[], None, &now2)
+        };
+
+        // 6 hours at 20-25/hr, with 10% tolerance: 108 - 165
+        let occupation = make_occupation(&occupation_id, "machinist", Some(WageRate::new(num!(20), num!(25), num!(0.1)).unwrap()), &now);
+        let mods = work_with(&state, &occupation, Some(num!(135))).unwrap().into_vec();
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(135))));
+        assert!(work_with(&state, &occupation, Some(num!(108))).is_ok());
+        assert!(work_with(&state, &occupation, Some(num!(165))).is_ok());
+        assert_eq!(work_with(&state, &occupation, Some(num!(107.99))), Err(Error::WageRateMismatch));
+        assert_eq!(work_with(&state, &occupation, Some(num!(500))), Err(Error::WageRateMismatch));
+        // hours-only tracking skips the wage check
+        assert!(work_with(&state, &occupation, None).is_ok());
+
+        // no registered rate means no check
+        let occupation2 = make_occupation(&occupation_id, "machinist", None, &now);
+        assert!(work_with(&state, &occupation2, Some(num!(500))).is_ok());
+
+        let occupation3 = make_occupation(&OccupationID::create(), "ceo", None, &now);
+        assert_eq!(work_with(&state, &occupation3, Some(num!(135))), Err(Error::OccupationMismatch));
+
+        let mut occupation4 = occupation.clone();
+        occupation4.set_deleted(Some(now.clone()));
+        assert_eq!(work_with(&state, &occupation4, Some(num!(135))), Err(Error::ObjectIsDeleted("occupation".into())));
+    }
+
+    #[test]
+    fn can_clock_in_and_out() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let now2: DateTime<Utc> = "2018-06-06T06:00:00Z".parse().unwrap();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::Work], &now);
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let worker = state.member().clone();
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        let occupation = make_occupation(&occupation_id, "machinist", Some(WageRate::new(num!(20), num!(25), num!(0.1)).unwrap()), &now);
+        state.model = Some(worker);
+        state.model2 = Some(process);
+
+        let testfn_in = |state: &TestState<Member, Process>| {
+            clock_in(state.user(), state.member(), state.company(), id.clone(), state.model(), &occupation, state.model2(), &vec![], None, now.clone(), Some("morning shift".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn_in);
+
+        let mods = testfn_in(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Work);
+        assert_eq!(event.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(event.inner().has_end(), &None);
+        assert_eq!(event.inner().effort_quantity(), &None);
+        assert_eq!(event.inner().input_of(), &Some(state.model2().id().clone()));
+        assert_eq!(event.inner().provider().clone(), state.model().agent_id());
+        assert_eq!(event.inner().receiver().clone(), state.company().agent_id());
+        assert_eq!(event.move_costs(), &None);
+        assert_eq!(event.created(), &now);
+
+        // can't clock in twice
+        let sessions = vec![event.clone()];
+        let res = clock_in(state.user(), state.member(), state.company(), EventID::create(), state.model(), &occupation, state.model2(), &sessions, None, now2.clone(), None, &now2);
+        assert_eq!(res, Err(Error::Event(EventError::WorkSessionOverlap)));
+        // but another worker can
+        let mut worker2 = state.model().clone();
+        worker2.set_id(MemberID::create());
+        let mut state2 = state.clone();
+        state2.member_mut().set_permissions(vec![CompanyPermission::WorkAdmin]);
+        assert!(clock_in(state2.user(), state2.member(), state2.company(), EventID::create(), &worker2, &occupation, state2.model2(), &sessions, None, now2.clone(), None, &now2).is_ok());
+
+        let mut state3 = state.clone();
+        state3.model2_mut().set_company_id(CompanyID::new("zing"));
+        assert_eq!(testfn_in(&state3), Err(Error::Event(EventError::ProcessOwnerMismatch)));
+
+        let testfn_out = |state: &TestState<Member, Process>, event: Event, sessions: &Vec<Event>, wage: Option<Decimal>, end: DateTime<Utc>| {
+            clock_out(state.user(), state.member(), state.company(), event, state.model().clone(), &occupation, state.model2().clone(), wage, sessions, None, None, end, &now2)
+        };
+        let mods = testfn_out(&state, event.clone(), &sessions, Some(num!(135)), now2.clone()).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let event2 = mods[0].clone().expect_op::<Event>(Op::Update).unwrap();
+        assert_eq!(event2.id(), &id);
+        assert_eq!(event2.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(event2.inner().has_end(), &Some(now2.clone()));
+        assert_eq!(event2.inner().effort_quantity(), &Some(Measure::new(num!(6), Unit::Hour)));
+        assert_eq!(event2.inner().note(), &Some("morning shift".into()));
+        assert_eq!(event2.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(135))));
+        assert_eq!(event2.created(), &now);
+        assert_eq!(event2.updated(), &now2);
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let mut costs2 = Costs::new();
+        costs2.track_labor(occupation_id.clone(), num!(135));
+        costs2.track_labor_hours(occupation_id.clone(), num!(6));
+        assert_eq!(process2.costs(), &costs2);
+
+        // once clocked out, the next session can start where this one ended
+        assert!(clock_in(state.user(), state.member(), state.company(), EventID::create(), state.model(), &occupation, state.model2(), &vec![event2.clone()], None, now2.clone(), None, &now2).is_ok());
+        let later: DateTime<Utc> = "2018-06-06T05:00:00Z".parse().unwrap();
+        let res = clock_in(state.user(), state.member(), state.company(), EventID::create(), state.model(), &occupation, state.model2(), &vec![event2.clone()], None, later.clone(), None, &now2);
+        assert_eq!(res, Err(Error::Event(EventError::WorkSessionOverlap)));
+
+        // a session that ran in the middle of ours blocks clocking out
+        let mut other = event2.clone();
+        other.set_id(EventID::create());
+        other.inner_mut().set_has_beginning(Some("2018-06-06T02:00:00Z".parse().unwrap()));
+        other.inner_mut().set_has_end(Some("2018-06-06T03:00:00Z".parse().unwrap()));
+        let res = testfn_out(&state, event.clone(), &vec![event.clone(), other.clone()], Some(num!(135)), now2.clone());
+        assert_eq!(res, Err(Error::Event(EventError::WorkSessionOverlap)));
+        // unless it was deleted
+        other.set_deleted(Some(now.clone()));
+        assert!(testfn_out(&state, event.clone(), &vec![event.clone(), other.clone()], Some(num!(135)), now2.clone()).is_ok());
+
+        assert_eq!(testfn_out(&state, event2.clone(), &sessions, None, now2.clone()), Err(Error::Event(EventError::NotPending)));
+        assert_eq!(testfn_out(&state, event.clone(), &sessions, None, now.clone()), Err(Error::Event(EventError::DateEndBeforeBegin)));
+        assert_eq!(testfn_out(&state, event.clone(), &sessions, Some(num!(500)), now2.clone()), Err(Error::WageRateMismatch));
+        let mut event3 = event.clone();
+        event3.inner_mut().set_action(vf::Action::Use);
+        assert_eq!(testfn_out(&state, event3, &sessions, None, now2.clone()), Err(Error::Event(EventError::NotWorkEvent)));
+        let mut event4 = event.clone();
+        event4.set_deleted(Some(now.clone()));
+        assert_eq!(testfn_out(&state, event4, &sessions, None, now2.clone()), Err(Error::ObjectIsDeleted("event".into())));
+        let mut state4 = state.clone();
+        state4.model_mut().set_id(MemberID::create());
+        state4.member_mut().set_permissions(vec![CompanyPermission::WorkAdmin]);
+        assert_eq!(testfn_out(&state4, event.clone(), &sessions, None, now2.clone()), Err(Error::Event(EventError::MismatchedProviderID)));
+    }
+
+    #[test]
+    fn work_derives_wage_from_compensation() {
+        let jan: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let jun: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let mut state = TestState::standard(vec![CompanyPermission::Work], &jan);
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let mut history = CompensationHistory::new();
+        history.push(jan.clone(), Compensation::new_hourly(num!(20), "account"));
+        history.push(jun.clone(), Compensation::new_hourly(num!(25), "account"));
+        let mut worker_class = MemberWorker::new(occupation_id.clone(), Some(Compensation::new_hourly(num!(25), "account")));
+        worker_class.set_compensation_history(history);
+        state.member_mut().set_class(MemberClass::Worker(worker_class));
+        let worker = state.member().clone();
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &jan);
+        let occupation = make_occupation(&occupation_id, "machinist", None, &jan);
+        state.model = Some(worker);
+        state.model2 = Some(process);
+
+        let work_at = |state: &TestState<Member, Process>, begin: &str, end: &str, wage: Option<Decimal>| {
+            let begin: DateTime<Utc> = begin.parse().unwrap();
+            let end: DateTime<Utc> = end.parse().unwrap();
+            let mods = work(state.user(), state.member(), state.company(), EventID::create(), state.model().clone(), &occupation, state.model2().clone(), wage, begin, end.clone(), None, None, &[], None, &end).unwrap().into_vec();
+            mods[0].clone().expect_op::<Event>(Op::Create).unwrap()
+        };
+
+        // work before the raise is paid at the old rate
+        let event = work_at(&state, "2020-03-01T09:00:00Z", "2020-03-01T13:00:00Z", None);
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(80))));
+        let event = work_at(&state, "2020-07-01T09:00:00Z", "2020-07-01T13:00:00Z", None);
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(100))));
+        // explicit wages win
+        let event = work_at(&state, "2020-07-01T09:00:00Z", "2020-07-01T13:00:00Z", Some(num!(90)));
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(90))));
+        // no compensation in effect yet
+        let event = work_at(&state, "2019-07-01T09:00:00Z", "2019-07-01T13:00:00Z", None);
+        assert_eq!(event.move_costs(), &Some(Costs::new()));
+    }
+
+    #[test]
+    fn work_under_other_occupation() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let now2: DateTime<Utc> = "2018-06-06T06:00:00Z".parse().unwrap();
+        let mut state = TestState::standard(vec![CompanyPermission::Work], &now);
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let welder_id = OccupationID::new("welder");
+        let mut worker_class = MemberWorker::new(occupation_id.clone(), None);
+        worker_class.add_occupation(welder_id.clone());
+        state.member_mut().set_class(MemberClass::Worker(worker_class));
+        let worker = state.member().clone();
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        state.model = Some(worker);
+        state.model2 = Some(process);
+        let welder = make_occupation(&welder_id, "welder", None, &now);
+
+        let work_with = |state: &TestState<Member, Process>, occupation: &Occupation, wage: Option<Decimal>| {
+            work(state.user(), state.member(), state.company(), EventID::create(), state.model().clone(), occupation, state.model2().clone(), wage, now.clone(), now2.clone(), None, None, &[], None, &now2)
+        };
+
+        let mods = work_with(&state, &welder, Some(num!(120))).unwrap().into_vec();
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor(welder_id.clone(), num!(120))));
+        let mut costs = Costs::new_with_labor(welder_id.clone(), num!(120));
+        costs.track_labor_hours(welder_id.clone(), num!(6));
+        assert_eq!(process2.costs(), &costs);
+
+        // without a wage, the hours name the occupation
+        let mods = work_with(&state, &welder, None).unwrap().into_vec();
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor_hours(welder_id.clone(), num!(6))));
+        assert_eq!(process2.costs(), &Costs::new_with_labor_hours(welder_id.clone(), num!(6)));
+
+        // the primary occupation still works as before
+        let occupation = make_occupation(&occupation_id, "machinist", None, &now);
+        let mods = work_with(&state, &occupation, None).unwrap().into_vec();
+        let process2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.costs(), &Costs::new_with_labor_hours(occupation_id.clone(), num!(6)));
+
+        let painter = make_occupation(&OccupationID::new("painter"), "painter", None, &now);
+        assert_eq!(work_with(&state, &painter, Some(num!(120))), Err(Error::OccupationMismatch));
+    }
+
+    #[test]
+    fn caps_work_hours() {
+        let now: DateTime<Utc> = "2018-06-06T00:00:00Z".parse().unwrap();
+        let at = |time: &str| -> DateTime<Utc> { time.parse().unwrap() };
+        let mut state = TestState::standard(vec![CompanyPermission::Work, CompanyPermission::WorkAdmin], &now);
+        let occupation_id = state.member().occupation_id().unwrap().clone();
+        let occupation = make_occupation(&occupation_id, "machinist", None, &now);
+        let worker = state.member().clone();
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        state.model = Some(worker.clone());
+        state.model2 = Some(process);
+        let cap = WorkHoursCap::new(num!(10), Measure::new(num!(1), Unit::Day)).unwrap();
+
+        let work_with = |state: &TestState<Member, Process>, begin: DateTime<Utc>, end: DateTime<Utc>, sessions: &[Event]| {
+            work(state.user(), state.member(), state.company(), EventID::create(), state.model().clone(), &occupation, state.model2().clone(), None, begin, end.clone(), Some(&cap), None, sessions, None, &end)
+        };
+        let mods = work_with(&state, at("2018-06-06T00:00:00Z"), at("2018-06-06T06:00:00Z"), &[]).unwrap().into_vec();
+        let session1 = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let sessions = vec![session1.clone()];
+
+        let res = work_with(&state, at("2018-06-06T06:00:00Z"), at("2018-06-06T12:00:00Z"), &sessions);
+        assert_eq!(res, Err(Error::Event(EventError::WorkHoursCapExceeded("6 hours of work with only 4 hours left in the period".into()))));
+        // without the cap, anything goes
+        assert!(work(state.user(), state.member(), state.company(), EventID::create(), worker.clone(), &occupation, state.model2().clone(), None, at("2018-06-06T06:00:00Z"), at("2018-06-06T12:00:00Z"), None, None, &sessions, None, &now).is_ok());
+        assert!(work_with(&state, at("2018-06-06T06:00:00Z"), at("2018-06-06T10:00:00Z"), &sessions).is_ok());
+        // the period rolls, so only the last two hours of session1 count here
+        assert!(work_with(&state, at("2018-06-06T20:00:00Z"), at("2018-06-07T04:00:00Z"), &sessions).is_ok());
+        assert!(work_with(&state, at("2018-06-06T19:00:00Z"), at("2018-06-07T04:00:00Z"), &sessions).is_err());
+        // other workers' sessions and deleted sessions don't count
+        let mut other = session1.clone();
+        other.inner_mut().set_provider(MemberID::create().into());
+        let mut deleted = session1.clone();
+        deleted.set_deleted(Some(now.clone()));
+        assert!(work_with(&state, at("2018-06-06T06:00:00Z"), at("2018-06-06T16:00:00Z"), &[other, deleted]).is_ok());
+
+        // entries in a batch count towards the entries after them
+        let state_bulk: TestState<Process, Process> = TestState {
+            user: state.user.clone(),
+            member: state.member.clone(),
+            company: state.company.clone(),
+            model: state.model2.clone(),
+            model2: None,
+            loc: None,
+        };
+        let entries = vec![
+            WorkEntry::new(EventID::create(), worker.clone(), occupation.clone(), None, at("2018-06-06T08:00:00Z"), at("2018-06-06T14:00:00Z")),
+            WorkEntry::new(EventID::create(), worker.clone(), occupation.clone(), None, at("2018-06-06T14:00:00Z"), at("2018-06-06T20:00:00Z")),
+        ];
+        let res = work_bulk(state_bulk.user(), state_bulk.member(), state_bulk.company(), state_bulk.model().clone(), entries.clone(), Some(&cap), None, &[], None, &now);
+        assert_eq!(res, Err(Error::Event(EventError::WorkHoursCapExceeded("6 hours of work with only 4 hours left in the period".into()))));
+        assert!(work_bulk(state_bulk.user(), state_bulk.member(), state_bulk.company(), state_bulk.model().clone(), entries, None, None, &[], None, &now).is_ok());
+
+        // can't clock in with no hours left, and clocking out checks the cap
+        let mut full = session1.clone();
+        full.inner_mut().set_has_end(Some(at("2018-06-06T10:00:00Z")));
+        let res = clock_in(state.user(), state.member(), state.company(), EventID::create(), &worker, &occupation, state.model2(), &[full.clone()], Some(&cap), at("2018-06-06T10:00:00Z"), None, &now);
+        assert_eq!(res, Err(Error::Event(EventError::WorkHoursCapExceeded("no hours left in the period".into()))));
+        let mods = clock_in(state.user(), state.member(), state.company(), EventID::create(), &worker, &occupation, state.model2(), &sessions, Some(&cap), at("2018-06-06T06:00:00Z"), None, &now).unwrap().into_vec();
+        let pending = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let sessions2 = vec![session1.clone(), pending.clone()];
+        let res = clock_out(state.user(), state.member(), state.company(), pending.clone(), worker.clone(), &occupation, state.model2().clone(), None, &sessions2, Some(&cap), None, at("2018-06-06T11:00:00Z"), &now);
+        assert_eq!(res, Err(Error::Event(EventError::WorkHoursCapExceeded("5 hours of work with only 4 hours left in the period".into()))));
+        assert!(clock_out(state.user(), state.member(), state.company(), pending.clone(), worker.clone(), &occupation, state.model2().clone(), None, &sessions2, Some(&cap), None, at("2018-06-06T10:00:00Z"), &now).is_ok());
+    }
+}
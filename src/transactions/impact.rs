@@ -0,0 +1,164 @@
+//! Impacts are the kinds of ecological or social externalities tracked in our
+//! costs (CO2e, water use, etc).
+//!
+//! See the [impact model.][1]
+//!
+//! [1]: ../../models/impact/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        lib::basis_model::Model,
+        impact::{Impact, ImpactID},
+        user::User,
+    },
+};
+use om2::Unit;
+
+/// Create a new `Impact`.
+pub fn create<T: Into<String>>(caller: &User, id: ImpactID, name: T, unit: Unit, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::ImpactCreate)?;
+    let model = Impact::builder()
+        .id(id)
+        .name(name)
+        .unit(unit)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update an existing `Impact`
+pub fn update(caller: &User, mut subject: Impact, name: Option<String>, unit: Option<Unit>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::ImpactUpdate)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("impact".into()))?;
+    }
+    if let Some(name) = name {
+        subject.set_name(name);
+    }
+    if let Some(unit) = unit {
+        subject.set_unit(unit);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Delete a `Impact`
+pub fn delete(caller: &User, mut subject: Impact, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::ImpactDelete)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("impact".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        util::{self, test::{self, *}},
+    };
+
+    #[test]
+    fn can_create() {
+        let id = ImpactID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let testfn = |state: &TestState<Impact, Impact>| {
+            create(state.user(), id.clone(), "CO2e", Unit::Kilogram, true, &now)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+
+        let impact = mods[0].clone().expect_op::<Impact>(Op::Create).unwrap();
+        assert_eq!(impact.id(), &id);
+        assert_eq!(impact.name(), "CO2e");
+        assert_eq!(impact.unit(), &Unit::Kilogram);
+        assert_eq!(impact.active(), &true);
+        assert_eq!(impact.created(), &now);
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_update() {
+        let id = ImpactID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let mods = create(state.user(), id.clone(), "CO2e", Unit::Kilogram, true, &now).unwrap().into_vec();
+        let impact = mods[0].clone().expect_op::<Impact>(Op::Create).unwrap();
+        state.model = Some(impact);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Impact, Impact>| {
+            update(state.user(), state.model().clone(), None, Some(Unit::CubicMetre), Some(false), &now2)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        let impact2 = mods[0].clone().expect_op::<Impact>(Op::Update).unwrap();
+        assert_eq!(impact2.name(), "CO2e");
+        assert_eq!(impact2.unit(), &Unit::CubicMetre);
+        assert_eq!(impact2.active(), &false);
+        assert_eq!(impact2.created(), &now);
+        assert_eq!(impact2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("impact".into())));
+    }
+
+    #[test]
+    fn can_delete() {
+        let id = ImpactID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let mods = create(state.user(), id.clone(), "CO2e", Unit::Kilogram, true, &now).unwrap().into_vec();
+        let impact = mods[0].clone().expect_op::<Impact>(Op::Create).unwrap();
+        state.model = Some(impact);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Impact, Impact>| {
+            delete(state.user(), state.model().clone(), &now2)
+        };
+        test::double_deleted_tester(&state, "impact", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let impact2 = mods[0].clone().expect_op::<Impact>(Op::Delete).unwrap();
+        assert_eq!(impact2.id(), &id);
+        assert_eq!(impact2.deleted(), &Some(now2));
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
@@ -4,6 +4,11 @@
 //! For instance, if you made a widget and you want someone to purchase it, you
 //! would create and publish an intent to `transfer` that widget.
 //!
+//! Services can be offered with a recurring window of availability via
+//! `create_service()` ("10 hours of plumbing per week"). Commitments made
+//! against an intent draw down its available quantity, and the intent closes
+//! itself once there's nothing left to offer.
+//!
 //! See the [intent model.][1]
 //!
 //! [1]: ../../models/intent/index.html
@@ -22,7 +27,7 @@ use crate::{
             agent::{Agent, AgentID},
             basis_model::Model,
         },
-        intent::{Intent, IntentID},
+        intent::{Availability, Intent, IntentID},
         resource::ResourceID,
         resource_spec::ResourceSpecID,
         user::User,
@@ -34,7 +39,7 @@ use url::Url;
 use vf_rs::{vf, geo::SpatialThing};
 
 /// Create a new intent
-pub fn create(caller: &User, member: &Member, company: &Company, id: IntentID, move_costs: Option<Costs>, action: OrderAction, agreed_in: Option<Url>, at_location: Option<SpatialThing>, available_quantity: Option<Measure>, due: Option<DateTime<Utc>>, effort_quantity: Option<Measure>, finished: Option<bool>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, has_point_in_time: Option<DateTime<Utc>>, in_scope_of: Vec<AgentID>, name: Option<String>, note: Option<String>, provider: Option<AgentID>, receiver: Option<AgentID>, resource_conforms_to: Option<ResourceSpecID>, resource_inventoried_as: Option<ResourceID>, resource_quantity: Option<Measure>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn create(caller: &User, member: &Member, company: &Company, id: IntentID, move_costs: Option<Costs>, action: OrderAction, agreed_in: Option<Url>, at_location: Option<SpatialThing>, available_quantity: Option<Measure>, due: Option<DateTime<Utc>>, effort_quantity: Option<Measure>, finished: Option<bool>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, has_point_in_time: Option<DateTime<Utc>>, in_scope_of: Vec<AgentID>, name: Option<String>, note: Option<String>, provider: Option<AgentID>, receiver: Option<AgentID>, resource_conforms_to: Option<ResourceSpecID>, resource_inventoried_as: Option<ResourceID>, resource_quantity: Option<Measure>, expires_at: Option<DateTime<Utc>>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateIntents)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::IntentCreate)?;
     if !company.is_active() {
@@ -49,11 +54,7 @@ pub fn create(caller: &User, member: &Member, company: &Company, id: IntentID, m
         // can't create an intent for a company you aren't a member of DUUUHHH
         Err(Error::InsufficientPrivileges)?;
     }
-    let event_action = match action {
-        OrderAction::DeliverService => vf::Action::DeliverService,
-        OrderAction::Transfer => vf::Action::Transfer,
-        OrderAction::TransferCustody => vf::Action::TransferCustody,
-    };
+    let event_action = action.to_action();
     let model = Intent::builder()
         .id(id)
         .inner(
@@ -80,6 +81,7 @@ pub fn create(caller: &User, member: &Member, company: &Company, id: IntentID, m
                 .map_err(|e| Error::BuilderFailed(e))?
         )
         .move_costs(move_costs)
+        .expires_at(expires_at)
         .active(active)
         .created(now.clone())
         .updated(now.clone())
@@ -88,8 +90,20 @@ pub fn create(caller: &User, member: &Member, company: &Company, id: IntentID, m
     Ok(Modifications::new_single(Op::Create, model))
 }
 
+/// Create an intent offering a service, with `per_period` of the service
+/// (generally measured in hours) available every `period` starting at
+/// `starts`. The offer recurs until `expires_at` (or forever).
+pub fn create_service(caller: &User, member: &Member, company: &Company, id: IntentID, move_costs: Option<Costs>, at_location: Option<SpatialThing>, name: Option<String>, note: Option<String>, resource_conforms_to: Option<ResourceSpecID>, per_period: Measure, period: Measure, starts: DateTime<Utc>, expires_at: Option<DateTime<Utc>>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    let availability = Availability::new(per_period.clone(), period, starts.clone());
+    availability.period_seconds()?;
+    let mods = create(caller, member, company, id, move_costs, OrderAction::DeliverService, None, at_location, Some(per_period), None, None, None, Some(starts), None, None, vec![], name, note, Some(company.agent_id()), None, resource_conforms_to, None, None, expires_at, active, now)?;
+    let mut intent = mods.into_vec().swap_remove(0).expect_op::<Intent>(Op::Create)?;
+    intent.set_availability(Some(availability));
+    Ok(Modifications::new_single(Op::Create, intent))
+}
+
 /// Update an intent
-pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Intent, move_costs: Option<Option<Costs>>, action: Option<OrderAction>, agreed_in: Option<Option<Url>>, at_location: Option<Option<SpatialThing>>, available_quantity: Option<Option<Measure>>, due: Option<Option<DateTime<Utc>>>, effort_quantity: Option<Option<Measure>>, finished: Option<Option<bool>>, has_beginning: Option<Option<DateTime<Utc>>>, has_end: Option<Option<DateTime<Utc>>>, has_point_in_time: Option<Option<DateTime<Utc>>>, in_scope_of: Option<Vec<AgentID>>, name: Option<Option<String>>, note: Option<Option<String>>, provider: Option<Option<AgentID>>, receiver: Option<Option<AgentID>>, resource_conforms_to: Option<Option<ResourceSpecID>>, resource_inventoried_as: Option<Option<ResourceID>>, resource_quantity: Option<Option<Measure>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Intent, move_costs: Option<Option<Costs>>, action: Option<OrderAction>, agreed_in: Option<Option<Url>>, at_location: Option<Option<SpatialThing>>, available_quantity: Option<Option<Measure>>, due: Option<Option<DateTime<Utc>>>, effort_quantity: Option<Option<Measure>>, finished: Option<Option<bool>>, has_beginning: Option<Option<DateTime<Utc>>>, has_end: Option<Option<DateTime<Utc>>>, has_point_in_time: Option<Option<DateTime<Utc>>>, in_scope_of: Option<Vec<AgentID>>, name: Option<Option<String>>, note: Option<Option<String>>, provider: Option<Option<AgentID>>, receiver: Option<Option<AgentID>>, resource_conforms_to: Option<Option<ResourceSpecID>>, resource_inventoried_as: Option<Option<ResourceID>>, resource_quantity: Option<Option<Measure>>, expires_at: Option<Option<DateTime<Utc>>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateIntents)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::IntentUpdate)?;
     if !company.is_active() {
@@ -114,13 +128,7 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: In
         }
         subject.inner_mut().set_receiver(receiver);
     }
-    let event_action = action.map(|x| {
-        match x {
-            OrderAction::DeliverService => vf::Action::DeliverService,
-            OrderAction::Transfer => vf::Action::Transfer,
-            OrderAction::TransferCustody => vf::Action::TransferCustody,
-        }
-    });
+    let event_action = action.map(|x| x.to_action());
 
     if let Some(move_costs) = move_costs {
         subject.set_move_costs(move_costs);
@@ -174,6 +182,9 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: In
     if let Some(resource_quantity) = resource_quantity {
         subject.inner_mut().set_resource_quantity(resource_quantity);
     }
+    if let Some(expires_at) = expires_at {
+        subject.set_expires_at(expires_at);
+    }
     if let Some(active) = active {
         subject.set_active(active);
     }
@@ -195,6 +206,46 @@ pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: In
     Ok(Modifications::new_single(Op::Delete, subject))
 }
 
+/// Withdraw (deactivate) any of the given intents that have expired, returning
+/// an update for each one.
+///
+/// This is meant to be run periodically by the system itself rather than by a
+/// member of any particular company, so there are no permission checks: an
+/// intent's expiration is decided when it's created. Intents that haven't
+/// expired, or that are already inactive or deleted, are skipped.
+pub fn expire_batch(intents: Vec<Intent>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let mut mods = Modifications::new();
+    for mut intent in intents {
+        if !intent.is_active() || !intent.is_expired(now) {
+            continue;
+        }
+        intent.set_active(false);
+        intent.set_updated(now.clone());
+        mods.push(Op::Update, intent);
+    }
+    Ok(mods)
+}
+
+/// Roll any of the given intents with recurring availability over into the
+/// current period, resetting their available quantity. Returns an update for
+/// each intent that changed.
+///
+/// Like `expire_batch()`, this is run by the system and has no permission
+/// checks. Inactive or deleted intents are skipped.
+pub fn refresh_batch(intents: Vec<Intent>, now: &DateTime<Utc>) -> Result<Modifications> {
+    let mut mods = Modifications::new();
+    for mut intent in intents {
+        if !intent.is_active() {
+            continue;
+        }
+        if intent.refresh_availability(now)? {
+            intent.set_updated(now.clone());
+            mods.push(Op::Update, intent);
+        }
+    }
+    Ok(mods)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -214,7 +265,7 @@ mod tests {
         let costs = Costs::new_with_labor("widgetmaker", 42);
 
         let testfn_inner = |state: &TestState<Intent, Intent>, provider: Option<AgentID>, receiver: Option<AgentID>| {
-            create(state.user(), state.member(), state.company(), id.clone(), Some(costs.clone()), OrderAction::Transfer, None, Some(state.loc().clone()), Some(Measure::new(10, Unit::One)), None, None, Some(false), Some(now.clone()), None, None, vec![state.company().agent_id()], Some("buy my widget".into()), Some("gee willickers i hope someone buys my widget".into()), provider, receiver, None, Some(ResourceID::new("widget1")), None, true, &now)
+            create(state.user(), state.member(), state.company(), id.clone(), Some(costs.clone()), OrderAction::Transfer, None, Some(state.loc().clone()), Some(Measure::new(10, Unit::One)), None, None, Some(false), Some(now.clone()), None, None, vec![state.company().agent_id()], Some("buy my widget".into()), Some("gee willickers i hope someone buys my widget".into()), provider, receiver, None, Some(ResourceID::new("widget1")), None, None, true, &now)
         };
         let testfn = |state: &TestState<Intent, Intent>| {
             testfn_inner(state, Some(state.company().agent_id()), None)
@@ -245,6 +296,7 @@ mod tests {
         assert_eq!(intent.inner().resource_conforms_to(), &None);
         assert_eq!(intent.inner().resource_inventoried_as(), &Some(ResourceID::new("widget1")));
         assert_eq!(intent.inner().resource_quantity(), &None);
+        assert_eq!(intent.expires_at(), &None);
         assert_eq!(intent.active(), &true);
         assert_eq!(intent.created(), &now);
         assert_eq!(intent.updated(), &now);
@@ -269,13 +321,14 @@ mod tests {
         let costs1 = Costs::new_with_labor("widgetmaker", 42);
         let costs2 = Costs::new_with_labor("widgetmaker", 41);
 
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), Some(costs1.clone()), OrderAction::Transfer, None, Some(state.loc().clone()), Some(Measure::new(10, Unit::One)), None, None, Some(false), Some(now.clone()), None, None, vec![state.company().agent_id()], Some("buy my widget".into()), Some("gee willickers i hope someone buys my widget".into()), Some(state.company().agent_id()), None, None, Some(ResourceID::new("widget1")), None, true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), id.clone(), Some(costs1.clone()), OrderAction::Transfer, None, Some(state.loc().clone()), Some(Measure::new(10, Unit::One)), None, None, Some(false), Some(now.clone()), None, None, vec![state.company().agent_id()], Some("buy my widget".into()), Some("gee willickers i hope someone buys my widget".into()), Some(state.company().agent_id()), None, None, Some(ResourceID::new("widget1")), None, None, true, &now).unwrap().into_vec();
         let intent = mods[0].clone().expect_op::<Intent>(Op::Create).unwrap();
         state.model = Some(intent);
 
         let now2 = util::time::now();
+        let expires = now2 + chrono::Duration::days(7);
         let testfn_inner = |state: &TestState<Intent, Intent>, provider: Option<Option<AgentID>>, receiver: Option<Option<AgentID>>| {
-            update(state.user(), state.member(), state.company(), state.model().clone(), Some(Some(costs2.clone())), None, None, Some(None), None, None, None, None, None, None, None, Some(vec![]), Some(Some("buy widget".into())), None, provider, receiver, None, None, None, Some(false), &now2)
+            update(state.user(), state.member(), state.company(), state.model().clone(), Some(Some(costs2.clone())), None, None, Some(None), None, None, None, None, None, None, None, Some(vec![]), Some(Some("buy widget".into())), None, provider, receiver, None, None, None, Some(Some(expires.clone())), Some(false), &now2)
         };
         let testfn = |state: &TestState<Intent, Intent>| {
             testfn_inner(state, None, None)
@@ -305,6 +358,7 @@ mod tests {
         assert_eq!(intent2.inner().resource_conforms_to(), state.model().inner().resource_conforms_to());
         assert_eq!(intent2.inner().resource_inventoried_as(), state.model().inner().resource_inventoried_as());
         assert_eq!(intent2.inner().resource_quantity(), state.model().inner().resource_quantity());
+        assert_eq!(intent2.expires_at(), &Some(expires.clone()));
         assert_eq!(intent2.active(), &false);
         assert_eq!(intent2.created(), &now);
         assert_eq!(intent2.updated(), &now2);
@@ -328,7 +382,7 @@ mod tests {
         let mut state = TestState::standard(vec![CompanyPermission::IntentCreate, CompanyPermission::IntentDelete], &now);
         let costs = Costs::new_with_labor("widgetmaker", 42);
 
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), Some(costs.clone()), OrderAction::Transfer, None, Some(state.loc().clone()), Some(Measure::new(10, Unit::One)), None, None, Some(false), Some(now.clone()), None, None, vec![state.company().agent_id()], Some("buy my widget".into()), Some("gee willickers i hope someone buys my widget".into()), Some(state.company().agent_id()), None, None, Some(ResourceID::new("widget1")), None, true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), id.clone(), Some(costs.clone()), OrderAction::Transfer, None, Some(state.loc().clone()), Some(Measure::new(10, Unit::One)), None, None, Some(false), Some(now.clone()), None, None, vec![state.company().agent_id()], Some("buy my widget".into()), Some("gee willickers i hope someone buys my widget".into()), Some(state.company().agent_id()), None, None, Some(ResourceID::new("widget1")), None, None, true, &now).unwrap().into_vec();
         let intent = mods[0].clone().expect_op::<Intent>(Op::Create).unwrap();
         state.model = Some(intent);
 
@@ -351,5 +405,85 @@ mod tests {
         assert_eq!(intent2.updated(), state.model().updated());
         assert_eq!(intent2.deleted(), &Some(now2));
     }
-}
 
+    #[test]
+    fn can_expire_batch() {
+        let now: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let later: DateTime<Utc> = "2020-06-08T00:00:00Z".parse().unwrap();
+        let state: TestState<Intent, Intent> = TestState::standard(vec![CompanyPermission::IntentCreate], &now);
+        let make = |expires_at: Option<DateTime<Utc>>| -> Intent {
+            let mods = create(state.user(), state.member(), state.company(), IntentID::create(), None, OrderAction::Transfer, None, None, None, None, None, None, None, None, None, vec![], Some("widget".into()), None, Some(state.company().agent_id()), None, None, None, None, expires_at, true, &now).unwrap().into_vec();
+            mods[0].clone().expect_op::<Intent>(Op::Create).unwrap()
+        };
+        let expired = make(Some("2020-06-05T00:00:00Z".parse().unwrap()));
+        let expires_now = make(Some(later.clone()));
+        let fresh = make(Some("2020-07-01T00:00:00Z".parse().unwrap()));
+        let forever = make(None);
+        let mut withdrawn = make(Some("2020-06-02T00:00:00Z".parse().unwrap()));
+        withdrawn.set_active(false);
+
+        assert!(expired.is_available(&now));
+        assert!(!expired.is_available(&later));
+        assert!(expires_now.is_expired(&later));
+        assert!(!fresh.is_expired(&later));
+        assert!(!forever.is_expired(&later));
+        assert!(!withdrawn.is_available(&now));
+
+        let mods = expire_batch(vec![expired.clone(), fresh.clone(), expires_now.clone(), forever.clone(), withdrawn.clone()], &later).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let expired2 = mods[0].clone().expect_op::<Intent>(Op::Update).unwrap();
+        let expires_now2 = mods[1].clone().expect_op::<Intent>(Op::Update).unwrap();
+        assert_eq!(expired2.id(), expired.id());
+        assert_eq!(expired2.active(), &false);
+        assert_eq!(expired2.updated(), &later);
+        assert_eq!(expires_now2.id(), expires_now.id());
+        assert_eq!(expires_now2.active(), &false);
+
+        let mods = expire_batch(vec![expired2, expires_now2], &later).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+    }
+
+    #[test]
+    fn can_create_service() {
+        let now: DateTime<Utc> = "2020-06-01T00:00:00Z".parse().unwrap();
+        let id = IntentID::create();
+        let state = TestState::standard(vec![CompanyPermission::IntentCreate], &now);
+
+        let testfn = |state: &TestState<Intent, Intent>| {
+            create_service(state.user(), state.member(), state.company(), id.clone(), None, None, Some("plumbing".into()), None, None, Measure::new(10, Unit::Hour), Measure::new(1, Unit::Week), now.clone(), None, true, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let intent = mods[0].clone().expect_op::<Intent>(Op::Create).unwrap();
+        assert_eq!(intent.id(), &id);
+        assert_eq!(intent.inner().action(), &vf::Action::DeliverService);
+        assert_eq!(intent.inner().available_quantity(), &Some(Measure::new(10, Unit::Hour)));
+        assert_eq!(intent.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(intent.inner().name(), &Some("plumbing".into()));
+        assert_eq!(intent.inner().provider(), &Some(state.company().agent_id()));
+        assert_eq!(intent.availability(), &Some(Availability::new(Measure::new(10, Unit::Hour), Measure::new(1, Unit::Week), now.clone())));
+        assert_eq!(intent.active(), &true);
+
+        let res = create_service(state.user(), state.member(), state.company(), id.clone(), None, None, None, None, None, Measure::new(10, Unit::Hour), Measure::new(1, Unit::Month), now.clone(), None, true, &now);
+        assert_eq!(res, Err(Error::MeasureUnitsMismatched));
+
+        // rolling the intent over into later periods
+        let mut used = intent.clone();
+        used.inner_mut().set_available_quantity(Some(Measure::new(2, Unit::Hour)));
+        let mut withdrawn = used.clone();
+        withdrawn.set_active(false);
+        let midweek: DateTime<Utc> = "2020-06-03T00:00:00Z".parse().unwrap();
+        let mods = refresh_batch(vec![used.clone(), withdrawn.clone()], &midweek).unwrap().into_vec();
+        assert_eq!(mods.len(), 0);
+        let next_week: DateTime<Utc> = "2020-06-09T00:00:00Z".parse().unwrap();
+        let mods = refresh_batch(vec![used.clone(), withdrawn.clone()], &next_week).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let used2 = mods[0].clone().expect_op::<Intent>(Op::Update).unwrap();
+        assert_eq!(used2.id(), used.id());
+        assert_eq!(used2.inner().available_quantity(), &Some(Measure::new(10, Unit::Hour)));
+        assert_eq!(used2.availability().as_ref().unwrap().period_start(), &"2020-06-08T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(used2.updated(), &next_week);
+    }
+}
@@ -0,0 +1,360 @@
+//! Market transactions are the interface between the network and the
+//! capitalist markets it exists alongside of.
+//!
+//! Selling a resource to a buyer outside of the network removes the sold
+//! quantity (and its share of the costs) from the resource. Because there's no
+//! agent in the network to receive those costs, they're destroyed: the
+//! company's `total_costs` are reduced by the same amount, and the sale is
+//! recorded with the currency received so the [company ledger][1] can track the
+//! surplus (or deficit) of the sale against the currency costs of what was
+//! sold.
+//!
+//! Buying from outside of the network works the other way around: the purchased
+//! goods enter the network as a new resource whose costs are the currency paid
+//! (valued in credits at the currency's exchange rate when the purchase is
+//! made). Optionally, the purchased quantity can also be tracked as a raw
+//! resource, which lets externally-bought materials show up in resource
+//! accounting without double-counting their credit value. The purchase is
+//! recorded as a `raise` event, and the costs are added to the company's
+//! `total_costs`, just as they would be if the resource had been transferred in
+//! from another company.
+//!
+//! [1]: ../../models/company_ledger/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    costs::{Costs, validate::SanityPolicy},
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        currency::Currency,
+        event::{Event, EventID, EventProcessState, MoveType},
+        member::Member,
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
+        resource::{Resource, ResourceID},
+        resource_spec::ResourceSpecID,
+        user::User,
+    },
+    transactions::event::accounting,
+    util::{measure, number::Ratio},
+};
+use om2::{Measure, NumericUnion};
+use rust_decimal::prelude::*;
+use vf_rs::vf;
+
+/// Sell some quantity of a resource to a buyer outside of the network, in
+/// exchange for `currency_received` of the given currency.
+///
+/// The costs that leave with the sold quantity are proportional to the
+/// quantity sold (selling 2 of 10 widgets removes 20% of the resource's
+/// costs).
+pub fn sell_external<T: Into<NumericUnion>>(caller: &User, member: &Member, company: &Company, id: EventID, resource: Resource, move_measure: T, currency: &Currency, currency_received: Decimal, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateMarketSell)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MarketSell)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !currency.is_active() {
+        Err(Error::ObjectIsInactive("currency".into()))?;
+    }
+    if resource.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource".into()))?;
+    }
+    if currency_received < Decimal::zero() {
+        Err(Error::InvalidAmount(currency_received))?;
+    }
+    resource.check_lot(now)?;
+
+    let unit = resource.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+    let measure = Measure::new(move_measure, unit);
+    let total = resource.inner().accounting_quantity().as_ref().ok_or(Error::ResourceMeasureMissing)?;
+    let total = measure::to_decimal(total)?;
+    let amount = measure::to_decimal(&measure)?;
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    if total <= Decimal::zero() {
+        Err(Error::ResourceMeasureMissing)?;
+    }
+    let move_costs = resource.costs().clone() * Ratio::new(amount / total)?;
+
+    let resource_id = resource.id().clone();
+    let state = EventProcessState::builder()
+        .resource(resource)
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let event = Event::builder()
+        .id(id)
+        .inner(
+            vf::EconomicEvent::builder()
+                .action(vf::Action::Move)
+                .has_point_in_time(now.clone())
+                .note(note)
+                .provider(company.id().clone())
+                .receiver(company.id().clone())
+                .resource_inventoried_as(Some(resource_id))
+                .resource_quantity(Some(measure))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(Some(move_costs.clone()))
+        .move_type(Some(MoveType::ExternalSale(currency.id().clone(), currency_received)))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+
+    let evmods = event.process(state, now)?.into_vec();
+    let mut company = company.clone();
+    company.decrease_costs(move_costs)?;
+    company.set_updated(now.clone());
+
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, event);
+    for evmod in evmods {
+        mods.push_raw(evmod);
+    }
+    mods.push(Op::Update, company);
+    Ok(mods)
+}
+
+/// Buy some quantity of a resource from a seller outside of the network,
+/// paying `currency_paid` of the given currency.
+///
+/// The new resource's costs are seeded from the currency paid. If `track_as`
+/// is given, the purchased quantity is also tracked in the costs as that raw
+/// resource (with no additional credit value, since the credits already come
+/// from the currency).
+///
+/// If a `sanity_policy` is given (generally the network config's), the costs
+/// of the purchase are checked against it.
+///
+/// Returns the `raise` event (using `event_id`) that records the purchase, the
+/// created resource, and an update of the company.
+pub fn purchase_external(caller: &User, member: &Member, company: &Company, id: ResourceID, event_id: EventID, spec_id: ResourceSpecID, quantity: Measure, currency: &Currency, currency_paid: Decimal, track_as: Option<ResourceSpecID>, name: Option<String>, note: Option<String>, sanity_policy: Option<&SanityPolicy>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::EventCreateRaise)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MarketPurchase)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !currency.is_active() {
+        Err(Error::ObjectIsInactive("currency".into()))?;
+    }
+    if currency_paid < Decimal::zero() {
+        Err(Error::InvalidAmount(currency_paid))?;
+    }
+    let amount = measure::to_decimal(&quantity)?;
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+
+    let mut costs = Costs::new();
+    costs.track_currency_at(currency, currency_paid, now)?;
+    if let Some(track_as) = track_as {
+        costs.track_resource(track_as, amount, Decimal::zero());
+    }
+
+    let resource = Resource::builder()
+        .id(id)
+        .inner(
+            vf::EconomicResource::builder()
+                .conforms_to(spec_id)
+                .name(name)
+                .note(note)
+                .primary_accountable(Some(company.agent_id()))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .in_custody_of(company.id().clone())
+        .costs(Costs::new())
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+
+    let mut mods = accounting::raise_new(company, event_id, resource, quantity, costs.clone(), sanity_policy, Some("purchased from an external market".into()), now)?;
+    let mut company = company.clone();
+    company.increase_costs(costs)?;
+    company.set_updated(now.clone());
+    mods.push(Op::Update, company);
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            company::CompanyID,
+            company_ledger::{CompanyLedger, CompanyLedgerID},
+            currency::{CurrencyID, CurrencyRate},
+            event::EventError,
+        },
+        util::{self, test::{self, *}},
+    };
+    use om2::Unit;
+    use std::collections::HashMap;
+
+    #[test]
+    fn can_sell_external() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::MarketSell], &now);
+        let currency = make_currency(&CurrencyID::new("usd"), "usd", 2, &now);
+        let costs = Costs::new_with_labor("machinist", num!(300)) + Costs::new_with_currency("usd", num!(100), num!(1));
+        let resource = make_resource(&ResourceID::new("widgets"), state.company().id(), &Measure::new(num!(10), Unit::One), &costs, &now);
+        let mut company = state.company().clone();
+        company.set_total_costs(costs.clone() * num!(2));
+        state.company = Some(company);
+        state.model = Some(resource);
+
+        let testfn = |state: &TestState<Resource, Resource>| {
+            sell_external(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), 2, &currency, num!(35), Some("sold some widgets at the flea market".into()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap();
+        let mods_vec = mods.clone().into_vec();
+        assert_eq!(mods_vec.len(), 3);
+        let event = mods_vec[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods_vec[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let company2 = mods_vec[2].clone().expect_op::<Company>(Op::Update).unwrap();
+
+        let sold_costs = Costs::new_with_labor("machinist", num!(60)) + Costs::new_with_currency("usd", num!(20), num!(1));
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Move);
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(2, Unit::One)));
+        assert_eq!(event.move_type(), &Some(MoveType::ExternalSale(currency.id().clone(), num!(35))));
+        assert_eq!(event.move_costs(), &Some(sold_costs.clone()));
+        assert_eq!(event.inner().note(), &Some("sold some widgets at the flea market".into()));
+
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(8), Unit::One)));
+        assert_eq!(resource2.inner().onhand_quantity(), &Some(Measure::new(num!(8), Unit::One)));
+        assert_eq!(resource2.costs(), &(costs.clone() - sold_costs.clone()));
+        assert_eq!(company2.total_costs(), &(costs.clone() * num!(2) - sold_costs.clone()));
+
+        // the ledger picks up the sale
+        let mut ledger = CompanyLedger::builder()
+            .id(CompanyLedgerID::create())
+            .company_id(state.company().id().clone())
+            .costs_received(Costs::new())
+            .costs_sent(Costs::new())
+            .labor_paid(Costs::new())
+            .waste(Costs::new())
+            .external_sales(Costs::new())
+            .market_balance(HashMap::new())
+            .debts(HashMap::new())
+            .recorded_events(HashMap::new())
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        assert!(ledger.apply(&mods));
+        assert_eq!(ledger.external_sales(), &sold_costs);
+        assert_eq!(ledger.market_balance().get(currency.id()), Some(&num!(15)));
+
+        let res = sell_external(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), 2, &currency, num!(-1), None, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+        let res = sell_external(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), 0, &currency, num!(35), None, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = sell_external(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), 11, &currency, num!(35), None, &now);
+        assert_eq!(res, Err(Error::InvalidRatio(num!(1.1))));
+
+        let mut currency2 = currency.clone();
+        currency2.set_active(false);
+        let res = sell_external(state.user(), state.member(), state.company(), id.clone(), state.model().clone(), 2, &currency2, num!(35), None, &now);
+        assert_eq!(res, Err(Error::ObjectIsInactive("currency".into())));
+
+        let mut state2 = state.clone();
+        state2.model_mut().inner_mut().set_primary_accountable(Some(CompanyID::new("larry's widgets").into()));
+        assert_eq!(testfn(&state2), Err(Error::Event(EventError::ResourceOwnerMismatch)));
+
+        // can't destroy costs the company doesn't have
+        let mut state3 = state.clone();
+        state3.company_mut().set_total_costs(Costs::new());
+        assert_eq!(testfn(&state3), Err(Error::NegativeCosts));
+    }
+
+    #[test]
+    fn can_purchase_external() {
+        let now = util::time::now();
+        let id = ResourceID::new("steel beams");
+        let state = TestState::standard(vec![CompanyPermission::MarketPurchase], &now);
+        let mut currency = make_currency(&CurrencyID::new("usd"), "usd", 2, &now);
+        currency.push_rate(CurrencyRate::new(num!(0.5), now.clone()));
+        let quantity = Measure::new(num!(20), Unit::Kilogram);
+
+        let event_id = EventID::create();
+        let testfn = |state: &TestState<Resource, Resource>| {
+            purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency, num!(400), Some(ResourceSpecID::new("steel")), Some("beams".into()), Some("bought some beams at the hardware store".into()), None, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource = mods[1].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let company2 = mods[2].clone().expect_op::<Company>(Op::Update).unwrap();
+
+        let mut costs = Costs::new_with_currency("usd", num!(400), num!(0.5));
+        costs.track_resource("steel", num!(20), num!(0));
+        assert_eq!(event.id(), &event_id);
+        assert_eq!(event.inner().action(), &vf::Action::Raise);
+        assert_eq!(event.inner().provider().clone(), state.company().agent_id());
+        assert_eq!(event.inner().receiver().clone(), state.company().agent_id());
+        assert_eq!(event.inner().resource_inventoried_as(), &Some(id.clone()));
+        assert_eq!(event.inner().resource_quantity(), &Some(quantity.clone()));
+        assert_eq!(event.move_costs(), &Some(costs.clone()));
+        assert_eq!(event.created(), &now);
+        assert_eq!(resource.id(), &id);
+        assert_eq!(resource.inner().conforms_to(), &ResourceSpecID::new("beams"));
+        assert_eq!(resource.inner().name(), &Some("beams".into()));
+        assert_eq!(resource.inner().note(), &Some("bought some beams at the hardware store".into()));
+        assert_eq!(resource.inner().accounting_quantity(), &Some(quantity.clone()));
+        assert_eq!(resource.inner().onhand_quantity(), &Some(quantity.clone()));
+        assert_eq!(resource.inner().primary_accountable(), &Some(state.company().agent_id()));
+        assert_eq!(resource.in_custody_of(), &state.company().agent_id());
+        assert_eq!(resource.costs(), &costs);
+        assert_eq!(resource.costs().credits(), &num!(200));
+        assert_eq!(resource.created(), &now);
+        assert_eq!(company2.total_costs(), &costs);
+        assert_eq!(company2.updated(), &now);
+
+        // no resource mapping, only currency
+        let mods = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency, num!(400), None, None, None, None, &now).unwrap().into_vec();
+        let resource = mods[1].clone().expect_op::<Resource>(Op::Create).unwrap();
+        assert_eq!(resource.costs(), &Costs::new_with_currency("usd", num!(400), num!(0.5)));
+
+        let res = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency, num!(-1), None, None, None, None, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+        let res = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), Measure::new(0, Unit::Kilogram), &currency, num!(400), None, None, None, None, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        // no rate in effect
+        let currency2 = make_currency(&CurrencyID::new("usd"), "usd", 2, &now);
+        let res = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency2, num!(400), None, None, None, None, &now);
+        assert_eq!(res, Err(Error::CurrencyRateMissing));
+
+        let mut currency3 = currency.clone();
+        currency3.set_active(false);
+        let res = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency3, num!(400), None, None, None, None, &now);
+        assert_eq!(res, Err(Error::ObjectIsInactive("currency".into())));
+
+        // the company can't go over its max costs
+        let res = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency, num!(2001), None, None, None, None, &now);
+        assert_eq!(res, Err(Error::MaxCostsReached));
+
+        // a purchase over the network's currency cap is rejected
+        let policy = SanityPolicy::new(None, None, Some(num!(300)), vec![]);
+        let res = purchase_external(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), ResourceSpecID::new("beams"), quantity.clone(), &currency, num!(400), None, None, None, Some(&policy), &now);
+        assert_eq!(res, Err(Error::CostsRejected("currency entry usd of 400 is over the limit of 300".into())));
+    }
+}
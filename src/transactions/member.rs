@@ -1,27 +1,54 @@
 //! Membership is a link between a user and a company, which comes with certain
 //! privileges (such as company ownership).
 //!
+//! Generally new members are brought in via an invite: the company sends a
+//! [membership invite][2] (`invite()`), and the membership is only created once
+//! the invitee accepts (`accept_invite()`), giving their consent to join under
+//! the terms of the invite. The invitee can also decline (`reject_invite()`).
+//!
+//! When a member leaves, `offboard()` takes care of everything they leave
+//! behind (open commitments, in-progress work, final pay) in one go.
+//!
+//! Members can also lend their payroll approval to another member for a
+//! limited time (`delegate()`), for instance to have someone cover for them
+//! while they're away. See the [delegation model][3].
+//!
 //! See the [company member model.][1]
 //!
 //! [1]: ../../models/member/index.html
+//! [2]: ../../models/membership_invite/index.html
+//! [3]: ../../models/delegation/index.html
 
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
+    costs::Costs,
     error::{Error, Result},
     models::{
         Op,
         Modifications,
+        commitment::Commitment,
         company::{Company, Permission as CompanyPermission},
+        company_role::CompanyRole,
+        delegation::{DELEGABLE_PERMISSIONS, Delegation, DelegationID},
+        event::{Event, EventError, EventID, EventProcessState},
         member::{Compensation, Member, MemberID, MemberClass},
+        membership_invite::{InviteStatus, MembershipInvite, MembershipInviteID},
         lib::{
             agent::Agent,
             basis_model::Model,
         },
-        occupation::OccupationID,
+        occupation::{Occupation, OccupationID},
+        process::Process,
         user::User,
     },
+    transactions::event::work,
 };
+use getset::Getters;
+use om2::{Measure, Unit};
+use rust_decimal::prelude::*;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
 use url::Url;
 use vf_rs::vf;
 
@@ -70,7 +97,7 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Me
     if let Some(occupation_id) = occupation_id {
         match subject.class_mut() {
             MemberClass::Worker(worker) => {
-                worker.set_occupation(occupation_id);
+                worker.set_primary_occupation(occupation_id);
             }
             _ => Err(Error::MemberMustBeWorker)?,
         }
@@ -101,8 +128,76 @@ pub fn set_permissions(caller: &User, member: &Member, company: &Company, mut su
     Ok(Modifications::new_single(Op::Update, subject))
 }
 
-/// Set a member's compensation.
-pub fn set_compensation(caller: &User, member: &Member, company: &Company, mut subject: Member, compensation: Compensation, now: &DateTime<Utc>) -> Result<Modifications> {
+/// Assign a set of company roles to a member, replacing any roles they had
+/// before. All roles must belong to the member's company.
+///
+/// `previous_roles` must be the roles the member currently holds: along with
+/// the new `roles`, they're updated to track which members they're assigned to.
+pub fn set_roles(caller: &User, member: &Member, company: &Company, mut subject: Member, roles: &[CompanyRole], previous_roles: &[CompanyRole], now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MemberSetPermissions)?;
+    if company.id() != &subject.company_id()? {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    for role in roles.iter().chain(previous_roles.iter()) {
+        if role.company_id() != company.id() {
+            Err(Error::InsufficientPrivileges)?;
+        }
+    }
+    for role in roles {
+        if role.is_deleted() {
+            Err(Error::ObjectIsDeleted("company_role".into()))?;
+        }
+    }
+    let mut previous_ids = previous_roles.iter().map(|role| role.id().as_str()).collect::<Vec<_>>();
+    previous_ids.sort();
+    previous_ids.dedup();
+    let mut held_ids = subject.role_ids().into_iter().map(|id| id.as_str()).collect::<Vec<_>>();
+    held_ids.sort();
+    if previous_ids.len() != previous_roles.len() || previous_ids != held_ids {
+        Err(Error::RoleAssignmentMismatch)?;
+    }
+
+    let mut updated_roles = Vec::new();
+    for role in previous_roles {
+        if roles.iter().any(|new_role| new_role.id() == role.id()) {
+            continue;
+        }
+        let mut role = role.clone();
+        role.unassign(subject.id());
+        role.set_updated(now.clone());
+        updated_roles.push(role);
+    }
+    let mut assigned = Vec::with_capacity(roles.len());
+    for role in roles {
+        let mut role = role.clone();
+        if role.assign(subject.id()) {
+            role.set_updated(now.clone());
+            updated_roles.push(role.clone());
+        }
+        assigned.push(role);
+    }
+
+    subject.set_roles(assigned);
+    subject.set_updated(now.clone());
+    let mut mods = Modifications::new_single(Op::Update, subject);
+    for role in updated_roles {
+        mods.push(Op::Update, role);
+    }
+    Ok(mods)
+}
+
+/// Set a member's compensation, effective as of `effective`.
+///
+/// The new compensation is appended to the member's compensation history
+/// rather than overwriting it, so past (or future) rates are kept around. The
+/// member's current compensation is set to whichever entry is in effect `now`.
+/// If the member had a compensation from before the history was kept, it's
+/// recorded as having been in effect since the member was created.
+pub fn set_compensation(caller: &User, member: &Member, company: &Company, mut subject: Member, compensation: Compensation, effective: DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateMembers)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::MemberSetCompensation)?;
     if company.id() != &subject.company_id()? {
@@ -112,9 +207,63 @@ pub fn set_compensation(caller: &User, member: &Member, company: &Company, mut s
         Err(Error::ObjectIsInactive("company".into()))?;
     }
 
+    let created = subject.created().clone();
+    match subject.class_mut() {
+        MemberClass::Worker(worker) => {
+            let mut history = worker.compensation_history().clone();
+            if let (true, Some(existing)) = (history.is_empty(), worker.compensation()) {
+                history.push(created.clone(), existing.clone());
+            }
+            history.push(effective, compensation);
+            worker.set_compensation(history.at(now).cloned());
+            worker.set_compensation_history(history);
+        }
+        _ => Err(Error::MemberMustBeWorker)?,
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Give a worker member an additional occupation. The member can then record
+/// labor under this occupation as well as their primary one.
+pub fn add_occupation(caller: &User, member: &Member, company: &Company, mut subject: Member, occupation: &Occupation, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MemberUpdate)?;
+    if company.id() != &subject.company_id()? {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if occupation.is_deleted() {
+        Err(Error::ObjectIsDeleted("occupation".into()))?;
+    }
+
+    match subject.class_mut() {
+        MemberClass::Worker(worker) => {
+            worker.add_occupation(occupation.id().clone());
+        }
+        _ => Err(Error::MemberMustBeWorker)?,
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Remove one of a worker member's additional occupations. A worker's primary
+/// occupation can't be removed, only replaced (via `update()`).
+pub fn remove_occupation(caller: &User, member: &Member, company: &Company, mut subject: Member, occupation_id: &OccupationID, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MemberUpdate)?;
+    if company.id() != &subject.company_id()? {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+
     match subject.class_mut() {
         MemberClass::Worker(worker) => {
-            worker.set_compensation(Some(compensation));
+            worker.remove_occupation(occupation_id)?;
         }
         _ => Err(Error::MemberMustBeWorker)?,
     }
@@ -140,6 +289,365 @@ pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: Me
     Ok(Modifications::new_single(Op::Delete, subject))
 }
 
+/// The final pay owed to a member leaving a company (for instance, for work
+/// they haven't been paid for yet or unused leave). See [offboard].
+///
+/// [offboard]: fn.offboard.html
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Settlement {
+    /// The ID of the work event recording the settlement
+    id: EventID,
+    /// The process the settlement's labor costs are attributed to
+    process: Process,
+    /// The wage amount of the settlement
+    amount: Decimal,
+}
+
+impl Settlement {
+    /// Create a new settlement
+    pub fn new(id: EventID, process: Process, amount: Decimal) -> Self {
+        Self {
+            id,
+            process,
+            amount,
+        }
+    }
+}
+
+/// A member taking over the in-progress work of a member leaving the company.
+/// See [offboard].
+///
+/// [offboard]: fn.offboard.html
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct Reassignment {
+    /// The member taking over
+    member: Member,
+    /// The IDs of the work sessions opened for the member, one for each of the
+    /// departing member's open sessions
+    ids: Vec<EventID>,
+    /// The member's own work events, so we can make sure the new sessions
+    /// don't overlap them
+    sessions: Vec<Event>,
+}
+
+impl Reassignment {
+    /// Create a new reassignment
+    pub fn new(member: Member, ids: Vec<EventID>, sessions: Vec<Event>) -> Self {
+        Self {
+            member,
+            ids,
+            sessions,
+        }
+    }
+}
+
+/// Offboard a member that is leaving the company.
+///
+/// This deactivates the member and cleans up after them:
+///
+/// - Any of the member's `commitments` that are unfinished work commitments
+///   are cancelled (other commitments are ignored, so it's fine to pass in all of
+///   the member's commitments).
+/// - The member's in-progress (pending) work events, given as `open_events`,
+///   are cancelled. Pending events haven't had their labor attributed yet, so
+///   cancelling them doesn't need to unwind anything: pay owed for that work
+///   should be included in the `settlement`. If someone is taking the work
+///   over (`reassign_to`), a fresh session is opened for them on the same
+///   process starting now, as long as it doesn't overlap their own sessions.
+/// - The member is removed from the company roles they hold, which must be
+///   passed in as `roles`.
+/// - If a `settlement` is given, a final work event is recorded for its amount
+///   (under the member's primary occupation, with no hours) so the member is
+///   paid out through the normal payroll process. This requires the `Payroll`
+///   permission.
+pub fn offboard(caller: &User, member: &Member, company: &Company, mut subject: Member, commitments: Vec<Commitment>, open_events: Vec<Event>, reassign_to: Option<Reassignment>, roles: &[CompanyRole], settlement: Option<Settlement>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MemberDelete)?;
+    if settlement.is_some() {
+        member.access_check(caller.id(), company.id(), CompanyPermission::Payroll)?;
+    }
+    if company.id() != &subject.company_id()? {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("member".into()))?;
+    }
+    if let Some(reassign_to) = reassign_to.as_ref() {
+        if company.id() != &reassign_to.member().company_id()? || reassign_to.member().id() == subject.id() {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        if !reassign_to.member().is_active() {
+            Err(Error::ObjectIsInactive("member".into()))?;
+        }
+        if reassign_to.ids().len() != open_events.len() {
+            Err(Error::MissingFields(vec!["reassign_to::ids".into()]))?;
+        }
+    }
+    let mut role_ids = roles.iter().map(|role| role.id()).collect::<Vec<_>>();
+    role_ids.sort();
+    role_ids.dedup();
+    let mut held_ids = subject.role_ids();
+    held_ids.sort();
+    if role_ids.len() != roles.len() || role_ids != held_ids {
+        Err(Error::RoleAssignmentMismatch)?;
+    }
+    let subject_agent = subject.agent_id();
+    let mut mods = Modifications::new();
+
+    for mut commitment in commitments {
+        if commitment.inner().action() != &vf::Action::Work || commitment.inner().provider() != &subject_agent {
+            continue;
+        }
+        if commitment.is_deleted() || commitment.inner().finished() == &Some(true) {
+            continue;
+        }
+        commitment.set_deleted(Some(now.clone()));
+        mods.push(Op::Delete, commitment);
+    }
+
+    let (reassign_to, mut reassigned_ids, mut reassigned_sessions) = match reassign_to {
+        Some(Reassignment { member, ids, sessions }) => (Some(member), ids.into_iter(), sessions),
+        None => (None, vec![].into_iter(), vec![]),
+    };
+    for mut event in open_events {
+        if event.inner().provider() != &subject_agent {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        if event.is_deleted() || event.inner().has_beginning().is_none() || event.inner().has_end().is_some() {
+            Err(EventError::NotPending)?;
+        }
+        if let (Some(reassign_to), Some(id)) = (reassign_to.as_ref(), reassigned_ids.next()) {
+            work::check_overlap(&reassigned_sessions, reassign_to, None, now, None)?;
+            let mut inner = event.inner().clone();
+            inner.set_agreed_in(reassign_to.agreement().clone());
+            inner.set_has_beginning(Some(*now));
+            inner.set_provider(reassign_to.agent_id());
+            let session = Event::builder()
+                .id(id)
+                .inner(inner)
+                .active(true)
+                .created(*now)
+                .updated(*now)
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?;
+            reassigned_sessions.push(session.clone());
+            mods.push(Op::Create, session);
+        }
+        event.set_deleted(Some(*now));
+        mods.push(Op::Delete, event);
+    }
+
+    for role in roles {
+        let mut role = role.clone();
+        role.unassign(subject.id());
+        role.set_updated(*now);
+        mods.push(Op::Update, role);
+    }
+
+    if let Some(Settlement { id, process, amount }) = settlement {
+        if amount <= Decimal::zero() {
+            Err(Error::InvalidAmount(amount))?;
+        }
+        let occupation_id = subject.occupation_id().ok_or(Error::MemberMustBeWorker)?.clone();
+        let process_id = process.id().clone();
+        let event = Event::builder()
+            .id(id)
+            .inner(
+                vf::EconomicEvent::builder()
+                    .action(vf::Action::Work)
+                    .agreed_in(subject.agreement().clone())
+                    .effort_quantity(Some(Measure::new(Decimal::zero(), Unit::Hour)))
+                    .has_point_in_time(Some(now.clone()))
+                    .input_of(Some(process_id))
+                    .note(Some("final settlement".into()))
+                    .provider(subject.id().clone())
+                    .receiver(company.id().clone())
+                    .build()
+                    .map_err(|e| Error::BuilderFailed(e))?
+            )
+            .move_costs(Some(Costs::new_with_labor(occupation_id, amount)))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))?;
+        let state = EventProcessState::builder()
+            .input_of(process)
+            .provider(subject.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))?;
+        let evmods = event.process(state, now)?.into_vec();
+        mods.push(Op::Create, event);
+        for evmod in evmods {
+            mods.push_raw(evmod);
+        }
+    }
+
+    subject.set_roles(vec![]);
+    subject.set_active(false);
+    subject.set_updated(now.clone());
+    mods.push(Op::Update, subject);
+    Ok(mods)
+}
+
+/// Lend some of our permissions to another member of the company until
+/// `expires`. We can only delegate permissions we hold ourselves, and only the
+/// [delegable permissions][1] (payroll approval) can be delegated.
+///
+/// [1]: ../../models/delegation/constant.DELEGABLE_PERMISSIONS.html
+pub fn delegate(caller: &User, member: &Member, company: &Company, id: DelegationID, to_member: &Member, permissions: Vec<CompanyPermission>, expires: DateTime<Utc>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    if permissions.is_empty() {
+        Err(Error::MissingFields(vec!["permissions".into()]))?;
+    }
+    for permission in &permissions {
+        member.access_check(caller.id(), company.id(), permission.clone())?;
+    }
+    if company.id() != &to_member.company_id()? {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !to_member.is_active() {
+        Err(Error::ObjectIsInactive("member".into()))?;
+    }
+    let delegable = permissions.iter().all(|permission| DELEGABLE_PERMISSIONS.contains(permission));
+    if to_member.id() == member.id() || !delegable || &expires <= now {
+        Err(Error::DelegationInvalid)?;
+    }
+    let model = Delegation::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .from_member(member.id().clone())
+        .to_member(to_member.id().clone())
+        .permissions(permissions)
+        .expires(expires)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Revoke a delegation before it expires. This can be done by the member who
+/// made the delegation, or by anyone who can set member permissions.
+pub fn revoke_delegation(caller: &User, member: &Member, company: &Company, mut subject: Delegation, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    if member.id() != subject.from_member() {
+        member.access_check(caller.id(), company.id(), CompanyPermission::MemberSetPermissions)?;
+    } else if member.member_id() != &caller.id().clone().into() || member.group_id() != &company.id().clone().into() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("delegation".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+/// Invite a user to become a member of a company.
+pub fn invite(caller: &User, member: &Member, company: &Company, id: MembershipInviteID, invitee: &User, class: MemberClass, permissions: Vec<CompanyPermission>, agreement: Option<Url>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::MemberCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if !invitee.is_active() {
+        Err(Error::ObjectIsInactive("user".into()))?;
+    }
+    let model = MembershipInvite::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .invitee(invitee.id().clone())
+        .invited_by(caller.id().clone())
+        .class(class)
+        .permissions(permissions)
+        .agreement(agreement)
+        .status(InviteStatus::Pending)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Make sure an invite can be responded to by the caller.
+fn invite_response_check(caller: &User, subject: &MembershipInvite) -> Result<()> {
+    caller.access_check(Permission::CompanyUpdateMembers)?;
+    if subject.invitee() != caller.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("membership_invite".into()))?;
+    }
+    if !subject.is_pending() {
+        Err(Error::InviteNotPending)?;
+    }
+    Ok(())
+}
+
+/// Accept an invite, creating the new membership. Only the invitee can accept
+/// an invite.
+pub fn accept_invite(caller: &User, company: &Company, mut subject: MembershipInvite, member_id: MemberID, now: &DateTime<Utc>) -> Result<Modifications> {
+    invite_response_check(caller, &subject)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    let model = Member::builder()
+        .id(member_id)
+        .inner(
+            vf::AgentRelationship::builder()
+                .subject(caller.agent_id())
+                .object(company.agent_id())
+                .relationship(())
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .class(subject.class().clone())
+        .permissions(subject.permissions().clone())
+        .agreement(subject.agreement().clone())
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    subject.set_status(InviteStatus::Accepted);
+    subject.set_responded(Some(now.clone()));
+    subject.set_updated(now.clone());
+    let mut mods = Modifications::new();
+    mods.push(Op::Update, subject);
+    mods.push(Op::Create, model);
+    Ok(mods)
+}
+
+/// Decline an invite. Only the invitee can reject an invite.
+pub fn reject_invite(caller: &User, mut subject: MembershipInvite, now: &DateTime<Utc>) -> Result<Modifications> {
+    invite_response_check(caller, &subject)?;
+    subject.set_status(InviteStatus::Rejected);
+    subject.set_responded(Some(now.clone()));
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,10 +655,15 @@ mod tests {
         models::{
             member::*,
             account::AccountID,
+            commitment::CommitmentID,
+            company::CompanyID,
+            company_role::CompanyRoleID,
+            delegation::Delegation,
             lib::{
                 agent::Agent,
                 basis_model::Model,
             },
+            process::ProcessID,
             user::UserID,
         },
         util::{self, test::{self, *}},
@@ -280,6 +793,87 @@ mod tests {
         assert!(res.is_ok());
     }
 
+    #[test]
+    fn can_set_roles() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MemberSetPermissions], &now);
+        let new_user = make_user(&UserID::create(), None, &now);
+        let member = make_member_worker(&MemberID::create(), new_user.id(), state.company().id(), &OccupationID::create(), vec![], &now);
+        state.model = Some(member);
+        let role = CompanyRole::builder()
+            .id(CompanyRoleID::create())
+            .company_id(state.company().id().clone())
+            .name("bookkeeper")
+            .permissions(vec![CompanyPermission::Payroll])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<Member, Member>, roles: Vec<CompanyRole>, previous_roles: Vec<CompanyRole>| {
+            set_roles(state.user(), state.member(), state.company(), state.model().clone(), &roles, &previous_roles, &now2)
+        };
+        let testfn = |state: &TestState<Member, Member>| {
+            testfn_inner(state, vec![role.clone()], vec![])
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let member2 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        let role_assigned = mods[1].clone().expect_op::<CompanyRole>(Op::Update).unwrap();
+        assert_eq!(role_assigned.member_ids(), &vec![member2.id().clone()]);
+        assert_eq!(role_assigned.updated(), &now2);
+        assert_eq!(member2.roles(), &vec![role_assigned.clone()]);
+        assert_eq!(member2.permissions().len(), 0);
+        assert!(member2.access_check(new_user.id(), state.company().id(), CompanyPermission::Payroll).is_ok());
+        assert_eq!(member2.updated(), &now2);
+
+        // swapping the role out for another unassigns it
+        let role_other = CompanyRole::builder()
+            .id(CompanyRoleID::create())
+            .company_id(state.company().id().clone())
+            .name("floor lead")
+            .permissions(vec![CompanyPermission::Produce])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let mut state2 = state.clone();
+        state2.model = Some(member2.clone());
+        let mods = testfn_inner(&state2, vec![role_other.clone()], vec![role_assigned.clone()]).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let member3 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        let role_unassigned = mods[1].clone().expect_op::<CompanyRole>(Op::Update).unwrap();
+        let role_other2 = mods[2].clone().expect_op::<CompanyRole>(Op::Update).unwrap();
+        assert_eq!(role_unassigned.id(), role.id());
+        assert_eq!(role_unassigned.member_ids().len(), 0);
+        assert_eq!(role_other2.member_ids(), &vec![member3.id().clone()]);
+        assert_eq!(member3.roles(), &vec![role_other2.clone()]);
+        assert!(member3.access_check(new_user.id(), state.company().id(), CompanyPermission::Payroll).is_err());
+
+        // keeping a role doesn't touch it
+        let mods = testfn_inner(&state2, vec![role_assigned.clone()], vec![role_assigned.clone()]).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+
+        // the previous roles must be the ones the member holds
+        let res = testfn_inner(&state2, vec![role_other.clone()], vec![]);
+        assert_eq!(res, Err(Error::RoleAssignmentMismatch));
+        let res = testfn_inner(&state2, vec![role_other.clone()], vec![role_other.clone()]);
+        assert_eq!(res, Err(Error::RoleAssignmentMismatch));
+
+        let mut role2 = role.clone();
+        role2.set_company_id(CompanyID::create());
+        let res = testfn_inner(&state, vec![role2], vec![]);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut role3 = role.clone();
+        role3.set_deleted(Some(now.clone()));
+        let res = testfn_inner(&state, vec![role3], vec![]);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("company_role".into())));
+    }
+
     #[test]
     fn can_set_compensation() {
         let now = util::time::now();
@@ -295,7 +889,7 @@ mod tests {
         let compensation = Compensation::new_hourly(32 as u32, AccountID::create());
         let now2 = util::time::now();
         let testfn = |state: &TestState<Member, Member>| {
-            set_compensation(state.user(), state.member(), state.company(), state.model().clone(), compensation.clone(), &now2)
+            set_compensation(state.user(), state.member(), state.company(), state.model().clone(), compensation.clone(), now2.clone(), &now2)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -305,8 +899,29 @@ mod tests {
         assert_eq!(state.model().compensation(), None);
         assert_eq!(member2.compensation().unwrap().wage(), &Measure::new(num!(32), Unit::Hour));
         assert_eq!(member2.compensation().unwrap(), &compensation);
+        assert_eq!(member2.compensation_at(&now2), Some(&compensation));
+        assert_eq!(member2.compensation_at(&now), None);
         assert_eq!(member2.updated(), &now2);
 
+        // a raise, effective in the future, doesn't replace the current
+        // compensation yet and keeps the old one in the history
+        let raise = Compensation::new_hourly(40 as u32, AccountID::create());
+        let next_year: DateTime<Utc> = now2 + chrono::Duration::days(365);
+        let mods = set_compensation(state.user(), state.member(), state.company(), member2.clone(), raise.clone(), next_year.clone(), &now2).unwrap().into_vec();
+        let member3 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(member3.compensation(), Some(&compensation));
+        assert_eq!(member3.compensation_at(&now2), Some(&compensation));
+        assert_eq!(member3.compensation_at(&next_year), Some(&raise));
+
+        // existing compensation from before the history gets recorded
+        let mut state3 = state.clone();
+        state3.model_mut().set_class(MemberClass::Worker(MemberWorker::new(occupation_id.clone(), Some(raise.clone()))));
+        let mods = testfn(&state3).unwrap().into_vec();
+        let member4 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(member4.compensation(), Some(&compensation));
+        assert_eq!(member4.compensation_at(&now), Some(&raise));
+        assert_eq!(member4.compensation_at(&now2), Some(&compensation));
+
         let mut state2 = state.clone();
         state2.model_mut().set_class(MemberClass::User(MemberUser::new()));
         let res = testfn(&state2);
@@ -316,6 +931,68 @@ mod tests {
         assert_eq!(res, Err(Error::MemberMustBeWorker));
     }
 
+    #[test]
+    fn can_add_remove_occupations() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MemberUpdate], &now);
+        let primary = OccupationID::new("machinist");
+        let mut worker = state.member().clone();
+        worker.set_id(MemberID::create());
+        worker.set_class(MemberClass::Worker(MemberWorker::new(primary.clone(), None)));
+        state.model = Some(worker);
+        let welder = make_occupation(&OccupationID::new("welder"), "welder", None, &now);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Member, Member>| {
+            add_occupation(state.user(), state.member(), state.company(), state.model().clone(), &welder, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let member2 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(member2.occupation_id(), Some(&primary));
+        assert_eq!(member2.occupation_ids(), vec![&primary, welder.id()]);
+        assert!(member2.has_occupation(welder.id()));
+        assert_eq!(member2.updated(), &now2);
+
+        // adding twice doesn't duplicate
+        let mods = add_occupation(state.user(), state.member(), state.company(), member2.clone(), &welder, &now2).unwrap().into_vec();
+        let member3 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(member3.occupation_ids(), vec![&primary, welder.id()]);
+
+        let mut welder2 = welder.clone();
+        welder2.set_deleted(Some(now2.clone()));
+        let res = add_occupation(state.user(), state.member(), state.company(), state.model().clone(), &welder2, &now2);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("occupation".into())));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_class(MemberClass::User(MemberUser::new()));
+        assert_eq!(testfn(&state2), Err(Error::MemberMustBeWorker));
+
+        let testfn_remove = |state: &TestState<Member, Member>| {
+            remove_occupation(state.user(), state.member(), state.company(), state.model().clone(), welder.id(), &now2)
+        };
+        let mut state3 = state.clone();
+        state3.model = Some(member2.clone());
+        test::standard_transaction_tests(&state3, &testfn_remove);
+
+        let mods = testfn_remove(&state3).unwrap().into_vec();
+        let member4 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(member4.occupation_ids(), vec![&primary]);
+        assert!(!member4.has_occupation(welder.id()));
+
+        assert_eq!(testfn_remove(&state), Err(Error::OccupationMismatch));
+        let res = remove_occupation(state.user(), state.member(), state.company(), member2.clone(), &primary, &now2);
+        assert_eq!(res, Err(Error::OccupationIsPrimary));
+
+        // promoting an additional occupation to primary moves it out of the
+        // list
+        let mods = update(state.user(), state.member(), state.company(), member2.clone(), Some(welder.id().clone()), None, None, &now2).unwrap().into_vec();
+        let member5 = mods[0].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(member5.occupation_ids(), vec![welder.id()]);
+    }
+
     #[test]
     fn can_delete() {
         let now = util::time::now();
@@ -343,5 +1020,395 @@ mod tests {
         assert!(member2.active());
         assert!(!member2.is_active());
     }
-}
 
+    #[test]
+    fn can_delegate() {
+        let now = util::time::now();
+        let expires = now.clone() + chrono::Duration::days(14);
+        let mut state = TestState::standard(vec![CompanyPermission::Payroll, CompanyPermission::MemberCreate], &now);
+        let occupation_id = OccupationID::create();
+        let user2_id = UserID::create();
+        let coworker = make_member_worker(&MemberID::create(), &user2_id, state.company().id(), &occupation_id, vec![], &now);
+        state.model = Some(coworker.clone());
+        let id = DelegationID::create();
+
+        let testfn = |state: &TestState<Member, Member>| {
+            delegate(state.user(), state.member(), state.company(), id.clone(), state.model(), vec![CompanyPermission::Payroll], expires.clone(), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let delegation = mods[0].clone().expect_op::<Delegation>(Op::Create).unwrap();
+        assert_eq!(delegation.id(), &id);
+        assert_eq!(delegation.company_id(), state.company().id());
+        assert_eq!(delegation.from_member(), state.member().id());
+        assert_eq!(delegation.to_member(), coworker.id());
+        assert_eq!(delegation.permissions(), &vec![CompanyPermission::Payroll]);
+        assert_eq!(delegation.expires(), &expires);
+        assert_eq!(delegation.active(), &true);
+        assert_eq!(delegation.created(), &now);
+
+        // the coworker can run payroll while the delegation is in effect
+        let delegations = vec![delegation.clone()];
+        let grantors = vec![state.member().clone()];
+        assert_eq!(coworker.access_check(&user2_id, state.company().id(), CompanyPermission::Payroll), Err(Error::InsufficientPrivileges));
+        assert_eq!(coworker.access_check_delegated(&user2_id, state.company().id(), CompanyPermission::Payroll, &delegations, &grantors, &now), Ok(()));
+        assert_eq!(coworker.access_check_delegated(&user2_id, state.company().id(), CompanyPermission::MemberCreate, &delegations, &grantors, &now), Err(Error::InsufficientPrivileges));
+        assert_eq!(coworker.access_check_delegated(&user2_id, state.company().id(), CompanyPermission::Payroll, &delegations, &grantors, &expires), Err(Error::InsufficientPrivileges));
+        assert_eq!(coworker.access_check_delegated(&UserID::create(), state.company().id(), CompanyPermission::Payroll, &delegations, &grantors, &now), Err(Error::InsufficientPrivileges));
+        // the delegation lapses if the grantor loses the permission
+        let mut grantor2 = state.member().clone();
+        grantor2.set_permissions(vec![CompanyPermission::MemberCreate]);
+        assert_eq!(coworker.access_check_delegated(&user2_id, state.company().id(), CompanyPermission::Payroll, &delegations, &vec![grantor2], &now), Err(Error::InsufficientPrivileges));
+        assert_eq!(coworker.access_check_delegated(&user2_id, state.company().id(), CompanyPermission::Payroll, &delegations, &vec![], &now), Err(Error::InsufficientPrivileges));
+
+        // can't delegate what we don't have
+        let res = delegate(state.user(), state.member(), state.company(), id.clone(), state.model(), vec![CompanyPermission::MemberDelete], expires.clone(), &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let res = delegate(state.user(), state.member(), state.company(), id.clone(), state.model(), vec![], expires.clone(), &now);
+        assert_eq!(res, Err(Error::MissingFields(vec!["permissions".into()])));
+        let res = delegate(state.user(), state.member(), state.company(), id.clone(), state.model(), vec![CompanyPermission::Payroll], now.clone(), &now);
+        assert_eq!(res, Err(Error::DelegationInvalid));
+        let res = delegate(state.user(), state.member(), state.company(), id.clone(), state.member(), vec![CompanyPermission::Payroll], expires.clone(), &now);
+        assert_eq!(res, Err(Error::DelegationInvalid));
+        // only payroll approval can be delegated
+        let res = delegate(state.user(), state.member(), state.company(), id.clone(), state.model(), vec![CompanyPermission::Payroll, CompanyPermission::MemberCreate], expires.clone(), &now);
+        assert_eq!(res, Err(Error::DelegationInvalid));
+
+        let mut state2 = state.clone();
+        state2.model_mut().inner_mut().set_object(CompanyID::new("zing").into());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_active(false);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsInactive("member".into())));
+    }
+
+    #[test]
+    fn can_revoke_delegation() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::Payroll], &now);
+        let coworker = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), &OccupationID::create(), vec![], &now);
+        let mods = delegate(state.user(), state.member(), state.company(), DelegationID::create(), &coworker, vec![CompanyPermission::Payroll], now.clone() + chrono::Duration::days(1), &now).unwrap().into_vec();
+        let delegation = mods[0].clone().expect_op::<Delegation>(Op::Create).unwrap();
+        state.model = Some(delegation);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Delegation, Delegation>| {
+            revoke_delegation(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::double_deleted_tester(&state, "delegation", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let delegation2 = mods[0].clone().expect_op::<Delegation>(Op::Delete).unwrap();
+        assert_eq!(delegation2.deleted(), &Some(now2.clone()));
+
+        // someone else needs MemberSetPermissions to revoke it
+        let mut state2 = state.clone();
+        state2.model_mut().set_from_member(MemberID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        state2.member_mut().set_permissions(vec![CompanyPermission::MemberSetPermissions]);
+        assert!(testfn(&state2).is_ok());
+    }
+
+    #[test]
+    fn can_offboard() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MemberDelete, CompanyPermission::Payroll], &now);
+        let occupation_id = OccupationID::create();
+        let mut subject = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), &occupation_id, vec![], &now);
+        let coworker = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), &occupation_id, vec![], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        let mut role = CompanyRole::builder()
+            .id(CompanyRoleID::create())
+            .company_id(state.company().id().clone())
+            .name("bookkeeper")
+            .permissions(vec![CompanyPermission::Payroll])
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        role.assign(subject.id());
+        subject.set_roles(vec![role.clone()]);
+        let roles = vec![role.clone()];
+        state.model = Some(subject.clone());
+
+        let make_commitment = |action: vf::Action, provider: &Member, finished: Option<bool>| {
+            Commitment::builder()
+                .id(CommitmentID::create())
+                .inner(
+                    vf::Commitment::builder()
+                        .action(action)
+                        .finished(finished)
+                        .input_of(Some(process.id().clone()))
+                        .provider(provider.agent_id())
+                        .receiver(state.company().agent_id())
+                        .build().unwrap()
+                )
+                .move_costs(Costs::new())
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        let make_pending = |provider: &Member| {
+            Event::builder()
+                .id(EventID::create())
+                .inner(
+                    vf::EconomicEvent::builder()
+                        .action(vf::Action::Work)
+                        .has_beginning(Some(now.clone()))
+                        .input_of(Some(process.id().clone()))
+                        .provider(provider.agent_id())
+                        .receiver(state.company().agent_id())
+                        .build().unwrap()
+                )
+                .move_costs(Some(Costs::new()))
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        let work_commitment = make_commitment(vf::Action::Work, &subject, Some(false));
+        let commitments = vec![
+            work_commitment.clone(),
+            make_commitment(vf::Action::Work, &subject, Some(true)),
+            make_commitment(vf::Action::Work, &coworker, None),
+            make_commitment(vf::Action::DeliverService, &subject, None),
+        ];
+        let pending = make_pending(&subject);
+        let session_id = EventID::create();
+        let settlement_id = EventID::create();
+
+        let now2 = util::time::now();
+        let reassignment = Reassignment::new(coworker.clone(), vec![session_id.clone()], vec![]);
+        let testfn = |state: &TestState<Member, Member>| {
+            offboard(state.user(), state.member(), state.company(), state.model().clone(), commitments.clone(), vec![pending.clone()], Some(reassignment.clone()), &roles, Some(Settlement::new(settlement_id.clone(), process.clone(), num!(450))), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "member", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 7);
+        let commitment2 = mods[0].clone().expect_op::<Commitment>(Op::Delete).unwrap();
+        assert_eq!(commitment2.id(), work_commitment.id());
+        assert_eq!(commitment2.deleted(), &Some(now2.clone()));
+        // the departing member's session ends, and a fresh one starts for
+        // whoever is taking over
+        let session = mods[1].clone().expect_op::<Event>(Op::Create).unwrap();
+        assert_eq!(session.id(), &session_id);
+        assert_eq!(session.inner().action(), &vf::Action::Work);
+        assert_eq!(session.inner().provider(), &coworker.agent_id());
+        assert_eq!(session.inner().input_of(), pending.inner().input_of());
+        assert_eq!(session.inner().has_beginning(), &Some(now2.clone()));
+        assert_eq!(session.inner().has_end(), &None);
+        assert_eq!(session.created(), &now2);
+        let pending2 = mods[2].clone().expect_op::<Event>(Op::Delete).unwrap();
+        assert_eq!(pending2.id(), pending.id());
+        assert_eq!(pending2.inner().provider(), &subject.agent_id());
+        assert_eq!(pending2.deleted(), &Some(now2.clone()));
+        let role2 = mods[3].clone().expect_op::<CompanyRole>(Op::Update).unwrap();
+        assert_eq!(role2.id(), role.id());
+        assert_eq!(role2.member_ids().len(), 0);
+        assert_eq!(role2.updated(), &now2);
+        let settlement = mods[4].clone().expect_op::<Event>(Op::Create).unwrap();
+        assert_eq!(settlement.id(), &settlement_id);
+        assert_eq!(settlement.inner().action(), &vf::Action::Work);
+        assert_eq!(settlement.inner().provider(), &subject.agent_id());
+        assert_eq!(settlement.inner().effort_quantity(), &Some(Measure::new(num!(0), Unit::Hour)));
+        assert_eq!(settlement.move_costs(), &Some(Costs::new_with_labor(occupation_id.clone(), num!(450))));
+        let process2 = mods[5].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.costs(), &Costs::new_with_labor(occupation_id.clone(), num!(450)));
+        let subject2 = mods[6].clone().expect_op::<Member>(Op::Update).unwrap();
+        assert_eq!(subject2.id(), subject.id());
+        assert_eq!(subject2.roles().len(), 0);
+        assert_eq!(subject2.active(), &false);
+        assert_eq!(subject2.updated(), &now2);
+
+        // without anyone to take over, pending work is cancelled
+        let mods = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![pending.clone()], None, &roles, None, &now2).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let pending3 = mods[0].clone().expect_op::<Event>(Op::Delete).unwrap();
+        assert_eq!(pending3.deleted(), &Some(now2.clone()));
+        mods[1].clone().expect_op::<CompanyRole>(Op::Update).unwrap();
+        mods[2].clone().expect_op::<Member>(Op::Update).unwrap();
+
+        // the new session can't overlap the coworker's own sessions
+        let busy = Reassignment::new(coworker.clone(), vec![session_id.clone()], vec![make_pending(&coworker)]);
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![pending.clone()], Some(busy), &roles, None, &now2);
+        assert_eq!(res, Err(Error::Event(EventError::WorkSessionOverlap)));
+        // and can only take over one session at a time
+        let double = Reassignment::new(coworker.clone(), vec![session_id.clone(), EventID::create()], vec![]);
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![pending.clone(), make_pending(&subject)], Some(double), &roles, None, &now2);
+        assert_eq!(res, Err(Error::Event(EventError::WorkSessionOverlap)));
+        // every open session needs an ID for its replacement
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![pending.clone()], Some(Reassignment::new(coworker.clone(), vec![], vec![])), &roles, None, &now2);
+        assert_eq!(res, Err(Error::MissingFields(vec!["reassign_to::ids".into()])));
+
+        // the roles given must be the ones the member holds
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![], None, &[], None, &now2);
+        assert_eq!(res, Err(Error::RoleAssignmentMismatch));
+
+        // only the member's own pending events can be handed off
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![make_pending(&coworker)], None, &roles, None, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let mut finished = pending.clone();
+        finished.inner_mut().set_has_end(Some(now.clone()));
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![finished], None, &roles, None, &now2);
+        assert_eq!(res, Err(Error::Event(EventError::NotPending)));
+
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![], Some(Reassignment::new(subject.clone(), vec![], vec![])), &roles, None, &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let mut coworker2 = coworker.clone();
+        coworker2.set_active(false);
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![], Some(Reassignment::new(coworker2, vec![], vec![])), &roles, None, &now2);
+        assert_eq!(res, Err(Error::ObjectIsInactive("member".into())));
+
+        let res = offboard(state.user(), state.member(), state.company(), subject.clone(), vec![], vec![], None, &roles, Some(Settlement::new(EventID::create(), process.clone(), num!(0))), &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        // settling up requires payroll permissions
+        let mut state2 = state.clone();
+        state2.member.as_mut().unwrap().set_permissions(vec![CompanyPermission::MemberDelete]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let res = offboard(state2.user(), state2.member(), state2.company(), subject.clone(), vec![], vec![], None, &roles, None, &now2);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn can_invite() {
+        let now = util::time::now();
+        let id = MembershipInviteID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::MemberCreate], &now);
+        let occupation_id = OccupationID::create();
+        let agreement: Url = "https://mydoc.com/work_agreement_1".parse().unwrap();
+        let new_class = MemberClass::Worker(MemberWorker::new(occupation_id.clone(), None));
+        state.model = Some(make_user(&UserID::create(), None, &now));
+
+        let testfn = |state: &TestState<User, MembershipInvite>| {
+            invite(state.user(), state.member(), state.company(), id.clone(), state.model(), new_class.clone(), vec![CompanyPermission::Work], Some(agreement.clone()), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let invite = mods[0].clone().expect_op::<MembershipInvite>(Op::Create).unwrap();
+        assert_eq!(invite.id(), &id);
+        assert_eq!(invite.company_id(), state.company().id());
+        assert_eq!(invite.invitee(), state.model().id());
+        assert_eq!(invite.invited_by(), state.user().id());
+        assert_eq!(invite.class(), &new_class);
+        assert_eq!(invite.permissions(), &vec![CompanyPermission::Work]);
+        assert_eq!(invite.agreement(), &Some(agreement.clone()));
+        assert_eq!(invite.status(), &InviteStatus::Pending);
+        assert_eq!(invite.responded(), &None);
+        assert!(invite.is_pending());
+        assert_eq!(invite.active(), &true);
+        assert_eq!(invite.created(), &now);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::ObjectIsInactive("user".into())));
+    }
+
+    #[test]
+    fn can_accept_invite() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MemberCreate], &now);
+        let invitee = make_user(&UserID::create(), None, &now);
+        let new_class = MemberClass::Worker(MemberWorker::new(OccupationID::create(), None));
+        let mods = invite(state.user(), state.member(), state.company(), MembershipInviteID::create(), &invitee, new_class.clone(), vec![CompanyPermission::Work], None, &now).unwrap().into_vec();
+        let invite = mods[0].clone().expect_op::<MembershipInvite>(Op::Create).unwrap();
+        state.user = Some(invitee);
+        state.model = Some(invite);
+
+        let member_id = MemberID::create();
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<MembershipInvite, Member>| {
+            accept_invite(state.user(), state.company(), state.model().clone(), member_id.clone(), &now2)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let invite2 = mods[0].clone().expect_op::<MembershipInvite>(Op::Update).unwrap();
+        let member = mods[1].clone().expect_op::<Member>(Op::Create).unwrap();
+        assert_eq!(invite2.status(), &InviteStatus::Accepted);
+        assert_eq!(invite2.responded(), &Some(now2.clone()));
+        assert_eq!(invite2.updated(), &now2);
+        assert!(!invite2.is_pending());
+        assert_eq!(member.id(), &member_id);
+        assert_eq!(member.inner().subject(), &state.user().agent_id());
+        assert_eq!(member.inner().object(), &state.company().agent_id());
+        assert_eq!(member.class(), &new_class);
+        assert_eq!(member.permissions(), &vec![CompanyPermission::Work]);
+        assert_eq!(member.active(), &true);
+        assert_eq!(member.created(), &now2);
+
+        // only the invitee can accept
+        let mut state2 = state.clone();
+        state2.user = Some(make_user(&UserID::create(), None, &now));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.user_mut().set_roles(vec![]);
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state4 = state.clone();
+        state4.model = Some(invite2.clone());
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::InviteNotPending));
+
+        let mut state5 = state.clone();
+        state5.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state5);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("membership_invite".into())));
+
+        let mut state6 = state.clone();
+        state6.company_mut().set_active(false);
+        let res = testfn(&state6);
+        assert_eq!(res, Err(Error::ObjectIsInactive("company".into())));
+    }
+
+    #[test]
+    fn can_reject_invite() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::MemberCreate], &now);
+        let invitee = make_user(&UserID::create(), None, &now);
+        let new_class = MemberClass::User(MemberUser::new());
+        let mods = invite(state.user(), state.member(), state.company(), MembershipInviteID::create(), &invitee, new_class, vec![], None, &now).unwrap().into_vec();
+        let invite = mods[0].clone().expect_op::<MembershipInvite>(Op::Create).unwrap();
+        state.user = Some(invitee);
+        state.model = Some(invite);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<MembershipInvite, MembershipInvite>| {
+            reject_invite(state.user(), state.model().clone(), &now2)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let invite2 = mods[0].clone().expect_op::<MembershipInvite>(Op::Update).unwrap();
+        assert_eq!(invite2.status(), &InviteStatus::Rejected);
+        assert_eq!(invite2.responded(), &Some(now2.clone()));
+        assert_eq!(invite2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model = Some(invite2);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InviteNotPending));
+
+        let mut state3 = state.clone();
+        state3.user = Some(make_user(&UserID::create(), None, &now));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
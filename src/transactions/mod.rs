@@ -12,8 +12,12 @@
 //! could (in theory) be used without needing to couple any of the logic to the
 //! storage mechanism.
 
+use vf_rs::vf;
+
 /// An action that happens between companies. This is used for intents and
 /// commitments.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OrderAction {
     /// A service will be delivered
     DeliverService,
@@ -23,18 +27,53 @@ pub enum OrderAction {
     TransferCustody,
 }
 
+impl OrderAction {
+    /// Get the ValueFlows action this order action maps to
+    pub fn to_action(&self) -> vf::Action {
+        match self {
+            OrderAction::DeliverService => vf::Action::DeliverService,
+            OrderAction::Transfer => vf::Action::Transfer,
+            OrderAction::TransferCustody => vf::Action::TransferCustody,
+        }
+    }
+
+    /// Map a ValueFlows action back into an order action, if it is one
+    pub fn from_action(action: &vf::Action) -> Option<Self> {
+        match action {
+            vf::Action::DeliverService => Some(OrderAction::DeliverService),
+            vf::Action::Transfer => Some(OrderAction::Transfer),
+            vf::Action::TransferCustody => Some(OrderAction::TransferCustody),
+            _ => None,
+        }
+    }
+}
+
 pub mod account;
 pub mod agreement;
+pub mod agreement_template;
 pub mod commitment;
 pub mod company;
+pub mod company_ledger;
+pub mod company_link;
+pub mod company_role;
+pub mod company_treasury;
+pub mod config;
 pub mod member;
 pub mod currency;
+pub mod debt;
 pub mod event;
+pub mod impact;
 pub mod intent;
+pub mod market;
 pub mod occupation;
+pub mod order;
 pub mod process;
 pub mod process_spec;
+pub mod region;
 pub mod resource;
+pub mod resource_group;
 pub mod resource_spec;
+pub mod service_subscription;
+pub mod transfer_policy;
 pub mod user;
 
@@ -13,14 +13,14 @@ use crate::{
         Op,
         Modifications,
         lib::basis_model::Model,
-        occupation::{Occupation, OccupationID},
+        occupation::{Occupation, OccupationID, WageRate},
         user::User,
     },
 };
 use vf_rs::vf;
 
 /// Create a new `Occupation`.
-pub fn create<T: Into<String>>(caller: &User, id: OccupationID, label: T, note: T, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn create<T: Into<String>>(caller: &User, id: OccupationID, label: T, note: T, wage_rate: Option<WageRate>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::OccupationCreate)?;
     let model = Occupation::builder()
         .id(id)
@@ -31,6 +31,7 @@ pub fn create<T: Into<String>>(caller: &User, id: OccupationID, label: T, note:
                 .build()
                 .map_err(|e| Error::BuilderFailed(e))?
         )
+        .wage_rate(wage_rate)
         .active(active)
         .created(now.clone())
         .updated(now.clone())
@@ -40,7 +41,7 @@ pub fn create<T: Into<String>>(caller: &User, id: OccupationID, label: T, note:
 }
 
 /// Update an existing `Occupation`
-pub fn update(caller: &User, mut subject: Occupation, label: Option<String>, note: Option<String>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn update(caller: &User, mut subject: Occupation, label: Option<String>, note: Option<String>, wage_rate: Option<Option<WageRate>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::OccupationUpdate)?;
     if let Some(label) = label {
         subject.inner_mut().set_role_label(label);
@@ -48,6 +49,9 @@ pub fn update(caller: &User, mut subject: Occupation, label: Option<String>, not
     if let Some(note) = note {
         subject.inner_mut().set_note(Some(note));
     }
+    if let Some(wage_rate) = wage_rate {
+        subject.set_wage_rate(wage_rate);
+    }
     if let Some(active) = active {
         subject.set_active(active);
     }
@@ -86,7 +90,7 @@ mod tests {
         state.user_mut().set_roles(vec![Role::SuperAdmin]);
 
         let testfn = |state: &TestState<Occupation, Occupation>| {
-            create(state.user(), id.clone(), "machinist", "builds things", true, &now)
+            create(state.user(), id.clone(), "machinist", "builds things", Some(WageRate::new(num!(20), num!(35), num!(0.05)).unwrap()), true, &now)
         };
 
         let mods = testfn(&state).unwrap().into_vec();
@@ -96,6 +100,7 @@ mod tests {
         assert_eq!(occupation.id(), &id);
         assert_eq!(occupation.inner().role_label(), "machinist");
         assert_eq!(occupation.inner().note(), &Some("builds things".into()));
+        assert_eq!(occupation.wage_rate(), &Some(WageRate::new(num!(20), num!(35), num!(0.05)).unwrap()));
         assert_eq!(occupation.active(), &true);
 
         let mut state2 = state.clone();
@@ -111,13 +116,13 @@ mod tests {
         let mut state = TestState::standard(vec![], &now);
         state.user_mut().set_roles(vec![Role::SuperAdmin]);
 
-        let mods = create(state.user(), id.clone(), "bone spurs in chief", "glorious leader", true, &now).unwrap().into_vec();
+        let mods = create(state.user(), id.clone(), "bone spurs in chief", "glorious leader", Some(WageRate::fixed(num!(400000), num!(0)).unwrap()), true, &now).unwrap().into_vec();
         let occupation = mods[0].clone().expect_op::<Occupation>(Op::Create).unwrap();
         state.model = Some(occupation);
 
         let now2 = util::time::now();
         let testfn = |state: &TestState<Occupation, Occupation>| {
-            update(state.user(), state.model().clone(), Some("coward".into()), None, None, &now2)
+            update(state.user(), state.model().clone(), Some("coward".into()), None, Some(None), None, &now2)
         };
 
         // not truly an update but ok
@@ -128,6 +133,7 @@ mod tests {
         assert_eq!(occupation2.updated(), &now2);
         assert_eq!(occupation2.inner().role_label(), "coward");
         assert_eq!(occupation2.inner().note(), &Some("glorious leader".into()));
+        assert_eq!(occupation2.wage_rate(), &None);
 
         let mut state2 = state.clone();
         state2.user_mut().set_roles(vec![Role::User]);
@@ -142,7 +148,7 @@ mod tests {
         let mut state = TestState::standard(vec![], &now);
         state.user_mut().set_roles(vec![Role::SuperAdmin]);
 
-        let mods = create(state.user(), id.clone(), "the best president", "false acquisitions", true, &now).unwrap().into_vec();
+        let mods = create(state.user(), id.clone(), "the best president", "false acquisitions", None, true, &now).unwrap().into_vec();
         let occupation = mods[0].clone().expect_op::<Occupation>(Op::Create).unwrap();
         state.model = Some(occupation);
 
@@ -0,0 +1,316 @@
+//! Orders are how members of the system get things from companies: a user
+//! spends credits from one of their accounts to acquire a resource a company
+//! has on offer.
+//!
+//! A company puts a resource up for purchase by publishing an [intent][1] with
+//! itself as the provider and the resource (or the resource's spec) as the
+//! subject. Purchasing transfers ownership and custody of the resource (along
+//! with its costs) to the user, debits the credit value of those costs from the
+//! user's account, and removes the costs from the company's `total_costs`. In
+//! other words, the credits the user spends and the costs the company carried
+//! cancel each other out and are destroyed.
+//!
+//! If the company's [treasury][2] is passed in, the destroyed credits are
+//! recorded there as well.
+//!
+//! [1]: ../../models/intent/index.html
+//! [2]: ../../models/company_treasury/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        account::Account,
+        company::Company,
+        company_treasury::CompanyTreasury,
+        event::{Event, EventID, EventProcessState},
+        intent::Intent,
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
+        resource::Resource,
+        user::User,
+    },
+    transactions::event::ResourceMover,
+    util::{measure, number::Ratio},
+};
+use om2::{Measure, NumericUnion};
+use rust_decimal::prelude::*;
+use vf_rs::vf;
+
+/// Make sure an offer (intent) is one the company has made for the given
+/// resource, and that it's still open.
+fn check_offer(company: &Company, offer: &Intent, resource: &Resource, now: &DateTime<Utc>) -> Result<()> {
+    if offer.inner().provider().as_ref() != Some(&company.agent_id()) {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let matches_resource = match (offer.inner().resource_inventoried_as(), offer.inner().resource_conforms_to()) {
+        (Some(resource_id), _) => resource_id == resource.id(),
+        (None, Some(spec_id)) => spec_id == resource.inner().conforms_to(),
+        (None, None) => false,
+    };
+    if !matches_resource {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !offer.is_available(now) {
+        Err(Error::IntentExpired)?;
+    }
+    Ok(())
+}
+
+/// Purchase some quantity of a resource from a company using the credits in
+/// one of the caller's accounts.
+///
+/// The costs moved to the user are proportional to the quantity purchased
+/// (buying 2 of 10 widgets moves 20% of the resource's costs), and the price
+/// paid is the credit value of those costs. The company's treasury records the
+/// price as destroyed and is returned updated after the company.
+pub fn purchase<T: Into<NumericUnion>>(caller: &User, account: Account, company: &Company, treasury: CompanyTreasury, offer: &Intent, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    purchase_for(caller, caller, account, company, treasury, offer, id, resource_from, resource_to, move_measure, note, now)
+}
+
+/// Purchase a resource using the caller's account, with the resource going to
+/// `receiver` (which is generally the caller, but see the [proxy] module).
+///
+/// [proxy]: ../../system/proxy/index.html
+pub(crate) fn purchase_for<T: Into<NumericUnion>>(caller: &User, receiver: &User, mut account: Account, company: &Company, mut treasury: CompanyTreasury, offer: &Intent, id: EventID, resource_from: Resource, resource_to: ResourceMover, move_measure: T, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::OrderPurchase)?;
+    if !account.user_ids().contains(caller.id()) {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if account.is_deleted() {
+        Err(Error::ObjectIsDeleted("account".into()))?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if treasury.company_id() != company.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if resource_from.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource".into()))?;
+    }
+    check_offer(company, offer, &resource_from, now)?;
+    resource_from.check_lot(now)?;
+
+    let unit = resource_from.get_unit().ok_or(Error::ResourceMeasureMissing)?;
+    let measure = Measure::new(move_measure, unit);
+    let total = resource_from.inner().accounting_quantity().as_ref().ok_or(Error::ResourceMeasureMissing)?;
+    let total = measure::to_decimal(total)?;
+    let amount = measure::to_decimal(&measure)?;
+    if amount <= Decimal::zero() {
+        Err(Error::InvalidAmount(amount))?;
+    }
+    if total <= Decimal::zero() {
+        Err(Error::ResourceMeasureMissing)?;
+    }
+    let move_costs = resource_from.costs().clone() * Ratio::new(amount / total)?;
+    let price = *move_costs.credits();
+
+    let resource_id = resource_from.id().clone();
+    let mut statebuilder = EventProcessState::builder()
+        .resource(resource_from);
+    let resource_to_id = match resource_to {
+        ResourceMover::Create(resource_id) => resource_id,
+        ResourceMover::Update(resource) => {
+            let resource_id = resource.id().clone();
+            statebuilder = statebuilder.to_resource(resource);
+            resource_id
+        }
+    };
+
+    let state = statebuilder
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    let event = Event::builder()
+        .id(id)
+        .inner(
+            vf::EconomicEvent::builder()
+                .action(vf::Action::Transfer)
+                .has_point_in_time(now.clone())
+                .note(note)
+                .provider(company.id().clone())
+                .receiver(receiver.id().clone())
+                .resource_inventoried_as(Some(resource_id))
+                .resource_quantity(Some(measure))
+                .to_resource_inventoried_as(Some(resource_to_id))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(Some(move_costs.clone()))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+
+    let evmods = event.process(state, now)?.into_vec();
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, event);
+    for evmod in evmods {
+        mods.push_raw(evmod);
+    }
+
+    account.move_credits(-price, now)?;
+    account.set_updated(now.clone());
+    let mut company_new = company.clone();
+    company_new.decrease_costs(move_costs)?;
+    company_new.set_updated(now.clone());
+    mods.push(Op::Update, account);
+    mods.push(Op::Update, company_new);
+    treasury.destroy(price)?;
+    treasury.set_updated(now.clone());
+    mods.push(Op::Update, treasury);
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        costs::Costs,
+        models::{
+            account::AccountID,
+            company::CompanyID,
+            event::EventError,
+            intent::IntentID,
+            resource::ResourceID,
+            resource_spec::ResourceSpecID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+    use om2::Unit;
+    use vf_rs::dfc;
+
+    fn make_offer(company: &Company, resource_id: Option<ResourceID>, spec_id: Option<ResourceSpecID>, now: &DateTime<Utc>) -> Intent {
+        Intent::builder()
+            .id(IntentID::create())
+            .inner(
+                vf::Intent::builder()
+                    .action(vf::Action::Transfer)
+                    .provider(Some(company.agent_id()))
+                    .resource_inventoried_as(resource_id)
+                    .resource_conforms_to(spec_id)
+                    .build().unwrap()
+            )
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap()
+    }
+
+    #[test]
+    fn can_purchase() {
+        let now = util::time::now();
+        let id = EventID::create();
+        let user = make_user(&UserID::create(), Some(vec![Role::User]), &now);
+        let account = make_account(&AccountID::create(), user.id(), num!(100), "jerry's account", &now);
+        let mut company = make_company(&CompanyID::create(), "jerry's widgets", &now);
+        let resource = make_resource(&ResourceID::new("widgets"), company.id(), &Measure::new(num!(10), Unit::One), &Costs::new_with_labor("machinist", num!(300)), &now);
+        company.set_total_costs(Costs::new_with_labor("machinist", num!(500)));
+        let treasury = make_company_treasury(company.id(), num!(1000), &now);
+        let offer = make_offer(&company, Some(resource.id().clone()), None, &now);
+        let user_resource_id = ResourceID::new("my widgets");
+
+        let testfn = |user: &User, account: &Account, company: &Company, offer: &Intent, resource: &Resource, quantity: i64| {
+            purchase(user, account.clone(), company, treasury.clone(), offer, id.clone(), resource.clone(), ResourceMover::Create(user_resource_id.clone()), quantity, Some("need some widgets".into()), &now)
+        };
+
+        let mods = testfn(&user, &account, &company, &offer, &resource, 2).unwrap().into_vec();
+        assert_eq!(mods.len(), 6);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource2 = mods[1].clone().expect_op::<Resource>(Op::Update).unwrap();
+        let user_resource = mods[2].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let account2 = mods[3].clone().expect_op::<Account>(Op::Update).unwrap();
+        let company2 = mods[4].clone().expect_op::<Company>(Op::Update).unwrap();
+        let treasury2 = mods[5].clone().expect_op::<CompanyTreasury>(Op::Update).unwrap();
+
+        assert_eq!(event.id(), &id);
+        assert_eq!(event.inner().action(), &vf::Action::Transfer);
+        assert_eq!(event.inner().provider().clone(), company.agent_id());
+        assert_eq!(event.inner().receiver().clone(), user.agent_id());
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(2, Unit::One)));
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(60))));
+
+        assert_eq!(resource2.inner().accounting_quantity(), &Some(Measure::new(num!(8), Unit::One)));
+        assert_eq!(resource2.costs(), &Costs::new_with_labor("machinist", num!(240)));
+        assert_eq!(user_resource.id(), &user_resource_id);
+        assert_eq!(user_resource.inner().primary_accountable(), &Some(user.agent_id()));
+        assert_eq!(user_resource.in_custody_of(), &user.agent_id());
+        assert_eq!(user_resource.inner().accounting_quantity(), &Some(Measure::new(num!(2), Unit::One)));
+        assert_eq!(user_resource.costs(), &Costs::new_with_labor("machinist", num!(60)));
+
+        assert_eq!(account2.balance(), &num!(40));
+        assert_eq!(account2.updated(), &now);
+        assert_eq!(company2.total_costs(), &Costs::new_with_labor("machinist", num!(440)));
+        // the company's treasury records the credits destroyed
+        assert_eq!(treasury2.destroyed(), &num!(60));
+        assert_eq!(treasury2.outstanding(), num!(940));
+        assert_eq!(treasury2.updated(), &now);
+
+        // offers can be made by resource spec as well
+        let offer_spec = make_offer(&company, None, Some(resource.inner().conforms_to().clone()), &now);
+        assert!(testfn(&user, &account, &company, &offer_spec, &resource, 2).is_ok());
+
+        // can't afford it
+        let res = testfn(&user, &account, &company, &offer, &resource, 4);
+        assert_eq!(res, Err(Error::NegativeAccountBalance));
+
+        // not our account
+        let user2 = make_user(&UserID::create(), Some(vec![Role::User]), &now);
+        let res = testfn(&user2, &account, &company, &offer, &resource, 2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let user3 = make_user(user.id(), Some(vec![Role::Guest]), &now);
+        let res = testfn(&user3, &account, &company, &offer, &resource, 2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // the company has to be offering the resource
+        let offer2 = make_offer(&make_company(&CompanyID::create(), "larry's widgets", &now), Some(resource.id().clone()), None, &now);
+        let res = testfn(&user, &account, &company, &offer2, &resource, 2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let offer3 = make_offer(&company, Some(ResourceID::new("gears")), None, &now);
+        let res = testfn(&user, &account, &company, &offer3, &resource, 2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        let mut offer4 = offer.clone();
+        offer4.set_expires_at(Some(now.clone()));
+        let res = testfn(&user, &account, &company, &offer4, &resource, 2);
+        assert_eq!(res, Err(Error::IntentExpired));
+
+        // the company needs to own the resource
+        let mut resource3 = resource.clone();
+        resource3.inner_mut().set_primary_accountable(Some(CompanyID::new("larry's widgets").into()));
+        let res = testfn(&user, &account, &company, &offer, &resource3, 2);
+        assert_eq!(res, Err(Error::Event(EventError::ResourceOwnerMismatch)));
+
+        let mut resource4 = resource.clone();
+        resource4.inner_mut().set_lot(Some(dfc::ProductBatch::builder().batch_number("A-113").expiry_date(Some(now.clone())).build().unwrap()));
+        let res = testfn(&user, &account, &company, &offer, &resource4, 2);
+        assert_eq!(res, Err(Error::LotExpired("A-113".into())));
+
+        let res = testfn(&user, &account, &company, &offer, &resource, 0);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        let mut company3 = company.clone();
+        company3.set_active(false);
+        let res = testfn(&user, &account, &company3, &offer, &resource, 2);
+        assert_eq!(res, Err(Error::ObjectIsInactive("company".into())));
+
+        let mut account3 = account.clone();
+        account3.set_deleted(Some(now.clone()));
+        let res = testfn(&user, &account3, &company, &offer, &resource, 2);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("account".into())));
+
+        // the treasury has to be the company's
+        let mut treasury3 = treasury.clone();
+        treasury3.set_company_id(CompanyID::create());
+        let res = purchase(&user, account.clone(), &company, treasury3, &offer, id.clone(), resource.clone(), ResourceMover::Create(user_resource_id.clone()), 2, None, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
@@ -12,10 +12,20 @@
 //! end. This is not always the case, but generally is. For a more general
 //! process "type" see the [process_spec][1] transactions.
 //!
+//! When a process is done, it should be [finished][finish] rather than just
+//! left alone (or deleted), which forces a decision on what happens to any
+//! costs still sitting in it.
+//!
+//! Processes can be created, updated, and deleted by members of a federation
+//! the company is [linked][3] to, as long as the link grants them the
+//! permission.
+//!
 //! See the [process model.][2]
 //!
 //! [1]: ../process_spec/index.html
 //! [2]: ../../models/process/index.html
+//! [3]: ../company_link/index.html
+//! [finish]: fn.finish.html
 
 use chrono::{DateTime, Utc};
 use crate::{
@@ -25,24 +35,49 @@ use crate::{
     models::{
         Op,
         Modifications,
+        Model as AnyModel,
+        commitment::{Commitment, CommitmentID},
         company::{Company, Permission as CompanyPermission},
+        company_link::CompanyLink,
+        event::EventID,
         member::Member,
         lib::{
-            agent::AgentID,
+            agent::{Agent, AgentID},
             basis_model::Model,
         },
         process::{Process, ProcessID},
-        process_spec::ProcessSpecID,
+        process_spec::{ProcessSpec, ProcessSpecID},
+        resource_spec::ResourceSpecID,
         user::User,
     },
+    transactions::event::accounting::{self, WriteOff},
+    util::number::Ratio,
 };
+use om2::{Measure, Unit};
+use rust_decimal::prelude::*;
 use url::Url;
 use vf_rs::vf;
 
-/// Create a new process
-pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, id: ProcessID, spec_id: ProcessSpecID, name: T, note: T, classifications: Vec<Url>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, in_scope_of: Vec<AgentID>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+/// What to do with the costs left in a process when it's [finished].
+///
+/// [finished]: fn.finish.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeftoverPolicy {
+    /// Refuse to finish the process if it still holds any costs
+    Block,
+    /// Move all leftover costs into another process, recorded by a move event
+    /// with the given id
+    MoveTo(Process, EventID),
+    /// Write all leftover costs off, recorded by a write-off event with the
+    /// given id
+    WriteOff(EventID),
+}
+
+/// Create a new process. The `links` are the company's federation links, and
+/// let members of its federations create processes for it.
+pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, links: &[CompanyLink], id: ProcessID, spec_id: ProcessSpecID, name: T, note: T, classifications: Vec<Url>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, in_scope_of: Vec<AgentID>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateProcesses)?;
-    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessCreate)?;
+    member.access_check_federated(caller.id(), company.id(), CompanyPermission::ProcessCreate, links)?;
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
@@ -62,6 +97,7 @@ pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company
         )
         .company_id(company.id().clone())
         .costs(Costs::new())
+        .labor_spent(Costs::new())
         .active(active)
         .created(now.clone())
         .updated(now.clone())
@@ -70,10 +106,16 @@ pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company
     Ok(Modifications::new_single(Op::Create, model))
 }
 
-/// Update a process
-pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Process, name: Option<String>, note: Option<String>, classifications: Option<Vec<Url>>, finished: Option<bool>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, in_scope_of: Option<Vec<AgentID>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+/// Update a process (see `create()` for `links`). A process can only be marked
+/// finished via [finish].
+///
+/// [finish]: fn.finish.html
+pub fn update(caller: &User, member: &Member, company: &Company, links: &[CompanyLink], mut subject: Process, name: Option<String>, note: Option<String>, classifications: Option<Vec<Url>>, has_beginning: Option<DateTime<Utc>>, has_end: Option<DateTime<Utc>>, in_scope_of: Option<Vec<AgentID>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateProcesses)?;
-    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessUpdate)?;
+    member.access_check_federated(caller.id(), company.id(), CompanyPermission::ProcessUpdate, links)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
@@ -86,9 +128,6 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Pr
     if let Some(classifications) = classifications {
         subject.inner_mut().set_classified_as(classifications);
     }
-    if finished.is_some() {
-        subject.inner_mut().set_finished(finished);
-    }
     if has_beginning.is_some() {
         subject.inner_mut().set_has_beginning(has_beginning);
     }
@@ -105,10 +144,265 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Pr
     Ok(Modifications::new_single(Op::Update, subject))
 }
 
-/// Delete a process
-pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: Process, now: &DateTime<Utc>) -> Result<Modifications> {
+/// Mark a process as finished, dealing with any costs it still holds according
+/// to the given [LeftoverPolicy]. Moving or writing off the leftover costs
+/// requires the `MoveCosts` or `WriteOff` permission (respectively) on top of
+/// `ProcessUpdate`.
+///
+/// [LeftoverPolicy]: enum.LeftoverPolicy.html
+pub fn finish(caller: &User, member: &Member, company: &Company, subject: Process, policy: LeftoverPolicy, note: Option<String>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcesses)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process".into()))?;
+    }
+    if subject.inner().finished() == &Some(true) {
+        Err(Error::ObjectIsReadOnly("process".into()))?;
+    }
+
+    let mut leftover_mods = Modifications::new();
+    if !subject.costs().is_zero() {
+        leftover_mods = match policy {
+            LeftoverPolicy::Block => Err(Error::CannotEraseCosts)?,
+            LeftoverPolicy::MoveTo(process_to, event_id) => {
+                accounting::move_costs(caller, member, company, event_id, subject.clone(), process_to, Ratio::new(num!(1))?, note, now)?
+            }
+            LeftoverPolicy::WriteOff(event_id) => {
+                let costs = subject.costs().clone();
+                accounting::write_off(caller, member, company, event_id, WriteOff::Process(subject.clone()), costs, note, now)?
+            }
+        };
+    }
+
+    // grab the post-event version of our process (if the leftover costs were
+    // dealt with) and mark it finished
+    let mut mods = Modifications::new();
+    let mut process = subject;
+    for modification in leftover_mods.into_vec() {
+        match modification.model() {
+            AnyModel::Process(updated) if updated.id() == process.id() => {
+                process = updated.clone();
+            }
+            _ => mods.push_raw(modification),
+        }
+    }
+    process.inner_mut().set_finished(Some(true));
+    process.set_updated(now.clone());
+    mods.push(Op::Update, process);
+    Ok(mods)
+}
+
+/// Plan a process from a process spec's recipe.
+///
+/// This creates a new process based on the spec, along with a set of draft
+/// (inactive) commitments for each item in the recipe: `consume` commitments
+/// for the inputs, `work` commitments for the labor (with the occupation and
+/// hours tracked in the commitment's `move_costs`), and `produce` commitments
+/// for the outputs.
+///
+/// `commitment_ids` must have one id per item in the recipe, and they're used
+/// in order: inputs, then labor, then outputs.
+pub fn plan_from_spec<T: Into<String>>(caller: &User, member: &Member, company: &Company, spec: &ProcessSpec, id: ProcessID, commitment_ids: Vec<CommitmentID>, name: T, has_beginning: Option<DateTime<Utc>>, due: Option<DateTime<Utc>>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcesses)?;
+    caller.access_check(Permission::CompanyUpdateCommitments)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessCreate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::CommitmentCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if spec.company_id() != company.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if spec.is_deleted() {
+        Err(Error::ObjectIsDeleted("process_spec".into()))?;
+    }
+    let recipe = spec.recipe().as_ref().ok_or_else(|| Error::MissingFields(vec!["process_spec.recipe".into()]))?;
+    if commitment_ids.len() != recipe.len() {
+        Err(Error::MissingFields(vec!["commitment_ids".into()]))?;
+    }
+
+    let mut mods = create(caller, member, company, &[], id.clone(), spec.id().clone(), name.into(), spec.inner().note().clone().unwrap_or_default(), vec![], has_beginning, None, vec![], true, now)?;
+    let company_agent_id = company.agent_id();
+    let make_commitment = |commitment_id: CommitmentID, action: vf::Action, effort_quantity: Option<Measure>, resource_conforms_to: Option<ResourceSpecID>, resource_quantity: Option<Measure>, move_costs: Costs| -> Result<Commitment> {
+        let (input_of, output_of) = match action {
+            vf::Action::Produce => (None, Some(id.clone())),
+            _ => (Some(id.clone()), None),
+        };
+        Commitment::builder()
+            .id(commitment_id)
+            .inner(
+                vf::Commitment::builder()
+                    .action(action)
+                    .created(Some(now.clone()))
+                    .due(due)
+                    .effort_quantity(effort_quantity)
+                    .input_of(input_of)
+                    .output_of(output_of)
+                    .provider(company_agent_id.clone())
+                    .receiver(company_agent_id.clone())
+                    .resource_conforms_to(resource_conforms_to)
+                    .resource_quantity(resource_quantity)
+                    .build()
+                    .map_err(|e| Error::BuilderFailed(e))?
+            )
+            .move_costs(move_costs)
+            .active(false)
+            .created(now.clone())
+            .updated(now.clone())
+            .build()
+            .map_err(|e| Error::BuilderFailed(e))
+    };
+    let mut commitment_ids = commitment_ids.into_iter();
+    for input in recipe.inputs() {
+        let commitment = make_commitment(commitment_ids.next().unwrap(), vf::Action::Consume, None, Some(input.resource_spec_id().clone()), Some(input.quantity().clone()), Costs::new())?;
+        mods.push(Op::Create, commitment);
+    }
+    for labor in recipe.labor() {
+        let effort = Measure::new(labor.hours().clone(), Unit::Hour);
+        let costs = Costs::new_with_labor_hours(labor.occupation_id().clone(), labor.hours().clone());
+        let commitment = make_commitment(commitment_ids.next().unwrap(), vf::Action::Work, Some(effort), None, None, costs)?;
+        mods.push(Op::Create, commitment);
+    }
+    for output in recipe.outputs() {
+        let commitment = make_commitment(commitment_ids.next().unwrap(), vf::Action::Produce, None, Some(output.resource_spec_id().clone()), Some(output.quantity().clone()), Costs::new())?;
+        mods.push(Op::Create, commitment);
+    }
+    Ok(mods)
+}
+
+/// Set (or clear) the labor budget for a process. Once set, any `work` event
+/// that pushes the process' labor costs over budget will fail.
+pub fn set_labor_budget(caller: &User, member: &Member, company: &Company, mut subject: Process, labor_budget: Option<Costs>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcesses)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process".into()))?;
+    }
+    subject.set_labor_budget(labor_budget);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Set the commitments a process is blocked on. Until each of them is
+/// fulfilled (via [commitment::execute][1]), the process can't take on work or
+/// consume resources. Passing an empty list unblocks the process.
+///
+/// [1]: ../commitment/fn.execute.html
+pub fn set_blocked_on(caller: &User, member: &Member, company: &Company, mut subject: Process, blocked_on: Vec<CommitmentID>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateProcesses)?;
-    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessDelete)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process".into()))?;
+    }
+    subject.set_blocked_on(blocked_on);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Returns true if the window from `begin` to `end` (open-ended if `None`)
+/// overlaps the window from `begin2` to `end2`.
+fn windows_overlap(begin: &DateTime<Utc>, end: Option<&DateTime<Utc>>, begin2: &DateTime<Utc>, end2: Option<&DateTime<Utc>>) -> bool {
+    let starts_before_end = end2.map(|end2| begin < end2).unwrap_or(true);
+    let ends_after_begin = end.map(|end| end > begin2).unwrap_or(true);
+    starts_before_end && ends_after_begin
+}
+
+/// Schedule a process to run from `has_beginning` to `has_end`, validating the
+/// proposed window against the process' spec and existing plans.
+///
+/// The window must be at least as long as the spec's `estimated_duration`, the
+/// number of other processes of the same spec in `processes` that overlap the
+/// window must be under the spec's `capacity`, and none of the `workers` can
+/// have an overlapping `work` commitment in `commitments` (commitments that are
+/// inputs of the process being scheduled are ignored). Processes and
+/// commitments without a beginning are not considered scheduled, and ones
+/// without an end are treated as running indefinitely.
+pub fn schedule(caller: &User, member: &Member, company: &Company, mut subject: Process, spec: &ProcessSpec, has_beginning: DateTime<Utc>, has_end: DateTime<Utc>, processes: &[Process], workers: &[Member], commitments: &[Commitment], now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcesses)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessUpdate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process".into()))?;
+    }
+    if subject.company_id() != company.id() || spec.company_id() != company.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if subject.inner().based_on().as_ref() != Some(spec.id()) {
+        Err(Error::ScheduleConflict("process is not based on the given spec".into()))?;
+    }
+    if has_end <= has_beginning {
+        Err(Error::ScheduleConflict("process must end after it begins".into()))?;
+    }
+    if let Some(estimated_duration) = spec.estimated_duration() {
+        let hours = (Decimal::from((has_end - has_beginning).num_seconds()) / Decimal::from(3600)).normalize();
+        if &hours < estimated_duration {
+            Err(Error::ScheduleConflict(format!("process spec needs at least {} hours but only {} are scheduled", estimated_duration, hours)))?;
+        }
+    }
+    if let Some(capacity) = spec.capacity() {
+        let running = processes.iter()
+            .filter(|x| x.id() != subject.id() && !x.is_deleted() && x.inner().finished() != &Some(true))
+            .filter(|x| x.inner().based_on().as_ref() == Some(spec.id()))
+            .filter(|x| {
+                match x.inner().has_beginning() {
+                    Some(begin) => windows_overlap(begin, x.inner().has_end().as_ref(), &has_beginning, Some(&has_end)),
+                    None => false,
+                }
+            })
+            .count();
+        if running >= *capacity as usize {
+            Err(Error::ScheduleConflict(format!("process spec is at capacity ({} running)", running)))?;
+        }
+    }
+    for worker in workers {
+        let worker_id = worker.agent_id();
+        let conflict = commitments.iter()
+            .filter(|x| !x.is_deleted() && x.inner().finished() != &Some(true))
+            .filter(|x| x.inner().action() == &vf::Action::Work && x.inner().provider() == &worker_id)
+            .filter(|x| x.inner().input_of().as_ref() != Some(subject.id()))
+            .any(|x| {
+                match x.inner().has_beginning() {
+                    Some(begin) => windows_overlap(begin, x.inner().has_end().as_ref(), &has_beginning, Some(&has_end)),
+                    None => false,
+                }
+            });
+        if conflict {
+            Err(Error::ScheduleConflict(format!("member {} has an overlapping work commitment", worker.id().as_str())))?;
+        }
+    }
+    subject.inner_mut().set_has_beginning(Some(has_beginning));
+    subject.inner_mut().set_has_end(Some(has_end));
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Delete a process (see `create()` for `links`)
+pub fn delete(caller: &User, member: &Member, company: &Company, links: &[CompanyLink], mut subject: Process, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcesses)?;
+    member.access_check_federated(caller.id(), company.id(), CompanyPermission::ProcessDelete, links)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
@@ -127,8 +421,13 @@ mod tests {
     use super::*;
     use crate::{
         models::{
-            lib::agent::Agent,
-            process_spec::ProcessSpecID,
+            company::CompanyID,
+            company_link::CompanyLinkID,
+            event::Event,
+            member::MemberID,
+            occupation::OccupationID,
+            process_spec::{Recipe, RecipeLabor, RecipeResource},
+            user::UserID,
         },
         util::{self, test::{self, *}},
     };
@@ -141,7 +440,7 @@ mod tests {
         let spec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "Make Gazelle Freestyle", true, &now);
 
         let testfn = |state: &TestState<Process, Process>| {
-            create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec!["https://www.wikidata.org/wiki/Q1141557".parse().unwrap()], Some(now.clone()), None, vec![], true, &now)
+            create(state.user(), state.member(), state.company(), &vec![], id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec!["https://www.wikidata.org/wiki/Q1141557".parse().unwrap()], Some(now.clone()), None, vec![], true, &now)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -163,6 +462,30 @@ mod tests {
         assert_eq!(process.created(), &now);
         assert_eq!(process.updated(), &now);
         assert_eq!(process.deleted(), &None);
+
+        // members of a linked federation can create processes for the company
+        let federation_id = CompanyID::create();
+        let fed_member = make_member_worker(&MemberID::create(), state.user().id(), &federation_id, &OccupationID::create(), vec![CompanyPermission::ProcessCreate], &now);
+        let link = CompanyLink::builder()
+            .id(CompanyLinkID::create())
+            .parent_id(federation_id.clone())
+            .child_id(state.company().id().clone())
+            .inherit_permissions(vec![CompanyPermission::ProcessCreate])
+            .accepted(Some(now.clone()))
+            .active(true)
+            .created(now.clone())
+            .updated(now.clone())
+            .build().unwrap();
+        let testfn_fed = |links: &[CompanyLink]| {
+            create(state.user(), &fed_member, state.company(), links, id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec![], None, None, vec![], true, &now)
+        };
+        let mods = testfn_fed(&[link.clone()]).unwrap().into_vec();
+        let process2 = mods[0].clone().expect_op::<Process>(Op::Create).unwrap();
+        assert_eq!(process2.company_id(), state.company().id());
+        assert_eq!(testfn_fed(&[]), Err(Error::InsufficientPrivileges));
+        let mut link2 = link.clone();
+        link2.set_inherit_permissions(vec![CompanyPermission::ProcessDelete]);
+        assert_eq!(testfn_fed(&[link2]), Err(Error::InsufficientPrivileges));
     }
 
     #[test]
@@ -172,13 +495,13 @@ mod tests {
         let mut state = TestState::standard(vec![CompanyPermission::ProcessCreate, CompanyPermission::ProcessUpdate], &now);
         let spec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "Make Gazelle Freestyle", true, &now);
 
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec!["https://www.wikidata.org/wiki/Q1141557".parse().unwrap()], Some(now.clone()), None, vec![], true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), &vec![], id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec!["https://www.wikidata.org/wiki/Q1141557".parse().unwrap()], Some(now.clone()), None, vec![], true, &now).unwrap().into_vec();
         let process = mods[0].clone().expect_op::<Process>(Op::Create).unwrap();
         state.model = Some(process);
 
         let now2 = util::time::now();
         let testfn = |state: &TestState<Process, Process>| {
-            update(state.user(), state.member(), state.company(), state.model().clone(), Some("Make a GaZeLLe fReeStYlE".into()), None, None, Some(true), None, Some(now2.clone()), Some(vec![state.company().agent_id()]), Some(false), &now2)
+            update(state.user(), state.member(), state.company(), &vec![], state.model().clone(), Some("Make a GaZeLLe fReeStYlE".into()), None, None, None, Some(now2.clone()), Some(vec![state.company().agent_id()]), Some(false), &now2)
         };
         test::standard_transaction_tests(&state, &testfn);
 
@@ -193,6 +516,7 @@ mod tests {
         assert_eq!(process2.inner().has_end(), &Some(now2.clone()));
         assert_eq!(process2.inner().in_scope_of(), &vec![state.company().agent_id()]);
         assert_eq!(process2.inner().name(), "Make a GaZeLLe fReeStYlE");
+        assert_eq!(process2.inner().finished(), &None);
         assert_eq!(process2.inner().note(), &Some("tony making me build five of these stupid things".into()));
         assert_eq!(process2.company_id(), state.company().id());
         assert!(process2.costs().is_zero());
@@ -202,6 +526,323 @@ mod tests {
         assert_eq!(process2.deleted(), &None);
     }
 
+    #[test]
+    fn can_plan_from_spec() {
+        let now = util::time::now();
+        let id = ProcessID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessCreate, CompanyPermission::CommitmentCreate], &now);
+        let mut spec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "make widgets", true, &now);
+        spec.set_recipe(Some(Recipe::new(
+            vec![
+                RecipeResource::new("steel", Measure::new(num!(2.5), Unit::Kilogram)),
+                RecipeResource::new("bolts", Measure::new(num!(16), Unit::One)),
+            ],
+            vec![RecipeLabor::new("machinist", num!(3))],
+            vec![RecipeResource::new("widget", Measure::new(num!(10), Unit::One))],
+        )));
+        state.model = Some(spec);
+        let commitment_ids = vec![CommitmentID::create(), CommitmentID::create(), CommitmentID::create(), CommitmentID::create()];
+
+        let testfn_inner = |state: &TestState<ProcessSpec, Process>, commitment_ids: Vec<CommitmentID>| {
+            plan_from_spec(state.user(), state.member(), state.company(), state.model(), id.clone(), commitment_ids, "make widgets (tuesday)", Some(now.clone()), None, &now)
+        };
+        let testfn = |state: &TestState<ProcessSpec, Process>| {
+            testfn_inner(state, commitment_ids.clone())
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 5);
+        let process = mods[0].clone().expect_op::<Process>(Op::Create).unwrap();
+        assert_eq!(process.id(), &id);
+        assert_eq!(process.inner().based_on(), &Some(state.model().id().clone()));
+        assert_eq!(process.inner().name(), "make widgets (tuesday)");
+        assert_eq!(process.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(process.company_id(), state.company().id());
+        assert!(process.costs().is_zero());
+
+        let steel = mods[1].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert_eq!(steel.id(), &commitment_ids[0]);
+        assert_eq!(steel.inner().action(), &vf::Action::Consume);
+        assert_eq!(steel.inner().input_of(), &Some(id.clone()));
+        assert_eq!(steel.inner().output_of(), &None);
+        assert_eq!(steel.inner().provider(), &state.company().agent_id());
+        assert_eq!(steel.inner().receiver(), &state.company().agent_id());
+        assert_eq!(steel.inner().resource_conforms_to(), &Some("steel".into()));
+        assert_eq!(steel.inner().resource_quantity(), &Some(Measure::new(num!(2.5), Unit::Kilogram)));
+        assert_eq!(steel.active(), &false);
+        let bolts = mods[2].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert_eq!(bolts.id(), &commitment_ids[1]);
+        assert_eq!(bolts.inner().resource_conforms_to(), &Some("bolts".into()));
+        let labor = mods[3].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert_eq!(labor.id(), &commitment_ids[2]);
+        assert_eq!(labor.inner().action(), &vf::Action::Work);
+        assert_eq!(labor.inner().input_of(), &Some(id.clone()));
+        assert_eq!(labor.inner().effort_quantity(), &Some(Measure::new(num!(3), Unit::Hour)));
+        assert_eq!(labor.move_costs(), &Costs::new_with_labor_hours("machinist", num!(3)));
+        let widget = mods[4].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        assert_eq!(widget.id(), &commitment_ids[3]);
+        assert_eq!(widget.inner().action(), &vf::Action::Produce);
+        assert_eq!(widget.inner().input_of(), &None);
+        assert_eq!(widget.inner().output_of(), &Some(id.clone()));
+        assert_eq!(widget.inner().resource_quantity(), &Some(Measure::new(num!(10), Unit::One)));
+
+        let res = testfn_inner(&state, commitment_ids[0..3].to_vec());
+        assert_eq!(res, Err(Error::MissingFields(vec!["commitment_ids".into()])));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_recipe(None);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::MissingFields(vec!["process_spec.recipe".into()])));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state4 = state.clone();
+        state4.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("process_spec".into())));
+    }
+
+    #[test]
+    fn can_set_labor_budget() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessUpdate], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        state.model = Some(process);
+
+        let now2 = util::time::now();
+        let budget = Costs::new_with_labor("machinist", num!(500));
+        let testfn = |state: &TestState<Process, Process>| {
+            set_labor_budget(state.user(), state.member(), state.company(), state.model().clone(), Some(budget.clone()), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let process2 = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.id(), state.model().id());
+        assert_eq!(process2.labor_budget(), &Some(budget.clone()));
+        assert_eq!(process2.costs(), state.model().costs());
+        assert_eq!(process2.created(), &now);
+        assert_eq!(process2.updated(), &now2);
+
+        let mods = set_labor_budget(state.user(), state.member(), state.company(), process2, None, &now2).unwrap().into_vec();
+        let process3 = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process3.labor_budget(), &None);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_set_blocked_on() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessUpdate], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new(), &now);
+        state.model = Some(process);
+
+        let now2 = util::time::now();
+        let blocked_on = vec![CommitmentID::new("steel delivery")];
+        let testfn = |state: &TestState<Process, Process>| {
+            set_blocked_on(state.user(), state.member(), state.company(), state.model().clone(), blocked_on.clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let process2 = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.blocked_on(), &blocked_on);
+        assert!(process2.is_blocked());
+        assert_eq!(process2.updated(), &now2);
+
+        let mods = set_blocked_on(state.user(), state.member(), state.company(), process2, vec![], &now2).unwrap().into_vec();
+        let process3 = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert!(!process3.is_blocked());
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_schedule() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessUpdate], &now);
+        let mut spec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "fire pottery", true, &now);
+        spec.set_estimated_duration(Some(num!(8)));
+        spec.set_capacity(Some(2));
+        let company_id = state.company().id().clone();
+        let make_scheduled = |id: &str, begin: &str, end: Option<&str>| -> Process {
+            let mut process = make_process(&ProcessID::new(id), &company_id, "fire some pots", &Costs::new(), &now);
+            process.inner_mut().set_based_on(Some(spec.id().clone()));
+            process.inner_mut().set_has_beginning(Some(begin.parse().unwrap()));
+            process.inner_mut().set_has_end(end.map(|x| x.parse().unwrap()));
+            process
+        };
+        let mut process = make_process(&ProcessID::new("kiln run"), state.company().id(), "fire some pots", &Costs::new(), &now);
+        process.inner_mut().set_based_on(Some(spec.id().clone()));
+        state.model = Some(process);
+
+        let begin: DateTime<Utc> = "2021-03-01T08:00:00Z".parse().unwrap();
+        let end: DateTime<Utc> = "2021-03-01T17:00:00Z".parse().unwrap();
+        let worker = make_member_worker(&MemberID::create(), &UserID::create(), state.company().id(), &OccupationID::new("potter"), vec![], &now);
+        let make_work_commitment = |begin: &str, end: &str, input_of: Option<ProcessID>| -> Commitment {
+            Commitment::builder()
+                .id(CommitmentID::create())
+                .inner(
+                    vf::Commitment::builder()
+                        .action(vf::Action::Work)
+                        .has_beginning(Some(begin.parse().unwrap()))
+                        .has_end(Some(end.parse().unwrap()))
+                        .input_of(input_of)
+                        .provider(worker.agent_id())
+                        .receiver(state.company().agent_id())
+                        .build().unwrap()
+                )
+                .move_costs(Costs::new())
+                .active(true)
+                .created(now.clone())
+                .updated(now.clone())
+                .build().unwrap()
+        };
+        let processes = vec![
+            make_scheduled("morning run", "2021-03-01T06:00:00Z", Some("2021-03-01T14:00:00Z")),
+            make_scheduled("yesterday's run", "2021-02-28T06:00:00Z", Some("2021-02-28T14:00:00Z")),
+        ];
+        let commitments = vec![
+            make_work_commitment("2021-02-28T08:00:00Z", "2021-02-28T17:00:00Z", None),
+            make_work_commitment("2021-03-01T08:00:00Z", "2021-03-01T17:00:00Z", Some(ProcessID::new("kiln run"))),
+        ];
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Process, Process>| {
+            schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec, begin.clone(), end.clone(), &processes, &vec![worker.clone()], &commitments, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let process2 = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.id(), state.model().id());
+        assert_eq!(process2.inner().has_beginning(), &Some(begin.clone()));
+        assert_eq!(process2.inner().has_end(), &Some(end.clone()));
+        assert_eq!(process2.updated(), &now2);
+
+        // too short for the spec
+        let res = schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec, begin.clone(), "2021-03-01T15:30:00Z".parse().unwrap(), &processes, &vec![], &vec![], &now2);
+        assert_eq!(res, Err(Error::ScheduleConflict("process spec needs at least 8 hours but only 7.5 are scheduled".into())));
+        let res = schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec, end.clone(), begin.clone(), &processes, &vec![], &vec![], &now2);
+        assert_eq!(res, Err(Error::ScheduleConflict("process must end after it begins".into())));
+
+        // kilns are full
+        let mut processes2 = processes.clone();
+        processes2.push(make_scheduled("all day run", "2021-03-01T00:00:00Z", None));
+        let res = schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec, begin.clone(), end.clone(), &processes2, &vec![], &vec![], &now2);
+        assert_eq!(res, Err(Error::ScheduleConflict("process spec is at capacity (2 running)".into())));
+        processes2[2].inner_mut().set_finished(Some(true));
+        assert!(schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec, begin.clone(), end.clone(), &processes2, &vec![], &vec![], &now2).is_ok());
+
+        // the worker is busy
+        let mut commitments2 = commitments.clone();
+        commitments2.push(make_work_commitment("2021-03-01T16:00:00Z", "2021-03-01T20:00:00Z", None));
+        let res = schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec, begin.clone(), end.clone(), &processes, &vec![worker.clone()], &commitments2, &now2);
+        assert_eq!(res, Err(Error::ScheduleConflict(format!("member {} has an overlapping work commitment", worker.id().as_str()))));
+
+        let spec2 = make_process_spec(&ProcessSpecID::create(), state.company().id(), "throw pottery", true, &now);
+        let res = schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec2, begin.clone(), end.clone(), &processes, &vec![], &vec![], &now2);
+        assert_eq!(res, Err(Error::ScheduleConflict("process is not based on the given spec".into())));
+        let spec3 = make_process_spec(spec.id(), &CompanyID::create(), "fire pottery", true, &now);
+        let res = schedule(state.user(), state.member(), state.company(), state.model().clone(), &spec3, begin.clone(), end.clone(), &processes, &vec![], &vec![], &now2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_finish() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessUpdate, CompanyPermission::MoveCosts, CompanyPermission::WriteOff], &now);
+        let process = make_process(&ProcessID::create(), state.company().id(), "make widgets", &Costs::new_with_labor("machinist", num!(40)), &now);
+        let process_to = make_process(&ProcessID::create(), state.company().id(), "overhead", &Costs::new(), &now);
+        state.company_mut().set_total_costs(process.costs().clone());
+        state.model = Some(process);
+
+        let now2 = util::time::now();
+        let testfn_block = |state: &TestState<Process, Process>| {
+            finish(state.user(), state.member(), state.company(), state.model().clone(), LeftoverPolicy::Block, None, &now2)
+        };
+        let testfn_move = |state: &TestState<Process, Process>| {
+            finish(state.user(), state.member(), state.company(), state.model().clone(), LeftoverPolicy::MoveTo(process_to.clone(), EventID::new("move1")), Some("closing out".into()), &now2)
+        };
+        let testfn_write_off = |state: &TestState<Process, Process>| {
+            finish(state.user(), state.member(), state.company(), state.model().clone(), LeftoverPolicy::WriteOff(EventID::new("writeoff1")), None, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn_move);
+        test::standard_transaction_tests(&state, &testfn_write_off);
+
+        // can't finish a process with costs in it without saying what to do
+        // with them
+        let res = testfn_block(&state);
+        assert_eq!(res, Err(Error::CannotEraseCosts));
+
+        // but an empty process is fine
+        let mut state2 = state.clone();
+        state2.model_mut().set_costs(Costs::new());
+        let mods = testfn_block(&state2).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let process2 = mods[0].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(process2.inner().finished(), &Some(true));
+        assert_eq!(process2.updated(), &now2);
+
+        let mods = testfn_move(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let process_to2 = mods[1].clone().expect_op::<Process>(Op::Update).unwrap();
+        let process2 = mods[2].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(event.id(), &EventID::new("move1"));
+        assert_eq!(event.inner().note(), &Some("closing out".into()));
+        assert_eq!(process_to2.id(), process_to.id());
+        assert_eq!(process_to2.costs(), &Costs::new_with_labor("machinist", num!(40)));
+        assert_eq!(process2.id(), state.model().id());
+        assert!(process2.costs().is_zero());
+        assert_eq!(process2.inner().finished(), &Some(true));
+
+        let mods = testfn_write_off(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let company2 = mods[1].clone().expect_op::<Company>(Op::Update).unwrap();
+        let process2 = mods[2].clone().expect_op::<Process>(Op::Update).unwrap();
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("machinist", num!(40))));
+        assert_eq!(company2.id(), state.company().id());
+        assert!(company2.total_costs().is_zero());
+        assert!(process2.costs().is_zero());
+        assert_eq!(process2.inner().finished(), &Some(true));
+
+        // moving costs needs its own permission
+        let mut state3 = state.clone();
+        state3.member_mut().set_permissions(vec![CompanyPermission::ProcessUpdate]);
+        let res = testfn_move(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // finished processes stay finished
+        let mut state5 = state2.clone();
+        state5.model = Some(process2);
+        let res = testfn_block(&state5);
+        assert_eq!(res, Err(Error::ObjectIsReadOnly("process".into())));
+
+        let mut state4 = state.clone();
+        state4.model_mut().set_company_id(CompanyID::new("zing"));
+        let res = testfn_move(&state4);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
     #[test]
     fn can_delete() {
         let now = util::time::now();
@@ -209,13 +850,13 @@ mod tests {
         let mut state = TestState::standard(vec![CompanyPermission::CommitmentCreate, CompanyPermission::ProcessCreate, CompanyPermission::ProcessDelete], &now);
         let spec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "Make Gazelle Freestyle", true, &now);
 
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec!["https://www.wikidata.org/wiki/Q1141557".parse().unwrap()], Some(now.clone()), None, vec![], true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), &vec![], id.clone(), spec.id().clone(), "Gazelle Freestyle Marathon", "tony making me build five of these stupid things", vec!["https://www.wikidata.org/wiki/Q1141557".parse().unwrap()], Some(now.clone()), None, vec![], true, &now).unwrap().into_vec();
         let process = mods[0].clone().expect_op::<Process>(Op::Create).unwrap();
         state.model = Some(process);
 
         let now2 = util::time::now();
         let testfn = |state: &TestState<Process, Process>| {
-            delete(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+            delete(state.user(), state.member(), state.company(), &vec![], state.model().clone(), &now2)
         };
         test::standard_transaction_tests(&state, &testfn);
         test::double_deleted_tester(&state, "process", &testfn);
@@ -5,6 +5,9 @@
 //! for each widget, but one process specification called "build widgets" that
 //! those five processes reference.
 //!
+//! Process specs can be published to a shared catalog with `set_published()`,
+//! after which any company can `fork()` them into their own catalog.
+//!
 //! See the [process spec model.][1]
 //!
 //! [1]: ../../models/process_spec/index.html
@@ -19,10 +22,11 @@ use crate::{
         company::{Company, Permission as CompanyPermission},
         member::Member,
         lib::basis_model::Model,
-        process_spec::{ProcessSpec, ProcessSpecID},
+        process_spec::{ProcessSpec, ProcessSpecID, Recipe},
         user::User,
     },
 };
+use rust_decimal::prelude::*;
 use vf_rs::vf;
 
 /// Create a new ProcessSpec
@@ -70,6 +74,114 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Pr
     Ok(Modifications::new_single(Op::Update, subject))
 }
 
+/// Set (or clear) the recipe for a process spec, describing the inputs, labor,
+/// and outputs we expect processes based on this spec to have.
+pub fn set_recipe(caller: &User, member: &Member, company: &Company, mut subject: ProcessSpec, recipe: Option<Recipe>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcessSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process_spec".into()))?;
+    }
+    subject.set_recipe(recipe);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Set (or clear) the planning estimates for a process spec: how long (in
+/// hours) we expect its processes to take, and how many of them can run at
+/// once.
+pub fn set_capacity(caller: &User, member: &Member, company: &Company, mut subject: ProcessSpec, estimated_duration: Option<Decimal>, capacity: Option<u32>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcessSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process_spec".into()))?;
+    }
+    if let Some(hours) = estimated_duration.as_ref() {
+        if hours <= &Decimal::zero() {
+            Err(Error::InvalidAmount(*hours))?;
+        }
+    }
+    if capacity == Some(0) {
+        Err(Error::InvalidAmount(Decimal::zero()))?;
+    }
+    subject.set_estimated_duration(estimated_duration);
+    subject.set_capacity(capacity);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Publish a process spec to the shared catalog (or pull it back out). Specs
+/// that have been published can be forked by other companies.
+///
+/// Unpublishing a spec doesn't affect any forks already made from it.
+pub fn set_published(caller: &User, member: &Member, company: &Company, mut subject: ProcessSpec, published: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcessSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("process_spec".into()))?;
+    }
+    if !published {
+        subject.set_published(None);
+    } else if !subject.is_published() {
+        subject.set_published(Some(now.clone()));
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Fork a process spec into the given company, creating a copy of it (with
+/// the given id) that links back to the original via `forked_from`.
+///
+/// The spec being forked must be published, unless it belongs to the company
+/// forking it. The fork copies the original's name, note, recipe, and
+/// estimated duration, but not its capacity (which depends on the equipment
+/// the forking company has) and starts out unpublished. Note that the recipe
+/// still refers to the original's resource specs.
+pub fn fork(caller: &User, member: &Member, company: &Company, source: &ProcessSpec, id: ProcessSpecID, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateProcessSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ProcessSpecCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if source.is_deleted() {
+        Err(Error::ObjectIsDeleted("process_spec".into()))?;
+    }
+    if !source.is_published() && source.company_id() != company.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let model = ProcessSpec::builder()
+        .id(id)
+        .inner(source.inner().clone())
+        .company_id(company.id().clone())
+        .recipe(source.recipe().clone())
+        .estimated_duration(*source.estimated_duration())
+        .forked_from(Some(source.id().clone()))
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
 /// Delete a resource spec
 pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: ProcessSpec, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateProcessSpecs)?;
@@ -89,10 +201,12 @@ mod tests {
     use super::*;
     use crate::{
         models::{
-            process_spec::{ProcessSpec, ProcessSpecID},
+            company::CompanyID,
+            process_spec::{ProcessSpec, ProcessSpecID, RecipeLabor, RecipeResource},
         },
         util::{self, test::{self, *}},
     };
+    use om2::{Measure, Unit};
 
     #[test]
     fn can_create() {
@@ -146,6 +260,77 @@ mod tests {
         assert_eq!(procspec2.deleted(), &None);
     }
 
+    #[test]
+    fn can_set_recipe() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessSpecUpdate], &now);
+        let procspec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "make widgets", true, &now);
+        state.model = Some(procspec);
+
+        let now2 = util::time::now();
+        let recipe = Recipe::new(
+            vec![RecipeResource::new("steel", Measure::new(num!(2.5), Unit::Kilogram))],
+            vec![RecipeLabor::new("machinist", num!(3))],
+            vec![RecipeResource::new("widget", Measure::new(num!(10), Unit::One))],
+        );
+        let testfn = |state: &TestState<ProcessSpec, ProcessSpec>| {
+            set_recipe(state.user(), state.member(), state.company(), state.model().clone(), Some(recipe.clone()), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let procspec2 = mods[0].clone().expect_op::<ProcessSpec>(Op::Update).unwrap();
+        assert_eq!(procspec2.id(), state.model().id());
+        assert_eq!(procspec2.recipe(), &Some(recipe.clone()));
+        assert_eq!(procspec2.recipe().as_ref().unwrap().len(), 3);
+        assert_eq!(procspec2.created(), &now);
+        assert_eq!(procspec2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_set_capacity() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessSpecUpdate], &now);
+        let procspec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "fire pottery", true, &now);
+        state.model = Some(procspec);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ProcessSpec, ProcessSpec>| {
+            set_capacity(state.user(), state.member(), state.company(), state.model().clone(), Some(num!(8.5)), Some(3), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let procspec2 = mods[0].clone().expect_op::<ProcessSpec>(Op::Update).unwrap();
+        assert_eq!(procspec2.estimated_duration(), &Some(num!(8.5)));
+        assert_eq!(procspec2.capacity(), &Some(3));
+        assert_eq!(procspec2.updated(), &now2);
+
+        let mods = set_capacity(state.user(), state.member(), state.company(), procspec2, None, None, &now2).unwrap().into_vec();
+        let procspec3 = mods[0].clone().expect_op::<ProcessSpec>(Op::Update).unwrap();
+        assert_eq!(procspec3.estimated_duration(), &None);
+        assert_eq!(procspec3.capacity(), &None);
+
+        let res = set_capacity(state.user(), state.member(), state.company(), state.model().clone(), Some(num!(0)), None, &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = set_capacity(state.user(), state.member(), state.company(), state.model().clone(), None, Some(0), &now2);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
     #[test]
     fn can_delete() {
         let now = util::time::now();
@@ -174,5 +359,86 @@ mod tests {
         assert_eq!(procspec2.updated(), &now);
         assert_eq!(procspec2.deleted(), &Some(now2.clone()));
     }
-}
 
+    #[test]
+    fn can_set_published() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessSpecUpdate], &now);
+        let procspec = make_process_spec(&ProcessSpecID::create(), state.company().id(), "make widgets", true, &now);
+        state.model = Some(procspec);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ProcessSpec, ProcessSpec>| {
+            set_published(state.user(), state.member(), state.company(), state.model().clone(), true, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let procspec2 = mods[0].clone().expect_op::<ProcessSpec>(Op::Update).unwrap();
+        assert_eq!(procspec2.published(), &Some(now2.clone()));
+        assert!(procspec2.is_published());
+        assert_eq!(procspec2.updated(), &now2);
+
+        // publishing again keeps the original publish date
+        let now3 = util::time::now();
+        let mods = set_published(state.user(), state.member(), state.company(), procspec2.clone(), true, &now3).unwrap().into_vec();
+        let procspec3 = mods[0].clone().expect_op::<ProcessSpec>(Op::Update).unwrap();
+        assert_eq!(procspec3.published(), &Some(now2.clone()));
+
+        let mods = set_published(state.user(), state.member(), state.company(), procspec3.clone(), false, &now3).unwrap().into_vec();
+        let procspec4 = mods[0].clone().expect_op::<ProcessSpec>(Op::Update).unwrap();
+        assert_eq!(procspec4.published(), &None);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_fork() {
+        let now = util::time::now();
+        let id = ProcessSpecID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ProcessSpecCreate], &now);
+        let mut source = make_process_spec(&ProcessSpecID::create(), &CompanyID::new("larry's widgets"), "make widgets", true, &now);
+        source.set_recipe(Some(Recipe::new(vec![], vec![RecipeLabor::new("machinist", num!(3))], vec![])));
+        source.set_estimated_duration(Some(num!(4)));
+        source.set_capacity(Some(2));
+        source.set_published(Some(now.clone()));
+        state.model = Some(source);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ProcessSpec, ProcessSpec>| {
+            fork(state.user(), state.member(), state.company(), state.model(), id.clone(), true, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "process_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let forked = mods[0].clone().expect_op::<ProcessSpec>(Op::Create).unwrap();
+        assert_eq!(forked.id(), &id);
+        assert_eq!(forked.inner(), state.model().inner());
+        assert_eq!(forked.company_id(), state.company().id());
+        assert_eq!(forked.recipe(), state.model().recipe());
+        assert_eq!(forked.estimated_duration(), &Some(num!(4)));
+        assert_eq!(forked.capacity(), &None);
+        assert_eq!(forked.published(), &None);
+        assert_eq!(forked.forked_from(), &Some(state.model().id().clone()));
+        assert_eq!(forked.active(), &true);
+        assert_eq!(forked.created(), &now2);
+        assert_eq!(forked.updated(), &now2);
+
+        // can't fork another company's unpublished specs...
+        let mut state2 = state.clone();
+        state2.model_mut().set_published(None);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        // ...but can fork our own
+        state2.model_mut().set_company_id(state.company().id().clone());
+        assert!(testfn(&state2).is_ok());
+    }
+}
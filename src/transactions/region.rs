@@ -0,0 +1,172 @@
+//! Regions are geographic areas that resources can be tagged with in order to
+//! track depletion.
+//!
+//! See the [region model.][1]
+//!
+//! [1]: ../../models/region/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        lib::basis_model::Model,
+        region::{Region, RegionID},
+        user::User,
+    },
+};
+use vf_rs::geo::SpatialThing;
+
+/// Create a new `Region`.
+pub fn create<T: Into<String>>(caller: &User, id: RegionID, name: T, description: T, location: Option<SpatialThing>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::RegionCreate)?;
+    let model = Region::builder()
+        .id(id)
+        .name(name)
+        .description(description)
+        .location(location)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update an existing `Region`
+pub fn update(caller: &User, mut subject: Region, name: Option<String>, description: Option<String>, location: Option<Option<SpatialThing>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::RegionUpdate)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("region".into()))?;
+    }
+    if let Some(name) = name {
+        subject.set_name(name);
+    }
+    if let Some(description) = description {
+        subject.set_description(description);
+    }
+    if let Some(location) = location {
+        subject.set_location(location);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Delete a `Region`
+pub fn delete(caller: &User, mut subject: Region, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::RegionDelete)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("region".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        util::{self, test::{self, *}},
+    };
+
+    #[test]
+    fn can_create() {
+        let id = RegionID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+        let location = SpatialThing::builder().lat(Some(47.6062)).long(Some(-122.3321)).build().unwrap();
+
+        let testfn = |state: &TestState<Region, Region>| {
+            create(state.user(), id.clone(), "cascadia", "the pacific northwest", Some(location.clone()), true, &now)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+
+        let region = mods[0].clone().expect_op::<Region>(Op::Create).unwrap();
+        assert_eq!(region.id(), &id);
+        assert_eq!(region.name(), "cascadia");
+        assert_eq!(region.description(), "the pacific northwest");
+        assert_eq!(region.location(), &Some(location.clone()));
+        assert_eq!(region.active(), &true);
+        assert_eq!(region.created(), &now);
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_update() {
+        let id = RegionID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let mods = create(state.user(), id.clone(), "cascadia", "the pacific northwest", None, true, &now).unwrap().into_vec();
+        let region = mods[0].clone().expect_op::<Region>(Op::Create).unwrap();
+        state.model = Some(region);
+        let location = SpatialThing::builder().lat(Some(47.6062)).long(Some(-122.3321)).build().unwrap();
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Region, Region>| {
+            update(state.user(), state.model().clone(), None, Some("rain, mostly".into()), Some(Some(location.clone())), Some(false), &now2)
+        };
+
+        let mods = testfn(&state).unwrap().into_vec();
+        let region2 = mods[0].clone().expect_op::<Region>(Op::Update).unwrap();
+        assert_eq!(region2.name(), "cascadia");
+        assert_eq!(region2.description(), "rain, mostly");
+        assert_eq!(region2.location(), &Some(location.clone()));
+        assert_eq!(region2.active(), &false);
+        assert_eq!(region2.created(), &now);
+        assert_eq!(region2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("region".into())));
+    }
+
+    #[test]
+    fn can_delete() {
+        let id = RegionID::create();
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![], &now);
+        state.user_mut().set_roles(vec![Role::SuperAdmin]);
+
+        let mods = create(state.user(), id.clone(), "cascadia", "the pacific northwest", None, true, &now).unwrap().into_vec();
+        let region = mods[0].clone().expect_op::<Region>(Op::Create).unwrap();
+        state.model = Some(region);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<Region, Region>| {
+            delete(state.user(), state.model().clone(), &now2)
+        };
+        test::double_deleted_tester(&state, "region", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let region2 = mods[0].clone().expect_op::<Region>(Op::Delete).unwrap();
+        assert_eq!(region2.id(), &id);
+        assert_eq!(region2.deleted(), &Some(now2));
+
+        let mut state2 = state.clone();
+        state2.user_mut().set_roles(vec![Role::User]);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+}
@@ -5,6 +5,15 @@
 //! specification is a product description on an online shop, the resource is
 //! the actual delivered good that you receive when you order it.
 //!
+//! Resources normally start out empty and take on costs as events move costs
+//! into them. When a network is just getting started though, resources come
+//! from outside of it with no tracked costs at all. `bootstrap()` creates a
+//! resource seeded with its spec's default (estimated) costs so the network can
+//! start operating before full upstream data exists. Bootstrapped resources are
+//! flagged via their `estimated_from` field, and the bootstrapping is recorded
+//! as a `raise` event that brings the estimated costs into the network (and
+//! onto the company's `total_costs`).
+//!
 //! See the [resource model.][2]
 //!
 //! [1]: ../resource_spec/index.html
@@ -13,28 +22,33 @@
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
-    costs::Costs,
+    costs::{Costs, validate::SanityPolicy},
     error::{Error, Result},
     models::{
         Op,
         Modifications,
         company::{Company, Permission as CompanyPermission},
+        event::EventID,
         member::Member,
+        region::RegionID,
         lib::{
             agent::Agent,
             basis_model::Model,
         },
         resource::{Resource, ResourceID},
-        resource_spec::ResourceSpecID,
+        resource_spec::{ResourceSpec, ResourceSpecID},
         user::User,
     },
+    transactions::event::accounting,
+    util::measure,
 };
-use om2::Unit;
+use om2::{Measure, Unit};
+use rust_decimal::prelude::*;
 use url::Url;
 use vf_rs::{vf, dfc};
 
 /// Create a new resource
-pub fn create(caller: &User, member: &Member, company: &Company, id: ResourceID, spec_id: ResourceSpecID, lot: Option<dfc::ProductBatch>, name: Option<String>, tracking_id: Option<String>, classifications: Vec<Url>, note: Option<String>, unit_of_effort: Option<Unit>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn create(caller: &User, member: &Member, company: &Company, id: ResourceID, spec_id: ResourceSpecID, lot: Option<dfc::ProductBatch>, name: Option<String>, tracking_id: Option<String>, classifications: Vec<Url>, note: Option<String>, unit_of_effort: Option<Unit>, region_id: Option<RegionID>, allow_negative: bool, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateResources)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::ResourceCreate)?;
     if !company.is_active() {
@@ -57,6 +71,8 @@ pub fn create(caller: &User, member: &Member, company: &Company, id: ResourceID,
         )
         .in_custody_of(company.id().clone())
         .costs(Costs::new())
+        .region_id(region_id)
+        .allow_negative(allow_negative)
         .active(active)
         .created(now.clone())
         .updated(now.clone())
@@ -65,8 +81,73 @@ pub fn create(caller: &User, member: &Member, company: &Company, id: ResourceID,
     Ok(Modifications::new_single(Op::Create, model))
 }
 
+/// Bootstrap a resource brought in from outside the network, seeding its
+/// costs from the spec's `default_costs_per_unit` (multiplied by the given
+/// quantity, in the spec's default unit). The resource is flagged as having
+/// estimated costs.
+///
+/// Returns the `raise` event (using `event_id`) that records the bootstrap,
+/// the created resource, and an update of the company, whose `total_costs`
+/// take on the estimated costs. If a `sanity_policy` is given, the estimated
+/// costs are checked against it.
+pub fn bootstrap(caller: &User, member: &Member, company: &Company, id: ResourceID, event_id: EventID, spec: &ResourceSpec, quantity: Measure, lot: Option<dfc::ProductBatch>, name: Option<String>, region_id: Option<RegionID>, active: bool, sanity_policy: Option<&SanityPolicy>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceBootstrap)?;
+    if company.id() != spec.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if spec.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_spec".into()))?;
+    }
+    let costs_per_unit = spec.default_costs_per_unit().as_ref()
+        .ok_or(Error::MissingFields(vec!["default_costs_per_unit".into()]))?;
+    let units = match spec.inner().default_unit_of_resource() {
+        Some(unit) => {
+            if !measure::is_convertible(quantity.has_unit(), unit) {
+                Err(Error::ResourceSpecUnitMismatch(format!("{:?}", quantity.has_unit())))?;
+            }
+            measure::to_decimal(&measure::convert(&quantity, unit)?)?
+        }
+        None => measure::to_decimal(&quantity)?,
+    };
+    if units < Decimal::zero() {
+        Err(Error::NegativeMeasurement)?;
+    }
+    let costs = costs_per_unit.clone() * units;
+    let model = Resource::builder()
+        .id(id)
+        .inner(
+            vf::EconomicResource::builder()
+                .conforms_to(spec.id().clone())
+                .lot(lot)
+                .name(name)
+                .primary_accountable(Some(company.agent_id()))
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .in_custody_of(company.id().clone())
+        .costs(Costs::new())
+        .region_id(region_id)
+        .estimated_from(Some(spec.id().clone()))
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+
+    let mut mods = accounting::raise_new(company, event_id, model, quantity, costs.clone(), sanity_policy, Some("bootstrapped from estimated costs".into()), now)?;
+    let mut company = company.clone();
+    company.increase_costs(costs)?;
+    company.set_updated(now.clone());
+    mods.push(Op::Update, company);
+    Ok(mods)
+}
+
 /// Update a resource
-pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Resource, lot: Option<dfc::ProductBatch>, name: Option<String>, tracking_id: Option<String>, classifications: Option<Vec<Url>>, note: Option<String>, unit_of_effort: Option<Unit>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Resource, lot: Option<dfc::ProductBatch>, name: Option<String>, tracking_id: Option<String>, classifications: Option<Vec<Url>>, note: Option<String>, unit_of_effort: Option<Unit>, region_id: Option<Option<RegionID>>, allow_negative: Option<bool>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateResources)?;
     member.access_check(caller.id(), company.id(), CompanyPermission::ResourceUpdate)?;
     if !company.is_active() {
@@ -90,6 +171,12 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Re
     if unit_of_effort.is_some() {
         subject.inner_mut().set_unit_of_effort(unit_of_effort);
     }
+    if let Some(region_id) = region_id {
+        subject.set_region_id(region_id);
+    }
+    if let Some(allow_negative) = allow_negative {
+        subject.set_allow_negative(allow_negative);
+    }
     if let Some(active) = active {
         subject.set_active(active);
     }
@@ -119,7 +206,8 @@ mod tests {
     use super::*;
     use crate::{
         models::{
-            resource_spec::ResourceSpecID,
+            company::CompanyID,
+            event::Event,
         },
         util::{self, test::{self, *}},
     };
@@ -135,7 +223,7 @@ mod tests {
             .build().unwrap();
 
         let testfn = |state: &TestState<Resource, Resource>| {
-            create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), Some(lot.clone()), Some("widget batch".into()), None, vec!["https://www.wikidata.org/wiki/Q605117".parse().unwrap()], Some("niceee".into()), Some(Unit::Hour), true, &now)
+            create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), Some(lot.clone()), Some("widget batch".into()), None, vec!["https://www.wikidata.org/wiki/Q605117".parse().unwrap()], Some("niceee".into()), Some(Unit::Hour), Some(RegionID::new("cascadia")), false, true, &now)
         };
 
         let mods = testfn(&state).unwrap().into_vec();
@@ -151,6 +239,8 @@ mod tests {
         assert_eq!(resource.inner().note(), &Some("niceee".into()));
         assert_eq!(resource.inner().unit_of_effort(), &Some(Unit::Hour));
         assert_eq!(resource.in_custody_of(), &state.company().agent_id());
+        assert_eq!(resource.region_id(), &Some(RegionID::new("cascadia")));
+        assert_eq!(resource.allow_negative(), &false);
         assert!(resource.costs().is_zero());
         assert_eq!(resource.active(), &true);
         assert_eq!(resource.created(), &now);
@@ -158,6 +248,70 @@ mod tests {
         assert_eq!(resource.deleted(), &None);
     }
 
+    #[test]
+    fn can_bootstrap() {
+        let now = util::time::now();
+        let id = ResourceID::create();
+        let event_id = EventID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceBootstrap], &now);
+        let mut spec = make_resource_spec(&ResourceSpecID::create(), state.company().id(), "iron ore", &now);
+        spec.inner_mut().set_default_unit_of_resource(Some(Unit::Kilogram));
+        spec.set_default_costs_per_unit(Some(Costs::new_with_labor("miner", num!(0.25))));
+        state.model = Some(spec);
+
+        let testfn_inner = |state: &TestState<ResourceSpec, ResourceSpec>, quantity: Measure| {
+            bootstrap(state.user(), state.member(), state.company(), id.clone(), event_id.clone(), state.model(), quantity, None, Some("ore pile".into()), Some(RegionID::new("cascadia")), true, None, &now)
+        };
+        let testfn = |state: &TestState<ResourceSpec, ResourceSpec>| {
+            testfn_inner(state, Measure::new(num!(2), Unit::Tonne))
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 3);
+        let event = mods[0].clone().expect_op::<Event>(Op::Create).unwrap();
+        let resource = mods[1].clone().expect_op::<Resource>(Op::Create).unwrap();
+        let company = mods[2].clone().expect_op::<Company>(Op::Update).unwrap();
+        assert_eq!(event.id(), &event_id);
+        assert_eq!(event.inner().action(), &vf::Action::Raise);
+        assert_eq!(event.inner().provider(), &state.company().agent_id());
+        assert_eq!(event.inner().receiver(), &state.company().agent_id());
+        assert_eq!(event.inner().resource_inventoried_as(), &Some(id.clone()));
+        assert_eq!(event.inner().resource_quantity(), &Some(Measure::new(num!(2), Unit::Tonne)));
+        assert_eq!(event.move_costs(), &Some(Costs::new_with_labor("miner", num!(500))));
+        assert_eq!(company.total_costs(), &(state.company().total_costs().clone() + Costs::new_with_labor("miner", num!(500))));
+        assert_eq!(company.updated(), &now);
+        assert_eq!(resource.id(), &id);
+        assert_eq!(resource.inner().conforms_to(), state.model().id());
+        assert_eq!(resource.inner().accounting_quantity(), &Some(Measure::new(num!(2), Unit::Tonne)));
+        assert_eq!(resource.inner().onhand_quantity(), &Some(Measure::new(num!(2), Unit::Tonne)));
+        assert_eq!(resource.in_custody_of(), &state.company().agent_id());
+        assert_eq!(resource.costs(), &Costs::new_with_labor("miner", num!(500)));
+        assert_eq!(resource.estimated_from(), &Some(state.model().id().clone()));
+        assert!(resource.is_estimated());
+        assert_eq!(resource.region_id(), &Some(RegionID::new("cascadia")));
+
+        let res = testfn_inner(&state, Measure::new(num!(2), Unit::Litre));
+        assert_eq!(res, Err(Error::ResourceSpecUnitMismatch("Litre".into())));
+        let res = testfn_inner(&state, Measure::new(num!(-2), Unit::Kilogram));
+        assert_eq!(res, Err(Error::NegativeMeasurement));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_default_costs_per_unit(None);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::MissingFields(vec!["default_costs_per_unit".into()])));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state4 = state.clone();
+        state4.model_mut().set_deleted(Some(now.clone()));
+        let res = testfn(&state4);
+        assert_eq!(res, Err(Error::ObjectIsDeleted("resource_spec".into())));
+    }
+
     #[test]
     fn can_update() {
         let now = util::time::now();
@@ -167,13 +321,13 @@ mod tests {
         let lot = dfc::ProductBatch::builder()
             .batch_number("123")
             .build().unwrap();
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), Some(lot.clone()), Some("widget batch".into()), None, vec!["https://www.wikidata.org/wiki/Q605117".parse().unwrap()], Some("niceee".into()), Some(Unit::Hour), true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), Some(lot.clone()), Some("widget batch".into()), None, vec!["https://www.wikidata.org/wiki/Q605117".parse().unwrap()], Some("niceee".into()), Some(Unit::Hour), Some(RegionID::new("cascadia")), false, true, &now).unwrap().into_vec();
         let resource = mods[0].clone().expect_op::<Resource>(Op::Create).unwrap();
         state.model = Some(resource);
 
         let now2 = util::time::now();
         let testfn = |state: &TestState<Resource, Resource>| {
-            update(state.user(), state.member(), state.company(), state.model().clone(), None, Some("better widgets".into()), Some("444-computers-and-equipment".into()), None, None, Some(Unit::WattHour), Some(false), &now2)
+            update(state.user(), state.member(), state.company(), state.model().clone(), None, Some("better widgets".into()), Some("444-computers-and-equipment".into()), None, None, Some(Unit::WattHour), Some(None), Some(true), Some(false), &now2)
         };
 
         let mods = testfn(&state).unwrap().into_vec();
@@ -189,6 +343,8 @@ mod tests {
         assert_eq!(resource2.inner().note(), &Some("niceee".into()));
         assert_eq!(resource2.inner().unit_of_effort(), &Some(Unit::WattHour));
         assert_eq!(resource2.in_custody_of(), &state.company().agent_id());
+        assert_eq!(resource2.region_id(), &None);
+        assert_eq!(resource2.allow_negative(), &true);
         assert_eq!(resource2.active(), &false);
         assert_eq!(resource2.created(), &now);
         assert_eq!(resource2.updated(), &now2);
@@ -204,7 +360,7 @@ mod tests {
         let lot = dfc::ProductBatch::builder()
             .batch_number("123")
             .build().unwrap();
-        let mods = create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), Some(lot.clone()), Some("widget batch".into()), None, vec!["https://www.wikidata.org/wiki/Q605117".parse().unwrap()], Some("niceee".into()), Some(Unit::Hour), true, &now).unwrap().into_vec();
+        let mods = create(state.user(), state.member(), state.company(), id.clone(), spec.id().clone(), Some(lot.clone()), Some("widget batch".into()), None, vec!["https://www.wikidata.org/wiki/Q605117".parse().unwrap()], Some("niceee".into()), Some(Unit::Hour), Some(RegionID::new("cascadia")), false, true, &now).unwrap().into_vec();
         let resource = mods[0].clone().expect_op::<Resource>(Op::Create).unwrap();
         state.model = Some(resource);
 
@@ -0,0 +1,305 @@
+//! Resource groups let a company organize its inventory. Resources are added to
+//! and removed from groups using `link()` and `unlink()`.
+//!
+//! See the [resource group model.][1]
+//!
+//! [1]: ../../models/resource_group/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        company::{Company, Permission as CompanyPermission},
+        member::Member,
+        lib::{
+            agent::Agent,
+            basis_model::Model,
+        },
+        resource::Resource,
+        resource_group::{ResourceGroup, ResourceGroupID},
+        resource_group_link::{ResourceGroupLink, ResourceGroupLinkID},
+        user::User,
+    },
+};
+
+/// Create a new resource group
+pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company, id: ResourceGroupID, name: T, note: Option<String>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceGroupCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    let model = ResourceGroup::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .name(name)
+        .note(note)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update a resource group
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: ResourceGroup, name: Option<String>, note: Option<Option<String>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceGroupUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_group".into()))?;
+    }
+    if let Some(name) = name {
+        subject.set_name(name);
+    }
+    if let Some(note) = note {
+        subject.set_note(note);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Delete a resource group. The resources in the group are not affected.
+pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: ResourceGroup, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceGroupDelete)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_group".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+/// Add a resource to a group. The resource must be owned by (or in the custody
+/// of) the group's company.
+pub fn link(caller: &User, member: &Member, company: &Company, group: &ResourceGroup, resource: &Resource, id: ResourceGroupLinkID, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceGroupUpdate)?;
+    if company.id() != group.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if group.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_group".into()))?;
+    }
+    if resource.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource".into()))?;
+    }
+    let company_agent_id = company.agent_id();
+    if resource.inner().primary_accountable().as_ref() != Some(&company_agent_id) && resource.in_custody_of() != &company_agent_id {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let model = ResourceGroupLink::builder()
+        .id(id)
+        .group_id(group.id().clone())
+        .resource_id(resource.id().clone())
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Remove a resource from a group.
+pub fn unlink(caller: &User, member: &Member, company: &Company, group: &ResourceGroup, mut subject: ResourceGroupLink, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResources)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceGroupUpdate)?;
+    if company.id() != group.company_id() || subject.group_id() != group.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_group_link".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            resource::ResourceID,
+        },
+        util::{self, test::{self, *}},
+    };
+    use om2::{Measure, Unit};
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = ResourceGroupID::create();
+        let state = TestState::standard(vec![CompanyPermission::ResourceGroupCreate], &now);
+
+        let testfn = |state: &TestState<ResourceGroup, ResourceGroup>| {
+            create(state.user(), state.member(), state.company(), id.clone(), "lumber (warehouse 3)", Some("all of it".into()), true, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let group = mods[0].clone().expect_op::<ResourceGroup>(Op::Create).unwrap();
+        assert_eq!(group.id(), &id);
+        assert_eq!(group.company_id(), state.company().id());
+        assert_eq!(group.name(), "lumber (warehouse 3)");
+        assert_eq!(group.note(), &Some("all of it".into()));
+        assert_eq!(group.active(), &true);
+        assert_eq!(group.created(), &now);
+        assert_eq!(group.updated(), &now);
+        assert_eq!(group.deleted(), &None);
+    }
+
+    #[test]
+    fn can_update() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceGroupCreate, CompanyPermission::ResourceGroupUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), ResourceGroupID::create(), "lumber (warehouse 3)", None, true, &now).unwrap().into_vec();
+        state.model = Some(mods[0].clone().expect_op::<ResourceGroup>(Op::Create).unwrap());
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ResourceGroup, ResourceGroup>| {
+            update(state.user(), state.member(), state.company(), state.model().clone(), Some("lumber (warehouse 4)".into()), Some(Some("we moved".into())), Some(false), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_group", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let group = mods[0].clone().expect_op::<ResourceGroup>(Op::Update).unwrap();
+        assert_eq!(group.id(), state.model().id());
+        assert_eq!(group.name(), "lumber (warehouse 4)");
+        assert_eq!(group.note(), &Some("we moved".into()));
+        assert_eq!(group.active(), &false);
+        assert_eq!(group.created(), &now);
+        assert_eq!(group.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_delete() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceGroupCreate, CompanyPermission::ResourceGroupDelete], &now);
+        let mods = create(state.user(), state.member(), state.company(), ResourceGroupID::create(), "lumber (warehouse 3)", None, true, &now).unwrap().into_vec();
+        state.model = Some(mods[0].clone().expect_op::<ResourceGroup>(Op::Create).unwrap());
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ResourceGroup, ResourceGroup>| {
+            delete(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_group", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let group = mods[0].clone().expect_op::<ResourceGroup>(Op::Delete).unwrap();
+        assert_eq!(group.deleted(), &Some(now2.clone()));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_link() {
+        let now = util::time::now();
+        let id = ResourceGroupLinkID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceGroupCreate, CompanyPermission::ResourceGroupUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), ResourceGroupID::create(), "lumber (warehouse 3)", None, true, &now).unwrap().into_vec();
+        state.model = Some(mods[0].clone().expect_op::<ResourceGroup>(Op::Create).unwrap());
+        state.model2 = Some(make_resource(&ResourceID::new("oak"), state.company().id(), &Measure::new(num!(20), Unit::Metre), &Costs::new_with_labor("logger", num!(100)), &now));
+
+        let testfn = |state: &TestState<ResourceGroup, Resource>| {
+            link(state.user(), state.member(), state.company(), state.model(), state.model2(), id.clone(), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let link1 = mods[0].clone().expect_op::<ResourceGroupLink>(Op::Create).unwrap();
+        assert_eq!(link1.id(), &id);
+        assert_eq!(link1.group_id(), state.model().id());
+        assert_eq!(link1.resource_id(), state.model2().id());
+        assert_eq!(link1.active(), &true);
+        assert_eq!(link1.created(), &now);
+
+        // resources we only have custody of can be grouped
+        let mut state2 = state.clone();
+        state2.model2_mut().inner_mut().set_primary_accountable(Some(CompanyID::new("larry's lumber").into()));
+        assert!(testfn(&state2).is_ok());
+        state2.model2_mut().set_in_custody_of(CompanyID::new("larry's lumber").into());
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_company_id(CompanyID::create());
+        assert_eq!(testfn(&state3), Err(Error::InsufficientPrivileges));
+
+        let mut state4 = state.clone();
+        state4.model_mut().set_deleted(Some(now.clone()));
+        assert_eq!(testfn(&state4), Err(Error::ObjectIsDeleted("resource_group".into())));
+
+        let mut state5 = state.clone();
+        state5.model2_mut().set_deleted(Some(now.clone()));
+        assert_eq!(testfn(&state5), Err(Error::ObjectIsDeleted("resource".into())));
+    }
+
+    #[test]
+    fn can_unlink() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceGroupCreate, CompanyPermission::ResourceGroupUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), ResourceGroupID::create(), "lumber (warehouse 3)", None, true, &now).unwrap().into_vec();
+        let group = mods[0].clone().expect_op::<ResourceGroup>(Op::Create).unwrap();
+        let resource = make_resource(&ResourceID::new("oak"), state.company().id(), &Measure::new(num!(20), Unit::Metre), &Costs::new_with_labor("logger", num!(100)), &now);
+        let mods = link(state.user(), state.member(), state.company(), &group, &resource, ResourceGroupLinkID::create(), &now).unwrap().into_vec();
+        state.model = Some(group);
+        state.model2 = Some(mods[0].clone().expect_op::<ResourceGroupLink>(Op::Create).unwrap());
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ResourceGroup, ResourceGroupLink>| {
+            unlink(state.user(), state.member(), state.company(), state.model(), state.model2().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let link2 = mods[0].clone().expect_op::<ResourceGroupLink>(Op::Delete).unwrap();
+        assert_eq!(link2.id(), state.model2().id());
+        assert_eq!(link2.deleted(), &Some(now2.clone()));
+
+        let mut state2 = state.clone();
+        state2.model2_mut().set_group_id(ResourceGroupID::create());
+        assert_eq!(testfn(&state2), Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model2_mut().set_deleted(Some(now.clone()));
+        assert_eq!(testfn(&state3), Err(Error::ObjectIsDeleted("resource_group_link".into())));
+    }
+}
@@ -5,14 +5,23 @@
 //! "Haworth Zody" chair, and the *resource* is the cheap knock-off counterfeit
 //! that Amazon ships to you when you order it.
 //!
+//! Specs that resources have been built against shouldn't be edited in place,
+//! since that changes what those resources (and their costs) mean. Instead, use
+//! [revise] to create a new version of the spec.
+//!
+//! Resource specs can be published to a shared catalog with `set_published()`,
+//! after which any company can `fork()` them into their own catalog.
+//!
 //! See the [resource spec model.][2]
 //!
 //! [1]: ../resource/index.html
 //! [2]: ../../models/resource_spec/index.html
+//! [revise]: fn.revise.html
 
 use chrono::{DateTime, Utc};
 use crate::{
     access::Permission,
+    costs::Costs,
     error::{Error, Result},
     models::{
         Op,
@@ -20,7 +29,7 @@ use crate::{
         company::{Company, Permission as CompanyPermission},
         member::Member,
         lib::basis_model::Model,
-        resource_spec::{ResourceSpec, ResourceSpecID},
+        resource_spec::{Dimensions, ResourceSpec, ResourceSpecID},
         user::User,
     },
 };
@@ -48,6 +57,7 @@ pub fn create<T: Into<String>>(caller: &User, member: &Member, company: &Company
                 .map_err(|e| Error::BuilderFailed(e))?
         )
         .company_id(company.id().clone())
+        .version(1u32)
         .active(active)
         .created(now.clone())
         .updated(now.clone())
@@ -63,6 +73,9 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Re
     if !company.is_active() {
         Err(Error::ObjectIsInactive("company".into()))?;
     }
+    if subject.is_deprecated() {
+        Err(Error::ObjectIsReadOnly("resource_spec".into()))?;
+    }
     if let Some(name) = name {
         subject.inner_mut().set_name(name);
     }
@@ -85,6 +98,174 @@ pub fn update(caller: &User, member: &Member, company: &Company, mut subject: Re
     Ok(Modifications::new_single(Op::Update, subject))
 }
 
+/// Revise a resource spec, creating a new version (with the given id) that
+/// supersedes it. The new version copies the old one, with any of the given
+/// fields replaced. The old version is marked as deprecated and can no longer
+/// be updated, and resources that conform to it are left untouched.
+pub fn revise(caller: &User, member: &Member, company: &Company, mut subject: ResourceSpec, id: ResourceSpecID, name: Option<String>, note: Option<String>, classifications: Option<Vec<Url>>, default_unit_of_effort: Option<Unit>, default_unit_of_resource: Option<Unit>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResourceSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_spec".into()))?;
+    }
+    if subject.is_deprecated() {
+        Err(Error::ObjectIsReadOnly("resource_spec".into()))?;
+    }
+    let mut inner = subject.inner().clone();
+    if let Some(name) = name {
+        inner.set_name(name);
+    }
+    if let Some(note) = note {
+        inner.set_note(Some(note));
+    }
+    if let Some(classifications) = classifications {
+        inner.set_resource_classified_as(classifications);
+    }
+    if default_unit_of_effort.is_some() {
+        inner.set_default_unit_of_effort(default_unit_of_effort);
+    }
+    if default_unit_of_resource.is_some() {
+        inner.set_default_unit_of_resource(default_unit_of_resource);
+    }
+    let model = ResourceSpec::builder()
+        .id(id.clone())
+        .inner(inner)
+        .company_id(company.id().clone())
+        .version(subject.version() + 1)
+        .supersedes(Some(subject.id().clone()))
+        .dimensions(subject.dimensions().clone())
+        .default_costs_per_unit(subject.default_costs_per_unit().clone())
+        .active(*subject.active())
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    subject.set_superseded_by(Some(id));
+    subject.set_updated(now.clone());
+    let mut mods = Modifications::new();
+    mods.push(Op::Create, model);
+    mods.push(Op::Update, subject);
+    Ok(mods)
+}
+
+/// Set (or clear) the physical dimensions of one unit of a resource spec.
+///
+/// Once a spec has dimensions, resources conforming to it can be produced or
+/// raised in units of weight/volume as well as its default unit.
+pub fn set_dimensions(caller: &User, member: &Member, company: &Company, mut subject: ResourceSpec, dimensions: Option<Dimensions>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResourceSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_spec".into()))?;
+    }
+    if subject.is_deprecated() {
+        Err(Error::ObjectIsReadOnly("resource_spec".into()))?;
+    }
+    subject.set_dimensions(dimensions);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Set (or clear) the estimated costs for one unit of a resource spec. These
+/// are used to bootstrap resources whose real costs aren't known.
+pub fn set_default_costs(caller: &User, member: &Member, company: &Company, mut subject: ResourceSpec, default_costs_per_unit: Option<Costs>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResourceSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_spec".into()))?;
+    }
+    if subject.is_deprecated() {
+        Err(Error::ObjectIsReadOnly("resource_spec".into()))?;
+    }
+    if let Some(costs) = default_costs_per_unit.as_ref() {
+        if costs.is_lt_0() {
+            Err(Error::NegativeCosts)?;
+        }
+    }
+    subject.set_default_costs_per_unit(default_costs_per_unit);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Publish a resource spec to the shared catalog (or pull it back out). Specs
+/// that have been published can be forked by other companies.
+///
+/// Unpublishing a spec doesn't affect any forks already made from it.
+pub fn set_published(caller: &User, member: &Member, company: &Company, mut subject: ResourceSpec, published: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResourceSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceSpecUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_spec".into()))?;
+    }
+    if subject.is_deprecated() {
+        Err(Error::ObjectIsReadOnly("resource_spec".into()))?;
+    }
+    if !published {
+        subject.set_published(None);
+    } else if !subject.is_published() {
+        subject.set_published(Some(now.clone()));
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Fork a resource spec into the given company, creating a copy of it (with
+/// the given id) that links back to the original via `forked_from`.
+///
+/// The spec being forked must be published, unless it belongs to the company
+/// forking it. The fork starts over at version 1 and starts out unpublished.
+pub fn fork(caller: &User, member: &Member, company: &Company, source: &ResourceSpec, id: ResourceSpecID, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdateResourceSpecs)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::ResourceSpecCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if source.is_deleted() {
+        Err(Error::ObjectIsDeleted("resource_spec".into()))?;
+    }
+    if !source.is_published() && source.company_id() != company.id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    let model = ResourceSpec::builder()
+        .id(id)
+        .inner(source.inner().clone())
+        .company_id(company.id().clone())
+        .version(1u32)
+        .dimensions(source.dimensions().clone())
+        .default_costs_per_unit(source.default_costs_per_unit().clone())
+        .forked_from(Some(source.id().clone()))
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
 /// Delete a resource spec
 pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: ResourceSpec, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::CompanyUpdateResourceSpecs)?;
@@ -104,10 +285,12 @@ mod tests {
     use super::*;
     use crate::{
         models::{
+            company::CompanyID,
             resource_spec::{ResourceSpec, ResourceSpecID},
         },
         util::{self, test::{self, *}},
     };
+    use om2::Measure;
 
     #[test]
     fn can_create() {
@@ -167,6 +350,151 @@ mod tests {
         assert_eq!(recspec2.created(), &now);
         assert_eq!(recspec2.updated(), &now2);
         assert_eq!(recspec2.deleted(), &None);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_superseded_by(Some(ResourceSpecID::create()));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::ObjectIsReadOnly("resource_spec".into())));
+    }
+
+    #[test]
+    fn can_revise() {
+        let now = util::time::now();
+        let id = ResourceSpecID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceSpecCreate, CompanyPermission::ResourceSpecUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), id.clone(), "Beans", "yummy", vec!["https://www.wikidata.org/wiki/Q379813".parse().unwrap()], Some(Unit::Hour), Some(Unit::Kilogram), true, &now).unwrap().into_vec();
+        let recspec = mods[0].clone().expect_op::<ResourceSpec>(Op::Create).unwrap();
+        assert_eq!(recspec.version(), &1);
+        assert_eq!(recspec.supersedes(), &None);
+        assert_eq!(recspec.superseded_by(), &None);
+        assert!(!recspec.is_deprecated());
+        state.model = Some(recspec);
+
+        let now2 = util::time::now();
+        let id2 = ResourceSpecID::create();
+        let testfn = |state: &TestState<ResourceSpec, ResourceSpec>| {
+            revise(state.user(), state.member(), state.company(), state.model().clone(), id2.clone(), None, Some("now with less salmonella".into()), None, None, Some(Unit::Gram), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let recspec2 = mods[0].clone().expect_op::<ResourceSpec>(Op::Create).unwrap();
+        let recspec1 = mods[1].clone().expect_op::<ResourceSpec>(Op::Update).unwrap();
+
+        assert_eq!(recspec2.id(), &id2);
+        assert_eq!(recspec2.inner().name(), "Beans");
+        assert_eq!(recspec2.inner().note(), &Some("now with less salmonella".into()));
+        assert_eq!(recspec2.inner().default_unit_of_effort(), &Some(Unit::Hour));
+        assert_eq!(recspec2.inner().default_unit_of_resource(), &Some(Unit::Gram));
+        assert_eq!(recspec2.company_id(), state.company().id());
+        assert_eq!(recspec2.version(), &2);
+        assert_eq!(recspec2.supersedes(), &Some(id.clone()));
+        assert_eq!(recspec2.superseded_by(), &None);
+        assert!(!recspec2.is_deprecated());
+        assert_eq!(recspec2.active(), &true);
+        assert_eq!(recspec2.created(), &now2);
+        assert_eq!(recspec2.updated(), &now2);
+
+        assert_eq!(recspec1.id(), &id);
+        assert_eq!(recspec1.inner(), state.model().inner());
+        assert_eq!(recspec1.version(), &1);
+        assert_eq!(recspec1.superseded_by(), &Some(id2.clone()));
+        assert!(recspec1.is_deprecated());
+        assert_eq!(recspec1.updated(), &now2);
+
+        // deprecated specs can't be revised again
+        let mut state2 = state.clone();
+        state2.model = Some(recspec1);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::ObjectIsReadOnly("resource_spec".into())));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_set_dimensions() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceSpecUpdate], &now);
+        let recspec = make_resource_spec(&ResourceSpecID::create(), state.company().id(), "Beans", &now);
+        state.model = Some(recspec);
+        let dimensions = Dimensions::new(Some(Measure::new(num!(0.5), Unit::Kilogram)), Some(Measure::new(num!(0.75), Unit::Litre))).unwrap();
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ResourceSpec, ResourceSpec>| {
+            set_dimensions(state.user(), state.member(), state.company(), state.model().clone(), Some(dimensions.clone()), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let recspec2 = mods[0].clone().expect_op::<ResourceSpec>(Op::Update).unwrap();
+        assert_eq!(recspec2.dimensions(), &Some(dimensions.clone()));
+        assert_eq!(recspec2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::new("zing"));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_superseded_by(Some(ResourceSpecID::create()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsReadOnly("resource_spec".into())));
+    }
+
+    #[test]
+    fn can_set_default_costs() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceSpecUpdate], &now);
+        let recspec = make_resource_spec(&ResourceSpecID::create(), state.company().id(), "Beans", &now);
+        state.model = Some(recspec);
+        let costs = Costs::new_with_labor("farmer", num!(0.3));
+
+        let now2 = util::time::now();
+        let testfn_inner = |state: &TestState<ResourceSpec, ResourceSpec>, costs: Option<Costs>| {
+            set_default_costs(state.user(), state.member(), state.company(), state.model().clone(), costs, &now2)
+        };
+        let testfn = |state: &TestState<ResourceSpec, ResourceSpec>| {
+            testfn_inner(state, Some(costs.clone()))
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let recspec2 = mods[0].clone().expect_op::<ResourceSpec>(Op::Update).unwrap();
+        assert_eq!(recspec2.default_costs_per_unit(), &Some(costs.clone()));
+        assert_eq!(recspec2.updated(), &now2);
+
+        // revisions carry the default costs forward
+        let mut state1 = state.clone();
+        state1.model = Some(recspec2.clone());
+        let mods = revise(state1.user(), state1.member(), state1.company(), recspec2.clone(), ResourceSpecID::create(), None, None, None, None, None, &now2).unwrap().into_vec();
+        let recspec3 = mods[0].clone().expect_op::<ResourceSpec>(Op::Create).unwrap();
+        assert_eq!(recspec3.default_costs_per_unit(), &Some(costs.clone()));
+
+        let mods = testfn_inner(&state1, None).unwrap().into_vec();
+        let recspec4 = mods[0].clone().expect_op::<ResourceSpec>(Op::Update).unwrap();
+        assert_eq!(recspec4.default_costs_per_unit(), &None);
+
+        let res = testfn_inner(&state, Some(Costs::new() - costs.clone()));
+        assert_eq!(res, Err(Error::NegativeCosts));
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::new("zing"));
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_superseded_by(Some(ResourceSpecID::create()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsReadOnly("resource_spec".into())));
     }
 
     #[test]
@@ -200,5 +528,80 @@ mod tests {
         assert_eq!(recspec2.updated(), &now);
         assert_eq!(recspec2.deleted(), &Some(now2.clone()));
     }
-}
 
+    #[test]
+    fn can_set_published() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceSpecUpdate], &now);
+        let spec = make_resource_spec(&ResourceSpecID::create(), state.company().id(), "widget", &now);
+        state.model = Some(spec);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ResourceSpec, ResourceSpec>| {
+            set_published(state.user(), state.member(), state.company(), state.model().clone(), true, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let spec2 = mods[0].clone().expect_op::<ResourceSpec>(Op::Update).unwrap();
+        assert_eq!(spec2.published(), &Some(now2.clone()));
+        assert!(spec2.is_published());
+        assert_eq!(spec2.updated(), &now2);
+
+        let mods = set_published(state.user(), state.member(), state.company(), spec2.clone(), false, &now2).unwrap().into_vec();
+        let spec3 = mods[0].clone().expect_op::<ResourceSpec>(Op::Update).unwrap();
+        assert_eq!(spec3.published(), &None);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let mut state3 = state.clone();
+        state3.model_mut().set_superseded_by(Some(ResourceSpecID::create()));
+        let res = testfn(&state3);
+        assert_eq!(res, Err(Error::ObjectIsReadOnly("resource_spec".into())));
+    }
+
+    #[test]
+    fn can_fork() {
+        let now = util::time::now();
+        let id = ResourceSpecID::create();
+        let mut state = TestState::standard(vec![CompanyPermission::ResourceSpecCreate], &now);
+        let mut source = make_resource_spec(&ResourceSpecID::create(), &CompanyID::new("larry's widgets"), "widget", &now);
+        source.set_version(3);
+        source.set_supersedes(Some(ResourceSpecID::create()));
+        source.set_dimensions(Some(Dimensions::new(Some(Measure::new(num!(2), Unit::Kilogram)), None).unwrap()));
+        source.set_published(Some(now.clone()));
+        state.model = Some(source);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<ResourceSpec, ResourceSpec>| {
+            fork(state.user(), state.member(), state.company(), state.model(), id.clone(), true, &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "resource_spec", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let forked = mods[0].clone().expect_op::<ResourceSpec>(Op::Create).unwrap();
+        assert_eq!(forked.id(), &id);
+        assert_eq!(forked.inner(), state.model().inner());
+        assert_eq!(forked.company_id(), state.company().id());
+        assert_eq!(forked.version(), &1);
+        assert_eq!(forked.supersedes(), &None);
+        assert_eq!(forked.dimensions(), state.model().dimensions());
+        assert_eq!(forked.published(), &None);
+        assert_eq!(forked.forked_from(), &Some(state.model().id().clone()));
+        assert_eq!(forked.created(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_published(None);
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        state2.model_mut().set_company_id(state.company().id().clone());
+        assert!(testfn(&state2).is_ok());
+    }
+}
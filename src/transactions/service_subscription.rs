@@ -0,0 +1,348 @@
+//! Service subscriptions are ongoing agreements for one company to provide a
+//! service to another every period. Either company in the subscription can
+//! manage it.
+//!
+//! Once a period starts, `materialize_period()` creates that period's
+//! `DeliverService` commitment from the subscription's terms, which is then
+//! fulfilled like any other commitment.
+//!
+//! See the [service subscription model.][1]
+//!
+//! [1]: ../../models/service_subscription/index.html
+
+use chrono::{DateTime, Duration, Utc};
+use crate::{
+    access::Permission,
+    costs::Costs,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        agreement::Agreement,
+        commitment::{Commitment, CommitmentID},
+        company::{Company, CompanyID, Permission as CompanyPermission},
+        lib::basis_model::Model,
+        member::Member,
+        service_subscription::{ServiceSubscription, ServiceSubscriptionID, ServiceTerms, SubscriptionStatus},
+        user::User,
+    },
+    util::measure,
+};
+use om2::Unit;
+use rust_decimal::prelude::*;
+use vf_rs::vf;
+
+/// Make sure the caller can manage subscriptions for the given company, and
+/// that the company is party to the subscription.
+fn check_access(caller: &User, member: &Member, company: &Company, provider: &CompanyID, receiver: &CompanyID, permission: CompanyPermission) -> Result<()> {
+    caller.access_check(Permission::CompanyUpdateCommitments)?;
+    member.access_check(caller.id(), company.id(), permission)?;
+    if company.id() != provider && company.id() != receiver {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    Ok(())
+}
+
+/// Make sure a subscription's terms make sense
+fn check_terms(terms: &ServiceTerms, cost_cap: Option<&Decimal>) -> Result<()> {
+    terms.period_seconds()?;
+    if !measure::is_convertible(terms.effort_per_period().has_unit(), &Unit::Hour) {
+        Err(Error::MeasureUnitsMismatched)?;
+    }
+    if measure::to_decimal(terms.effort_per_period())? < Decimal::zero() {
+        Err(Error::NegativeMeasurement)?;
+    }
+    if let Some(cost_cap) = cost_cap {
+        if cost_cap < &Decimal::zero() {
+            Err(Error::InvalidAmount(*cost_cap))?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a new service subscription. The first period starts at `starts`.
+///
+/// Both companies must be participants in the given agreement, which all of
+/// the subscription's commitments are made under.
+pub fn create(caller: &User, member: &Member, company: &Company, agreement: &Agreement, id: ServiceSubscriptionID, provider: CompanyID, receiver: CompanyID, terms: ServiceTerms, cost_cap: Option<Decimal>, starts: DateTime<Utc>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_access(caller, member, company, &provider, &receiver, CompanyPermission::CommitmentCreate)?;
+    if !agreement.has_participant(&provider.clone().into()) || !agreement.has_participant(&receiver.clone().into()) {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    check_terms(&terms, cost_cap.as_ref())?;
+    let model = ServiceSubscription::builder()
+        .id(id)
+        .provider(provider)
+        .receiver(receiver)
+        .agreement_id(agreement.id().clone())
+        .terms(terms)
+        .cost_cap(cost_cap)
+        .committed(Costs::new())
+        .next_period(starts)
+        .status(SubscriptionStatus::Running)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Pause a running subscription. No periods can be materialized while it's
+/// paused.
+pub fn pause(caller: &User, member: &Member, company: &Company, subject: ServiceSubscription, now: &DateTime<Utc>) -> Result<Modifications> {
+    set_status(caller, member, company, subject, SubscriptionStatus::Running, SubscriptionStatus::Paused, CompanyPermission::CommitmentUpdate, now)
+}
+
+/// Resume a paused subscription. Periods that started while it was paused are
+/// not skipped, so the next call to `materialize_period()` picks up where it
+/// left off.
+pub fn resume(caller: &User, member: &Member, company: &Company, subject: ServiceSubscription, now: &DateTime<Utc>) -> Result<Modifications> {
+    set_status(caller, member, company, subject, SubscriptionStatus::Paused, SubscriptionStatus::Running, CompanyPermission::CommitmentUpdate, now)
+}
+
+/// Cancel a subscription (running or paused) for good. Commitments already
+/// materialized are left alone.
+pub fn cancel(caller: &User, member: &Member, company: &Company, subject: ServiceSubscription, now: &DateTime<Utc>) -> Result<Modifications> {
+    let from = subject.status().clone();
+    if from == SubscriptionStatus::Canceled {
+        Err(Error::SubscriptionStatusInvalid)?;
+    }
+    set_status(caller, member, company, subject, from, SubscriptionStatus::Canceled, CompanyPermission::CommitmentDelete, now)
+}
+
+/// Move a subscription from one status to another.
+fn set_status(caller: &User, member: &Member, company: &Company, mut subject: ServiceSubscription, from: SubscriptionStatus, to: SubscriptionStatus, permission: CompanyPermission, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_access(caller, member, company, subject.provider(), subject.receiver(), permission)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("service_subscription".into()))?;
+    }
+    if subject.status() != &from {
+        Err(Error::SubscriptionStatusInvalid)?;
+    }
+    subject.set_status(to);
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Create the commitment for a subscription's next period, and move the
+/// subscription on to the period after.
+///
+/// The period must have started, the subscription must be running, and the
+/// period's costs can't push the subscription over its cost cap. Periods are
+/// materialized one at a time, so a subscription that's fallen behind needs one
+/// call (and one `commitment_id`) for each period it missed.
+pub fn materialize_period(caller: &User, member: &Member, company: &Company, mut subject: ServiceSubscription, commitment_id: CommitmentID, now: &DateTime<Utc>) -> Result<Modifications> {
+    check_access(caller, member, company, subject.provider(), subject.receiver(), CompanyPermission::CommitmentCreate)?;
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("service_subscription".into()))?;
+    }
+    if !subject.is_active() {
+        Err(Error::ObjectIsInactive("service_subscription".into()))?;
+    }
+    if !subject.is_running() {
+        Err(Error::SubscriptionStatusInvalid)?;
+    }
+    if subject.next_period() > now {
+        Err(Error::SubscriptionPeriodNotStarted)?;
+    }
+    subject.check_cap()?;
+
+    let terms = subject.terms().clone();
+    let begin = subject.next_period().clone();
+    let end = begin.clone() + Duration::seconds(terms.period_seconds()?);
+    let commitment = Commitment::builder()
+        .id(commitment_id)
+        .inner(
+            vf::Commitment::builder()
+                .action(vf::Action::DeliverService)
+                .clause_of(Some(subject.agreement_id().clone()))
+                .created(Some(now.clone()))
+                .due(Some(end.clone()))
+                .effort_quantity(Some(terms.effort_per_period().clone()))
+                .finished(Some(false))
+                .has_beginning(Some(begin))
+                .has_end(Some(end.clone()))
+                .provider(subject.provider().clone())
+                .receiver(subject.receiver().clone())
+                .resource_conforms_to(terms.resource_conforms_to().clone())
+                .build()
+                .map_err(|e| Error::BuilderFailed(e))?
+        )
+        .move_costs(terms.move_costs().clone())
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    subject.set_committed(subject.committed().clone() + terms.move_costs().clone());
+    subject.set_next_period(end);
+    subject.set_updated(now.clone());
+    let mut mods = Modifications::new_single(Op::Create, commitment);
+    mods.push(Op::Update, subject);
+    Ok(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            agreement::AgreementID,
+            lib::agent::Agent,
+        },
+        util::{self, test::{self, *}},
+    };
+    use om2::Measure;
+
+    fn make_terms() -> ServiceTerms {
+        ServiceTerms::new(Measure::new(num!(10), Unit::Hour), Measure::new(num!(1), Unit::Week), Costs::new_with_labor("janitor", num!(300)), None)
+    }
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = ServiceSubscriptionID::create();
+        let state: TestState<ServiceSubscription, ServiceSubscription> = TestState::standard(vec![CompanyPermission::CommitmentCreate], &now);
+        let provider = make_company(&CompanyID::create(), "jerry's janitorial", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![provider.agent_id(), state.company().agent_id()], "cleaning", "weekly cleaning", &now);
+
+        let testfn = |state: &TestState<ServiceSubscription, ServiceSubscription>| {
+            create(state.user(), state.member(), state.company(), &agreement, id.clone(), provider.id().clone(), state.company().id().clone(), make_terms(), Some(num!(1000)), now.clone(), true, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let subscription = mods[0].clone().expect_op::<ServiceSubscription>(Op::Create).unwrap();
+        assert_eq!(subscription.id(), &id);
+        assert_eq!(subscription.provider(), provider.id());
+        assert_eq!(subscription.receiver(), state.company().id());
+        assert_eq!(subscription.agreement_id(), agreement.id());
+        assert_eq!(subscription.terms(), &make_terms());
+        assert_eq!(subscription.cost_cap(), &Some(num!(1000)));
+        assert_eq!(subscription.committed(), &Costs::new());
+        assert_eq!(subscription.next_period(), &now);
+        assert_eq!(subscription.status(), &SubscriptionStatus::Running);
+        assert_eq!(subscription.active(), &true);
+        assert_eq!(subscription.created(), &now);
+
+        // can't create a subscription we're not party to
+        let other = make_company(&CompanyID::create(), "larry's widgets", &now);
+        let res = create(state.user(), state.member(), state.company(), &agreement, id.clone(), provider.id().clone(), other.id().clone(), make_terms(), None, now.clone(), true, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+        // or one outside the agreement
+        let agreement2 = make_agreement(&AgreementID::create(), &vec![state.company().agent_id()], "cleaning", "weekly cleaning", &now);
+        let res = create(state.user(), state.member(), state.company(), &agreement2, id.clone(), provider.id().clone(), state.company().id().clone(), make_terms(), None, now.clone(), true, &now);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+
+        let bad_terms = ServiceTerms::new(Measure::new(num!(10), Unit::Kilogram), Measure::new(num!(1), Unit::Week), Costs::new(), None);
+        let res = create(state.user(), state.member(), state.company(), &agreement, id.clone(), provider.id().clone(), state.company().id().clone(), bad_terms, None, now.clone(), true, &now);
+        assert_eq!(res, Err(Error::MeasureUnitsMismatched));
+        let bad_terms = ServiceTerms::new(Measure::new(num!(10), Unit::Hour), Measure::new(num!(0), Unit::Week), Costs::new(), None);
+        let res = create(state.user(), state.member(), state.company(), &agreement, id.clone(), provider.id().clone(), state.company().id().clone(), bad_terms, None, now.clone(), true, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(0))));
+        let res = create(state.user(), state.member(), state.company(), &agreement, id.clone(), provider.id().clone(), state.company().id().clone(), make_terms(), Some(num!(-1)), now.clone(), true, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+    }
+
+    #[test]
+    fn can_pause_resume_cancel() {
+        let now = util::time::now();
+        let now2 = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::CommitmentCreate, CompanyPermission::CommitmentUpdate, CompanyPermission::CommitmentDelete], &now);
+        let provider = make_company(&CompanyID::create(), "jerry's janitorial", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![provider.agent_id(), state.company().agent_id()], "cleaning", "weekly cleaning", &now);
+        let mods = create(state.user(), state.member(), state.company(), &agreement, ServiceSubscriptionID::create(), provider.id().clone(), state.company().id().clone(), make_terms(), None, now.clone(), true, &now).unwrap().into_vec();
+        let subscription = mods[0].clone().expect_op::<ServiceSubscription>(Op::Create).unwrap();
+        state.model = Some(subscription);
+
+        let testfn = |state: &TestState<ServiceSubscription, ServiceSubscription>| {
+            pause(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "service_subscription", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        let paused = mods[0].clone().expect_op::<ServiceSubscription>(Op::Update).unwrap();
+        assert_eq!(paused.status(), &SubscriptionStatus::Paused);
+        assert_eq!(paused.updated(), &now2);
+        assert_eq!(pause(state.user(), state.member(), state.company(), paused.clone(), &now2), Err(Error::SubscriptionStatusInvalid));
+        assert_eq!(resume(state.user(), state.member(), state.company(), state.model().clone(), &now2), Err(Error::SubscriptionStatusInvalid));
+
+        let mods = resume(state.user(), state.member(), state.company(), paused.clone(), &now2).unwrap().into_vec();
+        let resumed = mods[0].clone().expect_op::<ServiceSubscription>(Op::Update).unwrap();
+        assert_eq!(resumed.status(), &SubscriptionStatus::Running);
+
+        let mods = cancel(state.user(), state.member(), state.company(), paused.clone(), &now2).unwrap().into_vec();
+        let canceled = mods[0].clone().expect_op::<ServiceSubscription>(Op::Update).unwrap();
+        assert_eq!(canceled.status(), &SubscriptionStatus::Canceled);
+        assert_eq!(cancel(state.user(), state.member(), state.company(), canceled.clone(), &now2), Err(Error::SubscriptionStatusInvalid));
+        assert_eq!(resume(state.user(), state.member(), state.company(), canceled.clone(), &now2), Err(Error::SubscriptionStatusInvalid));
+
+        // canceling takes delete permissions
+        let mut state2 = state.clone();
+        state2.member_mut().set_permissions(vec![CompanyPermission::CommitmentUpdate]);
+        assert_eq!(cancel(state2.user(), state2.member(), state2.company(), state2.model().clone(), &now2), Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_materialize_period() {
+        let now: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+        let week2: DateTime<Utc> = "2020-01-08T00:00:00Z".parse().unwrap();
+        let week3: DateTime<Utc> = "2020-01-15T00:00:00Z".parse().unwrap();
+        let mut state = TestState::standard(vec![CompanyPermission::CommitmentCreate, CompanyPermission::CommitmentUpdate], &now);
+        let provider = make_company(&CompanyID::create(), "jerry's janitorial", &now);
+        let agreement = make_agreement(&AgreementID::create(), &vec![provider.agent_id(), state.company().agent_id()], "cleaning", "weekly cleaning", &now);
+        let mods = create(state.user(), state.member(), state.company(), &agreement, ServiceSubscriptionID::create(), provider.id().clone(), state.company().id().clone(), make_terms(), Some(num!(600)), now.clone(), true, &now).unwrap().into_vec();
+        let subscription = mods[0].clone().expect_op::<ServiceSubscription>(Op::Create).unwrap();
+        state.model = Some(subscription);
+        let commitment_id = CommitmentID::create();
+
+        let testfn = |state: &TestState<ServiceSubscription, ServiceSubscription>| {
+            materialize_period(state.user(), state.member(), state.company(), state.model().clone(), commitment_id.clone(), &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "service_subscription", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 2);
+        let commitment = mods[0].clone().expect_op::<Commitment>(Op::Create).unwrap();
+        let subscription2 = mods[1].clone().expect_op::<ServiceSubscription>(Op::Update).unwrap();
+        assert_eq!(commitment.id(), &commitment_id);
+        assert_eq!(commitment.inner().action(), &vf::Action::DeliverService);
+        assert_eq!(commitment.inner().clause_of(), &Some(agreement.id().clone()));
+        assert_eq!(commitment.inner().effort_quantity(), &Some(Measure::new(num!(10), Unit::Hour)));
+        assert_eq!(commitment.inner().has_beginning(), &Some(now.clone()));
+        assert_eq!(commitment.inner().has_end(), &Some(week2.clone()));
+        assert_eq!(commitment.inner().due(), &Some(week2.clone()));
+        assert_eq!(commitment.inner().provider().clone(), provider.agent_id());
+        assert_eq!(commitment.inner().receiver().clone(), state.company().agent_id());
+        assert_eq!(commitment.move_costs(), &Costs::new_with_labor("janitor", num!(300)));
+        assert_eq!(subscription2.committed(), &Costs::new_with_labor("janitor", num!(300)));
+        assert_eq!(subscription2.next_period(), &week2);
+        assert_eq!(subscription2.updated(), &now);
+
+        // the next period hasn't started yet
+        let res = materialize_period(state.user(), state.member(), state.company(), subscription2.clone(), CommitmentID::create(), &now);
+        assert_eq!(res, Err(Error::SubscriptionPeriodNotStarted));
+        let mods = materialize_period(state.user(), state.member(), state.company(), subscription2.clone(), CommitmentID::create(), &week2).unwrap().into_vec();
+        let subscription3 = mods[1].clone().expect_op::<ServiceSubscription>(Op::Update).unwrap();
+        assert_eq!(subscription3.next_period(), &week3);
+        assert_eq!(subscription3.committed(), &Costs::new_with_labor("janitor", num!(600)));
+        // the cap is reached
+        let res = materialize_period(state.user(), state.member(), state.company(), subscription3.clone(), CommitmentID::create(), &week3);
+        assert_eq!(res, Err(Error::SubscriptionCapExceeded("another period would commit 900 credits, over the cap of 600".into())));
+
+        // paused subscriptions don't materialize
+        let mut state2 = state.clone();
+        state2.model_mut().set_status(SubscriptionStatus::Paused);
+        assert_eq!(testfn(&state2), Err(Error::SubscriptionStatusInvalid));
+        let mut state3 = state.clone();
+        state3.model_mut().set_active(false);
+        assert_eq!(testfn(&state3), Err(Error::ObjectIsInactive("service_subscription".into())));
+    }
+}
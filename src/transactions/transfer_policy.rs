@@ -0,0 +1,190 @@
+//! Transfer policies let a company limit the transfers it makes to others.
+//!
+//! See the [transfer policy model.][1]
+//!
+//! [1]: ../../models/transfer_policy/index.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    access::Permission,
+    error::{Error, Result},
+    models::{
+        Op,
+        Modifications,
+        agreement::AgreementID,
+        company::{Company, CompanyID, Permission as CompanyPermission},
+        member::Member,
+        lib::basis_model::Model,
+        transfer_policy::{TransferPolicy, TransferPolicyID},
+        user::User,
+    },
+};
+use rust_decimal::prelude::*;
+
+/// Create a new transfer policy
+pub fn create(caller: &User, member: &Member, company: &Company, id: TransferPolicyID, max_credits: Option<Decimal>, allowed_counterparties: Option<Vec<CompanyID>>, required_agreement: Option<AgreementID>, active: bool, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::TransferPolicyCreate)?;
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if let Some(max_credits) = max_credits.as_ref() {
+        if max_credits < &Decimal::zero() {
+            Err(Error::InvalidAmount(*max_credits))?;
+        }
+    }
+    let model = TransferPolicy::builder()
+        .id(id)
+        .company_id(company.id().clone())
+        .max_credits(max_credits)
+        .allowed_counterparties(allowed_counterparties)
+        .required_agreement(required_agreement)
+        .active(active)
+        .created(now.clone())
+        .updated(now.clone())
+        .build()
+        .map_err(|e| Error::BuilderFailed(e))?;
+    Ok(Modifications::new_single(Op::Create, model))
+}
+
+/// Update a transfer policy
+pub fn update(caller: &User, member: &Member, company: &Company, mut subject: TransferPolicy, max_credits: Option<Option<Decimal>>, allowed_counterparties: Option<Option<Vec<CompanyID>>>, required_agreement: Option<Option<AgreementID>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::TransferPolicyUpdate)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("transfer_policy".into()))?;
+    }
+    if let Some(max_credits) = max_credits {
+        if let Some(val) = max_credits.as_ref() {
+            if val < &Decimal::zero() {
+                Err(Error::InvalidAmount(*val))?;
+            }
+        }
+        subject.set_max_credits(max_credits);
+    }
+    if let Some(allowed_counterparties) = allowed_counterparties {
+        subject.set_allowed_counterparties(allowed_counterparties);
+    }
+    if let Some(required_agreement) = required_agreement {
+        subject.set_required_agreement(required_agreement);
+    }
+    if let Some(active) = active {
+        subject.set_active(active);
+    }
+    subject.set_updated(now.clone());
+    Ok(Modifications::new_single(Op::Update, subject))
+}
+
+/// Delete a transfer policy
+pub fn delete(caller: &User, member: &Member, company: &Company, mut subject: TransferPolicy, now: &DateTime<Utc>) -> Result<Modifications> {
+    caller.access_check(Permission::CompanyUpdate)?;
+    member.access_check(caller.id(), company.id(), CompanyPermission::TransferPolicyDelete)?;
+    if company.id() != subject.company_id() {
+        Err(Error::InsufficientPrivileges)?;
+    }
+    if !company.is_active() {
+        Err(Error::ObjectIsInactive("company".into()))?;
+    }
+    if subject.is_deleted() {
+        Err(Error::ObjectIsDeleted("transfer_policy".into()))?;
+    }
+    subject.set_deleted(Some(now.clone()));
+    Ok(Modifications::new_single(Op::Delete, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        util::{self, test::{self, *}},
+    };
+
+    #[test]
+    fn can_create() {
+        let now = util::time::now();
+        let id = TransferPolicyID::create();
+        let state = TestState::standard(vec![CompanyPermission::TransferPolicyCreate], &now);
+        let counterparties = vec![CompanyID::new("larry's chairs")];
+
+        let testfn = |state: &TestState<TransferPolicy, TransferPolicy>| {
+            create(state.user(), state.member(), state.company(), id.clone(), Some(num!(5000)), Some(counterparties.clone()), None, true, &now)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let policy = mods[0].clone().expect_op::<TransferPolicy>(Op::Create).unwrap();
+        assert_eq!(policy.id(), &id);
+        assert_eq!(policy.company_id(), state.company().id());
+        assert_eq!(policy.max_credits(), &Some(num!(5000)));
+        assert_eq!(policy.allowed_counterparties(), &Some(counterparties.clone()));
+        assert_eq!(policy.required_agreement(), &None);
+        assert_eq!(policy.active(), &true);
+        assert_eq!(policy.created(), &now);
+        assert_eq!(policy.updated(), &now);
+        assert_eq!(policy.deleted(), &None);
+
+        let res = create(state.user(), state.member(), state.company(), id.clone(), Some(num!(-1)), None, None, true, &now);
+        assert_eq!(res, Err(Error::InvalidAmount(num!(-1))));
+    }
+
+    #[test]
+    fn can_update() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::TransferPolicyCreate, CompanyPermission::TransferPolicyUpdate], &now);
+        let mods = create(state.user(), state.member(), state.company(), TransferPolicyID::create(), Some(num!(5000)), None, None, true, &now).unwrap().into_vec();
+        let policy = mods[0].clone().expect_op::<TransferPolicy>(Op::Create).unwrap();
+        state.model = Some(policy);
+
+        let now2 = util::time::now();
+        let agreement_id = AgreementID::new("master agreement");
+        let testfn = |state: &TestState<TransferPolicy, TransferPolicy>| {
+            update(state.user(), state.member(), state.company(), state.model().clone(), Some(None), None, Some(Some(agreement_id.clone())), Some(false), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let policy2 = mods[0].clone().expect_op::<TransferPolicy>(Op::Update).unwrap();
+        assert_eq!(policy2.id(), state.model().id());
+        assert_eq!(policy2.max_credits(), &None);
+        assert_eq!(policy2.allowed_counterparties(), &None);
+        assert_eq!(policy2.required_agreement(), &Some(agreement_id.clone()));
+        assert_eq!(policy2.active(), &false);
+        assert_eq!(policy2.created(), &now);
+        assert_eq!(policy2.updated(), &now2);
+
+        let mut state2 = state.clone();
+        state2.model_mut().set_company_id(CompanyID::create());
+        let res = testfn(&state2);
+        assert_eq!(res, Err(Error::InsufficientPrivileges));
+    }
+
+    #[test]
+    fn can_delete() {
+        let now = util::time::now();
+        let mut state = TestState::standard(vec![CompanyPermission::TransferPolicyCreate, CompanyPermission::TransferPolicyDelete], &now);
+        let mods = create(state.user(), state.member(), state.company(), TransferPolicyID::create(), Some(num!(5000)), None, None, true, &now).unwrap().into_vec();
+        let policy = mods[0].clone().expect_op::<TransferPolicy>(Op::Create).unwrap();
+        state.model = Some(policy);
+
+        let now2 = util::time::now();
+        let testfn = |state: &TestState<TransferPolicy, TransferPolicy>| {
+            delete(state.user(), state.member(), state.company(), state.model().clone(), &now2)
+        };
+        test::standard_transaction_tests(&state, &testfn);
+        test::double_deleted_tester(&state, "transfer_policy", &testfn);
+
+        let mods = testfn(&state).unwrap().into_vec();
+        assert_eq!(mods.len(), 1);
+        let policy2 = mods[0].clone().expect_op::<TransferPolicy>(Op::Delete).unwrap();
+        assert_eq!(policy2.id(), state.model().id());
+        assert_eq!(policy2.deleted(), &Some(now2.clone()));
+    }
+}
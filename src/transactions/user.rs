@@ -13,6 +13,7 @@ use crate::{
         Modifications,
         account::{Account, AccountID, Multisig, Ubi},
         lib::basis_model::Model,
+        region::RegionID,
         user::{User, UserID},
     },
 };
@@ -66,7 +67,7 @@ pub fn create_permissioned<T: Into<String>>(caller: &User, id: UserID, roles: Ve
 }
 
 /// Update a user object
-pub fn update(caller: &User, mut subject: User, email: Option<String>, name: Option<String>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
+pub fn update(caller: &User, mut subject: User, email: Option<String>, name: Option<String>, region_id: Option<Option<RegionID>>, active: Option<bool>, now: &DateTime<Utc>) -> Result<Modifications> {
     caller.access_check(Permission::UserAdminUpdate)
         .or_else(|_| {
             caller.access_check(Permission::UserUpdate)
@@ -84,6 +85,9 @@ pub fn update(caller: &User, mut subject: User, email: Option<String>, name: Opt
     if let Some(name) = name {
         subject.set_name(name);
     }
+    if let Some(region_id) = region_id {
+        subject.set_region_id(region_id);
+    }
     if let Some(active) = active {
         subject.set_active(active);
     }
@@ -196,7 +200,7 @@ mod tests {
 
         let now2 = util::time::now();
         let testfn_inner = |state: &TestState<User, User>, active: Option<bool>| {
-            update(state.user(), state.model().clone(), Some("obvious_day@camp.stupid".into()), None, active, &now2)
+            update(state.user(), state.model().clone(), Some("obvious_day@camp.stupid".into()), None, Some(Some(RegionID::new("cascadia"))), active, &now2)
         };
         let testfn = |state: &TestState<User, User>| {
             testfn_inner(state, None)
@@ -206,6 +210,7 @@ mod tests {
         let user2 = mods[0].clone().expect_op::<User>(Op::Update).unwrap();
         assert_eq!(user2.email(), "obvious_day@camp.stupid");
         assert_eq!(user2.name(), "leonard");
+        assert_eq!(user2.region_id(), &Some(RegionID::new("cascadia")));
         assert_eq!(user2.active(), &true);
         assert_eq!(user2.updated(), &now2);
 
@@ -0,0 +1,157 @@
+//! Hashing utilities, enabled via the `with_serde` feature.
+//!
+//! Distributed deployments of the core need a cheap way to check that two
+//! nodes agree on the state of the system. Comparing models directly is
+//! fragile: costs are stored in `HashMap`s (which have no stable iteration
+//! order) and decimals can represent the same value with different scales
+//! (`1.0` vs `1.00`). The [state_digest] function gets around this by hashing
+//! a canonical serialization of each model, in which every decimal is written
+//! out exactly (not as a float) in its normalized form.
+//!
+//! [state_digest]: fn.state_digest.html
+
+use crate::{
+    error::{Error, Result},
+    models::Model,
+};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// Write a JSON value to a buffer in canonical form: object keys are sorted
+/// and numbers are normalized so that equal values always produce the same
+/// bytes.
+fn write_canonical(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => {
+            buf.extend_from_slice(value.to_string().as_bytes());
+        }
+        Value::Number(num) => {
+            // decimals serialize with their full precision, so we can read
+            // them back exactly and normalize their scale (which also makes
+            // -0 and 0 hash the same).
+            let text = num.to_string();
+            match Decimal::from_str(&text).or_else(|_| Decimal::from_scientific(&text)) {
+                Ok(dec) => buf.extend_from_slice(dec.normalize().to_string().as_bytes()),
+                Err(_) => buf.extend_from_slice(text.as_bytes()),
+            }
+        }
+        Value::Array(vals) => {
+            buf.push(b'[');
+            for (i, val) in vals.iter().enumerate() {
+                if i > 0 {
+                    buf.push(b',');
+                }
+                write_canonical(buf, val);
+            }
+            buf.push(b']');
+        }
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            buf.push(b'{');
+            for (i, (key, val)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    buf.push(b',');
+                }
+                write_canonical(buf, &Value::String(key.clone()));
+                buf.push(b':');
+                write_canonical(buf, val);
+            }
+            buf.push(b'}');
+        }
+    }
+}
+
+/// Serialize a single model in canonical form.
+fn canonical_bytes(model: &Model) -> Result<Vec<u8>> {
+    let value = serde_json::to_value(model)
+        .map_err(|e| Error::SerializeFailed(e.to_string()))?;
+    let mut buf = Vec::new();
+    write_canonical(&mut buf, &value);
+    Ok(buf)
+}
+
+/// Compute a SHA-256 digest over a set of models, suitable for comparing state
+/// roots between nodes.
+///
+/// The digest doesn't depend on the order the models are given in (they are
+/// sorted by type and ID before hashing), on the iteration order of any maps
+/// within the models, or on the scale of any decimal values.
+pub fn state_digest<'a>(models: impl Iterator<Item = &'a Model>) -> Result<[u8; 32]> {
+    let mut entries = models
+        .map(|model| {
+            let (ty, id) = model.type_and_id();
+            Ok(((ty, id.to_string()), canonical_bytes(model)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut hasher = Sha256::new();
+    for (_, bytes) in entries {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    }
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&hasher.finalize());
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        costs::Costs,
+        models::{
+            company::CompanyID,
+            process::ProcessID,
+            user::UserID,
+        },
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn canonical_json() {
+        let value: Value = serde_json::from_str(r#"{"b":[1,-0.0,"x",2.50],"a":{"d":null,"c":true}}"#).unwrap();
+        let mut buf = Vec::new();
+        write_canonical(&mut buf, &value);
+        assert_eq!(String::from_utf8(buf).unwrap(), r#"{"a":{"c":true,"d":null},"b":[1,0,"x",2.5]}"#);
+    }
+
+    #[test]
+    fn digests_state() {
+        let now = util::time::now();
+        let user: Model = make_user(&UserID::new("jerry"), None, &now).into();
+        let company: Model = make_company(&CompanyID::new("jerry's widgets"), "jerry's widgets", &now).into();
+        let mut costs1 = Costs::new();
+        costs1.track_labor("machinist", num!(12.0));
+        costs1.track_labor("janitor", num!(4));
+        costs1.track_resource("steel", num!(3.50), num!(1.1));
+        let mut costs2 = Costs::new();
+        costs2.track_resource("steel", num!(3.5), num!(1.10));
+        costs2.track_labor("janitor", num!(4.000));
+        costs2.track_labor("machinist", num!(12));
+        let process1: Model = make_process(&ProcessID::new("widgets"), &CompanyID::new("jerry's widgets"), "make widgets", &costs1, &now).into();
+        let process2: Model = make_process(&ProcessID::new("widgets"), &CompanyID::new("jerry's widgets"), "make widgets", &costs2, &now).into();
+
+        let digest1 = state_digest(vec![user.clone(), company.clone(), process1.clone()].iter()).unwrap();
+        // order of models and scale of decimals don't matter
+        let digest2 = state_digest(vec![process2.clone(), user.clone(), company.clone()].iter()).unwrap();
+        assert_eq!(digest1, digest2);
+        assert_eq!(digest1, state_digest(vec![user.clone(), company.clone(), process1.clone()].iter()).unwrap());
+
+        // actual changes do
+        let mut costs3 = costs1.clone();
+        costs3.track_labor("janitor", num!(0.0001));
+        let process3: Model = make_process(&ProcessID::new("widgets"), &CompanyID::new("jerry's widgets"), "make widgets", &costs3, &now).into();
+        assert_ne!(digest1, state_digest(vec![user.clone(), company.clone(), process3].iter()).unwrap());
+        assert_ne!(digest1, state_digest(vec![user.clone(), company.clone()].iter()).unwrap());
+        assert_ne!(state_digest(vec![].iter()).unwrap(), state_digest(vec![user.clone()].iter()).unwrap());
+
+        // decimals are hashed exactly, even past what a float can hold
+        let mut costs4 = costs1.clone();
+        costs4.track_labor("janitor", num!(0.0000000000000001));
+        let process4: Model = make_process(&ProcessID::new("widgets"), &CompanyID::new("jerry's widgets"), "make widgets", &costs4, &now).into();
+        assert_ne!(digest1, state_digest(vec![user.clone(), company.clone(), process4].iter()).unwrap());
+    }
+}
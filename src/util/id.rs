@@ -0,0 +1,47 @@
+//! Helpers for generating model ids.
+
+use sha2::{Digest, Sha256};
+
+/// Deterministically derive a UUID-formatted id from a namespace and payload.
+///
+/// The id is the first 128 bits of `SHA256(namespace || 0x00 || payload)`,
+/// with the version and variant bits set as a UUIDv8 (custom, name-based) so
+/// derived ids look like the random v4 ids used elsewhere. The namespace keeps
+/// the same payload from producing the same id in two different contexts.
+pub(crate) fn derive(namespace: &str, payload: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(namespace.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(payload);
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[0..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        company::CompanyID,
+        user::UserID,
+    };
+
+    #[test]
+    fn derives() {
+        let id1 = derive("user", b"jerry@widgets.biz");
+        let id2 = derive("user", b"jerry@widgets.biz");
+        let id3 = derive("company", b"jerry@widgets.biz");
+        assert_eq!(id1, id2);
+        assert_ne!(id1, id3);
+        assert_eq!(id1.len(), 36);
+        assert_eq!(&id1[14..15], "8");
+        assert!(["8", "9", "a", "b"].contains(&&id1[19..20]));
+
+        assert_eq!(UserID::derive("user", "jerry@widgets.biz"), UserID::new(id1));
+        assert_eq!(CompanyID::derive("company", b"jerry@widgets.biz".to_vec()), CompanyID::new(id3));
+    }
+}
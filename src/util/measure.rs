@@ -1,22 +1,139 @@
 //! Some helpful utilities for dealing with om2::Measure objects in the context
 //! of event processing.
+//!
+//! Measures in different but compatible units (kilograms and grams, hours and
+//! minutes) are converted on the fly, so an event can record a quantity in a
+//! different unit than the resource it acts on. Measures with incompatible
+//! units (kilograms and hours) are still an error.
 
 use crate::{
     error::{Error, Result},
 };
-use om2::{Measure, NumericUnion};
+use om2::{Measure, NumericUnion, Unit};
 use rust_decimal::prelude::*;
 
+/// The physical dimensions we know how to convert between.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dimension {
+    Area,
+    Energy,
+    Length,
+    Mass,
+    Power,
+    Time,
+    Volume,
+}
+
+/// Get a unit's dimension, along with how many of the dimension's base unit
+/// (square metres, joules, metres, grams, watts, seconds, litres) one of the
+/// unit is worth. Returns `None` for units we can't convert.
+fn base_factor(unit: &Unit) -> Option<(Dimension, Decimal)> {
+    let (dimension, factor) = match unit {
+        Unit::SquareMetre => (Dimension::Area, num!(1)),
+        Unit::Hectare => (Dimension::Area, num!(10000)),
+
+        Unit::Joule => (Dimension::Energy, num!(1)),
+        Unit::Kilojoule => (Dimension::Energy, num!(1000)),
+        Unit::Megajoule => (Dimension::Energy, num!(1000000)),
+        Unit::WattHour => (Dimension::Energy, num!(3600)),
+        Unit::KilowattHour => (Dimension::Energy, num!(3600000)),
+
+        Unit::Millimetre => (Dimension::Length, num!(0.001)),
+        Unit::Centimetre => (Dimension::Length, num!(0.01)),
+        Unit::Metre => (Dimension::Length, num!(1)),
+        Unit::Kilometre => (Dimension::Length, num!(1000)),
+        Unit::InchInternational => (Dimension::Length, num!(0.0254)),
+        Unit::FootInternational => (Dimension::Length, num!(0.3048)),
+        Unit::MileStatute => (Dimension::Length, num!(1609.344)),
+
+        Unit::Milligram => (Dimension::Mass, num!(0.001)),
+        Unit::Gram => (Dimension::Mass, num!(1)),
+        Unit::Kilogram => (Dimension::Mass, num!(1000)),
+        Unit::Tonne => (Dimension::Mass, num!(1000000)),
+        Unit::OunceAvoirdupois => (Dimension::Mass, num!(28.349523125)),
+        Unit::PoundAvoirdupois => (Dimension::Mass, num!(453.59237)),
+
+        Unit::Watt => (Dimension::Power, num!(1)),
+        Unit::Kilowatt => (Dimension::Power, num!(1000)),
+        Unit::Megawatt => (Dimension::Power, num!(1000000)),
+
+        Unit::MillisecondTime => (Dimension::Time, num!(0.001)),
+        Unit::SecondTime => (Dimension::Time, num!(1)),
+        Unit::MinuteTime => (Dimension::Time, num!(60)),
+        Unit::Hour => (Dimension::Time, num!(3600)),
+        Unit::Day => (Dimension::Time, num!(86400)),
+        Unit::Week => (Dimension::Time, num!(604800)),
+
+        Unit::Millilitre => (Dimension::Volume, num!(0.001)),
+        Unit::Centilitre => (Dimension::Volume, num!(0.01)),
+        Unit::Decilitre => (Dimension::Volume, num!(0.1)),
+        Unit::Litre => (Dimension::Volume, num!(1)),
+        Unit::Hectolitre => (Dimension::Volume, num!(100)),
+        Unit::CubicMetre => (Dimension::Volume, num!(1000)),
+        Unit::GallonUs => (Dimension::Volume, num!(3.785411784)),
+        Unit::GallonImperial => (Dimension::Volume, num!(4.54609)),
+        _ => return None,
+    };
+    Some((dimension, factor))
+}
+
+/// Convert a Measure into another (compatible) unit.
+///
+/// If the Measure is already in the given unit it's returned as-is, otherwise
+/// the converted Measure holds a Decimal value.
+pub fn convert(measure: &Measure, to: &Unit) -> Result<Measure> {
+    if measure.has_unit() == to {
+        return Ok(measure.clone());
+    }
+    match (base_factor(measure.has_unit()), base_factor(to)) {
+        (Some((dim_from, factor_from)), Some((dim_to, factor_to))) if dim_from == dim_to => {
+            let val = (to_decimal(measure)? * factor_from / factor_to).normalize();
+            Ok(Measure::new(val, to.clone()))
+        }
+        _ => Err(Error::MeasureUnitsMismatched),
+    }
+}
+
+/// Whether or not measures in one unit can be converted into another.
+pub fn is_convertible(from: &Unit, to: &Unit) -> bool {
+    if from == to {
+        return true;
+    }
+    match (base_factor(from), base_factor(to)) {
+        (Some((dim_from, _)), Some((dim_to, _))) => dim_from == dim_to,
+        _ => false,
+    }
+}
+
+/// Convert `by` into the unit of `measure` so the two can be added or
+/// subtracted. If `measure` holds an integer and the converted value has a
+/// fractional part, `measure` is switched over to holding a Decimal so no
+/// precision is lost.
+fn align(measure: &mut Measure, by: &Measure) -> Result<Measure> {
+    let converted = convert(by, measure.has_unit())?;
+    if let (NumericUnion::Integer(_), NumericUnion::Decimal(val)) = (measure.has_numerical_value(), converted.has_numerical_value()) {
+        if !val.fract().is_zero() {
+            let current = to_decimal(measure)?;
+            measure.set_has_numerical_value(NumericUnion::Decimal(current));
+        }
+    }
+    Ok(converted)
+}
+
 /// Decrement a Measure by some other Measure.
 ///
 /// This will fail if the Measure being decremented falls below zero or if the
-/// two Measures have units that don't match.
+/// two Measures have units that can't be converted into each other.
 ///
 /// Returns true if the first Measure was modified.
 pub fn dec_measure(measure: &mut Measure, dec_by: &Measure) -> Result<bool> {
-    if measure.has_unit() != dec_by.has_unit() {
-        Err(Error::MeasureUnitsMismatched)?;
-    }
+    dec_measure_with(measure, dec_by, false)
+}
+
+/// Decrement a Measure by some other Measure, optionally letting the result
+/// fall below zero (see `Resource::allow_negative`).
+pub fn dec_measure_with(measure: &mut Measure, dec_by: &Measure, allow_negative: bool) -> Result<bool> {
+    let dec_by = align(measure, dec_by)?;
     let from_quantity = measure.has_numerical_value().clone();
     let dec_quantity = dec_by.has_numerical_value().clone();
     if dec_quantity.is_zero() {
@@ -27,7 +144,7 @@ pub fn dec_measure(measure: &mut Measure, dec_by: &Measure) -> Result<bool> {
     }
     let remaining = from_quantity.clone().sub(dec_quantity.clone())
         .map_err(|e| Error::NumericUnionOpError(e))?;
-    if remaining.is_negative() {
+    if remaining.is_negative() && !allow_negative {
         Err(Error::NegativeMeasurement)?;
     }
     measure.set_has_numerical_value(remaining);
@@ -37,13 +154,17 @@ pub fn dec_measure(measure: &mut Measure, dec_by: &Measure) -> Result<bool> {
 /// Increment a Measure by some other Measure.
 ///
 /// This will fail if the Measure being decremented falls below zero or if the
-/// two Measures have units that don't match.
+/// two Measures have units that can't be converted into each other.
 ///
 /// Returns true if the first Measure was modified.
 pub fn inc_measure(measure: &mut Measure, inc_by: &Measure) -> Result<bool> {
-    if measure.has_unit() != inc_by.has_unit() {
-        Err(Error::MeasureUnitsMismatched)?;
-    }
+    inc_measure_with(measure, inc_by, false)
+}
+
+/// Increment a Measure by some other Measure, optionally allowing a Measure
+/// that is below zero to stay there (see `Resource::allow_negative`).
+pub fn inc_measure_with(measure: &mut Measure, inc_by: &Measure, allow_negative: bool) -> Result<bool> {
+    let inc_by = align(measure, inc_by)?;
     let from_quantity = measure.has_numerical_value().clone();
     let inc_quantity = inc_by.has_numerical_value().clone();
     if inc_quantity.is_zero() {
@@ -54,13 +175,33 @@ pub fn inc_measure(measure: &mut Measure, inc_by: &Measure) -> Result<bool> {
     }
     let added = from_quantity.clone().add(inc_quantity.clone())
         .map_err(|e| Error::NumericUnionOpError(e))?;
-    if added.is_negative() {
+    if added.is_negative() && !allow_negative {
         Err(Error::NegativeMeasurement)?;
     }
     measure.set_has_numerical_value(added);
     Ok(true)
 }
 
+/// Grab a Measure's numerical value as a Decimal.
+pub fn to_decimal(measure: &Measure) -> Result<Decimal> {
+    let num = NumericUnion::Decimal(Decimal::zero()).add(measure.has_numerical_value().clone())
+        .map_err(|e| Error::NumericUnionOpError(e))?;
+    match num {
+        NumericUnion::Decimal(val) => Ok(val),
+        _ => Err(Error::NumericUnionOpError(format!("error converting to Decimal: {:?}", num))),
+    }
+}
+
+/// Grab the length of a time Measure in (rounded) seconds. The length must be a
+/// positive amount of time.
+pub fn to_seconds(measure: &Measure) -> Result<i64> {
+    let seconds = to_decimal(&convert(measure, &Unit::SecondTime)?)?;
+    match seconds.round().to_i64() {
+        Some(seconds) if seconds > 0 => Ok(seconds),
+        _ => Err(Error::InvalidAmount(seconds)),
+    }
+}
+
 /// Either use the given `measure` if it exists, or create a measure of 0 and
 /// return it using the same units/numeric types as `default`.
 pub fn unwrap_or_zero(measure: &Option<Measure>, default: &Measure) -> Measure {
@@ -89,3 +230,62 @@ pub fn set_zero(measure: &mut Measure) {
     measure.set_has_numerical_value(num);
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts() {
+        let kg = Measure::new(num!(2.5), Unit::Kilogram);
+        assert_eq!(convert(&kg, &Unit::Gram).unwrap(), Measure::new(num!(2500), Unit::Gram));
+        assert_eq!(convert(&kg, &Unit::Kilogram).unwrap(), kg);
+        assert_eq!(convert(&Measure::new(90, Unit::MinuteTime), &Unit::Hour).unwrap(), Measure::new(num!(1.5), Unit::Hour));
+        assert_eq!(convert(&Measure::new(num!(1), Unit::CubicMetre), &Unit::Litre).unwrap(), Measure::new(num!(1000), Unit::Litre));
+        assert_eq!(convert(&kg, &Unit::Hour), Err(Error::MeasureUnitsMismatched));
+        assert_eq!(convert(&Measure::new(1, Unit::One), &Unit::Kilogram), Err(Error::MeasureUnitsMismatched));
+    }
+
+    #[test]
+    fn seconds() {
+        assert_eq!(to_seconds(&Measure::new(num!(1), Unit::Week)).unwrap(), 60 * 60 * 24 * 7);
+        assert_eq!(to_seconds(&Measure::new(num!(1.5), Unit::MinuteTime)).unwrap(), 90);
+        assert_eq!(to_seconds(&Measure::new(num!(0), Unit::Day)), Err(Error::InvalidAmount(num!(0))));
+        assert_eq!(to_seconds(&Measure::new(num!(-2), Unit::SecondTime)), Err(Error::InvalidAmount(num!(-2))));
+        assert_eq!(to_seconds(&Measure::new(num!(1), Unit::Kilogram)), Err(Error::MeasureUnitsMismatched));
+    }
+
+    #[test]
+    fn incdec_converts() {
+        let mut measure = Measure::new(num!(10), Unit::Kilogram);
+        dec_measure(&mut measure, &Measure::new(num!(500), Unit::Gram)).unwrap();
+        assert_eq!(measure, Measure::new(num!(9.5), Unit::Kilogram));
+        inc_measure(&mut measure, &Measure::new(num!(0.5), Unit::Tonne)).unwrap();
+        assert_eq!(measure, Measure::new(num!(509.5), Unit::Kilogram));
+        assert_eq!(dec_measure(&mut measure, &Measure::new(num!(1), Unit::Tonne)), Err(Error::NegativeMeasurement));
+        assert_eq!(inc_measure(&mut measure, &Measure::new(num!(1), Unit::Hour)), Err(Error::MeasureUnitsMismatched));
+
+        // integers stay integers unless a conversion leaves a fraction
+        let mut measure = Measure::new(2, Unit::Hour);
+        inc_measure(&mut measure, &Measure::new(60, Unit::MinuteTime)).unwrap();
+        assert_eq!(measure, Measure::new(3, Unit::Hour));
+        inc_measure(&mut measure, &Measure::new(30, Unit::MinuteTime)).unwrap();
+        assert_eq!(measure, Measure::new(num!(3.5), Unit::Hour));
+    }
+
+    #[test]
+    fn incdec_negative() {
+        let mut measure = Measure::new(3, Unit::One);
+        assert_eq!(dec_measure_with(&mut measure, &Measure::new(5, Unit::One), false), Err(Error::NegativeMeasurement));
+        assert_eq!(measure, Measure::new(3, Unit::One));
+        dec_measure_with(&mut measure, &Measure::new(5, Unit::One), true).unwrap();
+        assert_eq!(measure, Measure::new(-2, Unit::One));
+        assert_eq!(inc_measure_with(&mut measure, &Measure::new(1, Unit::One), false), Err(Error::NegativeMeasurement));
+        inc_measure_with(&mut measure, &Measure::new(1, Unit::One), true).unwrap();
+        assert_eq!(measure, Measure::new(-1, Unit::One));
+        inc_measure(&mut measure, &Measure::new(1, Unit::One)).unwrap();
+        assert_eq!(measure, Measure::new(0, Unit::One));
+        // negative adjustments are never allowed
+        assert_eq!(dec_measure_with(&mut measure, &Measure::new(-1, Unit::One), true), Err(Error::NegativeMeasurement));
+    }
+}
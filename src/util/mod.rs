@@ -1,9 +1,13 @@
 //! A set of utility structs and functions used when operating the core.
 
-pub(crate) mod measure;
 #[macro_use]
 pub mod number;
+pub(crate) mod id;
+pub(crate) mod measure;
 pub(crate) mod time;
+#[cfg(feature = "with_serde")]
+pub mod hash;
+pub mod signature;
 
 #[cfg(test)]
 #[macro_use]
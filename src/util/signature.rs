@@ -0,0 +1,184 @@
+//! Signature envelopes for authenticating transaction submissions.
+//!
+//! The core trusts whatever `caller` it's handed. That's fine when the core is
+//! embedded behind an API that already authenticates its users, but
+//! distributed deployments need a way to prove that a user actually asked for
+//! a transaction to run. A [SignedTransaction] wraps a transaction invocation
+//! (whatever payload describes it) along with the ID of the user submitting it
+//! and their signature over the two.
+//!
+//! The envelope itself is always available, but creating and verifying
+//! signatures (ed25519) requires the `signatures` feature. Verification checks
+//! the signature against a [KeyRegistry] of the users' public keys, and
+//! [verify_caller] additionally makes sure the user signing is the `caller`
+//! being passed into the transaction.
+//!
+//! [SignedTransaction]: struct.SignedTransaction.html
+//! [KeyRegistry]: type.KeyRegistry.html
+//! [verify_caller]: struct.SignedTransaction.html#method.verify_caller
+
+#[cfg(feature = "signatures")]
+use crate::{
+    error::{Error, Result},
+    models::user::User,
+};
+use crate::models::user::UserID;
+#[cfg(feature = "signatures")]
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer};
+use getset::Getters;
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+#[cfg(feature = "signatures")]
+use std::convert::TryFrom;
+
+/// Maps users to their (32 byte, ed25519) public keys.
+pub type KeyRegistry = HashMap<UserID, [u8; 32]>;
+
+/// Anything that can be signed. The bytes returned must be the same every time
+/// for the same payload, or signatures won't verify.
+pub trait Signable {
+    /// Get the bytes to sign for this payload
+    fn signing_bytes(&self) -> Vec<u8>;
+}
+
+impl Signable for Vec<u8> {
+    fn signing_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl Signable for String {
+    fn signing_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+/// A transaction payload, signed by the user submitting it.
+#[derive(Clone, Debug, PartialEq, Getters)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+#[getset(get = "pub")]
+pub struct SignedTransaction<T> {
+    /// The user who signed the payload
+    user_id: UserID,
+    /// The transaction invocation being signed
+    payload: T,
+    /// The signature over the user ID and payload (see `message()`)
+    signature: Vec<u8>,
+}
+
+impl<T> SignedTransaction<T> {
+    /// Wrap an already-signed payload
+    pub fn new(user_id: UserID, payload: T, signature: Vec<u8>) -> Self {
+        Self { user_id, payload, signature }
+    }
+
+    /// Unwrap the payload, discarding the signature
+    pub fn into_payload(self) -> T {
+        self.payload
+    }
+}
+
+impl<T: Signable> SignedTransaction<T> {
+    /// The message that gets signed: the signing user's ID, a null byte, and
+    /// the payload's bytes. Including the user ID means a signature can't be
+    /// lifted from one user's envelope and passed off as another's.
+    pub fn message(&self) -> Vec<u8> {
+        signing_message(&self.user_id, &self.payload)
+    }
+
+    /// Sign a payload as the given user.
+    #[cfg(feature = "signatures")]
+    pub fn sign(user_id: UserID, payload: T, keypair: &Keypair) -> Self {
+        let signature = keypair.sign(&signing_message(&user_id, &payload));
+        Self::new(user_id, payload, signature.to_bytes().to_vec())
+    }
+
+    /// Verify the envelope's signature against the signing user's public key
+    /// in the registry, returning the payload if it checks out.
+    #[cfg(feature = "signatures")]
+    pub fn verify(&self, registry: &KeyRegistry) -> Result<&T> {
+        let key_bytes = registry.get(self.user_id())
+            .ok_or_else(|| Error::SignatureInvalid(format!("no public key for user {}", self.user_id().as_str())))?;
+        let public = PublicKey::from_bytes(key_bytes)
+            .map_err(|_| Error::SignatureInvalid(format!("bad public key for user {}", self.user_id().as_str())))?;
+        let signature = Signature::try_from(self.signature().as_slice())
+            .map_err(|_| Error::SignatureInvalid("malformed signature".into()))?;
+        public.verify_strict(&self.message(), &signature)
+            .map_err(|_| Error::SignatureInvalid("signature does not match".into()))?;
+        Ok(self.payload())
+    }
+
+    /// Verify the envelope (see `verify()`) and make sure it was signed by the
+    /// given caller. Transactions that want to authenticate their caller can
+    /// run this before doing anything else.
+    #[cfg(feature = "signatures")]
+    pub fn verify_caller(&self, caller: &User, registry: &KeyRegistry) -> Result<&T> {
+        if caller.id() != self.user_id() {
+            Err(Error::InsufficientPrivileges)?;
+        }
+        self.verify(registry)
+    }
+}
+
+/// Build the message a user signs for a payload.
+fn signing_message<T: Signable>(user_id: &UserID, payload: &T) -> Vec<u8> {
+    let mut message = user_id.as_str().as_bytes().to_vec();
+    message.push(0);
+    message.extend(payload.signing_bytes());
+    message
+}
+
+#[cfg(all(test, feature = "signatures"))]
+mod tests {
+    use super::*;
+    use crate::{
+        access::Role,
+        util::{self, test::*},
+    };
+    use ed25519_dalek::SecretKey;
+
+    fn make_keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    #[test]
+    fn signs_and_verifies() {
+        let now = util::time::now();
+        let user = make_user(&UserID::create(), Some(vec![Role::User]), &now);
+        let user2 = make_user(&UserID::create(), Some(vec![Role::User]), &now);
+        let keypair = make_keypair(7);
+        let keypair2 = make_keypair(8);
+        let mut registry = KeyRegistry::new();
+        registry.insert(user.id().clone(), keypair.public.to_bytes());
+        registry.insert(user2.id().clone(), keypair2.public.to_bytes());
+
+        let payload = String::from("order::purchase widgets x2");
+        let signed = SignedTransaction::sign(user.id().clone(), payload.clone(), &keypair);
+        assert_eq!(signed.verify(&registry), Ok(&payload));
+        assert_eq!(signed.verify_caller(&user, &registry), Ok(&payload));
+        assert_eq!(signed.verify_caller(&user2, &registry), Err(Error::InsufficientPrivileges));
+        assert_eq!(signed.clone().into_payload(), payload);
+
+        // tampering with the payload breaks the signature
+        let tampered = SignedTransaction::new(user.id().clone(), String::from("order::purchase widgets x200"), signed.signature().clone());
+        assert_eq!(tampered.verify(&registry), Err(Error::SignatureInvalid("signature does not match".into())));
+
+        // so does claiming someone else signed it
+        let stolen = SignedTransaction::new(user2.id().clone(), payload.clone(), signed.signature().clone());
+        assert_eq!(stolen.verify_caller(&user2, &registry), Err(Error::SignatureInvalid("signature does not match".into())));
+
+        // signed with the wrong key
+        let wrong_key = SignedTransaction::sign(user.id().clone(), payload.clone(), &keypair2);
+        assert_eq!(wrong_key.verify(&registry), Err(Error::SignatureInvalid("signature does not match".into())));
+
+        let malformed = SignedTransaction::new(user.id().clone(), payload.clone(), vec![1, 2, 3]);
+        assert_eq!(malformed.verify(&registry), Err(Error::SignatureInvalid("malformed signature".into())));
+
+        let mut registry2 = registry.clone();
+        registry2.remove(user.id());
+        assert_eq!(signed.verify(&registry2), Err(Error::SignatureInvalid(format!("no public key for user {}", user.id().as_str()))));
+    }
+}
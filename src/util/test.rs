@@ -9,12 +9,14 @@ use crate::{
         account::{Account, AccountID, Multisig},
         agreement::{Agreement, AgreementID},
         company::{Company, CompanyID, Permission as CompanyPermission},
+        company_treasury::{CompanyTreasury, CompanyTreasuryID},
+        currency::{Currency, CurrencyID},
         lib::{
             agent::AgentID,
             basis_model::Model,
         },
         member::*,
-        occupation::OccupationID,
+        occupation::{Occupation, OccupationID, WageRate},
         process::{Process, ProcessID},
         process_spec::{ProcessSpec, ProcessSpecID},
         resource::{Resource, ResourceID},
@@ -247,6 +249,30 @@ pub fn make_company<T: Into<String>>(id: &CompanyID, name: T, now: &DateTime<Utc
         .active(true)
         .max_costs(num!(1000))
         .total_costs(Costs::new())
+        .waste_costs(Costs::new())
+        .created(now.clone())
+        .updated(now.clone())
+        .build().unwrap()
+}
+
+pub fn make_company_treasury(company_id: &CompanyID, issued: Decimal, now: &DateTime<Utc>) -> CompanyTreasury {
+    CompanyTreasury::builder()
+        .id(CompanyTreasuryID::create())
+        .company_id(company_id.clone())
+        .issued(issued)
+        .destroyed(num!(0))
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build().unwrap()
+}
+
+pub fn make_currency<T: Into<String>>(id: &CurrencyID, name: T, decimal_places: u8, now: &DateTime<Utc>) -> Currency {
+    Currency::builder()
+        .id(id.clone())
+        .name(name)
+        .decimal_places(decimal_places)
+        .active(true)
         .created(now.clone())
         .updated(now.clone())
         .build().unwrap()
@@ -270,12 +296,28 @@ pub fn make_member_worker(member_id: &MemberID, user_id: &UserID, company_id: &C
         .build().unwrap()
 }
 
+pub fn make_occupation<T: Into<String>>(id: &OccupationID, label: T, wage_rate: Option<WageRate>, now: &DateTime<Utc>) -> Occupation {
+    Occupation::builder()
+        .id(id.clone())
+        .inner(
+            vf::AgentRelationshipRole::builder()
+                .role_label(label)
+                .build().unwrap()
+        )
+        .wage_rate(wage_rate)
+        .active(true)
+        .created(now.clone())
+        .updated(now.clone())
+        .build().unwrap()
+}
+
 pub fn make_process<T: Into<String>>(id: &ProcessID, company_id: &CompanyID, name: T, costs: &Costs, now: &DateTime<Utc>) -> Process {
     Process::builder()
         .id(id.clone())
         .inner(vf::Process::builder().name(name).build().unwrap())
         .company_id(company_id.clone())
         .costs(costs.clone())
+        .labor_spent(Costs::new())
         .active(true)
         .created(now.clone())
         .updated(now.clone())
@@ -324,6 +366,7 @@ pub fn make_resource_spec<T: Into<String>>(id: &ResourceSpecID, company_id: &Com
                 .build().unwrap()
         )
         .company_id(company_id.clone())
+        .version(1u32)
         .created(now.clone())
         .updated(now.clone())
         .build().unwrap()
@@ -1,4 +1,7 @@
-use chrono::{DateTime, Utc};
+#[cfg(test)]
+use chrono::{DateTime, TimeZone, Utc};
+#[cfg(test)]
+use std::time::SystemTime;
 
 /// Determine if a year (eg `2017`) is a leap year.
 #[allow(dead_code)]
@@ -6,9 +9,15 @@ pub(crate) fn is_leap_year(year: i64) -> bool {
     ((year % 4) == 0 && (year % 100) != 0) || (year % 400) == 0
 }
 
-/// Get the current time. Mainly for testing.
-#[allow(dead_code)]
+/// Get the current time, for testing.
+///
+/// This only exists in tests: `SystemTime::now()` panics on
+/// `wasm32-unknown-unknown`, so outside of tests the time is always passed in
+/// by the caller. We go through `SystemTime` instead of `Utc::now()` so tests
+/// also run without chrono's `clock` feature.
+#[cfg(test)]
 pub(crate) fn now() -> DateTime<Utc> {
-    Utc::now()
+    let since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    Utc.timestamp_opt(since_epoch.as_secs() as i64, since_epoch.subsec_nanos()).unwrap()
 }
 
@@ -0,0 +1,277 @@
+//! Views are flat, read-only projections of models, meant for API layers
+//! (GraphQL, REST, etc).
+//!
+//! Models like [Resource] and [Process] wrap a ValueFlows object (their
+//! `inner()`) and add Basis-specific fields around it. That composition is
+//! great for the core, but it means anything serving the models over an API
+//! has to flatten the two levels into one, and everyone ends up writing the
+//! same flattener by hand. The views here do it once: every VF field and every
+//! Basis field sits at the top level, and a view can be created from its model
+//! via `From`:
+//!
+//! ```rust
+//! use basis_core::{
+//!     models::resource::Resource,
+//!     views::ResourceView,
+//! };
+//!
+//! fn to_api(resource: &Resource) -> ResourceView {
+//!     ResourceView::from(resource)
+//! }
+//! ```
+//!
+//! Views are one-way: there's no converting a view back into a model, since
+//! models can only be changed through transactions.
+//!
+//! [Resource]: ../models/resource/struct.Resource.html
+//! [Process]: ../models/process/struct.Process.html
+
+use chrono::{DateTime, Utc};
+use crate::{
+    costs::Costs,
+    models::{
+        commitment::CommitmentID,
+        company::CompanyID,
+        lib::agent::AgentID,
+        process::{Process, ProcessID},
+        process_spec::ProcessSpecID,
+        region::RegionID,
+        resource::{Amortization, LocationChange, Resource, ResourceID},
+        resource_spec::ResourceSpecID,
+    },
+};
+use om2::{Measure, Unit};
+#[cfg(feature = "with_serde")]
+use serde::{Serialize, Deserialize};
+use url::Url;
+use vf_rs::{dfc, geo::SpatialThing};
+
+/// A flattened view of a [Resource](../models/resource/struct.Resource.html).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub struct ResourceView {
+    /// The resource's ID
+    pub id: ResourceID,
+    /// The resource's name
+    pub name: Option<String>,
+    /// The spec this resource conforms to
+    pub conforms_to: ResourceSpecID,
+    /// Classifications of the resource
+    pub classified_as: Vec<Url>,
+    /// The resource's tracking identifier (serial number, etc)
+    pub tracking_identifier: Option<String>,
+    /// The resource's image
+    pub image: Option<Url>,
+    /// A free-text note about the resource
+    pub note: Option<String>,
+    /// The resource's state (for instance, "pass" or "fail")
+    pub state: Option<String>,
+    /// The stage the resource is in
+    pub stage: Option<ProcessID>,
+    /// The resource this resource is contained in
+    pub contained_in: Option<ResourceID>,
+    /// Where the resource currently is
+    pub current_location: Option<SpatialThing>,
+    /// Where events have moved the resource, oldest first
+    pub location_history: Vec<LocationChange>,
+    /// The quantity of the resource for accounting purposes
+    pub accounting_quantity: Option<Measure>,
+    /// The quantity of the resource on hand
+    pub onhand_quantity: Option<Measure>,
+    /// The unit used when this resource is used as an effort
+    pub unit_of_effort: Option<Unit>,
+    /// The agent that owns the resource
+    pub primary_accountable: Option<AgentID>,
+    /// The agent that has custody of the resource
+    pub in_custody_of: AgentID,
+    /// The costs imbued in the resource
+    pub costs: Costs,
+    /// The resource's amortization schedule
+    pub amortization: Option<Amortization>,
+    /// The region the resource is located in
+    pub region_id: Option<RegionID>,
+    /// The lot the resource belongs to
+    pub lot: Option<dfc::ProductBatch>,
+    /// Whether the resource's quantities can go negative
+    pub allow_negative: bool,
+    /// The spec whose default costs the resource was bootstrapped with
+    pub estimated_from: Option<ResourceSpecID>,
+    /// Whether the resource is active
+    pub active: bool,
+    /// When the resource was created
+    pub created: DateTime<Utc>,
+    /// When the resource was last updated
+    pub updated: DateTime<Utc>,
+    /// When the resource was deleted
+    pub deleted: Option<DateTime<Utc>>,
+}
+
+impl From<&Resource> for ResourceView {
+    fn from(resource: &Resource) -> Self {
+        let inner = resource.inner();
+        Self {
+            id: resource.id().clone(),
+            name: inner.name().clone(),
+            conforms_to: inner.conforms_to().clone(),
+            classified_as: inner.classified_as().clone(),
+            tracking_identifier: inner.tracking_identifier().clone(),
+            image: inner.image().clone(),
+            note: inner.note().clone(),
+            state: inner.state().clone(),
+            stage: inner.stage().clone(),
+            contained_in: inner.contained_in().clone(),
+            current_location: inner.current_location().clone(),
+            location_history: resource.location_history().clone(),
+            accounting_quantity: inner.accounting_quantity().clone(),
+            onhand_quantity: inner.onhand_quantity().clone(),
+            unit_of_effort: inner.unit_of_effort().clone(),
+            primary_accountable: inner.primary_accountable().clone(),
+            in_custody_of: resource.in_custody_of().clone(),
+            costs: resource.costs().clone(),
+            amortization: resource.amortization().clone(),
+            region_id: resource.region_id().clone(),
+            lot: resource.inner().lot().clone(),
+            allow_negative: *resource.allow_negative(),
+            estimated_from: resource.estimated_from().clone(),
+            active: *resource.active(),
+            created: *resource.created(),
+            updated: *resource.updated(),
+            deleted: *resource.deleted(),
+        }
+    }
+}
+
+impl From<Resource> for ResourceView {
+    fn from(resource: Resource) -> Self {
+        Self::from(&resource)
+    }
+}
+
+/// A flattened view of a [Process](../models/process/struct.Process.html).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with_serde", derive(Serialize, Deserialize))]
+pub struct ProcessView {
+    /// The process' ID
+    pub id: ProcessID,
+    /// The process' name
+    pub name: String,
+    /// The process spec this process is based on
+    pub based_on: Option<ProcessSpecID>,
+    /// Classifications of the process
+    pub classified_as: Vec<Url>,
+    /// Whether the process has finished
+    pub finished: Option<bool>,
+    /// When the process began
+    pub has_beginning: Option<DateTime<Utc>>,
+    /// When the process ended
+    pub has_end: Option<DateTime<Utc>>,
+    /// The agents the process is in the scope of
+    pub in_scope_of: Vec<AgentID>,
+    /// A free-text note about the process
+    pub note: Option<String>,
+    /// The company that owns the process
+    pub company_id: CompanyID,
+    /// The costs accumulated in the process
+    pub costs: Costs,
+    /// The labor put into the process by work events
+    pub labor_spent: Costs,
+    /// The process' labor budget
+    pub labor_budget: Option<Costs>,
+    /// The commitments the process is blocked on
+    pub blocked_on: Vec<CommitmentID>,
+    /// Whether the process is active
+    pub active: bool,
+    /// When the process was created
+    pub created: DateTime<Utc>,
+    /// When the process was last updated
+    pub updated: DateTime<Utc>,
+    /// When the process was deleted
+    pub deleted: Option<DateTime<Utc>>,
+}
+
+impl From<&Process> for ProcessView {
+    fn from(process: &Process) -> Self {
+        let inner = process.inner();
+        Self {
+            id: process.id().clone(),
+            name: inner.name().clone(),
+            based_on: inner.based_on().clone(),
+            classified_as: inner.classified_as().clone(),
+            finished: *inner.finished(),
+            has_beginning: *inner.has_beginning(),
+            has_end: *inner.has_end(),
+            in_scope_of: inner.in_scope_of().clone(),
+            note: inner.note().clone(),
+            company_id: process.company_id().clone(),
+            costs: process.costs().clone(),
+            labor_spent: process.labor_spent().clone(),
+            labor_budget: process.labor_budget().clone(),
+            blocked_on: process.blocked_on().clone(),
+            active: *process.active(),
+            created: *process.created(),
+            updated: *process.updated(),
+            deleted: *process.deleted(),
+        }
+    }
+}
+
+impl From<Process> for ProcessView {
+    fn from(process: Process) -> Self {
+        Self::from(&process)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::lib::basis_model::Model,
+        util::{self, test::*},
+    };
+
+    #[test]
+    fn resource_view() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let mut resource = make_resource(&ResourceID::new("widget"), &company_id, &Measure::new(num!(15), Unit::One), &Costs::new_with_labor("machinist", num!(100)), &now);
+        resource.inner_mut().set_note(Some("shiny".into()));
+        resource.inner_mut().set_lot(Some(dfc::ProductBatch::builder().batch_number("2020-A").build().unwrap()));
+        let view = ResourceView::from(&resource);
+        assert_eq!(&view.id, resource.id());
+        assert_eq!(&view.name, resource.inner().name());
+        assert_eq!(&view.conforms_to, resource.inner().conforms_to());
+        assert_eq!(view.note, Some("shiny".into()));
+        assert_eq!(view.accounting_quantity, Some(Measure::new(num!(15), Unit::One)));
+        assert_eq!(view.primary_accountable, Some(company_id.clone().into()));
+        assert_eq!(&view.in_custody_of, resource.in_custody_of());
+        assert_eq!(view.costs, Costs::new_with_labor("machinist", num!(100)));
+        assert_eq!(&view.lot, resource.inner().lot());
+        assert_eq!(&view.active, resource.active());
+        assert_eq!(view.created, now);
+        assert_eq!(view.deleted, None);
+        assert_eq!(ResourceView::from(resource.clone()), view);
+    }
+
+    #[test]
+    fn process_view() {
+        let now = util::time::now();
+        let company_id = CompanyID::new("jerry's widgets");
+        let mut process = make_process(&ProcessID::new("make-widgets"), &company_id, "make widgets", &Costs::new_with_labor("machinist", num!(42)), &now);
+        process.set_blocked_on(vec![CommitmentID::new("steel")]);
+        process.set_labor_spent(Costs::new_with_labor("machinist", num!(12)));
+        process.set_labor_budget(Some(Costs::new_with_labor("machinist", num!(40))));
+        process.set_deleted(Some(now.clone()));
+        let view = ProcessView::from(&process);
+        assert_eq!(&view.id, process.id());
+        assert_eq!(view.name, "make widgets");
+        assert_eq!(&view.based_on, process.inner().based_on());
+        assert_eq!(view.company_id, company_id);
+        assert_eq!(view.costs, Costs::new_with_labor("machinist", num!(42)));
+        assert_eq!(view.blocked_on, vec![CommitmentID::new("steel")]);
+        assert_eq!(view.labor_spent, Costs::new_with_labor("machinist", num!(12)));
+        assert_eq!(view.labor_budget, Some(Costs::new_with_labor("machinist", num!(40))));
+        assert_eq!(&view.active, process.active());
+        assert_eq!(view.deleted, Some(now.clone()));
+        assert_eq!(ProcessView::from(process.clone()), view);
+    }
+}